@@ -0,0 +1,145 @@
+//! Benchmark of end-to-end request handling: a session is served over an
+//! in-memory stream (a Unix socket pair) and sent requests like a validator
+//! would, measuring how long each takes to be answered, i.e. reading and
+//! decoding the request, checking it, signing it and encoding and writing
+//! the response.
+//!
+//! Consensus state is kept in memory, so the state file isn't what's being
+//! measured. Run with `cargo bench --features bench`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use prost::Message as _;
+use std::{
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+};
+use tendermint::TendermintKey;
+use tendermint_proto::{
+    google::protobuf::Timestamp,
+    privval::{message::Sum, Message, PingRequest, SignVoteRequest},
+    types::{SignedMsgType, Vote},
+};
+use tmkms::{
+    chain::{self, state::NullStateStore, Chain},
+    config::KmsConfig,
+    keyring::{self, ed25519, SigningProvider},
+    signer::Signer,
+};
+
+/// Chain the benchmark signs for
+const CHAIN_ID: &str = "bench-chain";
+
+/// Serve a session for a chain with an in-memory Ed25519 key, returning the
+/// validator's end of its connection and the key's validator address
+fn serve() -> (Signer, UnixStream, Vec<u8>) {
+    let secret = ed25519::SecretKey::from_bytes(&[42; 32]).unwrap();
+    let public = ed25519::PublicKey::from(&secret);
+    let chain_id: chain::Id = CHAIN_ID.parse().unwrap();
+
+    let mut registry = chain::Registry::default();
+    registry
+        .register_chain(
+            Chain::new(
+                chain_id.clone(),
+                keyring::Format::HEX,
+                Box::new(NullStateStore),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+    registry
+        .add_consensus_key(
+            &chain_id,
+            ed25519::Signer::new(
+                SigningProvider::SoftSign,
+                TendermintKey::ConsensusKey(public.into()),
+                Box::new(ed25519::Keypair { secret, public }),
+            ),
+        )
+        .unwrap();
+
+    let config: KmsConfig = format!(
+        r#"
+        [[validator]]
+        chain_id = "{}"
+        addr = "unix:///var/run/validator.sock"
+        protocol_version = "v0.34"
+
+        [providers]
+        "#,
+        CHAIN_ID
+    )
+    .parse()
+    .unwrap();
+
+    let signer = Signer::with_registry(config, registry).unwrap();
+    let validator_config = signer.config().validator[0].clone();
+
+    let (kms_end, validator_end) = UnixStream::pair().unwrap();
+    signer.serve(validator_config, kms_end).unwrap();
+
+    let address = tendermint::account::Id::from(public).as_bytes().to_vec();
+    (signer, validator_end, address)
+}
+
+/// Send a request to the KMS and read its response into the given buffer
+fn request(stream: &mut UnixStream, request: &[u8], response: &mut [u8]) -> usize {
+    stream.write_all(request).unwrap();
+    stream.read(response).unwrap()
+}
+
+/// Encode a request with its length prefix
+fn encode(sum: Sum) -> Vec<u8> {
+    Message { sum: Some(sum) }.encode_length_delimited_to_vec()
+}
+
+fn request_handling(c: &mut Criterion) {
+    let (_signer, mut stream, validator_address) = serve();
+    let mut response = [0u8; 1024];
+
+    c.bench_function("ping", |b| {
+        let ping = encode(Sum::PingRequest(PingRequest {}));
+        b.iter(|| request(&mut stream, &ping, &mut response));
+    });
+
+    // Each vote is at a new height, so each one is signed
+    let mut height = 0;
+
+    c.bench_function("sign_vote", |b| {
+        b.iter_with_setup(
+            || {
+                height += 1;
+
+                encode(Sum::SignVoteRequest(SignVoteRequest {
+                    vote: Some(Vote {
+                        r#type: SignedMsgType::Precommit as i32,
+                        height,
+                        round: 0,
+                        block_id: None,
+                        timestamp: Some(Timestamp {
+                            seconds: 1_700_000_000,
+                            nanos: 0,
+                        }),
+                        validator_address: validator_address.clone(),
+                        validator_index: 0,
+                        signature: vec![],
+                    }),
+                    chain_id: CHAIN_ID.to_owned(),
+                }))
+            },
+            |vote| {
+                let len = request(&mut stream, &vote, &mut response);
+                let response = Message::decode_length_delimited(&response[..len]).unwrap();
+
+                match response.sum {
+                    Some(Sum::SignedVoteResponse(response)) => assert!(response.error.is_none()),
+                    other => panic!("unexpected response: {:?}", other),
+                }
+            },
+        );
+    });
+}
+
+criterion_group!(benches, request_handling);
+criterion_main!(benches);
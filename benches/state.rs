@@ -0,0 +1,39 @@
+//! Benchmark of persisting consensus state to a JSON state file, which is
+//! done (durably) before every signature is returned, so is on the signing
+//! hot path.
+//!
+//! The state file is kept under the target directory rather than the system
+//! temporary directory, which may be in memory (making syncs free). Run with
+//! `cargo bench --features bench --bench state`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tendermint::{block, consensus};
+use tmkms::chain::state::{JsonStateStore, StateStore};
+
+/// Consensus state at the given height
+fn state_at(height: u64) -> consensus::State {
+    consensus::State {
+        height: block::Height::try_from(height).unwrap(),
+        round: block::Round::default(),
+        step: 3,
+        block_id: None,
+    }
+}
+
+fn json_store(c: &mut Criterion) {
+    let dir = tempfile::tempdir_in(env!("CARGO_TARGET_TMPDIR")).unwrap();
+    let mut store = JsonStateStore::new(dir.path().join("priv_validator_state.json"));
+
+    // Each state is at a new height, as when signing a vote at each height
+    let mut height = 0;
+
+    c.bench_function("json_store", |b| {
+        b.iter(|| {
+            height += 1;
+            store.store(&state_at(height)).unwrap();
+        });
+    });
+}
+
+criterion_group!(benches, json_store);
+criterion_main!(benches);
@@ -0,0 +1,67 @@
+//! Build script: records the git commit and rustc version `tmkms` is built
+//! from and with, for `tmkms::build_info`
+
+use std::{env, fs, path::Path, process::Command};
+
+fn main() {
+    // Only look for a commit in our own checkout (not e.g. a repository a
+    // crates.io release happens to be unpacked in)
+    let git_dir = Path::new(".git");
+
+    if git_dir.join("HEAD").exists() {
+        if let Some(commit) = git_commit() {
+            println!("cargo:rustc-env=TMKMS_GIT_COMMIT={}", commit);
+        }
+
+        // Rebuild when a commit is checked out or made
+        println!("cargo:rerun-if-changed=.git/HEAD");
+
+        if let Ok(head) = fs::read_to_string(git_dir.join("HEAD")) {
+            if let Some(reference) = head.trim().strip_prefix("ref: ") {
+                // Refs which were packed only appear in `.git/packed-refs`
+                for path in &[git_dir.join(reference), git_dir.join("packed-refs")] {
+                    if path.exists() {
+                        println!("cargo:rerun-if-changed={}", path.display());
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(version) = rustc_version() {
+        println!("cargo:rustc-env=TMKMS_RUSTC_VERSION={}", version);
+    }
+
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+/// Get the commit being built (if building from a git checkout)
+fn git_commit() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+
+    let commit = String::from_utf8(output.stdout).ok()?;
+    let commit = commit.trim();
+
+    if output.status.success() && commit.len() == 40 {
+        Some(commit.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Get the version of the compiler building `tmkms`
+fn rustc_version() -> Option<String> {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let output = Command::new(rustc).arg("--version").output().ok()?;
+
+    if output.status.success() {
+        String::from_utf8(output.stdout)
+            .ok()
+            .map(|version| version.trim().to_owned())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,185 @@
+//! Signing with a provider supplied by the application embedding the KMS.
+//!
+//! Implements [`SignerProvider`] for a dummy backend holding an Ed25519 key
+//! in memory (a real one would talk to e.g. an HSM), adds it to a chain
+//! registry and serves a validator session over a [`UnixStream`] pair,
+//! requesting the public key and a signed prevote like a validator would.
+//!
+//! Run with `cargo run --example custom_provider --features softsign` (tmkms
+//! requires at least one built-in provider to be compiled in).
+
+use prost::Message as _;
+use std::{
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+use tendermint::TendermintKey;
+use tendermint_proto::{
+    crypto::public_key,
+    google::protobuf::Timestamp,
+    privval::{message::Sum, Message, PubKeyRequest, SignVoteRequest},
+    types::{SignedMsgType, Vote},
+};
+use tmkms::{
+    chain::{self, state::JsonStateStore, Chain},
+    config::KmsConfig,
+    error::Error,
+    keyring::{self, ed25519, KeyType, SignerProvider},
+    signer::Signer,
+};
+
+/// Chain the example signs for
+const CHAIN_ID: &str = "example-chain";
+
+/// Dummy provider signing with an in-memory Ed25519 key
+struct DummyProvider {
+    /// Key to sign with
+    keypair: ed25519::Keypair,
+
+    /// Number of signatures produced
+    signatures: AtomicUsize,
+}
+
+impl SignerProvider for DummyProvider {
+    fn name(&self) -> &str {
+        "dummy"
+    }
+
+    fn describe(&self) -> String {
+        "dummy (in-memory key)".to_owned()
+    }
+
+    fn key_types(&self) -> Vec<KeyType> {
+        vec![KeyType::Ed25519]
+    }
+
+    fn public_key(&self, _chain_id: &chain::Id) -> Result<TendermintKey, Error> {
+        Ok(TendermintKey::ConsensusKey(self.keypair.public.into()))
+    }
+
+    fn sign(&self, chain_id: &chain::Id, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        use signature::Signer as _;
+
+        println!("[{}] signing {} bytes", chain_id, msg.len());
+        self.signatures.fetch_add(1, Ordering::SeqCst);
+        Ok(self.keypair.sign(msg).to_bytes().to_vec())
+    }
+}
+
+fn main() -> Result<(), Error> {
+    let secret = ed25519::SecretKey::from_bytes(&[42; 32]).unwrap();
+    let public = ed25519::PublicKey::from(&secret);
+    let provider = Arc::new(DummyProvider {
+        keypair: ed25519::Keypair { secret, public },
+        signatures: AtomicUsize::new(0),
+    });
+
+    // Register the chain, persisting its consensus state in a temporary
+    // directory, and add the provider's key to it
+    let state_dir = tempfile::tempdir()?;
+    let chain_id: chain::Id = CHAIN_ID.parse()?;
+    let state_store = JsonStateStore::new(state_dir.path().join("state.json"));
+
+    let mut registry = chain::Registry::default();
+    registry.register_chain(Chain::new(
+        chain_id.clone(),
+        keyring::Format::HEX,
+        Box::new(state_store),
+    )?)?;
+    registry.add_provider(&chain_id, provider.clone())?;
+
+    let config: KmsConfig = format!(
+        r#"
+        [[validator]]
+        chain_id = "{}"
+        addr = "unix:///var/run/validator.sock"
+        protocol_version = "v0.34"
+
+        # Keys are supplied by `DummyProvider` rather than built-in providers
+        [providers]
+        "#,
+        CHAIN_ID
+    )
+    .parse()?;
+
+    let signer = Signer::with_registry(config, registry)?;
+    let validator_config = signer.config().validator[0].clone();
+
+    let (kms_end, mut validator_end) = UnixStream::pair()?;
+    let handle = signer.serve(validator_config, kms_end)?;
+
+    let response = request(
+        &mut validator_end,
+        Sum::PubKeyRequest(PubKeyRequest {
+            chain_id: CHAIN_ID.to_owned(),
+        }),
+    )?;
+
+    match response {
+        Sum::PubKeyResponse(response) => {
+            let public_key = response.pub_key.and_then(|pub_key| pub_key.sum);
+            assert_eq!(
+                public_key,
+                Some(public_key::Sum::Ed25519(public.as_bytes().to_vec()))
+            );
+            println!(
+                "public key: {}",
+                keyring::Format::HEX.serialize(provider.public_key(&chain_id)?)
+            );
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+
+    let vote = Vote {
+        r#type: SignedMsgType::Prevote as i32,
+        height: 1,
+        round: 0,
+        block_id: None,
+        timestamp: Some(Timestamp {
+            seconds: now.as_secs() as i64,
+            nanos: now.subsec_nanos() as i32,
+        }),
+        validator_address: tendermint::account::Id::from(public).as_bytes().to_vec(),
+        validator_index: 0,
+        signature: vec![],
+    };
+
+    let response = request(
+        &mut validator_end,
+        Sum::SignVoteRequest(SignVoteRequest {
+            vote: Some(vote),
+            chain_id: CHAIN_ID.to_owned(),
+        }),
+    )?;
+
+    match response {
+        Sum::SignedVoteResponse(response) => {
+            let signature = response.vote.map(|vote| vote.signature).unwrap_or_default();
+            println!("signed vote ({}-byte signature)", signature.len());
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+
+    assert_eq!(provider.signatures.load(Ordering::SeqCst), 1);
+    handle.shutdown()
+}
+
+/// Send a request to the KMS as a validator would, returning its response
+fn request(stream: &mut UnixStream, request: Sum) -> Result<Sum, Error> {
+    let request = Message { sum: Some(request) };
+    stream.write_all(&request.encode_length_delimited_to_vec())?;
+
+    let mut response = [0u8; 1024];
+    let len = stream.read(&mut response)?;
+    let response = Message::decode_length_delimited(&response[..len]).unwrap();
+    Ok(response.sum.expect("empty response"))
+}
@@ -0,0 +1,298 @@
+//! Webhook alerts for events which need an operator's immediate attention:
+//! attempted double signs, repeated failures to connect to a validator, and
+//! signing provider errors (e.g. an unreachable HSM).
+//!
+//! Alerts are only sent if an `[alerts]` section is present in `tmkms.toml`.
+//! They're queued (up to [`QUEUE_SIZE`] at a time) and `POST`ed to the
+//! webhook by a dedicated thread, so raising an alert never delays signing.
+//! Alerts raised while the queue is full are dropped and counted in the
+//! `tmkms_alerts_dropped_total` metric.
+
+use crate::{
+    chain,
+    config::{alerts::AlertsConfig, ValidatorAddr},
+    error::{Error, ErrorKind::*},
+    metrics,
+    prelude::*,
+};
+use chrono::{SecondsFormat, Utc};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::sync::mpsc::{SyncSender, TrySendError};
+
+#[cfg(feature = "alerts")]
+use {
+    crate::config::alerts::{DEFAULT_CONNECTION_FAILURE_THRESHOLD, DEFAULT_TIMEOUT},
+    hyper::{client::HttpConnector, http::Uri, Body, Method, Request},
+    hyper_rustls::{HttpsConnector, HttpsConnectorBuilder},
+    std::{sync::mpsc, thread, time::Duration},
+};
+
+/// Maximum number of alerts waiting to be delivered
+pub const QUEUE_SIZE: usize = 64;
+
+/// Alert queue (if alerts are enabled)
+static QUEUE: OnceCell<Queue> = OnceCell::new();
+
+/// Queue of alerts waiting to be delivered to the webhook
+struct Queue {
+    /// Sender half of the channel to the delivery thread
+    sender: SyncSender<Alert>,
+
+    /// Number of consecutive connection failures which raise an alert
+    connection_failure_threshold: u32,
+}
+
+/// Events which raise alerts
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /// A signing request was refused as an attempted double sign
+    DoubleSign,
+
+    /// Connecting to a validator failed repeatedly
+    ConnectionFailure,
+
+    /// The signing provider failed to produce a signature
+    ProviderError,
+}
+
+impl Event {
+    /// Get the name of this event (as used in the alert payload)
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Event::DoubleSign => "double_sign",
+            Event::ConnectionFailure => "connection_failure",
+            Event::ProviderError => "provider_error",
+        }
+    }
+}
+
+/// Alert payload, `POST`ed to the webhook as JSON
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Alert {
+    /// Event which raised this alert
+    pub event: Event,
+
+    /// Chain the event occurred on
+    pub chain_id: String,
+
+    /// Address of the validator involved
+    pub validator: String,
+
+    /// Description of the event
+    pub message: String,
+
+    /// Time the event occurred at (RFC 3339)
+    pub timestamp: String,
+}
+
+impl Alert {
+    /// Create a new alert for an event which just occurred
+    pub fn new(
+        event: Event,
+        chain_id: &chain::Id,
+        validator: &ValidatorAddr,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            event,
+            chain_id: chain_id.to_string(),
+            validator: validator.to_string(),
+            message: message.into(),
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        }
+    }
+}
+
+/// Raise an alert (if alerts are enabled) without waiting for it to be
+/// delivered
+pub fn raise(alert: Alert) {
+    if let Some(queue) = QUEUE.get() {
+        enqueue(&queue.sender, alert);
+    }
+}
+
+/// Raise a [`Event::ConnectionFailure`] alert if `failures` consecutive
+/// connection failures have reached the configured threshold
+pub fn connection_failed(
+    chain_id: &chain::Id,
+    validator: &ValidatorAddr,
+    failures: u32,
+    err: &Error,
+) {
+    if let Some(queue) = QUEUE.get() {
+        if failures == queue.connection_failure_threshold {
+            let message = format!("{} consecutive connection failures: {}", failures, err);
+            let alert = Alert::new(Event::ConnectionFailure, chain_id, validator, message);
+            enqueue(&queue.sender, alert);
+        }
+    }
+}
+
+/// Queue an alert for delivery, dropping it if the queue is full. Returns
+/// whether the alert was queued.
+fn enqueue(sender: &SyncSender<Alert>, alert: Alert) -> bool {
+    match sender.try_send(alert) {
+        Ok(()) => true,
+        Err(TrySendError::Full(alert)) | Err(TrySendError::Disconnected(alert)) => {
+            warn!(
+                "dropping {} alert for chain {}: alert queue is full",
+                alert.event.as_str(),
+                alert.chain_id
+            );
+
+            metrics::alert_dropped(alert.event);
+            false
+        }
+    }
+}
+
+/// Spawn the thread which delivers alerts to the configured webhook
+#[cfg(feature = "alerts")]
+pub fn spawn(config: &AlertsConfig) -> Result<(), Error> {
+    let webhook = Webhook::new(config)?;
+    info!(
+        "sending alerts to webhook at {}",
+        webhook.uri.host().unwrap_or("?")
+    );
+    let (sender, receiver) = mpsc::sync_channel::<Alert>(QUEUE_SIZE);
+
+    thread::Builder::new()
+        .name("alerts".to_owned())
+        .spawn(move || {
+            for alert in receiver {
+                if let Err(e) = webhook.deliver(&alert) {
+                    error!("couldn't deliver {} alert: {}", alert.event.as_str(), e);
+                }
+            }
+        })?;
+
+    let queue = Queue {
+        sender,
+        connection_failure_threshold: config
+            .connection_failure_threshold
+            .unwrap_or(DEFAULT_CONNECTION_FAILURE_THRESHOLD),
+    };
+
+    QUEUE
+        .set(queue)
+        .map_err(|_| format_err!(ConfigError, "alerts already enabled"))?;
+
+    Ok(())
+}
+
+/// Alerts placeholder when the `alerts` feature is disabled
+#[cfg(not(feature = "alerts"))]
+pub fn spawn(_config: &AlertsConfig) -> Result<(), Error> {
+    fail!(
+        ConfigError,
+        "`[alerts]` requires tmkms to be built with the `alerts` feature"
+    );
+}
+
+/// Webhook alerts are delivered to
+#[cfg(feature = "alerts")]
+struct Webhook {
+    /// Runtime which performs requests
+    runtime: tokio::runtime::Runtime,
+
+    /// HTTP client
+    http: hyper::Client<HttpsConnector<HttpConnector>>,
+
+    /// Webhook URL
+    uri: Uri,
+
+    /// Bearer token (if any)
+    bearer_token: Option<String>,
+
+    /// Timeout for delivering an alert
+    timeout: Duration,
+}
+
+#[cfg(feature = "alerts")]
+impl Webhook {
+    /// Create a webhook client from the given configuration
+    fn new(config: &AlertsConfig) -> Result<Self, Error> {
+        let uri = config
+            .webhook_url
+            .parse::<Uri>()
+            .map_err(|e| format_err!(ConfigError, "invalid alerts `webhook_url`: {}", e))?;
+
+        let connector = HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        Ok(Self {
+            runtime,
+            http: hyper::Client::builder().build(connector),
+            uri,
+            bearer_token: config.bearer_token.clone(),
+            timeout: Duration::from_secs(config.timeout.unwrap_or(DEFAULT_TIMEOUT)),
+        })
+    }
+
+    /// `POST` an alert to the webhook, blocking until it's been accepted
+    fn deliver(&self, alert: &Alert) -> Result<(), Error> {
+        let body = serde_json::to_vec(alert)
+            .map_err(|e| format_err!(SerializationError, "couldn't serialize alert: {}", e))?;
+
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(self.uri.clone())
+            .header("content-type", "application/json")
+            .header(
+                "user-agent",
+                concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")),
+            );
+
+        if let Some(token) = &self.bearer_token {
+            request = request.header("authorization", format!("Bearer {}", token));
+        }
+
+        let request = request.body(Body::from(body))?;
+
+        let status = self
+            .runtime
+            .block_on(async {
+                tokio::time::timeout(self.timeout, self.http.request(request)).await
+            })
+            .map_err(|_| format_err!(HttpError, "request timed out after {:?}", self.timeout))??
+            .status();
+
+        if !status.is_success() {
+            fail!(HttpError, "webhook responded with {}", status);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn full_queue_drops_alerts() {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let chain_id = "alerts-test-chain".parse().unwrap();
+        let validator = "unix:///tmp/alerts-test.sock".parse().unwrap();
+
+        for expected in &[true, false] {
+            let alert = Alert::new(Event::DoubleSign, &chain_id, &validator, "test");
+            assert_eq!(enqueue(&sender, alert), *expected);
+        }
+
+        let alert = receiver.try_recv().unwrap();
+        assert_eq!(alert.event, Event::DoubleSign);
+        assert_eq!(alert.validator, "unix:///tmp/alerts-test.sock");
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -5,6 +5,8 @@
 
 pub mod block_id;
 pub mod ed25519;
+#[cfg(test)]
+mod golden_vectors;
 pub mod message;
 pub mod ping;
 pub mod proposal;
@@ -26,7 +28,7 @@ pub use self::{
         Proposal, SignProposalRequest, SignedProposalResponse, AMINO_NAME as PROPOSAL_AMINO_NAME,
         AMINO_PREFIX as PROPOSAL_PREFIX,
     },
-    remote_error::RemoteError,
+    remote_error::{RemoteError, RemoteErrorCode},
     signature::{SignableMsg, SignedMsgType},
     time::TimeMsg,
     validate::ConsensusMessage,
@@ -37,9 +39,8 @@ pub use self::{
     },
 };
 
-use crate::rpc;
+use crate::{chain, error::Error, rpc};
 use sha2::{Digest, Sha256};
-use tendermint::{chain, error::Error};
 
 /// Parse `chain::Id` from a type
 pub trait ParseChainId {
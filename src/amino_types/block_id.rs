@@ -19,6 +19,81 @@ impl BlockId {
     pub fn new(hash: Vec<u8>, parts_header: Option<PartsSetHeader>) -> Self {
         BlockId { hash, parts_header }
     }
+
+    /// Is this the zero (i.e. nil) block ID? As with Go's `BlockID.IsZero`,
+    /// a block ID with an empty hash but a part set header isn't.
+    pub fn is_zero(&self) -> bool {
+        self.hash.is_empty()
+            && self
+                .parts_header
+                .as_ref()
+                .map_or(true, PartsSetHeader::is_zero)
+    }
+
+    /// Is this a complete block ID, i.e. a block hash and a non-empty part
+    /// set header? As with Go's `BlockID.IsComplete`.
+    pub fn is_complete(&self) -> bool {
+        self.hash.len() == SHA256_HASH_SIZE
+            && self.parts_header.as_ref().map_or(false, |psh| {
+                psh.total > 0 && psh.hash.len() == SHA256_HASH_SIZE
+            })
+    }
+
+    /// Validate a block ID which may be nil (or absent). Nil block IDs need
+    /// no further validation, and any other must be complete.
+    pub fn validate_optional(block_id: Option<&BlockId>) -> Result<(), validate::Error> {
+        match block_id {
+            Some(block_id) if !block_id.is_zero() => {
+                block_id.validate_basic()?;
+
+                if block_id.is_complete() {
+                    Ok(())
+                } else {
+                    Err(IncompleteBlockId)
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Canonical Amino form of this block ID: nil block IDs are omitted, as
+    /// is an empty part set header (Amino never encodes empty structs)
+    pub fn to_canonical(&self) -> Option<CanonicalBlockId> {
+        if self.is_zero() {
+            return None;
+        }
+
+        Some(CanonicalBlockId {
+            hash: self.hash.clone(),
+            parts_header: self
+                .parts_header
+                .as_ref()
+                .filter(|psh| !psh.is_zero())
+                .map(|psh| CanonicalPartSetHeader {
+                    hash: psh.hash.clone(),
+                    total: psh.total,
+                }),
+        })
+    }
+
+    /// Canonical Protobuf form of this block ID: nil block IDs are omitted,
+    /// but the part set header of any other is always present (it isn't
+    /// nullable in Tendermint's Protobuf definitions)
+    pub fn to_canonical_proto(&self) -> Option<proto::types::CanonicalBlockId> {
+        if self.is_zero() {
+            return None;
+        }
+
+        let psh = self.parts_header.clone().unwrap_or_default();
+
+        Some(proto::types::CanonicalBlockId {
+            hash: self.hash.clone(),
+            part_set_header: Some(proto::types::CanonicalPartSetHeader {
+                total: psh.total as u32,
+                hash: psh.hash,
+            }),
+        })
+    }
 }
 
 /// Parse an Amino-encoded SHA-256 hash
@@ -131,6 +206,11 @@ impl PartsSetHeader {
     pub fn new(total: i64, hash: Vec<u8>) -> Self {
         PartsSetHeader { total, hash }
     }
+
+    /// Is this the zero (i.e. empty) part set header?
+    pub fn is_zero(&self) -> bool {
+        self.total == 0 && self.hash.is_empty()
+    }
 }
 
 impl From<&parts::Header> for PartsSetHeader {
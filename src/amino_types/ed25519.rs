@@ -21,7 +21,18 @@ pub struct PubKeyResponse {
 
 #[derive(Clone, PartialEq, Message)]
 #[amino_name = "tendermint/remotesigner/PubKeyRequest"]
-pub struct PubKeyRequest {}
+pub struct PubKeyRequest {
+    // Only present in Protobuf-encoded requests (v0.34+)
+    #[prost_amino(string, tag = "1")]
+    pub chain_id: String,
+}
+
+impl PubKeyRequest {
+    /// Get the chain ID the public key is requested for (if any)
+    pub fn chain_id(&self) -> Option<&str> {
+        Some(self.chain_id.as_str()).filter(|id| !id.is_empty())
+    }
+}
 
 impl TryFrom<PubKeyResponse> for PublicKey {
     type Error = eyre::Report;
@@ -87,7 +98,7 @@ mod tests {
         //
 
         let want = vec![0x4, 0xcb, 0x94, 0xd6, 0x20];
-        let msg = PubKeyRequest {};
+        let msg = PubKeyRequest::default();
         let mut got = vec![];
         let _have = msg.encode(&mut got);
 
@@ -0,0 +1,422 @@
+//! Golden vectors for the sign bytes of votes and proposals under every
+//! supported protocol version, so encoding regressions are caught here rather
+//! than by a validator's signatures failing to verify.
+//!
+//! Protobuf vectors marked "Go" were generated with `types.VoteSignBytes` and
+//! `types.ProposalSignBytes` from Tendermint v0.34 (as published alongside the
+//! same cases in tendermint-rs). The remaining Protobuf vectors were generated
+//! once with tendermint-rs's canonical encoding, and the Amino vectors once with
+//! this crate (Amino is frozen: no Tendermint release after v0.33 uses it).
+//! Nil block IDs are omitted from the sign bytes in both encodings, however
+//! they're represented in the request, as in Go (whose Amino codec never
+//! encodes empty structs).
+//!
+//! The canonical encodings didn't change between Tendermint v0.34, v0.37 and
+//! CometBFT v0.38, but each protocol version is checked regardless. Vectors are
+//! hex-encoded and include the length prefix. They must never be regenerated
+//! to make a failing test pass.
+
+use super::{
+    BlockId, PartsSetHeader, Proposal, SignProposalRequest, SignVoteRequest, SignableMsg,
+    SignedMsgType, TimeMsg, Vote,
+};
+use crate::config::validator::ProtocolVersion;
+use subtle_encoding::hex;
+
+/// Every supported protocol version
+const PROTOCOL_VERSIONS: &[ProtocolVersion] = &[
+    ProtocolVersion::Grpc,
+    ProtocolVersion::V0_38,
+    ProtocolVersion::V0_37,
+    ProtocolVersion::V0_34,
+    ProtocolVersion::V0_33,
+    ProtocolVersion::Legacy,
+];
+
+/// Chain ID signed over by every vector
+const CHAIN_ID: &str = "test_chain_id";
+
+/// Height above 2^53 (i.e. not representable as a JSON number)
+const HIGH_HEIGHT: i64 = 9_007_199_254_740_993;
+
+/// Expected sign bytes of a message in each encoding
+struct Vector {
+    /// Name of the case (shown on failure)
+    name: &'static str,
+
+    /// Amino sign bytes (legacy, v0.33)
+    amino: &'static str,
+
+    /// Protobuf sign bytes (v0.34 and later, and gRPC)
+    protobuf: &'static str,
+}
+
+impl Vector {
+    /// Get the expected sign bytes for the given protocol version
+    fn expected(&self, protocol_version: ProtocolVersion) -> Vec<u8> {
+        let expected = match protocol_version {
+            ProtocolVersion::Legacy | ProtocolVersion::V0_33 => self.amino,
+            ProtocolVersion::V0_34
+            | ProtocolVersion::V0_37
+            | ProtocolVersion::V0_38
+            | ProtocolVersion::Grpc => self.protobuf,
+        };
+
+        hex::decode(expected).unwrap()
+    }
+
+    /// Check the sign bytes of `msg` under every protocol version
+    fn check(&self, msg: &impl SignableMsg) {
+        for &protocol_version in PROTOCOL_VERSIONS {
+            let mut sign_bytes = vec![];
+            msg.sign_bytes(CHAIN_ID.parse().unwrap(), protocol_version, &mut sign_bytes)
+                .unwrap();
+
+            assert_eq!(
+                String::from_utf8(hex::encode(&sign_bytes)).unwrap(),
+                String::from_utf8(hex::encode(self.expected(protocol_version))).unwrap(),
+                "{} ({})",
+                self.name,
+                protocol_version.as_str()
+            );
+        }
+    }
+}
+
+/// 2017-12-25T03:00:01.234Z
+fn timestamp_2017() -> TimeMsg {
+    TimeMsg {
+        seconds: 1_514_170_801,
+        nanos: 234_000_000,
+    }
+}
+
+/// 2018-02-11T07:09:22.765Z
+fn timestamp_2018() -> TimeMsg {
+    TimeMsg {
+        seconds: 1_518_332_962,
+        nanos: 765_000_000,
+    }
+}
+
+/// 2023-06-01T12:34:56.789012345Z
+fn timestamp_2023() -> TimeMsg {
+    TimeMsg {
+        seconds: 1_685_622_896,
+        nanos: 789_012_345,
+    }
+}
+
+/// Block ID with the given hash, and a part set header
+fn block_id(hash: &[u8], parts_total: i64, parts_hash: &[u8]) -> Option<BlockId> {
+    Some(BlockId::new(
+        hash.to_vec(),
+        Some(PartsSetHeader {
+            total: parts_total,
+            hash: parts_hash.to_vec(),
+        }),
+    ))
+}
+
+/// Nil block ID, as sent in Protobuf-encoded requests
+fn nil_block_id() -> Option<BlockId> {
+    block_id(b"", 0, b"")
+}
+
+/// Every representation of a nil block ID, all of which must be signed alike
+fn nil_block_ids() -> [Option<BlockId>; 3] {
+    [None, Some(BlockId::new(vec![], None)), nil_block_id()]
+}
+
+/// Vote with the given fields, cast by a fixed validator
+fn vote(vote_type: SignedMsgType, height: i64, round: i64, block_id: Option<BlockId>) -> Vote {
+    Vote {
+        vote_type: vote_type.to_u32(),
+        height,
+        round,
+        block_id,
+        timestamp: None,
+        validator_address: vec![
+            0xa3, 0xb2, 0xcc, 0xdd, 0x71, 0x86, 0xf1, 0x68, 0x5f, 0x21, 0xf2, 0x48, 0x2a, 0xf4,
+            0xfb, 0x34, 0x46, 0xa8, 0x4b, 0x35,
+        ],
+        validator_index: 56789,
+        signature: vec![],
+        extension: vec![],
+        extension_signature: vec![],
+    }
+}
+
+/// Request to sign the given vote
+fn sign_vote(vote: Vote, timestamp: TimeMsg) -> SignVoteRequest {
+    SignVoteRequest {
+        vote: Some(Vote {
+            timestamp: Some(timestamp),
+            ..vote
+        }),
+        chain_id: CHAIN_ID.to_owned(),
+    }
+}
+
+/// Request to sign a proposal with the given fields
+fn sign_proposal(
+    height: i64,
+    round: i64,
+    pol_round: i64,
+    block_id: Option<BlockId>,
+    timestamp: TimeMsg,
+) -> SignProposalRequest {
+    SignProposalRequest {
+        proposal: Some(Proposal {
+            msg_type: SignedMsgType::Proposal.to_u32(),
+            height,
+            round,
+            pol_round,
+            block_id,
+            timestamp: Some(timestamp),
+            signature: vec![],
+        }),
+        chain_id: CHAIN_ID.to_owned(),
+    }
+}
+
+/// 32-byte block hash (ASCII, as in the Go vectors for votes)
+const VOTE_BLOCK_HASH: &[u8] = b"DEADBEEFDEADBEEFBAFBAFBAFBAFBAFA";
+
+/// 32-byte part set hash (ASCII, as in the Go vectors for votes)
+const VOTE_PARTS_HASH: &[u8] = b"0022446688AACCEE1133557799BBDDFF";
+
+/// 32-byte block hash (as in the Go vectors for proposals)
+const PROPOSAL_BLOCK_HASH: [u8; 32] = [
+    0xde, 0xad, 0xbe, 0xef, 0xde, 0xad, 0xbe, 0xef, 0xba, 0xfb, 0xaf, 0xba, 0xfb, 0xaf, 0xba, 0xfa,
+    0xde, 0xad, 0xbe, 0xef, 0xde, 0xad, 0xbe, 0xef, 0xba, 0xfb, 0xaf, 0xba, 0xfb, 0xaf, 0xba, 0xfa,
+];
+
+/// 32-byte part set hash (as in the Go vectors for proposals)
+const PROPOSAL_PARTS_HASH: [u8; 32] = [
+    0x00, 0x22, 0x44, 0x66, 0x88, 0xaa, 0xcc, 0xee, 0x11, 0x33, 0x55, 0x77, 0x99, 0xbb, 0xdd, 0xff,
+    0x00, 0x22, 0x44, 0x66, 0x88, 0xaa, 0xcc, 0xee, 0x11, 0x33, 0x55, 0x77, 0x99, 0xbb, 0xdd, 0xff,
+];
+
+#[test]
+fn prevote_for_block() {
+    let block_id = block_id(VOTE_BLOCK_HASH, 1_000_000, VOTE_PARTS_HASH);
+    let msg = sign_vote(
+        vote(SignedMsgType::PreVote, 12345, 2, block_id),
+        timestamp_2017(),
+    );
+
+    Vector {
+        name: "prevote for a block",
+        amino: "7c0801113930000000000000190200000000000000224a0a204445414442454546444541\
+                44424545464241464241464241464241464241464112260a203030323234343636383841\
+                41434345453131333335353737393942424444464610c0843d2a0b08b1d381d20510809d\
+                ca6f320d746573745f636861696e5f6964",
+        // Go
+        protobuf: "7c0801113930000000000000190200000000000000224a0a204445414442454546444541\
+                   444245454642414642414642414642414642414641122608c0843d122030303232343436\
+                   363838414143434545313133333535373739394242444446462a0b08b1d381d20510809d\
+                   ca6f320d746573745f636861696e5f6964",
+    }
+    .check(&msg);
+}
+
+#[test]
+fn prevote_with_malformed_block_id() {
+    // Not a nil vote: the part set header must still be signed over
+    let block_id = block_id(b"", 1_000_000, VOTE_PARTS_HASH);
+    let msg = sign_vote(
+        vote(SignedMsgType::PreVote, 12345, 2, block_id),
+        timestamp_2017(),
+    );
+
+    Vector {
+        name: "prevote with an empty block hash but a part set header",
+        amino: "5a0801113930000000000000190200000000000000222812260a20303032323434363638\
+                384141434345453131333335353737393942424444464610c0843d2a0b08b1d381d20510\
+                809dca6f320d746573745f636861696e5f6964",
+        // Go
+        protobuf: "5a08011139300000000000001902000000000000002228122608c0843d12203030323234\
+                   3436363838414143434545313133333535373739394242444446462a0b08b1d381d20510\
+                   809dca6f320d746573745f636861696e5f6964",
+    }
+    .check(&msg);
+}
+
+#[test]
+fn nil_prevote() {
+    for block_id in nil_block_ids() {
+        let msg = sign_vote(
+            vote(SignedMsgType::PreVote, 12345, 2, block_id),
+            timestamp_2017(),
+        );
+
+        Vector {
+            name: "nil prevote",
+            amino: "3008011139300000000000001902000000000000002a0b08b1d381d20510809dca6f32\
+                    0d746573745f636861696e5f6964",
+            protobuf: "3008011139300000000000001902000000000000002a0b08b1d381d20510809dca6f\
+                       320d746573745f636861696e5f6964",
+        }
+        .check(&msg);
+    }
+}
+
+#[test]
+fn nil_precommit() {
+    for block_id in nil_block_ids() {
+        let msg = sign_vote(
+            vote(SignedMsgType::PreCommit, 12345, 2, block_id),
+            timestamp_2017(),
+        );
+
+        Vector {
+            name: "nil precommit",
+            amino: "3008021139300000000000001902000000000000002a0b08b1d381d20510809dca6f32\
+                    0d746573745f636861696e5f6964",
+            protobuf: "3008021139300000000000001902000000000000002a0b08b1d381d20510809dca6f\
+                       320d746573745f636861696e5f6964",
+        }
+        .check(&msg);
+    }
+}
+
+#[test]
+fn nil_precommit_at_high_height() {
+    let msg = sign_vote(
+        vote(SignedMsgType::PreCommit, HIGH_HEIGHT, 3, nil_block_id()),
+        timestamp_2023(),
+    );
+
+    Vector {
+        name: "nil precommit above 2^53",
+        amino: "3108021101000000000020001903000000000000002a0c08f0a0e2a30610f9be9df80232\
+                0d746573745f636861696e5f6964",
+        // tendermint-rs
+        protobuf: "3108021101000000000020001903000000000000002a0c08f0a0e2a30610f9be9df80232\
+                   0d746573745f636861696e5f6964",
+    }
+    .check(&msg);
+}
+
+#[test]
+fn proposal_for_block() {
+    let block_id = block_id(&PROPOSAL_BLOCK_HASH, 65535, &PROPOSAL_PARTS_HASH);
+    let msg = sign_proposal(12345, 23456, -1, block_id, timestamp_2018());
+
+    Vector {
+        name: "proposal for a block",
+        amino: "8601082011393000000000000019a05b00000000000021ffffffffffffffff2a4a0a20de\
+                adbeefdeadbeefbafbafbafbafbafadeadbeefdeadbeefbafbafbafbafbafa12260a2000\
+                22446688aaccee1133557799bbddff0022446688aaccee1133557799bbddff10ffff0332\
+                0c08a2d8ffd30510c0f2e3ec023a0d746573745f636861696e5f6964",
+        // Go
+        protobuf: "8801082011393000000000000019a05b00000000000020ffffffffffffffffff012a4a0a\
+                   20deadbeefdeadbeefbafbafbafbafbafadeadbeefdeadbeefbafbafbafbafbafa122608\
+                   ffff0312200022446688aaccee1133557799bbddff0022446688aaccee1133557799bbdd\
+                   ff320c08a2d8ffd30510c0f2e3ec023a0d746573745f636861696e5f6964",
+    }
+    .check(&msg);
+}
+
+#[test]
+fn proposal_without_block_id() {
+    for block_id in nil_block_ids() {
+        let msg = sign_proposal(12345, 23456, -1, block_id, timestamp_2018());
+
+        Vector {
+            name: "proposal without a block ID",
+            amino: "3a082011393000000000000019a05b00000000000021ffffffffffffffff320c08a2d8ff\
+                    d30510c0f2e3ec023a0d746573745f636861696e5f6964",
+            protobuf: "3c082011393000000000000019a05b00000000000020ffffffffffffffffff01320c\
+                       08a2d8ffd30510c0f2e3ec023a0d746573745f636861696e5f6964",
+        }
+        .check(&msg);
+    }
+}
+
+#[test]
+fn proposal_with_malformed_block_id() {
+    let block_id = block_id(b"", 65535, &PROPOSAL_PARTS_HASH);
+    let msg = sign_proposal(12345, 23456, -1, block_id, timestamp_2018());
+
+    Vector {
+        name: "proposal with an empty block hash but a part set header",
+        amino: "64082011393000000000000019a05b00000000000021ffffffffffffffff2a2812260a20\
+                0022446688aaccee1133557799bbddff0022446688aaccee1133557799bbddff10ffff03\
+                320c08a2d8ffd30510c0f2e3ec023a0d746573745f636861696e5f6964",
+        // Go
+        protobuf: "66082011393000000000000019a05b00000000000020ffffffffffffffffff012a281226\
+                   08ffff0312200022446688aaccee1133557799bbddff0022446688aaccee1133557799bb\
+                   ddff320c08a2d8ffd30510c0f2e3ec023a0d746573745f636861696e5f6964",
+    }
+    .check(&msg);
+}
+
+#[test]
+fn proposal_with_pol_round_at_high_height() {
+    let block_id = block_id(&PROPOSAL_BLOCK_HASH, 3, &PROPOSAL_PARTS_HASH);
+    let msg = sign_proposal(HIGH_HEIGHT, 5, 4, block_id, timestamp_2023());
+
+    Vector {
+        name: "proposal with a POL round above 2^53",
+        amino: "840108201101000000000020001905000000000000002104000000000000002a480a20de\
+                adbeefdeadbeefbafbafbafbafbafadeadbeefdeadbeefbafbafbafbafbafa12240a2000\
+                22446688aaccee1133557799bbddff0022446688aaccee1133557799bbddff1003320c08\
+                f0a0e2a30610f9be9df8023a0d746573745f636861696e5f6964",
+        // tendermint-rs
+        protobuf: "7d082011010000000000200019050000000000000020042a480a20deadbeefdeadbeefba\
+                   fbafbafbafbafadeadbeefdeadbeefbafbafbafbafbafa1224080312200022446688aacc\
+                   ee1133557799bbddff0022446688aaccee1133557799bbddff320c08f0a0e2a30610f9be\
+                   9df8023a0d746573745f636861696e5f6964",
+    }
+    .check(&msg);
+}
+
+#[test]
+fn precommit_extension() {
+    let block_id = block_id(VOTE_BLOCK_HASH, 1_000_000, VOTE_PARTS_HASH);
+    let mut vote = vote(SignedMsgType::PreCommit, 12345, 2, block_id);
+    vote.extension = b"extension".to_vec();
+    let msg = sign_vote(vote.clone(), timestamp_2017());
+
+    // Vote extensions only exist in CometBFT v0.38
+    let expected = hex::decode(
+        "2c0a09657874656e73696f6e113930000000000000190200000000000000220d746573745f\
+         636861696e5f6964",
+    )
+    .unwrap();
+
+    for &protocol_version in PROTOCOL_VERSIONS {
+        let mut sign_bytes = vec![];
+        let signed = msg
+            .extension_sign_bytes(CHAIN_ID.parse().unwrap(), protocol_version, &mut sign_bytes)
+            .unwrap();
+
+        if protocol_version == ProtocolVersion::V0_38 {
+            assert!(signed);
+            assert_eq!(sign_bytes, expected);
+        } else {
+            assert!(!signed, "{}", protocol_version.as_str());
+            assert!(sign_bytes.is_empty());
+        }
+    }
+
+    // Nil precommits don't carry extensions
+    let nil_precommit = sign_vote(
+        Vote {
+            block_id: nil_block_id(),
+            ..vote
+        },
+        timestamp_2017(),
+    );
+    let mut sign_bytes = vec![];
+    assert!(!nil_precommit
+        .extension_sign_bytes(
+            CHAIN_ID.parse().unwrap(),
+            ProtocolVersion::V0_38,
+            &mut sign_bytes
+        )
+        .unwrap());
+    assert!(sign_bytes.is_empty());
+}
@@ -1,5 +1,5 @@
 use super::{
-    block_id::{BlockId, CanonicalBlockId, CanonicalPartSetHeader, ParseId},
+    block_id::{BlockId, CanonicalBlockId, ParseId},
     compute_prefix,
     remote_error::RemoteError,
     signature::{SignableMsg, SignedMsgType},
@@ -7,15 +7,14 @@ use super::{
     validate::{self, ConsensusMessage, Error::*},
     ParseChainId, TendermintRequest,
 };
-use crate::{config::validator::ProtocolVersion, rpc};
+use crate::{chain, config::validator::ProtocolVersion, rpc};
 use bytes::BufMut;
 use bytes_v0_5::BytesMut as BytesMutV05;
-use ed25519_dalek as ed25519;
 use once_cell::sync::Lazy;
 use prost::Message as _;
 use prost_amino::{EncodeError, Message};
 use prost_amino_derive::Message;
-use tendermint::{block, chain, consensus, error};
+use tendermint::{block, consensus, error};
 use tendermint_proto::types as proto_types;
 
 #[derive(Clone, PartialEq, Message)]
@@ -51,6 +50,9 @@ pub static AMINO_PREFIX: Lazy<Vec<u8>> = Lazy::new(|| compute_prefix(AMINO_NAME)
 pub struct SignProposalRequest {
     #[prost_amino(message, tag = "1")]
     pub proposal: Option<Proposal>,
+    // Only present in Protobuf-encoded requests (v0.34+)
+    #[prost_amino(string, tag = "2")]
+    pub chain_id: String,
 }
 
 #[derive(Clone, PartialEq, Message)]
@@ -73,7 +75,7 @@ struct CanonicalProposal {
 }
 
 impl ParseChainId for CanonicalProposal {
-    fn parse_chain_id(&self) -> Result<chain::Id, error::Error> {
+    fn parse_chain_id(&self) -> Result<chain::Id, crate::error::Error> {
         self.chain_id.parse()
     }
 }
@@ -110,25 +112,14 @@ impl SignableMsg for SignProposalRequest {
         let proposal = spr.proposal.unwrap();
 
         if protocol_version.is_protobuf() {
-            let block_id = match proposal.block_id.as_ref() {
-                Some(x) if x.hash.is_empty() => None,
-                Some(x) => Some(proto_types::CanonicalBlockId {
-                    hash: x.hash.clone(),
-                    part_set_header: x.parts_header.as_ref().map(|y| {
-                        proto_types::CanonicalPartSetHeader {
-                            total: y.total as u32,
-                            hash: y.hash.clone(),
-                        }
-                    }),
-                }),
-                None => None,
-            };
-
             let cp = proto_types::CanonicalProposal {
                 chain_id: chain_id.to_string(),
                 r#type: SignedMsgType::Proposal.to_u32() as i32,
                 height: proposal.height,
-                block_id,
+                block_id: proposal
+                    .block_id
+                    .as_ref()
+                    .and_then(BlockId::to_canonical_proto),
                 pol_round: proposal.pol_round,
                 round: proposal.round,
                 timestamp: proposal.timestamp.map(Into::into),
@@ -140,19 +131,7 @@ impl SignableMsg for SignProposalRequest {
                 chain_id: chain_id.to_string(),
                 msg_type: SignedMsgType::Proposal.to_u32(),
                 height: proposal.height,
-                block_id: match proposal.block_id {
-                    Some(bid) => Some(CanonicalBlockId {
-                        hash: bid.hash,
-                        parts_header: match bid.parts_header {
-                            Some(psh) => Some(CanonicalPartSetHeader {
-                                hash: psh.hash,
-                                total: psh.total,
-                            }),
-                            None => None,
-                        },
-                    }),
-                    None => None,
-                },
+                block_id: proposal.block_id.as_ref().and_then(BlockId::to_canonical),
                 pol_round: proposal.pol_round,
                 round: proposal.round,
                 timestamp: proposal.timestamp,
@@ -165,9 +144,9 @@ impl SignableMsg for SignProposalRequest {
 
         Ok(true)
     }
-    fn set_signature(&mut self, sig: &ed25519::Signature) {
+    fn set_signature(&mut self, sig: &[u8]) {
         if let Some(ref mut prop) = self.proposal {
-            prop.signature = sig.as_ref().to_vec();
+            prop.signature = sig.to_vec();
         }
     }
     fn validate(&self) -> Result<(), validate::Error> {
@@ -185,14 +164,13 @@ impl SignableMsg for SignProposalRequest {
                 },
                 round: block::Round::from(p.round as u16),
                 step: 3,
-                block_id: {
-                    match p.block_id {
-                        Some(ref b) => match b.parse_block_id() {
-                            Ok(id) => Some(id),
-                            Err(_) => None,
-                        },
-                        None => None,
-                    }
+                block_id: match p.block_id {
+                    // A malformed block ID must never be mistaken for nil
+                    Some(ref b) if !b.is_zero() => match b.parse_block_id() {
+                        Ok(id) => Some(id),
+                        Err(_) => return None,
+                    },
+                    _ => None,
                 },
             }),
             None => None,
@@ -206,6 +184,22 @@ impl SignableMsg for SignProposalRequest {
     fn msg_type(&self) -> Option<SignedMsgType> {
         Some(SignedMsgType::Proposal)
     }
+
+    fn timestamp(&self) -> Option<TimeMsg> {
+        self.proposal
+            .as_ref()
+            .and_then(|proposal| proposal.timestamp.clone())
+    }
+
+    fn set_timestamp(&mut self, timestamp: TimeMsg) {
+        if let Some(ref mut proposal) = self.proposal {
+            proposal.timestamp = Some(timestamp);
+        }
+    }
+
+    fn chain_id(&self) -> Option<&str> {
+        Some(self.chain_id.as_str()).filter(|id| !id.is_empty())
+    }
 }
 
 impl TendermintRequest for SignProposalRequest {
@@ -240,11 +234,10 @@ impl ConsensusMessage for Proposal {
         if self.pol_round < -1 {
             return Err(NegativePolRound);
         }
-        // TODO validate proposal's block_id
 
         // signature will be missing as the KMS provides it
 
-        Ok(())
+        BlockId::validate_optional(self.block_id.as_ref())
     }
 }
 
@@ -281,6 +274,7 @@ mod tests {
 
         let _have = SignProposalRequest {
             proposal: Some(proposal),
+            chain_id: String::new(),
         }
         .encode(&mut got);
         // test-vector generated via:
@@ -339,6 +333,7 @@ mod tests {
         };
         let want = SignProposalRequest {
             proposal: Some(proposal),
+            chain_id: String::new(),
         };
 
         let data = vec![
@@ -1,4 +1,8 @@
 use prost_amino_derive::Message;
+use std::{
+    fmt::{Debug, Display},
+    time::Duration,
+};
 
 #[derive(Clone, PartialEq, Message)]
 pub struct RemoteError {
@@ -19,6 +23,60 @@ pub enum RemoteErrorCode {
 
     /// Double signing detected
     DoubleSignError = 2,
+
+    /// Request is for a different chain than the one this validator is
+    /// configured for (or an unknown chain)
+    ChainIdError = 3,
+
+    /// Request exceeds the configured `max_height`
+    MaxHeightError = 4,
+
+    /// Signing provider (e.g. HSM) failed to produce a signature
+    SigningError = 5,
+
+    /// Request is below the configured `min_height`
+    MinHeightError = 6,
+
+    /// Request is for a message type the chain's policy doesn't allow
+    MsgTypeError = 7,
+
+    /// Request's timestamp is missing, negative, or deviates from the KMS
+    /// host's clock by more than the configured `max_clock_skew_secs`
+    ClockSkewError = 8,
+
+    /// Validator exceeded its configured `max_requests_per_second`
+    RateLimitError = 9,
+
+    /// Request is for a step before the last one signed at the same
+    /// height/round (e.g. a prevote after a precommit)
+    StepRegressionError = 10,
+
+    /// Request is of a supported type, but its contents are malformed (e.g.
+    /// a vote request without a vote)
+    MalformedRequestError = 11,
+
+    /// Vote is from a different validator than the one whose consensus key
+    /// the KMS holds
+    ValidatorAddressError = 12,
+
+    /// Request is of a type the KMS recognizes but doesn't support (e.g. a
+    /// newer CometBFT message)
+    UnsupportedRequestError = 13,
+
+    /// Request wasn't signed within the validator's configured
+    /// `request_deadline_ms` of arriving
+    DeadlineExceededError = 14,
+
+    /// Request arrived while the validator's configured `request_queue_depth`
+    /// others were queued
+    QueueFullError = 15,
+
+    /// Request is for a height in the chain's configured `blocked_heights`
+    BlockedHeightError = 16,
+
+    /// Request is for a message type the validator's configured `role`
+    /// doesn't sign (e.g. a proposal for a `voter`)
+    RoleError = 17,
 }
 
 impl RemoteError {
@@ -29,4 +87,181 @@ impl RemoteError {
             description: format!("double signing requested at height: {}", height),
         }
     }
+
+    /// Create a new chain ID mismatch error
+    pub fn chain_id_mismatch(expected: &str, requested: &str) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::ChainIdError as i32,
+            description: format!(
+                "chain ID mismatch: expected {}, requested {}",
+                expected, requested
+            ),
+        }
+    }
+
+    /// Create a new error for a chain which isn't registered with the KMS
+    pub fn unknown_chain_id(chain_id: &str) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::ChainIdError as i32,
+            description: format!("unknown chain ID: {}", chain_id),
+        }
+    }
+
+    /// Create a new error for a request exceeding the configured max height
+    pub fn exceed_max_height(height: i64, max_height: u64) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::MaxHeightError as i32,
+            description: format!(
+                "attempted to sign at height {} which is greater than {}",
+                height, max_height
+            ),
+        }
+    }
+
+    /// Create a new error for a request below the configured min height
+    pub fn below_min_height(height: i64, min_height: u64) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::MinHeightError as i32,
+            description: format!(
+                "attempted to sign at height {} which is less than {}",
+                height, min_height
+            ),
+        }
+    }
+
+    /// Create a new error for a request at a blocked height, naming the
+    /// `blocked_heights` rule which blocks it
+    pub fn blocked_height(height: i64, rule: impl Display) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::BlockedHeightError as i32,
+            description: format!(
+                "height {} is blocked (blocked_heights rule \"{}\")",
+                height, rule
+            ),
+        }
+    }
+
+    /// Create a new error for a message type which isn't allowed
+    pub fn msg_type_not_allowed(msg_type: impl Debug) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::MsgTypeError as i32,
+            description: format!("signing {:?} messages is not allowed", msg_type),
+        }
+    }
+
+    /// Create a new error for a message type the validator's role doesn't sign
+    pub fn role_not_allowed(role: impl Display, msg_type: impl Debug) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::RoleError as i32,
+            description: format!(
+                "signing {:?} messages is not allowed for role \"{}\"",
+                msg_type, role
+            ),
+        }
+    }
+
+    /// Create a new error for a timestamp too far from the KMS host's clock
+    pub fn clock_skew(skew: Duration, max_clock_skew: Duration) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::ClockSkewError as i32,
+            description: format!(
+                "timestamp differs from the KMS clock by {:?}, which is more than {:?}",
+                skew, max_clock_skew
+            ),
+        }
+    }
+
+    /// Create a new error for a missing or negative timestamp
+    pub fn invalid_timestamp() -> Self {
+        RemoteError {
+            code: RemoteErrorCode::ClockSkewError as i32,
+            description: "missing or negative timestamp".to_owned(),
+        }
+    }
+
+    /// Create a new error for a request exceeding the validator's rate limit
+    pub fn rate_limited(max_requests_per_second: u32) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::RateLimitError as i32,
+            description: format!(
+                "rate limited: more than {} signing requests per second",
+                max_requests_per_second
+            ),
+        }
+    }
+
+    /// Create a new error for a request for a step before the last one signed
+    /// at the same height/round
+    pub fn step_regression(height: i64, round: i64, last_step: i8, step: i8) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::StepRegressionError as i32,
+            description: format!(
+                "step regression at height: {} round: {} (last step: {}, requested: {})",
+                height, round, last_step, step
+            ),
+        }
+    }
+
+    /// Create a new error for a request whose contents are malformed
+    pub fn malformed_request(description: impl ToString) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::MalformedRequestError as i32,
+            description: format!("malformed request: {}", description.to_string()),
+        }
+    }
+
+    /// Create a new error for a vote from a different validator than ours
+    pub fn validator_address_mismatch(expected: &str, requested: &str) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::ValidatorAddressError as i32,
+            description: format!(
+                "validator address mismatch: expected {}, requested {}",
+                expected, requested
+            ),
+        }
+    }
+
+    /// Create a new error for a request of a type the KMS doesn't support
+    pub fn unsupported_request(type_url: &str) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::UnsupportedRequestError as i32,
+            description: format!("unsupported message: {}", type_url),
+        }
+    }
+
+    /// Create a new error for a request which wasn't signed before its deadline
+    pub fn deadline_exceeded(waited: Duration, deadline: Duration) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::DeadlineExceededError as i32,
+            description: format!(
+                "request expired: waited {:?}, which is more than {:?}",
+                waited, deadline
+            ),
+        }
+    }
+
+    /// Create a new error for a request which arrived while the queue was full
+    pub fn queue_full(max_depth: usize) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::QueueFullError as i32,
+            description: format!("request queue full ({} requests queued)", max_depth),
+        }
+    }
+
+    /// Create a new error for a failure in the signing provider
+    pub fn signing_error(description: impl ToString) -> Self {
+        RemoteError {
+            code: RemoteErrorCode::SigningError as i32,
+            description: description.to_string(),
+        }
+    }
+}
+
+impl From<RemoteError> for tendermint_proto::privval::RemoteSignerError {
+    fn from(err: RemoteError) -> tendermint_proto::privval::RemoteSignerError {
+        tendermint_proto::privval::RemoteSignerError {
+            code: err.code,
+            description: err.description,
+        }
+    }
 }
@@ -1,9 +1,8 @@
-use super::validate;
-use crate::config::validator::ProtocolVersion;
+use super::{validate, TimeMsg};
+use crate::{chain, config::validator::ProtocolVersion};
 use bytes::BufMut;
-use ed25519_dalek as ed25519;
 use prost_amino::{DecodeError, EncodeError};
-use tendermint::{chain, consensus};
+use tendermint::consensus;
 
 /// Amino messages which are signable within a Tendermint network
 pub trait SignableMsg {
@@ -15,17 +14,46 @@ pub trait SignableMsg {
         sign_bytes: &mut B,
     ) -> Result<bool, EncodeError>;
 
-    /// Set the Ed25519 signature on the underlying message
-    fn set_signature(&mut self, sig: &ed25519::Signature);
+    /// Set the (Ed25519 or secp256k1) signature on the underlying message
+    fn set_signature(&mut self, sig: &[u8]);
+
+    /// Sign the message's vote extension (if any) as bytes, returning
+    /// `false` if there is no extension to sign
+    fn extension_sign_bytes<B: BufMut>(
+        &self,
+        _chain_id: chain::Id,
+        _version: ProtocolVersion,
+        _sign_bytes: &mut B,
+    ) -> Result<bool, EncodeError> {
+        Ok(false)
+    }
+
+    /// Set the vote extension signature on the underlying message
+    fn set_extension_signature(&mut self, _sig: &[u8]) {}
     fn validate(&self) -> Result<(), validate::Error>;
     fn consensus_state(&self) -> Option<consensus::State>;
     fn height(&self) -> Option<i64>;
     fn msg_type(&self) -> Option<SignedMsgType>;
+
+    /// Timestamp of the vote or proposal (if any)
+    fn timestamp(&self) -> Option<TimeMsg>;
+
+    /// Set the timestamp of the vote or proposal
+    fn set_timestamp(&mut self, timestamp: TimeMsg);
+
+    /// Chain ID the validator expects this message to be signed for (if
+    /// included in the request, i.e. Protobuf-encoded requests only)
+    fn chain_id(&self) -> Option<&str>;
+
+    /// Address of the validator the message is from (votes only)
+    fn validator_address(&self) -> Option<&[u8]> {
+        None
+    }
 }
 
 /// Signed message types. This follows:
 /// <https://github.com/tendermint/tendermint/blob/455d34134cc53c334ebd3195ac22ea444c4b59bb/types/signed_msg_type.go#L3-L16>
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum SignedMsgType {
     /// Votes
     PreVote,
@@ -38,6 +66,15 @@ pub enum SignedMsgType {
 }
 
 impl SignedMsgType {
+    /// Name of the message type, as in logs and the audit log
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SignedMsgType::Proposal => "proposal",
+            SignedMsgType::PreVote => "prevote",
+            SignedMsgType::PreCommit => "precommit",
+        }
+    }
+
     pub fn to_u32(self) -> u32 {
         match self {
             // Votes
@@ -19,6 +19,23 @@ pub struct TimeMsg {
     pub nanos: i32,
 }
 
+impl TimeMsg {
+    /// Absolute difference between this timestamp and the given time, or
+    /// `None` if the timestamp is negative or otherwise out of range
+    pub fn skew_from(&self, now: SystemTime) -> Option<Duration> {
+        if self.seconds < 0 || !(0..1_000_000_000).contains(&self.nanos) {
+            return None;
+        }
+
+        let time = UNIX_EPOCH.checked_add(Duration::new(self.seconds as u64, self.nanos as u32))?;
+
+        Some(match time.duration_since(now) {
+            Ok(ahead) => ahead,
+            Err(behind) => behind.duration(),
+        })
+    }
+}
+
 impl ParseTimestamp for TimeMsg {
     fn parse_timestamp(&self) -> Result<Time, Error> {
         Time::from_unix_timestamp(self.seconds, self.nanos as u32)
@@ -64,3 +81,42 @@ impl From<proto::google::protobuf::Timestamp> for TimeMsg {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skew_from() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+
+        let ahead = TimeMsg {
+            seconds: 1_600_000_010,
+            nanos: 500,
+        };
+        assert_eq!(ahead.skew_from(now), Some(Duration::new(10, 500)));
+
+        let behind = TimeMsg {
+            seconds: 1_599_999_990,
+            nanos: 0,
+        };
+        assert_eq!(behind.skew_from(now), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn skew_from_negative_timestamp() {
+        let now = SystemTime::now();
+
+        let negative_seconds = TimeMsg {
+            seconds: -62_135_596_800,
+            nanos: 0,
+        };
+        assert_eq!(negative_seconds.skew_from(now), None);
+
+        let negative_nanos = TimeMsg {
+            seconds: 1_600_000_000,
+            nanos: -1,
+        };
+        assert_eq!(negative_nanos.skew_from(now), None);
+    }
+}
@@ -25,4 +25,6 @@ pub enum Error {
     InvalidHashSize,
     #[error("negative total")]
     NegativeTotal,
+    #[error("expected BlockID to be either empty or complete")]
+    IncompleteBlockId,
 }
@@ -1,5 +1,5 @@
 use super::{
-    block_id::{BlockId, CanonicalBlockId, CanonicalPartSetHeader, ParseId},
+    block_id::{BlockId, CanonicalBlockId, ParseId},
     compute_prefix,
     remote_error::RemoteError,
     signature::SignableMsg,
@@ -7,15 +7,14 @@ use super::{
     validate::{self, ConsensusMessage, Error::*},
     ParseChainId, SignedMsgType, TendermintRequest,
 };
-use crate::{config::validator::ProtocolVersion, rpc};
+use crate::{chain, config::validator::ProtocolVersion, rpc};
 use bytes::BufMut;
 use bytes_v0_5::BytesMut as BytesMutV05;
-use ed25519_dalek as ed25519;
 use once_cell::sync::Lazy;
 use prost::Message as _;
 use prost_amino::{error::EncodeError, Message};
 use prost_amino_derive::Message;
-use tendermint::{block, chain, consensus, error::Error, vote};
+use tendermint::{block, consensus, error::Error, vote};
 use tendermint_proto::types as proto_types;
 
 const VALIDATOR_ADDR_SIZE: usize = 20;
@@ -38,6 +37,11 @@ pub struct Vote {
     pub validator_index: i64,
     #[prost_amino(bytes)]
     pub signature: Vec<u8>,
+    // ABCI++ vote extensions (CometBFT v0.38+ only, never Amino-encoded)
+    #[prost_amino(bytes)]
+    pub extension: Vec<u8>,
+    #[prost_amino(bytes)]
+    pub extension_signature: Vec<u8>,
 }
 
 impl Vote {
@@ -70,6 +74,8 @@ impl From<&vote::Vote> for Vote {
                 .as_ref()
                 .map(|sig| sig.as_bytes().to_vec())
                 .unwrap_or_default(),
+            extension: vec![],
+            extension_signature: vec![],
         }
     }
 }
@@ -88,6 +94,9 @@ pub static AMINO_PREFIX: Lazy<Vec<u8>> = Lazy::new(|| compute_prefix(AMINO_NAME)
 pub struct SignVoteRequest {
     #[prost_amino(message, tag = "1")]
     pub vote: Option<Vote>,
+    // Only present in Protobuf-encoded requests (v0.34+)
+    #[prost_amino(string, tag = "2")]
+    pub chain_id: String,
 }
 
 #[derive(Clone, PartialEq, Message)]
@@ -134,7 +143,7 @@ impl TendermintRequest for SignVoteRequest {
 }
 
 impl ParseChainId for CanonicalVote {
-    fn parse_chain_id(&self) -> Result<chain::Id, Error> {
+    fn parse_chain_id(&self) -> Result<chain::Id, crate::error::Error> {
         self.chain_id.parse()
     }
 }
@@ -150,19 +159,7 @@ impl CanonicalVote {
         CanonicalVote {
             vote_type: vote.vote_type,
             chain_id: chain_id.to_string(),
-            block_id: match vote.block_id {
-                Some(bid) => Some(CanonicalBlockId {
-                    hash: bid.hash,
-                    parts_header: match bid.parts_header {
-                        Some(psh) => Some(CanonicalPartSetHeader {
-                            hash: psh.hash,
-                            total: psh.total,
-                        }),
-                        None => None,
-                    },
-                }),
-                None => None,
-            },
+            block_id: vote.block_id.as_ref().and_then(BlockId::to_canonical),
             height: vote.height,
             round: vote.round,
             timestamp: match vote.timestamp {
@@ -190,30 +187,17 @@ impl SignableMsg for SignVoteRequest {
 
         if let Some(ref mut vo) = svr.vote {
             vo.signature = vec![];
+            vo.extension_signature = vec![];
         }
 
         let vote = svr.vote.unwrap();
 
         if protocol_version.is_protobuf() {
-            let block_id = match vote.block_id.as_ref() {
-                Some(x) if x.hash.is_empty() => None,
-                Some(x) => Some(proto_types::CanonicalBlockId {
-                    hash: x.hash.clone(),
-                    part_set_header: x.parts_header.as_ref().map(|y| {
-                        proto_types::CanonicalPartSetHeader {
-                            total: y.total as u32,
-                            hash: y.hash.clone(),
-                        }
-                    }),
-                }),
-                None => None,
-            };
-
             let cv = proto_types::CanonicalVote {
                 r#type: vote.vote_type as i32,
                 height: vote.height,
                 round: vote.round as i64,
-                block_id,
+                block_id: vote.block_id.as_ref().and_then(BlockId::to_canonical_proto),
                 timestamp: vote.timestamp.map(Into::into),
                 chain_id: chain_id.to_string(),
             };
@@ -227,9 +211,45 @@ impl SignableMsg for SignVoteRequest {
 
         Ok(true)
     }
-    fn set_signature(&mut self, sig: &ed25519::Signature) {
+    fn set_signature(&mut self, sig: &[u8]) {
+        if let Some(ref mut vt) = self.vote {
+            vt.signature = sig.to_vec();
+        }
+    }
+    fn extension_sign_bytes<B>(
+        &self,
+        chain_id: chain::Id,
+        protocol_version: ProtocolVersion,
+        sign_bytes: &mut B,
+    ) -> Result<bool, EncodeError>
+    where
+        B: BufMut,
+    {
+        let vote = match self.vote {
+            Some(ref vote) if protocol_version.has_vote_extensions() => vote,
+            _ => return Ok(false),
+        };
+
+        // Only precommits for a block carry extensions: nil votes don't
+        let is_nil = vote.block_id.as_ref().map_or(true, BlockId::is_zero);
+
+        if vote.vote_type != SignedMsgType::PreCommit.to_u32() || is_nil {
+            return Ok(false);
+        }
+
+        let cve = rpc::v0_38::CanonicalVoteExtension {
+            extension: vote.extension.clone(),
+            height: vote.height,
+            round: vote.round,
+            chain_id: chain_id.to_string(),
+        };
+        cve.encode_length_delimited(sign_bytes).unwrap();
+
+        Ok(true)
+    }
+    fn set_extension_signature(&mut self, sig: &[u8]) {
         if let Some(ref mut vt) = self.vote {
-            vt.signature = sig.as_ref().to_vec();
+            vt.extension_signature = sig.to_vec();
         }
     }
     fn validate(&self) -> Result<(), validate::Error> {
@@ -247,14 +267,13 @@ impl SignableMsg for SignVoteRequest {
                 },
                 round: block::Round::from(v.round as u16),
                 step: 6,
-                block_id: {
-                    match v.block_id {
-                        Some(ref b) => match b.parse_block_id() {
-                            Ok(id) => Some(id),
-                            Err(_) => None,
-                        },
-                        None => None,
-                    }
+                block_id: match v.block_id {
+                    // A malformed block ID must never be mistaken for nil
+                    Some(ref b) if !b.is_zero() => match b.parse_block_id() {
+                        Ok(id) => Some(id),
+                        Err(_) => return None,
+                    },
+                    _ => None,
                 },
             }),
             None => None,
@@ -266,6 +285,22 @@ impl SignableMsg for SignVoteRequest {
     fn msg_type(&self) -> Option<SignedMsgType> {
         self.vote.as_ref().and_then(|vote| vote.msg_type())
     }
+    fn timestamp(&self) -> Option<TimeMsg> {
+        self.vote.as_ref().and_then(|vote| vote.timestamp.clone())
+    }
+    fn set_timestamp(&mut self, timestamp: TimeMsg) {
+        if let Some(ref mut vote) = self.vote {
+            vote.timestamp = Some(timestamp);
+        }
+    }
+    fn chain_id(&self) -> Option<&str> {
+        Some(self.chain_id.as_str()).filter(|id| !id.is_empty())
+    }
+    fn validator_address(&self) -> Option<&[u8]> {
+        self.vote
+            .as_ref()
+            .map(|vote| vote.validator_address.as_slice())
+    }
 }
 
 impl ConsensusMessage for Vote {
@@ -286,11 +321,9 @@ impl ConsensusMessage for Vote {
             return Err(InvalidValidatorAddressSize);
         }
 
-        self.block_id
-            .as_ref()
-            .map_or(Ok(()), ConsensusMessage::validate_basic)
-
         // signature will be missing as the KMS provides it
+
+        BlockId::validate_optional(self.block_id.as_ref())
     }
 }
 
@@ -331,8 +364,13 @@ mod tests {
              * 134, 212, 233, 100, 211, 10, 24, 174, 179, 117, 41, 65, 141, 134, 149, 239, 65,
              * 174, 217, 42, 6, 184, 112, 17, 7, 97, 255, 221, 252, 16, 60, 144, 30, 212, 167,
              * 39, 67, 35, 118, 192, 133, 130, 193, 115, 32, 206, 152, 91, 173, 10], */
+            extension: vec![],
+            extension_signature: vec![],
+        };
+        let sign_vote_msg = SignVoteRequest {
+            vote: Some(vote),
+            chain_id: String::new(),
         };
-        let sign_vote_msg = SignVoteRequest { vote: Some(vote) };
         let mut got = vec![];
         let _have = sign_vote_msg.encode(&mut got);
 
@@ -471,6 +509,71 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_extension_sign_bytes() {
+        let mut vote = Vote {
+            vote_type: SignedMsgType::PreCommit.to_u32(),
+            height: 12345,
+            round: 2,
+            block_id: Some(BlockId {
+                hash: b"hash".to_vec(),
+                parts_header: Some(PartsSetHeader {
+                    total: 1_000_000,
+                    hash: b"parts_hash".to_vec(),
+                }),
+            }),
+            extension: b"extension".to_vec(),
+            ..Default::default()
+        };
+
+        let chain_id: chain::Id = "test_chain_id".parse().unwrap();
+        let svr = SignVoteRequest {
+            vote: Some(vote.clone()),
+            chain_id: String::new(),
+        };
+
+        // Extensions are only signed for v0.38 and above
+        let mut got = vec![];
+        assert!(!svr
+            .extension_sign_bytes(chain_id.clone(), ProtocolVersion::V0_34, &mut got)
+            .unwrap());
+        assert!(got.is_empty());
+
+        assert!(svr
+            .extension_sign_bytes(chain_id.clone(), ProtocolVersion::V0_38, &mut got)
+            .unwrap());
+
+        let want = vec![
+            0x2c, // length prefix
+            0xa, 0x9, 0x65, 0x78, 0x74, 0x65, 0x6e, 0x73, 0x69, 0x6f, 0x6e, // extension
+            0x11, 0x39, 0x30, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, // height
+            0x19, 0x2, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, // round
+            0x22, 0xd, 0x74, 0x65, 0x73, 0x74, 0x5f, 0x63, 0x68, 0x61, 0x69, 0x6e, 0x5f, 0x69,
+            0x64, // chain ID
+        ];
+        assert_eq!(got, want);
+
+        // Nil precommits don't carry extensions
+        vote.block_id = None;
+        let svr = SignVoteRequest {
+            vote: Some(vote.clone()),
+            chain_id: String::new(),
+        };
+        assert!(!svr
+            .extension_sign_bytes(chain_id.clone(), ProtocolVersion::V0_38, &mut vec![])
+            .unwrap());
+
+        // ...and neither do prevotes
+        vote.vote_type = SignedMsgType::PreVote.to_u32();
+        let svr = SignVoteRequest {
+            vote: Some(vote),
+            chain_id: String::new(),
+        };
+        assert!(!svr
+            .extension_sign_bytes(chain_id, ProtocolVersion::V0_38, &mut vec![])
+            .unwrap());
+    }
+
     #[test]
     fn test_vote_rountrip_with_sig() {
         let dt = "2017-12-25T03:00:01.234Z".parse::<DateTime<Utc>>().unwrap();
@@ -502,6 +605,8 @@ mod tests {
                 184, 112, 17, 7, 97, 255, 221, 252, 16, 60, 144, 30, 212, 167, 39, 67, 35, 118,
                 192, 133, 130, 193, 115, 32, 206, 152, 91, 173, 10,
             ],
+            extension: vec![],
+            extension_signature: vec![],
         };
         let mut got = vec![];
         let _have = vote.encode(&mut got);
@@ -510,7 +615,10 @@ mod tests {
         assert_eq!(v, vote);
         // SignVoteRequest
         {
-            let svr = SignVoteRequest { vote: Some(vote) };
+            let svr = SignVoteRequest {
+                vote: Some(vote),
+                chain_id: String::new(),
+            };
             let mut got = vec![];
             let _have = svr.encode(&mut got);
 
@@ -551,8 +659,13 @@ mod tests {
                 }),
             }),
             signature: vec![],
+            extension: vec![],
+            extension_signature: vec![],
+        };
+        let want = SignVoteRequest {
+            vote: Some(vote),
+            chain_id: String::new(),
         };
-        let want = SignVoteRequest { vote: Some(vote) };
         match SignVoteRequest::decode(encoded.as_ref()) {
             Ok(have) => {
                 assert_eq!(have, want);
@@ -560,4 +673,74 @@ mod tests {
             Err(err) => panic!("{}", err.to_string()),
         }
     }
+
+    #[test]
+    fn test_nil_block_id() {
+        let vote = |block_id| SignVoteRequest {
+            vote: Some(Vote {
+                vote_type: SignedMsgType::PreCommit.to_u32(),
+                height: 12345,
+                round: 2,
+                block_id,
+                validator_address: vec![0xa3; 20],
+                ..Vote::default()
+            }),
+            chain_id: String::new(),
+        };
+
+        // Nil block IDs are valid however they're represented, and are nil
+        // in the consensus state
+        for block_id in [
+            None,
+            Some(BlockId::new(vec![], None)),
+            Some(BlockId::new(vec![], Some(PartsSetHeader::new(0, vec![])))),
+        ] {
+            let request = vote(block_id);
+            assert_eq!(request.validate(), Ok(()));
+            assert_eq!(request.consensus_state().unwrap().block_id, None);
+        }
+
+        // Anything else must be a complete block ID
+        for block_id in [
+            BlockId::new(vec![], Some(PartsSetHeader::new(1, vec![0xab; 32]))),
+            BlockId::new(vec![0xab; 32], None),
+            BlockId::new(vec![0xab; 32], Some(PartsSetHeader::new(0, vec![]))),
+        ] {
+            let request = vote(Some(block_id));
+            assert_eq!(request.validate(), Err(IncompleteBlockId));
+            assert!(request.consensus_state().is_none());
+        }
+    }
+
+    #[test]
+    fn test_chain_id_in_sign_bytes() {
+        let svr = SignVoteRequest {
+            vote: Some(Vote {
+                vote_type: SignedMsgType::PreVote.to_u32(),
+                height: 12345,
+                round: 2,
+                ..Vote::default()
+            }),
+            chain_id: String::new(),
+        };
+
+        // Chain IDs go into sign bytes exactly as given (longer than
+        // CometBFT's limit, mixed case, punctuation) for every encoding
+        let chain_id = "Mixed.Case_Chain-ID:".repeat(4)[..64].to_owned();
+
+        for protocol_version in [ProtocolVersion::Legacy, ProtocolVersion::V0_34] {
+            let mut sign_bytes = vec![];
+            svr.sign_bytes(chain_id.parse().unwrap(), protocol_version, &mut sign_bytes)
+                .unwrap();
+
+            let mut field = vec![chain_id.len() as u8];
+            field.extend_from_slice(chain_id.as_bytes());
+            assert!(
+                sign_bytes.ends_with(&field),
+                "{:?}: {:?}",
+                protocol_version,
+                sign_bytes
+            );
+        }
+    }
 }
@@ -1,11 +1,18 @@
 //! Abscissa `Application` for the KMS
 
-use crate::{commands::KmsCommand, config::KmsConfig};
+use crate::{
+    commands::KmsCommand,
+    config::KmsConfig,
+    key_utils,
+    logging::{self, LogFormat},
+};
 use abscissa_core::{
     application::{self, AppCell},
     config::{self, CfgCell},
-    trace, Application, FrameworkError, StandardPaths,
+    terminal::{component::Terminal, ColorChoice},
+    Application, Component, FrameworkError, FrameworkErrorKind, StandardPaths,
 };
+use std::{fs, path::Path};
 
 /// Application state
 pub static APP: AppCell<KmsApplication> = AppCell::new();
@@ -18,6 +25,9 @@ pub struct KmsApplication {
 
     /// Application state.
     state: application::State<Self>,
+
+    /// Log format given on the command line (overrides the configuration)
+    log_format: Option<LogFormat>,
 }
 
 impl Application for KmsApplication {
@@ -46,6 +56,9 @@ impl Application for KmsApplication {
     /// beyond the default ones provided by the framework, this is the place
     /// to do so.
     fn register_components(&mut self, command: &Self::Cmd) -> Result<(), FrameworkError> {
+        self.log_format = command.log_format();
+        logging::set_format(self.log_format.unwrap_or_default());
+
         #[allow(unused_mut)]
         let mut components = self.framework_components(command)?;
 
@@ -56,6 +69,17 @@ impl Application for KmsApplication {
         component_registry.register(components)
     }
 
+    /// Load the configuration file at the given path, recording where
+    /// settings reported in errors were set (see [`KmsConfig::parse_toml`])
+    fn load_config(&mut self, path: &Path) -> Result<KmsConfig, FrameworkError> {
+        let toml_string = fs::read_to_string(path).map_err(|e| {
+            FrameworkErrorKind::ConfigError.context(format!("{}: {}", path.display(), e))
+        })?;
+
+        KmsConfig::parse_toml(&toml_string, Some(path))
+            .map_err(|e| FrameworkErrorKind::ConfigError.context(e).into())
+    }
+
     /// Post-configuration lifecycle callback.
     ///
     /// Called regardless of whether config is loaded to indicate this is the
@@ -64,16 +88,36 @@ impl Application for KmsApplication {
     fn after_config(&mut self, config: Self::Cfg) -> Result<(), FrameworkError> {
         let mut component_registry = self.state.components_mut();
         component_registry.after_config(&config)?;
+        key_utils::mlock::set_enabled(config.mlock.unwrap_or(true));
+        logging::set_format(self.log_format.or(config.log_format).unwrap_or_default());
         self.config.set_once(config);
         Ok(())
     }
 
-    /// Get tracing configuration from command-line options
-    fn tracing_config(&self, command: &KmsCommand) -> trace::Config {
-        if command.verbose() {
-            trace::Config::verbose()
+    /// Framework components: the terminal and (in place of Abscissa's
+    /// tracing component) log output in the configured [`LogFormat`]
+    fn framework_components(
+        &mut self,
+        command: &Self::Cmd,
+    ) -> Result<Vec<Box<dyn Component<Self>>>, FrameworkError> {
+        let color_choice = self.term_colors(command);
+        let terminal = Terminal::new(color_choice);
+
+        let filter = if command.verbose() {
+            "debug"
+        } else if command.quiet() {
+            "warn"
         } else {
-            trace::Config::default()
-        }
+            "info"
+        };
+
+        logging::init(
+            filter,
+            color_choice != ColorChoice::Never,
+            command.logs_to_stderr(),
+        )
+        .map_err(|e| FrameworkErrorKind::ComponentError.context(e))?;
+
+        Ok(vec![Box::new(terminal)])
     }
 }
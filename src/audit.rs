@@ -0,0 +1,502 @@
+//! Tamper-evident audit log of signing decisions.
+//!
+//! Every signing request the KMS handles is appended to the log as a line of
+//! JSON recording what was signed (or refused, and why). Each record carries
+//! a `hash` field computed over the previous record's hash and the record's
+//! own contents, so editing, reordering, or removing records breaks the chain
+//! from that point on. Truncating the end of the log can be detected by
+//! comparing the last hash against a copy kept elsewhere.
+//!
+//! The chain continues across rotated files, and across restarts of the KMS.
+
+use crate::{
+    amino_types::SignedMsgType,
+    chain,
+    config::audit::{AuditLogConfig, DEFAULT_MAX_FILES},
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    privileges,
+};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    ffi::OsString,
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use subtle_encoding::hex;
+use tendermint::consensus;
+
+/// Hash preceding the first record of a new audit log
+const GENESIS_HASH: [u8; 32] = [0u8; 32];
+
+/// Append-only audit log, safe to share between validator connections
+pub struct AuditLog {
+    /// Path to the current log file
+    path: PathBuf,
+
+    /// Log writer
+    writer: Mutex<Writer>,
+}
+
+impl AuditLog {
+    /// Open the configured audit log, resuming its hash chain if it exists
+    pub fn open(config: &AuditLogConfig) -> Result<Self, Error> {
+        let max_files = config.max_files.unwrap_or(DEFAULT_MAX_FILES);
+        let path = privileges::resolve(&config.path);
+        let last_hash = last_hash(&path, max_files)?;
+        let (file, size) = open_append(&path)?;
+
+        Ok(Self {
+            path: config.path.clone(),
+            writer: Mutex::new(Writer {
+                path: config.path.clone(),
+                file,
+                size,
+                max_size: config.max_size,
+                max_files,
+                last_hash,
+            }),
+        })
+    }
+
+    /// Get the path to the current log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append an entry to the log, flushing it to disk before returning
+    pub fn append(&self, entry: Entry) -> Result<(), Error> {
+        self.writer.lock().unwrap().append(entry).map_err(|e| {
+            format_err!(
+                IoError,
+                "error writing audit log {}: {}",
+                self.path.display(),
+                e
+            )
+            .into()
+        })
+    }
+}
+
+/// Audit log writer
+struct Writer {
+    /// Path to the current log file
+    path: PathBuf,
+
+    /// Current log file (opened with `O_APPEND`)
+    file: File,
+
+    /// Size of the current log file
+    size: u64,
+
+    /// Size at which to rotate the log
+    max_size: Option<u64>,
+
+    /// Number of rotated log files to keep
+    max_files: usize,
+
+    /// Hash of the most recent record
+    last_hash: [u8; 32],
+}
+
+impl Writer {
+    /// Append an entry to the log
+    fn append(&mut self, entry: Entry) -> io::Result<()> {
+        let hash = entry.hash(&self.last_hash);
+        let mut line = serde_json::to_vec(&Record {
+            entry,
+            hash: String::from_utf8(hex::encode(hash)).unwrap(),
+        })?;
+        line.push(b'\n');
+
+        if let Some(max_size) = self.max_size {
+            if self.size > 0 && self.size + line.len() as u64 > max_size {
+                self.rotate()?;
+            }
+        }
+
+        self.file.write_all(&line)?;
+        self.file.flush()?;
+        self.file.sync_data()?;
+
+        self.size += line.len() as u64;
+        self.last_hash = hash;
+        Ok(())
+    }
+
+    /// Rotate the log, shifting `<path>.N` to `<path>.N+1` and discarding
+    /// the oldest file
+    fn rotate(&mut self) -> io::Result<()> {
+        let path = privileges::resolve(&self.path);
+
+        if self.max_files == 0 {
+            fs::remove_file(&path)?;
+        } else {
+            for n in (1..self.max_files).rev() {
+                match fs::rename(rotated_path(&path, n), rotated_path(&path, n + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => (),
+                }
+            }
+
+            fs::rename(&path, rotated_path(&path, 1))?;
+        }
+
+        let (file, size) = open_append(&path)?;
+        self.file = file;
+        self.size = size;
+        Ok(())
+    }
+}
+
+/// Decision made about a signing request
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// The request was signed
+    Signed,
+
+    /// The request was refused
+    Refused,
+}
+
+/// Audit log entry for a single signing request
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Entry {
+    /// Time the decision was made (RFC 3339)
+    pub timestamp: String,
+
+    /// Chain the request was for
+    pub chain_id: String,
+
+    /// Type of message (`proposal`, `prevote`, or `precommit`)
+    pub msg_type: Option<String>,
+
+    /// Block height
+    pub height: Option<u64>,
+
+    /// Consensus round
+    pub round: Option<u32>,
+
+    /// Consensus step
+    pub step: Option<i8>,
+
+    /// Hash of the block ID (hex), or `null` for nil votes
+    pub block_id: Option<String>,
+
+    /// Whether the request was signed or refused
+    pub decision: Decision,
+
+    /// Reason the request was refused
+    pub reason: Option<String>,
+
+    /// Signature (hex) if the request was signed
+    pub signature: Option<String>,
+
+    /// Bytes which were signed (hex), so the signature can be verified
+    /// offline (see [`crate::verify`]). Omitted by older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sign_bytes: Option<String>,
+
+    /// Public key which made the signature, in the chain's key format
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+impl Entry {
+    /// Create an entry for a signed request, recording the bytes which were
+    /// signed and the public key which signed them (if known)
+    pub fn signed(
+        chain_id: &chain::Id,
+        msg_type: Option<SignedMsgType>,
+        state: Option<&consensus::State>,
+        signature: &[u8],
+        sign_bytes: &[u8],
+        public_key: Option<String>,
+    ) -> Self {
+        let mut entry = Self::new(chain_id, msg_type, state, Decision::Signed);
+        entry.signature = Some(String::from_utf8(hex::encode(signature)).unwrap());
+        entry.sign_bytes = Some(String::from_utf8(hex::encode(sign_bytes)).unwrap());
+        entry.public_key = public_key;
+        entry
+    }
+
+    /// Create an entry for a refused request
+    pub fn refused(
+        chain_id: &chain::Id,
+        msg_type: Option<SignedMsgType>,
+        state: Option<&consensus::State>,
+        reason: impl Display,
+    ) -> Self {
+        let mut entry = Self::new(chain_id, msg_type, state, Decision::Refused);
+        entry.reason = Some(reason.to_string());
+        entry
+    }
+
+    /// Create an entry with the given decision
+    fn new(
+        chain_id: &chain::Id,
+        msg_type: Option<SignedMsgType>,
+        state: Option<&consensus::State>,
+        decision: Decision,
+    ) -> Self {
+        let msg_type = msg_type.map(|msg_type| msg_type.as_str().to_owned());
+
+        Self {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            chain_id: chain_id.to_string(),
+            msg_type,
+            height: state.map(|s| s.height.value()),
+            round: state.map(|s| s.round.value()),
+            step: state.map(|s| s.step),
+            block_id: state
+                .and_then(|s| s.block_id.as_ref())
+                .map(|block_id| String::from_utf8(hex::encode(block_id.hash.as_bytes())).unwrap()),
+            decision,
+            reason: None,
+            signature: None,
+            sign_bytes: None,
+            public_key: None,
+        }
+    }
+
+    /// Compute the hash chaining this entry to the previous record
+    fn hash(&self, prev_hash: &[u8; 32]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(prev_hash);
+        hasher.update(serde_json::to_vec(self).unwrap());
+
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&hasher.finalize());
+        hash
+    }
+}
+
+/// Audit log record: an entry followed by its chained hash
+#[derive(Debug, Deserialize, Serialize)]
+struct Record {
+    /// Logged entry
+    #[serde(flatten)]
+    entry: Entry,
+
+    /// Hash of the previous record's hash and this entry (hex)
+    hash: String,
+}
+
+/// Verify the hash chain of the records read from the given reader,
+/// starting from the given hash (all zeroes for the start of a log).
+///
+/// Returns the hash of the last record, or an error identifying the first
+/// line which doesn't match the chain.
+pub fn verify(reader: impl BufRead, mut prev_hash: [u8; 32]) -> Result<[u8; 32], Error> {
+    for (n, line) in reader.lines().enumerate() {
+        let record: Record = serde_json::from_str(&line?)
+            .map_err(|e| format_err!(ParseError, "audit log line {}: {}", n + 1, e))?;
+
+        let hash = record.entry.hash(&prev_hash);
+
+        if record.hash.as_bytes() != hex::encode(hash).as_slice() {
+            fail!(
+                VerificationError,
+                "audit log line {}: hash chain mismatch",
+                n + 1
+            );
+        }
+
+        prev_hash = hash;
+    }
+
+    Ok(prev_hash)
+}
+
+/// Open the given log file for appending, returning it along with its size
+fn open_append(path: &Path) -> io::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+/// Get the path of the `n`th rotated log file
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut rotated = OsString::from(path);
+    rotated.push(format!(".{}", n));
+    rotated.into()
+}
+
+/// Find the hash of the most recent record, looking in the most recently
+/// rotated log file if the current one is empty
+fn last_hash(path: &Path, max_files: usize) -> Result<[u8; 32], Error> {
+    let mut candidates = vec![path.to_owned()];
+
+    if max_files > 0 {
+        candidates.push(rotated_path(path, 1));
+    }
+
+    for candidate in candidates {
+        let file = match File::open(&candidate) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut last_line = None;
+
+        for line in BufReader::new(file).lines() {
+            last_line = Some(line?);
+        }
+
+        if let Some(line) = last_line {
+            return parse_hash(&line).ok_or_else(|| {
+                format_err!(
+                    ParseError,
+                    "can't resume audit log hash chain: last record of {} is malformed",
+                    candidate.display()
+                )
+                .into()
+            });
+        }
+    }
+
+    Ok(GENESIS_HASH)
+}
+
+/// Parse the hash of a serialized record
+fn parse_hash(line: &str) -> Option<[u8; 32]> {
+    let record: Record = serde_json::from_str(line).ok()?;
+    let bytes = hex::decode(record.hash.as_bytes()).ok()?;
+
+    if bytes.len() != 32 {
+        return None;
+    }
+
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&bytes);
+    Some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tendermint::{block, Hash};
+
+    fn test_entry(height: u32, signed: bool) -> Entry {
+        let chain_id = "test-chain".parse().unwrap();
+        let state = consensus::State {
+            height: block::Height::from(height),
+            round: block::Round::from(0u16),
+            step: 1,
+            block_id: Some(block::Id {
+                hash: Hash::Sha256([0xAB; 32]),
+                part_set_header: Default::default(),
+            }),
+        };
+
+        if signed {
+            Entry::signed(
+                &chain_id,
+                Some(SignedMsgType::PreVote),
+                Some(&state),
+                &[0x42; 64],
+                b"sign bytes",
+                None,
+            )
+        } else {
+            Entry::refused(
+                &chain_id,
+                Some(SignedMsgType::PreVote),
+                Some(&state),
+                "double_sign",
+            )
+        }
+    }
+
+    fn config(dir: &Path, max_size: Option<u64>) -> AuditLogConfig {
+        AuditLogConfig {
+            path: dir.join("audit.log"),
+            max_size,
+            max_files: Some(2),
+        }
+    }
+
+    #[test]
+    fn append_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), None);
+
+        let log = AuditLog::open(&config).unwrap();
+        log.append(test_entry(1, true)).unwrap();
+        log.append(test_entry(1, false)).unwrap();
+        drop(log);
+
+        // Reopening resumes the hash chain
+        let log = AuditLog::open(&config).unwrap();
+        log.append(test_entry(2, true)).unwrap();
+
+        let contents = fs::read_to_string(&config.path).unwrap();
+        assert_eq!(contents.lines().count(), 3);
+
+        let record: Record = serde_json::from_str(contents.lines().next().unwrap()).unwrap();
+        assert_eq!(record.entry.decision, Decision::Signed);
+        assert_eq!(record.entry.msg_type.as_deref(), Some("prevote"));
+        assert_eq!(record.entry.height, Some(1));
+        assert_eq!(record.entry.block_id, Some("ab".repeat(32)));
+        assert_eq!(record.entry.signature, Some("42".repeat(64)));
+        assert_eq!(
+            record.entry.sign_bytes.as_deref(),
+            Some("7369676e206279746573")
+        );
+        assert!(!contents.contains("public_key"));
+
+        assert!(verify(Cursor::new(&contents), GENESIS_HASH).is_ok());
+    }
+
+    #[test]
+    fn detect_tampering() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), None);
+
+        let log = AuditLog::open(&config).unwrap();
+        for height in 1..=3 {
+            log.append(test_entry(height, true)).unwrap();
+        }
+
+        let contents = fs::read_to_string(&config.path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+
+        let edited = contents.replacen("\"height\":2", "\"height\":4", 1);
+        assert!(verify(Cursor::new(edited), GENESIS_HASH).is_err());
+
+        let removed = format!("{}\n{}\n", lines[0], lines[2]);
+        assert!(verify(Cursor::new(removed), GENESIS_HASH).is_err());
+    }
+
+    #[test]
+    fn rotate_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), Some(1));
+
+        let log = AuditLog::open(&config).unwrap();
+        for height in 1..=4 {
+            log.append(test_entry(height, true)).unwrap();
+        }
+
+        // Only `max_files` rotated files are kept
+        assert!(rotated_path(&config.path, 1).exists());
+        assert!(rotated_path(&config.path, 2).exists());
+        assert!(!rotated_path(&config.path, 3).exists());
+
+        // The hash chain continues across files
+        let oldest = fs::read_to_string(rotated_path(&config.path, 2)).unwrap();
+        let hash = parse_hash(oldest.trim_end()).unwrap();
+
+        let mut rest = fs::read_to_string(rotated_path(&config.path, 1)).unwrap();
+        rest.push_str(&fs::read_to_string(&config.path).unwrap());
+        assert!(verify(Cursor::new(rest), hash).is_ok());
+    }
+}
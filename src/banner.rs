@@ -0,0 +1,340 @@
+//! Startup banner: a summary of each chain the KMS signs for, with the keys
+//! attached to it, its consensus state and the validators it serves, so
+//! it's easy to check the right keys are attached to the right chains.
+//!
+//! Logged by `tmkms start`, or printed to stdout as a single JSON document
+//! with `--json-banner` (e.g. for provisioning scripts to check).
+
+use crate::{
+    chain,
+    config::{chain::StateBackend, KmsConfig},
+    keyring::KeyEntry,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+};
+use tendermint::TendermintKey;
+
+/// Number of bytes of the SHA-256 digest of a public key in its fingerprint
+pub const FINGERPRINT_SIZE: usize = 8;
+
+/// Summary of the chains the KMS signs for
+#[derive(Clone, Debug, Serialize)]
+pub struct Banner {
+    /// Chains, in the order the KMS signs for them (see
+    /// [`KmsConfig::signers`])
+    pub chains: Vec<ChainSummary>,
+}
+
+/// Summary of a chain (or a labelled validator of one)
+#[derive(Clone, Debug, Serialize)]
+pub struct ChainSummary {
+    /// Chain ID
+    pub chain_id: chain::Id,
+
+    /// Label of the validator (if labelled)
+    pub validator: Option<String>,
+
+    /// Keys attached to the chain
+    pub keys: Vec<KeySummary>,
+
+    /// Consensus state the chain was loaded with
+    pub state: StateSummary,
+
+    /// Validators (i.e. `[[validator]]` sections) served
+    pub validators: Vec<ValidatorSummary>,
+}
+
+/// Summary of a key attached to a chain
+#[derive(Clone, Debug, Serialize)]
+pub struct KeySummary {
+    /// Provider of the key
+    pub provider: String,
+
+    /// `consensus` or `account`
+    pub key_type: &'static str,
+
+    /// `ed25519` or `secp256k1`
+    pub algorithm: &'static str,
+
+    /// Public key, in the chain's `key_format`
+    pub public_key: String,
+
+    /// Short fingerprint of the public key (see [`fingerprint`])
+    pub fingerprint: String,
+
+    /// Is the key a standby consensus key?
+    pub standby: bool,
+}
+
+/// Summary of the consensus state of a chain
+#[derive(Clone, Debug, Serialize)]
+pub struct StateSummary {
+    /// Backend the state is kept in (`json`, `sqlite` or `redis`)
+    pub backend: &'static str,
+
+    /// Path of the state file or database (`None` for Redis)
+    pub path: Option<PathBuf>,
+
+    /// Block height last signed
+    pub height: u64,
+
+    /// Consensus round last signed
+    pub round: u32,
+
+    /// Consensus step last signed
+    pub step: i8,
+}
+
+/// Summary of a validator served for a chain
+#[derive(Clone, Debug, Serialize)]
+pub struct ValidatorSummary {
+    /// Address of the validator
+    pub addr: String,
+
+    /// Addresses failed over to
+    pub failover_addrs: Vec<String>,
+
+    /// Height above which signing is refused
+    pub max_height: Option<u64>,
+
+    /// Height below which signing is refused
+    pub min_height: Option<u64>,
+}
+
+impl Banner {
+    /// Gather a summary of each chain the KMS signs for from the given
+    /// configuration and the global chain registry (i.e. once its chains are
+    /// registered and their keys loaded)
+    pub fn gather(config: &KmsConfig) -> Self {
+        let registry = chain::REGISTRY.get();
+        let mut chains = vec![];
+
+        for (label, chain_config) in config.signers() {
+            let chain = match registry.get_validator_chain(&chain_config.id, label) {
+                Some(chain) => chain,
+                None => continue,
+            };
+
+            let keys = chain
+                .keyring
+                .keys()
+                .iter()
+                .map(|key| KeySummary::new(key, chain.keyring.format().serialize(key.public_key)))
+                .collect();
+
+            let consensus_state = chain.state.lock().unwrap().consensus_state().clone();
+
+            let state = StateSummary {
+                backend: match chain_config.state_backend {
+                    StateBackend::Json => "json",
+                    StateBackend::Sqlite => "sqlite",
+                    StateBackend::Redis => "redis",
+                },
+                path: chain::state_lock_path(&chain_config),
+                height: consensus_state.height.value(),
+                round: consensus_state.round.value(),
+                step: consensus_state.step,
+            };
+
+            let validators = config
+                .validator
+                .iter()
+                .filter(|validator| {
+                    validator.serves(&chain_config.id) && validator.label.as_deref() == label
+                })
+                .map(|validator| ValidatorSummary {
+                    addr: validator.addr.to_string(),
+                    failover_addrs: validator
+                        .failover_addrs
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect(),
+                    max_height: validator.max_height.map(|height| height.value()),
+                    min_height: validator.min_height.map(|height| height.value()),
+                })
+                .collect();
+
+            chains.push(ChainSummary {
+                chain_id: chain_config.id.clone(),
+                validator: label.map(ToOwned::to_owned),
+                keys,
+                state,
+                validators,
+            });
+        }
+
+        Self { chains }
+    }
+}
+
+impl Display for Banner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chain in &self.chains {
+            writeln!(
+                f,
+                "chain {}:",
+                chain::name(&chain.chain_id, chain.validator.as_deref())
+            )?;
+
+            if chain.keys.is_empty() {
+                writeln!(f, "  key:       none")?;
+            }
+
+            for key in &chain.keys {
+                writeln!(
+                    f,
+                    "  key:       {}{} {} from {} (fingerprint {})",
+                    if key.standby { "standby " } else { "" },
+                    key.key_type,
+                    key.algorithm,
+                    key.provider,
+                    key.fingerprint
+                )?;
+                writeln!(f, "             {}", key.public_key)?;
+            }
+
+            let state = &chain.state;
+            write!(f, "  state:     {}", state.backend)?;
+
+            if let Some(path) = &state.path {
+                write!(f, " {}", path.display())?;
+            }
+
+            writeln!(
+                f,
+                " at height {}, round {}, step {}",
+                state.height, state.round, state.step
+            )?;
+
+            for validator in &chain.validators {
+                write!(f, "  validator: {}", validator.addr)?;
+
+                for addr in &validator.failover_addrs {
+                    write!(f, ", {}", addr)?;
+                }
+
+                writeln!(
+                    f,
+                    " (max_height: {}, min_height: {})",
+                    OptionalHeight(validator.max_height),
+                    OptionalHeight(validator.min_height)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl KeySummary {
+    /// Summarize the given key, whose public key is serialized as given
+    fn new(key: &KeyEntry<'_>, public_key: String) -> Self {
+        let raw_public_key = key.public_key.public_key();
+
+        Self {
+            provider: key.provider.describe(),
+            key_type: match key.public_key {
+                TendermintKey::AccountKey(_) => "account",
+                TendermintKey::ConsensusKey(_) => "consensus",
+            },
+            algorithm: if raw_public_key.ed25519().is_some() {
+                "ed25519"
+            } else {
+                "secp256k1"
+            },
+            public_key,
+            fingerprint: fingerprint(raw_public_key),
+            standby: key.standby,
+        }
+    }
+}
+
+/// Get the fingerprint of a public key: the first [`FINGERPRINT_SIZE`] bytes
+/// of the SHA-256 digest of its raw bytes, as uppercase hex
+pub fn fingerprint(public_key: &tendermint::PublicKey) -> String {
+    let digest = Sha256::digest(&public_key.to_bytes());
+    String::from_utf8(subtle_encoding::hex::encode_upper(
+        &digest[..FINGERPRINT_SIZE],
+    ))
+    .unwrap()
+}
+
+/// Height which may not be configured
+struct OptionalHeight(Option<u64>);
+
+impl Display for OptionalHeight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(height) => write!(f, "{}", height),
+            None => f.write_str("none"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_banner() {
+        let banner = Banner {
+            chains: vec![ChainSummary {
+                chain_id: "cosmoshub-4".parse().unwrap(),
+                validator: None,
+                keys: vec![KeySummary {
+                    provider: "softsign".to_owned(),
+                    key_type: "consensus",
+                    algorithm: "ed25519",
+                    public_key: "cosmosvalconspub1zcjduepq".to_owned(),
+                    fingerprint: "0123456789ABCDEF".to_owned(),
+                    standby: false,
+                }],
+                state: StateSummary {
+                    backend: "json",
+                    path: Some(PathBuf::from("/var/lib/tmkms/state.json")),
+                    height: 1234,
+                    round: 1,
+                    step: 2,
+                },
+                validators: vec![ValidatorSummary {
+                    addr: "tcp://127.0.0.1:26658".to_owned(),
+                    failover_addrs: vec!["tcp://127.0.0.2:26658".to_owned()],
+                    max_height: Some(5000),
+                    min_height: None,
+                }],
+            }],
+        };
+
+        assert_eq!(
+            banner.to_string(),
+            "chain cosmoshub-4:\n\
+             \x20 key:       consensus ed25519 from softsign (fingerprint 0123456789ABCDEF)\n\
+             \x20            cosmosvalconspub1zcjduepq\n\
+             \x20 state:     json /var/lib/tmkms/state.json at height 1234, round 1, step 2\n\
+             \x20 validator: tcp://127.0.0.1:26658, tcp://127.0.0.2:26658 \
+             (max_height: 5000, min_height: none)\n"
+        );
+
+        let json = serde_json::to_value(&banner).unwrap();
+        assert_eq!(json["chains"][0]["chain_id"], "cosmoshub-4");
+        assert_eq!(
+            json["chains"][0]["keys"][0]["fingerprint"],
+            "0123456789ABCDEF"
+        );
+        assert_eq!(json["chains"][0]["state"]["height"], 1234);
+        assert_eq!(json["chains"][0]["validators"][0]["max_height"], 5000);
+    }
+
+    #[test]
+    fn fingerprints() {
+        let public_key = tendermint::PublicKey::from_raw_ed25519(&[0; 32]).unwrap();
+        let fingerprint = fingerprint(&public_key);
+
+        // SHA-256 of 32 zero bytes
+        assert_eq!(fingerprint, "66687AADF862BD77");
+    }
+}
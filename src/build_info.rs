@@ -0,0 +1,139 @@
+//! Information about this build of `tmkms`: its version, the commit and
+//! compiler it was built from and with, and what it was built to support
+
+use crate::config::validator::ProtocolVersion;
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// Version of `tmkms`
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Git commit `tmkms` was built from (if built from a git checkout)
+pub const GIT_COMMIT: Option<&str> = option_env!("TMKMS_GIT_COMMIT");
+
+/// Version of the compiler `tmkms` was built with
+pub const RUSTC_VERSION: Option<&str> = option_env!("TMKMS_RUSTC_VERSION");
+
+/// Cargo features of `tmkms`, and whether each is enabled in this build
+const FEATURES: &[(&str, bool)] = &[
+    ("alerts", cfg!(feature = "alerts")),
+    ("async", cfg!(feature = "async")),
+    ("awskms", cfg!(feature = "awskms")),
+    ("azurekv", cfg!(feature = "azurekv")),
+    ("fortanixdsm", cfg!(feature = "fortanixdsm")),
+    ("gcpkms", cfg!(feature = "gcpkms")),
+    ("grpc", cfg!(feature = "grpc")),
+    ("ledger", cfg!(feature = "ledger")),
+    ("nitro", cfg!(feature = "nitro")),
+    ("pkcs11", cfg!(feature = "pkcs11")),
+    ("redis", cfg!(feature = "redis")),
+    ("softsign", cfg!(feature = "softsign")),
+    ("sqlite", cfg!(feature = "sqlite")),
+    ("systemd", cfg!(feature = "systemd")),
+    ("testing", cfg!(feature = "testing")),
+    ("threshold", cfg!(feature = "threshold")),
+    ("tls", cfg!(feature = "tls")),
+    ("tpm", cfg!(feature = "tpm")),
+    ("tx-signer", cfg!(feature = "tx-signer")),
+    ("vault", cfg!(feature = "vault")),
+    ("yubihsm", cfg!(feature = "yubihsm")),
+    ("yubihsm-mock", cfg!(feature = "yubihsm-mock")),
+    ("yubihsm-server", cfg!(feature = "yubihsm-server")),
+];
+
+/// Information about this build (serialized by `tmkms version --json`, whose
+/// format is stable: fields may be added, but never renamed or removed)
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct BuildInfo {
+    /// Version of `tmkms`
+    pub version: &'static str,
+
+    /// Git commit `tmkms` was built from (if built from a git checkout)
+    pub git_commit: Option<&'static str>,
+
+    /// Cargo features this build was compiled with
+    pub features: Vec<&'static str>,
+
+    /// Privval protocol versions (`protocol_version`) this build supports
+    pub protocol_versions: Vec<ProtocolVersion>,
+
+    /// Version of the compiler this build was compiled with
+    pub rustc_version: Option<&'static str>,
+}
+
+impl BuildInfo {
+    /// Get the information about this build
+    pub fn current() -> Self {
+        BuildInfo {
+            version: VERSION,
+            git_commit: GIT_COMMIT,
+            features: features(),
+            protocol_versions: protocol_versions(),
+            rustc_version: RUSTC_VERSION,
+        }
+    }
+
+    /// Get the abbreviated git commit (if any)
+    pub fn short_commit(&self) -> Option<&'static str> {
+        self.git_commit.map(|commit| &commit[..commit.len().min(7)])
+    }
+}
+
+impl Display for BuildInfo {
+    /// Display the version, and the commit it was built from (if any), e.g.
+    /// `0.12.0 (abc1234)`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.short_commit() {
+            Some(commit) => write!(f, "{} ({})", self.version, commit),
+            None => f.write_str(self.version),
+        }
+    }
+}
+
+/// Get the cargo features this build was compiled with
+pub fn features() -> Vec<&'static str> {
+    FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+/// Get the privval protocol versions this build supports (gRPC requires the
+/// `grpc` feature)
+pub fn protocol_versions() -> Vec<ProtocolVersion> {
+    ProtocolVersion::ALL
+        .iter()
+        .copied()
+        .filter(|version| cfg!(feature = "grpc") || *version != ProtocolVersion::Grpc)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json() {
+        let info = BuildInfo::current();
+        let json = serde_json::to_value(&info).unwrap();
+
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            json["features"]
+                .as_array()
+                .unwrap()
+                .contains(&"softsign".into()),
+            cfg!(feature = "softsign")
+        );
+        assert!(json["protocol_versions"]
+            .as_array()
+            .unwrap()
+            .contains(&"v0.34".into()));
+
+        if let Some(commit) = info.git_commit {
+            assert_eq!(json["git_commit"], commit);
+            assert_eq!(info.to_string(), format!("{} ({})", VERSION, &commit[..7]));
+        }
+    }
+}
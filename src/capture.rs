@@ -0,0 +1,616 @@
+//! Captures of the privval protocol, for debugging encoding issues.
+//!
+//! With `protocol_capture_dir` set on a `[[validator]]`, every request read
+//! from the validator and the response written back are saved to the
+//! directory as the exact bytes sent over the connection (after decryption),
+//! alongside a line of JSON per exchange in [`INDEX_FILE_NAME`] describing
+//! them: the decoded request, and the sign bytes and signature of signed
+//! responses. Captures never include key material.
+//!
+//! `tmkms replay` decodes the captured requests again, re-signs them with the
+//! configured keys (without consulting or updating the consensus state) and
+//! reports whether the sign bytes, signatures and encoded responses match the
+//! captured ones.
+
+use crate::{
+    amino_types::{PingResponse, SignableMsg, TendermintRequest, TimeMsg},
+    chain::{self, state, Chain, Registry},
+    config::validator::ProtocolVersion,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    privileges,
+    rpc::{Request, Response},
+};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs::{self, DirBuilder, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+use subtle_encoding::hex;
+use tendermint::{time::ParseTimestamp, Time};
+
+/// Name of the index of the exchanges in a capture directory
+pub const INDEX_FILE_NAME: &str = "index.jsonl";
+
+/// Type of requests which couldn't be decoded
+pub const MALFORMED: &str = "malformed";
+
+/// Capture of the requests received from a validator and the responses sent
+/// to it, written to a directory
+pub struct Capture {
+    /// Capture directory
+    dir: PathBuf,
+
+    /// Index of the exchanges (opened when the first one is recorded)
+    index: Option<File>,
+
+    /// Sequence number of the next exchange
+    next_seq: u64,
+}
+
+impl Capture {
+    /// Capture exchanges to the given directory, which is created (and any
+    /// existing capture in it appended to) when the first one is recorded
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_owned(),
+            index: None,
+            next_seq: 1,
+        }
+    }
+
+    /// Record a request and the response sent to it (if any, i.e. unless the
+    /// request was malformed), flushing them to disk before returning
+    pub fn record(
+        &mut self,
+        mut entry: Entry,
+        request: &[u8],
+        response: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        let dir = privileges::resolve(&self.dir).into_owned();
+
+        self.write(&dir, &mut entry, request, response)
+            .map_err(|e| {
+                format_err!(
+                    IoError,
+                    "error writing protocol capture to {}: {}",
+                    self.dir.display(),
+                    e
+                )
+                .into()
+            })
+    }
+
+    /// Write an exchange to the capture directory
+    fn write(
+        &mut self,
+        dir: &Path,
+        entry: &mut Entry,
+        request: &[u8],
+        response: Option<&[u8]>,
+    ) -> io::Result<()> {
+        if self.index.is_none() {
+            DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+
+            let path = dir.join(INDEX_FILE_NAME);
+            self.next_seq = match File::open(&path) {
+                Ok(file) => BufReader::new(file).lines().count() as u64 + 1,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => 1,
+                Err(e) => return Err(e),
+            };
+
+            self.index = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .mode(0o600)
+                    .open(&path)?,
+            );
+        }
+
+        entry.seq = self.next_seq;
+        let prefix = format!(
+            "{:06}-{}",
+            entry.seq,
+            Utc::now().format("%Y%m%dT%H%M%S%.6fZ")
+        );
+
+        entry.request_file = format!("{}-request.bin", prefix);
+        write_file(&dir.join(&entry.request_file), request)?;
+
+        if let Some(response) = response {
+            let response_file = format!("{}-response.bin", prefix);
+            write_file(&dir.join(&response_file), response)?;
+            entry.response_file = Some(response_file);
+        }
+
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        let index = self.index.as_mut().unwrap();
+        index.write_all(&line)?;
+        index.sync_data()?;
+
+        self.next_seq += 1;
+        Ok(())
+    }
+}
+
+/// Write a captured message to a new file
+fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+
+    file.write_all(contents)?;
+    file.sync_data()
+}
+
+/// Index entry describing a captured request and the response to it
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Entry {
+    /// Sequence number of the exchange within the capture
+    pub seq: u64,
+
+    /// Time the request was received (RFC 3339)
+    pub timestamp: String,
+
+    /// Chain the request was handled for (or the alias of it the request
+    /// named, which it was signed with)
+    pub chain_id: String,
+
+    /// Label of the validator the request was handled for (if any)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validator: Option<String>,
+
+    /// Protocol version the request was decoded (and the response encoded)
+    /// with
+    pub protocol_version: ProtocolVersion,
+
+    /// Type of request (`sign_vote`, `sign_proposal`, `pub_key`, `ping`, or
+    /// [`MALFORMED`])
+    pub request_type: String,
+
+    /// Type of message to sign (`proposal`, `prevote`, or `precommit`)
+    pub msg_type: Option<String>,
+
+    /// Block height
+    pub height: Option<u64>,
+
+    /// Consensus round
+    pub round: Option<u32>,
+
+    /// File the request was written to
+    pub request_file: String,
+
+    /// File the response was written to (none for malformed requests, which
+    /// aren't responded to)
+    pub response_file: Option<String>,
+
+    /// Bytes which were signed (hex)
+    pub sign_bytes: Option<String>,
+
+    /// Signature in the response (hex)
+    pub signature: Option<String>,
+
+    /// Timestamp the message was signed with (RFC 3339), if the KMS
+    /// substituted it for the request's (i.e. when re-signing a retried
+    /// request)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_timestamp: Option<String>,
+
+    /// Error in the response, or why the request couldn't be decoded
+    pub error: Option<String>,
+
+    /// Timestamp of the vote or proposal in the request
+    #[serde(skip)]
+    request_timestamp: Option<TimeMsg>,
+}
+
+impl Entry {
+    /// Describe a request received for the given chain
+    pub fn new(
+        chain_id: &chain::Id,
+        validator: Option<&str>,
+        protocol_version: ProtocolVersion,
+        request: &Request,
+    ) -> Self {
+        let mut entry = Self::empty(chain_id, validator, protocol_version);
+
+        entry.request_type = request_type(request).to_owned();
+
+        match request {
+            Request::SignProposal(req) => entry.describe(req),
+            Request::SignVote(req) => entry.describe(req),
+            Request::ShowPublicKey(_) | Request::ReplyPing(_) | Request::Unsupported(_) => (),
+        }
+
+        entry
+    }
+
+    /// Describe a request which couldn't be decoded
+    pub fn malformed(
+        chain_id: &chain::Id,
+        validator: Option<&str>,
+        protocol_version: ProtocolVersion,
+        error: &Error,
+    ) -> Self {
+        Self {
+            request_type: MALFORMED.to_owned(),
+            error: Some(error.to_string()),
+            ..Self::empty(chain_id, validator, protocol_version)
+        }
+    }
+
+    /// Entry for a request to the given chain, describing nothing yet
+    fn empty(
+        chain_id: &chain::Id,
+        validator: Option<&str>,
+        protocol_version: ProtocolVersion,
+    ) -> Self {
+        Self {
+            seq: 0,
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            chain_id: chain_id.to_string(),
+            validator: validator.map(ToOwned::to_owned),
+            protocol_version,
+            request_type: String::new(),
+            msg_type: None,
+            height: None,
+            round: None,
+            request_file: String::new(),
+            response_file: None,
+            sign_bytes: None,
+            signature: None,
+            signed_timestamp: None,
+            error: None,
+            request_timestamp: None,
+        }
+    }
+
+    /// Describe the message a signing request is for
+    fn describe(&mut self, request: &impl SignableMsg) {
+        let state = request.consensus_state();
+
+        self.msg_type = request
+            .msg_type()
+            .map(|msg_type| msg_type.as_str().to_owned());
+        self.height = state.as_ref().map(|s| s.height.value());
+        self.round = state.as_ref().map(|s| s.round.value());
+        self.request_timestamp = request.timestamp();
+    }
+
+    /// Describe the response sent to the request, recording what was signed
+    pub fn set_response(&mut self, response: &Response) -> Result<(), Error> {
+        match response {
+            Response::SignedVote(resp) => {
+                self.error = resp.err.as_ref().map(|e| e.description.clone());
+
+                if let (Some(vote), None) = (&resp.vote, &resp.err) {
+                    let signed = crate::amino_types::SignVoteRequest {
+                        vote: Some(vote.clone()),
+                        chain_id: String::new(),
+                    };
+
+                    self.set_signed(&signed, &vote.signature)?;
+                }
+            }
+            Response::SignedProposal(resp) => {
+                self.error = resp.err.as_ref().map(|e| e.description.clone());
+
+                if let (Some(proposal), None) = (&resp.proposal, &resp.err) {
+                    let signed = crate::amino_types::SignProposalRequest {
+                        proposal: Some(proposal.clone()),
+                        chain_id: String::new(),
+                    };
+
+                    self.set_signed(&signed, &proposal.signature)?;
+                }
+            }
+            Response::Ping(_) | Response::PublicKey(_) => (),
+            Response::PublicKeyRefused(err) => self.error = Some(err.description.clone()),
+            Response::Unsupported(req) => self.error = Some(req.describe()),
+        }
+
+        Ok(())
+    }
+
+    /// Record the sign bytes of a signed message and its signature
+    fn set_signed(&mut self, signed: &impl SignableMsg, signature: &[u8]) -> Result<(), Error> {
+        let chain_id = self.chain_id.parse()?;
+        let mut sign_bytes = vec![];
+        signed.sign_bytes(chain_id, self.protocol_version, &mut sign_bytes)?;
+
+        self.sign_bytes = Some(encode_hex(&sign_bytes));
+        self.signature = Some(encode_hex(signature));
+
+        let timestamp = signed.timestamp();
+        if timestamp != self.request_timestamp {
+            self.signed_timestamp = timestamp
+                .and_then(|timestamp| timestamp.parse_timestamp().ok())
+                .map(|timestamp| timestamp.to_rfc3339());
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the name of a request's type in capture indexes
+fn request_type(request: &Request) -> &'static str {
+    match request {
+        Request::SignProposal(_) => "sign_proposal",
+        Request::SignVote(_) => "sign_vote",
+        Request::ShowPublicKey(_) => "pub_key",
+        Request::ReplyPing(_) => "ping",
+        Request::Unsupported(_) => "unsupported",
+    }
+}
+
+/// Encode bytes as lowercase hex
+fn encode_hex(bytes: &[u8]) -> String {
+    String::from_utf8(hex::encode(bytes)).unwrap()
+}
+
+/// Read the index of the capture in the given directory
+pub fn read_index(dir: &Path) -> Result<Vec<Entry>, Error> {
+    let path = dir.join(INDEX_FILE_NAME);
+    let file = File::open(&path)
+        .map_err(|e| format_err!(IoError, "couldn't open {}: {}", path.display(), e))?;
+
+    let mut entries = vec![];
+
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let entry = serde_json::from_str(&line?).map_err(|e| {
+            format_err!(
+                ParseError,
+                "{}:{}: malformed capture index entry: {}",
+                path.display(),
+                i + 1,
+                e
+            )
+        })?;
+
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Result of replaying a captured request
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// The replayed request produced the same sign bytes, signature and
+    /// response as the captured one
+    Match,
+
+    /// The replayed request produced something different, as described
+    Mismatch(String),
+
+    /// The request wasn't replayed, for the given reason
+    Skipped(String),
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Match => f.write_str("match"),
+            Outcome::Mismatch(reason) => write!(f, "MISMATCH: {}", reason),
+            Outcome::Skipped(reason) => write!(f, "skipped: {}", reason),
+        }
+    }
+}
+
+/// Replay a captured request against the keys in the given registry (see
+/// [`chain::load_keys`]), without consulting any consensus state
+pub fn replay(registry: &Registry, dir: &Path, entry: &Entry) -> Result<Outcome, Error> {
+    let request_path = dir.join(&entry.request_file);
+    let request_bytes = fs::read(&request_path)
+        .map_err(|e| format_err!(IoError, "couldn't read {}: {}", request_path.display(), e))?;
+
+    let request = match Request::from_msg(&request_bytes, entry.protocol_version) {
+        Ok(request) if entry.request_type == MALFORMED => {
+            return Ok(Outcome::Mismatch(format!(
+                "captured as malformed, but now decodes as {}",
+                request_type(&request)
+            )));
+        }
+        Ok(request) => request,
+        Err(_) if entry.request_type == MALFORMED => return Ok(Outcome::Match),
+        Err(e) => {
+            return Ok(Outcome::Mismatch(format!(
+                "captured {} request no longer decodes: {}",
+                entry.request_type, e
+            )))
+        }
+    };
+
+    let chain_id = match registry.canonical_id(&entry.chain_id) {
+        Some(chain_id) => chain_id.clone(),
+        None => entry.chain_id.parse()?,
+    };
+    let chain = registry
+        .get_validator_chain(&chain_id, entry.validator.as_deref())
+        .ok_or_else(|| {
+            format_err!(
+                ConfigError,
+                "no keys configured for {}",
+                chain::name(&chain_id, entry.validator.as_deref())
+            )
+        })?;
+
+    let response = match request {
+        Request::SignProposal(req) => match replay_signing(chain, entry, req)? {
+            Ok(response) => response,
+            Err(outcome) => return Ok(outcome),
+        },
+        Request::SignVote(req) => match replay_signing(chain, entry, req)? {
+            Ok(response) => response,
+            Err(outcome) => return Ok(outcome),
+        },
+        Request::ShowPublicKey(_) => {
+            Response::PublicKey(*chain.keyring.default_consensus_pubkey()?)
+        }
+        Request::ReplyPing(_) => Response::Ping(PingResponse {}),
+        Request::Unsupported(req) => Response::Unsupported(req),
+    };
+
+    let response_path = match &entry.response_file {
+        Some(file) => dir.join(file),
+        None => return Ok(Outcome::Skipped("no response was captured".to_owned())),
+    };
+
+    let captured = fs::read(&response_path)
+        .map_err(|e| format_err!(IoError, "couldn't read {}: {}", response_path.display(), e))?;
+
+    if response.encode(entry.protocol_version)? != captured {
+        return Ok(Outcome::Mismatch(
+            "the encoded response differs from the captured one".to_owned(),
+        ));
+    }
+
+    Ok(Outcome::Match)
+}
+
+/// Re-sign a captured signing request, returning the response to it, or the
+/// outcome of the replay if it doesn't get that far
+fn replay_signing<R>(
+    chain: &Chain,
+    entry: &Entry,
+    mut request: R,
+) -> Result<Result<Response, Outcome>, Error>
+where
+    R: TendermintRequest,
+{
+    let (captured_sign_bytes, captured_signature) = match (&entry.sign_bytes, &entry.signature) {
+        (Some(sign_bytes), Some(signature)) => (sign_bytes, signature),
+        _ => {
+            return Ok(Err(Outcome::Skipped(format!(
+                "not signed when captured ({})",
+                entry.error.as_deref().unwrap_or("no signature")
+            ))))
+        }
+    };
+
+    if let Some(timestamp) = &entry.signed_timestamp {
+        let timestamp = Time::parse_from_rfc3339(timestamp).map_err(|e| {
+            format_err!(ParseError, "invalid signed_timestamp {}: {}", timestamp, e)
+        })?;
+
+        request.set_timestamp(TimeMsg::from(timestamp));
+    }
+
+    let mut sign_bytes = vec![];
+    request.sign_bytes(
+        chain.signing_id(Some(&entry.chain_id)),
+        entry.protocol_version,
+        &mut sign_bytes,
+    )?;
+
+    if encode_hex(&sign_bytes) != *captured_sign_bytes {
+        let captured = hex::decode(captured_sign_bytes)
+            .map_err(|e| format_err!(ParseError, "invalid sign_bytes in capture index: {}", e))?;
+
+        return Ok(Err(Outcome::Mismatch(format!(
+            "sign bytes differ (captured: {} bytes, SHA-256 {}; replayed: {} bytes, SHA-256 {})",
+            captured.len(),
+            state::sign_bytes_hash(&captured),
+            sign_bytes.len(),
+            state::sign_bytes_hash(&sign_bytes)
+        ))));
+    }
+
+    let signature = chain.keyring.sign_consensus(&sign_bytes)?;
+
+    if encode_hex(&signature) != *captured_signature {
+        return Ok(Err(Outcome::Mismatch(
+            "the signature differs (is the same consensus key configured?)".to_owned(),
+        )));
+    }
+
+    request.set_signature(&signature);
+
+    let mut extension_to_sign = vec![];
+    if request.extension_sign_bytes(
+        chain.id.clone(),
+        entry.protocol_version,
+        &mut extension_to_sign,
+    )? {
+        let signature = chain.keyring.sign_consensus(&extension_to_sign)?;
+        request.set_extension_signature(&signature);
+    }
+
+    Ok(Ok(request.build_response(None)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amino_types::{SignVoteRequest, Vote};
+
+    #[test]
+    fn record_and_read_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let chain_id = "test_chain_id".parse().unwrap();
+        let protocol_version = ProtocolVersion::V0_34;
+
+        let request = Request::SignVote(SignVoteRequest {
+            vote: Some(Vote {
+                vote_type: 1,
+                height: 5,
+                round: 2,
+                ..Vote::default()
+            }),
+            chain_id: "test_chain_id".to_owned(),
+        });
+
+        let mut capture = Capture::new(&dir.path().join("capture"));
+        let entry = Entry::new(&chain_id, None, protocol_version, &request);
+        capture
+            .record(entry, b"request", Some(b"response"))
+            .unwrap();
+
+        let error = format_err!(MalformedRequest, "unknown request type").into();
+        let entry = Entry::malformed(&chain_id, Some("a"), protocol_version, &error);
+        capture.record(entry, b"garbage", None).unwrap();
+
+        // Captures continue where the last one left off
+        let mut capture = Capture::new(&dir.path().join("capture"));
+        let entry = Entry::new(
+            &chain_id,
+            None,
+            protocol_version,
+            &Request::ReplyPing(Default::default()),
+        );
+        capture.record(entry, b"ping", Some(b"pong")).unwrap();
+
+        let entries = read_index(&dir.path().join("capture")).unwrap();
+        assert_eq!(entries.len(), 3);
+
+        assert_eq!(entries[0].seq, 1);
+        assert_eq!(entries[0].request_type, "sign_vote");
+        assert_eq!(entries[0].msg_type.as_deref(), Some("prevote"));
+        assert_eq!((entries[0].height, entries[0].round), (Some(5), Some(2)));
+
+        let request_path = dir.path().join("capture").join(&entries[0].request_file);
+        assert_eq!(fs::read(request_path).unwrap(), b"request");
+
+        assert_eq!(entries[1].seq, 2);
+        assert_eq!(entries[1].request_type, MALFORMED);
+        assert_eq!(entries[1].validator.as_deref(), Some("a"));
+        assert_eq!(entries[1].response_file, None);
+        assert!(entries[1]
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("unknown request type"));
+
+        assert_eq!(entries[2].seq, 3);
+        assert_eq!(entries[2].request_type, "ping");
+    }
+}
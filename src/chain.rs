@@ -1,44 +1,179 @@
 //! Information about particular Tendermint blockchain networks
 
 mod guard;
+pub mod id;
 mod registry;
 pub mod state;
 
 pub use self::{
     guard::Guard,
+    id::Id,
     registry::{GlobalRegistry, Registry, REGISTRY},
     state::State,
 };
 use crate::{
-    config::{chain::ChainConfig, KmsConfig},
-    error::Error,
+    audit::AuditLog,
+    config::{
+        audit::AuditLogConfig,
+        chain::{BlockedHeights, ChainConfig, MsgType, StateBackend},
+        KmsConfig,
+    },
+    error::{Error, ErrorKind::*},
+    key_utils,
     keyring::{self, KeyRing},
     prelude::*,
+    privileges,
 };
-use std::{path::PathBuf, sync::Mutex};
-pub use tendermint::chain::Id;
+use once_cell::sync::Lazy;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use zeroize::Zeroizing;
+
+/// Minimum size of a `state_hmac_key_path` key in bytes
+pub const MIN_STATE_HMAC_KEY_SIZE: usize = 32;
+
+/// Is consensus state which fails HMAC verification accepted?
+static ACCEPT_TAMPERED_STATE: AtomicBool = AtomicBool::new(false);
+
+/// Accept consensus state which fails HMAC verification (logging an error)
+/// when it's loaded, rather than refusing to (i.e. `--accept-tampered-state`)
+pub fn set_accept_tampered_state(accept: bool) {
+    ACCEPT_TAMPERED_STATE.store(accept, Ordering::SeqCst);
+}
+
+/// Are consensus keys which differ from the one recorded with the chain's
+/// consensus state accepted?
+static ACCEPT_KEY_CHANGE: AtomicBool = AtomicBool::new(false);
+
+/// Accept a consensus key which differs from the one recorded with the
+/// chain's consensus state (logging a warning and recording the new key),
+/// rather than refusing to start (i.e. `--accept-key-change`)
+pub fn set_accept_key_change(accept: bool) {
+    ACCEPT_KEY_CHANGE.store(accept, Ordering::SeqCst);
+}
+
+/// Are chains' consensus states locked when they're loaded?
+static LOCK_STATE: AtomicBool = AtomicBool::new(false);
+
+/// Take an exclusive lock on each chain's consensus state when it's loaded,
+/// held for as long as the chain is registered, so another `tmkms` process
+/// can't use the same state (i.e. `tmkms start`)
+pub fn set_lock_state(lock: bool) {
+    LOCK_STATE.store(lock, Ordering::SeqCst);
+}
+
+/// Aliases requests have been seen for
+static ALIASES_SEEN: Lazy<Mutex<BTreeSet<Id>>> = Lazy::new(Default::default);
+
+/// Log a notice the first time a request bearing the given alias of the
+/// given chain is seen
+pub fn note_alias(chain_id: &Id, alias: &Id) {
+    if ALIASES_SEEN.lock().unwrap().insert(alias.clone()) {
+        info!(
+            "[{}] accepting requests for alias chain ID {} (signed with the requested \
+             chain ID, sharing {}'s consensus state)",
+            chain_id, alias, chain_id
+        );
+    }
+}
 
 /// Information about a particular Tendermint blockchain network
 pub struct Chain {
     /// ID of a particular chain
     pub id: Id,
 
+    /// Other chain IDs requests for this chain may bear
+    pub aliases: Vec<Id>,
+
+    /// Label of the validator this chain signs for, if the KMS signs for
+    /// several validators of the chain (`None` for its unlabelled validator)
+    pub validator: Option<String>,
+
     /// Signing keyring for this chain
     pub keyring: KeyRing,
 
-    /// State from the last block signed for this chain
-    pub state: Mutex<State>,
+    /// State from the last block signed for this chain (shared with the
+    /// chain's replacement when the configuration is reloaded)
+    pub state: Arc<Mutex<State>>,
+
+    /// Audit log of signing decisions (if enabled)
+    pub audit_log: Option<Arc<AuditLog>>,
+
+    /// Types of consensus messages which may be signed (`None` for all)
+    pub allowed_msg_types: Option<Vec<MsgType>>,
+
+    /// Maximum difference between the timestamp of a vote or proposal and
+    /// the host's clock (`None` if unchecked)
+    pub max_clock_skew: Option<Duration>,
+
+    /// Heights which must never be signed
+    pub blocked_heights: BlockedHeights,
+
+    /// Refuse votes whose validator address isn't derived from the chain's
+    /// consensus key?
+    pub enforce_validator_address: bool,
+
+    /// Lock on the consensus state (if locked), released once the chain and
+    /// all of its replacements are dropped
+    pub state_lock: Option<Arc<state::StateLock>>,
 }
 
 impl Chain {
+    /// Create a `Chain` with an empty keyring of the given format, persisting
+    /// its consensus state in the given store
+    pub fn new(
+        id: Id,
+        key_format: keyring::Format,
+        state_store: Box<dyn state::StateStore>,
+    ) -> Result<Chain, Error> {
+        let keyring = KeyRing::new(id.clone(), key_format);
+
+        Ok(Self {
+            id,
+            aliases: vec![],
+            validator: None,
+            keyring,
+            state: Arc::new(Mutex::new(State::load(state_store)?)),
+            audit_log: None,
+            allowed_msg_types: None,
+            max_clock_skew: None,
+            blocked_heights: BlockedHeights::default(),
+            enforce_validator_address: true,
+            state_lock: None,
+        })
+    }
+
     /// Attempt to create a `Chain` state from the given configuration
     pub fn from_config(config: &ChainConfig) -> Result<Chain, Error> {
-        let state_file = match config.state_file {
-            Some(ref path) => path.to_owned(),
-            None => PathBuf::from(&format!("{}_priv_validator_state.json", config.id)),
+        if let Some(path) = state_lock_path(config) {
+            create_state_dir(&path)?;
+        }
+
+        // Lock the state before loading it, so it's never loaded while
+        // another process may be writing it
+        let state_lock = match state_lock_path(config) {
+            Some(path) if LOCK_STATE.load(Ordering::SeqCst) => {
+                Some(Arc::new(state::StateLock::acquire(&path)?))
+            }
+            _ => None,
         };
 
-        let mut state = State::load_state(state_file)?;
+        let store = open_state_store(config)?;
+
+        let mut state = if config.strict_state {
+            State::load_existing(store)?
+        } else {
+            State::load(store)?
+        };
 
         if let Some(ref hook) = config.state_hook {
             match state::hook::run(hook) {
@@ -56,18 +191,679 @@ impl Chain {
 
         Ok(Self {
             id: config.id.clone(),
-            keyring: KeyRing::new(config.key_format.clone()),
-            state: Mutex::new(state),
+            aliases: config.aliases.clone(),
+            validator: None,
+            keyring: KeyRing::new(config.id.clone(), config.key_format.clone())
+                .with_active_key(config.active_key.clone()),
+            state: Arc::new(Mutex::new(state)),
+            audit_log: None,
+            allowed_msg_types: config.allowed_msg_types.clone(),
+            max_clock_skew: config.max_clock_skew_secs.map(Duration::from_secs),
+            blocked_heights: config.blocked_heights.clone(),
+            enforce_validator_address: config.enforce_validator_address,
+            state_lock,
         })
     }
+
+    /// Create a replacement for this chain with the given (reloaded)
+    /// configuration's policies and an empty keyring, sharing this chain's
+    /// consensus state and audit log
+    pub fn reconfigure(&self, config: &ChainConfig) -> Chain {
+        Self {
+            id: self.id.clone(),
+            aliases: config.aliases.clone(),
+            validator: self.validator.clone(),
+            keyring: KeyRing::new(self.id.clone(), config.key_format.clone())
+                .with_active_key(config.active_key.clone()),
+            state: self.state.clone(),
+            audit_log: self.audit_log.clone(),
+            allowed_msg_types: config.allowed_msg_types.clone(),
+            max_clock_skew: config.max_clock_skew_secs.map(Duration::from_secs),
+            blocked_heights: config.blocked_heights.clone(),
+            enforce_validator_address: config.enforce_validator_address,
+            state_lock: self.state_lock.clone(),
+        }
+    }
+
+    /// Create a replacement for this chain with the given keyring, sharing
+    /// everything else
+    pub fn with_keyring(&self, keyring: KeyRing) -> Chain {
+        Self {
+            id: self.id.clone(),
+            aliases: self.aliases.clone(),
+            validator: self.validator.clone(),
+            keyring,
+            state: self.state.clone(),
+            audit_log: self.audit_log.clone(),
+            allowed_msg_types: self.allowed_msg_types.clone(),
+            max_clock_skew: self.max_clock_skew,
+            blocked_heights: self.blocked_heights.clone(),
+            enforce_validator_address: self.enforce_validator_address,
+            state_lock: self.state_lock.clone(),
+        }
+    }
+
+    /// Get the name of this chain in messages: its ID, along with the label
+    /// of the validator it signs for (if any)
+    pub fn name(&self) -> String {
+        name(&self.id, self.validator.as_deref())
+    }
+
+    /// Is the given chain ID one of this chain's aliases?
+    pub fn has_alias(&self, chain_id: &str) -> bool {
+        self.aliases.iter().any(|alias| alias.as_str() == chain_id)
+    }
+
+    /// Get the chain ID to sign a request bearing the given chain ID with:
+    /// the requested ID if it's one of this chain's aliases, otherwise the
+    /// chain's own
+    pub fn signing_id(&self, requested: Option<&str>) -> Id {
+        requested
+            .and_then(|chain_id| self.aliases.iter().find(|alias| alias.as_str() == chain_id))
+            .unwrap_or(&self.id)
+            .clone()
+    }
+}
+
+/// Get the name of the given chain, signing for the validator with the given
+/// label (if any), in messages
+pub fn name(chain_id: &Id, validator: Option<&str>) -> String {
+    match validator {
+        Some(label) => format!("{} (validator {})", chain_id, label),
+        None => chain_id.to_string(),
+    }
+}
+
+/// Open the configured backend for persisting the given chain's consensus
+/// state
+pub fn open_state_store(config: &ChainConfig) -> Result<Box<dyn state::StateStore>, Error> {
+    let state_file = state_file_path(config);
+
+    if config.state_hmac_key_path.is_some() && config.state_backend != StateBackend::Json {
+        fail!(
+            ConfigError,
+            "chain {}: `state_hmac_key_path` is only supported with `state_backend = \"json\"`",
+            config.id
+        );
+    }
+
+    match config.state_backend {
+        StateBackend::Json => {
+            let mut store = state::JsonStateStore::new(state_file)
+                .accept_tampered(ACCEPT_TAMPERED_STATE.load(Ordering::SeqCst));
+
+            if let Some(key_path) = &config.state_hmac_key_path {
+                store = store.with_hmac_key(load_state_hmac_key(config, key_path)?);
+            }
+
+            Ok(Box::new(store))
+        }
+        StateBackend::Sqlite => open_sqlite_store(config, &state_file),
+        StateBackend::Redis => open_redis_store(config),
+    }
+}
+
+/// Load the key the given chain's consensus state is authenticated with
+fn load_state_hmac_key(config: &ChainConfig, path: &Path) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let key = key_utils::load_base64_secret(path)?;
+
+    if key.len() < MIN_STATE_HMAC_KEY_SIZE {
+        fail!(
+            ConfigError,
+            "chain {}: `state_hmac_key_path` key {} is too short ({} bytes, must be at least {})",
+            config.id,
+            path.display(),
+            key.len(),
+            MIN_STATE_HMAC_KEY_SIZE
+        );
+    }
+
+    Ok(key)
+}
+
+/// Get the path of the given chain's `priv_validator_state.json` file
+pub fn state_file_path(config: &ChainConfig) -> PathBuf {
+    match config.state_file {
+        Some(ref path) => path.to_owned(),
+        None => PathBuf::from(&format!("{}_priv_validator_state.json", config.id)),
+    }
+}
+
+/// Create the directory the state file (or database) at the given path is
+/// kept in if it's missing, accessible only to the KMS's user
+fn create_state_dir(path: &Path) -> Result<(), Error> {
+    let dir = match privileges::resolve(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() && !dir.exists() => dir.to_owned(),
+        _ => return Ok(()),
+    };
+
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)
+        .map_err(|e| {
+            format_err!(
+                IoError,
+                "couldn't create state directory {}: {}",
+                dir.display(),
+                e
+            )
+        })?;
+
+    info!("created state directory {}", dir.display());
+    Ok(())
+}
+
+/// Get the path locked to keep other processes from using the given chain's
+/// consensus state (`None` if it isn't stored in a local file)
+pub fn state_lock_path(config: &ChainConfig) -> Option<PathBuf> {
+    match config.state_backend {
+        StateBackend::Json => Some(state_file_path(config)),
+        StateBackend::Sqlite => config.state_db_path.clone(),
+        StateBackend::Redis => None,
+    }
+}
+
+/// Open the SQLite state store for the given chain, importing its JSON state
+/// file (if any) on first start
+#[cfg(feature = "sqlite")]
+fn open_sqlite_store(
+    config: &ChainConfig,
+    state_file: &Path,
+) -> Result<Box<dyn state::StateStore>, Error> {
+    let db_path = config.state_db_path.as_ref().ok_or_else(|| -> Error {
+        format_err!(
+            ConfigError,
+            "chain {}: `state_db_path` is required with `state_backend = \"sqlite\"`",
+            config.id
+        )
+        .into()
+    })?;
+
+    let mut store = state::SqliteStateStore::open(db_path, config.id.clone())?;
+    store.import_json(state_file)?;
+    Ok(Box::new(store))
+}
+
+/// SQLite state store placeholder when the `sqlite` feature is disabled
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite_store(
+    config: &ChainConfig,
+    _state_file: &Path,
+) -> Result<Box<dyn state::StateStore>, Error> {
+    fail!(
+        ConfigError,
+        "chain {}: `state_backend = \"sqlite\"` requires tmkms to be built with the `sqlite` feature",
+        config.id
+    );
+}
+
+/// Open the Redis state store shared with other KMS instances for the given
+/// chain
+#[cfg(feature = "redis")]
+fn open_redis_store(config: &ChainConfig) -> Result<Box<dyn state::StateStore>, Error> {
+    let redis_config = config.state_redis.as_ref().ok_or_else(|| -> Error {
+        format_err!(
+            ConfigError,
+            "chain {}: `state_redis` is required with `state_backend = \"redis\"`",
+            config.id
+        )
+        .into()
+    })?;
+
+    Ok(Box::new(state::RedisStateStore::open(
+        redis_config,
+        &config.id,
+    )?))
+}
+
+/// Redis state store placeholder when the `redis` feature is disabled
+#[cfg(not(feature = "redis"))]
+fn open_redis_store(config: &ChainConfig) -> Result<Box<dyn state::StateStore>, Error> {
+    fail!(
+        ConfigError,
+        "chain {}: `state_backend = \"redis\"` requires tmkms to be built with the `redis` feature",
+        config.id
+    );
 }
 
 /// Initialize the chain registry from the configuration file
 pub fn load_config(config: &KmsConfig) -> Result<(), Error> {
-    for config in &config.chain {
-        REGISTRY.register(Chain::from_config(config)?)?;
+    check_aliases(config)?;
+    check_state_files(config)?;
+
+    // Chains logging to the same file share a writer (and hash chain)
+    let mut audit_logs = BTreeMap::new();
+
+    for (validator, chain_config) in chain_configs(config) {
+        let chain = load_chain(config, &chain_config, validator, &mut audit_logs)?;
+        REGISTRY.register(chain)?;
     }
 
     let mut registry = REGISTRY.0.write().unwrap();
-    keyring::load_config(&mut registry, &config.providers)
+    keyring::load_config(&mut registry, &config.providers)?;
+    check_consensus_keys(&registry)
+}
+
+/// Load the keys of each configured chain into a new registry, without
+/// opening (or locking) their consensus state, e.g. to check the keys of a
+/// configuration or replay captured requests alongside a running KMS
+pub fn load_keys(config: &KmsConfig) -> Result<Registry, Error> {
+    let mut registry = Registry::default();
+
+    for (validator, chain_config) in config.signers() {
+        let mut chain = Chain::new(
+            chain_config.id.clone(),
+            chain_config.key_format.clone(),
+            Box::new(state::NullStateStore),
+        )?;
+
+        chain.aliases = chain_config.aliases.clone();
+        chain.validator = validator.map(ToOwned::to_owned);
+        registry.register_chain(chain)?;
+    }
+
+    keyring::load_config(&mut registry, &config.providers)?;
+    Ok(registry)
+}
+
+/// Chains added to and removed from the registry by [`reload_config`]
+#[derive(Debug, Default)]
+pub struct Changes {
+    /// Newly registered chains
+    pub added: Vec<Id>,
+
+    /// Chains which are no longer registered
+    pub removed: Vec<Id>,
+}
+
+/// Reload the chain registry from a new configuration, replacing the one it
+/// was loaded from (`old_config`).
+///
+/// Chains which remain configured keep their consensus state, state store and
+/// audit log, while every chain's keyring and signing policies
+/// (`allowed_msg_types`, `max_clock_skew_secs`, `blocked_heights`,
+/// `enforce_validator_address`) are replaced with the new configuration's.
+/// The new registry (including its keyrings) is built without holding the
+/// registry lock, which is only taken to swap it in, so validators keep
+/// signing while a reload is in progress.
+/// If any part of the new configuration can't be loaded, the registry is left
+/// unchanged.
+pub fn reload_config(old_config: &KmsConfig, config: &KmsConfig) -> Result<Changes, Error> {
+    validate_config(config)?;
+
+    // TODO(tarcieri): better handle `PoisonError` here?
+    let old_chains = REGISTRY.0.read().unwrap().clone();
+    let mut new_chains = Registry::default();
+
+    let added = stage_chains(&old_chains, &mut new_chains, old_config, config)?;
+    keyring::load_config(&mut new_chains, &config.providers)?;
+
+    // A key change is only ever accepted at startup
+    check_keys(&new_chains, false)?;
+
+    let removed = old_chains
+        .chains()
+        .filter(|chain| chain.validator.is_none() && new_chains.get_chain(&chain.id).is_none())
+        .map(|chain| chain.id.clone())
+        .collect();
+
+    REGISTRY.replace(new_chains);
+    Ok(Changes { added, removed })
+}
+
+/// Consensus key rotation performed by [`rotate_consensus_key`]
+#[derive(Debug)]
+pub struct Rotation {
+    /// ID of the previously active key
+    pub from: String,
+
+    /// Public key of the newly active key (in the chain's key format)
+    pub public_key: String,
+
+    /// Did the chain's consensus public key change?
+    pub pubkey_changed: bool,
+}
+
+/// Rotate the active consensus key of the given chain to the standby key with
+/// the given ID (see [`KeyRing::rotate_consensus_key`]).
+///
+/// The chain is swapped in the registry for a copy with the rotated keyring,
+/// sharing its consensus state, so sessions finish any in-flight request
+/// with the old key and sign subsequent ones with the new key without
+/// losing double signing protection. The rotation lasts until the
+/// configuration is reloaded (or the KMS restarted). If the public key
+/// changes, the new key is recorded with the chain's consensus state.
+pub fn rotate_consensus_key(
+    chain_id: &Id,
+    key_id: &str,
+    allow_pubkey_change: bool,
+) -> Result<Rotation, Error> {
+    // TODO(tarcieri): better handle `PoisonError` here?
+    let mut registry = REGISTRY.0.write().unwrap();
+
+    let chain = registry
+        .chain(chain_id)
+        .ok_or_else(|| format_err!(ChainIdError, "unregistered chain: {}", chain_id))?;
+
+    let keyring = chain
+        .keyring
+        .rotate_consensus_key(key_id, allow_pubkey_change)?;
+
+    let old_public_key = chain.keyring.default_consensus_pubkey()?;
+    let new_public_key = keyring.default_consensus_pubkey()?;
+
+    let rotation = Rotation {
+        from: chain.keyring.active_key_id().unwrap_or_default().to_owned(),
+        public_key: keyring.format().serialize(new_public_key),
+        pubkey_changed: new_public_key != old_public_key,
+    };
+
+    // The new key is expected from now on, including after a restart
+    if rotation.pubkey_changed {
+        // TODO(tarcieri): better handle `PoisonError` here?
+        let mut state = chain.state.lock().unwrap();
+        state.record_pub_key(*new_public_key.public_key())?;
+    }
+
+    registry.replace_chain(chain.with_keyring(keyring));
+    Ok(rotation)
+}
+
+/// Check the consensus key of each chain in the registry is the one recorded
+/// with its consensus state, recording it if none is yet.
+///
+/// A chain whose key differs is refused (as its state may be another
+/// validator's, or the key may have been swapped unnoticed) unless key
+/// changes are accepted (see [`set_accept_key_change`]), in which case the
+/// new key is recorded.
+pub fn check_consensus_keys(registry: &Registry) -> Result<(), Error> {
+    check_keys(registry, ACCEPT_KEY_CHANGE.load(Ordering::SeqCst))
+}
+
+/// Check the consensus key of each chain in the registry, accepting changed
+/// keys if `accept_key_change` is set
+fn check_keys(registry: &Registry, accept_key_change: bool) -> Result<(), Error> {
+    for chain in registry.chains() {
+        // Chains without exactly one consensus key have nothing to check
+        let public_key = match chain.keyring.default_consensus_pubkey() {
+            Ok(key) => *key.public_key(),
+            Err(_) => continue,
+        };
+
+        // TODO(tarcieri): better handle `PoisonError` here?
+        let mut state = chain.state.lock().unwrap();
+
+        let recorded_key = match state.pub_key() {
+            Some(recorded_key) if recorded_key == public_key => continue,
+            Some(recorded_key) => recorded_key,
+            None => {
+                state.record_pub_key(public_key)?;
+
+                if state.pub_key().is_some() {
+                    info!(
+                        "[{}] recorded consensus key {} with the consensus state",
+                        chain.name(),
+                        bech32_key(chain, public_key)
+                    );
+                }
+
+                continue;
+            }
+        };
+
+        if !accept_key_change {
+            fail!(
+                InvalidKey,
+                "[{}] consensus key {} differs from {} recorded with the consensus state: \
+                 if the key was changed deliberately, restart with `--accept-key-change`",
+                chain.name(),
+                bech32_key(chain, public_key),
+                bech32_key(chain, recorded_key)
+            );
+        }
+
+        warn!(
+            "[{}] consensus key changed from {} to {} (accepted with `--accept-key-change`)",
+            chain.name(),
+            bech32_key(chain, recorded_key),
+            bech32_key(chain, public_key)
+        );
+
+        state.record_pub_key(public_key)?;
+    }
+
+    Ok(())
+}
+
+/// Serialize a consensus key of the given chain as Bech32, using the chain's
+/// prefix if it uses Bech32 keys
+fn bech32_key(chain: &Chain, public_key: tendermint::PublicKey) -> String {
+    let format = match chain.keyring.format() {
+        format @ keyring::Format::Bech32 { .. } => format.clone(),
+        _ => keyring::Format::bech32("cosmosvalconspub"),
+    };
+
+    format.serialize(tendermint::TendermintKey::ConsensusKey(public_key))
+}
+
+/// Check a configuration is internally consistent before reloading it
+fn validate_config(config: &KmsConfig) -> Result<(), Error> {
+    let mut chain_ids = BTreeSet::new();
+
+    for chain_config in &config.chain {
+        if !chain_ids.insert(&chain_config.id) {
+            fail!(
+                ConfigError,
+                "chain ID already registered: {}",
+                chain_config.id
+            );
+        }
+    }
+
+    for chain_id in config.validator.iter().flat_map(|v| &v.chain_ids) {
+        if !chain_ids.contains(chain_id) {
+            fail!(
+                ConfigError,
+                "unregistered chain: {} (add it to tmkms.toml's [[chain]] section)",
+                chain_id
+            );
+        }
+    }
+
+    check_aliases(config)?;
+    check_state_files(config)
+}
+
+/// Ensure each alias of a chain names only that chain, i.e. isn't the ID or
+/// an alias of another chain (or the chain's own ID)
+fn check_aliases(config: &KmsConfig) -> Result<(), Error> {
+    let mut chain_ids: BTreeMap<&Id, &Id> = config
+        .chain
+        .iter()
+        .map(|chain_config| (&chain_config.id, &chain_config.id))
+        .collect();
+
+    for chain_config in &config.chain {
+        for alias in &chain_config.aliases {
+            if let Some(other) = chain_ids.insert(alias, &chain_config.id) {
+                fail!(
+                    ConfigError,
+                    "chain {}: alias {} is already the ID or an alias of chain {}",
+                    chain_config.id,
+                    alias,
+                    other
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Ensure no two chains (or validators of the same chain) keep their
+/// consensus state in the same JSON file, as each one's double signing
+/// protection must be independent
+fn check_state_files(config: &KmsConfig) -> Result<(), Error> {
+    let mut state_files = BTreeMap::new();
+
+    for (validator, chain_config) in chain_configs(config) {
+        let name = name(&chain_config.id, validator);
+
+        if chain_config.state_backend != StateBackend::Json {
+            // Labelled validators only have state of their own in JSON files
+            if validator.is_some() {
+                fail!(
+                    ConfigError,
+                    "chain {}: labelled validators require `state_backend = \"json\"`",
+                    name
+                );
+            }
+
+            continue;
+        }
+
+        let state_file = state_file_path(&chain_config);
+
+        if let Some(other) = state_files.insert(state_file.clone(), name.clone()) {
+            fail!(
+                ConfigError,
+                "chains {} and {} share the state file {}",
+                other,
+                name,
+                state_file.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the configuration of each chain to register: every configured chain
+/// (for its unlabelled validator, if any), and each chain signed for by a
+/// labelled validator, along with the validator's label (see
+/// [`KmsConfig::signers`])
+fn chain_configs(config: &KmsConfig) -> Vec<(Option<&str>, ChainConfig)> {
+    config
+        .chain
+        .iter()
+        .map(|chain_config| (None, chain_config.clone()))
+        .chain(
+            config
+                .signers()
+                .into_iter()
+                .filter(|(validator, _)| validator.is_some()),
+        )
+        .collect()
+}
+
+/// Load the given chain for the validator with the given label (if any),
+/// sharing audit logs already opened for other chains
+fn load_chain(
+    config: &KmsConfig,
+    chain_config: &ChainConfig,
+    validator: Option<&str>,
+    audit_logs: &mut BTreeMap<PathBuf, Arc<AuditLog>>,
+) -> Result<Chain, Error> {
+    let mut chain = Chain::from_config(chain_config)?;
+    chain.validator = validator.map(ToOwned::to_owned);
+
+    if let Some(audit_config) = chain_config
+        .audit_log
+        .as_ref()
+        .or(config.audit_log.as_ref())
+    {
+        chain.audit_log = Some(open_audit_log(audit_logs, audit_config)?);
+    }
+
+    Ok(chain)
+}
+
+/// Register a chain for each chain in the new configuration in `new_chains`:
+/// chains which remain configured are carried over from `old_chains` with
+/// empty keyrings and the new policies, and newly configured chains are
+/// loaded. Returns the IDs of the new chains.
+fn stage_chains(
+    old_chains: &Registry,
+    new_chains: &mut Registry,
+    old_config: &KmsConfig,
+    config: &KmsConfig,
+) -> Result<Vec<Id>, Error> {
+    // New chains share the audit logs already open for existing ones
+    let mut audit_logs = old_chains
+        .chains()
+        .filter_map(|chain| chain.audit_log.clone())
+        .map(|audit_log| (audit_log.path().to_owned(), audit_log))
+        .collect();
+
+    let mut added = vec![];
+    let old_chain_configs = chain_configs(old_config);
+
+    for (validator, chain_config) in chain_configs(config) {
+        let chain = match old_chains.get_validator_chain(&chain_config.id, validator) {
+            Some(old_chain) => {
+                let old_chain_config = old_chain_configs
+                    .iter()
+                    .find(|(v, c)| *v == validator && c.id == old_chain.id)
+                    .map(|(_, c)| c);
+
+                if old_chain_config.map_or(false, |old| {
+                    !same_storage(old_config, old, config, &chain_config)
+                }) {
+                    warn!(
+                        "chain {}: changes to state storage or audit log settings require a restart",
+                        old_chain.name()
+                    );
+                }
+
+                old_chain.reconfigure(&chain_config)
+            }
+            None => {
+                let chain = load_chain(config, &chain_config, validator, &mut audit_logs)?;
+
+                match validator {
+                    Some(_) => info!("chain {} added", chain.name()),
+                    None => added.push(chain.id.clone()),
+                }
+
+                chain
+            }
+        };
+
+        new_chains.register_chain(chain)?;
+    }
+
+    Ok(added)
+}
+
+/// Do two configurations for a chain store its state and audit log the same
+/// way?
+fn same_storage(
+    old_config: &KmsConfig,
+    old: &ChainConfig,
+    config: &KmsConfig,
+    new: &ChainConfig,
+) -> bool {
+    old.state_backend == new.state_backend
+        && old.state_file == new.state_file
+        && old.state_db_path == new.state_db_path
+        && old.state_redis == new.state_redis
+        && old.audit_log.as_ref().or(old_config.audit_log.as_ref())
+            == new.audit_log.as_ref().or(config.audit_log.as_ref())
+}
+
+/// Open the audit log with the given configuration, reusing it if it's
+/// already been opened for another chain
+fn open_audit_log(
+    audit_logs: &mut BTreeMap<PathBuf, Arc<AuditLog>>,
+    config: &AuditLogConfig,
+) -> Result<Arc<AuditLog>, Error> {
+    if let Some(audit_log) = audit_logs.get(&config.path) {
+        return Ok(audit_log.clone());
+    }
+
+    let audit_log = Arc::new(AuditLog::open(config)?);
+    info!("writing audit log to {}", config.path.display());
+    audit_logs.insert(config.path.clone(), audit_log.clone());
+    Ok(audit_log)
 }
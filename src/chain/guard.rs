@@ -1,5 +1,5 @@
 use super::{Chain, Id, Registry};
-use std::sync::RwLockReadGuard;
+use std::sync::{Arc, RwLockReadGuard};
 
 /// Wrapper for a `RwLockReadGuard<'static, Registry>`, allowing access to
 /// global information about particular Tendermint networks / "chains"
@@ -16,4 +16,33 @@ impl<'lock> Guard<'lock> {
     pub fn get_chain(&self, chain_id: &Id) -> Option<&Chain> {
         self.0.get_chain(chain_id)
     }
+
+    /// Get a shared reference to a particular chain (if registered), which
+    /// remains usable after this guard is dropped
+    pub fn chain(&self, chain_id: &Id) -> Option<Arc<Chain>> {
+        self.0.chain(chain_id)
+    }
+
+    /// Get information about a particular chain ID, as signed for by the
+    /// given validator (its unlabelled validator if `None`)
+    pub fn get_validator_chain(&self, chain_id: &Id, validator: Option<&str>) -> Option<&Chain> {
+        self.0.get_validator_chain(chain_id, validator)
+    }
+
+    /// Get a shared reference to a particular chain, as signed for by the
+    /// given validator (its unlabelled validator if `None`), which remains
+    /// usable after this guard is dropped
+    pub fn validator_chain(&self, chain_id: &Id, validator: Option<&str>) -> Option<Arc<Chain>> {
+        self.0.validator_chain(chain_id, validator)
+    }
+
+    /// Iterate over the registered chains
+    pub fn chains(&self) -> impl Iterator<Item = &Chain> {
+        self.0.chains()
+    }
+
+    /// Get the ID of the registered chain with the given chain ID or alias
+    pub fn canonical_id(&self, chain_id: &str) -> Option<&Id> {
+        self.0.canonical_id(chain_id)
+    }
 }
@@ -0,0 +1,205 @@
+//! Chain IDs
+//!
+//! CometBFT only requires a chain ID to be non-empty and at most
+//! [`COMETBFT_MAX_LENGTH`] bytes long (`MaxChainIDLen`), and chains use
+//! uppercase letters, dots and other punctuation in theirs. IDs are accepted
+//! as long as they're printable ASCII without path separators (they name
+//! state files), up to [`MAX_LENGTH`] bytes: the limit of the validator's
+//! protocol version is checked against its configuration, as it can be
+//! raised for chains which raised it. IDs are never normalized (e.g.
+//! lowercased), so they go into sign bytes exactly as configured.
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    convert::TryFrom,
+    fmt::{self, Debug, Display},
+    str::FromStr,
+};
+
+/// Longest chain ID accepted by CometBFT (and Tendermint): `MaxChainIDLen`
+/// in `types/genesis.go`
+pub const COMETBFT_MAX_LENGTH: usize = 50;
+
+/// Longest chain ID tmkms accepts at all
+pub const MAX_LENGTH: usize = 128;
+
+/// Chain ID (e.g. `cosmoshub-4`)
+#[derive(Clone, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub struct Id(String);
+
+impl Id {
+    /// Get the chain ID as a `str`
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Get the chain ID as raw bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    /// Get the length of the chain ID in bytes
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Is the chain ID empty? (never, once parsed)
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Check the given chain ID is valid, returning the rule it breaks if not
+pub fn validate(id: &str) -> Result<(), String> {
+    if id.is_empty() {
+        return Err("it's empty".to_owned());
+    }
+
+    for (i, c) in id.chars().enumerate() {
+        if !c.is_ascii() {
+            return Err(format!("character {} ({:?}) isn't ASCII", i + 1, c));
+        }
+
+        if c.is_ascii_whitespace() || c.is_ascii_control() {
+            return Err(format!(
+                "character {} ({:?}) is whitespace or a control character",
+                i + 1,
+                c
+            ));
+        }
+
+        if c == '/' || c == '\\' {
+            return Err(format!("character {} ({:?}) is a path separator", i + 1, c));
+        }
+    }
+
+    if id.len() > MAX_LENGTH {
+        return Err(format!(
+            "it's {} characters long (at most {} are allowed)",
+            id.len(),
+            MAX_LENGTH
+        ));
+    }
+
+    Ok(())
+}
+
+impl TryFrom<String> for Id {
+    type Error = Error;
+
+    fn try_from(id: String) -> Result<Self, Error> {
+        validate(&id).map_err(|e| format_err!(ChainIdError, "invalid chain ID {:?}: {}", id, e))?;
+        Ok(Id(id))
+    }
+}
+
+impl TryFrom<&str> for Id {
+    type Error = Error;
+
+    fn try_from(id: &str) -> Result<Self, Error> {
+        Self::try_from(id.to_owned())
+    }
+}
+
+impl FromStr for Id {
+    type Err = Error;
+
+    fn from_str(id: &str) -> Result<Self, Error> {
+        Self::try_from(id.to_owned())
+    }
+}
+
+impl From<Id> for String {
+    fn from(id: Id) -> String {
+        id.0
+    }
+}
+
+impl AsRef<str> for Id {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "chain::Id({})", self.0)
+    }
+}
+
+impl Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for Id {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Id {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::try_from(String::deserialize(deserializer)?)
+            .map_err(|e| D::Error::custom(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_ids_cometbft_accepts() {
+        for id in &[
+            "cosmoshub-4",
+            "Oasis.Mainnet-1",
+            "evmos_9001-2",
+            "a",
+            "chain:id+with@punctuation",
+            &"x".repeat(COMETBFT_MAX_LENGTH),
+            &"long-chain-id-".repeat(5)[..64],
+            &"y".repeat(MAX_LENGTH),
+        ] {
+            let parsed = id.parse::<Id>().unwrap();
+
+            // Never normalized
+            assert_eq!(parsed.as_str(), *id);
+            assert_eq!(parsed.as_bytes(), id.as_bytes());
+        }
+    }
+
+    #[test]
+    fn rejection_names_the_rule() {
+        let err = |id: &str| id.parse::<Id>().unwrap_err().to_string();
+
+        assert!(err("").ends_with("invalid chain ID \"\": it's empty"));
+        assert!(err("chaîne-1").ends_with("character 4 ('î') isn't ASCII"));
+        assert!(
+            err("cosmos hub").ends_with("character 7 (' ') is whitespace or a control character")
+        );
+        assert!(err("tab\t1").ends_with("character 4 ('\\t') is whitespace or a control character"));
+        assert!(err("../state").ends_with("character 3 ('/') is a path separator"));
+        assert!(err(&"z".repeat(MAX_LENGTH + 1))
+            .ends_with("it's 129 characters long (at most 128 are allowed)"));
+    }
+
+    #[test]
+    fn deserialize() {
+        #[derive(Deserialize)]
+        struct Config {
+            id: Id,
+        }
+
+        let config: Config = toml::from_str(r#"id = "Cosmos.Hub-4""#).unwrap();
+        assert_eq!(config.id.to_string(), "Cosmos.Hub-4");
+
+        let err = toml::from_str::<Config>(r#"id = "日本-1""#).err().unwrap();
+        assert!(err.to_string().contains("character 1 ('日') isn't ASCII"));
+    }
+}
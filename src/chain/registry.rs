@@ -8,14 +8,24 @@ use crate::{
     Map,
 };
 use once_cell::sync::Lazy;
-use std::sync::RwLock;
+use std::{
+    fmt::Display,
+    mem,
+    sync::{Arc, RwLock},
+};
+use tendermint::TendermintKey;
 
 /// State of Tendermint blockchain networks
 pub static REGISTRY: Lazy<GlobalRegistry> = Lazy::new(GlobalRegistry::default);
 
-/// Registry of blockchain networks known to the KMS
-#[derive(Default)]
-pub struct Registry(Map<Id, Chain>);
+/// Registry of blockchain networks known to the KMS.
+///
+/// Chains are reference counted so sessions can keep using a chain after
+/// releasing the registry lock (see [`Guard::chain`]). A chain is registered
+/// once for its unlabelled validator, and once more for each labelled
+/// validator of it the KMS signs for (see [`Chain::validator`]).
+#[derive(Clone, Default)]
+pub struct Registry(Map<(Id, Option<String>), Arc<Chain>>);
 
 impl Registry {
     /// Add an account key to a keyring for a chain stored in the registry
@@ -24,62 +34,195 @@ impl Registry {
         chain_id: &Id,
         signer: keyring::ecdsa::Signer,
     ) -> Result<(), Error> {
-        let chain = self.0.get_mut(chain_id).ok_or_else(|| {
-            format_err!(
-                InvalidKey,
-                "can't add ECDSA signer {} to unregistered chain: {}",
-                signer.provider(),
-                chain_id
-            )
-        })?;
+        let chain = self.chain_mut(chain_id, None, "ECDSA", signer.provider().describe())?;
 
         chain.keyring.add_ecdsa(signer)
     }
 
     /// Add a consensus key to a keyring for a chain stored in the registry
+    /// (see [`KeyRing::add_consensus_ed25519`](keyring::KeyRing::add_consensus_ed25519))
     pub fn add_consensus_key(
         &mut self,
         chain_id: &Id,
         signer: keyring::ed25519::Signer,
     ) -> Result<(), Error> {
-        let chain = self.0.get_mut(chain_id).ok_or_else(|| {
+        self.add_validator_consensus_key(chain_id, None, signer)
+    }
+
+    /// Add a consensus key to the keyring of the given validator of a chain
+    /// stored in the registry (its unlabelled validator if `None`)
+    pub fn add_validator_consensus_key(
+        &mut self,
+        chain_id: &Id,
+        validator: Option<&str>,
+        signer: keyring::ed25519::Signer,
+    ) -> Result<(), Error> {
+        let chain = self.chain_mut(chain_id, validator, "Ed25519", signer.provider().describe())?;
+
+        chain.keyring.add_consensus_ed25519(signer)
+    }
+
+    /// Add an Ed25519 consensus key which can be rotated at runtime, either as
+    /// the active key or a standby key (see
+    /// [`KeyRing::add_rotatable_ed25519`](keyring::KeyRing::add_rotatable_ed25519))
+    pub fn add_rotatable_consensus_key(
+        &mut self,
+        chain_id: &Id,
+        key_id: &str,
+        signer: keyring::ed25519::Signer,
+        active: bool,
+    ) -> Result<(), Error> {
+        let chain = self.chain_mut(chain_id, None, "Ed25519", signer.provider().describe())?;
+
+        chain.keyring.add_rotatable_ed25519(key_id, signer, active)
+    }
+
+    /// Add an ECDSA (secp256k1) consensus key to a keyring for a chain stored
+    /// in the registry
+    pub fn add_ecdsa_consensus_key(
+        &mut self,
+        chain_id: &Id,
+        signer: keyring::ecdsa::Signer,
+    ) -> Result<(), Error> {
+        self.add_validator_ecdsa_consensus_key(chain_id, None, signer)
+    }
+
+    /// Add an ECDSA (secp256k1) consensus key to the keyring of the given
+    /// validator of a chain stored in the registry (its unlabelled validator
+    /// if `None`)
+    pub fn add_validator_ecdsa_consensus_key(
+        &mut self,
+        chain_id: &Id,
+        validator: Option<&str>,
+        signer: keyring::ecdsa::Signer,
+    ) -> Result<(), Error> {
+        let chain = self.chain_mut(chain_id, validator, "ECDSA", signer.provider().describe())?;
+
+        chain.keyring.add_ecdsa(signer)
+    }
+
+    /// Add the key a signing provider supplied by the application has for a
+    /// chain stored in the registry: an Ed25519 or secp256k1 consensus key,
+    /// or a secp256k1 account key, as returned by its
+    /// [`SignerProvider::public_key`](keyring::SignerProvider::public_key)
+    pub fn add_provider(
+        &mut self,
+        chain_id: &Id,
+        provider: Arc<dyn keyring::SignerProvider>,
+    ) -> Result<(), Error> {
+        let public_key = provider.public_key(chain_id)?;
+
+        if public_key.public_key().ed25519().is_some() {
+            let signer = keyring::ed25519::Signer::from_provider(provider, public_key)?;
+            self.add_consensus_key(chain_id, signer)
+        } else {
+            let signer = keyring::ecdsa::Signer::from_provider(provider, public_key)?;
+
+            match public_key {
+                TendermintKey::AccountKey(_) => self.add_account_key(chain_id, signer),
+                TendermintKey::ConsensusKey(_) => self.add_ecdsa_consensus_key(chain_id, signer),
+            }
+        }
+    }
+
+    /// Get a chain to add a key to, which must not yet be in use by a session
+    fn chain_mut(
+        &mut self,
+        chain_id: &Id,
+        validator: Option<&str>,
+        key_type: &str,
+        provider: impl Display,
+    ) -> Result<&mut Chain, Error> {
+        let name = super::name(chain_id, validator);
+
+        let chain = self.0.get_mut(&key(chain_id, validator)).ok_or_else(|| {
             format_err!(
                 InvalidKey,
-                "can't add Ed25519 signer {} to unregistered chain: {}",
-                signer.provider(),
-                chain_id
+                "can't add {} signer {} to unregistered chain: {}",
+                key_type,
+                provider,
+                name
             )
         })?;
 
-        chain.keyring.add_ed25519(signer)
+        Arc::get_mut(chain).ok_or_else(|| {
+            format_err!(
+                InvalidKey,
+                "can't add {} signer {} to chain {} while it's in use",
+                key_type,
+                provider,
+                name
+            )
+            .into()
+        })
     }
 
     /// Register a `Chain` with the registry
     pub fn register_chain(&mut self, chain: Chain) -> Result<(), Error> {
-        let chain_id = chain.id.clone();
+        let name = chain.name();
+        let key = key(&chain.id, chain.validator.as_deref());
 
-        if self.0.insert(chain_id.clone(), chain).is_none() {
+        if self.0.insert(key, Arc::new(chain)).is_none() {
             Ok(())
         } else {
             // TODO(tarcieri): handle updating the set of registered chains
-            fail!(ConfigError, "chain ID already registered: {}", chain_id);
+            fail!(ConfigError, "chain ID already registered: {}", name);
         }
     }
 
+    /// Replace a registered chain with the given one (with the same ID and
+    /// validator)
+    pub(crate) fn replace_chain(&mut self, chain: Chain) {
+        let key = key(&chain.id, chain.validator.as_deref());
+        self.0.insert(key, Arc::new(chain));
+    }
+
     /// Get information about a particular chain ID (if registered)
     pub fn get_chain(&self, chain_id: &Id) -> Option<&Chain> {
-        self.0.get(chain_id)
+        self.get_validator_chain(chain_id, None)
     }
+
+    /// Get information about a particular chain ID, as signed for by the
+    /// given validator (its unlabelled validator if `None`)
+    pub fn get_validator_chain(&self, chain_id: &Id, validator: Option<&str>) -> Option<&Chain> {
+        self.0.get(&key(chain_id, validator)).map(AsRef::as_ref)
+    }
+
+    /// Get a shared reference to a particular chain (if registered)
+    pub fn chain(&self, chain_id: &Id) -> Option<Arc<Chain>> {
+        self.validator_chain(chain_id, None)
+    }
+
+    /// Get a shared reference to a particular chain, as signed for by the
+    /// given validator (its unlabelled validator if `None`)
+    pub fn validator_chain(&self, chain_id: &Id, validator: Option<&str>) -> Option<Arc<Chain>> {
+        self.0.get(&key(chain_id, validator)).cloned()
+    }
+
+    /// Iterate over the registered chains
+    pub fn chains(&self) -> impl Iterator<Item = &Chain> {
+        self.0.values().map(AsRef::as_ref)
+    }
+
+    /// Get the ID of the registered chain with the given chain ID or alias
+    pub fn canonical_id(&self, chain_id: &str) -> Option<&Id> {
+        self.chains()
+            .find(|chain| chain.id.as_str() == chain_id || chain.has_alias(chain_id))
+            .map(|chain| &chain.id)
+    }
+}
+
+/// Get the registry key of the given chain, as signed for by the given
+/// validator
+fn key(chain_id: &Id, validator: Option<&str>) -> (Id, Option<String>) {
+    (chain_id.clone(), validator.map(ToOwned::to_owned))
 }
 
 /// Global registry of blockchain networks known to the KMS
-// NOTE: The `RwLock` is a bit of futureproofing as this data structure is for the
-// most part "immutable". New chains should be registered at boot time.
-// The only case in which this structure may change is in the event of
-// runtime configuration reloading, so the `RwLock` is included as
-// futureproofing for such a feature.
-//
-// See: <https://github.com/tendermint/kms/issues/183>
+// NOTE: This data structure is for the most part "immutable": chains are
+// registered at boot time, and only change when the configuration is reloaded
+// (see `chain::reload_config`), which takes the write lock just long enough to
+// swap in a new registry.
 #[derive(Default)]
 pub struct GlobalRegistry(pub(super) RwLock<Registry>);
 
@@ -96,4 +239,10 @@ impl GlobalRegistry {
         let mut registry = self.0.write().unwrap();
         registry.register_chain(chain)
     }
+
+    /// Replace the contents of the registry, returning the old contents
+    pub(crate) fn replace(&self, registry: Registry) -> Registry {
+        // TODO(tarcieri): better handle `PoisonError` here?
+        mem::replace(&mut *self.0.write().unwrap(), registry)
+    }
 }
@@ -5,53 +5,158 @@
 
 mod error;
 pub mod hook;
+pub mod import;
+pub mod lock;
+pub mod store;
+
+pub use self::{
+    error::{StateError, StateErrorKind},
+    lock::StateLock,
+    store::{JsonStateStore, NullStateStore, StateStore},
+};
 
-pub use self::error::{StateError, StateErrorKind};
+#[cfg(feature = "redis")]
+pub use self::store::RedisStateStore;
+#[cfg(feature = "sqlite")]
+pub use self::store::SqliteStateStore;
 
 use crate::{
-    error::{Error, ErrorKind::*},
+    amino_types::SignedMsgType,
+    error::{Error, ErrorKind::ConfigError},
     prelude::*,
 };
-use std::{
-    fs,
-    io::{self, prelude::*},
-    path::{Path, PathBuf},
-};
-use tempfile::NamedTempFile;
-use tendermint::consensus;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use subtle_encoding::hex;
+use tendermint::{consensus, PublicKey, Time};
 
 /// State tracking for double signing prevention
 pub struct State {
     consensus_state: consensus::State,
-    state_file_path: PathBuf,
+
+    /// Payload signed at the consensus state's height/round/step (if known)
+    signed_payload: Option<SignedPayload>,
+
+    /// Signature over the signed payload. Only kept in memory: after a
+    /// restart, retries of the signed payload are signed again.
+    signature: Option<Vec<u8>>,
+
+    store: Box<dyn StateStore>,
+}
+
+/// Consensus steps, in the order they're signed within a height/round and
+/// numbered as by Tendermint's `FilePV` (a step of 0 means nothing has been
+/// signed at the height/round yet).
+///
+/// Requests for a step before the last one signed at the same height/round
+/// (e.g. a proposal after a precommit) are refused.
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Step {
+    /// Proposals
+    Propose = 1,
+
+    /// Prevotes
+    Prevote = 2,
+
+    /// Precommits
+    Precommit = 3,
+}
+
+impl Step {
+    /// Get the value of this step in a consensus state
+    pub fn value(self) -> i8 {
+        self as i8
+    }
+
+    /// Renumber a step persisted by a version of tmkms which numbered steps
+    /// from 0 (0 = proposal, 1 = prevote, 2 = precommit).
+    ///
+    /// The step is never lowered (a step of 3 could only have been numbered
+    /// as by Tendermint), so state which was already renumbered is at worst
+    /// read as a later step, refusing rather than permitting a signature.
+    pub fn renumber_legacy(step: i8) -> i8 {
+        step.saturating_add(1).min(Step::Precommit.value())
+    }
+}
+
+impl From<SignedMsgType> for Step {
+    fn from(msg_type: SignedMsgType) -> Step {
+        match msg_type {
+            SignedMsgType::Proposal => Step::Propose,
+            SignedMsgType::PreVote => Step::Prevote,
+            SignedMsgType::PreCommit => Step::Precommit,
+        }
+    }
+}
+
+/// Payload signed at a particular height/round/step, kept so a retried
+/// request (e.g. after a network hiccup) can be told apart from a
+/// conflicting one
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignedPayload {
+    /// Bytes which were signed
+    pub sign_bytes: Vec<u8>,
+
+    /// Timestamp of the signed vote or proposal (if any)
+    pub timestamp: Option<Time>,
+
+    /// Type of the signed message (unknown for payloads persisted before it
+    /// was recorded, unless it can be told from the step)
+    pub msg_type: Option<SignedMsgType>,
+}
+
+impl SignedPayload {
+    /// SHA-256 of the signed bytes (as uppercase hex), which identifies
+    /// exactly what was signed, e.g. in a double signing postmortem
+    pub fn sign_bytes_hash(&self) -> String {
+        sign_bytes_hash(&self.sign_bytes)
+    }
+}
+
+/// SHA-256 of the given sign bytes (as uppercase hex)
+pub fn sign_bytes_hash(sign_bytes: &[u8]) -> String {
+    String::from_utf8(hex::encode_upper(Sha256::digest(sign_bytes))).unwrap()
 }
 
 impl State {
-    /// Load the state from the given path
+    /// Load the state from the given JSON state file
     pub fn load_state<P>(path: P) -> Result<Self, Error>
     where
         P: AsRef<Path>,
     {
-        match fs::read_to_string(path.as_ref()) {
-            Ok(state_json) => {
-                let consensus_state = serde_json::from_str(&state_json).map_err(|e| {
-                    format_err!(
-                        ParseError,
-                        "error parsing {}: {}",
-                        path.as_ref().display(),
-                        e
-                    )
-                })?;
+        Self::load(Box::new(JsonStateStore::new(path.as_ref())))
+    }
 
-                Ok(Self {
-                    consensus_state,
-                    state_file_path: path.as_ref().to_owned(),
-                })
-            }
-            Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                Self::write_initial_state(path.as_ref())
-            }
-            Err(e) => Err(Error::from(e)),
+    /// Load the state from the given store, initializing it if empty
+    pub fn load(mut store: Box<dyn StateStore>) -> Result<Self, Error> {
+        match store.load_signed()? {
+            Some((consensus_state, signed_payload)) => Ok(Self {
+                consensus_state,
+                signed_payload,
+                signature: None,
+                store,
+            }),
+            None => Self::write_initial_state(store),
+        }
+    }
+
+    /// Load the state from the given store, failing if it's empty (i.e. the
+    /// state was never initialized, or has been lost)
+    pub fn load_existing(mut store: Box<dyn StateStore>) -> Result<Self, Error> {
+        match store.load_signed()? {
+            Some((consensus_state, signed_payload)) => Ok(Self {
+                consensus_state,
+                signed_payload,
+                signature: None,
+                store,
+            }),
+            None => fail!(
+                ConfigError,
+                "no consensus state found in {} and `strict_state` is enabled: restore it \
+                 from a backup, or run `tmkms state init <chain_id> --height <height>` with \
+                 the height to resume signing from",
+                store
+            ),
         }
     }
 
@@ -60,12 +165,89 @@ impl State {
         &self.consensus_state
     }
 
+    /// Borrow the payload signed at the current consensus state (if known)
+    pub fn signed_payload(&self) -> Option<&SignedPayload> {
+        self.signed_payload.as_ref()
+    }
+
+    /// Get the chain's consensus public key recorded with the state (if any)
+    pub fn pub_key(&self) -> Option<PublicKey> {
+        self.store.pub_key()
+    }
+
+    /// Record the chain's consensus public key with the state, e.g. the first
+    /// time it's loaded or after the key was rotated
+    pub fn record_pub_key(&mut self, pub_key: PublicKey) -> Result<(), Error> {
+        self.store.set_pub_key(pub_key);
+        self.sync_to_disk()
+    }
+
+    /// Check and update the chain's height, round, and step before signing
+    /// the given payload at them.
+    ///
+    /// If the payload last signed at the same height/round/step is known, the
+    /// request is only permitted if it's a retry of it (i.e. the sign bytes
+    /// are identical), in which case the signature over it is returned if
+    /// it's still known. Any other payload at the same height/round/step is
+    /// a double sign.
+    pub fn update_signed_state(
+        &mut self,
+        new_state: consensus::State,
+        payload: SignedPayload,
+    ) -> Result<Option<Vec<u8>>, StateError> {
+        if let Some(signed_payload) = &self.signed_payload {
+            if is_same_hrs(&new_state, &self.consensus_state) {
+                if signed_payload.sign_bytes == payload.sign_bytes {
+                    return Ok(self.signature.clone());
+                }
+
+                fail!(
+                    StateErrorKind::DoubleSign,
+                    "Attempting to sign a conflicting payload at height:{} round:{} step:{} old block id:{} new block {}",
+                    new_state.height,
+                    new_state.round,
+                    new_state.step,
+                    self.consensus_state.block_id_prefix(),
+                    new_state.block_id_prefix()
+                );
+            }
+        }
+
+        self.check_and_store(new_state, Some(payload))?;
+        Ok(None)
+    }
+
+    /// Remember the signature over the given sign bytes, so retries of them
+    /// can be answered with it. Ignored unless they're the payload signed at
+    /// the current consensus state (i.e. it hasn't changed since).
+    pub fn record_signature(&mut self, sign_bytes: &[u8], signature: &[u8]) {
+        if self
+            .signed_payload
+            .as_ref()
+            .map(|p| p.sign_bytes.as_slice())
+            == Some(sign_bytes)
+        {
+            self.signature = Some(signature.to_vec());
+        }
+    }
+
     /// Check and update the chain's height, round, and step
+    pub fn update_consensus_state(
+        &mut self,
+        new_state: consensus::State,
+    ) -> Result<(), StateError> {
+        self.check_and_store(new_state, None)
+    }
+
+    /// Check the chain's height, round, and step don't regress and that the
+    /// new state isn't a double sign, then store it along with the payload
+    /// signed at it (if any)
     // TODO(tarcieri): rewrite this logic to follow Tendermint spec and be clippy-friendly
     #[allow(clippy::comparison_chain)]
-    pub fn update_consensus_state(
+    fn check_and_store(
         &mut self,
         new_state: consensus::State,
+        payload: Option<SignedPayload>,
     ) -> Result<(), StateError> {
         // TODO(tarcieri): rewrite this using `PartialOrd` impl on `consensus::State`
         if new_state.height < self.consensus_state.height {
@@ -88,7 +270,7 @@ impl State {
                 if new_state.step < self.consensus_state.step {
                     fail!(
                         StateErrorKind::StepRegression,
-                        "round regression at height:{} round:{} last step:{} new step:{}",
+                        "step regression at height:{} round:{} last step:{} new step:{}",
                         new_state.height,
                         new_state.round,
                         self.consensus_state.step,
@@ -116,18 +298,41 @@ impl State {
         }
 
         self.consensus_state = new_state;
+        self.signed_payload = payload;
+        self.signature = None;
 
         self.sync_to_disk().map_err(|e| {
             format_err!(
                 StateErrorKind::SyncError,
                 "error writing state to {}: {}",
-                self.state_file_path.display(),
+                self.store,
                 e
             )
         })?;
         Ok(())
     }
 
+    /// Replace the consensus state without any of the checks performed by
+    /// [`State::update_consensus_state`], e.g. when restoring a validator
+    /// from a backup
+    pub fn set_consensus_state(&mut self, new_state: consensus::State) -> Result<(), Error> {
+        self.restore(new_state, None)
+    }
+
+    /// Replace the consensus state and the payload signed at it (if known)
+    /// without any checks, e.g. when importing the state of a validator
+    /// which signed with another signer
+    pub fn restore(
+        &mut self,
+        new_state: consensus::State,
+        payload: Option<SignedPayload>,
+    ) -> Result<(), Error> {
+        self.consensus_state = new_state;
+        self.signed_payload = payload;
+        self.signature = None;
+        self.sync_to_disk()
+    }
+
     /// Update the internal state from the output from a hook command
     pub fn update_from_hook_output(&mut self, output: hook::Output) -> Result<(), StateError> {
         let hook_height = output.latest_block_height.value();
@@ -142,6 +347,8 @@ impl State {
                     ..Default::default()
                 };
                 self.consensus_state = new_state;
+                self.signed_payload = None;
+                self.signature = None;
 
                 info!("updated block height from hook: {}", hook_height);
             } else {
@@ -162,16 +369,25 @@ impl State {
         Ok(())
     }
 
-    /// Write the initial state to the given path on disk
-    fn write_initial_state(path: &Path) -> Result<Self, Error> {
+    /// Write the initial state to the given store
+    fn write_initial_state(store: Box<dyn StateStore>) -> Result<Self, Error> {
         let consensus_state = tendermint::consensus::State {
             height: 0u32.into(),
             ..Default::default()
         };
 
-        let initial_state = Self {
+        warn!(
+            "no consensus state found in {}: starting from height/round/step {} \
+             (if this validator has signed before, stop now and restore the state, \
+             or set `strict_state = true` to refuse to start without it)",
+            store, consensus_state
+        );
+
+        let mut initial_state = Self {
             consensus_state,
-            state_file_path: path.to_owned(),
+            signed_payload: None,
+            signature: None,
+            store,
         };
 
         initial_state.sync_to_disk()?;
@@ -179,36 +395,31 @@ impl State {
         Ok(initial_state)
     }
 
-    /// Sync the current state to disk
-    fn sync_to_disk(&self) -> io::Result<()> {
+    /// Sync the current state to the backing store
+    fn sync_to_disk(&mut self) -> Result<(), Error> {
         debug!(
             "writing new consensus state to {}: {:?}",
-            self.state_file_path.display(),
-            &self.consensus_state
+            self.store, &self.consensus_state
         );
 
-        let json = serde_json::to_string(&self.consensus_state)?;
+        self.store
+            .store_signed(&self.consensus_state, self.signed_payload.as_ref())?;
 
-        let state_file_dir = self.state_file_path.parent().unwrap_or_else(|| {
-            panic!("state file cannot be root directory");
-        });
-
-        let mut state_file = NamedTempFile::new_in(state_file_dir)?;
-        state_file.write_all(json.as_bytes())?;
-        state_file.persist(&self.state_file_path)?;
-
-        debug!(
-            "successfully wrote new consensus state to {}",
-            self.state_file_path.display(),
-        );
+        debug!("successfully wrote new consensus state to {}", self.store);
 
         Ok(())
     }
 }
 
+/// Are the given consensus states at the same height, round, and step?
+fn is_same_hrs(a: &consensus::State, b: &consensus::State) -> bool {
+    (a.height, a.round, a.step) == (b.height, b.round, b.step)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
     use tendermint::block;
 
     const EXAMPLE_BLOCK_ID: &str =
@@ -245,7 +456,9 @@ mod tests {
             fn $name() {
                 State {
                     consensus_state: $old_state,
-                    state_file_path: EXAMPLE_PATH.into(),
+                    signed_payload: None,
+                    signature: None,
+                    store: Box::new(JsonStateStore::new(EXAMPLE_PATH)),
                 }
                 .update_consensus_state($new_state)
                 .unwrap();
@@ -260,7 +473,9 @@ mod tests {
             fn $name() {
                 let err = State {
                     consensus_state: $old_state,
-                    state_file_path: EXAMPLE_PATH.into(),
+                    signed_payload: None,
+                    signature: None,
+                    store: Box::new(JsonStateStore::new(EXAMPLE_PATH)),
                 }
                 .update_consensus_state($new_state)
                 .expect_err("expected StateErrorKind::DoubleSign but succeeded");
@@ -300,6 +515,20 @@ mod tests {
         state!(1, 0, 1, None)
     );
 
+    #[test]
+    fn step_regression() {
+        let err = State {
+            consensus_state: state!(1, 1, Step::Precommit.value(), None),
+            signed_payload: None,
+            signature: None,
+            store: Box::new(JsonStateStore::new(EXAMPLE_PATH)),
+        }
+        .update_consensus_state(state!(1, 1, Step::Prevote.value(), None))
+        .expect_err("expected StateErrorKind::StepRegression but succeeded");
+
+        assert_eq!(err.kind(), StateErrorKind::StepRegression);
+    }
+
     double_sign_test!(
         step_update_with_different_block_id_double_sign,
         state!(1, 1, 0, block_id!(EXAMPLE_BLOCK_ID)),
@@ -311,4 +540,460 @@ mod tests {
         state!(1, 1, 2, None),
         state!(1, 1, 2, block_id!(EXAMPLE_BLOCK_ID))
     );
+
+    #[test]
+    fn recover_from_partial_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        let mut state = State::load_state(&path).unwrap();
+        state.update_consensus_state(state!(1, 0, 0, None)).unwrap();
+        state.update_consensus_state(state!(2, 0, 0, None)).unwrap();
+
+        // Simulate a partial write of the state file
+        let json = fs::read(&path).unwrap();
+        fs::write(&path, &json[..json.len() / 2]).unwrap();
+
+        // Previous generation should be recovered from the backup
+        let state = State::load_state(&path).unwrap();
+        assert_eq!(state.consensus_state(), &state!(1, 0, 0, None));
+
+        // ...and the state file should be rewritten
+        assert_eq!(
+            JsonStateStore::new(&path).load().unwrap(),
+            Some(state!(1, 0, 0, None))
+        );
+    }
+
+    #[test]
+    fn recover_from_missing_state_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        let mut state = State::load_state(&path).unwrap();
+        state.update_consensus_state(state!(1, 0, 0, None)).unwrap();
+        state.update_consensus_state(state!(1, 0, 1, None)).unwrap();
+
+        fs::remove_file(&path).unwrap();
+
+        let state = State::load_state(&path).unwrap();
+        assert_eq!(state.consensus_state(), &state!(1, 0, 0, None));
+    }
+
+    #[test]
+    fn replace_backup_link_left_by_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+        let link_path = dir.path().join("test_priv_validator_state.json.bak.tmp");
+        fs::write(&link_path, b"stale").unwrap();
+
+        let mut state = State::load_state(&path).unwrap();
+        state.update_consensus_state(state!(1, 0, 0, None)).unwrap();
+        state.update_consensus_state(state!(2, 0, 0, None)).unwrap();
+        assert!(!link_path.exists());
+
+        let mut backup = JsonStateStore::new(dir.path().join("test_priv_validator_state.json.bak"));
+        assert_eq!(backup.load().unwrap(), Some(state!(1, 0, 0, None)));
+    }
+
+    #[test]
+    fn set_lower_consensus_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        let mut state = State::load_state(&path).unwrap();
+        state.update_consensus_state(state!(2, 1, 2, None)).unwrap();
+        state.set_consensus_state(state!(1, 0, 0, None)).unwrap();
+
+        let state = State::load_state(&path).unwrap();
+        assert_eq!(state.consensus_state(), &state!(1, 0, 0, None));
+    }
+
+    #[test]
+    fn load_existing_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        let err = State::load_existing(Box::new(JsonStateStore::new(&path)))
+            .err()
+            .expect("missing state loaded");
+        assert!(err.to_string().contains("tmkms state init"));
+        assert!(!path.exists());
+
+        let mut state = State::load_state(&path).unwrap();
+        state.update_consensus_state(state!(1, 0, 0, None)).unwrap();
+
+        let state = State::load_existing(Box::new(JsonStateStore::new(&path))).unwrap();
+        assert_eq!(state.consensus_state(), &state!(1, 0, 0, None));
+    }
+
+    /// Open a JSON state store authenticated with a test HMAC key
+    fn hmac_store(path: &Path, accept_tampered: bool) -> Box<dyn StateStore> {
+        Box::new(
+            JsonStateStore::new(path)
+                .with_hmac_key(vec![0x42; 32].into())
+                .accept_tampered(accept_tampered),
+        )
+    }
+
+    #[test]
+    fn hmac_state_load_after_manual_edit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        let mut state = State::load(hmac_store(&path, false)).unwrap();
+        state.update_consensus_state(state!(5, 0, 0, None)).unwrap();
+        state
+            .update_consensus_state(state!(10, 0, 0, None))
+            .unwrap();
+
+        let state = State::load(hmac_store(&path, false)).unwrap();
+        assert_eq!(state.consensus_state(), &state!(10, 0, 0, None));
+
+        // Lower the height by hand
+        let json = fs::read_to_string(&path).unwrap();
+        fs::write(&path, json.replace("\"10\"", "\"1\"")).unwrap();
+
+        let err = State::load(hmac_store(&path, false))
+            .err()
+            .expect("tampered state loaded");
+        assert!(err.to_string().contains("failed verification"));
+
+        // ...or strip the HMAC
+        fs::write(
+            &path,
+            serde_json::to_string(&state!(1, 0, 0, None)).unwrap(),
+        )
+        .unwrap();
+        assert!(State::load(hmac_store(&path, false)).is_err());
+    }
+
+    #[test]
+    fn hmac_state_accept_tampered() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        let mut state = State::load(hmac_store(&path, false)).unwrap();
+        state
+            .update_consensus_state(state!(10, 0, 0, None))
+            .unwrap();
+
+        let json = fs::read_to_string(&path).unwrap();
+        fs::write(&path, json.replace("\"10\"", "\"1\"")).unwrap();
+
+        let mut state = State::load(hmac_store(&path, true)).unwrap();
+        assert_eq!(state.consensus_state(), &state!(1, 0, 0, None));
+
+        // Storing the state again authenticates it
+        state.update_consensus_state(state!(2, 0, 0, None)).unwrap();
+        let state = State::load(hmac_store(&path, false)).unwrap();
+        assert_eq!(state.consensus_state(), &state!(2, 0, 0, None));
+    }
+
+    /// Payload with the given sign bytes
+    fn payload(sign_bytes: &[u8]) -> SignedPayload {
+        SignedPayload {
+            sign_bytes: sign_bytes.to_vec(),
+            timestamp: Some("2018-02-11T07:09:22.765Z".parse().unwrap()),
+            msg_type: Some(SignedMsgType::PreVote),
+        }
+    }
+
+    #[test]
+    fn retry_same_payload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        let mut state = State::load_state(&path).unwrap();
+        let hrs = state!(1, 0, 2, block_id!(EXAMPLE_BLOCK_ID));
+
+        assert_eq!(
+            state
+                .update_signed_state(hrs.clone(), payload(b"vote"))
+                .unwrap(),
+            None
+        );
+        state.record_signature(b"vote", b"signature");
+
+        // Retries are answered with the original signature
+        assert_eq!(
+            state
+                .update_signed_state(hrs.clone(), payload(b"vote"))
+                .unwrap(),
+            Some(b"signature".to_vec())
+        );
+
+        // ...which isn't recorded for anything but the payload signed
+        state.record_signature(b"other vote", b"other signature");
+        assert_eq!(
+            state
+                .update_signed_state(hrs.clone(), payload(b"vote"))
+                .unwrap(),
+            Some(b"signature".to_vec())
+        );
+
+        // After a restart, only the payload is known, so retries are signed again
+        let mut state = State::load_state(&path).unwrap();
+        assert_eq!(state.signed_payload(), Some(&payload(b"vote")));
+        assert_eq!(
+            state.update_signed_state(hrs, payload(b"vote")).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn conflicting_payload_double_sign() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        let mut state = State::load_state(&path).unwrap();
+        let hrs = state!(1, 0, 2, block_id!(EXAMPLE_BLOCK_ID));
+        state
+            .update_signed_state(hrs.clone(), payload(b"vote"))
+            .unwrap();
+
+        // Same block ID, but a different payload
+        let err = state
+            .update_signed_state(hrs.clone(), payload(b"conflicting vote"))
+            .unwrap_err();
+        assert_eq!(err.kind(), StateErrorKind::DoubleSign);
+
+        // ...including after a restart
+        let mut state = State::load_state(&path).unwrap();
+        let err = state
+            .update_signed_state(hrs, payload(b"conflicting vote"))
+            .unwrap_err();
+        assert_eq!(err.kind(), StateErrorKind::DoubleSign);
+
+        // Later heights are unaffected
+        state
+            .update_signed_state(state!(2, 0, 0, None), payload(b"proposal"))
+            .unwrap();
+    }
+
+    #[test]
+    fn nil_vote_retry_and_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        let mut state = State::load_state(&path).unwrap();
+        let hrs = state!(1, 0, Step::Precommit.value(), None);
+        state
+            .update_signed_state(hrs.clone(), payload(b"nil precommit"))
+            .unwrap();
+        state.record_signature(b"nil precommit", b"signature");
+
+        // Nil votes are retried and conflict exactly like votes for a block
+        assert_eq!(
+            state
+                .update_signed_state(hrs.clone(), payload(b"nil precommit"))
+                .unwrap(),
+            Some(b"signature".to_vec())
+        );
+
+        let err = state
+            .update_signed_state(hrs, payload(b"conflicting nil precommit"))
+            .unwrap_err();
+        assert_eq!(err.kind(), StateErrorKind::DoubleSign);
+
+        let err = state
+            .update_signed_state(
+                state!(1, 0, Step::Precommit.value(), block_id!(EXAMPLE_BLOCK_ID)),
+                payload(b"precommit"),
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), StateErrorKind::DoubleSign);
+    }
+
+    #[test]
+    fn hmac_state_covers_signed_payload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        let mut state = State::load(hmac_store(&path, false)).unwrap();
+        state
+            .update_signed_state(state!(10, 0, 0, None), payload(&[0xAB]))
+            .unwrap();
+
+        let state = State::load(hmac_store(&path, false)).unwrap();
+        assert_eq!(state.signed_payload(), Some(&payload(&[0xAB])));
+
+        // Replace the signed payload (and its hash) by hand
+        let json = fs::read_to_string(&path)
+            .unwrap()
+            .replace("\"AB\"", "\"CD\"")
+            .replace(&sign_bytes_hash(&[0xAB]), &sign_bytes_hash(&[0xCD]));
+        fs::write(&path, json).unwrap();
+
+        let err = State::load(hmac_store(&path, false))
+            .err()
+            .expect("tampered state loaded");
+        assert!(err.to_string().contains("failed verification"));
+    }
+
+    #[test]
+    fn signed_payload_type_and_hash_are_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        let mut state = State::load_state(&path).unwrap();
+        state
+            .update_signed_state(state!(1, 0, 2, None), payload(b"vote"))
+            .unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["version"], 2);
+        assert_eq!(json["msg_type"], "prevote");
+        assert_eq!(
+            json["signbytes_sha256"],
+            "AB274474A6AA82C100DDDCA63977FACB556F66F489FB558C044A456F9BA919CE"
+        );
+
+        let state = State::load_state(&path).unwrap();
+        assert_eq!(state.signed_payload(), Some(&payload(b"vote")));
+
+        // A hash which doesn't match the sign bytes is refused
+        fs::remove_file(dir.path().join("test_priv_validator_state.json.bak")).unwrap();
+        fs::write(
+            &path,
+            serde_json::to_string(&serde_json::json!({
+                "height": "1",
+                "round": "0",
+                "step": 2,
+                "block_id": null,
+                "signbytes": "766F7465",
+                "signbytes_sha256": sign_bytes_hash(b"other vote"),
+                "version": 2
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+
+        let err = State::load_state(&path)
+            .err()
+            .expect("mismatched hash loaded");
+        assert!(err.to_string().contains("doesn't match"));
+    }
+
+    #[test]
+    fn migrate_version_1_state_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        // As written before the message type and hash were recorded
+        fs::write(
+            &path,
+            r#"{"height":"1","round":"0","step":3,"block_id":null,"signbytes":"766F7465","timestamp":"2018-02-11T07:09:22.765Z"}"#,
+        )
+        .unwrap();
+
+        let state = State::load_state(&path).unwrap();
+        let payload = state.signed_payload().unwrap();
+        assert_eq!(payload.sign_bytes, b"vote");
+        assert_eq!(payload.msg_type, Some(SignedMsgType::PreCommit));
+
+        // The file is rewritten in the current format
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["version"], 2);
+        assert_eq!(json["msg_type"], "precommit");
+        assert_eq!(json["signbytes_sha256"], sign_bytes_hash(b"vote"));
+
+        // Files written by a newer version are refused
+        fs::write(
+            &path,
+            r#"{"height":"1","round":"0","step":3,"block_id":null,"version":3}"#,
+        )
+        .unwrap();
+        fs::remove_file(dir.path().join("test_priv_validator_state.json.bak")).unwrap();
+        assert!(State::load_state(&path).is_err());
+    }
+
+    #[test]
+    fn renumber_steps_of_unversioned_state_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        // A nil prevote, as written by versions of tmkms which numbered steps
+        // from 0 (i.e. 1 = prevote)
+        fs::write(
+            &path,
+            r#"{"height":"1","round":"0","step":1,"block_id":null}"#,
+        )
+        .unwrap();
+
+        let mut state = State::load_state(&path).unwrap();
+        assert_eq!(state.consensus_state().step, Step::Prevote.value());
+
+        // A prevote for a block at the same height/round is a double sign
+        let err = state
+            .update_consensus_state(state!(
+                1,
+                0,
+                Step::Prevote.value(),
+                block_id!(EXAMPLE_BLOCK_ID)
+            ))
+            .expect_err("expected StateErrorKind::DoubleSign but succeeded");
+        assert_eq!(err.kind(), StateErrorKind::DoubleSign);
+
+        // The file is rewritten with the renumbered step, so it's only
+        // renumbered once
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["version"], 2);
+        assert_eq!(json["step"], Step::Prevote.value());
+
+        let state = State::load_state(&path).unwrap();
+        assert_eq!(state.consensus_state().step, Step::Prevote.value());
+
+        // Steps are never lowered, nor renumbered past a precommit
+        assert_eq!(Step::renumber_legacy(0), Step::Propose.value());
+        assert_eq!(Step::renumber_legacy(2), Step::Precommit.value());
+        assert_eq!(Step::renumber_legacy(3), Step::Precommit.value());
+    }
+
+    #[test]
+    fn pub_key_is_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+        let pub_key = PublicKey::from_raw_ed25519(&[0x11; 32]).unwrap();
+
+        let mut state = State::load(hmac_store(&path, false)).unwrap();
+        assert_eq!(state.pub_key(), None);
+        state.record_pub_key(pub_key).unwrap();
+
+        // The key is kept as the state is updated
+        let mut state = State::load(hmac_store(&path, false)).unwrap();
+        assert_eq!(state.pub_key(), Some(pub_key));
+        state.update_consensus_state(state!(5, 0, 0, None)).unwrap();
+
+        let state = State::load(hmac_store(&path, false)).unwrap();
+        assert_eq!(state.pub_key(), Some(pub_key));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["version"], 2);
+        assert_eq!(json["pub_key"]["type"], "tendermint/PubKeyEd25519");
+
+        // The key is authenticated along with the state
+        let other_key = PublicKey::from_raw_ed25519(&[0x22; 32]).unwrap();
+        let mut json = json;
+        json["pub_key"] = serde_json::to_value(other_key).unwrap();
+        fs::write(&path, json.to_string()).unwrap();
+
+        let err = State::load(hmac_store(&path, false))
+            .err()
+            .expect("tampered key loaded");
+        assert!(err.to_string().contains("failed verification"));
+    }
+
+    #[test]
+    fn corrupt_state_file_without_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test_priv_validator_state.json");
+
+        fs::write(&path, b"{\"height\":\"1").unwrap();
+        assert!(State::load_state(&path).is_err());
+    }
 }
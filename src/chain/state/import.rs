@@ -0,0 +1,254 @@
+//! Import of a Tendermint/CometBFT validator's `priv_validator_state.json`
+//! (i.e. the state of a FilePV), e.g. when migrating a validator to tmkms.
+//!
+//! Heights are strings, as are rounds in files written by Tendermint v0.33
+//! and earlier (which encoded them with Amino JSON). The bytes last signed
+//! (`signbytes`, hex) and the signature over them (`signature`, Base64) are
+//! only present once something has been signed. The sign bytes are kept, so
+//! a retry of the last request signed by the FilePV is told apart from a
+//! conflicting one; the signature isn't (retries are signed again).
+
+use super::{SignedPayload, Step};
+use crate::{
+    amino_types::SignedMsgType,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    privileges,
+};
+use std::{fs, path::Path};
+use subtle_encoding::{base64, hex};
+use tendermint::{block, consensus};
+
+/// Consensus state read from a FilePV's state file
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ImportedState {
+    /// Last signed height/round/step
+    pub state: consensus::State,
+
+    /// Payload signed at it (if the file records one)
+    pub payload: Option<SignedPayload>,
+}
+
+/// Read the `priv_validator_state.json` file at the given path
+pub fn read(path: &Path) -> Result<ImportedState, Error> {
+    let json = fs::read_to_string(privileges::resolve(path))
+        .map_err(|e| format_err!(IoError, "couldn't read {}: {}", path.display(), e))?;
+
+    parse(&json)
+        .map_err(|e| format_err!(ParseError, "error parsing {}: {}", path.display(), e).into())
+}
+
+/// Parse the contents of a `priv_validator_state.json` file
+pub fn parse(json: &str) -> Result<ImportedState, Error> {
+    let json: serde_json::Value = serde_json::from_str(json)?;
+
+    if !json.is_object() {
+        fail!(ParseError, "expected a JSON object");
+    }
+
+    let height = integer_field(&json, "height")?;
+    let round = integer_field(&json, "round")?;
+    let step = integer_field(&json, "step")?;
+
+    let state = consensus::State {
+        height: block::Height::try_from(height)
+            .map_err(|e| format_err!(ParseError, "invalid `height` {}: {}", height, e))?,
+        round: u32::try_from(round)
+            .ok()
+            .and_then(|round| block::Round::try_from(round).ok())
+            .ok_or_else(|| format_err!(ParseError, "invalid `round` {}", round))?,
+        step: match step {
+            0..=3 => step as i8,
+            _ => fail!(ParseError, "invalid `step` {} (must be 0-3)", step),
+        },
+        block_id: None,
+    };
+
+    if let Some(signature) = string_field(&json, "signature")? {
+        base64::decode(signature)
+            .map_err(|e| format_err!(ParseError, "invalid `signature`: {}", e))?;
+    }
+
+    let payload = match string_field(&json, "signbytes")? {
+        // A FilePV only records sign bytes along with the step they were
+        // signed at, so they can't be told apart from a corrupt file
+        Some(_) if state.step == 0 => fail!(
+            ParseError,
+            "`signbytes` recorded at step 0 (nothing signed at height {} round {})",
+            state.height,
+            state.round
+        ),
+        Some(sign_bytes) => Some(SignedPayload {
+            sign_bytes: hex::decode(sign_bytes.to_ascii_lowercase())
+                .map_err(|e| format_err!(ParseError, "invalid `signbytes`: {}", e))?,
+            timestamp: None,
+            msg_type: [
+                SignedMsgType::Proposal,
+                SignedMsgType::PreVote,
+                SignedMsgType::PreCommit,
+            ]
+            .iter()
+            .copied()
+            .find(|msg_type| Step::from(*msg_type).value() == state.step),
+        }),
+        None => None,
+    };
+
+    Ok(ImportedState { state, payload })
+}
+
+/// Get a required integer field, encoded as either a JSON number or a string
+fn integer_field(json: &serde_json::Value, field: &str) -> Result<u64, Error> {
+    match &json[field] {
+        serde_json::Value::Number(n) => n.as_u64(),
+        serde_json::Value::String(s) => s.parse().ok(),
+        serde_json::Value::Null => fail!(ParseError, "missing `{}`", field),
+        _ => None,
+    }
+    .ok_or_else(|| format_err!(ParseError, "invalid `{}`: {}", field, json[field]).into())
+}
+
+/// Get an optional string field (absent if missing, `null` or empty)
+fn string_field<'a>(json: &'a serde_json::Value, field: &str) -> Result<Option<&'a str>, Error> {
+    match &json[field] {
+        serde_json::Value::String(s) if s.is_empty() => Ok(None),
+        serde_json::Value::String(s) => Ok(Some(s)),
+        serde_json::Value::Null => Ok(None),
+        other => fail!(ParseError, "invalid `{}`: {}", field, other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The samples below hold the sign bytes of the "prevote for a block"
+    // golden vector (see `amino_types::golden_vectors`), as a FilePV writes
+    // them after signing that vote: the Protobuf encoding generated by
+    // Tendermint v0.34's `types.VoteSignBytes`, and the Amino encoding of
+    // Tendermint v0.33. Their signatures are genuine, by the Ed25519 key
+    // with seed `[0x01; 32]` (see `SAMPLE_SIGNING_SEED`), so the samples are
+    // consistent with each other. They aren't captured from a live network.
+
+    /// Seed of the Ed25519 key the samples were signed with
+    const SAMPLE_SIGNING_SEED: [u8; 32] = [0x01; 32];
+
+    /// State of a CometBFT v0.34+ validator which has signed a prevote
+    const COMETBFT_STATE: &str = r#"{
+  "height": "12345",
+  "round": 2,
+  "step": 2,
+  "signature": "im3Gyxn6HLys1bO+lReT40S3R7Yicm3wNgHe5S8XfBNvLd4NGuHED1a9zIdKTDHJaYi7cR5i1m+6ebYtRwTkBQ==",
+  "signbytes": "7C0801113930000000000000190200000000000000224A0A204445414442454546444541444245454642414642414642414642414642414641122608C0843D122030303232343436363838414143434545313133333535373739394242444446462A0B08B1D381D20510809DCA6F320D746573745F636861696E5F6964"
+}"#;
+
+    /// State of a Tendermint v0.33 validator which has signed the same
+    /// prevote (with the round encoded as a string, as by Amino JSON)
+    const TENDERMINT_V0_33_STATE: &str = r#"{
+  "height": "12345",
+  "round": "2",
+  "step": 2,
+  "signature": "SUSedqhJFsYlOCp9O4GUAWvYgcGurtKXfe4mt2ovM3OFWc2QNKPmPWhffsblCbov5rrsbPAhNwPHjZktkW4iDA==",
+  "signbytes": "7C0801113930000000000000190200000000000000224A0A20444541444245454644454144424545464241464241464241464241464241464112260A20303032323434363638384141434345453131333335353737393942424444464610C0843D2A0B08B1D381D20510809DCA6F320D746573745F636861696E5F6964"
+}"#;
+
+    /// State of a validator which hasn't signed anything yet
+    const INITIAL_STATE: &str = r#"{
+  "height": "0",
+  "round": 0,
+  "step": 0
+}"#;
+
+    #[test]
+    fn parse_cometbft_state() {
+        let imported = parse(COMETBFT_STATE).unwrap();
+        assert_eq!(imported.state.height.value(), 12345);
+        assert_eq!(imported.state.round.value(), 2);
+        assert_eq!(imported.state.step, 2);
+
+        let payload = imported.payload.unwrap();
+        assert_eq!(payload.msg_type, Some(SignedMsgType::PreVote));
+        assert_eq!(payload.sign_bytes.len(), 125);
+    }
+
+    #[test]
+    fn parse_round_as_string() {
+        let imported = parse(TENDERMINT_V0_33_STATE).unwrap();
+        assert_eq!(imported.state.height.value(), 12345);
+        assert_eq!(imported.state.round.value(), 2);
+        assert_eq!(imported.state.step, 2);
+        assert_eq!(
+            imported.payload.unwrap().msg_type,
+            Some(SignedMsgType::PreVote)
+        );
+    }
+
+    #[test]
+    fn samples_are_signed() {
+        use ed25519_dalek::{self as ed25519, Verifier as _};
+
+        let secret = ed25519::SecretKey::from_bytes(&SAMPLE_SIGNING_SEED).unwrap();
+        let public = ed25519::PublicKey::from(&secret);
+
+        for json in &[COMETBFT_STATE, TENDERMINT_V0_33_STATE] {
+            let value: serde_json::Value = serde_json::from_str(json).unwrap();
+            let signature = base64::decode(value["signature"].as_str().unwrap()).unwrap();
+            let signature = ed25519::Signature::from_bytes(&signature).unwrap();
+            let sign_bytes = parse(json).unwrap().payload.unwrap().sign_bytes;
+
+            public.verify(&sign_bytes, &signature).unwrap();
+        }
+    }
+
+    #[test]
+    fn parse_initial_state() {
+        let imported = parse(INITIAL_STATE).unwrap();
+        assert_eq!(imported.state.height.value(), 0);
+        assert_eq!(imported.state.round.value(), 0);
+        assert_eq!(imported.state.step, 0);
+        assert!(imported.payload.is_none());
+
+        // Empty and `null` signatures and sign bytes are the same as missing
+        let imported =
+            parse(r#"{"height": "5", "round": 0, "step": 1, "signature": null, "signbytes": ""}"#)
+                .unwrap();
+        assert_eq!(imported.state.height.value(), 5);
+        assert!(imported.payload.is_none());
+    }
+
+    #[test]
+    fn round_trip_through_state_file() {
+        use crate::chain::state::{JsonStateStore, State, StateStore};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+
+        for json in &[COMETBFT_STATE, TENDERMINT_V0_33_STATE, INITIAL_STATE] {
+            let imported = parse(json).unwrap();
+
+            let mut state = State::load_state(&path).unwrap();
+            state
+                .restore(imported.state.clone(), imported.payload.clone())
+                .unwrap();
+
+            let loaded = JsonStateStore::new(&path).load_signed().unwrap().unwrap();
+            assert_eq!(loaded, (imported.state, imported.payload));
+        }
+    }
+
+    #[test]
+    fn parse_invalid_states() {
+        for json in &[
+            "[]",
+            r#"{"round": 0, "step": 0}"#,
+            r#"{"height": "-1", "round": 0, "step": 0}"#,
+            r#"{"height": "1", "round": "x", "step": 0}"#,
+            r#"{"height": "1", "round": 0, "step": 4}"#,
+            r#"{"height": "1", "round": 0, "step": 3, "signbytes": "XYZ"}"#,
+            r#"{"height": "1", "round": 0, "step": 3, "signature": 42}"#,
+            r#"{"height": "1", "round": 0, "step": 0, "signbytes": "0A0B"}"#,
+        ] {
+            assert!(parse(json).is_err(), "parsed: {}", json);
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Locks preventing several `tmkms` processes from using the same consensus
+//! state.
+//!
+//! State files are replaced (renamed over) on every write, so the lock is
+//! held on a `<path>.lock` file next to them instead, which also records the
+//! pid of the process holding it.
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Exclusive lock on a chain's consensus state, held until dropped
+#[derive(Debug)]
+pub struct StateLock {
+    /// Path to the lock file
+    path: PathBuf,
+
+    /// Lock file (closing it releases the lock)
+    _file: File,
+}
+
+impl StateLock {
+    /// Take an exclusive lock on the consensus state at the given path,
+    /// failing if another process holds it
+    pub fn acquire(state_path: &Path) -> Result<Self, Error> {
+        let path = lock_path(state_path);
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            // Not truncated until locked: it holds the pid of the lock holder
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| format_err!(IoError, "couldn't open {}: {}", path.display(), e))?;
+
+        if let Err(e) = flock(&file) {
+            if e.kind() != io::ErrorKind::WouldBlock {
+                fail!(IoError, "couldn't lock {}: {}", path.display(), e);
+            }
+
+            let pid = fs::read_to_string(&path).unwrap_or_default();
+
+            fail!(
+                StateLockError,
+                "{}: another tmkms instance holds the lock (pid {})",
+                state_path.display(),
+                match pid.trim() {
+                    "" => "unknown",
+                    pid => pid,
+                }
+            );
+        }
+
+        file.set_len(0)?;
+        writeln!(file, "{}", process::id())?;
+        file.sync_all()?;
+
+        debug!("locked consensus state: {}", state_path.display());
+        Ok(Self { path, _file: file })
+    }
+
+    /// Path to the lock file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Get the path of the lock file for the consensus state at the given path
+pub fn lock_path(state_path: &Path) -> PathBuf {
+    let mut path = OsString::from(state_path.as_os_str());
+    path.push(".lock");
+    path.into()
+}
+
+/// Take an exclusive `flock(2)` on the given file without blocking
+#[allow(unsafe_code)]
+fn flock(file: &File) -> io::Result<()> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_path = dir.path().join("state.json");
+
+        let lock = StateLock::acquire(&state_path).unwrap();
+        assert_eq!(lock.path(), dir.path().join("state.json.lock"));
+        assert_eq!(
+            fs::read_to_string(lock.path()).unwrap(),
+            format!("{}\n", process::id())
+        );
+
+        // `flock` locks belong to open files, so they also conflict within
+        // a process
+        let err = StateLock::acquire(&state_path).unwrap_err();
+        assert_eq!(*err.kind(), StateLockError);
+        assert!(err.to_string().contains(&format!(
+            "another tmkms instance holds the lock (pid {})",
+            process::id()
+        )));
+
+        drop(lock);
+        StateLock::acquire(&state_path).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+//! Persistent storage for consensus state
+
+mod json;
+mod null;
+#[cfg(feature = "redis")]
+mod redis;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "redis")]
+pub use self::redis::{CasBackend, RedisBackend, RedisStateStore};
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteStateStore;
+pub use self::{json::JsonStateStore, null::NullStateStore};
+
+use super::SignedPayload;
+use crate::{amino_types::SignedMsgType, error::Error};
+use std::fmt::Display;
+use tendermint::{consensus, PublicKey};
+
+/// Signed message types, as persisted with the payload last signed
+const MSG_TYPES: &[SignedMsgType] = &[
+    SignedMsgType::Proposal,
+    SignedMsgType::PreVote,
+    SignedMsgType::PreCommit,
+];
+
+/// Get the signed message type with the given name, as persisted (see
+/// [`SignedMsgType::as_str`])
+fn msg_type_named(name: &str) -> Option<SignedMsgType> {
+    MSG_TYPES
+        .iter()
+        .copied()
+        .find(|msg_type| msg_type.as_str() == name)
+}
+
+/// Backend which durably persists the last signed consensus state of a chain.
+///
+/// Double signing checks are performed by [`super::State`] independently of
+/// the backend: stores only need to load and save the state.
+pub trait StateStore: Display + Send {
+    /// Load the persisted consensus state, or `None` if nothing has been
+    /// persisted yet
+    fn load(&mut self) -> Result<Option<consensus::State>, Error>;
+
+    /// Durably persist the given consensus state, replacing the old one
+    fn store(&mut self, state: &consensus::State) -> Result<(), Error>;
+
+    /// Load the persisted consensus state along with the payload signed at
+    /// it, for stores which keep it (others never return a payload)
+    fn load_signed(&mut self) -> Result<Option<(consensus::State, Option<SignedPayload>)>, Error> {
+        Ok(self.load()?.map(|state| (state, None)))
+    }
+
+    /// Durably persist the given consensus state along with the payload
+    /// signed at it (if any), for stores which keep it (others only persist
+    /// the state)
+    fn store_signed(
+        &mut self,
+        state: &consensus::State,
+        _payload: Option<&SignedPayload>,
+    ) -> Result<(), Error> {
+        self.store(state)
+    }
+
+    /// Get the chain's consensus public key persisted alongside the state,
+    /// as of when it was loaded (for stores which keep it, others never
+    /// return one)
+    fn pub_key(&self) -> Option<PublicKey> {
+        None
+    }
+
+    /// Set the chain's consensus public key, which is persisted alongside
+    /// the state the next time it's stored (for stores which keep it, others
+    /// ignore it)
+    fn set_pub_key(&mut self, _pub_key: PublicKey) {}
+}
@@ -0,0 +1,616 @@
+//! JSON file state store (i.e. `priv_validator_state.json`)
+//!
+//! If an HMAC key is configured (`state_hmac_key_path`), an HMAC-SHA256 over
+//! the canonical serialization of the state is stored alongside it in the
+//! `hmac` field, so state edited outside of tmkms (e.g. a height lowered by
+//! hand) is detected when loaded.
+//!
+//! The bytes last signed (`signbytes`, as in Tendermint's own
+//! `priv_validator_state.json`) and the timestamp they were signed with are
+//! also stored, so retries of the same request can be told apart from
+//! conflicting ones after a restart.
+//!
+//! Since version 2 of the format (`version`), the type of the message last
+//! signed (`msg_type`) and the SHA-256 of its sign bytes (`signbytes_sha256`)
+//! are stored with them too, as is the chain's consensus public key
+//! (`pub_key`), so a different key being loaded for the chain is noticed.
+//!
+//! Unversioned (version 1) files were written by versions of tmkms which
+//! numbered steps from 0 rather than as Tendermint does (see [`Step`]), so
+//! their step is renumbered. They're migrated to the current version when
+//! loaded.
+
+use super::{msg_type_named, SignedPayload, StateStore, MSG_TYPES};
+use crate::{
+    amino_types::SignedMsgType,
+    chain::state::Step,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    privileges,
+};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use std::{
+    ffi::OsString,
+    fmt::{self, Debug, Display},
+    fs,
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+};
+use subtle_encoding::hex;
+use tempfile::NamedTempFile;
+use tendermint::{consensus, PublicKey};
+use zeroize::Zeroizing;
+
+/// Name of the field holding the HMAC of the state
+const HMAC_FIELD: &str = "hmac";
+
+/// Name of the field holding the (hex encoded) bytes last signed
+const SIGN_BYTES_FIELD: &str = "signbytes";
+
+/// Name of the field holding the timestamp of the payload last signed
+const TIMESTAMP_FIELD: &str = "timestamp";
+
+/// Name of the field holding the type of the message last signed
+const MSG_TYPE_FIELD: &str = "msg_type";
+
+/// Name of the field holding the (hex encoded) SHA-256 of the bytes last signed
+const SIGN_BYTES_HASH_FIELD: &str = "signbytes_sha256";
+
+/// Name of the field holding the chain's consensus public key
+const PUB_KEY_FIELD: &str = "pub_key";
+
+/// Name of the field holding the version of the state file format
+const VERSION_FIELD: &str = "version";
+
+/// Version of the state file format written by this version of tmkms
+const VERSION: u64 = 2;
+
+/// Earliest version of the state file format whose steps are numbered as by
+/// Tendermint (unversioned files number them from 0)
+const TENDERMINT_STEPS_VERSION: u64 = 2;
+
+/// State file contents
+struct StateFile {
+    /// Consensus state
+    state: consensus::State,
+
+    /// Payload signed at the consensus state (if any)
+    payload: Option<SignedPayload>,
+
+    /// Consensus public key of the chain (if recorded)
+    pub_key: Option<PublicKey>,
+
+    /// HMAC authenticating the state, payload and public key (if any)
+    hmac: Option<String>,
+
+    /// Version of the file format
+    version: u64,
+}
+
+/// HMAC-SHA256
+type HmacSha256 = Hmac<Sha256>;
+
+/// Consensus state stored as a JSON file, with a backup of the previous
+/// generation alongside it (`<path>.bak`)
+#[derive(Clone, Debug)]
+pub struct JsonStateStore {
+    path: PathBuf,
+
+    /// Key the state is authenticated with (if any)
+    hmac_key: Option<HmacKey>,
+
+    /// Load state which fails HMAC verification instead of refusing to
+    accept_tampered: bool,
+
+    /// Consensus public key of the chain, as last loaded or set
+    pub_key: Option<PublicKey>,
+}
+
+impl JsonStateStore {
+    /// Create a new JSON state store at the given path
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            hmac_key: None,
+            accept_tampered: false,
+            pub_key: None,
+        }
+    }
+
+    /// Authenticate the state with HMAC-SHA256 under the given key
+    pub fn with_hmac_key(mut self, key: Zeroizing<Vec<u8>>) -> Self {
+        self.hmac_key = Some(HmacKey(key));
+        self
+    }
+
+    /// Load state which fails HMAC verification (logging an error) rather
+    /// than refusing to
+    pub fn accept_tampered(mut self, accept_tampered: bool) -> Self {
+        self.accept_tampered = accept_tampered;
+        self
+    }
+
+    /// Path to the state file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path to the backup of the previous generation of the state file
+    pub fn backup_path(&self) -> PathBuf {
+        let mut backup_path = OsString::from(self.path.as_os_str());
+        backup_path.push(".bak");
+        backup_path.into()
+    }
+}
+
+impl StateStore for JsonStateStore {
+    fn load(&mut self) -> Result<Option<consensus::State>, Error> {
+        Ok(self.load_signed()?.map(|(state, _)| state))
+    }
+
+    fn store(&mut self, state: &consensus::State) -> Result<(), Error> {
+        self.store_signed(state, None)
+    }
+
+    /// Load the state file.
+    ///
+    /// If the state file is missing or corrupt (e.g. truncated by a crash
+    /// mid-write) but a backup of the previous generation exists, the backup
+    /// is loaded instead and the state file is rewritten from it.
+    ///
+    /// State which fails HMAC verification (if enabled) is refused rather
+    /// than recovered from the backup.
+    fn load_signed(&mut self) -> Result<Option<(consensus::State, Option<SignedPayload>)>, Error> {
+        let err = match read_authenticated_state_file(&self.path) {
+            Ok(file) => {
+                let version = file.version;
+                let pub_key = file.pub_key;
+                let (consensus_state, payload) = self.verify(&self.path, file)?;
+                self.pub_key = pub_key;
+
+                if version < VERSION {
+                    info!(
+                        "migrating consensus state in {} from version {} to {}",
+                        self.path.display(),
+                        version,
+                        VERSION
+                    );
+
+                    self.store_signed(&consensus_state, payload.as_ref())?;
+                }
+
+                return Ok(Some((consensus_state, payload)));
+            }
+            Err(e) => e,
+        };
+
+        let backup_path = self.backup_path();
+
+        match read_authenticated_state_file(&backup_path) {
+            Ok(file) => {
+                let pub_key = file.pub_key;
+                let (consensus_state, payload) = self.verify(&backup_path, file)?;
+                self.pub_key = pub_key;
+
+                error!(
+                    "*** RECOVERING CONSENSUS STATE FROM BACKUP *** {} ({}); using previous \
+                     generation from {}: {:?}",
+                    self.path.display(),
+                    err,
+                    backup_path.display(),
+                    &consensus_state
+                );
+
+                self.store_signed(&consensus_state, payload.as_ref())?;
+                Ok(Some((consensus_state, payload)))
+            }
+            Err(_) if !privileges::resolve(&self.path).exists() => Ok(None),
+            Err(_) => Err(err),
+        }
+    }
+
+    /// Sync the given state to disk.
+    ///
+    /// The previous generation (if valid) is first preserved as a backup, by
+    /// hard linking it (it was already made durable when written), then the
+    /// new state is written atomically: to a temporary file in the same
+    /// directory which is fsync'd and renamed over the old file, followed by
+    /// an fsync of the directory itself (covering the backup's link too).
+    fn store_signed(
+        &mut self,
+        state: &consensus::State,
+        payload: Option<&SignedPayload>,
+    ) -> Result<(), Error> {
+        let json = self.serialize(state, payload, self.pub_key.as_ref())?;
+
+        // Only back up the previous generation if it's intact (and authentic),
+        // so a corrupt or tampered state file never clobbers a good backup
+        if let Ok(previous) = read_authenticated_state_file(&self.path) {
+            if self.is_authentic(&previous) {
+                link_backup(&self.path, &self.backup_path())?;
+            }
+        }
+
+        write_atomically(&self.path, &json)?;
+        Ok(())
+    }
+
+    fn pub_key(&self) -> Option<PublicKey> {
+        self.pub_key
+    }
+
+    fn set_pub_key(&mut self, pub_key: PublicKey) {
+        self.pub_key = Some(pub_key);
+    }
+}
+
+impl JsonStateStore {
+    /// Serialize the given state, the payload signed at it and the chain's
+    /// public key, including their HMAC (if enabled)
+    fn serialize(
+        &self,
+        state: &consensus::State,
+        payload: Option<&SignedPayload>,
+        pub_key: Option<&PublicKey>,
+    ) -> Result<String, Error> {
+        let mut json = serde_json::to_value(state)?;
+        json[VERSION_FIELD] = VERSION.into();
+
+        if let Some(payload) = payload {
+            json[SIGN_BYTES_FIELD] = encode_sign_bytes(payload).into();
+            json[SIGN_BYTES_HASH_FIELD] = payload.sign_bytes_hash().into();
+
+            if let Some(timestamp) = payload.timestamp {
+                json[TIMESTAMP_FIELD] = serde_json::to_value(timestamp)?;
+            }
+
+            if let Some(msg_type) = payload.msg_type {
+                json[MSG_TYPE_FIELD] = msg_type.as_str().into();
+            }
+        }
+
+        if let Some(pub_key) = pub_key {
+            json[PUB_KEY_FIELD] = serde_json::to_value(pub_key)?;
+        }
+
+        if let Some(key) = &self.hmac_key {
+            let tag = key.tag(state, payload, pub_key)?;
+            let tag = String::from_utf8(hex::encode(tag)).unwrap();
+            json[HMAC_FIELD] = tag.into();
+        }
+
+        Ok(serde_json::to_string(&json)?)
+    }
+
+    /// Does the HMAC of the given state file authenticate its state and
+    /// payload? (always true if HMACs aren't enabled)
+    fn is_authentic(&self, file: &StateFile) -> bool {
+        let key = match &self.hmac_key {
+            Some(key) => key,
+            None => return true,
+        };
+
+        match (
+            file.hmac.as_deref().map(hex::decode),
+            key.mac(&file.state, file.payload.as_ref(), file.pub_key.as_ref()),
+        ) {
+            (Some(Ok(tag)), Ok(mac)) => mac.verify(&tag).is_ok(),
+            _ => false,
+        }
+    }
+
+    /// Ensure state loaded from the given path is authentic, unless tampered
+    /// state is accepted
+    fn verify(
+        &self,
+        path: &Path,
+        mut file: StateFile,
+    ) -> Result<(consensus::State, Option<SignedPayload>), Error> {
+        let authentic = self.is_authentic(&file);
+        file.renumber_step();
+
+        if authentic {
+            return Ok((file.state, file.payload));
+        }
+
+        let StateFile {
+            state,
+            payload,
+            hmac,
+            ..
+        } = file;
+
+        let problem = if hmac.is_some() {
+            "its HMAC doesn't match"
+        } else {
+            "it has no HMAC"
+        };
+
+        if !self.accept_tampered {
+            fail!(
+                VerificationError,
+                "consensus state in {} failed verification: {} (edited outside of tmkms?). \
+                 Set the intended state with `tmkms state set --force`, or start with \
+                 `tmkms start --accept-tampered-state` to accept it",
+                path.display(),
+                problem
+            );
+        }
+
+        error!(
+            "*** ACCEPTING TAMPERED CONSENSUS STATE *** {}: {}; using {:?}",
+            path.display(),
+            problem,
+            &state
+        );
+
+        Ok((state, payload))
+    }
+}
+
+impl StateFile {
+    /// Renumber the step of the state as by Tendermint, if the file numbers
+    /// steps from 0. Must only be done once its HMAC has been checked, as
+    /// the HMAC covers the state as written.
+    fn renumber_step(&mut self) {
+        self.state.step = current_step(self.state.step, self.version);
+    }
+}
+
+/// Key the consensus state is authenticated with
+#[derive(Clone)]
+struct HmacKey(Zeroizing<Vec<u8>>);
+
+impl HmacKey {
+    /// Compute the HMAC of the canonical serialization of the given state,
+    /// followed by the payload signed at it (if any) and the chain's public
+    /// key (if any)
+    fn mac(
+        &self,
+        state: &consensus::State,
+        payload: Option<&SignedPayload>,
+        pub_key: Option<&PublicKey>,
+    ) -> Result<HmacSha256, Error> {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts keys of any size");
+        mac.update(serde_json::to_string(state)?.as_bytes());
+
+        if let Some(payload) = payload {
+            mac.update(encode_sign_bytes(payload).as_bytes());
+            mac.update(serde_json::to_string(&payload.timestamp)?.as_bytes());
+            mac.update(
+                payload
+                    .msg_type
+                    .map_or("", SignedMsgType::as_str)
+                    .as_bytes(),
+            );
+        }
+
+        if let Some(pub_key) = pub_key {
+            mac.update(serde_json::to_string(pub_key)?.as_bytes());
+        }
+
+        Ok(mac)
+    }
+
+    /// Compute the HMAC tag of the given state, payload and public key
+    fn tag(
+        &self,
+        state: &consensus::State,
+        payload: Option<&SignedPayload>,
+        pub_key: Option<&PublicKey>,
+    ) -> Result<Vec<u8>, Error> {
+        Ok(self
+            .mac(state, payload, pub_key)?
+            .finalize()
+            .into_bytes()
+            .to_vec())
+    }
+}
+
+impl Debug for HmacKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("HmacKey(...)")
+    }
+}
+
+impl Display for JsonStateStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())
+    }
+}
+
+/// Read and parse the state file at the given path
+#[cfg(feature = "sqlite")]
+pub(super) fn read_state_file(path: &Path) -> Result<consensus::State, Error> {
+    let mut file = read_authenticated_state_file(path)?;
+    file.renumber_step();
+    Ok(file.state)
+}
+
+/// Get the step of a state read from a file in the given version of the
+/// format, numbered as by Tendermint
+fn current_step(step: i8, version: u64) -> i8 {
+    if version < TENDERMINT_STEPS_VERSION {
+        Step::renumber_legacy(step)
+    } else {
+        step
+    }
+}
+
+/// Encode the bytes of a signed payload as uppercase hex (as Tendermint does)
+fn encode_sign_bytes(payload: &SignedPayload) -> String {
+    String::from_utf8(hex::encode_upper(&payload.sign_bytes)).unwrap()
+}
+
+/// Read and parse the state file at the given path, along with the payload
+/// signed at it and its HMAC (if any)
+fn read_authenticated_state_file(path: &Path) -> Result<StateFile, Error> {
+    let parse_err =
+        |e: serde_json::Error| format_err!(ParseError, "error parsing {}: {}", path.display(), e);
+
+    let state_json = fs::read_to_string(privileges::resolve(path))?;
+    let mut json: serde_json::Value = serde_json::from_str(&state_json).map_err(parse_err)?;
+
+    let version = match take_field(&mut json, VERSION_FIELD) {
+        Some(version) => match version.as_u64() {
+            Some(version @ 1..=VERSION) => version,
+            _ => fail!(
+                ParseError,
+                "error parsing {}: unsupported `version` {} (written by a newer tmkms?)",
+                path.display(),
+                version
+            ),
+        },
+        None => 1,
+    };
+
+    let hmac = match take_field(&mut json, HMAC_FIELD) {
+        Some(serde_json::Value::String(hmac)) => Some(hmac),
+        Some(_) => fail!(
+            ParseError,
+            "error parsing {}: invalid `hmac`",
+            path.display()
+        ),
+        None => None,
+    };
+
+    let sign_bytes = match take_field(&mut json, SIGN_BYTES_FIELD) {
+        Some(serde_json::Value::String(sign_bytes)) => Some(
+            hex::decode(sign_bytes.to_ascii_lowercase())
+                .map_err(|e| format_err!(ParseError, "error parsing {}: {}", path.display(), e))?,
+        ),
+        Some(_) => fail!(
+            ParseError,
+            "error parsing {}: invalid `signbytes`",
+            path.display()
+        ),
+        None => None,
+    };
+
+    let timestamp = match take_field(&mut json, TIMESTAMP_FIELD) {
+        Some(timestamp) => Some(serde_json::from_value(timestamp).map_err(parse_err)?),
+        None => None,
+    };
+
+    let msg_type = match take_field(&mut json, MSG_TYPE_FIELD) {
+        Some(serde_json::Value::String(name)) => Some(msg_type_named(&name).ok_or_else(|| {
+            format_err!(
+                ParseError,
+                "error parsing {}: unknown `msg_type` {}",
+                path.display(),
+                name
+            )
+        })?),
+        Some(_) => fail!(
+            ParseError,
+            "error parsing {}: invalid `msg_type`",
+            path.display()
+        ),
+        None => None,
+    };
+
+    let pub_key = match take_field(&mut json, PUB_KEY_FIELD) {
+        Some(pub_key) => Some(serde_json::from_value(pub_key).map_err(parse_err)?),
+        None => None,
+    };
+
+    let sign_bytes_hash = match take_field(&mut json, SIGN_BYTES_HASH_FIELD) {
+        Some(serde_json::Value::String(hash)) => Some(hash),
+        Some(_) => fail!(
+            ParseError,
+            "error parsing {}: invalid `signbytes_sha256`",
+            path.display()
+        ),
+        None => None,
+    };
+
+    let state: consensus::State = serde_json::from_value(json).map_err(parse_err)?;
+
+    let payload = match sign_bytes {
+        Some(sign_bytes) => {
+            let payload = SignedPayload {
+                sign_bytes,
+                timestamp,
+                // Version 1 files don't record the message type, but it can
+                // be told from the step (once renumbered)
+                msg_type: msg_type.or_else(|| {
+                    let step = current_step(state.step, version);
+
+                    MSG_TYPES
+                        .iter()
+                        .copied()
+                        .find(|msg_type| Step::from(*msg_type).value() == step)
+                }),
+            };
+
+            if let Some(hash) = sign_bytes_hash {
+                if !hash.eq_ignore_ascii_case(&payload.sign_bytes_hash()) {
+                    fail!(
+                        ParseError,
+                        "error parsing {}: `signbytes_sha256` doesn't match `signbytes`",
+                        path.display()
+                    );
+                }
+            }
+
+            Some(payload)
+        }
+        None => None,
+    };
+
+    Ok(StateFile {
+        state,
+        payload,
+        pub_key,
+        hmac,
+        version,
+    })
+}
+
+/// Remove the given field from a JSON object (if present)
+fn take_field(json: &mut serde_json::Value, field: &str) -> Option<serde_json::Value> {
+    json.as_object_mut().and_then(|obj| obj.remove(field))
+}
+
+/// Replace the backup at the given path with a hard link to the file at the
+/// given path, via a temporary link renamed over it. Neither is synced: the
+/// directory is when the new generation is written.
+fn link_backup(path: &Path, backup_path: &Path) -> io::Result<()> {
+    let path = &*privileges::resolve(path);
+    let backup_path = &*privileges::resolve(backup_path);
+
+    let mut link_path = OsString::from(backup_path.as_os_str());
+    link_path.push(".tmp");
+    let link_path = Path::new(&link_path);
+
+    if let Err(e) = fs::hard_link(path, link_path) {
+        if e.kind() != io::ErrorKind::AlreadyExists {
+            return Err(e);
+        }
+
+        // Left behind by a crash between linking and renaming
+        fs::remove_file(link_path)?;
+        fs::hard_link(path, link_path)?;
+    }
+
+    fs::rename(link_path, backup_path)
+}
+
+/// Atomically replace the file at the given path with the given contents,
+/// ensuring both the file and the directory entry are durable
+fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let path = &*privileges::resolve(path);
+    let dir = match path.parent() {
+        Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+        Some(dir) => dir,
+        None => panic!("state file cannot be root directory"),
+    };
+
+    let mut file = NamedTempFile::new_in(dir)?;
+    file.write_all(contents.as_bytes())?;
+    file.as_file().sync_all()?;
+    file.persist(path)?;
+
+    fs::File::open(dir)?.sync_all()
+}
@@ -0,0 +1,32 @@
+//! State store which never persists anything
+
+use super::StateStore;
+use crate::error::Error;
+use std::fmt::{self, Display};
+use tendermint::consensus;
+
+/// State store for chains which only need their keys (e.g. when checking a
+/// configuration or replaying captured requests), never persisting anything
+#[derive(Debug, Default)]
+pub struct NullStateStore;
+
+impl Display for NullStateStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(none)")
+    }
+}
+
+impl StateStore for NullStateStore {
+    /// Load the initial state (rather than none, which would be warned about
+    /// as lost state)
+    fn load(&mut self) -> Result<Option<consensus::State>, Error> {
+        Ok(Some(consensus::State {
+            height: 0u32.into(),
+            ..Default::default()
+        }))
+    }
+
+    fn store(&mut self, _state: &consensus::State) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,719 @@
+//! Redis state store: a high-watermark shared by active/passive KMS instances.
+//!
+//! The state is only ever replaced using compare-and-set, and only with a
+//! state which advances the stored height/round/step, so two instances sharing
+//! a key can't both sign past the same HRS after a split brain.
+//!
+//! States are stored as JSON along with the version of their format
+//! (`version`). Unversioned states were stored by versions of tmkms which
+//! numbered steps from 0 rather than as Tendermint does (see [`Step`]), so
+//! their step is renumbered when loaded.
+//!
+//! The payload last signed at the state is stored with it, in the same value
+//! (so it's replaced by the same compare-and-set): its sign bytes
+//! (`signbytes`), their SHA-256 (`signbytes_sha256`), timestamp and message
+//! type (`msg_type`), as in a JSON state file. So is the chain's consensus
+//! public key (`pub_key`), so a different key being loaded for the chain is
+//! noticed by every instance sharing it.
+
+use super::{msg_type_named, SignedPayload, StateStore};
+use crate::{
+    chain::{self, state::Step},
+    config::chain::RedisConfig,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use redis::{ConnectionAddr, ConnectionInfo, IntoConnectionInfo};
+use std::{
+    fmt::{self, Display},
+    time::Duration,
+};
+use subtle_encoding::hex;
+use tendermint::{consensus, PublicKey};
+
+/// Default key prefix
+const DEFAULT_KEY_PREFIX: &str = "tmkms";
+
+/// Name of the field holding the version of the stored state's format
+const VERSION_FIELD: &str = "version";
+
+/// Name of the field holding the (hex encoded) bytes last signed
+const SIGN_BYTES_FIELD: &str = "signbytes";
+
+/// Name of the field holding the (hex encoded) SHA-256 of the bytes last signed
+const SIGN_BYTES_HASH_FIELD: &str = "signbytes_sha256";
+
+/// Name of the field holding the timestamp of the payload last signed
+const TIMESTAMP_FIELD: &str = "timestamp";
+
+/// Name of the field holding the type of the message last signed
+const MSG_TYPE_FIELD: &str = "msg_type";
+
+/// Name of the field holding the chain's consensus public key
+const PUB_KEY_FIELD: &str = "pub_key";
+
+/// Version of the stored state's format written by this version of tmkms
+const VERSION: u64 = 2;
+
+/// Default connect/request timeout in seconds
+const DEFAULT_TIMEOUT_SECS: u64 = 5;
+
+/// Number of times to retry a compare-and-set which lost a race before
+/// giving up
+const MAX_CAS_ATTEMPTS: usize = 3;
+
+/// Key/value store supporting compare-and-set (i.e. Redis)
+pub trait CasBackend: Send {
+    /// Get the value of the given key, if present
+    fn get(&mut self, key: &str) -> Result<Option<String>, Error>;
+
+    /// Set the given key to `new` if and only if its current value is
+    /// `expected`. Returns `false` if the value was changed concurrently.
+    fn compare_and_set(
+        &mut self,
+        key: &str,
+        expected: Option<&str>,
+        new: &str,
+    ) -> Result<bool, Error>;
+}
+
+/// Consensus state for a particular chain stored under a key in a
+/// compare-and-set capable store (Redis unless testing)
+pub struct RedisStateStore<B: CasBackend = RedisBackend> {
+    /// Backend holding the state
+    backend: B,
+
+    /// Key the state is stored under
+    key: String,
+
+    /// Description of where the state is stored (for logging)
+    description: String,
+
+    /// Consensus public key of the chain, as last loaded or set
+    pub_key: Option<PublicKey>,
+}
+
+impl RedisStateStore {
+    /// Connect to Redis using the given configuration, storing the state of
+    /// the given chain
+    pub fn open(config: &RedisConfig, chain_id: &chain::Id) -> Result<Self, Error> {
+        let backend = RedisBackend::new(config)?;
+        let key_prefix = config.key_prefix.as_deref().unwrap_or(DEFAULT_KEY_PREFIX);
+        let description = format!("{}", backend);
+        Ok(Self::with_backend(
+            backend,
+            key_prefix,
+            chain_id,
+            description,
+        ))
+    }
+}
+
+impl<B: CasBackend> RedisStateStore<B> {
+    /// Create a state store using the given backend
+    pub fn with_backend(
+        backend: B,
+        key_prefix: &str,
+        chain_id: &chain::Id,
+        description: impl Display,
+    ) -> Self {
+        let key = format!("{}:{}", key_prefix, chain_id);
+        let description = format!("{}/{}", description, key);
+
+        Self {
+            backend,
+            key,
+            description,
+            pub_key: None,
+        }
+    }
+}
+
+impl<B: CasBackend> StateStore for RedisStateStore<B> {
+    fn load(&mut self) -> Result<Option<consensus::State>, Error> {
+        Ok(self.load_signed()?.map(|(state, _)| state))
+    }
+
+    fn store(&mut self, state: &consensus::State) -> Result<(), Error> {
+        self.store_signed(state, None)
+    }
+
+    fn load_signed(&mut self) -> Result<Option<(consensus::State, Option<SignedPayload>)>, Error> {
+        match self.backend.get(&self.key)? {
+            Some(json) => {
+                let stored = parse_state(&self.key, &json)?;
+                self.pub_key = stored.pub_key;
+                Ok(Some((stored.state, stored.payload)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Compare-and-set the stored state along with the payload signed at it,
+    /// refusing to replace it with one which doesn't advance the stored
+    /// height/round/step
+    fn store_signed(
+        &mut self,
+        state: &consensus::State,
+        payload: Option<&SignedPayload>,
+    ) -> Result<(), Error> {
+        let mut json = serde_json::to_value(state)?;
+        json[VERSION_FIELD] = VERSION.into();
+
+        if let Some(payload) = payload {
+            json[SIGN_BYTES_FIELD] = String::from_utf8(hex::encode_upper(&payload.sign_bytes))
+                .unwrap()
+                .into();
+            json[SIGN_BYTES_HASH_FIELD] = payload.sign_bytes_hash().into();
+
+            if let Some(timestamp) = payload.timestamp {
+                json[TIMESTAMP_FIELD] = serde_json::to_value(timestamp)?;
+            }
+
+            if let Some(msg_type) = payload.msg_type {
+                json[MSG_TYPE_FIELD] = msg_type.as_str().into();
+            }
+        }
+
+        if let Some(pub_key) = &self.pub_key {
+            json[PUB_KEY_FIELD] = serde_json::to_value(pub_key)?;
+        }
+
+        let json = serde_json::to_string(&json)?;
+
+        for _ in 0..MAX_CAS_ATTEMPTS {
+            let current_json = self.backend.get(&self.key)?;
+
+            if let Some(ref current_json) = current_json {
+                check_advance(&parse_state(&self.key, current_json)?.state, state)?;
+            }
+
+            if self
+                .backend
+                .compare_and_set(&self.key, current_json.as_deref(), &json)?
+            {
+                return Ok(());
+            }
+
+            warn!(
+                "consensus state in {} was concurrently modified; retrying",
+                self
+            );
+        }
+
+        fail!(
+            DoubleSign,
+            "lost {} compare-and-set races updating {}",
+            MAX_CAS_ATTEMPTS,
+            self
+        );
+    }
+
+    fn pub_key(&self) -> Option<PublicKey> {
+        self.pub_key
+    }
+
+    fn set_pub_key(&mut self, pub_key: PublicKey) {
+        self.pub_key = Some(pub_key);
+    }
+}
+
+impl<B: CasBackend> Display for RedisStateStore<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.description)
+    }
+}
+
+/// Redis connection which is (re)established on demand
+pub struct RedisBackend {
+    /// Redis client
+    client: redis::Client,
+
+    /// Current connection, if any
+    conn: Option<redis::Connection>,
+
+    /// Connect/request timeout
+    timeout: Duration,
+}
+
+impl RedisBackend {
+    /// Create a new Redis backend from the given configuration.
+    ///
+    /// No connection is made until the backend is first used.
+    pub fn new(config: &RedisConfig) -> Result<Self, Error> {
+        let mut info: ConnectionInfo = config.url.as_str().into_connection_info()?;
+
+        if config.tls {
+            if let ConnectionAddr::Tcp(host, port) = info.addr {
+                info.addr = ConnectionAddr::TcpTls {
+                    host,
+                    port,
+                    insecure: false,
+                };
+            }
+        }
+
+        if config.username.is_some() {
+            info.redis.username = config.username.clone();
+        }
+
+        if config.password.is_some() {
+            info.redis.password = config.password.clone();
+        }
+
+        let timeout = Duration::from_secs(config.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+
+        Ok(Self {
+            client: redis::Client::open(info)?,
+            conn: None,
+            timeout,
+        })
+    }
+
+    /// Run the given function with a connection, dropping the connection if
+    /// the function fails so the next request reconnects
+    fn with_connection<T>(
+        &mut self,
+        f: impl FnOnce(&mut redis::Connection) -> redis::RedisResult<T>,
+    ) -> Result<T, Error> {
+        if self.conn.is_none() {
+            let conn = self.client.get_connection_with_timeout(self.timeout)?;
+            conn.set_read_timeout(Some(self.timeout))?;
+            conn.set_write_timeout(Some(self.timeout))?;
+            self.conn = Some(conn);
+        }
+
+        let result = f(self.conn.as_mut().unwrap());
+
+        if result.is_err() {
+            self.conn = None;
+        }
+
+        Ok(result?)
+    }
+}
+
+impl CasBackend for RedisBackend {
+    fn get(&mut self, key: &str) -> Result<Option<String>, Error> {
+        self.with_connection(|conn| redis::cmd("GET").arg(key).query(conn))
+    }
+
+    /// Compare-and-set using `WATCH`/`MULTI`/`EXEC`
+    fn compare_and_set(
+        &mut self,
+        key: &str,
+        expected: Option<&str>,
+        new: &str,
+    ) -> Result<bool, Error> {
+        self.with_connection(|conn| {
+            redis::cmd("WATCH").arg(key).query::<()>(conn)?;
+
+            let current: Option<String> = redis::cmd("GET").arg(key).query(conn)?;
+
+            if current.as_deref() != expected {
+                redis::cmd("UNWATCH").query::<()>(conn)?;
+                return Ok(false);
+            }
+
+            // `EXEC` returns nil if the watched key was modified
+            let result: Option<()> = redis::pipe().atomic().set(key, new).ignore().query(conn)?;
+
+            Ok(result.is_some())
+        })
+    }
+}
+
+impl Display for RedisBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Avoid displaying credentials
+        match &self.client.get_connection_info().addr {
+            ConnectionAddr::Tcp(host, port) => write!(f, "redis://{}:{}", host, port),
+            ConnectionAddr::TcpTls { host, port, .. } => write!(f, "rediss://{}:{}", host, port),
+            ConnectionAddr::Unix(path) => write!(f, "redis+unix://{}", path.display()),
+        }
+    }
+}
+
+/// Consensus state stored under a key, along with what's stored with it
+struct StoredState {
+    /// Consensus state
+    state: consensus::State,
+
+    /// Payload signed at the consensus state (if any)
+    payload: Option<SignedPayload>,
+
+    /// Consensus public key of the chain (if stored)
+    pub_key: Option<PublicKey>,
+}
+
+/// Parse a consensus state stored under the given key, along with the
+/// payload signed at it and the chain's public key (if any), renumbering its
+/// step if it was stored unversioned
+fn parse_state(key: &str, json: &str) -> Result<StoredState, Error> {
+    let parse_err =
+        |e: serde_json::Error| format_err!(ParseError, "error parsing state in {}: {}", key, e);
+
+    let mut json: serde_json::Value = serde_json::from_str(json).map_err(parse_err)?;
+    let mut take_field = |field| json.as_object_mut().and_then(|obj| obj.remove(field));
+
+    let version = match take_field(VERSION_FIELD) {
+        Some(version) => match version.as_u64() {
+            Some(version @ 2..=VERSION) => version,
+            _ => fail!(
+                ParseError,
+                "error parsing state in {}: unsupported `version` {} (written by a newer tmkms?)",
+                key,
+                version
+            ),
+        },
+        None => 1,
+    };
+
+    let sign_bytes = match take_field(SIGN_BYTES_FIELD) {
+        Some(serde_json::Value::String(sign_bytes)) => Some(
+            hex::decode(sign_bytes.to_ascii_lowercase())
+                .map_err(|e| format_err!(ParseError, "error parsing state in {}: {}", key, e))?,
+        ),
+        Some(_) => fail!(
+            ParseError,
+            "error parsing state in {}: invalid `signbytes`",
+            key
+        ),
+        None => None,
+    };
+
+    let sign_bytes_hash = match take_field(SIGN_BYTES_HASH_FIELD) {
+        Some(serde_json::Value::String(hash)) => Some(hash),
+        Some(_) => fail!(
+            ParseError,
+            "error parsing state in {}: invalid `signbytes_sha256`",
+            key
+        ),
+        None => None,
+    };
+
+    let timestamp = match take_field(TIMESTAMP_FIELD) {
+        Some(timestamp) => Some(serde_json::from_value(timestamp).map_err(parse_err)?),
+        None => None,
+    };
+
+    let msg_type = match take_field(MSG_TYPE_FIELD) {
+        Some(serde_json::Value::String(name)) => Some(msg_type_named(&name).ok_or_else(|| {
+            format_err!(
+                ParseError,
+                "error parsing state in {}: unknown `msg_type` {}",
+                key,
+                name
+            )
+        })?),
+        Some(_) => fail!(
+            ParseError,
+            "error parsing state in {}: invalid `msg_type`",
+            key
+        ),
+        None => None,
+    };
+
+    let pub_key = match take_field(PUB_KEY_FIELD) {
+        Some(pub_key) => Some(serde_json::from_value(pub_key).map_err(parse_err)?),
+        None => None,
+    };
+
+    let mut state: consensus::State = serde_json::from_value(json).map_err(parse_err)?;
+
+    if version < 2 {
+        state.step = Step::renumber_legacy(state.step);
+    }
+
+    let payload = match sign_bytes {
+        Some(sign_bytes) => {
+            let payload = SignedPayload {
+                sign_bytes,
+                timestamp,
+                msg_type,
+            };
+
+            if let Some(hash) = sign_bytes_hash {
+                if !hash.eq_ignore_ascii_case(&payload.sign_bytes_hash()) {
+                    fail!(
+                        ParseError,
+                        "error parsing state in {}: `signbytes_sha256` doesn't match `signbytes`",
+                        key
+                    );
+                }
+            }
+
+            Some(payload)
+        }
+        None => None,
+    };
+
+    Ok(StoredState {
+        state,
+        payload,
+        pub_key,
+    })
+}
+
+/// Ensure the new state advances the stored height/round/step. Re-signing at
+/// the stored HRS is only permitted for the same block ID.
+fn check_advance(current: &consensus::State, new: &consensus::State) -> Result<(), Error> {
+    let current_hrs = (current.height, current.round, current.step);
+    let new_hrs = (new.height, new.round, new.step);
+
+    if new_hrs < current_hrs || (new_hrs == current_hrs && new.block_id != current.block_id) {
+        fail!(
+            DoubleSign,
+            "shared state is at h/r/s {}/{}/{} ({}); refusing to store {}/{}/{} ({})",
+            current.height,
+            current.round,
+            current.step,
+            current.block_id_prefix(),
+            new.height,
+            new.round,
+            new.step,
+            new.block_id_prefix()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{amino_types::SignedMsgType, chain::state::sign_bytes_hash};
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+    use tendermint::block;
+
+    const EXAMPLE_BLOCK_ID: &str =
+        "26C0A41F3243C6BCD7AD2DFF8A8D83A71D29D307B5326C227F734A1A512FE47D";
+
+    /// In-process compare-and-set store which can be shared between several
+    /// state stores, standing in for a Redis server
+    #[derive(Clone, Default)]
+    struct FakeBackend(Arc<Mutex<HashMap<String, String>>>);
+
+    impl CasBackend for FakeBackend {
+        fn get(&mut self, key: &str) -> Result<Option<String>, Error> {
+            Ok(self.0.lock().unwrap().get(key).cloned())
+        }
+
+        fn compare_and_set(
+            &mut self,
+            key: &str,
+            expected: Option<&str>,
+            new: &str,
+        ) -> Result<bool, Error> {
+            let mut map = self.0.lock().unwrap();
+
+            if map.get(key).map(String::as_str) != expected {
+                return Ok(false);
+            }
+
+            map.insert(key.to_owned(), new.to_owned());
+            Ok(true)
+        }
+    }
+
+    fn store(backend: &FakeBackend) -> RedisStateStore<FakeBackend> {
+        RedisStateStore::with_backend(
+            backend.clone(),
+            DEFAULT_KEY_PREFIX,
+            &"example-chain".parse().unwrap(),
+            "fake",
+        )
+    }
+
+    fn state(height: u32, round: u16, step: i8, block_id: Option<&str>) -> consensus::State {
+        consensus::State {
+            height: block::Height::from(height),
+            round: block::Round::from(round),
+            step,
+            block_id: block_id.map(|id| id.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn advancing_updates_accepted() {
+        let backend = FakeBackend::default();
+        let mut store = store(&backend);
+
+        assert_eq!(store.load().unwrap(), None);
+
+        store.store(&state(1, 0, 0, None)).unwrap();
+        store
+            .store(&state(1, 0, 1, Some(EXAMPLE_BLOCK_ID)))
+            .unwrap();
+        store
+            .store(&state(1, 0, 1, Some(EXAMPLE_BLOCK_ID)))
+            .unwrap();
+        store.store(&state(2, 0, 0, None)).unwrap();
+
+        assert_eq!(store.load().unwrap(), Some(state(2, 0, 0, None)));
+    }
+
+    #[test]
+    fn regressing_update_rejected() {
+        let backend = FakeBackend::default();
+        let mut active = store(&backend);
+        let mut passive = store(&backend);
+
+        active
+            .store(&state(5, 1, 2, Some(EXAMPLE_BLOCK_ID)))
+            .unwrap();
+
+        // The other instance can't sign at or below the shared high-watermark
+        for regression in &[
+            state(4, 9, 3, None),
+            state(5, 0, 3, None),
+            state(5, 1, 1, None),
+            state(5, 1, 2, None),
+        ] {
+            let err = passive.store(regression).unwrap_err();
+            assert_eq!(*err.kind(), DoubleSign);
+        }
+
+        assert_eq!(
+            passive.load().unwrap(),
+            Some(state(5, 1, 2, Some(EXAMPLE_BLOCK_ID)))
+        );
+    }
+
+    #[test]
+    fn concurrent_modification_rechecked() {
+        /// Backend which lets another instance win the race once
+        struct RacingBackend {
+            inner: FakeBackend,
+            raced: bool,
+        }
+
+        impl CasBackend for RacingBackend {
+            fn get(&mut self, key: &str) -> Result<Option<String>, Error> {
+                self.inner.get(key)
+            }
+
+            fn compare_and_set(
+                &mut self,
+                key: &str,
+                expected: Option<&str>,
+                new: &str,
+            ) -> Result<bool, Error> {
+                if !self.raced {
+                    self.raced = true;
+                    store(&self.inner).store(&state(7, 0, 0, None)).unwrap();
+                }
+
+                self.inner.compare_and_set(key, expected, new)
+            }
+        }
+
+        let backend = FakeBackend::default();
+        store(&backend).store(&state(5, 0, 0, None)).unwrap();
+
+        let mut racing = RedisStateStore::with_backend(
+            RacingBackend {
+                inner: backend.clone(),
+                raced: false,
+            },
+            DEFAULT_KEY_PREFIX,
+            &"example-chain".parse().unwrap(),
+            "racing",
+        );
+
+        let err = racing.store(&state(6, 0, 0, None)).unwrap_err();
+        assert_eq!(*err.kind(), DoubleSign);
+        assert_eq!(store(&backend).load().unwrap(), Some(state(7, 0, 0, None)));
+    }
+
+    #[test]
+    fn signed_payload_is_shared() {
+        let backend = FakeBackend::default();
+        let payload = SignedPayload {
+            sign_bytes: b"vote".to_vec(),
+            timestamp: Some("2018-02-11T07:09:22.765Z".parse().unwrap()),
+            msg_type: Some(SignedMsgType::PreVote),
+        };
+
+        store(&backend)
+            .store_signed(&state(1, 0, 2, None), Some(&payload))
+            .unwrap();
+
+        // The payload is stored in the same value as the state
+        let json: serde_json::Value =
+            serde_json::from_str(&backend.0.lock().unwrap()["tmkms:example-chain"]).unwrap();
+        assert_eq!(json["msg_type"], "prevote");
+        assert_eq!(json["signbytes_sha256"], sign_bytes_hash(b"vote"));
+
+        let mut passive = store(&backend);
+        assert_eq!(
+            passive.load_signed().unwrap(),
+            Some((state(1, 0, 2, None), Some(payload)))
+        );
+
+        // A hash which doesn't match the sign bytes is refused
+        backend.0.lock().unwrap().insert(
+            "tmkms:example-chain".to_owned(),
+            r#"{"height":"1","round":"0","step":2,"block_id":null,"version":2,"signbytes":"00","signbytes_sha256":"00"}"#.to_owned(),
+        );
+
+        let err = passive.load_signed().unwrap_err();
+        assert!(err.to_string().contains("doesn't match"));
+    }
+
+    #[test]
+    fn pub_key_is_shared() {
+        let backend = FakeBackend::default();
+        let pub_key = PublicKey::from_raw_ed25519(&[0x11; 32]).unwrap();
+
+        let mut active = store(&backend);
+        active.set_pub_key(pub_key);
+        active.store(&state(1, 0, 0, None)).unwrap();
+
+        // The key is kept as the state is updated, and seen by the other
+        // instance sharing it
+        let mut passive = store(&backend);
+        passive.load().unwrap();
+        assert_eq!(passive.pub_key(), Some(pub_key));
+        passive.store(&state(2, 0, 0, None)).unwrap();
+
+        active.load().unwrap();
+        assert_eq!(active.pub_key(), Some(pub_key));
+    }
+
+    #[test]
+    fn renumber_step_of_unversioned_state() {
+        let backend = FakeBackend::default();
+
+        // A nil prevote, as stored by versions of tmkms which numbered steps
+        // from 0 (i.e. 1 = prevote)
+        backend.0.lock().unwrap().insert(
+            "tmkms:example-chain".to_owned(),
+            r#"{"height":"5","round":"1","step":1,"block_id":null}"#.to_owned(),
+        );
+
+        let mut store = store(&backend);
+        assert_eq!(
+            store.load().unwrap(),
+            Some(state(5, 1, Step::Prevote.value(), None))
+        );
+
+        // A prevote for a block at the same height/round is refused
+        let err = store
+            .store(&state(5, 1, Step::Prevote.value(), Some(EXAMPLE_BLOCK_ID)))
+            .unwrap_err();
+        assert_eq!(*err.kind(), DoubleSign);
+
+        // States are stored versioned, so they're only renumbered once
+        store
+            .store(&state(5, 1, Step::Precommit.value(), None))
+            .unwrap();
+        assert_eq!(
+            store.load().unwrap(),
+            Some(state(5, 1, Step::Precommit.value(), None))
+        );
+    }
+}
@@ -0,0 +1,517 @@
+//! SQLite state store: consensus state for any number of chains kept in a
+//! single database file
+//!
+//! The version of the schema is kept in the database's `user_version`.
+//! Databases created before it was (version 0) number steps from 0 rather
+//! than as Tendermint does (see [`Step`]), so their steps are renumbered when
+//! opened.
+//!
+//! Each chain's row also holds the payload last signed at its state (its
+//! sign bytes, their SHA-256, timestamp and message type), so retries can be
+//! told apart from conflicting requests after a restart, and the chain's
+//! consensus public key, so a different key being loaded for it is noticed.
+
+use super::{json::read_state_file, msg_type_named, SignedPayload, StateStore};
+use crate::{
+    chain,
+    chain::state::Step,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tendermint::{block, consensus, PublicKey};
+
+/// How long to wait on a database locked by another chain's connection
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Version of the schema created by this version of tmkms
+const SCHEMA_VERSION: u32 = 1;
+
+/// Database schema: one row per chain
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS consensus_state (
+        chain_id TEXT PRIMARY KEY NOT NULL,
+        height INTEGER NOT NULL,
+        round INTEGER NOT NULL,
+        step INTEGER NOT NULL,
+        block_id TEXT,
+        sign_bytes BLOB,
+        sign_bytes_sha256 TEXT,
+        timestamp TEXT,
+        msg_type TEXT,
+        pub_key TEXT
+    );
+";
+
+/// Consensus state for a particular chain stored in a SQLite database
+pub struct SqliteStateStore {
+    /// Connection to the database
+    conn: Connection,
+
+    /// Path to the database
+    path: PathBuf,
+
+    /// Chain this store holds the state of
+    chain_id: chain::Id,
+
+    /// Consensus public key of the chain, as last loaded or set
+    pub_key: Option<PublicKey>,
+}
+
+impl SqliteStateStore {
+    /// Open (or create) the SQLite database at the given path, storing the
+    /// state of the given chain
+    pub fn open(path: impl Into<PathBuf>, chain_id: chain::Id) -> Result<Self, Error> {
+        let path = path.into();
+        let conn = Connection::open(&path)?;
+
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        conn.pragma_update(None, "synchronous", "FULL")?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+
+        let mut store = Self {
+            conn,
+            path,
+            chain_id,
+            pub_key: None,
+        };
+
+        store.migrate()?;
+        Ok(store)
+    }
+
+    /// Create the schema, or migrate an existing database to the current
+    /// version of it.
+    ///
+    /// Done inside a transaction which locks the database, so chains sharing
+    /// it never migrate it twice.
+    fn migrate(&mut self) -> Result<(), Error> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let version: u32 = tx.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+        if version < SCHEMA_VERSION {
+            let existing = tx
+                .query_row(
+                    "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'consensus_state'",
+                    [],
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some();
+
+            if existing {
+                info!(
+                    "[{}] renumbering consensus steps in {} as Tendermint does",
+                    &self.chain_id,
+                    self.path.display()
+                );
+
+                let mut stmt = tx.prepare("SELECT chain_id, step FROM consensus_state")?;
+                let rows = stmt
+                    .query_map([], |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, i8>(1)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                drop(stmt);
+
+                for (chain_id, step) in rows {
+                    tx.execute(
+                        "UPDATE consensus_state SET step = ?1 WHERE chain_id = ?2",
+                        params![Step::renumber_legacy(step), chain_id],
+                    )?;
+                }
+            }
+
+            tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
+
+        tx.execute_batch(SCHEMA)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// If no state has been stored for this chain yet, import it from the
+    /// given JSON state file (if it exists)
+    pub fn import_json(&mut self, json_path: &Path) -> Result<(), Error> {
+        if self.load()?.is_some() || !json_path.exists() {
+            return Ok(());
+        }
+
+        let consensus_state = read_state_file(json_path)?;
+
+        info!(
+            "[{}] importing consensus state from {} into {}: {:?}",
+            &self.chain_id,
+            json_path.display(),
+            self,
+            &consensus_state
+        );
+
+        self.store(&consensus_state)
+    }
+}
+
+impl StateStore for SqliteStateStore {
+    fn load(&mut self) -> Result<Option<consensus::State>, Error> {
+        Ok(self.load_signed()?.map(|(state, _)| state))
+    }
+
+    fn store(&mut self, state: &consensus::State) -> Result<(), Error> {
+        self.store_signed(state, None)
+    }
+
+    fn load_signed(&mut self) -> Result<Option<(consensus::State, Option<SignedPayload>)>, Error> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT height, round, step, block_id, sign_bytes, sign_bytes_sha256, timestamp, \
+                 msg_type, pub_key FROM consensus_state WHERE chain_id = ?1",
+                params![self.chain_id.as_str()],
+                |row| {
+                    Ok(Row {
+                        height: row.get(0)?,
+                        round: row.get(1)?,
+                        step: row.get(2)?,
+                        block_id: row.get(3)?,
+                        sign_bytes: row.get(4)?,
+                        sign_bytes_hash: row.get(5)?,
+                        timestamp: row.get(6)?,
+                        msg_type: row.get(7)?,
+                        pub_key: row.get(8)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let block_id = match row.block_id {
+            Some(json) => serde_json::from_str::<Option<block::Id>>(&json)?,
+            None => None,
+        };
+
+        let payload = match row.sign_bytes {
+            Some(sign_bytes) => Some(self.parse_payload(
+                sign_bytes,
+                row.sign_bytes_hash,
+                row.timestamp,
+                row.msg_type,
+            )?),
+            None => None,
+        };
+
+        self.pub_key = match row.pub_key {
+            Some(json) => Some(serde_json::from_str(&json)?),
+            None => None,
+        };
+
+        let state = consensus::State {
+            height: block::Height::try_from(row.height)?,
+            round: block::Round::try_from(row.round)?,
+            step: row.step,
+            block_id,
+        };
+
+        Ok(Some((state, payload)))
+    }
+
+    /// Upsert this chain's row inside a transaction, so the state and the
+    /// payload signed at it are always updated together
+    fn store_signed(
+        &mut self,
+        state: &consensus::State,
+        payload: Option<&SignedPayload>,
+    ) -> Result<(), Error> {
+        let block_id = match state.block_id {
+            Some(ref id) => Some(serde_json::to_string(id)?),
+            None => None,
+        };
+
+        let timestamp = match payload.and_then(|payload| payload.timestamp) {
+            Some(timestamp) => Some(serde_json::to_string(&timestamp)?),
+            None => None,
+        };
+
+        let pub_key = match self.pub_key {
+            Some(ref pub_key) => Some(serde_json::to_string(pub_key)?),
+            None => None,
+        };
+
+        let tx = self.conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO consensus_state (chain_id, height, round, step, block_id, sign_bytes,
+                sign_bytes_sha256, timestamp, msg_type, pub_key)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT (chain_id) DO UPDATE SET
+                height = excluded.height,
+                round = excluded.round,
+                step = excluded.step,
+                block_id = excluded.block_id,
+                sign_bytes = excluded.sign_bytes,
+                sign_bytes_sha256 = excluded.sign_bytes_sha256,
+                timestamp = excluded.timestamp,
+                msg_type = excluded.msg_type,
+                pub_key = excluded.pub_key",
+            params![
+                self.chain_id.as_str(),
+                state.height.value() as i64,
+                state.round.value() as i64,
+                state.step,
+                block_id,
+                payload.map(|payload| &payload.sign_bytes),
+                payload.map(SignedPayload::sign_bytes_hash),
+                timestamp,
+                payload.and_then(|payload| payload.msg_type.map(|msg_type| msg_type.as_str())),
+                pub_key
+            ],
+        )?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn pub_key(&self) -> Option<PublicKey> {
+        self.pub_key
+    }
+
+    fn set_pub_key(&mut self, pub_key: PublicKey) {
+        self.pub_key = Some(pub_key);
+    }
+}
+
+impl SqliteStateStore {
+    /// Parse the payload signed at this chain's state from the columns of its
+    /// row, ensuring the stored hash matches the sign bytes
+    fn parse_payload(
+        &self,
+        sign_bytes: Vec<u8>,
+        sign_bytes_hash: Option<String>,
+        timestamp: Option<String>,
+        msg_type: Option<String>,
+    ) -> Result<SignedPayload, Error> {
+        let timestamp = match timestamp {
+            Some(json) => Some(serde_json::from_str(&json)?),
+            None => None,
+        };
+
+        let msg_type =
+            match msg_type {
+                Some(name) => Some(msg_type_named(&name).ok_or_else(|| {
+                    format_err!(ParseError, "{}: unknown `msg_type` {}", self, name)
+                })?),
+                None => None,
+            };
+
+        let payload = SignedPayload {
+            sign_bytes,
+            timestamp,
+            msg_type,
+        };
+
+        if let Some(hash) = sign_bytes_hash {
+            if !hash.eq_ignore_ascii_case(&payload.sign_bytes_hash()) {
+                fail!(
+                    ParseError,
+                    "{}: `sign_bytes_sha256` doesn't match `sign_bytes`",
+                    self
+                );
+            }
+        }
+
+        Ok(payload)
+    }
+}
+
+/// Columns of a chain's row
+struct Row {
+    height: i64,
+    round: u32,
+    step: i8,
+    block_id: Option<String>,
+    sign_bytes: Option<Vec<u8>>,
+    sign_bytes_hash: Option<String>,
+    timestamp: Option<String>,
+    msg_type: Option<String>,
+    pub_key: Option<String>,
+}
+
+impl Display for SqliteStateStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sqlite:{}#{}", self.path.display(), &self.chain_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        amino_types::SignedMsgType,
+        chain::state::{sign_bytes_hash, JsonStateStore},
+    };
+
+    const EXAMPLE_BLOCK_ID: &str =
+        "26C0A41F3243C6BCD7AD2DFF8A8D83A71D29D307B5326C227F734A1A512FE47D";
+
+    fn example_state(height: u32, block_id: Option<&str>) -> consensus::State {
+        consensus::State {
+            height: block::Height::from(height),
+            round: block::Round::from(1u16),
+            step: 2,
+            block_id: block_id.map(|id| id.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn store_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.sqlite");
+        let mut store = SqliteStateStore::open(&path, "chain-a".parse().unwrap()).unwrap();
+
+        assert_eq!(store.load().unwrap(), None);
+
+        store.store(&example_state(1, None)).unwrap();
+        store
+            .store(&example_state(2, Some(EXAMPLE_BLOCK_ID)))
+            .unwrap();
+
+        // Reopen to ensure the state was persisted
+        let mut store = SqliteStateStore::open(&path, "chain-a".parse().unwrap()).unwrap();
+        assert_eq!(
+            store.load().unwrap(),
+            Some(example_state(2, Some(EXAMPLE_BLOCK_ID)))
+        );
+
+        // Other chains in the same database are unaffected
+        let mut other = SqliteStateStore::open(&path, "chain-b".parse().unwrap()).unwrap();
+        assert_eq!(other.load().unwrap(), None);
+    }
+
+    #[test]
+    fn import_from_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let json_path = dir.path().join("chain-a_priv_validator_state.json");
+        let state = example_state(42, Some(EXAMPLE_BLOCK_ID));
+        JsonStateStore::new(&json_path).store(&state).unwrap();
+
+        let mut store =
+            SqliteStateStore::open(dir.path().join("state.sqlite"), "chain-a".parse().unwrap())
+                .unwrap();
+
+        store.import_json(&json_path).unwrap();
+        assert_eq!(store.load().unwrap(), Some(state));
+
+        // Existing state in the database takes precedence over the JSON file
+        store.store(&example_state(43, None)).unwrap();
+        store.import_json(&json_path).unwrap();
+        assert_eq!(store.load().unwrap(), Some(example_state(43, None)));
+    }
+
+    #[test]
+    fn signed_payload_is_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.sqlite");
+        let payload = SignedPayload {
+            sign_bytes: b"vote".to_vec(),
+            timestamp: Some("2018-02-11T07:09:22.765Z".parse().unwrap()),
+            msg_type: Some(SignedMsgType::PreVote),
+        };
+
+        let mut store = SqliteStateStore::open(&path, "chain-a".parse().unwrap()).unwrap();
+        store
+            .store_signed(&example_state(1, None), Some(&payload))
+            .unwrap();
+
+        let mut store = SqliteStateStore::open(&path, "chain-a".parse().unwrap()).unwrap();
+        assert_eq!(
+            store.load_signed().unwrap(),
+            Some((example_state(1, None), Some(payload)))
+        );
+
+        // The hash of the sign bytes is stored with them...
+        let hash: String = store
+            .conn
+            .query_row(
+                "SELECT sign_bytes_sha256 FROM consensus_state WHERE chain_id = 'chain-a'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(hash, sign_bytes_hash(b"vote"));
+
+        // ...and must match them
+        store
+            .conn
+            .execute("UPDATE consensus_state SET sign_bytes = x'00'", [])
+            .unwrap();
+        let err = store.load_signed().unwrap_err();
+        assert!(err.to_string().contains("doesn't match"));
+
+        // Storing a state without a payload clears it
+        let mut store = SqliteStateStore::open(&path, "chain-a".parse().unwrap()).unwrap();
+        store.store(&example_state(2, None)).unwrap();
+        assert_eq!(
+            store.load_signed().unwrap(),
+            Some((example_state(2, None), None))
+        );
+    }
+
+    #[test]
+    fn pub_key_is_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.sqlite");
+        let pub_key = PublicKey::from_raw_ed25519(&[0x11; 32]).unwrap();
+
+        let mut store = SqliteStateStore::open(&path, "chain-a".parse().unwrap()).unwrap();
+        store.set_pub_key(pub_key);
+        store.store(&example_state(1, None)).unwrap();
+
+        // The key is kept as the state is updated
+        let mut store = SqliteStateStore::open(&path, "chain-a".parse().unwrap()).unwrap();
+        store.load().unwrap();
+        assert_eq!(store.pub_key(), Some(pub_key));
+        store.store(&example_state(2, None)).unwrap();
+
+        let mut store = SqliteStateStore::open(&path, "chain-a".parse().unwrap()).unwrap();
+        store.load().unwrap();
+        assert_eq!(store.pub_key(), Some(pub_key));
+
+        // Other chains in the same database have their own key
+        let mut other = SqliteStateStore::open(&path, "chain-b".parse().unwrap()).unwrap();
+        other.load().unwrap();
+        assert_eq!(other.pub_key(), None);
+    }
+
+    #[test]
+    fn renumber_steps_of_unversioned_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.sqlite");
+
+        // A nil prevote, as stored by versions of tmkms which numbered steps
+        // from 0 (i.e. 1 = prevote)
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        conn.execute(
+            "INSERT INTO consensus_state (chain_id, height, round, step) VALUES ('chain-a', 5, 1, 1)",
+            [],
+        )
+        .unwrap();
+        drop(conn);
+
+        for _ in 0..2 {
+            let mut store = SqliteStateStore::open(&path, "chain-a".parse().unwrap()).unwrap();
+            assert_eq!(store.load().unwrap().unwrap().step, Step::Prevote.value());
+        }
+    }
+}
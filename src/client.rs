@@ -6,20 +6,507 @@
 //! as a "Key Management System".
 
 use crate::{
-    chain,
-    config::ValidatorConfig,
+    alerts, chain,
+    config::{ConfigOrigin, ProtocolVersion, ValidatorAddr, ValidatorConfig, ValidatorRole},
+    connection::{Interrupt, Listener},
     error::{Error, ErrorKind},
+    key_utils, metrics,
     prelude::*,
     session::Session,
+    status,
+    systemd::Heartbeat,
 };
-use std::{panic, process::exit, thread, time::Duration};
+use once_cell::sync::Lazy;
+use rand_core::{OsRng, RngCore};
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    process::exit,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tendermint::block;
+
+#[cfg(feature = "async")]
+mod async_loop;
+
+/// Join handle of a client's thread (or task, for async clients)
+enum JoinHandle {
+    /// Thread running the client
+    Thread(thread::JoinHandle<Result<(), Error>>),
+
+    /// Task running the client on the async runtime
+    #[cfg(feature = "async")]
+    Task(tokio::task::JoinHandle<Result<(), Error>>),
+}
 
-/// Join handle type used by our clients
-type JoinHandle = thread::JoinHandle<Result<(), Error>>;
+/// Environment variable which overrides `async_clients` when set to `1` (or
+/// `0`), e.g. to run the integration tests against both client loops
+pub const ASYNC_CLIENTS_ENV_VAR: &str = "TMKMS_ASYNC_CLIENTS";
 
-/// How long to wait after a crash before respawning (in seconds)
+/// Are clients run on the async runtime, rather than a thread each?
+static ASYNC: AtomicBool = AtomicBool::new(false);
+
+/// How long to wait after a crash (or connection failure) before respawning
+/// (in seconds)
 pub const RESPAWN_DELAY: u64 = 1;
 
+/// Default delay before the first reconnect attempt when backoff is
+/// configured (in seconds)
+pub const DEFAULT_RECONNECT_INITIAL_DELAY: u64 = 1;
+
+/// Default maximum delay between reconnect attempts when backoff is
+/// configured (in seconds)
+pub const DEFAULT_RECONNECT_MAX_DELAY: u64 = 60;
+
+/// Run clients spawned from now on on the async runtime (if `enabled`), or
+/// on a thread each (the default). Async clients require the `async` cargo
+/// feature.
+pub fn set_async(enabled: Option<bool>) -> Result<(), Error> {
+    let enabled = match std::env::var(ASYNC_CLIENTS_ENV_VAR).as_deref() {
+        Ok("1") => true,
+        Ok("0") => false,
+        _ => enabled.unwrap_or(false),
+    };
+
+    if enabled && cfg!(not(feature = "async")) {
+        fail!(
+            ErrorKind::ConfigError,
+            "`async_clients` requires tmkms to be built with the `async` feature"
+        );
+    }
+
+    ASYNC.store(enabled, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Validator clients spawned by the `start` command
+pub static CLIENTS: Lazy<Clients> = Lazy::new(Clients::default);
+
+/// Set of validator clients, which changes as the configuration is reloaded
+#[derive(Default)]
+pub struct Clients {
+    /// Clients which are running (or have exited but not yet been joined)
+    clients: Mutex<Vec<Client>>,
+
+    /// Notified whenever a client thread exits
+    exited: Condvar,
+}
+
+impl Clients {
+    /// Spawn a client for each of the given validators
+    pub fn spawn(&self, configs: &[ValidatorConfig]) {
+        let mut clients = self.clients.lock().unwrap();
+        clients.extend(configs.iter().cloned().map(Client::spawn));
+    }
+
+    /// Update the running clients to match the given validator configurations.
+    ///
+    /// Clients for removed validators are stopped, and clients for added
+    /// validators are spawned. Validators whose configuration only differs
+    /// in `min_height`/`max_height` keep their existing connection, and any
+    /// other change is handled by stopping the old client and spawning a new
+    /// one.
+    pub fn reload(&self, configs: &[ValidatorConfig]) {
+        let mut clients = self.clients.lock().unwrap();
+        let mut added: Vec<&ValidatorConfig> = configs.iter().collect();
+
+        for client in clients.iter_mut() {
+            if client.control.is_stopped() || client.control.has_exited() {
+                continue;
+            }
+
+            match added
+                .iter()
+                .position(|config| same_session(config, &client.config))
+            {
+                Some(i) => {
+                    let config = added.remove(i);
+
+                    if config.min_height != client.config.min_height {
+                        info!(
+                            "[{}] min_height changed: {} -> {}",
+                            client.name,
+                            display_height(client.config.min_height),
+                            display_height(config.min_height)
+                        );
+
+                        client.control.set_min_height(config.min_height);
+                        client.config.min_height = config.min_height;
+                        warn_if_above_watermark(config);
+                    }
+
+                    if config.max_height != client.config.max_height {
+                        info!(
+                            "[{}] max_height changed: {} -> {}",
+                            client.name,
+                            display_height(client.config.max_height),
+                            display_height(config.max_height)
+                        );
+                    }
+
+                    // Even if unchanged, `max_height` may have moved within the file
+                    client
+                        .control
+                        .set_max_height(config.max_height, config.max_height_origin.clone());
+                    client.config.max_height = config.max_height;
+                    client.config.max_height_origin = config.max_height_origin.clone();
+                }
+                None => {
+                    info!("[{}] validator removed: disconnecting", client.name);
+                    client.control.stop();
+                }
+            }
+        }
+
+        for config in added {
+            info!(
+                "[{}@{}] validator added: connecting",
+                config.chain_id, config.addr
+            );
+            clients.push(Client::spawn(config.clone()));
+        }
+    }
+
+    /// Stop all running clients. Their threads exit once their current
+    /// session (if any) is interrupted or handles its next request.
+    pub fn stop(&self) {
+        for client in self.clients.lock().unwrap().iter() {
+            client.control.stop();
+        }
+    }
+
+    /// Stop all running clients, interrupting sessions which are waiting for
+    /// a request so their threads exit promptly
+    pub fn interrupt(&self) {
+        for client in self.clients.lock().unwrap().iter() {
+            client.control.interrupt();
+        }
+    }
+
+    /// Wait until all clients have exited or the deadline passes, returning
+    /// the names of the clients which are still running
+    pub fn wait_until(&self, deadline: Instant) -> Vec<String> {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+
+        loop {
+            let running = clients
+                .iter()
+                .filter(|client| !client.control.has_exited())
+                .map(|client| client.name.clone())
+                .collect::<Vec<_>>();
+
+            let now = Instant::now();
+
+            if running.is_empty() || now >= deadline {
+                return running;
+            }
+
+            clients = self
+                .exited
+                .wait_timeout(clients, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Log the status of each client (e.g. on `SIGUSR1`)
+    pub fn log_status(&self) {
+        for client in self
+            .clients
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            info!(
+                chain_id = %client.config.chain_id,
+                "[{}] status: {} (role: {}; chains: {}; restarts: {})",
+                client.name,
+                client.control.status(),
+                client.config.role,
+                client
+                    .config
+                    .chain_ids
+                    .iter()
+                    .map(chain::Id::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                client.control.restarts()
+            );
+        }
+    }
+
+    /// Wait for all clients to exit (including those spawned while waiting),
+    /// returning `true` if they all exited successfully
+    pub fn wait(&self) -> bool {
+        let mut clients = self.clients.lock().unwrap();
+        let mut success = true;
+
+        loop {
+            let (exited, running): (Vec<_>, Vec<_>) = clients
+                .drain(..)
+                .partition(|client| client.control.has_exited());
+
+            *clients = running;
+
+            for client in exited {
+                let name = client.name().to_owned();
+
+                if let Err(e) = client.join() {
+                    status_err!("client '{}' exited with error: {}", name, e);
+                    success = false;
+                }
+            }
+
+            if clients.is_empty() {
+                return success;
+            }
+
+            clients = self.exited.wait(clients).unwrap();
+        }
+    }
+
+    /// Notify the waiting thread that a client has exited
+    fn notify_exited(&self) {
+        let _clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        self.exited.notify_all();
+    }
+}
+
+/// Status of a client, as reported by [`Clients::log_status`] and in the
+/// [`status`] of its chains
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Status {
+    /// Connecting to (or waiting for a connection from) the validator
+    Connecting,
+
+    /// Connected to the validator and handling its requests
+    Connected,
+
+    /// Waiting to reconnect after the connection failed
+    Reconnecting,
+
+    /// Waiting to restart after the client crashed
+    Crashed,
+
+    /// Exited after being stopped
+    Stopped,
+
+    /// Exited with an error (e.g. after giving up reconnecting)
+    Failed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Connecting => "connecting",
+            Status::Connected => "connected",
+            Status::Reconnecting => "reconnecting",
+            Status::Crashed => "crashed",
+            Status::Stopped => "stopped",
+            Status::Failed => "failed",
+        })
+    }
+}
+
+/// Runtime control of a client thread, used to apply configuration changes
+/// to its sessions or stop it
+pub struct Control {
+    /// Chains served by the client
+    chain_ids: Vec<chain::Id>,
+
+    /// Address of the client's validator (the one currently connected to,
+    /// if it has several)
+    addr: Mutex<String>,
+
+    /// Minimum block height to sign at
+    min_height: Mutex<Option<block::Height>>,
+
+    /// Maximum block height to sign at, and where it's set in the
+    /// configuration file
+    max_height: Mutex<(Option<block::Height>, Option<ConfigOrigin>)>,
+
+    /// Has the client been asked to stop?
+    stopped: AtomicBool,
+
+    /// Has the client thread exited?
+    exited: AtomicBool,
+
+    /// Current status of the client
+    status: Mutex<Status>,
+
+    /// Number of times the client has been restarted after crashing
+    restarts: AtomicU32,
+
+    /// Handle used to interrupt the current session's connection (if any)
+    interrupt: Mutex<Option<Box<dyn Interrupt>>>,
+
+    /// Notified when the client is stopped, waking it from [`Control::sleep`]
+    wakeup: Condvar,
+
+    /// Heartbeat checked by the systemd watchdog
+    heartbeat: Arc<Heartbeat>,
+}
+
+impl Control {
+    /// Create a new control for a client with the given configuration
+    pub fn new(config: &ValidatorConfig) -> Self {
+        let addr = config.addr.to_string();
+        status::connection(&config.chain_ids, &addr, Status::Connecting);
+        status::role(&config.chain_ids, &addr, config.role);
+
+        Self {
+            chain_ids: config.chain_ids.clone(),
+            addr: Mutex::new(addr),
+            min_height: Mutex::new(config.min_height),
+            max_height: Mutex::new((config.max_height, config.max_height_origin.clone())),
+            stopped: AtomicBool::new(false),
+            exited: AtomicBool::new(false),
+            status: Mutex::new(Status::Connecting),
+            restarts: AtomicU32::new(0),
+            interrupt: Mutex::new(None),
+            wakeup: Condvar::new(),
+            heartbeat: Heartbeat::register(format!("{}@{}", &config.chain_id, &config.addr)),
+        }
+    }
+
+    /// Get the minimum block height to sign at
+    pub fn min_height(&self) -> Option<block::Height> {
+        *self.min_height.lock().unwrap()
+    }
+
+    /// Set the minimum block height to sign at
+    pub fn set_min_height(&self, min_height: Option<block::Height>) {
+        *self.min_height.lock().unwrap() = min_height;
+    }
+
+    /// Get the maximum block height to sign at
+    pub fn max_height(&self) -> Option<block::Height> {
+        self.max_height.lock().unwrap().0
+    }
+
+    /// Get where the maximum block height is set in the configuration file
+    pub fn max_height_origin(&self) -> Option<ConfigOrigin> {
+        self.max_height.lock().unwrap().1.clone()
+    }
+
+    /// Set the maximum block height to sign at, and where it's set in the
+    /// configuration file
+    pub fn set_max_height(&self, max_height: Option<block::Height>, origin: Option<ConfigOrigin>) {
+        *self.max_height.lock().unwrap() = (max_height, origin);
+    }
+
+    /// Ask the client to stop once its current request has been handled
+    pub fn stop(&self) {
+        let _status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        self.stopped.store(true, Ordering::SeqCst);
+        self.wakeup.notify_all();
+    }
+
+    /// Stop the client, interrupting its session if it's waiting for a
+    /// request
+    pub fn interrupt(&self) {
+        self.stop();
+        self.interrupt_session();
+    }
+
+    /// Interrupt the current session's connection (if any) without stopping
+    /// the client, which reconnects as after any other disconnect
+    pub fn interrupt_session(&self) {
+        if let Some(interrupt) = &*self.interrupt.lock().unwrap_or_else(|e| e.into_inner()) {
+            // The connection may already have been closed
+            let _ = interrupt.interrupt();
+        }
+    }
+
+    /// Set (or clear) the handle used to interrupt the current session,
+    /// interrupting it right away if the client has already been stopped
+    pub fn set_interrupt(&self, interrupt: Option<Box<dyn Interrupt>>) {
+        let mut current = self.interrupt.lock().unwrap_or_else(|e| e.into_inner());
+        *current = interrupt;
+
+        if let Some(interrupt) = &*current {
+            if self.is_stopped() {
+                let _ = interrupt.interrupt();
+            }
+        }
+    }
+
+    /// Sleep for the given duration, waking early if the client is stopped
+    pub fn sleep(&self, duration: Duration) {
+        let deadline = Instant::now() + duration;
+        let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+
+        while !self.is_stopped() {
+            let now = Instant::now();
+
+            if now >= deadline {
+                return;
+            }
+
+            status = self
+                .wakeup
+                .wait_timeout(status, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Get the current status of the client
+    pub fn status(&self) -> Status {
+        *self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Set the current status of the client
+    pub fn set_status(&self, status: Status) {
+        *self.status.lock().unwrap_or_else(|e| e.into_inner()) = status;
+        let addr = self.addr.lock().unwrap_or_else(|e| e.into_inner());
+        status::connection(&self.chain_ids, &addr, status);
+    }
+
+    /// Get the address of the validator the client connects to (the current
+    /// one, if it has several)
+    pub fn endpoint(&self) -> String {
+        self.addr.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Set the address of the validator the client connects to, for
+    /// validators with several
+    pub fn set_endpoint(&self, addr: &ValidatorAddr) {
+        let addr = addr.to_string();
+        let mut current = self.addr.lock().unwrap_or_else(|e| e.into_inner());
+
+        if *current != addr {
+            status::connection_moved(&self.chain_ids, &current, &addr);
+            *current = addr;
+        }
+    }
+
+    /// Get the number of times the client has been restarted after crashing
+    pub fn restarts(&self) -> u32 {
+        self.restarts.load(Ordering::SeqCst)
+    }
+
+    /// Has the client been asked to stop?
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Has the client thread exited?
+    pub fn has_exited(&self) -> bool {
+        self.exited.load(Ordering::SeqCst)
+    }
+
+    /// Get the heartbeat of the client, raised while it handles a request
+    pub fn heartbeat(&self) -> &Arc<Heartbeat> {
+        &self.heartbeat
+    }
+}
+
 /// Client connections: wraps a thread which makes a connection to a particular
 /// validator node and then receives RPCs.
 ///
@@ -30,6 +517,12 @@ pub struct Client {
     /// Name of the client thread
     name: String,
 
+    /// Validator configuration the client was spawned with
+    config: ValidatorConfig,
+
+    /// Control of the client thread
+    control: Arc<Control>,
+
     /// Handle to the client thread
     handle: JoinHandle,
 }
@@ -37,19 +530,58 @@ pub struct Client {
 impl Client {
     /// Spawn a new client, returning a handle so it can be joined
     pub fn spawn(config: ValidatorConfig) -> Self {
-        register_chain(&config.chain_id);
+        for chain_id in &config.chain_ids {
+            register_chain(chain_id, config.label.as_deref());
+        }
+
+        warn_if_above_watermark(&config);
+        write_identity_key_if_missing(&config);
 
         let name = format!("{}@{}", &config.chain_id, &config.addr);
+        info!("[{}] role: {}", name, describe_role(config.role));
+
+        let control = Arc::new(Control::new(&config));
+        let thread_config = config.clone();
+        let thread_control = control.clone();
+
+        // Listeners are bound before the client thread starts, so they're
+        // bound by the time `tmkms start` drops privileges. If binding fails,
+        // the client thread retries it (and reports the error).
+        let listener = if config.addr.is_listener() {
+            Listener::bind(&config).ok()
+        } else {
+            None
+        };
+
+        #[cfg(feature = "async")]
+        if ASYNC.load(Ordering::SeqCst) {
+            let handle = async_loop::spawn(thread_config, listener, thread_control);
+
+            return Self {
+                name,
+                config,
+                control,
+                handle: JoinHandle::Task(handle),
+            };
+        }
 
         let handle = thread::Builder::new()
             .name(name.clone())
-            .spawn(move || main_loop(config))
+            .spawn(move || {
+                let result = supervise(thread_config, listener, &thread_control);
+                exited(&thread_control, result)
+            })
             .unwrap_or_else(|e| {
                 status_err!("error spawning thread: {}", e);
                 exit(1);
             });
 
-        Self { name, handle }
+        Self {
+            name,
+            config,
+            control,
+            handle: JoinHandle::Thread(handle),
+        }
     }
 
     /// Get the name of this client
@@ -59,48 +591,618 @@ impl Client {
 
     /// Wait for a running client to finish
     pub fn join(self) -> Result<(), Error> {
-        self.handle.join().unwrap()
+        match self.handle {
+            JoinHandle::Thread(handle) => handle.join().unwrap(),
+            #[cfg(feature = "async")]
+            JoinHandle::Task(handle) => async_loop::join(handle),
+        }
     }
 }
 
+/// Run a client's main loop, restarting it after [`RESPAWN_DELAY`] if it
+/// crashes (i.e. panics outside of a session) until it exits or is stopped
+fn supervise(
+    config: ValidatorConfig,
+    mut listener: Option<Listener>,
+    control: &Arc<Control>,
+) -> Result<(), Error> {
+    loop {
+        let panic_msg = match panic::catch_unwind(AssertUnwindSafe(|| {
+            main_loop(config.clone(), &mut listener, control)
+        })) {
+            Ok(result) => return result,
+            Err(panic_msg) => panic_msg,
+        };
+
+        crashed(&config, control, Error::from_panic(panic_msg));
+        control.sleep(Duration::from_secs(RESPAWN_DELAY));
+
+        if control.is_stopped() {
+            return Ok(());
+        }
+    }
+}
+
+/// Record a client crashing with the given error
+fn crashed(config: &ValidatorConfig, control: &Control, e: Error) {
+    let endpoint = control.endpoint();
+    control.set_status(Status::Crashed);
+    control.restarts.fetch_add(1, Ordering::SeqCst);
+    metrics::client_restarted(&config.chain_id, &endpoint);
+
+    error!(
+        chain_id = %config.chain_id,
+        "[{}@{}] client crashed: {} (restarting in {}s)",
+        &config.chain_id,
+        endpoint,
+        e,
+        RESPAWN_DELAY
+    );
+}
+
+/// Record a client thread (or task) exiting with the given result
+fn exited(control: &Control, result: Result<(), Error>) -> Result<(), Error> {
+    control.set_status(if result.is_ok() {
+        Status::Stopped
+    } else {
+        Status::Failed
+    });
+
+    control.exited.store(true, Ordering::SeqCst);
+    CLIENTS.notify_exited();
+    result
+}
+
 /// Main loop for all clients. Handles reconnecting in the event of an error
-fn main_loop(config: ValidatorConfig) -> Result<(), Error> {
-    while let Err(e) = run_client(config.clone()) {
-        // `PoisonError` is unrecoverable
-        if *e.kind() == ErrorKind::PoisonError {
-            error!("[{}@{}] FATAL -- {}", &config.chain_id, &config.addr, e);
+/// until the client is stopped
+///
+/// Listeners stay bound across sessions: reconnecting means accepting the
+/// validator's next connection on `listener`. Validators with several
+/// addresses are connected to one at a time, staying with the current one
+/// until its connection fails and then moving on to the next (wrapping
+/// around after the last).
+fn main_loop(
+    config: ValidatorConfig,
+    listener: &mut Option<Listener>,
+    control: &Arc<Control>,
+) -> Result<(), Error> {
+    let mut reconnect = Reconnect::new(&config);
+
+    loop {
+        let config = reconnect.connect(&config, control);
+        let connected = AtomicBool::new(false);
+
+        let e = match run_client(config.clone(), listener, &connected, control) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+
+        match reconnect.failed(&config, e, connected.load(Ordering::SeqCst), control)? {
+            Some(delay) => {
+                control.sleep(delay);
+
+                if control.is_stopped() {
+                    return Ok(());
+                }
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Reconnection state of a client across its sessions: which of its
+/// validator's addresses to connect to next, and how long to wait first
+struct Reconnect {
+    /// Reconnect delay policy
+    backoff: Backoff,
+
+    /// Addresses of the validator
+    endpoints: Vec<ValidatorAddr>,
+
+    /// Index of the address to connect to
+    current: usize,
+
+    /// Consecutive connection failures (including the loss of an
+    /// established connection), for alerting
+    failures: u32,
+}
+
+impl Reconnect {
+    /// Create the reconnection state for the given validator
+    fn new(config: &ValidatorConfig) -> Self {
+        Self {
+            backoff: Backoff::new(config),
+            endpoints: config.endpoints(),
+            current: 0,
+            failures: 0,
+        }
+    }
+
+    /// Get the configuration to connect to the validator's current address
+    /// with
+    fn connect(&self, config: &ValidatorConfig, control: &Control) -> ValidatorConfig {
+        let config = config.with_endpoint(&self.endpoints[self.current]);
+        control.set_endpoint(&config.addr);
+
+        if self.endpoints.len() > 1 {
+            info!(
+                chain_id = %config.chain_id,
+                "[{}@{}] connecting (address {} of {})",
+                &config.chain_id,
+                &config.addr,
+                self.current + 1,
+                self.endpoints.len()
+            );
+        }
+
+        control.set_status(Status::Connecting);
+        config
+    }
+
+    /// Handle a session which ended with the given error (`connected` if it
+    /// was established), returning how long to wait before reconnecting, or
+    /// `None` if the client has been stopped. Errors which the client should
+    /// exit with are returned.
+    fn failed(
+        &mut self,
+        config: &ValidatorConfig,
+        e: Error,
+        connected: bool,
+        control: &Control,
+    ) -> Result<Option<Duration>, Error> {
+        if control.is_stopped() {
+            return Ok(None);
+        }
+
+        // Poisoned state, untrusted validator certificates, and listener sockets
+        // which couldn't be given the configured permissions are unrecoverable
+        if e.kind().is_fatal() {
+            error!(
+                chain_id = %config.chain_id,
+                "[{}@{}] FATAL -- {}",
+                &config.chain_id,
+                &config.addr,
+                e
+            );
             return Err(e);
         } else {
-            error!("[{}@{}] {}", &config.chain_id, &config.addr, e);
+            error!(
+                chain_id = %config.chain_id,
+                "[{}@{}] {}",
+                &config.chain_id,
+                &config.addr,
+                e
+            );
         }
 
-        if config.reconnect {
-            // TODO: configurable respawn delay
-            thread::sleep(Duration::from_secs(RESPAWN_DELAY));
+        metrics::connection_reset(&config.chain_id, &config.addr.to_string());
+
+        if connected {
+            self.failures = 1;
         } else {
+            self.failures += 1;
+        }
+
+        alerts::connection_failed(&config.chain_id, &config.addr, self.failures, &e);
+
+        if !config.reconnect {
             return Err(e);
         }
+
+        // Only back off while we're failing to connect
+        if connected {
+            self.backoff.reset();
+        }
+
+        if self.endpoints.len() > 1 {
+            self.current = (self.current + 1) % self.endpoints.len();
+
+            info!(
+                chain_id = %config.chain_id,
+                "[{}@{}] failing over to {}",
+                &config.chain_id, &config.addr, &self.endpoints[self.current]
+            );
+        }
+
+        match self.backoff.next_delay() {
+            Some(delay) => {
+                if self.backoff.is_exponential() {
+                    info!(
+                        chain_id = %config.chain_id,
+                        "[{}@{}] reconnect attempt {} in {:?}",
+                        &config.chain_id,
+                        &self.endpoints[self.current],
+                        self.backoff.attempts,
+                        delay
+                    );
+                }
+
+                control.set_status(Status::Reconnecting);
+                Ok(Some(delay))
+            }
+            None => {
+                error!(
+                    chain_id = %config.chain_id,
+                    "[{}@{}] giving up after {} reconnect attempts",
+                    &config.chain_id, &config.addr, self.backoff.max_attempts
+                );
+                Err(e)
+            }
+        }
     }
+}
 
-    Ok(())
+/// Reconnect delay policy: a fixed delay of [`RESPAWN_DELAY`] unless any of
+/// the `reconnect_*` options are configured, in which case exponential
+/// backoff with jitter is used
+#[derive(Clone, Debug)]
+struct Backoff {
+    /// Delay before the first reconnect attempt (`None` for a fixed delay)
+    initial_delay: Option<Duration>,
+
+    /// Maximum delay between reconnect attempts
+    max_delay: Duration,
+
+    /// Maximum number of consecutive attempts (0 = unlimited)
+    max_attempts: u32,
+
+    /// Number of consecutive reconnect attempts so far
+    attempts: u32,
+}
+
+impl Backoff {
+    /// Create the reconnect policy for the given validator configuration
+    fn new(config: &ValidatorConfig) -> Self {
+        let configured = config.reconnect_initial_delay.is_some()
+            || config.reconnect_max_delay.is_some()
+            || config.reconnect_max_attempts.is_some();
+
+        let initial_delay = Duration::from_secs(
+            config
+                .reconnect_initial_delay
+                .unwrap_or(DEFAULT_RECONNECT_INITIAL_DELAY),
+        );
+
+        let max_delay = Duration::from_secs(
+            config
+                .reconnect_max_delay
+                .unwrap_or(DEFAULT_RECONNECT_MAX_DELAY),
+        );
+
+        Self {
+            initial_delay: if configured {
+                Some(initial_delay)
+            } else {
+                None
+            },
+            max_delay: max_delay.max(initial_delay),
+            max_attempts: config.reconnect_max_attempts.unwrap_or(0),
+            attempts: 0,
+        }
+    }
+
+    /// Is exponential backoff (as opposed to a fixed delay) in use?
+    fn is_exponential(&self) -> bool {
+        self.initial_delay.is_some()
+    }
+
+    /// Reset the backoff after a successful connection
+    fn reset(&mut self) {
+        self.attempts = 0;
+    }
+
+    /// Record a reconnect attempt, returning how long to wait before making
+    /// it, or `None` if the maximum number of attempts has been exceeded
+    fn next_delay(&mut self) -> Option<Duration> {
+        self.attempts = self.attempts.saturating_add(1);
+
+        if self.max_attempts != 0 && self.attempts > self.max_attempts {
+            return None;
+        }
+
+        let initial_delay = match self.initial_delay {
+            Some(delay) => delay,
+            None => return Some(Duration::from_secs(RESPAWN_DELAY)),
+        };
+
+        let delay = self.base_delay(initial_delay);
+
+        // "Equal jitter": wait at least half the delay, plus a random amount
+        // up to the other half, so clients don't reconnect in lockstep
+        let half_millis = delay.as_millis() as u64 / 2;
+        let jitter = OsRng.next_u64() % (half_millis + 1);
+
+        Some(Duration::from_millis(half_millis + jitter))
+    }
+
+    /// Delay for the current attempt before jitter is applied
+    fn base_delay(&self, initial_delay: Duration) -> Duration {
+        let exponent = self.attempts.saturating_sub(1).min(31);
+
+        initial_delay
+            .checked_mul(1 << exponent)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
 }
 
-/// Ensure chain with given ID is properly registered
-pub fn register_chain(chain_id: &chain::Id) {
+/// Ensure chain with given ID is properly registered (for the validator with
+/// the given label, if any)
+pub fn register_chain(chain_id: &chain::Id, validator: Option<&str>) {
     let registry = chain::REGISTRY.get();
+    let name = chain::name(chain_id, validator);
 
-    debug!("registering chain: {}", chain_id);
-    registry.get_chain(chain_id).unwrap_or_else(|| {
-        status_err!(
-            "unregistered chain: {} (add it to tmkms.toml's [[chain]] section)",
-            chain_id
+    debug!("registering chain: {}", name);
+    registry
+        .get_validator_chain(chain_id, validator)
+        .unwrap_or_else(|| {
+            status_err!(
+                "unregistered chain: {} (add it to tmkms.toml's [[chain]] section)",
+                name
+            );
+            exit(1);
+        });
+}
+
+/// Open a new session and run the session loop until `control` stops the
+/// client. `connected` is set once the session has been established.
+///
+/// For listen addresses the session is accepted from the validator on
+/// `listener`, which is bound first if need be.
+pub fn run_client(
+    config: ValidatorConfig,
+    listener: &mut Option<Listener>,
+    connected: &AtomicBool,
+    control: &Arc<Control>,
+) -> Result<(), Error> {
+    if config.addr.is_grpc() != (config.protocol_version == ProtocolVersion::Grpc) {
+        fail!(
+            ErrorKind::ConfigError,
+            "[{}@{}] `grpc://` addresses require `protocol_version = \"grpc\"` (and vice versa)",
+            &config.chain_id,
+            &config.addr
         );
-        exit(1);
-    });
+    }
+
+    if config.addr.is_grpc() {
+        return run_grpc_server(config, control.clone());
+    }
+
+    if config.addr.is_listener() && listener.is_none() {
+        *listener = Some(Listener::bind(&config)?);
+    }
+
+    let listener = listener.as_ref();
+
+    panic::catch_unwind(move || {
+        let mut session = match listener {
+            Some(listener) => Session::accept(config, listener)?,
+            None => Session::open(config)?,
+        };
+
+        connected.store(true, Ordering::SeqCst);
+        control.set_status(Status::Connected);
+        control.set_interrupt(session.take_interrupt());
+
+        let result = session.request_loop(control);
+        control.set_interrupt(None);
+        result
+    })
+    .unwrap_or_else(|e| Err(Error::from_panic(e)))
 }
 
-/// Open a new session and run the session loop
-pub fn run_client(config: ValidatorConfig) -> Result<(), Error> {
-    panic::catch_unwind(move || Session::open(config)?.request_loop())
+/// Serve the gRPC `PrivValidatorAPI` on the configured listen address
+#[cfg(feature = "grpc")]
+fn run_grpc_server(config: ValidatorConfig, control: Arc<Control>) -> Result<(), Error> {
+    panic::catch_unwind(move || crate::connection::grpc::serve(config, control))
         .unwrap_or_else(|e| Err(Error::from_panic(e)))
 }
+
+/// Serve the gRPC `PrivValidatorAPI` on the configured listen address
+#[cfg(not(feature = "grpc"))]
+fn run_grpc_server(config: ValidatorConfig, _control: Arc<Control>) -> Result<(), Error> {
+    fail!(
+        ErrorKind::ConfigError,
+        "[{}@{}] gRPC support not enabled (rebuild with the `grpc` cargo feature)",
+        &config.chain_id,
+        &config.addr
+    )
+}
+
+/// Can a session for the `old` validator configuration carry on under the
+/// `new` one? (i.e. do they only differ in `min_height`/`max_height`)
+fn same_session(new: &ValidatorConfig, old: &ValidatorConfig) -> bool {
+    let new = ValidatorConfig {
+        min_height: old.min_height,
+        max_height: old.max_height,
+        max_height_origin: old.max_height_origin.clone(),
+        ..new.clone()
+    };
+
+    new == *old
+}
+
+/// Generate a `tcp://` validator's `secret_key` if there's no file there and
+/// `secret_key_write_if_missing` is set (while the KMS still has the
+/// privileges to write it)
+fn write_identity_key_if_missing(config: &ValidatorConfig) {
+    let path = match &config.secret_key {
+        Some(path) if config.secret_key_write_if_missing => path,
+        _ => return,
+    };
+
+    if !matches!(
+        config.addr,
+        ValidatorAddr::Tcp { .. } | ValidatorAddr::TcpListen { .. }
+    ) {
+        return;
+    }
+
+    let format = config.secret_key_format.unwrap_or_default();
+
+    match key_utils::write_identity_key_if_missing(path, format) {
+        Ok(true) => info!(
+            "[{}@{}] generated new Secret Connection key: {}",
+            &config.chain_id,
+            &config.addr,
+            path.display()
+        ),
+        Ok(false) => (),
+        Err(e) => error!(
+            "[{}@{}] couldn't generate Secret Connection key: {}",
+            &config.chain_id, &config.addr, e
+        ),
+    }
+}
+
+/// Describe what a validator's `role` signs, for the startup log
+fn describe_role(role: ValidatorRole) -> &'static str {
+    match role {
+        ValidatorRole::Voter => "voter (signs votes; proposals are refused)",
+        ValidatorRole::Full => "full (signs votes and proposals)",
+    }
+}
+
+/// Display an optional `min_height`/`max_height`
+fn display_height(height: Option<block::Height>) -> String {
+    height.map_or_else(|| "none".to_owned(), |height| height.to_string())
+}
+
+/// Warn if a validator's `min_height` is above the last height persisted in
+/// the consensus state of any of its chains (e.g. because the state was lost
+/// or predates a chain restart), as signing will resume from `min_height`
+pub fn warn_if_above_watermark(config: &ValidatorConfig) {
+    let min_height = match config.min_height {
+        Some(min_height) => min_height,
+        None => return,
+    };
+
+    let registry = chain::REGISTRY.get();
+
+    for chain in config
+        .chain_ids
+        .iter()
+        .filter_map(|chain_id| registry.get_validator_chain(chain_id, config.label.as_deref()))
+    {
+        let height = chain.state.lock().unwrap().consensus_state().height;
+
+        if min_height > height {
+            warn!(
+                "[{}@{}] min_height {} exceeds the last signed height {}",
+                &chain.id, &config.addr, min_height, height
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Read, os::unix::net::UnixStream};
+
+    fn control() -> Control {
+        let config: ValidatorConfig = toml::from_str(
+            r#"
+            addr = "unix:///tmp/validator.sock"
+            chain_id = "test-chain"
+            protocol_version = "v0.34"
+            "#,
+        )
+        .unwrap();
+
+        Control::new(&config)
+    }
+
+    fn exponential_backoff(max_attempts: u32) -> Backoff {
+        Backoff {
+            initial_delay: Some(Duration::from_secs(2)),
+            max_delay: Duration::from_secs(10),
+            max_attempts,
+            attempts: 0,
+        }
+    }
+
+    #[test]
+    fn fixed_delay_by_default() {
+        let mut backoff = Backoff {
+            initial_delay: None,
+            max_delay: Duration::from_secs(DEFAULT_RECONNECT_MAX_DELAY),
+            max_attempts: 0,
+            attempts: 0,
+        };
+
+        for _ in 0..100 {
+            assert_eq!(
+                backoff.next_delay(),
+                Some(Duration::from_secs(RESPAWN_DELAY))
+            );
+        }
+    }
+
+    #[test]
+    fn exponential_delay_with_jitter() {
+        let mut backoff = exponential_backoff(0);
+
+        for expected_secs in &[2, 4, 8, 10, 10] {
+            let delay = backoff.next_delay().unwrap();
+            let expected = Duration::from_secs(*expected_secs);
+            assert!(delay >= expected / 2 && delay <= expected, "{:?}", delay);
+        }
+
+        backoff.reset();
+        assert!(backoff.next_delay().unwrap() <= Duration::from_secs(2));
+    }
+
+    #[test]
+    fn max_attempts() {
+        let mut backoff = exponential_backoff(3);
+
+        for _ in 0..3 {
+            assert!(backoff.next_delay().is_some());
+        }
+
+        assert_eq!(backoff.next_delay(), None);
+    }
+
+    #[test]
+    fn sleep_wakes_when_stopped() {
+        let control = Arc::new(control());
+        let started_at = Instant::now();
+
+        let stopper = {
+            let control = control.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                control.stop();
+            })
+        };
+
+        control.sleep(Duration::from_secs(30));
+        stopper.join().unwrap();
+
+        assert!(control.is_stopped());
+        assert!(started_at.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn interrupt_session() {
+        let control = control();
+        let (mut kms_end, _validator_end) = UnixStream::pair().unwrap();
+
+        control.set_interrupt(Some(Box::new(kms_end.try_clone().unwrap())));
+        control.interrupt();
+
+        // Reading from the interrupted connection fails rather than blocking
+        assert_eq!(kms_end.read(&mut [0u8; 1]).unwrap(), 0);
+    }
+
+    #[test]
+    fn interrupt_session_started_after_stop() {
+        let control = control();
+        let (mut kms_end, _validator_end) = UnixStream::pair().unwrap();
+
+        control.interrupt();
+        control.set_interrupt(Some(Box::new(kms_end.try_clone().unwrap())));
+
+        assert_eq!(kms_end.read(&mut [0u8; 1]).unwrap(), 0);
+    }
+}
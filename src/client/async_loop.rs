@@ -0,0 +1,258 @@
+//! Clients run as tasks on a shared async runtime (`async` cargo feature,
+//! enabled with `async_clients = true`), rather than a thread each.
+//!
+//! The client loop is the same as that of threaded clients, except waiting
+//! (for a validator to connect to a listener, or to reconnect) ends as soon
+//! as the client is stopped, and sessions are run with [`Session::run`].
+
+use super::{crashed, exited, Control, Reconnect, Status, RESPAWN_DELAY};
+use crate::{
+    config::{ProtocolVersion, ValidatorConfig},
+    connection::Listener,
+    error::Error,
+    prelude::*,
+    session::{
+        async_loop::{from_join_error, stopped},
+        Session,
+    },
+};
+use once_cell::sync::Lazy;
+use std::{
+    os::unix::io::AsRawFd,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::unix::AsyncFd,
+    runtime::{self, Runtime},
+    task::{self, JoinHandle},
+};
+
+/// Runtime the clients are run on
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    runtime::Builder::new_multi_thread()
+        .thread_name("tmkms-client")
+        .enable_all()
+        .build()
+        .unwrap_or_else(|e| {
+            status_err!("couldn't start async runtime: {}", e);
+            std::process::exit(1);
+        })
+});
+
+/// Spawn a client task on the runtime
+pub(super) fn spawn(
+    config: ValidatorConfig,
+    listener: Option<Listener>,
+    control: Arc<Control>,
+) -> JoinHandle<Result<(), Error>> {
+    RUNTIME.spawn(async move {
+        let result = supervise(config, listener, &control).await;
+        exited(&control, result)
+    })
+}
+
+/// Wait for a client task to finish
+pub(super) fn join(handle: JoinHandle<Result<(), Error>>) -> Result<(), Error> {
+    RUNTIME
+        .block_on(handle)
+        .unwrap_or_else(|e| Err(from_join_error(e)))
+}
+
+/// Run a client's main loop, restarting it after [`RESPAWN_DELAY`] if it
+/// crashes until it exits or is stopped. The main loop is run as a task of
+/// its own so a panic can be caught, which drops the listener (if any): it's
+/// bound again on restart.
+async fn supervise(
+    config: ValidatorConfig,
+    listener: Option<Listener>,
+    control: &Arc<Control>,
+) -> Result<(), Error> {
+    let mut listener = listener;
+
+    loop {
+        let main_loop = task::spawn(main_loop(config.clone(), listener.take(), control.clone()));
+
+        let e = match main_loop.await {
+            Ok(result) => return result,
+            Err(e) => from_join_error(e),
+        };
+
+        crashed(&config, control, e);
+        sleep(control, Duration::from_secs(RESPAWN_DELAY)).await;
+
+        if control.is_stopped() {
+            return Ok(());
+        }
+    }
+}
+
+/// Main loop of a client, which reconnects as threaded clients do (see
+/// [`super::main_loop`])
+async fn main_loop(
+    config: ValidatorConfig,
+    mut listener: Option<Listener>,
+    control: Arc<Control>,
+) -> Result<(), Error> {
+    let mut reconnect = Reconnect::new(&config);
+
+    loop {
+        let config = reconnect.connect(&config, &control);
+        let connected = AtomicBool::new(false);
+
+        let e = match run_client(config.clone(), &mut listener, &connected, &control).await {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+
+        match reconnect.failed(&config, e, connected.load(Ordering::SeqCst), &control)? {
+            Some(delay) => {
+                sleep(&control, delay).await;
+
+                if control.is_stopped() {
+                    return Ok(());
+                }
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Open a new session and run it until `control` stops the client (see
+/// [`super::run_client`])
+async fn run_client(
+    config: ValidatorConfig,
+    listener: &mut Option<Listener>,
+    connected: &AtomicBool,
+    control: &Arc<Control>,
+) -> Result<(), Error> {
+    // gRPC servers run on a runtime of their own (and the threaded client
+    // reports gRPC configuration errors)
+    if config.addr.is_grpc() || config.protocol_version == ProtocolVersion::Grpc {
+        let control = control.clone();
+        return task::spawn_blocking(move || {
+            super::run_client(config, &mut None, &AtomicBool::new(false), &control)
+        })
+        .await
+        .map_err(from_join_error)?;
+    }
+
+    let mut session = if config.addr.is_listener() {
+        let bound = match listener.take() {
+            Some(listener) => listener,
+            None => Listener::bind(&config)?,
+        };
+
+        if !accept_ready(&bound, control).await? {
+            *listener = Some(bound);
+            return Ok(());
+        }
+
+        let (bound, session) = task::spawn_blocking(move || {
+            let session = Session::accept(config, &bound);
+            (bound, session)
+        })
+        .await
+        .map_err(from_join_error)?;
+
+        *listener = Some(bound);
+        session?
+    } else {
+        task::spawn_blocking(move || Session::open(config))
+            .await
+            .map_err(from_join_error)??
+    };
+
+    connected.store(true, Ordering::SeqCst);
+    control.set_status(Status::Connected);
+    control.set_interrupt(session.take_interrupt());
+
+    let result = session.run(control.clone()).await;
+    control.set_interrupt(None);
+    result
+}
+
+/// Wait for a validator to connect to the given listener, returning `false`
+/// if the client is stopped first. Listeners which can't be waited on
+/// without blocking (i.e. vsock) are ready right away.
+async fn accept_ready(listener: &Listener, control: &Control) -> Result<bool, Error> {
+    let fd = match listener {
+        Listener::Tcp(listener) => listener.as_raw_fd(),
+        Listener::Unix(listener) => listener.as_raw_fd(),
+        #[cfg(feature = "nitro")]
+        Listener::Vsock(_) => return Ok(true),
+    };
+
+    let fd = AsyncFd::new(fd)?;
+
+    tokio::select! {
+        ready = fd.readable() => {
+            ready?.retain_ready();
+            Ok(true)
+        }
+        _ = stopped(control) => Ok(false),
+    }
+}
+
+/// Sleep for the given duration, waking early if the client is stopped
+async fn sleep(control: &Control, duration: Duration) {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => (),
+        _ = stopped(control) => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{os::unix::net::UnixListener, time::Instant};
+
+    fn control() -> Control {
+        let config: ValidatorConfig = toml::from_str(
+            r#"
+            addr = "unix-listen:///tmp/validator.sock"
+            chain_id = "test-chain"
+            protocol_version = "v0.34"
+            "#,
+        )
+        .unwrap();
+
+        Control::new(&config)
+    }
+
+    #[test]
+    fn waiting_ends_when_stopped() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = Listener::Unix(UnixListener::bind(dir.path().join("kms.sock")).unwrap());
+        let control = Arc::new(control());
+
+        let stopper = {
+            let control = control.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(200));
+                control.stop();
+            })
+        };
+
+        let started_at = Instant::now();
+        assert!(!RUNTIME.block_on(accept_ready(&listener, &control)).unwrap());
+        RUNTIME.block_on(sleep(&control, Duration::from_secs(60)));
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+
+        stopper.join().unwrap();
+    }
+
+    #[test]
+    fn listener_ready_when_validator_connects() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kms.sock");
+        let listener = Listener::Unix(UnixListener::bind(&path).unwrap());
+        let control = control();
+
+        let _validator = std::os::unix::net::UnixStream::connect(&path).unwrap();
+        assert!(RUNTIME.block_on(accept_ready(&listener, &control)).unwrap());
+    }
+}
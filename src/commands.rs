@@ -1,25 +1,51 @@
 //! Subcommands of the `tmkms` command-line application
 
+pub mod config;
+pub mod doctor;
+#[cfg(feature = "fortanixdsm")]
+pub mod fortanixdsm;
 pub mod init;
 #[cfg(feature = "ledger")]
 pub mod ledger;
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
+pub mod pubkey;
+pub mod replay;
+pub mod rotate;
 #[cfg(feature = "softsign")]
 pub mod softsign;
 pub mod start;
+pub mod state;
+#[cfg(feature = "threshold")]
+pub mod threshold;
+pub mod verify;
 pub mod version;
 #[cfg(feature = "yubihsm")]
 pub mod yubihsm;
 
+#[cfg(feature = "fortanixdsm")]
+pub use self::fortanixdsm::FortanixDsmCommand;
 #[cfg(feature = "ledger")]
 pub use self::ledger::LedgerCommand;
+#[cfg(feature = "pkcs11")]
+pub use self::pkcs11::Pkcs11Command;
 #[cfg(feature = "softsign")]
 pub use self::softsign::SoftsignCommand;
+#[cfg(feature = "threshold")]
+pub use self::threshold::ThresholdCommand;
 #[cfg(feature = "yubihsm")]
 pub use self::yubihsm::YubihsmCommand;
 
-pub use self::{init::InitCommand, start::StartCommand, version::VersionCommand};
+pub use self::{
+    config::ConfigCommand, doctor::DoctorCommand, init::InitCommand, pubkey::PubkeyCommand,
+    replay::ReplayCommand, rotate::RotateCommand, start::StartCommand, state::StateCommand,
+    verify::VerifyCommand, version::VersionCommand,
+};
 
-use crate::config::{KmsConfig, CONFIG_ENV_VAR, CONFIG_FILE_NAME};
+use crate::{
+    config::{KmsConfig, CONFIG_ENV_VAR, CONFIG_FILE_NAME},
+    logging::LogFormat,
+};
 use abscissa_core::{Command, Configurable, Runnable};
 use clap::Parser;
 use std::{env, path::PathBuf};
@@ -27,6 +53,18 @@ use std::{env, path::PathBuf};
 /// Subcommands of the KMS command-line application
 #[derive(Command, Debug, Parser, Runnable)]
 pub enum KmsCommand {
+    /// subcommands for checking configuration files
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+
+    /// check the signing providers, state and validator addresses work
+    Doctor(DoctorCommand),
+
+    /// subcommands for Fortanix DSM
+    #[cfg(feature = "fortanixdsm")]
+    #[clap(subcommand)]
+    Fortanixdsm(FortanixDsmCommand),
+
     /// initialize KMS configuration
     Init(InitCommand),
 
@@ -35,6 +73,20 @@ pub enum KmsCommand {
     #[clap(subcommand)]
     Ledger(LedgerCommand),
 
+    /// subcommands for PKCS#11 tokens
+    #[cfg(feature = "pkcs11")]
+    #[clap(subcommand)]
+    Pkcs11(Pkcs11Command),
+
+    /// print the consensus public key of each chain
+    Pubkey(PubkeyCommand),
+
+    /// re-sign the requests in a protocol capture and compare the results
+    Replay(ReplayCommand),
+
+    /// rotate a running KMS's consensus key to a standby key
+    Rotate(RotateCommand),
+
     /// subcommands for software signer
     #[cfg(feature = "softsign")]
     #[clap(subcommand)]
@@ -43,6 +95,18 @@ pub enum KmsCommand {
     /// start the KMS application"
     Start(StartCommand),
 
+    /// subcommands for inspecting and setting consensus state
+    #[clap(subcommand)]
+    State(StateCommand),
+
+    /// subcommands for threshold signing (experimental)
+    #[cfg(feature = "threshold")]
+    #[clap(subcommand)]
+    Threshold(ThresholdCommand),
+
+    /// verify signatures recorded in an audit log, or a single signature
+    Verify(VerifyCommand),
+
     /// display the version
     Version(VersionCommand),
 
@@ -62,6 +126,37 @@ impl KmsCommand {
             _ => false,
         }
     }
+
+    /// Log format given on the command line (if any)
+    pub fn log_format(&self) -> Option<LogFormat> {
+        match self {
+            KmsCommand::Start(run) => run.log_format,
+            _ => None,
+        }
+    }
+
+    /// Should informational log messages be suppressed and the rest written
+    /// to stderr (i.e. because the command's output is intended to be
+    /// machine-readable)?
+    pub fn quiet(&self) -> bool {
+        matches!(
+            self,
+            KmsCommand::Doctor(_)
+                | KmsCommand::Pubkey(_)
+                | KmsCommand::Replay(_)
+                | KmsCommand::State(_)
+                | KmsCommand::Verify(_)
+        )
+    }
+
+    /// Should log messages be written to stderr rather than stdout (i.e.
+    /// because the command writes machine-readable output to stdout)?
+    pub fn logs_to_stderr(&self) -> bool {
+        match self {
+            KmsCommand::Start(start) => start.json_banner,
+            _ => self.quiet(),
+        }
+    }
 }
 
 impl Configurable<KmsConfig> for KmsCommand {
@@ -69,19 +164,34 @@ impl Configurable<KmsConfig> for KmsCommand {
     /// or the default
     fn config_path(&self) -> Option<PathBuf> {
         let config = match self {
+            KmsCommand::Doctor(doctor) => doctor.config.as_ref(),
+            KmsCommand::Pubkey(pubkey) => pubkey.config.as_ref(),
+            KmsCommand::Replay(replay) => replay.config.as_ref(),
+            KmsCommand::Rotate(rotate) => rotate.config.as_ref(),
             KmsCommand::Start(start) => start.config.as_ref(),
+            KmsCommand::State(state) => state.config_path(),
+            #[cfg(feature = "softsign")]
+            KmsCommand::Softsign(softsign) => return softsign.config_path(),
             #[cfg(feature = "yubihsm")]
             KmsCommand::Yubihsm(yubihsm) => yubihsm.config_path(),
             #[cfg(feature = "ledger")]
             KmsCommand::Ledger(ledger) => ledger.config_path(),
+            #[cfg(feature = "pkcs11")]
+            KmsCommand::Pkcs11(pkcs11) => pkcs11.config_path(),
+            #[cfg(feature = "fortanixdsm")]
+            KmsCommand::Fortanixdsm(fortanixdsm) => fortanixdsm.config_path(),
             _ => return None,
         };
 
-        let path = config
-            .cloned()
-            .or_else(|| env::var(CONFIG_ENV_VAR).ok().map(PathBuf::from))
-            .unwrap_or_else(|| PathBuf::from(CONFIG_FILE_NAME));
-
-        Some(path)
+        Some(resolve_config_path(config))
     }
 }
+
+/// Get the path to the configuration file: the one given on the command line,
+/// otherwise from the environment, otherwise the default
+pub(crate) fn resolve_config_path(config: Option<&PathBuf>) -> PathBuf {
+    config
+        .cloned()
+        .or_else(|| env::var(CONFIG_ENV_VAR).ok().map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from(CONFIG_FILE_NAME))
+}
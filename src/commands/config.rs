@@ -0,0 +1,14 @@
+//! `tmkms config` CLI (sub)commands
+
+mod validate;
+
+pub use self::validate::ValidateCommand;
+use abscissa_core::{Command, Runnable};
+use clap::Subcommand;
+
+/// The `config` subcommand
+#[derive(Command, Debug, Runnable, Subcommand)]
+pub enum ConfigCommand {
+    /// check a configuration file for errors
+    Validate(ValidateCommand),
+}
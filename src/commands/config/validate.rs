@@ -0,0 +1,69 @@
+//! Check a configuration file for errors
+
+use crate::{
+    commands::resolve_config_path,
+    config::{validate, KmsConfig},
+    prelude::*,
+};
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use std::{fs, path::PathBuf, process};
+
+/// The `config validate` subcommand
+#[derive(Command, Debug, Default, Parser)]
+pub struct ValidateCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// also load keys held by signing providers (which may require
+    /// connecting to HSMs or remote key management services)
+    #[clap(long)]
+    pub online: bool,
+}
+
+impl Runnable for ValidateCommand {
+    /// Validate the configuration file, printing each problem found on its
+    /// own line. The file is read directly (rather than through the
+    /// application) so it's reported on even if it doesn't parse.
+    fn run(&self) {
+        let path = resolve_config_path(self.config.as_ref());
+
+        let toml_string = fs::read_to_string(&path).unwrap_or_else(|e| {
+            status_err!("couldn't read {}: {}", path.display(), e);
+            process::exit(1);
+        });
+
+        let diagnostics = validate::validate(&toml_string, self.online);
+
+        if diagnostics.is_empty() {
+            status_ok!("Valid", "{}", path.display());
+            print_blocked_heights(&toml_string);
+            return;
+        }
+
+        for diagnostic in &diagnostics {
+            status_err!("{}: {}", path.display(), diagnostic);
+        }
+
+        process::exit(1);
+    }
+}
+
+/// Print the effective `blocked_heights` of each chain which has any (sorted,
+/// as they're checked)
+fn print_blocked_heights(toml_string: &str) {
+    let config = match toml_string.parse::<KmsConfig>() {
+        Ok(config) => config,
+        Err(_) => return,
+    };
+
+    for chain in &config.chain {
+        if !chain.blocked_heights.is_empty() {
+            println!(
+                "chain {}: blocked heights {}",
+                chain.id, chain.blocked_heights
+            );
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Check the whole signing path works without connecting to a validator
+
+use crate::{
+    chain,
+    config::{
+        chain::{ChainConfig, StateBackend},
+        validator::ValidatorAddr,
+    },
+    prelude::*,
+};
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use std::{
+    fs::OpenOptions,
+    net::ToSocketAddrs,
+    path::{Path, PathBuf},
+    process,
+};
+use tempfile::NamedTempFile;
+
+/// The `doctor` command
+#[derive(Command, Debug, Default, Parser)]
+pub struct DoctorCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// also sign a throwaway test message with each key and verify the
+    /// signature (software keys are always tested)
+    #[clap(long)]
+    pub sign_test: bool,
+}
+
+impl Runnable for DoctorCommand {
+    /// Check each chain's keys and consensus state, and each validator's
+    /// address, printing a table of the results
+    fn run(&self) {
+        let config = APP.config();
+        let mut checks = vec![];
+
+        let loaded = chain::load_config(&config).map_err(|e| e.to_string());
+        let keys_loaded = loaded.is_ok();
+        checks.push(Check::new(
+            "config",
+            "all chains",
+            loaded.map(|()| format!("loaded {} chain(s)", config.chain.len())),
+        ));
+
+        let registry = chain::REGISTRY.get();
+
+        for (validator, chain_config) in config.signers() {
+            let name = chain::name(&chain_config.id, validator);
+            let chain_id = name.as_str();
+            checks.push(Check::new("state", chain_id, check_state(&chain_config)));
+
+            if !keys_loaded {
+                continue;
+            }
+
+            let chain = registry
+                .get_validator_chain(&chain_config.id, validator)
+                .unwrap();
+
+            let consensus_key = chain
+                .keyring
+                .default_consensus_pubkey()
+                .map(|key| chain.keyring.format().serialize(key))
+                .map_err(|e| e.to_string());
+
+            checks.push(Check::new("consensus key", chain_id, consensus_key));
+
+            let selftest = if chain_config.skip_selftest {
+                Ok("skipped (`skip_selftest = true`)".to_owned())
+            } else {
+                chain
+                    .keyring
+                    .selftest(chain_config.selftest)
+                    .map_err(|e| e.to_string())
+            };
+
+            checks.push(Check::new("self-test", chain_id, selftest));
+
+            for key in chain.keyring.healthcheck(self.sign_test) {
+                let detail = format!("{} {}", key.provider, key.public_key);
+
+                let result = match key.result {
+                    Ok(()) if key.sign_tested => {
+                        Ok(format!("{} (test signature verified)", detail))
+                    }
+                    Ok(()) => Ok(detail),
+                    Err(e) => Err(format!("{}: {}", detail, e)),
+                };
+
+                checks.push(Check::new("provider", chain_id, result));
+            }
+        }
+
+        for validator in &config.validator {
+            let target = format!("{}@{}", validator.chain_id, validator.addr);
+            checks.push(Check::new(
+                "validator",
+                &target,
+                check_addr(&validator.addr),
+            ));
+        }
+
+        print_checks(&checks);
+
+        let failed = checks.iter().filter(|check| check.result.is_err()).count();
+
+        if failed > 0 {
+            status_err!("{} of {} checks failed", failed, checks.len());
+            process::exit(1);
+        }
+    }
+}
+
+/// Outcome of a single check
+struct Check {
+    /// What was checked
+    name: &'static str,
+
+    /// What it was checked for (e.g. a chain ID)
+    target: String,
+
+    /// Details of the outcome
+    result: Result<String, String>,
+}
+
+impl Check {
+    /// Record the outcome of a check
+    fn new(name: &'static str, target: &str, result: Result<String, String>) -> Self {
+        Self {
+            name,
+            target: target.to_owned(),
+            result,
+        }
+    }
+}
+
+/// Print a table of the outcomes of the given checks
+fn print_checks(checks: &[Check]) {
+    let name_width = checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    let target_width = checks.iter().map(|c| c.target.len()).max().unwrap_or(0);
+
+    for check in checks {
+        let (status, detail) = match &check.result {
+            Ok(detail) => ("pass", detail),
+            Err(detail) => ("FAIL", detail),
+        };
+
+        println!(
+            "{}  {:name_width$}  {:target_width$}  {}",
+            status,
+            check.name,
+            check.target,
+            detail,
+            name_width = name_width,
+            target_width = target_width
+        );
+    }
+}
+
+/// Check the given chain's consensus state can be persisted, without
+/// modifying it
+fn check_state(config: &ChainConfig) -> Result<String, String> {
+    let path = match config.state_backend {
+        StateBackend::Json => chain::state_file_path(config),
+        StateBackend::Sqlite => config
+            .state_db_path
+            .clone()
+            .ok_or("`state_db_path` is required with `state_backend = \"sqlite\"`")?,
+        StateBackend::Redis => return Ok("stored in Redis".to_owned()),
+    };
+
+    // New state is written to a temporary file in the same directory (or
+    // the database's journal) before replacing the old one
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    NamedTempFile::new_in(dir).map_err(|e| format!("can't write to {}: {}", dir.display(), e))?;
+
+    if path.exists() {
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .map_err(|e| format!("can't write to {}: {}", path.display(), e))?;
+    }
+
+    Ok(format!("{} is writable", path.display()))
+}
+
+/// Check the given validator address resolves (without connecting to it)
+fn check_addr(addr: &ValidatorAddr) -> Result<String, String> {
+    match addr {
+        ValidatorAddr::Tcp { host, port, .. }
+        | ValidatorAddr::Tls { host, port }
+        | ValidatorAddr::TcpListen { host, port, .. }
+        | ValidatorAddr::Grpc { host, port } => {
+            let addrs = (host.as_str(), *port)
+                .to_socket_addrs()
+                .map_err(|e| format!("can't resolve {}: {}", host, e))?
+                .map(|addr| addr.to_string())
+                .collect::<Vec<_>>();
+
+            Ok(format!("resolves to {}", addrs.join(", ")))
+        }
+        ValidatorAddr::Unix { path } => {
+            if Path::new(path).exists() {
+                Ok(format!("{} exists", path))
+            } else {
+                Err(format!("no socket at {} (is the validator running?)", path))
+            }
+        }
+        ValidatorAddr::UnixListen { path } => {
+            let dir = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+
+            if dir.as_os_str().is_empty() || dir.is_dir() {
+                Ok(format!("can listen on {}", path))
+            } else {
+                Err(format!("directory {} doesn't exist", dir.display()))
+            }
+        }
+        ValidatorAddr::Vsock { .. } | ValidatorAddr::VsockListen { .. } => {
+            Ok("nothing to resolve".to_owned())
+        }
+    }
+}
@@ -0,0 +1,22 @@
+//! `tmkms fortanixdsm` CLI (sub)commands
+
+pub use super::rotate::RotateCommand;
+use abscissa_core::{Command, Runnable};
+use clap::Subcommand;
+use std::path::PathBuf;
+
+/// The `fortanixdsm` subcommand
+#[derive(Command, Debug, Runnable, Subcommand)]
+pub enum FortanixDsmCommand {
+    /// rotate a running KMS's consensus key to a standby key (same as
+    /// `tmkms rotate`)
+    Rotate(RotateCommand),
+}
+
+impl FortanixDsmCommand {
+    pub(super) fn config_path(&self) -> Option<&PathBuf> {
+        match self {
+            FortanixDsmCommand::Rotate(rotate) => rotate.config.as_ref(),
+        }
+    }
+}
@@ -3,7 +3,10 @@
 pub mod config_builder;
 pub mod networks;
 
-use self::{config_builder::ConfigBuilder, networks::Network};
+use self::{
+    config_builder::ConfigBuilder,
+    networks::{Network, Selection},
+};
 use crate::{config::CONFIG_FILE_NAME, key_utils, prelude::*};
 use abscissa_core::{Command, Runnable};
 use clap::Parser;
@@ -17,8 +20,12 @@ use std::{
 /// Subdirectories to create within the parent directory
 pub const SUBDIRECTORIES: &[&str] = &["schema", "secrets", "state"];
 
-/// Filesystem permissions to set on the secrets directory
-pub const SECRETS_DIR_PERMISSIONS: u32 = 0o700;
+/// Subdirectories holding keys and consensus state, which only the KMS's
+/// user may access
+pub const PRIVATE_SUBDIRECTORIES: &[&str] = &["secrets", "state"];
+
+/// Filesystem permissions to set on private subdirectories
+pub const PRIVATE_DIR_PERMISSIONS: u32 = 0o700;
 
 /// Default name of the Secret Connection key
 pub const SECRET_CONNECTION_KEY: &str = "kms-identity.key";
@@ -51,15 +58,28 @@ impl Runnable for InitCommand {
         }
 
         // Parse specified networks to initialize
-        let mut networks = vec![];
-        match &self.networks {
-            Some(chain_ids) => {
-                for chain_id in chain_ids.split(',') {
-                    networks.push(Network::parse(chain_id));
-                }
-            }
-            None => {
-                networks.push(Network::CosmosHub);
+        let networks = match &self.networks {
+            Some(names) => names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(Selection::parse)
+                .collect::<Vec<_>>(),
+            None => vec![Selection::Known(Network::find("cosmoshub").unwrap())],
+        };
+
+        for network in &networks {
+            if let Selection::Unknown(name) = network {
+                status_warn!(
+                    "unknown network `{}`: fill in the TODOs in its configuration \
+                     (known networks: {})",
+                    name,
+                    Network::all()
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
             }
         }
 
@@ -89,10 +109,13 @@ impl Runnable for InitCommand {
             });
         }
 
-        // Restrict filesystem permissions to the `secrets` subdirectory
-        let secrets_dir = kms_home.join("secrets");
+        // Restrict filesystem permissions to the `secrets` and `state`
+        // subdirectories
+        for subdir in PRIVATE_SUBDIRECTORIES {
+            set_permissions(kms_home.join(subdir), PRIVATE_DIR_PERMISSIONS);
+        }
 
-        set_permissions(&secrets_dir, SECRETS_DIR_PERMISSIONS);
+        let secrets_dir = kms_home.join("secrets");
 
         let config_path = kms_home.join(CONFIG_FILE_NAME);
         let config_toml = ConfigBuilder::new(&kms_home, &networks).generate();
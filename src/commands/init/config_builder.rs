@@ -1,6 +1,6 @@
 //! Configuration file builder
 
-use super::networks::Network;
+use super::networks::Selection;
 use std::{
     fmt::{self, Display},
     path::Path,
@@ -15,7 +15,7 @@ pub struct ConfigBuilder {
     kms_home: String,
 
     /// Networks to include in configuration
-    networks: Vec<Network>,
+    networks: Vec<Selection>,
 
     /// Contents of the configuration file in-progress
     contents: String,
@@ -23,7 +23,7 @@ pub struct ConfigBuilder {
 
 impl ConfigBuilder {
     /// Create config builder in the default state
-    pub fn new(kms_home: impl AsRef<Path>, networks: &[Network]) -> Self {
+    pub fn new(kms_home: impl AsRef<Path>, networks: &[Selection]) -> Self {
         let mut result = Self {
             // We need to template the KMS homedir into a config file so we have
             // to convert it into a string
@@ -60,14 +60,25 @@ impl ConfigBuilder {
         self.add_section_comment("Chain Configuration");
 
         for network in &self.networks.clone() {
-            self.add_template(match network {
-                Network::Columbus => include_str!("templates/networks/columbus.toml"),
-                Network::CosmosHub => include_str!("templates/networks/cosmoshub.toml"),
-                Network::IrisHub => include_str!("templates/networks/irishub.toml"),
-                Network::SentinelHub => include_str!("templates/networks/sentinelhub.toml"),
-                Network::Osmosis => include_str!("templates/networks/osmosis.toml"),
-                Network::Persistence => include_str!("templates/networks/persistence.toml"),
-            });
+            let chain_config = match network {
+                Selection::Known(network) => format_template(
+                    include_str!("templates/chain.toml"),
+                    &[
+                        ("$KMS_HOME", self.kms_home.as_ref()),
+                        ("$DESCRIPTION", network.description),
+                        ("$CHAIN_ID", network.chain_id),
+                        ("$ACCOUNT_KEY_PREFIX", network.account_key_prefix),
+                        ("$CONSENSUS_KEY_PREFIX", network.consensus_key_prefix),
+                    ],
+                ),
+                Selection::Unknown(name) => format_template(
+                    include_str!("templates/chain_unknown.toml"),
+                    &[("$KMS_HOME", self.kms_home.as_ref()), ("$CHAIN_ID", name)],
+                ),
+            };
+
+            self.add_str(chain_config);
+            self.add_str("\n\n");
         }
     }
 
@@ -91,7 +102,7 @@ impl ConfigBuilder {
     /// Add `[[validator]]` configurations
     fn add_validator_config(&mut self) {
         self.add_section_comment("Validator Configuration");
-        self.add_template_with_chain_id(include_str!("templates/validator.toml"));
+        self.add_network_template(include_str!("templates/validator.toml"));
     }
 
     /// Add `[[tx_signer]]` configurations
@@ -158,7 +169,7 @@ impl ConfigBuilder {
     #[cfg(feature = "ledger")]
     fn add_ledgertm_provider_config(&mut self) {
         self.add_str("### Ledger Provider Configuration\n\n");
-        self.add_template_with_chain_id(include_str!("templates/keyring/ledgertm.toml"));
+        self.add_network_template(include_str!("templates/keyring/ledgertm.toml"));
     }
 
     /// Add `[[provider.softsign]]` configuration
@@ -166,37 +177,29 @@ impl ConfigBuilder {
     fn add_softsign_provider_config(&mut self) {
         self.add_str("### Software-based Signer Configuration\n\n");
 
-        self.add_template_with_chain_id(include_str!("templates/keyring/softsign_consensus.toml"));
+        self.add_network_template(include_str!("templates/keyring/softsign_consensus.toml"));
 
         #[cfg(feature = "tx-signer")]
-        self.add_template_with_chain_id(include_str!("templates/keyring/softsign_account.toml"));
+        self.add_network_template(include_str!("templates/keyring/softsign_account.toml"));
     }
 
     /// Add `[[provider.fortanixdsm]]` configuration
     #[cfg(feature = "fortanixdsm")]
     fn add_fortanixdsm_provider_config(&mut self) {
         self.add_str("### Fortanix DSM Signer Configuration\n\n");
-        self.add_template_with_chain_id(include_str!("templates/keyring/fortanixdsm.toml"));
-    }
-
-    /// Append a template to the config file, substituting `$KMS_HOME`
-    fn add_template(&mut self, template: &str) {
-        self.add_str(&format_template(
-            template,
-            &[("$KMS_HOME", self.kms_home.as_ref())],
-        ));
-
-        self.add_str("\n\n");
+        self.add_network_template(include_str!("templates/keyring/fortanixdsm.toml"));
     }
 
-    /// Append a template to the config file, substituting `$KMS_HOME` and `$CHAIN_ID`
-    fn add_template_with_chain_id(&mut self, template: &str) {
+    /// Append a template to the config file for each network, substituting
+    /// `$KMS_HOME`, `$CHAIN_ID`, and `$PROTOCOL_VERSION`
+    fn add_network_template(&mut self, template: &str) {
         for network in self.networks.clone() {
             self.add_str(&format_template(
                 template,
                 &[
                     ("$KMS_HOME", self.kms_home.as_ref()),
                     ("$CHAIN_ID", network.chain_id()),
+                    ("$PROTOCOL_VERSION", network.protocol_version().as_str()),
                 ],
             ));
 
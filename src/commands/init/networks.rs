@@ -1,102 +1,198 @@
 //! Tendermint KMS configuration file networks
+//!
+//! Well-known networks `tmkms init -n` generates configuration for. To add a
+//! network, add an entry to [`NETWORKS`].
 
-use crate::prelude::*;
-use std::{
-    fmt::{self, Display},
-    process,
-};
+use crate::config::validator::ProtocolVersion;
+use std::fmt::{self, Display};
 
-/// Tendermint networks we have config networks for
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub enum Network {
-    /// Terra `columbus` chain
-    Columbus,
+/// Networks we have configuration for
+pub const NETWORKS: &[Network] = &[
+    Network {
+        name: "columbus",
+        description: "Terra Classic Columbus Network",
+        chain_id: "columbus-5",
+        account_key_prefix: "terrapub",
+        consensus_key_prefix: "terravalconspub",
+        protocol_version: ProtocolVersion::V0_34,
+        schema_file: "terra.toml",
+    },
+    Network {
+        name: "cosmoshub",
+        description: "Cosmos Hub Network",
+        chain_id: "cosmoshub-4",
+        account_key_prefix: "cosmospub",
+        consensus_key_prefix: "cosmosvalconspub",
+        protocol_version: ProtocolVersion::V0_38,
+        schema_file: "cosmos-sdk.toml",
+    },
+    Network {
+        name: "irishub",
+        description: "Iris Hub Network",
+        chain_id: "irishub-1",
+        account_key_prefix: "iap",
+        consensus_key_prefix: "icp",
+        protocol_version: ProtocolVersion::V0_34,
+        schema_file: "iris.toml",
+    },
+    Network {
+        name: "juno",
+        description: "Juno Network",
+        chain_id: "juno-1",
+        account_key_prefix: "junopub",
+        consensus_key_prefix: "junovalconspub",
+        protocol_version: ProtocolVersion::V0_38,
+        schema_file: "cosmos-sdk.toml",
+    },
+    Network {
+        name: "sentinelhub",
+        description: "Sentinel Network",
+        chain_id: "sentinelhub-2",
+        account_key_prefix: "sentpub",
+        consensus_key_prefix: "sentvalconspub",
+        protocol_version: ProtocolVersion::V0_34,
+        schema_file: "sentinelhub.toml",
+    },
+    Network {
+        name: "osmosis",
+        description: "Osmosis Network",
+        chain_id: "osmosis-1",
+        account_key_prefix: "osmopub",
+        consensus_key_prefix: "osmovalconspub",
+        protocol_version: ProtocolVersion::V0_38,
+        schema_file: "osmosis.toml",
+    },
+    Network {
+        name: "core",
+        description: "Persistence Network",
+        chain_id: "core-1",
+        account_key_prefix: "persistencepub",
+        consensus_key_prefix: "persistencevalconspub",
+        protocol_version: ProtocolVersion::V0_34,
+        schema_file: "persistence.toml",
+    },
+];
 
-    /// Cosmos `cosmoshub` chain
-    CosmosHub,
+/// Protocol version assumed for networks we don't have configuration for
+pub const DEFAULT_PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion::V0_38;
 
-    /// Iris `irishub` chain
-    IrisHub,
+/// Schema file assumed for networks we don't have configuration for
+pub const DEFAULT_SCHEMA_FILE: &str = "cosmos-sdk.toml";
 
-    /// Sentinel `sentinelhub` chain
-    SentinelHub,
+/// Well-known Tendermint network
+#[derive(Debug, Eq, PartialEq)]
+pub struct Network {
+    /// Name of the network (i.e. its chain ID prefix), as passed to `-n`
+    pub name: &'static str,
 
-    /// Osmosis `osmosis` chain
-    Osmosis,
+    /// Description of the network
+    pub description: &'static str,
 
-    /// Persistence `core` chain
-    Persistence,
-}
+    /// Current production chain ID
+    pub chain_id: &'static str,
 
-impl Display for Network {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(match self {
-            Network::Columbus => "columbus",
-            Network::CosmosHub => "cosmoshub",
-            Network::IrisHub => "irishub",
-            Network::SentinelHub => "sentinelhub",
-            Network::Osmosis => "osmosis",
-            Network::Persistence => "core",
-        })
-    }
+    /// Bech32 prefix of account public keys
+    pub account_key_prefix: &'static str,
+
+    /// Bech32 prefix of consensus public keys
+    pub consensus_key_prefix: &'static str,
+
+    /// Protocol version spoken by the network's validators
+    pub protocol_version: ProtocolVersion,
+
+    /// Transaction schema file (for the transaction signer)
+    pub schema_file: &'static str,
 }
 
 impl Network {
     /// Get a slice containing all known networks
     pub fn all() -> &'static [Network] {
-        &[
-            Network::Columbus,
-            Network::CosmosHub,
-            Network::IrisHub,
-            Network::SentinelHub,
-            Network::Osmosis,
-            Network::Persistence,
-        ]
+        NETWORKS
+    }
+
+    /// Find the known network with the given name
+    pub fn find(name: &str) -> Option<&'static Network> {
+        NETWORKS.iter().find(|network| network.name == name)
     }
+}
+
+impl Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+/// Network to generate configuration for: a known one, or one whose details
+/// are left for the user to fill in
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Selection {
+    /// Network we have configuration for
+    Known(&'static Network),
+
+    /// Network we don't have configuration for (by name)
+    Unknown(String),
+}
 
-    /// Parse a network name from the chain ID prefix
-    pub fn parse(s: &str) -> Self {
-        match s {
-            "columbus" => Network::Columbus,
-            "cosmoshub" => Network::CosmosHub,
-            "irishub" => Network::IrisHub,
-            "sentinelhub" => Network::SentinelHub,
-            "osmosis" => Network::Osmosis,
-            "core" => Network::Persistence,
-            other => {
-                status_err!("unknown Tendermint network: `{}`", other);
-                eprintln!("\nRegistered networks:");
-
-                for network in Self::all() {
-                    eprintln!("- {}", network);
-                }
-
-                process::exit(1);
-            }
+impl Selection {
+    /// Select the network with the given name
+    pub fn parse(name: &str) -> Self {
+        match Network::find(name) {
+            Some(network) => Selection::Known(network),
+            None => Selection::Unknown(name.to_owned()),
         }
     }
 
-    /// Get the current production chain ID for this network
+    /// Get the chain ID to configure (the network's name if unknown)
     pub fn chain_id(&self) -> &str {
         match self {
-            Network::Columbus => "columbus-3",
-            Network::CosmosHub => "cosmoshub-3",
-            Network::IrisHub => "irishub",
-            Network::SentinelHub => "sentinelhub-2",
-            Network::Osmosis => "osmosis-1",
-            Network::Persistence => "core-1",
+            Selection::Known(network) => network.chain_id,
+            Selection::Unknown(name) => name,
         }
     }
 
-    /// Get the schema file for this network
+    /// Get the protocol version to configure
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        match self {
+            Selection::Known(network) => network.protocol_version,
+            Selection::Unknown(_) => DEFAULT_PROTOCOL_VERSION,
+        }
+    }
+
+    /// Get the transaction schema file to configure
     pub fn schema_file(&self) -> &str {
         match self {
-            Network::Columbus => "terra.toml",
-            Network::CosmosHub => "cosmos-sdk.toml",
-            Network::IrisHub => "iris.toml",
-            Network::SentinelHub => "sentinelhub.toml",
-            Network::Osmosis => "osmosis.toml",
-            Network::Persistence => "persistence.toml",
+            Selection::Known(network) => network.schema_file,
+            Selection::Unknown(_) => DEFAULT_SCHEMA_FILE,
         }
     }
 }
+
+impl Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selection::Known(network) => network.fmt(f),
+            Selection::Unknown(name) => f.write_str(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_chain_id_prefixes() {
+        for network in Network::all() {
+            assert!(network.chain_id.starts_with(network.name), "{}", network);
+            assert_eq!(Selection::parse(network.name), Selection::Known(network));
+        }
+    }
+
+    #[test]
+    fn unknown_network() {
+        let selection = Selection::parse("mychain-1");
+        assert_eq!(selection, Selection::Unknown("mychain-1".to_owned()));
+        assert_eq!(selection.chain_id(), "mychain-1");
+        assert_eq!(selection.protocol_version(), DEFAULT_PROTOCOL_VERSION);
+    }
+}
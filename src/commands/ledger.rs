@@ -64,7 +64,10 @@ impl Runnable for InitCommand {
             ..Default::default()
         };
         println!("{:?}", vote);
-        let sign_vote_req = SignVoteRequest { vote: Some(vote) };
+        let sign_vote_req = SignVoteRequest {
+            vote: Some(vote),
+            chain_id: String::new(),
+        };
         let mut to_sign = vec![];
         sign_vote_req
             .sign_bytes(
@@ -0,0 +1,23 @@
+//! `tmkms pkcs11` CLI (sub)commands
+
+mod list_keys;
+
+pub use self::list_keys::ListKeysCommand;
+use abscissa_core::{Command, Runnable};
+use clap::Subcommand;
+use std::path::PathBuf;
+
+/// The `pkcs11` subcommand
+#[derive(Command, Debug, Runnable, Subcommand)]
+pub enum Pkcs11Command {
+    /// list the slots and Ed25519 keys visible through each configured module
+    ListKeys(ListKeysCommand),
+}
+
+impl Pkcs11Command {
+    pub(super) fn config_path(&self) -> Option<&PathBuf> {
+        match self {
+            Pkcs11Command::ListKeys(list_keys) => list_keys.config.as_ref(),
+        }
+    }
+}
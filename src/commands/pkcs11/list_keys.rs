@@ -0,0 +1,119 @@
+//! List keys on PKCS#11 tokens
+
+use crate::{
+    config::provider::pkcs11::Pkcs11Config,
+    keyring::providers::pkcs11::{Module, Token},
+    prelude::*,
+};
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use std::{path::PathBuf, process};
+use subtle_encoding::hex;
+use tendermint::{PublicKey, TendermintKey};
+
+/// The `pkcs11 list-keys` subcommand
+#[derive(Command, Debug, Default, Parser)]
+pub struct ListKeysCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+}
+
+impl Runnable for ListKeysCommand {
+    /// List the slots and Ed25519 keys of each configured PKCS#11 token
+    fn run(&self) {
+        let config = APP.config();
+
+        if config.providers.pkcs11.is_empty() {
+            status_err!("no [[providers.pkcs11]] sections in configuration");
+            process::exit(1);
+        }
+
+        let mut ok = true;
+
+        for pkcs11_config in &config.providers.pkcs11 {
+            ok &= list_keys(pkcs11_config);
+        }
+
+        if !ok {
+            process::exit(1);
+        }
+    }
+}
+
+/// List the slots and keys for a single `[[providers.pkcs11]]` section,
+/// returning `false` if any configured keys couldn't be found
+fn list_keys(config: &Pkcs11Config) -> bool {
+    println!("PKCS#11 module: {}", config.module.display());
+
+    let module = Module::load(&config.module).unwrap_or_else(|e| {
+        status_err!("{}", e);
+        process::exit(1);
+    });
+
+    let slots = module.slots().unwrap_or_else(|e| {
+        status_err!("couldn't list slots: {}", e);
+        process::exit(1);
+    });
+
+    for slot in &slots {
+        println!(
+            "- slot {}: token `{}` ({} {}, serial {})",
+            slot.id, slot.token_label, slot.manufacturer, slot.model, slot.serial_number
+        );
+    }
+
+    let token = match Token::open(config) {
+        Ok(token) => token,
+        Err(e) => {
+            status_err!("{}", e);
+            return false;
+        }
+    };
+
+    let keys = token.list_keys().unwrap_or_else(|e| {
+        status_err!("couldn't list keys: {}", e);
+        process::exit(1);
+    });
+
+    println!("Ed25519 keys on PKCS#11 {}:", token.description());
+
+    for key in &keys {
+        let key_label = format!("- `{}`", key.label);
+
+        let public_key = match key
+            .public_key
+            .as_ref()
+            .and_then(|pk| PublicKey::from_raw_ed25519(pk))
+        {
+            Some(pk) => TendermintKey::ConsensusKey(pk).to_hex(),
+            None => "<no matching public key object>".to_owned(),
+        };
+
+        if config.signing_keys.iter().any(|k| k.key_label == key.label) {
+            status_attr_ok!(key_label, "[cons] {}", public_key);
+        } else {
+            status_attr_err!(key_label, "[not configured] {}", public_key);
+        }
+
+        println!(
+            "   id: {}",
+            String::from_utf8(hex::encode(&key.id)).unwrap()
+        );
+    }
+
+    let mut ok = true;
+
+    for signing_key in &config.signing_keys {
+        if !keys.iter().any(|k| k.label == signing_key.key_label) {
+            status_err!(
+                "configured key `{}` not found on PKCS#11 {}",
+                signing_key.key_label,
+                token.description()
+            );
+            ok = false;
+        }
+    }
+
+    ok
+}
@@ -0,0 +1,187 @@
+//! Print the consensus public key of each chain
+
+use crate::{chain, keyring::Format, prelude::*};
+use abscissa_core::Command;
+use clap::Parser;
+use serde::Serialize;
+use serde_json::json;
+use std::{path::PathBuf, process};
+use subtle_encoding::base64;
+use tendermint::{account, PublicKey, TendermintKey};
+
+/// The `pubkey` command
+#[derive(Command, Debug, Default, Parser)]
+pub struct PubkeyCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// print keys as `priv_validator_key.json`-style JSON
+    #[clap(long)]
+    pub json: bool,
+
+    /// print the key of this Interchain Security consumer chain as the
+    /// JSON taken by the provider chain's `assign-consensus-key` transaction
+    #[clap(long, value_name = "CHAIN_ID")]
+    pub consumer: Option<String>,
+
+    /// only print the key of the chain with this ID
+    pub chain_id: Option<String>,
+}
+
+impl Runnable for PubkeyCommand {
+    /// Load the configured signing providers and print each chain's
+    /// consensus public key
+    fn run(&self) {
+        let config = APP.config();
+
+        if self.consumer.is_some() && (self.json || self.chain_id.is_some()) {
+            status_err!("--consumer can't be combined with --json or a chain ID");
+            process::exit(1);
+        }
+
+        let selected_chain = self.consumer.as_ref().or(self.chain_id.as_ref());
+
+        if let Some(chain_id) = selected_chain {
+            if !config
+                .chain
+                .iter()
+                .any(|chain| chain.id.as_str() == chain_id)
+            {
+                status_err!("no [[chain]] section for chain ID: {}", chain_id);
+                process::exit(1);
+            }
+        }
+
+        chain::load_config(&config).unwrap_or_else(|e| {
+            status_err!("error loading configuration: {}", e);
+            process::exit(1);
+        });
+
+        let registry = chain::REGISTRY.get();
+        let mut keys = vec![];
+
+        for (validator, chain_config) in config.signers() {
+            if selected_chain.map_or(false, |chain_id| chain_id != chain_config.id.as_str()) {
+                continue;
+            }
+
+            let chain = registry
+                .get_validator_chain(&chain_config.id, validator)
+                .unwrap();
+
+            match chain.keyring.default_consensus_pubkey() {
+                Ok(public_key) => keys.push(KeyInfo::new(
+                    &chain.id,
+                    validator,
+                    *public_key.public_key(),
+                    chain.keyring.format(),
+                )),
+                Err(e) => {
+                    status_err!("no consensus key for chain {}: {}", chain.name(), e);
+                    process::exit(1);
+                }
+            }
+        }
+
+        if self.consumer.is_some() {
+            println!("{}", consumer_key_json(&keys[0].pub_key));
+            return;
+        }
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&keys).unwrap());
+            return;
+        }
+
+        for key in &keys {
+            match &key.validator {
+                Some(label) => println!("{} (validator {}):", key.chain_id, label),
+                None => println!("{}:", key.chain_id),
+            }
+            println!("  key:     {}", key.key);
+
+            if let Some(bech32) = &key.bech32 {
+                println!("  bech32:  {}", bech32);
+            }
+
+            println!("  hex:     {}", key.hex);
+            println!("  address: {}", key.address);
+        }
+    }
+}
+
+/// Consensus public key of a chain in each of the supported encodings
+#[derive(Debug, Serialize)]
+struct KeyInfo {
+    /// Chain ID
+    chain_id: String,
+
+    /// Label of the validator the key belongs to (only for chains signed
+    /// for by several validators)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    validator: Option<String>,
+
+    /// Tendermint address of the key
+    address: account::Id,
+
+    /// Public key (`priv_validator_key.json` encoding)
+    pub_key: PublicKey,
+
+    /// Public key in the chain's `key_format`
+    key: String,
+
+    /// Bech32 encoding, using the chain's consensus key prefix (only for
+    /// chains with a `bech32` key format)
+    bech32: Option<String>,
+
+    /// Raw key bytes as hex
+    hex: String,
+}
+
+impl KeyInfo {
+    /// Describe the given chain's consensus public key
+    fn new(
+        chain_id: &chain::Id,
+        validator: Option<&str>,
+        public_key: PublicKey,
+        format: &Format,
+    ) -> Self {
+        let bech32 = match format {
+            Format::Bech32 { .. } => {
+                Some(format.serialize(TendermintKey::ConsensusKey(public_key)))
+            }
+            _ => None,
+        };
+
+        Self {
+            chain_id: chain_id.to_string(),
+            validator: validator.map(ToOwned::to_owned),
+            address: account::Id::from(public_key),
+            pub_key: public_key,
+            key: format.serialize(TendermintKey::ConsensusKey(public_key)),
+            bech32,
+            hex: Format::HEX.serialize(TendermintKey::ConsensusKey(public_key)),
+        }
+    }
+}
+
+/// Encode a consensus public key as the JSON of a Cosmos SDK `PubKey` (as
+/// output by `<appd> tendermint show-validator`), which is what Interchain
+/// Security's `tx provider assign-consensus-key` takes
+fn consumer_key_json(public_key: &PublicKey) -> String {
+    let type_url = match public_key {
+        PublicKey::Ed25519(_) => "/cosmos.crypto.ed25519.PubKey",
+        PublicKey::Secp256k1(_) => "/cosmos.crypto.secp256k1.PubKey",
+        _ => {
+            status_err!("unsupported consensus key type: {:?}", public_key);
+            process::exit(1);
+        }
+    };
+
+    json!({
+        "@type": type_url,
+        "key": String::from_utf8(base64::encode(public_key.to_bytes())).unwrap(),
+    })
+    .to_string()
+}
@@ -0,0 +1,84 @@
+//! Replay a protocol capture against the configured signing providers
+
+use crate::{
+    capture::{self, Outcome},
+    chain,
+    prelude::*,
+};
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use std::{path::PathBuf, process};
+
+/// The `replay` command
+#[derive(Command, Debug, Parser)]
+pub struct ReplayCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// directory written by a validator's `protocol_capture_dir`
+    pub capture_dir: PathBuf,
+}
+
+impl Runnable for ReplayCommand {
+    /// Re-sign each captured request with the configured keys, printing
+    /// whether the results match the captured ones
+    fn run(&self) {
+        let config = APP.config();
+
+        let entries = capture::read_index(&self.capture_dir).unwrap_or_else(|e| {
+            status_err!("{}", e);
+            process::exit(1);
+        });
+
+        // Keys are loaded without touching the consensus state, so captures
+        // can be replayed alongside the KMS which wrote them
+        let registry = chain::load_keys(&config).unwrap_or_else(|e| {
+            status_err!("error loading configuration: {}", e);
+            process::exit(1);
+        });
+
+        let (mut matched, mut mismatched, mut skipped) = (0, 0, 0);
+
+        for entry in &entries {
+            let outcome =
+                capture::replay(&registry, &self.capture_dir, entry).unwrap_or_else(|e| {
+                    status_err!("request #{}: {}", entry.seq, e);
+                    process::exit(1);
+                });
+
+            match outcome {
+                Outcome::Match => matched += 1,
+                Outcome::Mismatch(_) => mismatched += 1,
+                Outcome::Skipped(_) => skipped += 1,
+            }
+
+            let request = match (&entry.msg_type, entry.height, entry.round) {
+                (Some(msg_type), Some(height), Some(round)) => {
+                    format!(
+                        "{} {} at {}/{}",
+                        entry.request_type, msg_type, height, round
+                    )
+                }
+                _ => entry.request_type.clone(),
+            };
+
+            println!("#{:06} {}: {}", entry.seq, request, outcome);
+        }
+
+        let summary = format!(
+            "{} request(s): {} matched, {} mismatched, {} skipped",
+            entries.len(),
+            matched,
+            mismatched,
+            skipped
+        );
+
+        if mismatched > 0 {
+            status_err!("{}", summary);
+            process::exit(1);
+        }
+
+        status_ok!("Replayed", "{}", summary);
+    }
+}
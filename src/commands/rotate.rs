@@ -0,0 +1,65 @@
+//! Rotate the consensus key of a running KMS
+
+use crate::{
+    control::{self, Request, Response},
+    prelude::*,
+};
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use std::{path::PathBuf, process};
+
+/// The `rotate` subcommand (also available as `fortanixdsm rotate`)
+#[derive(Command, Debug, Parser)]
+pub struct RotateCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// permit rotating to a key with a different public key (which changes
+    /// the validator's consensus key as far as the chain is concerned)
+    #[clap(long)]
+    pub allow_pubkey_change: bool,
+
+    /// standby key to rotate to: its public key (in the chain's key format),
+    /// or for Fortanix DSM keys, its DSM key ID or name
+    #[clap(long = "to")]
+    pub to: String,
+
+    /// chain whose consensus key to rotate
+    pub chain_id: String,
+}
+
+impl Runnable for RotateCommand {
+    /// Ask the running KMS to rotate to the given standby key
+    fn run(&self) {
+        let config = APP.config();
+
+        let control_socket = config.control_socket.as_ref().unwrap_or_else(|| {
+            status_err!("no `control_socket` in configuration (required to rotate keys)");
+            process::exit(1);
+        });
+
+        let chain_id = self.chain_id.parse().unwrap_or_else(|e| {
+            status_err!("invalid chain ID {:?}: {}", self.chain_id, e);
+            process::exit(1);
+        });
+
+        let request = Request::RotateKey {
+            chain_id,
+            key_id: self.to.clone(),
+            allow_pubkey_change: self.allow_pubkey_change,
+        };
+
+        match control::send(control_socket, &request) {
+            Ok(Response::Ok { message }) => status_ok!("Rotated", "{}", message),
+            Ok(Response::Error { message }) => {
+                status_err!("{}", message);
+                process::exit(1);
+            }
+            Err(e) => {
+                status_err!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -1,11 +1,20 @@
 //! `tmkms softsign` CLI (sub)commands
 
+mod export;
 mod import;
 mod keygen;
+#[cfg(feature = "tpm")]
+mod seal;
+mod verify;
 
-use self::{import::ImportCommand, keygen::KeygenCommand};
+#[cfg(feature = "tpm")]
+use self::seal::SealCommand;
+use self::{
+    export::ExportCommand, import::ImportCommand, keygen::KeygenCommand, verify::VerifyCommand,
+};
 use abscissa_core::{Command, Runnable};
 use clap::Subcommand;
+use std::path::PathBuf;
 
 /// The `softsign` subcommand
 #[derive(Command, Debug, Runnable, Subcommand)]
@@ -15,4 +24,28 @@ pub enum SoftsignCommand {
 
     /// convert existing private key to base64 format
     Import(ImportCommand),
+
+    /// export a key as a `priv_validator_key.json` file
+    Export(ExportCommand),
+
+    /// check a key against an expected public key and/or address
+    Verify(VerifyCommand),
+
+    /// seal a key to the host's TPM 2.0
+    #[cfg(feature = "tpm")]
+    Seal(SealCommand),
+}
+
+impl SoftsignCommand {
+    /// Get the path to the configuration file, if the subcommand uses one
+    pub(super) fn config_path(&self) -> Option<PathBuf> {
+        match self {
+            SoftsignCommand::Keygen(keygen) => keygen.config_path(),
+            SoftsignCommand::Import(_)
+            | SoftsignCommand::Export(_)
+            | SoftsignCommand::Verify(_) => None,
+            #[cfg(feature = "tpm")]
+            SoftsignCommand::Seal(_) => None,
+        }
+    }
 }
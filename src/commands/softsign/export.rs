@@ -0,0 +1,111 @@
+//! `tmkms softsign export` command
+
+use crate::{config::provider::softsign::KeyFormat, key_utils, prelude::*};
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use std::{fs, path::PathBuf, process};
+
+/// Format of `priv_validator_key.json` files
+const PRIV_VALIDATOR_FORMAT: &str = "priv-validator";
+
+/// `export` command: export a softsign key as a Tendermint
+/// `priv_validator_key.json` file (e.g. to move back to a vanilla node)
+#[derive(Command, Debug, Default, Parser)]
+pub struct ExportCommand {
+    /// format to export: 'priv-validator' (default)
+    #[clap(short = 'f', long = "format")]
+    format: Option<String>,
+
+    /// softsign key format to read: 'base64' or 'json' (default 'base64')
+    #[clap(long = "key-format")]
+    key_format: Option<String>,
+
+    /// path to a file containing the passphrase of an encrypted key
+    #[clap(long = "passphrase-file")]
+    passphrase_file: Option<PathBuf>,
+
+    /// path to write the exported key to
+    #[clap(short = 'o', long = "output")]
+    output: PathBuf,
+
+    /// acknowledge the private key will be written out in plaintext
+    #[clap(long = "i-know-this-is-dangerous")]
+    dangerous: bool,
+
+    /// path to the softsign key to export
+    path: PathBuf,
+}
+
+impl Runnable for ExportCommand {
+    /// Export a softsign key
+    fn run(&self) {
+        if !self.dangerous {
+            status_err!(
+                "this writes the private key out in plaintext; \
+                 pass --i-know-this-is-dangerous to continue"
+            );
+            process::exit(1);
+        }
+
+        if let Some(format) = &self.format {
+            if format != PRIV_VALIDATOR_FORMAT {
+                status_err!(
+                    "invalid format: {} (must be '{}')",
+                    format,
+                    PRIV_VALIDATOR_FORMAT
+                );
+                process::exit(1);
+            }
+        }
+
+        let key_format = self
+            .key_format
+            .as_ref()
+            .map(|f| {
+                f.parse::<KeyFormat>().unwrap_or_else(|e| {
+                    status_err!("{} (must be 'base64' or 'json')", e);
+                    process::exit(1);
+                })
+            })
+            .unwrap_or_default();
+
+        if self.output.exists() {
+            status_err!("{} already exists", self.output.display());
+            process::exit(1);
+        }
+
+        let keypair = match key_format {
+            KeyFormat::Base64 => {
+                key_utils::load_ed25519_key(&self.path, self.passphrase_file.as_deref())
+            }
+            KeyFormat::Json => key_utils::load_json_ed25519_key(&self.path),
+        }
+        .unwrap_or_else(|e| {
+            status_err!("couldn't load {}: {}", self.path.display(), e);
+            process::exit(1);
+        });
+
+        key_utils::write_json_ed25519_key(&self.output, &keypair).unwrap_or_else(|e| {
+            status_err!("{}", e);
+            process::exit(1);
+        });
+
+        // Read the exported key back, refusing to leave behind a file whose
+        // public key doesn't match the source key
+        match key_utils::load_json_ed25519_key(&self.output) {
+            Ok(exported) if exported.public == keypair.public => (),
+            Ok(_) => {
+                let _ = fs::remove_file(&self.output);
+                status_err!("exported public key doesn't match {}", self.path.display());
+                process::exit(1);
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&self.output);
+                status_err!("{}", e);
+                process::exit(1);
+            }
+        }
+
+        info!("Exported Ed25519 private key to {}", self.output.display());
+    }
+}
@@ -4,8 +4,6 @@ use crate::{config::provider::softsign::KeyFormat, key_utils, prelude::*};
 use abscissa_core::{Command, Runnable};
 use clap::Parser;
 use std::{path::PathBuf, process};
-use tendermint::PrivateKey;
-use tendermint_config::PrivValidatorKey;
 
 /// `import` command: import a `priv_validator.json` formatted key and convert
 /// it into the raw format used by the softsign backend (by default)
@@ -15,6 +13,14 @@ pub struct ImportCommand {
     #[clap(short = 'f')]
     format: Option<String>,
 
+    /// path to write the imported key to
+    #[clap(short = 'o', long = "output")]
+    output: Option<PathBuf>,
+
+    /// softsign key format to write: 'base64' or 'json' (default 'base64')
+    #[clap(long = "key-format")]
+    key_format: Option<String>,
+
     /// [INPUT] and [OUTPUT] paths for key generation
     paths: Vec<PathBuf>,
 }
@@ -22,14 +28,17 @@ pub struct ImportCommand {
 impl Runnable for ImportCommand {
     /// Import a `priv_validator.json`
     fn run(&self) {
-        if self.paths.len() != 2 {
-            status_err!("expected 2 arguments, got {}", self.paths.len());
-            eprintln!("\nUsage: tmkms softsign import [priv_validator.json] [output.key]");
-            process::exit(1);
-        }
-
-        let input_path = &self.paths[0];
-        let output_path = &self.paths[1];
+        let (input_path, output_path) = match (self.paths.as_slice(), &self.output) {
+            ([input_path], Some(output_path)) => (input_path, output_path),
+            ([input_path, output_path], None) => (input_path, output_path),
+            _ => {
+                status_err!("expected an input and an output path");
+                eprintln!(
+                    "\nUsage: tmkms softsign import [priv_validator_key.json] -o [output.key]"
+                );
+                process::exit(1);
+            }
+        };
 
         let format = self
             .format
@@ -47,24 +56,34 @@ impl Runnable for ImportCommand {
             process::exit(1);
         }
 
-        let private_key = PrivValidatorKey::load_json_file(input_path)
-            .unwrap_or_else(|e| {
-                status_err!("couldn't load {}: {}", input_path.display(), e);
-                process::exit(1);
+        let key_format = self
+            .key_format
+            .as_ref()
+            .map(|f| {
+                f.parse::<KeyFormat>().unwrap_or_else(|e| {
+                    status_err!("{} (must be 'base64' or 'json')", e);
+                    process::exit(1);
+                })
             })
-            .priv_key;
+            .unwrap_or_default();
 
-        match private_key {
-            PrivateKey::Ed25519(pk) => {
-                key_utils::write_base64_secret(output_path, pk.secret.as_bytes()).unwrap_or_else(
-                    |e| {
-                        status_err!("{}", e);
-                        process::exit(1);
-                    },
-                );
+        // Checks the key's public key matches the one in the file
+        let keypair = key_utils::load_json_ed25519_key(input_path).unwrap_or_else(|e| {
+            status_err!("couldn't load {}: {}", input_path.display(), e);
+            process::exit(1);
+        });
+
+        let result = match key_format {
+            KeyFormat::Base64 => {
+                key_utils::write_base64_secret(output_path, keypair.secret.as_bytes())
             }
-            _ => unreachable!("unsupported priv_validator.json algorithm"),
-        }
+            KeyFormat::Json => key_utils::write_json_ed25519_key(output_path, &keypair),
+        };
+
+        result.unwrap_or_else(|e| {
+            status_err!("{}", e);
+            process::exit(1);
+        });
 
         info!("Imported Ed25519 private key to {}", output_path.display());
     }
@@ -1,16 +1,37 @@
 //! `tmkms softsign keygen` subcommand
 
-use crate::{key_utils, prelude::*};
+use crate::{
+    commands::resolve_config_path,
+    config::provider::{
+        softsign::{KeyAlgorithm, KeyFormat},
+        KeyType,
+    },
+    key_utils::{self, mnemonic},
+    prelude::*,
+};
 use abscissa_core::{Command, Runnable};
 use clap::Parser;
 use ed25519_dalek as ed25519;
 use k256::ecdsa;
 use rand_core::{OsRng, RngCore};
-use std::{path::Path, path::PathBuf, process};
+use std::{
+    io::{self, Read},
+    path::Path,
+    path::PathBuf,
+    process,
+};
+use subtle_encoding::{base64, bech32};
+use zeroize::Zeroizing;
 
 /// Default type of key to generate
 pub const DEFAULT_KEY_TYPE: &str = "consensus";
 
+/// Default Bech32 prefix of printed consensus addresses
+pub const DEFAULT_BECH32_PREFIX: &str = "cosmosvalcons";
+
+/// `--from-mnemonic` path which reads the mnemonic from standard input
+const STDIN_PATH: &str = "-";
+
 /// `keygen` command
 #[derive(Command, Debug, Default, Parser)]
 pub struct KeygenCommand {
@@ -18,29 +39,337 @@ pub struct KeygenCommand {
     #[clap(short = 't', long = "type")]
     key_type: Option<String>,
 
+    /// key algorithm: 'ed25519' or 'secp256k1' (default 'ed25519' for
+    /// consensus keys, 'secp256k1' for account keys)
+    #[clap(short = 'a', long = "algorithm")]
+    algorithm: Option<String>,
+
+    /// encrypt the generated key under a passphrase (prompted for)
+    #[clap(long = "encrypt")]
+    encrypt: bool,
+
+    /// path to tmkms.toml (used with --chain)
+    #[clap(short = 'c', long = "config")]
+    config: Option<PathBuf>,
+
+    /// generate the consensus key of the chain with this ID (e.g. an
+    /// Interchain Security consumer chain), at the path of the
+    /// [[providers.softsign]] key configured for it
+    #[clap(long = "chain", value_name = "CHAIN_ID")]
+    chain: Option<String>,
+
+    /// derive the (Ed25519) key from the 24-word BIP39 mnemonic in this file
+    /// ('-' reads it from standard input)
+    #[clap(long = "from-mnemonic", value_name = "PATH")]
+    from_mnemonic: Option<PathBuf>,
+
+    /// path to a file containing the BIP39 passphrase of the mnemonic
+    #[clap(long = "bip39-passphrase-file", value_name = "PATH")]
+    bip39_passphrase_file: Option<PathBuf>,
+
+    /// derivation path of the key (default "m/44'/118'/0'/0'/0'")
+    #[clap(long = "derivation-path", value_name = "PATH")]
+    derivation_path: Option<String>,
+
+    /// format to write Ed25519 keys in: 'base64' or 'json' (default 'base64')
+    #[clap(short = 'f', long = "key-format")]
+    key_format: Option<String>,
+
+    /// Bech32 prefix of the printed consensus address of mnemonic-derived
+    /// keys (default 'cosmosvalcons')
+    #[clap(long = "bech32-prefix")]
+    bech32_prefix: Option<String>,
+
     /// path where generated key should be created
     output_paths: Vec<PathBuf>,
 }
 
+impl KeygenCommand {
+    /// Get the path to the configuration file (only loaded with `--chain`)
+    pub(super) fn config_path(&self) -> Option<PathBuf> {
+        self.chain
+            .as_ref()
+            .map(|_| resolve_config_path(self.config.as_ref()))
+    }
+
+    /// Generate the consensus key of the given chain at the path of the
+    /// `[[providers.softsign]]` key configured for it (and it alone)
+    fn generate_chain_key(&self, chain_id: &str) {
+        if !self.output_paths.is_empty()
+            || self.key_type.is_some()
+            || self.algorithm.is_some()
+            || self.key_format.is_some()
+        {
+            status_err!("--chain takes the key's path, type, algorithm and format from tmkms.toml");
+            process::exit(1);
+        }
+
+        let config = APP.config();
+
+        if !config
+            .chain
+            .iter()
+            .any(|chain| chain.id.as_str() == chain_id)
+        {
+            status_err!("no [[chain]] section for chain ID: {}", chain_id);
+            process::exit(1);
+        }
+
+        let mut provider_configs = config.providers.softsign.iter().filter(|provider| {
+            matches!(provider.key_type, KeyType::Consensus)
+                && provider.chain_ids.iter().any(|id| id.as_str() == chain_id)
+        });
+
+        let provider_config = provider_configs.next().unwrap_or_else(|| {
+            status_err!(
+                "no [[providers.softsign]] consensus key configured for chain {} \
+                 (add one with its own `path`)",
+                chain_id
+            );
+            process::exit(1);
+        });
+
+        if provider_config.chain_ids.len() > 1 {
+            status_err!(
+                "the [[providers.softsign]] key for chain {} is shared with other chains \
+                 (give each chain its own key file)",
+                chain_id
+            );
+            process::exit(1);
+        }
+
+        if provider_config.key_format.unwrap_or_default() != KeyFormat::Base64 {
+            status_err!("only `base64` softsign keys can be generated");
+            process::exit(1);
+        }
+
+        let output_path = provider_config.path.as_ref();
+
+        if !matches!(
+            key_utils::SecretSource::parse(output_path),
+            Ok(key_utils::SecretSource::File(_))
+        ) {
+            status_err!(
+                "chain {}'s key is read from {} (generate it into a file instead)",
+                chain_id,
+                output_path.display()
+            );
+            process::exit(1);
+        }
+
+        if output_path.exists() {
+            status_err!(
+                "{} already exists (refusing to overwrite chain {}'s key)",
+                output_path.display(),
+                chain_id
+            );
+            process::exit(1);
+        }
+
+        match provider_config.key_algorithm() {
+            KeyAlgorithm::Ed25519 => self.generate_ed25519_key(output_path, KeyFormat::Base64),
+            KeyAlgorithm::Secp256k1 => self.generate_secp256k1_key(output_path, "consensus"),
+        }
+    }
+
+    /// Generate an Ed25519 key (or derive it from the given mnemonic) and
+    /// store it at the given path in the given format
+    fn generate_ed25519_key(&self, output_path: &Path, key_format: KeyFormat) {
+        let keypair = match &self.from_mnemonic {
+            Some(mnemonic_path) => self.derive_ed25519_key(mnemonic_path),
+            None => {
+                let mut sk_bytes = Zeroizing::new([0u8; 32]);
+                OsRng.fill_bytes(&mut *sk_bytes);
+                let secret = ed25519::SecretKey::from_bytes(&*sk_bytes).unwrap();
+                let public = ed25519::PublicKey::from(&secret);
+                ed25519::Keypair { secret, public }
+            }
+        };
+
+        match key_format {
+            KeyFormat::Base64 => write_secret(output_path, keypair.secret.as_ref(), self.encrypt),
+            KeyFormat::Json => {
+                if self.encrypt {
+                    status_err!("only `base64` keys can be encrypted");
+                    process::exit(1);
+                }
+
+                key_utils::write_json_ed25519_key(output_path, &keypair).unwrap_or_else(|e| {
+                    status_err!("{}", e);
+                    process::exit(1);
+                })
+            }
+        }
+
+        status_ok!(
+            "Generated",
+            "consensus (Ed25519) private key at: {}",
+            output_path.display()
+        );
+
+        if self.from_mnemonic.is_some() {
+            self.print_derived_key(&keypair.public);
+        }
+    }
+
+    /// Randomly generate a secp256k1 key of the given type and store it at
+    /// the given path
+    fn generate_secp256k1_key(&self, output_path: &Path, key_type: &str) {
+        if self.from_mnemonic.is_some() {
+            status_err!("only Ed25519 keys can be derived from a mnemonic");
+            process::exit(1);
+        }
+
+        let signing_key = ecdsa::SigningKey::random(&mut OsRng);
+        write_secret(output_path, &signing_key.to_bytes(), self.encrypt);
+
+        status_ok!(
+            "Generated",
+            "{} (secp256k1) private key at: {}",
+            key_type,
+            output_path.display()
+        );
+    }
+
+    /// Derive an Ed25519 key from the mnemonic read from the given path
+    fn derive_ed25519_key(&self, mnemonic_path: &Path) -> ed25519::Keypair {
+        let phrase = if mnemonic_path == Path::new(STDIN_PATH) {
+            let mut phrase = Zeroizing::new(String::new());
+
+            if let Err(e) = io::stdin().read_to_string(&mut phrase) {
+                status_err!("couldn't read mnemonic from standard input: {}", e);
+                process::exit(1);
+            }
+
+            phrase
+        } else {
+            key_utils::read_secret(mnemonic_path).unwrap_or_else(|e| {
+                status_err!("couldn't read mnemonic: {}", e);
+                process::exit(1);
+            })
+        };
+
+        let passphrase = match &self.bip39_passphrase_file {
+            Some(path) => {
+                let mut passphrase = key_utils::read_secret(path).unwrap_or_else(|e| {
+                    status_err!("couldn't read BIP39 passphrase: {}", e);
+                    process::exit(1);
+                });
+
+                // Only the line ending is dropped: whitespace is significant
+                let len = passphrase.trim_end_matches(&['\r', '\n'][..]).len();
+                passphrase.truncate(len);
+                passphrase
+            }
+            None => Zeroizing::new(String::new()),
+        };
+
+        let derivation_path = self
+            .derivation_path
+            .as_deref()
+            .unwrap_or(mnemonic::DEFAULT_DERIVATION_PATH);
+
+        mnemonic::derive_ed25519_key(&phrase, &passphrase, derivation_path).unwrap_or_else(|e| {
+            status_err!("{}", e);
+            process::exit(1);
+        })
+    }
+
+    /// Print the public key and consensus address of a derived key, so they
+    /// can be checked against the key ceremony's records
+    fn print_derived_key(&self, public_key: &ed25519::PublicKey) {
+        let public_key = tendermint::PublicKey::from_raw_ed25519(public_key.as_bytes())
+            .expect("invalid Ed25519 public key");
+        let address = tendermint::account::Id::from(public_key);
+        let prefix = self
+            .bech32_prefix
+            .as_deref()
+            .unwrap_or(DEFAULT_BECH32_PREFIX);
+
+        println!(
+            "  key:     {}",
+            String::from_utf8(base64::encode(public_key.to_bytes())).unwrap()
+        );
+        println!("  address: {}", bech32::encode(prefix, address));
+        println!("  hex:     {}", address);
+    }
+}
+
 impl Runnable for KeygenCommand {
     /// Generate an Ed25519 secret key for use with a software provider (i.e. ed25519-dalek)
     fn run(&self) {
+        if self.from_mnemonic.is_none()
+            && (self.bip39_passphrase_file.is_some()
+                || self.derivation_path.is_some()
+                || self.bech32_prefix.is_some())
+        {
+            status_err!(
+                "--bip39-passphrase-file, --derivation-path and --bech32-prefix \
+                 require --from-mnemonic"
+            );
+            process::exit(1);
+        }
+
+        if let Some(chain_id) = &self.chain {
+            self.generate_chain_key(chain_id);
+            return;
+        }
+
         if self.output_paths.len() != 1 {
-            eprintln!("Usage: tmkms softsign keygen [-t account,consensus] PATH");
+            eprintln!(
+                "Usage: tmkms softsign keygen [-t account,consensus] [-a ed25519,secp256k1] \
+                 [-f base64,json] [--encrypt] PATH\n       \
+                 tmkms softsign keygen [-c tmkms.toml] [--encrypt] --chain CHAIN_ID\n       \
+                 tmkms softsign keygen --from-mnemonic PATH [--bip39-passphrase-file PATH] \
+                 [--derivation-path PATH] [-f base64,json] [--encrypt] PATH"
+            );
             process::exit(1);
         }
 
         let output_path = &self.output_paths[0];
 
-        match self
+        let key_type = self
             .key_type
             .as_ref()
             .map(AsRef::as_ref)
-            .unwrap_or(DEFAULT_KEY_TYPE)
+            .unwrap_or(DEFAULT_KEY_TYPE);
+
+        let algorithm = self.algorithm.as_ref().map(|algorithm| {
+            algorithm.parse::<KeyAlgorithm>().unwrap_or_else(|e| {
+                status_err!("{} (must be 'ed25519' or 'secp256k1')", e);
+                process::exit(1);
+            })
+        });
+
+        let key_format = self.key_format.as_ref().map(|format| {
+            format.parse::<KeyFormat>().unwrap_or_else(|e| {
+                status_err!("{}", e);
+                process::exit(1);
+            })
+        });
+
+        if key_format == Some(KeyFormat::Json)
+            && (key_type != "consensus" || algorithm == Some(KeyAlgorithm::Secp256k1))
         {
-            "account" => generate_secp256k1_key(output_path),
-            "consensus" => generate_ed25519_key(output_path),
-            other => {
+            status_err!("only Ed25519 consensus keys can be written as `json`");
+            process::exit(1);
+        }
+
+        match (key_type, algorithm) {
+            ("account", None | Some(KeyAlgorithm::Secp256k1)) => {
+                self.generate_secp256k1_key(output_path, "account")
+            }
+            ("consensus", None | Some(KeyAlgorithm::Ed25519)) => {
+                self.generate_ed25519_key(output_path, key_format.unwrap_or_default())
+            }
+            ("consensus", Some(KeyAlgorithm::Secp256k1)) => {
+                self.generate_secp256k1_key(output_path, "consensus")
+            }
+            ("account", Some(KeyAlgorithm::Ed25519)) => {
+                status_err!("account keys must be secp256k1");
+                process::exit(1);
+            }
+            (other, _) => {
                 status_err!(
                     "unknown key type: {} (must be 'account' or 'consensus')",
                     other
@@ -51,42 +380,42 @@ impl Runnable for KeygenCommand {
     }
 }
 
-/// Randomly generate a Base64-encoded secp256k1 key and store it at the given path
-fn generate_secp256k1_key(output_path: &Path) {
-    let signing_key = ecdsa::SigningKey::random(&mut OsRng);
+/// Store the secret key at the given path, either Base64-encoded or encrypted
+/// under a passphrase prompted for on the terminal
+fn write_secret(output_path: &Path, secret: &[u8], encrypt: bool) {
+    let result = if encrypt {
+        let passphrase = prompt_new_passphrase();
+        key_utils::write_encrypted_secret(output_path, secret, passphrase.as_bytes())
+    } else {
+        key_utils::write_base64_secret(output_path, secret)
+    };
 
-    key_utils::write_base64_secret(output_path, &signing_key.to_bytes()).unwrap_or_else(|e| {
+    result.unwrap_or_else(|e| {
         status_err!("{}", e);
         process::exit(1);
     });
-
-    status_ok!(
-        "Generated",
-        "account (secp256k1) private key at: {}",
-        output_path.display()
-    );
 }
 
-/// Randomly generate a Base64-encoded Ed25519 key and store it at the given path
-fn generate_ed25519_key(output_path: &Path) {
-    let mut sk_bytes = [0u8; 32];
-    OsRng.fill_bytes(&mut sk_bytes);
-    let sk = ed25519::SecretKey::from_bytes(&sk_bytes).unwrap();
-    let pk = ed25519::PublicKey::from(&sk);
-
-    let keypair = ed25519::Keypair {
-        public: pk,
-        secret: sk,
+/// Prompt for a new passphrase (twice, to catch typos)
+fn prompt_new_passphrase() -> Zeroizing<String> {
+    let prompt = |msg| {
+        key_utils::encrypted::prompt(msg).unwrap_or_else(|e| {
+            status_err!("{}", e);
+            process::exit(1);
+        })
     };
 
-    key_utils::write_base64_secret(output_path, keypair.secret.as_ref()).unwrap_or_else(|e| {
-        status_err!("{}", e);
+    let passphrase = prompt("Enter passphrase: ");
+
+    if passphrase.is_empty() {
+        status_err!("passphrase must not be empty");
         process::exit(1);
-    });
+    }
+
+    if prompt("Confirm passphrase: ") != passphrase {
+        status_err!("passphrases don't match");
+        process::exit(1);
+    }
 
-    status_ok!(
-        "Generated",
-        "consensus (Ed25519) private key at: {}",
-        output_path.display()
-    );
+    passphrase
 }
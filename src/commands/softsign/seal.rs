@@ -0,0 +1,68 @@
+//! `tmkms softsign seal` command
+
+use crate::{
+    key_utils::{self, sealed},
+    keyring::Format,
+    prelude::*,
+};
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use std::{path::PathBuf, process};
+use tendermint::{PublicKey, TendermintKey};
+
+/// `seal` command: seal a softsign Ed25519 key to the host's TPM 2.0, so it
+/// can only be unsealed on this host while the given PCRs are unchanged
+#[derive(Command, Debug, Default, Parser)]
+pub struct SealCommand {
+    /// comma-separated SHA-256 PCRs to seal the key to (e.g. '0,2,4')
+    #[clap(long = "pcrs", value_name = "PCRS")]
+    pcrs: String,
+
+    /// path to write the sealed key to
+    #[clap(short = 'o', long = "output")]
+    output: PathBuf,
+
+    /// path to a file containing the passphrase of an encrypted key
+    #[clap(long = "passphrase-file")]
+    passphrase_file: Option<PathBuf>,
+
+    /// path to the softsign key to seal
+    path: PathBuf,
+}
+
+impl Runnable for SealCommand {
+    /// Seal a softsign key to the TPM
+    fn run(&self) {
+        let pcrs = sealed::parse_pcrs(&self.pcrs).unwrap_or_else(|e| {
+            status_err!("invalid --pcrs: {}", e);
+            process::exit(1);
+        });
+
+        let keypair = key_utils::load_ed25519_key(&self.path, self.passphrase_file.as_deref())
+            .unwrap_or_else(|e| {
+                status_err!("couldn't load {}: {}", self.path.display(), e);
+                process::exit(1);
+            });
+
+        key_utils::write_sealed_secret(&self.output, keypair.secret.as_bytes(), &pcrs)
+            .unwrap_or_else(|e| {
+                status_err!("{}", e);
+                process::exit(1);
+            });
+
+        let public_key = PublicKey::from_raw_ed25519(keypair.public.as_bytes()).unwrap();
+
+        status_ok!(
+            "Sealed",
+            "{} to PCRs {} of the TPM: {}",
+            self.path.display(),
+            sealed::tpm::format_pcrs(&pcrs),
+            self.output.display()
+        );
+
+        println!(
+            "public key: {}",
+            Format::bech32("cosmosvalconspub").serialize(TendermintKey::ConsensusKey(public_key))
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! `tmkms softsign verify` command
+
+use crate::{
+    config::provider::softsign::{KeyAlgorithm, KeyFormat},
+    error::ErrorKind::InvalidKey,
+    key_utils,
+    keyring::{format::bech32, Format},
+    prelude::*,
+};
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use k256::ecdsa;
+use std::{path::PathBuf, process};
+use subtle_encoding::hex;
+use tendermint::{account, PublicKey, TendermintKey};
+
+/// `verify` command: check a softsign key against the public key and/or
+/// consensus address recorded for it (e.g. by a key ceremony)
+#[derive(Command, Debug, Default, Parser)]
+pub struct VerifyCommand {
+    /// expected public key, as Bech32 (e.g. 'cosmosvalconspub1...'), hex,
+    /// Base64, Cosmos JSON or did:key
+    #[clap(long = "expect", value_name = "PUBKEY")]
+    expect: Option<String>,
+
+    /// expected consensus address, as Bech32 (e.g. 'cosmosvalcons1...') or hex
+    #[clap(long = "expect-address", value_name = "ADDRESS")]
+    expect_address: Option<String>,
+
+    /// key algorithm: 'ed25519' or 'secp256k1' (default that of the --expect
+    /// key, or 'ed25519')
+    #[clap(short = 'a', long = "algorithm")]
+    algorithm: Option<String>,
+
+    /// softsign key format to read: 'base64' or 'json' (default 'base64')
+    #[clap(long = "key-format")]
+    key_format: Option<String>,
+
+    /// path to a file containing the passphrase of an encrypted key
+    #[clap(long = "passphrase-file")]
+    passphrase_file: Option<PathBuf>,
+
+    /// path to the softsign key to verify
+    path: PathBuf,
+}
+
+impl Runnable for VerifyCommand {
+    /// Verify a softsign key
+    fn run(&self) {
+        if self.expect.is_none() && self.expect_address.is_none() {
+            status_err!("nothing to verify (pass --expect and/or --expect-address)");
+            process::exit(1);
+        }
+
+        let expected_key = self.expect.as_ref().map(|encoded| {
+            let format = Format::detect(encoded);
+            let public_key = format.parse(encoded).unwrap_or_else(|e| {
+                status_err!("invalid --expect: {}", e);
+                process::exit(1);
+            });
+            (format, public_key)
+        });
+
+        let expected_address = self
+            .expect_address
+            .as_ref()
+            .map(|encoded| parse_address(encoded));
+
+        let public_key = self.load_public_key(expected_key.as_ref().map(|(_, pk)| pk));
+        let address = account::Id::from(public_key);
+        let mut verified = true;
+
+        if let (Some((format, expected)), Some(encoded)) = (&expected_key, &self.expect) {
+            println!(
+                "public key: {}",
+                format.serialize(TendermintKey::ConsensusKey(public_key))
+            );
+            println!("  expected: {}", encoded.trim());
+            verified &= public_key == *expected;
+        }
+
+        if let (Some((prefix, expected)), Some(encoded)) = (&expected_address, &self.expect_address)
+        {
+            let derived = match prefix {
+                Some((prefix, checksum)) => bech32::encode(prefix, address.as_bytes(), *checksum),
+                None => address.to_string(),
+            };
+
+            println!("address:    {}", derived);
+            println!("  expected: {}", encoded.trim());
+            verified &= address == *expected;
+        }
+
+        if verified {
+            status_ok!(
+                "Verified",
+                "{} matches the expected key",
+                self.path.display()
+            );
+        } else {
+            status_err!("{} doesn't match the expected key", self.path.display());
+            process::exit(1);
+        }
+    }
+}
+
+impl VerifyCommand {
+    /// Load the key and derive its public key, using the algorithm of the
+    /// expected key unless one was given
+    fn load_public_key(&self, expected_key: Option<&PublicKey>) -> PublicKey {
+        let algorithm = match &self.algorithm {
+            Some(algorithm) => algorithm.parse::<KeyAlgorithm>().unwrap_or_else(|e| {
+                status_err!("{} (must be 'ed25519' or 'secp256k1')", e);
+                process::exit(1);
+            }),
+            None => match expected_key {
+                Some(PublicKey::Secp256k1(_)) => KeyAlgorithm::Secp256k1,
+                _ => KeyAlgorithm::Ed25519,
+            },
+        };
+
+        let key_format = self
+            .key_format
+            .as_ref()
+            .map(|f| {
+                f.parse::<KeyFormat>().unwrap_or_else(|e| {
+                    status_err!("{} (must be 'base64' or 'json')", e);
+                    process::exit(1);
+                })
+            })
+            .unwrap_or_default();
+
+        let public_key = match (algorithm, key_format) {
+            (KeyAlgorithm::Ed25519, KeyFormat::Base64) => {
+                key_utils::load_ed25519_key(&self.path, self.passphrase_file.as_deref())
+                    .map(|keypair| PublicKey::from_raw_ed25519(keypair.public.as_bytes()))
+            }
+            (KeyAlgorithm::Ed25519, KeyFormat::Json) => {
+                key_utils::load_json_ed25519_key(&self.path)
+                    .map(|keypair| PublicKey::from_raw_ed25519(keypair.public.as_bytes()))
+            }
+            (KeyAlgorithm::Secp256k1, KeyFormat::Base64) => {
+                key_utils::load_secret(&self.path, self.passphrase_file.as_deref()).and_then(
+                    |bytes| {
+                        let signing_key = ecdsa::SigningKey::from_bytes(bytes.as_slice())
+                            .map_err(|e| format_err!(InvalidKey, "invalid secp256k1 key: {}", e))?;
+
+                        Ok(PublicKey::from_raw_secp256k1(
+                            &signing_key.verifying_key().to_bytes(),
+                        ))
+                    },
+                )
+            }
+            (KeyAlgorithm::Secp256k1, KeyFormat::Json) => {
+                status_err!("secp256k1 keys must be `base64` encoded");
+                process::exit(1);
+            }
+        };
+
+        public_key
+            .map(|public_key| public_key.expect("invalid public key"))
+            .unwrap_or_else(|e| {
+                status_err!("couldn't load {}: {}", self.path.display(), e);
+                process::exit(1);
+            })
+    }
+}
+
+/// Parse an expected consensus address, returning its Bech32 prefix and
+/// checksum variant (if any) so the key's address can be displayed the same way
+fn parse_address(encoded: &str) -> (Option<(String, bech32::Checksum)>, account::Id) {
+    let encoded = encoded.trim();
+
+    let (prefix, bytes) = match bech32::decode(encoded) {
+        Ok((prefix, bytes, checksum)) => (Some((prefix, checksum)), bytes),
+        Err(_) => {
+            let digits = encoded.strip_prefix("0x").unwrap_or(encoded);
+            let bytes = hex::decode(digits.to_ascii_lowercase()).unwrap_or_else(|_| {
+                status_err!(
+                    "invalid --expect-address (must be Bech32 or hex): {}",
+                    encoded
+                );
+                process::exit(1);
+            });
+            (None, bytes)
+        }
+    };
+
+    let address = account::Id::try_from(bytes).unwrap_or_else(|e| {
+        status_err!("invalid --expect-address: {}", e);
+        process::exit(1);
+    });
+
+    (prefix, address)
+}
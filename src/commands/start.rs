@@ -1,13 +1,36 @@
 //! Start the KMS
 
-use crate::{chain, client::Client, prelude::*};
+use super::resolve_config_path;
+use crate::{
+    banner::Banner,
+    build_info::BuildInfo,
+    chain,
+    config::KmsConfig,
+    error::{Error, ErrorKind::*},
+    latency,
+    logging::LogFormat,
+    prelude::*,
+    privileges::{self, Privileges},
+    shutdown,
+    signer::{Handle, Signer},
+    systemd,
+};
 use abscissa_core::Command;
 use clap::Parser;
-use std::{path::PathBuf, process};
+use once_cell::sync::OnceCell;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
 
 #[cfg(feature = "tx-signer")]
 use crate::{application::APP, config::TxSignerConfig, tx_signer::TxSigner};
 
+/// Signer started by the `start` command
+static SIGNER: OnceCell<Signer> = OnceCell::new();
+
 /// The `start` command
 #[derive(Command, Debug, Default, Parser)]
 pub struct StartCommand {
@@ -18,6 +41,24 @@ pub struct StartCommand {
     /// enable verbose debug logging
     #[clap(short = 'v', long = "verbose")]
     pub verbose: bool,
+
+    /// log format: 'plain' or 'json' (default from tmkms.toml, else 'plain')
+    #[clap(long = "log-format")]
+    pub log_format: Option<LogFormat>,
+
+    /// load consensus state which fails HMAC verification (see `state_hmac_key_path`)
+    #[clap(long = "accept-tampered-state")]
+    pub accept_tampered_state: bool,
+
+    /// start with a consensus key which differs from the one recorded with a
+    /// chain's consensus state (recording the new key)
+    #[clap(long = "accept-key-change")]
+    pub accept_key_change: bool,
+
+    /// print the startup summary of each chain to stdout as a JSON document,
+    /// rather than logging it
+    #[clap(long = "json-banner")]
+    pub json_banner: bool,
 }
 
 impl Runnable for StartCommand {
@@ -26,42 +67,198 @@ impl Runnable for StartCommand {
         info!(
             "{} {} starting up...",
             env!("CARGO_PKG_NAME"),
-            env!("CARGO_PKG_VERSION")
+            BuildInfo::current()
         );
 
-        run_app(self.spawn_clients());
+        // Signal handling must be installed before any threads are spawned
+        let config_path = resolve_config_path(self.config.as_ref());
+        let grace_period = APP
+            .config()
+            .shutdown_grace_period
+            .unwrap_or(shutdown::DEFAULT_GRACE_PERIOD);
+
+        shutdown::install_handlers(Duration::from_secs(grace_period), move || {
+            if let Some(signer) = SIGNER.get() {
+                reload(&config_path, signer)
+            }
+        })
+        .unwrap_or_else(|e| {
+            status_err!("error installing signal handlers: {}", e);
+            process::exit(1);
+        });
+
+        chain::set_accept_tampered_state(self.accept_tampered_state);
+        chain::set_accept_key_change(self.accept_key_change);
+        chain::set_lock_state(true);
+
+        for state_file in &APP.config().legacy_state_files {
+            warn!(
+                "using state file {} relative to the working directory, which is deprecated: \
+                 move it next to the configuration file, or set `state_dir` or an absolute \
+                 `state_file`",
+                state_file.display()
+            );
+        }
+
+        // User and group names are looked up before anything is started, as
+        // they may not be resolvable inside `chroot_dir`
+        let privileges = Privileges::from_config(&APP.config()).unwrap_or_else(|e| {
+            status_err!("error loading configuration: {}", e);
+            process::exit(1);
+        });
+
+        let handle = self.start_signer();
+
+        // systemd's notification socket may not be reachable inside
+        // `chroot_dir`, so it's connected to before dropping privileges
+        systemd::connect().unwrap_or_else(|e| {
+            status_err!("{}", e);
+            process::exit(1);
+        });
+
+        // Listeners are bound, keys loaded, and HSM sessions established by
+        // now, so root is no longer needed
+        if !privileges.is_empty() {
+            privileges.apply().unwrap_or_else(|e| {
+                status_err!("{}", e);
+                process::exit(1);
+            });
+        }
+
+        systemd::ready().unwrap_or_else(|e| warn!("{}", e));
+        run_app(handle);
     }
 }
 
 impl StartCommand {
-    /// Spawn clients from the app's configuration
-    fn spawn_clients(&self) -> Vec<Client> {
-        let config = APP.config();
+    /// Start the signer from the app's configuration
+    fn start_signer(&self) -> Handle {
+        let signer = SIGNER.get_or_init(|| {
+            Signer::new(APP.config()).unwrap_or_else(|e| {
+                status_err!("error loading configuration: {}", e);
+                process::exit(1);
+            })
+        });
 
-        chain::load_config(&config).unwrap_or_else(|e| {
-            status_err!("error loading configuration: {}", e);
+        selftest(&signer.config());
+        self.banner(&signer.config());
+
+        signer.start().unwrap_or_else(|e| {
+            status_err!("error starting KMS: {}", e);
             process::exit(1);
-        });
+        })
+    }
+}
+
+impl StartCommand {
+    /// Log the startup summary of each chain (or print it as JSON with
+    /// `--json-banner`)
+    fn banner(&self, config: &KmsConfig) {
+        let banner = Banner::gather(config);
 
-        // Spawn the validator client threads
-        config
-            .validator
-            .iter()
-            .cloned()
-            .map(Client::spawn)
-            .collect()
+        if self.json_banner {
+            println!("{}", serde_json::to_string(&banner).unwrap());
+        } else {
+            for line in banner.to_string().lines() {
+                info!("{}", line);
+            }
+        }
+    }
+}
+
+/// Run the self-test of each chain's keys (see [`KeyRing::selftest`]),
+/// or those of each validator of a chain signed for by several, exiting if
+/// any fail
+///
+/// [`KeyRing::selftest`]: crate::keyring::KeyRing::selftest
+fn selftest(config: &KmsConfig) {
+    let registry = chain::REGISTRY.get();
+    let mut failed = false;
+
+    for (validator, chain_config) in config.signers() {
+        let name = chain::name(&chain_config.id, validator);
+
+        if chain_config.skip_selftest {
+            warn!("[{}] self-test skipped (`skip_selftest = true`)", name);
+            continue;
+        }
+
+        let result = match registry.get_validator_chain(&chain_config.id, validator) {
+            Some(chain) => chain.keyring.selftest(chain_config.selftest),
+            None => continue,
+        };
+
+        match result {
+            Ok(summary) => info!("[{}] self-test passed: {}", name, summary),
+            Err(e) => {
+                status_err!("[{}] self-test failed: {}", name, e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// Reload the configuration file at `path` on `SIGHUP`, applying changes to
+/// the signer. If the new configuration can't be loaded, the signer's current
+/// configuration remains active.
+fn reload(path: &Path, signer: &Signer) {
+    info!(
+        "received SIGHUP: reloading configuration from {}",
+        path.display()
+    );
+
+    let changes = match load_config(path).and_then(|config| signer.reload(config)) {
+        Ok(changes) => changes,
+        Err(e) => {
+            error!(
+                "error reloading configuration (keeping current configuration): {}",
+                e
+            );
+            return;
+        }
+    };
+
+    for chain_id in &changes.added {
+        info!("chain {} added", chain_id);
+    }
+
+    for chain_id in &changes.removed {
+        info!("chain {} removed", chain_id);
     }
+
+    info!("configuration reloaded successfully");
+}
+
+/// Load the configuration file at the given path
+fn load_config(path: &Path) -> Result<KmsConfig, Error> {
+    let resolved_path = privileges::resolve(path);
+    let toml_string = fs::read_to_string(&resolved_path).map_err(|e| {
+        format_err!(
+            ConfigError,
+            "couldn't read {}: {}",
+            resolved_path.display(),
+            e
+        )
+    })?;
+
+    // Parsed as if outside the chroot, so paths resolve the same way as
+    // when starting up
+    KmsConfig::parse_toml(&toml_string, Some(path))
 }
 
 /// Run the application (non-`tx_signer` version)
 #[cfg(not(feature = "tx-signer"))]
-fn run_app(validator_clients: Vec<Client>) {
-    blocking_wait(validator_clients);
+fn run_app(handle: Handle) {
+    blocking_wait(handle);
 }
 
 /// Run the application, launching the Tokio executor if need be
 #[cfg(feature = "tx-signer")]
-fn run_app(validator_clients: Vec<Client>) {
+fn run_app(handle: Handle) {
     let signer_config = {
         let cfg = APP.config();
 
@@ -75,27 +272,19 @@ fn run_app(validator_clients: Vec<Client>) {
     if let Some(cfg) = signer_config {
         run_async_executor(cfg);
     } else {
-        blocking_wait(validator_clients);
+        blocking_wait(handle);
     }
 }
 
 /// Wait for clients to shut down using synchronous thread joins
-fn blocking_wait(validator_clients: Vec<Client>) {
+fn blocking_wait(handle: Handle) {
     // Wait for all of the validator client threads to exit
     debug!("Main thread waiting on clients...");
 
-    let mut success = true;
-
-    for client in validator_clients {
-        let name = client.name().to_owned();
-
-        if let Err(e) = client.join() {
-            status_err!("client '{}' exited with error: {}", name, e);
-            success = false;
-        }
-    }
+    let result = handle.wait();
+    latency::log_summary();
 
-    if success {
+    if result.is_ok() {
         info!("Shutdown completed successfully");
     } else {
         warn!("Shutdown completed with errors");
@@ -0,0 +1,183 @@
+//! `tmkms state` CLI (sub)commands
+
+mod import;
+mod init;
+mod set;
+mod show;
+
+pub use self::{import::ImportCommand, init::InitCommand, set::SetCommand, show::ShowCommand};
+use crate::{
+    chain::{
+        self,
+        state::{SignedPayload, StateStore},
+    },
+    config::chain::ChainConfig,
+    keyring::Format,
+    prelude::*,
+};
+use abscissa_core::{Command, Runnable};
+use clap::Subcommand;
+use serde::Serialize;
+use std::{path::PathBuf, process};
+use tendermint::{consensus, PublicKey, TendermintKey};
+
+/// The `state` subcommand
+#[derive(Command, Debug, Runnable, Subcommand)]
+pub enum StateCommand {
+    /// show the last signed height/round/step of each chain
+    Show(ShowCommand),
+
+    /// set the last signed height/round/step of a chain
+    Set(SetCommand),
+
+    /// initialize the consensus state of a chain which has none
+    Init(InitCommand),
+
+    /// import the state of a chain from a validator's priv_validator_state.json
+    Import(ImportCommand),
+}
+
+impl StateCommand {
+    pub(super) fn config_path(&self) -> Option<&PathBuf> {
+        match self {
+            StateCommand::Show(show) => show.config.as_ref(),
+            StateCommand::Set(set) => set.config.as_ref(),
+            StateCommand::Init(init) => init.config.as_ref(),
+            StateCommand::Import(import) => import.config.as_ref(),
+        }
+    }
+}
+
+/// Consensus state of a chain, as displayed by `tmkms state`
+#[derive(Debug, Serialize)]
+struct StateInfo {
+    /// Chain ID
+    chain_id: String,
+
+    /// Last signed height (`None` if nothing has been signed)
+    height: Option<u64>,
+
+    /// Last signed round
+    round: Option<u32>,
+
+    /// Last signed step
+    step: Option<i8>,
+
+    /// Last signed block ID
+    block_id: Option<String>,
+
+    /// Type of the message last signed (if known)
+    msg_type: Option<&'static str>,
+
+    /// SHA-256 of the bytes last signed (if known)
+    sign_bytes_sha256: Option<String>,
+
+    /// Consensus public key recorded with the state (if any)
+    pub_key: Option<String>,
+}
+
+impl StateInfo {
+    /// Create state info for the given chain, including the payload signed at
+    /// its state (if known)
+    fn new(
+        chain_id: &chain::Id,
+        state: Option<&consensus::State>,
+        payload: Option<&SignedPayload>,
+    ) -> Self {
+        Self {
+            chain_id: chain_id.to_string(),
+            height: state.map(|s| s.height.value()),
+            round: state.map(|s| s.round.value()),
+            step: state.map(|s| s.step),
+            block_id: state
+                .and_then(|s| s.block_id.as_ref())
+                .map(|block_id| block_id.hash.to_string()),
+            msg_type: payload
+                .and_then(|p| p.msg_type)
+                .map(|msg_type| msg_type.as_str()),
+            sign_bytes_sha256: payload.map(SignedPayload::sign_bytes_hash),
+            pub_key: None,
+        }
+    }
+
+    /// Include the consensus public key recorded with the state (if any),
+    /// serialized in the given format
+    fn with_pub_key(mut self, pub_key: Option<PublicKey>, format: &Format) -> Self {
+        self.pub_key =
+            pub_key.map(|pub_key| format.serialize(TendermintKey::ConsensusKey(pub_key)));
+        self
+    }
+}
+
+/// Find the configuration for the given chain, exiting if it isn't configured
+fn find_chain<'a>(chains: &'a [ChainConfig], chain_id: &str) -> &'a ChainConfig {
+    chains
+        .iter()
+        .find(|chain| chain.id.as_str() == chain_id)
+        .unwrap_or_else(|| {
+            status_err!("no [[chain]] section for chain ID: {}", chain_id);
+            process::exit(1);
+        })
+}
+
+/// Open the state store configured for the given chain, exiting on error
+fn open_store(config: &ChainConfig) -> Box<dyn StateStore> {
+    chain::open_state_store(config).unwrap_or_else(|e| {
+        status_err!("couldn't open state for chain {}: {}", config.id, e);
+        process::exit(1);
+    })
+}
+
+/// Get the height/round/step of the given state, for comparison
+fn hrs(state: &consensus::State) -> (u64, u32, i8) {
+    (state.height.value(), state.round.value(), state.step)
+}
+
+/// Print the given states, either as a table or as JSON
+fn print_states(states: &[StateInfo], json: bool) {
+    if json {
+        println!("{}", serde_json::to_string_pretty(states).unwrap());
+        return;
+    }
+
+    let width = states
+        .iter()
+        .map(|state| state.chain_id.len())
+        .chain(Some("CHAIN ID".len()))
+        .max()
+        .unwrap();
+
+    println!(
+        "{:width$}  {:>10}  {:>5}  {:>4}  {:9}  {:64}  {:64}  PUBLIC KEY",
+        "CHAIN ID",
+        "HEIGHT",
+        "ROUND",
+        "STEP",
+        "TYPE",
+        "BLOCK ID",
+        "SIGN BYTES SHA-256",
+        width = width
+    );
+
+    for state in states {
+        println!(
+            "{:width$}  {:>10}  {:>5}  {:>4}  {:9}  {:64}  {:64}  {}",
+            state.chain_id,
+            display_or_dash(state.height),
+            display_or_dash(state.round),
+            display_or_dash(state.step),
+            state.msg_type.unwrap_or("-"),
+            state.block_id.as_deref().unwrap_or("-"),
+            state.sign_bytes_sha256.as_deref().unwrap_or("-"),
+            state.pub_key.as_deref().unwrap_or("-"),
+            width = width
+        );
+    }
+}
+
+/// Display the given value, or `-` if it's absent
+fn display_or_dash(value: Option<impl ToString>) -> String {
+    value
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| "-".to_owned())
+}
@@ -0,0 +1,95 @@
+//! Import the consensus state of a chain from a validator's
+//! `priv_validator_state.json`
+
+use super::{find_chain, hrs, open_store, print_states, StateInfo};
+use crate::{
+    chain::{self, state::import, State},
+    prelude::*,
+};
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use std::{path::PathBuf, process};
+
+/// The `state import` subcommand
+#[derive(Command, Debug, Default, Parser)]
+pub struct ImportCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// print the imported state as JSON
+    #[clap(long)]
+    pub json: bool,
+
+    /// allow importing a state lower than the current one, or replacing one
+    /// which fails HMAC verification
+    #[clap(long)]
+    pub force: bool,
+
+    /// ID of the chain to import the state of
+    pub chain_id: String,
+
+    /// path to the validator's priv_validator_state.json
+    pub path: PathBuf,
+}
+
+impl Runnable for ImportCommand {
+    /// Import the last signed height/round/step of a chain (and the bytes
+    /// signed at it, if recorded) from the state file of a validator which
+    /// signed with Tendermint's FilePV. The KMS must not be running while
+    /// this happens.
+    fn run(&self) {
+        let config = APP.config();
+        let chain_config = find_chain(&config.chain, &self.chain_id);
+
+        let imported = import::read(&self.path).unwrap_or_else(|e| {
+            status_err!("couldn't import state for chain {}: {}", chain_config.id, e);
+            process::exit(1);
+        });
+
+        // Replacing state which fails HMAC verification requires `--force`
+        chain::set_accept_tampered_state(self.force);
+
+        let mut state = State::load(open_store(chain_config)).unwrap_or_else(|e| {
+            status_err!("couldn't load state for chain {}: {}", chain_config.id, e);
+            process::exit(1);
+        });
+
+        let current_state = state.consensus_state();
+
+        if hrs(&imported.state) < hrs(current_state) {
+            if !self.force {
+                status_err!(
+                    "imported state {} is lower than the current state {} for chain {} (use --force to override)",
+                    imported.state,
+                    current_state,
+                    chain_config.id
+                );
+                process::exit(1);
+            }
+
+            status_warn!(
+                "lowering state for chain {} from {} to {}",
+                chain_config.id,
+                current_state,
+                imported.state
+            );
+        }
+
+        state
+            .restore(imported.state.clone(), imported.payload.clone())
+            .unwrap_or_else(|e| {
+                status_err!("couldn't write state for chain {}: {}", chain_config.id, e);
+                process::exit(1);
+            });
+
+        print_states(
+            &[StateInfo::new(
+                &chain_config.id,
+                Some(&imported.state),
+                imported.payload.as_ref(),
+            )],
+            self.json,
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Initialize the consensus state of a chain which has none
+
+use super::{find_chain, open_store, print_states, StateInfo};
+use crate::prelude::*;
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use std::{convert::TryFrom, path::PathBuf, process};
+use tendermint::{block, consensus};
+
+/// The `state init` subcommand
+#[derive(Command, Debug, Default, Parser)]
+pub struct InitCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// print the new state as JSON
+    #[clap(long)]
+    pub json: bool,
+
+    /// height to resume signing from (nothing at or below it is signed)
+    #[clap(long)]
+    pub height: u64,
+
+    /// ID of the chain to initialize the state of
+    pub chain_id: String,
+}
+
+impl Runnable for InitCommand {
+    /// Write the initial consensus state of a chain, refusing to overwrite an
+    /// existing one (see `tmkms state set`)
+    fn run(&self) {
+        let config = APP.config();
+        let chain_config = find_chain(&config.chain, &self.chain_id);
+        let mut store = open_store(chain_config);
+
+        let height = block::Height::try_from(self.height).unwrap_or_else(|e| {
+            status_err!("invalid height {}: {}", self.height, e);
+            process::exit(1);
+        });
+
+        match store.load() {
+            Ok(None) => (),
+            Ok(Some(current_state)) => {
+                status_err!(
+                    "chain {} already has a consensus state ({}): use `tmkms state set` to change it",
+                    chain_config.id,
+                    current_state
+                );
+                process::exit(1);
+            }
+            Err(e) => {
+                status_err!("couldn't load state for chain {}: {}", chain_config.id, e);
+                process::exit(1);
+            }
+        }
+
+        let state = consensus::State {
+            height,
+            ..Default::default()
+        };
+
+        store.store(&state).unwrap_or_else(|e| {
+            status_err!("couldn't write state for chain {}: {}", chain_config.id, e);
+            process::exit(1);
+        });
+
+        print_states(
+            &[StateInfo::new(&chain_config.id, Some(&state), None)],
+            self.json,
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Set the last signed consensus state of a chain
+
+use super::{find_chain, hrs, open_store, print_states, StateInfo};
+use crate::{
+    chain::{self, state::Step, State},
+    prelude::*,
+};
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use std::{convert::TryFrom, path::PathBuf, process};
+use tendermint::{block, consensus};
+
+/// Highest consensus step the KMS signs at (precommits)
+const MAX_STEP: u8 = Step::Precommit as u8;
+
+/// The `state set` subcommand
+#[derive(Command, Debug, Default, Parser)]
+pub struct SetCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// print the new state as JSON
+    #[clap(long)]
+    pub json: bool,
+
+    /// last signed block height
+    #[clap(long)]
+    pub height: u64,
+
+    /// last signed consensus round
+    #[clap(long, default_value = "0")]
+    pub round: u32,
+
+    /// last signed consensus step (0 = none, 1 = proposal, 2 = prevote,
+    /// 3 = precommit)
+    #[clap(long, default_value = "0")]
+    pub step: u8,
+
+    /// allow setting a state lower than the current one, or replacing one
+    /// which fails HMAC verification
+    #[clap(long)]
+    pub force: bool,
+
+    /// ID of the chain to set the state of
+    pub chain_id: String,
+}
+
+impl Runnable for SetCommand {
+    /// Set the last signed height/round/step of a chain. The KMS must not be
+    /// running while this happens.
+    fn run(&self) {
+        let config = APP.config();
+        let chain_config = find_chain(&config.chain, &self.chain_id);
+        let new_state = self.new_state();
+
+        // Replacing state which fails HMAC verification requires `--force`
+        chain::set_accept_tampered_state(self.force);
+
+        let mut state = State::load(open_store(chain_config)).unwrap_or_else(|e| {
+            status_err!("couldn't load state for chain {}: {}", chain_config.id, e);
+            process::exit(1);
+        });
+
+        let current_state = state.consensus_state();
+
+        if hrs(&new_state) < hrs(current_state) {
+            if !self.force {
+                status_err!(
+                    "new state {} is lower than the current state {} for chain {} (use --force to override)",
+                    new_state,
+                    current_state,
+                    chain_config.id
+                );
+                process::exit(1);
+            }
+
+            status_warn!(
+                "lowering state for chain {} from {} to {}",
+                chain_config.id,
+                current_state,
+                new_state
+            );
+        }
+
+        state
+            .set_consensus_state(new_state.clone())
+            .unwrap_or_else(|e| {
+                status_err!("couldn't write state for chain {}: {}", chain_config.id, e);
+                process::exit(1);
+            });
+
+        for validator in &config.validator {
+            match validator.min_height {
+                Some(min_height)
+                    if validator.serves(&chain_config.id) && min_height > new_state.height =>
+                {
+                    status_warn!(
+                        "min_height {} for validator {} exceeds the new state's height {}",
+                        min_height,
+                        validator.addr,
+                        new_state.height
+                    );
+                }
+                _ => (),
+            }
+        }
+
+        print_states(
+            &[StateInfo::new(&chain_config.id, Some(&new_state), None)],
+            self.json,
+        );
+    }
+}
+
+impl SetCommand {
+    /// Validate the requested state
+    fn new_state(&self) -> consensus::State {
+        let height = block::Height::try_from(self.height).unwrap_or_else(|e| {
+            status_err!("invalid height {}: {}", self.height, e);
+            process::exit(1);
+        });
+
+        let round = block::Round::try_from(self.round).unwrap_or_else(|e| {
+            status_err!("invalid round {}: {}", self.round, e);
+            process::exit(1);
+        });
+
+        if self.step > MAX_STEP {
+            status_err!("invalid step {} (must be 0-{})", self.step, MAX_STEP);
+            process::exit(1);
+        }
+
+        consensus::State {
+            height,
+            round,
+            step: self.step as i8,
+            block_id: None,
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Show the last signed consensus state of each chain
+
+use super::{find_chain, open_store, print_states, StateInfo};
+use crate::prelude::*;
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use std::{path::PathBuf, process};
+
+/// The `state show` subcommand
+#[derive(Command, Debug, Default, Parser)]
+pub struct ShowCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// print the state as JSON
+    #[clap(long)]
+    pub json: bool,
+
+    /// only show the state of the chain with this ID
+    pub chain_id: Option<String>,
+}
+
+impl Runnable for ShowCommand {
+    /// Print the last signed height/round/step of the configured chains, along
+    /// with the type and hash of the message last signed and the consensus
+    /// key recorded with the state (if known)
+    fn run(&self) {
+        let config = APP.config();
+
+        let chains = match &self.chain_id {
+            Some(chain_id) => vec![find_chain(&config.chain, chain_id)],
+            None => config.chain.iter().collect(),
+        };
+
+        let states = chains
+            .into_iter()
+            .map(|chain_config| {
+                let mut store = open_store(chain_config);
+                let state = store.load_signed().unwrap_or_else(|e| {
+                    status_err!("couldn't load state for chain {}: {}", chain_config.id, e);
+                    process::exit(1);
+                });
+
+                let info = match &state {
+                    Some((state, payload)) => {
+                        StateInfo::new(&chain_config.id, Some(state), payload.as_ref())
+                    }
+                    None => StateInfo::new(&chain_config.id, None, None),
+                };
+
+                info.with_pub_key(store.pub_key(), &chain_config.key_format)
+            })
+            .collect::<Vec<_>>();
+
+        print_states(&states, self.json);
+    }
+}
@@ -0,0 +1,14 @@
+//! `tmkms threshold` CLI (sub)commands
+
+mod keygen;
+
+use self::keygen::KeygenCommand;
+use abscissa_core::{Command, Runnable};
+use clap::Subcommand;
+
+/// The `threshold` subcommand
+#[derive(Command, Debug, Runnable, Subcommand)]
+pub enum ThresholdCommand {
+    /// split a consensus key into FROST key shares for a set of cosigners
+    Keygen(KeygenCommand),
+}
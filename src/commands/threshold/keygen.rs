@@ -0,0 +1,120 @@
+//! `tmkms threshold keygen` subcommand
+
+use crate::{
+    key_utils,
+    keyring::providers::threshold::{frost, share},
+    prelude::*,
+};
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use curve25519_dalek::{constants::ED25519_BASEPOINT_TABLE, scalar::Scalar};
+use ed25519_dalek as ed25519;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+use tendermint::{PublicKey, TendermintKey};
+use tendermint_p2p::secret_connection;
+use zeroize::Zeroizing;
+
+/// `keygen` command
+#[derive(Command, Debug, Default, Parser)]
+pub struct KeygenCommand {
+    /// number of cosigners required to produce a signature
+    #[clap(short = 't', long = "threshold")]
+    threshold: u16,
+
+    /// total number of cosigners
+    #[clap(short = 'n', long = "count")]
+    count: u16,
+
+    /// split an existing Base64-encoded softsign consensus key instead of
+    /// generating a new one
+    #[clap(long = "import")]
+    import: Option<PathBuf>,
+
+    /// directory where key shares and cosigner identity keys are written
+    #[clap(short = 'o', long = "output")]
+    output_dir: PathBuf,
+}
+
+impl Runnable for KeygenCommand {
+    /// Act as a trusted dealer, splitting a consensus key into key shares
+    fn run(&self) {
+        let secret = match &self.import {
+            Some(path) => import_secret(path),
+            None => frost::random_scalar(),
+        };
+
+        let shares = frost::split(&secret, self.threshold, self.count).unwrap_or_else(|e| {
+            status_err!("{}", e);
+            process::exit(1);
+        });
+
+        fs::create_dir_all(&self.output_dir).unwrap_or_else(|e| {
+            status_err!("couldn't create {}: {}", self.output_dir.display(), e);
+            process::exit(1);
+        });
+
+        for key_share in &shares {
+            let share_path = self
+                .output_dir
+                .join(format!("share-{}.json", key_share.identifier));
+
+            let identity_path = self
+                .output_dir
+                .join(format!("cosigner-{}-identity.key", key_share.identifier));
+
+            share::write(&share_path, key_share)
+                .and_then(|_| key_utils::generate_key(&identity_path))
+                .and_then(|_| key_utils::load_base64_ed25519_key(&identity_path))
+                .map(|identity_key| {
+                    status_ok!(
+                        "Generated",
+                        "cosigner {}: {} (node ID: {})",
+                        key_share.identifier,
+                        share_path.display(),
+                        secret_connection::PublicKey::from(&identity_key).peer_id()
+                    );
+                })
+                .unwrap_or_else(|e| {
+                    status_err!("{}", e);
+                    process::exit(1);
+                });
+        }
+
+        let public_key =
+            PublicKey::from_raw_ed25519(shares[0].group_public_key.compress().as_bytes()).unwrap();
+
+        status_ok!(
+            "Split",
+            "{}-of-{} consensus key: {}",
+            self.threshold,
+            self.count,
+            TendermintKey::ConsensusKey(public_key).to_hex()
+        );
+    }
+}
+
+/// Load an existing softsign key, returning its secret scalar
+fn import_secret(path: &Path) -> Scalar {
+    let keypair = key_utils::load_base64_ed25519_key(path).unwrap_or_else(|e| {
+        status_err!("{}", e);
+        process::exit(1);
+    });
+
+    // Ed25519 derives its secret scalar by hashing the secret key
+    let expanded = Zeroizing::new(ed25519::ExpandedSecretKey::from(&keypair.secret).to_bytes());
+    let mut scalar_bytes = Zeroizing::new([0u8; 32]);
+    scalar_bytes.copy_from_slice(&expanded[..32]);
+
+    let scalar = Scalar::from_bytes_mod_order(*scalar_bytes);
+
+    if (&scalar * &ED25519_BASEPOINT_TABLE).compress().as_bytes() != keypair.public.as_bytes() {
+        status_err!("couldn't derive secret scalar from {}", path.display());
+        process::exit(1);
+    }
+
+    scalar
+}
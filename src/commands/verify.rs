@@ -0,0 +1,113 @@
+//! Verify signatures recorded in an audit log, or a single signature
+
+use crate::{
+    prelude::*,
+    verify::{self, SignedMessage},
+};
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use std::{fs::File, io::BufReader, path::PathBuf, process};
+use subtle_encoding::hex;
+
+/// The `verify` command
+#[derive(Command, Debug, Default, Parser)]
+pub struct VerifyCommand {
+    /// path to an audit log to verify
+    #[clap(long = "audit-log")]
+    pub audit_log: Option<PathBuf>,
+
+    /// bytes which were signed (hex)
+    #[clap(long = "sign-bytes")]
+    pub sign_bytes: Option<String>,
+
+    /// signature to verify (hex)
+    #[clap(long = "signature")]
+    pub signature: Option<String>,
+
+    /// public key which made the signature, as Bech32 (e.g.
+    /// 'cosmosvalconspub1...'), hex, Base64, Cosmos JSON or did:key
+    #[clap(long = "pubkey")]
+    pub pubkey: Option<String>,
+}
+
+impl Runnable for VerifyCommand {
+    /// Verify the given signature(s), exiting with an error if any is invalid
+    fn run(&self) {
+        match (
+            &self.audit_log,
+            &self.sign_bytes,
+            &self.signature,
+            &self.pubkey,
+        ) {
+            (Some(path), None, None, None) => verify_audit_log(path),
+            (None, Some(sign_bytes), Some(signature), Some(pubkey)) => {
+                verify_one(sign_bytes, signature, pubkey)
+            }
+            _ => {
+                status_err!(
+                    "expected either --audit-log, or all of --sign-bytes, --signature and --pubkey"
+                );
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Verify the signatures in the given audit log, printing each failure
+fn verify_audit_log(path: &PathBuf) {
+    let file = File::open(path).unwrap_or_else(|e| {
+        status_err!("couldn't open {}: {}", path.display(), e);
+        process::exit(1);
+    });
+
+    let report = verify::verify_audit_log(BufReader::new(file)).unwrap_or_else(|e| {
+        status_err!("error reading {}: {}", path.display(), e);
+        process::exit(1);
+    });
+
+    for failure in &report.failures {
+        println!("{}:{}", path.display(), failure);
+    }
+
+    let summary = format!(
+        "{} signature(s) verified, {} failed, {} record(s) skipped ({:.0} signatures/s)",
+        report.verified,
+        report.failures.len(),
+        report.skipped,
+        report.throughput()
+    );
+
+    if !report.failures.is_empty() {
+        status_err!("{}", summary);
+        process::exit(1);
+    }
+
+    status_ok!("Verified", "{}", summary);
+}
+
+/// Verify a single signature
+fn verify_one(sign_bytes: &str, signature: &str, pubkey: &str) {
+    let message = SignedMessage {
+        sign_bytes: decode_hex("--sign-bytes", sign_bytes),
+        signature: decode_hex("--signature", signature),
+        public_key: verify::parse_public_key(pubkey).unwrap_or_else(|e| {
+            status_err!("invalid --pubkey: {}", e);
+            process::exit(1);
+        }),
+    };
+
+    if let Err(e) = message.verify() {
+        status_err!("{}", e);
+        process::exit(1);
+    }
+
+    status_ok!("Verified", "signature is valid");
+}
+
+/// Decode a hex argument, exiting with an error if it's malformed
+fn decode_hex(arg: &str, digits: &str) -> Vec<u8> {
+    hex::decode(digits.trim().to_ascii_lowercase()).unwrap_or_else(|e| {
+        status_err!("invalid {}: {}", arg, e);
+        process::exit(1);
+    })
+}
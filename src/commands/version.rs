@@ -1,18 +1,32 @@
 //! Provide the version
 
-use crate::prelude::*;
+use crate::{
+    build_info::{self, BuildInfo},
+    prelude::*,
+};
 use abscissa_core::Command;
 use clap::Parser;
-use std::{option_env, process};
+use std::process;
 
 /// The `version` command
 #[derive(Command, Debug, Default, Parser)]
-pub struct VersionCommand {}
+pub struct VersionCommand {
+    /// print the version, git commit, enabled features, supported protocol
+    /// versions and rustc version as JSON
+    #[clap(long)]
+    pub json: bool,
+}
 
 impl Runnable for VersionCommand {
     /// Run the KMS
     fn run(&self) {
-        println!("{}", option_env!("CARGO_PKG_VERSION").unwrap_or("unknown"));
+        if self.json {
+            let info = BuildInfo::current();
+            println!("{}", serde_json::to_string_pretty(&info).unwrap());
+        } else {
+            println!("{}", build_info::VERSION);
+        }
+
         process::exit(0);
     }
 }
@@ -1,12 +1,15 @@
 //! YubiHSM2 key management commands
 
+mod backup;
 mod export;
 mod generate;
 mod import;
 mod list;
+mod restore;
 
 use self::{
     export::ExportCommand, generate::GenerateCommand, import::ImportCommand, list::ListCommand,
+    restore::RestoreCommand,
 };
 use abscissa_core::{Command, Runnable};
 use clap::Subcommand;
@@ -35,6 +38,9 @@ pub enum KeysCommand {
 
     /// list all suitable Ed25519 keys in the HSM
     List(ListCommand),
+
+    /// restore an encrypted backup of a key made with 'yubihsm keys export'
+    Restore(RestoreCommand),
 }
 
 impl KeysCommand {
@@ -45,6 +51,7 @@ impl KeysCommand {
             KeysCommand::Generate(generate) => generate.config.as_ref(),
             KeysCommand::List(list) => list.config.as_ref(),
             KeysCommand::Import(import) => import.config.as_ref(),
+            KeysCommand::Restore(restore) => restore.config.as_ref(),
         }
     }
 }
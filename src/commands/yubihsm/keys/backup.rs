@@ -0,0 +1,164 @@
+//! Encrypted backups of keys, wrapped (i.e. encrypted) under a wrap key
+//! inside the HSM
+
+use crate::{
+    error::{Error, ErrorKind::YubihsmError},
+    prelude::*,
+};
+use yubihsm::{object, wrap, Capability, Client, Domain};
+
+/// Export the given asymmetric key wrapped under the given wrap key
+pub fn export(
+    hsm: &Client,
+    key_id: object::Id,
+    wrap_key_id: object::Id,
+) -> Result<wrap::Message, Error> {
+    check_wrap_key(hsm, wrap_key_id, Capability::EXPORT_WRAPPED)?;
+
+    hsm.export_wrapped(wrap_key_id, object::Type::AsymmetricKey, key_id)
+        .map_err(|e| {
+            format_err!(
+                YubihsmError,
+                "couldn't export key 0x{:04x} under wrap key 0x{:04x}: {}",
+                key_id,
+                wrap_key_id,
+                e
+            )
+            .into()
+        })
+}
+
+/// Restore a key exported with [`export`] under the given wrap key, returning
+/// information about the restored object.
+///
+/// The wrap key must be able to export the restored key again, so the
+/// replacement device can itself be backed up.
+pub fn restore(
+    hsm: &Client,
+    wrap_key_id: object::Id,
+    message: wrap::Message,
+) -> Result<object::Info, Error> {
+    check_wrap_key(
+        hsm,
+        wrap_key_id,
+        Capability::EXPORT_WRAPPED | Capability::IMPORT_WRAPPED,
+    )?;
+
+    let handle = hsm.import_wrapped(wrap_key_id, message).map_err(|e| {
+        format_err!(
+            YubihsmError,
+            "couldn't import key under wrap key 0x{:04x}: {}",
+            wrap_key_id,
+            e
+        )
+    })?;
+
+    Ok(hsm.get_object_info(handle.object_id, handle.object_type)?)
+}
+
+/// Ensure the given wrap key exists and has the required capabilities
+fn check_wrap_key(
+    hsm: &Client,
+    wrap_key_id: object::Id,
+    required: Capability,
+) -> Result<(), Error> {
+    let info = hsm
+        .get_object_info(wrap_key_id, object::Type::WrapKey)
+        .map_err(|e| {
+            format_err!(
+                YubihsmError,
+                "couldn't get wrap key 0x{:04x}: {}",
+                wrap_key_id,
+                e
+            )
+        })?;
+
+    for capability in &[Capability::EXPORT_WRAPPED, Capability::IMPORT_WRAPPED] {
+        if required.contains(*capability) && !info.capabilities.contains(*capability) {
+            fail!(
+                YubihsmError,
+                "wrap key 0x{:04x} lacks the {} capability",
+                wrap_key_id,
+                capability
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Format domains as a list of domain numbers, e.g. `1, 2`
+pub fn format_domains(domains: Domain) -> String {
+    (0..16)
+        .filter(|i| domains.bits() & (1 << i) != 0)
+        .map(|i| (i + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(all(test, feature = "yubihsm-mock"))]
+mod tests {
+    use super::*;
+    use yubihsm::{asymmetric, Connector, Credentials};
+
+    /// ID of the key backed up in tests
+    const KEY_ID: object::Id = 2;
+
+    /// ID of the wrap key used in tests
+    const WRAP_KEY_ID: object::Id = 3;
+
+    /// Open a client for a new mock HSM with a wrap key with the given
+    /// capabilities, and an exportable Ed25519 key
+    fn mock_hsm(wrap_key_capabilities: Capability) -> Client {
+        let hsm = Client::open(Connector::mockhsm(), Credentials::default(), true).unwrap();
+
+        hsm.put_wrap_key(
+            WRAP_KEY_ID,
+            "backup".into(),
+            Domain::DOM1,
+            wrap_key_capabilities,
+            Capability::all(),
+            wrap::Algorithm::Aes256Ccm,
+            [0x42; 32],
+        )
+        .unwrap();
+
+        hsm.generate_asymmetric_key(
+            KEY_ID,
+            "validator".into(),
+            Domain::DOM1 | Domain::DOM2,
+            Capability::SIGN_EDDSA | Capability::EXPORTABLE_UNDER_WRAP,
+            asymmetric::Algorithm::Ed25519,
+        )
+        .unwrap();
+
+        hsm
+    }
+
+    #[test]
+    fn export_and_restore_round_trip() {
+        let hsm = mock_hsm(Capability::EXPORT_WRAPPED | Capability::IMPORT_WRAPPED);
+        let public_key = hsm.get_public_key(KEY_ID).unwrap();
+
+        let message = export(&hsm, KEY_ID, WRAP_KEY_ID).unwrap();
+        let message = wrap::Message::from_vec(message.into_vec()).unwrap();
+
+        // Replacement device (with the same wrap key)
+        hsm.delete_object(KEY_ID, object::Type::AsymmetricKey)
+            .unwrap();
+
+        let info = restore(&hsm, WRAP_KEY_ID, message).unwrap();
+        assert_eq!(info.object_id, KEY_ID);
+        assert_eq!(info.label.to_string(), "validator");
+        assert_eq!(format_domains(info.domains), "1, 2");
+        assert_eq!(hsm.get_public_key(KEY_ID).unwrap(), public_key);
+    }
+
+    #[test]
+    fn wrap_key_without_export_wrapped_is_refused() {
+        let hsm = mock_hsm(Capability::IMPORT_WRAPPED);
+
+        let err = export(&hsm, KEY_ID, WRAP_KEY_ID).unwrap_err();
+        assert!(err.to_string().contains("export-wrapped"));
+    }
+}
@@ -18,34 +18,38 @@ pub struct ExportCommand {
     pub key_id: u16,
 
     /// ID of the wrap key to encrypt the exported key under
-    #[clap(short = 'w', long = "wrapkey")]
+    #[clap(short = 'w', long = "wrapkey", visible_alias = "wrap-key")]
     pub wrap_key_id: Option<u16>,
 
     /// Path to write the resulting file to
-    pub path: PathBuf,
+    #[clap(short = 'o', long = "output")]
+    pub output: Option<PathBuf>,
+
+    /// Path to write the resulting file to (alternative to `-o`)
+    pub path: Option<PathBuf>,
 }
 
 impl Runnable for ExportCommand {
     fn run(&self) {
+        let path = match (&self.output, &self.path) {
+            (Some(path), None) | (None, Some(path)) => path,
+            _ => {
+                status_err!(
+                    "specify the path to write the encrypted key to once (e.g. -o key.enc)"
+                );
+                process::exit(1);
+            }
+        };
+
         let wrap_key_id = self.wrap_key_id.unwrap_or(DEFAULT_WRAP_KEY);
 
-        let wrapped_bytes = crate::yubihsm::client()
-            .export_wrapped(
-                wrap_key_id,
-                yubihsm::object::Type::AsymmetricKey,
-                self.key_id,
-            )
+        let wrapped_bytes = backup::export(&crate::yubihsm::client(), self.key_id, wrap_key_id)
             .unwrap_or_else(|e| {
-                status_err!(
-                    "couldn't export key {} under wrap key {}: {}",
-                    self.key_id,
-                    wrap_key_id,
-                    e
-                );
+                status_err!("{}", e);
                 process::exit(1);
             });
 
-        key_utils::write_base64_secret(&self.path, &wrapped_bytes.into_vec()).unwrap_or_else(|e| {
+        key_utils::write_base64_secret(path, &wrapped_bytes.into_vec()).unwrap_or_else(|e| {
             status_err!("{}", e);
             process::exit(1);
         });
@@ -55,7 +59,7 @@ impl Runnable for ExportCommand {
             "key 0x{:04x} (encrypted under wrap key 0x{:04x}) to {}",
             self.key_id,
             wrap_key_id,
-            self.path.display()
+            path.display()
         );
     }
 }
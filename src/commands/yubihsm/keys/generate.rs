@@ -168,24 +168,16 @@ impl Runnable for GenerateCommand {
 }
 
 /// Create an encrypted backup of this key under the given wrap key ID
-// TODO(tarcieri): unify this with the similar code in export?
 fn create_encrypted_backup(
     hsm: &yubihsm::Client,
     key_id: yubihsm::object::Id,
     backup_file_path: &Path,
     wrap_key_id: yubihsm::object::Id,
 ) {
-    let wrapped_bytes = hsm
-        .export_wrapped(wrap_key_id, yubihsm::object::Type::AsymmetricKey, key_id)
-        .unwrap_or_else(|e| {
-            status_err!(
-                "couldn't export key {} under wrap key {}: {}",
-                key_id,
-                wrap_key_id,
-                e
-            );
-            process::exit(1);
-        });
+    let wrapped_bytes = backup::export(hsm, key_id, wrap_key_id).unwrap_or_else(|e| {
+        status_err!("{}", e);
+        process::exit(1);
+    });
 
     key_utils::write_base64_secret(backup_file_path, &wrapped_bytes.into_vec()).unwrap_or_else(
         |e| {
@@ -1,9 +1,10 @@
 //! List keys inside the YubiHSM2
 
-use crate::{chain, keyring, prelude::*, Map};
+use crate::{chain, config::provider::KeyType, keyring, prelude::*, Map};
 use abscissa_core::{Command, Runnable};
 use clap::Parser;
 use k256::elliptic_curve::generic_array::GenericArray;
+use serde::Serialize;
 use std::{path::PathBuf, process};
 use tendermint::{PublicKey, TendermintKey};
 
@@ -13,11 +14,35 @@ pub struct ListCommand {
     /// path to tmkms.toml
     #[clap(short = 'c', long = "config")]
     pub config: Option<PathBuf>,
+
+    /// list the key configured for each chain instead, checking it exists
+    /// on the device and is of the configured type
+    #[clap(long)]
+    pub chains: bool,
+
+    /// with --chains: expected label of each chain's key, where `{chain_id}`
+    /// is replaced with the chain's ID
+    #[clap(long, value_name = "PATTERN")]
+    pub expect_label: Option<String>,
+
+    /// with --chains: print the keys as JSON
+    #[clap(long)]
+    pub json: bool,
 }
 
 impl Runnable for ListCommand {
     /// List all suitable Ed25519 keys in the HSM
     fn run(&self) {
+        if self.chains {
+            self.list_chain_keys();
+            return;
+        }
+
+        if self.json || self.expect_label.is_some() {
+            status_err!("--json and --expect-label require --chains");
+            process::exit(1);
+        }
+
         let key_formatters = load_key_formatters();
         let hsm = crate::yubihsm::client();
 
@@ -54,6 +79,217 @@ impl Runnable for ListCommand {
     }
 }
 
+impl ListCommand {
+    /// List the key each chain is configured to use, flagging keys which are
+    /// missing from the device, of the wrong type, or labelled unexpectedly
+    fn list_chain_keys(&self) {
+        let chain_formatters = load_chain_formatters();
+        let cfg = crate::yubihsm::config();
+        let hsm = crate::yubihsm::client();
+
+        let objects = hsm.list_objects(&[]).unwrap_or_else(|e| {
+            status_err!("couldn't list YubiHSM objects: {}", e);
+            process::exit(1);
+        });
+
+        let mut keys = vec![];
+
+        for key_config in &cfg.keys {
+            for chain_id in &key_config.chain_ids {
+                let mut key = ChainKeyInfo::new(chain_id, key_config.key, key_config.key_type);
+
+                if objects.iter().any(|o| {
+                    o.object_type == yubihsm::object::Type::AsymmetricKey
+                        && o.object_id == key_config.key
+                }) {
+                    key.inspect(&hsm, chain_formatters.get(chain_id));
+                } else {
+                    key.problems
+                        .push("no such asymmetric key on the device".to_owned());
+                }
+
+                if !chain_formatters.contains_key(chain_id) {
+                    key.problems.push("no [[chain]] section".to_owned());
+                }
+
+                if let (Some(pattern), Some(label)) = (&self.expect_label, &key.label) {
+                    let expected = pattern.replace("{chain_id}", chain_id.as_str());
+
+                    if *label != expected {
+                        key.problems
+                            .push(format!("label differs from \"{}\"", expected));
+                    }
+                }
+
+                keys.push(key);
+            }
+        }
+
+        keys.sort_by(|k1, k2| k1.chain_id.cmp(&k2.chain_id));
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&keys).unwrap());
+        } else {
+            print_chain_keys(&keys, serial_number(&hsm));
+        }
+
+        if keys.iter().any(|key| !key.problems.is_empty()) {
+            process::exit(1);
+        }
+    }
+}
+
+/// YubiHSM key a chain is configured to use
+#[derive(Debug, Serialize)]
+struct ChainKeyInfo {
+    /// Chain ID
+    chain_id: String,
+
+    /// Object ID of the key
+    key_id: u16,
+
+    /// Configured type of key
+    key_type: KeyType,
+
+    /// Label of the key on the device (if it exists)
+    label: Option<String>,
+
+    /// Public key in the chain's `key_format` (hex if the chain isn't
+    /// configured)
+    public_key: Option<String>,
+
+    /// Problems found with the key (none if it's usable)
+    problems: Vec<String>,
+}
+
+impl ChainKeyInfo {
+    /// Describe the key with the given ID configured for the given chain
+    fn new(chain_id: &chain::Id, key_id: u16, key_type: KeyType) -> Self {
+        Self {
+            chain_id: chain_id.to_string(),
+            key_id,
+            key_type,
+            label: None,
+            public_key: None,
+            problems: vec![],
+        }
+    }
+
+    /// Fetch the key's label and public key from the device, checking the
+    /// key's algorithm suits its configured type
+    fn inspect(&mut self, hsm: &yubihsm::Client, key_formatter: Option<&keyring::Format>) {
+        let key_info = hsm
+            .get_object_info(self.key_id, yubihsm::object::Type::AsymmetricKey)
+            .unwrap_or_else(|e| {
+                status_err!(
+                    "couldn't get object info for asymmetric key #{}: {}",
+                    self.key_id,
+                    e
+                );
+                process::exit(1);
+            });
+
+        self.label = Some(key_info.label.to_string());
+
+        let public_key = hsm.get_public_key(self.key_id).unwrap_or_else(|e| {
+            status_err!(
+                "couldn't get public key for asymmetric key #{}: {}",
+                self.key_id,
+                e
+            );
+            process::exit(1);
+        });
+
+        let tendermint_key = match tendermint_key(&public_key) {
+            Some(TendermintKey::AccountKey(_)) if self.key_type == KeyType::Consensus => {
+                self.problems
+                    .push("consensus keys must be Ed25519, found secp256k1".to_owned());
+                return;
+            }
+            Some(TendermintKey::ConsensusKey(_)) if self.key_type == KeyType::Account => {
+                self.problems
+                    .push("account keys must be secp256k1, found Ed25519".to_owned());
+                return;
+            }
+            Some(tendermint_key) => tendermint_key,
+            None => {
+                self.problems
+                    .push(format!("unsupported algorithm: {:?}", public_key.algorithm));
+                return;
+            }
+        };
+
+        self.public_key = Some(match key_formatter {
+            Some(key_formatter) => key_formatter.serialize(tendermint_key),
+            None => tendermint_key.to_hex(),
+        });
+    }
+}
+
+/// Get the serial number of the YubiHSM
+fn serial_number(hsm: &yubihsm::Client) -> yubihsm::device::SerialNumber {
+    hsm.device_info()
+        .unwrap_or_else(|e| {
+            status_err!("couldn't get YubiHSM serial number: {}", e);
+            process::exit(1);
+        })
+        .serial_number
+}
+
+/// Print the keys configured for each chain as a table
+fn print_chain_keys(keys: &[ChainKeyInfo], serial_number: yubihsm::device::SerialNumber) {
+    if keys.is_empty() {
+        status_err!("no keys configured for YubiHSM #{}", serial_number);
+        return;
+    }
+
+    println!("Keys configured for chains in YubiHSM #{}:", serial_number);
+
+    let chain_width = keys
+        .iter()
+        .map(|key| key.chain_id.len())
+        .chain(Some("CHAIN ID".len()))
+        .max()
+        .unwrap();
+
+    let label_width = keys
+        .iter()
+        .filter_map(|key| key.label.as_ref().map(String::len))
+        .chain(Some("LABEL".len()))
+        .max()
+        .unwrap();
+
+    println!(
+        "{:chain_width$}  {:6}  {:9}  {:label_width$}  PUBLIC KEY",
+        "CHAIN ID",
+        "KEY",
+        "TYPE",
+        "LABEL",
+        chain_width = chain_width,
+        label_width = label_width
+    );
+
+    for key in keys {
+        println!(
+            "{:chain_width$}  0x{:04x}  {:9}  {:label_width$}  {}",
+            key.chain_id,
+            key.key_id,
+            match key.key_type {
+                KeyType::Account => "account",
+                KeyType::Consensus => "consensus",
+            },
+            key.label.as_deref().unwrap_or("-"),
+            key.public_key.as_deref().unwrap_or("-"),
+            chain_width = chain_width,
+            label_width = label_width
+        );
+
+        for problem in &key.problems {
+            status_attr_err!(&key.chain_id, "{}", problem);
+        }
+    }
+}
+
 /// Load information about configured YubiHSM keys
 fn load_key_formatters() -> Map<u16, keyring::Format> {
     let chain_formatters = load_chain_formatters();
@@ -121,25 +357,10 @@ fn display_key_info(
 
     let key_id = format!("- 0x{:04x}", key.object_id);
 
-    let tendermint_key = match public_key.algorithm {
-        yubihsm::asymmetric::Algorithm::EcK256 => {
-            // The YubiHSM2 returns the uncompressed public key, so for
-            // compatibility with Tendermint, we have to compress it first
-            let compressed_pubkey = k256::EncodedPoint::from_untagged_bytes(
-                GenericArray::from_slice(public_key.as_ref()),
-            )
-            .compress();
-
-            TendermintKey::AccountKey(
-                PublicKey::from_raw_secp256k1(compressed_pubkey.as_ref()).unwrap(),
-            )
-        }
-        yubihsm::asymmetric::Algorithm::Ed25519 => {
-            let pk = PublicKey::from_raw_ed25519(public_key.as_ref()).unwrap();
-            TendermintKey::ConsensusKey(pk)
-        }
-        other => {
-            status_attr_err!(key_id, "unsupported algorithm: {:?}", other);
+    let tendermint_key = match tendermint_key(&public_key) {
+        Some(tendermint_key) => tendermint_key,
+        None => {
+            status_attr_err!(key_id, "unsupported algorithm: {:?}", public_key.algorithm);
             return;
         }
     };
@@ -157,3 +378,26 @@ fn display_key_info(
     status_attr_ok!(key_id, "[{}] {}", key_type, key_serialized);
     println!("   label: \"{}\"", &key_info.label);
 }
+
+/// Convert a public key from the YubiHSM into a Tendermint key: secp256k1 keys
+/// are account keys, and Ed25519 keys consensus keys
+fn tendermint_key(public_key: &yubihsm::asymmetric::PublicKey) -> Option<TendermintKey> {
+    match public_key.algorithm {
+        yubihsm::asymmetric::Algorithm::EcK256 => {
+            // The YubiHSM2 returns the uncompressed public key, so for
+            // compatibility with Tendermint, we have to compress it first
+            let compressed_pubkey = k256::EncodedPoint::from_untagged_bytes(
+                GenericArray::from_slice(public_key.as_ref()),
+            )
+            .compress();
+
+            Some(TendermintKey::AccountKey(
+                PublicKey::from_raw_secp256k1(compressed_pubkey.as_ref()).unwrap(),
+            ))
+        }
+        yubihsm::asymmetric::Algorithm::Ed25519 => Some(TendermintKey::ConsensusKey(
+            PublicKey::from_raw_ed25519(public_key.as_ref()).unwrap(),
+        )),
+        _ => None,
+    }
+}
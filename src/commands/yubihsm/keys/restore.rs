@@ -0,0 +1,62 @@
+//! Restore encrypted backups of YubiHSM2 keys onto a (replacement) device
+
+use super::*;
+use crate::prelude::*;
+use abscissa_core::{Command, Runnable};
+use clap::Parser;
+use std::{fs, path::PathBuf, process};
+use subtle_encoding::base64;
+use yubihsm::wrap;
+
+/// The `yubihsm keys restore` subcommand: restore encrypted backups of keys
+/// created with `yubihsm keys export`
+#[derive(Command, Debug, Default, Parser)]
+pub struct RestoreCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// path to the encrypted backup to restore
+    #[clap(short = 'i', long = "input")]
+    pub input: PathBuf,
+
+    /// ID of the wrap key the backup is encrypted under
+    #[clap(short = 'w', long = "wrap-key", visible_alias = "wrapkey")]
+    pub wrap_key_id: Option<u16>,
+}
+
+impl Runnable for RestoreCommand {
+    fn run(&self) {
+        let contents = fs::read_to_string(&self.input).unwrap_or_else(|e| {
+            status_err!("couldn't read {}: {}", self.input.display(), e);
+            process::exit(1);
+        });
+
+        let message = base64::decode(contents.trim_end())
+            .ok()
+            .and_then(|bytes| wrap::Message::from_vec(bytes).ok())
+            .unwrap_or_else(|| {
+                status_err!("{} is not an encrypted key backup", self.input.display());
+                process::exit(1);
+            });
+
+        let wrap_key_id = self.wrap_key_id.unwrap_or(DEFAULT_WRAP_KEY);
+
+        let info =
+            backup::restore(&crate::yubihsm::client(), wrap_key_id, message).unwrap_or_else(|e| {
+                status_err!("{}", e);
+                process::exit(1);
+            });
+
+        status_ok!(
+            "Restored",
+            "{:?} 0x{:04x} from {}",
+            info.object_type,
+            info.object_id,
+            self.input.display()
+        );
+        status_attr_ok!("label", "{}", info.label);
+        status_attr_ok!("domains", "{}", backup::format_domains(info.domains));
+        status_attr_ok!("algorithm", "{:?}", info.algorithm);
+    }
+}
@@ -0,0 +1,29 @@
+//! Alerting configuration
+
+use serde::Deserialize;
+
+/// Default timeout for delivering an alert (in seconds)
+pub const DEFAULT_TIMEOUT: u64 = 5;
+
+/// Default number of consecutive failures to connect to a validator before
+/// alerting
+pub const DEFAULT_CONNECTION_FAILURE_THRESHOLD: u32 = 3;
+
+/// Webhook alerts for double sign attempts, connection loss and signing
+/// provider errors
+#[derive(Clone, Deserialize, Debug, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AlertsConfig {
+    /// URL alerts are `POST`ed to as JSON
+    pub webhook_url: String,
+
+    /// Bearer token sent in the `Authorization` header (if any)
+    pub bearer_token: Option<String>,
+
+    /// Timeout for delivering an alert (in seconds, default 5)
+    pub timeout: Option<u64>,
+
+    /// Number of consecutive failures to connect to a validator before
+    /// alerting (default 3)
+    pub connection_failure_threshold: Option<u32>,
+}
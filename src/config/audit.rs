@@ -0,0 +1,22 @@
+//! Audit log configuration
+
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Default number of rotated audit log files to keep
+pub const DEFAULT_MAX_FILES: usize = 10;
+
+/// Append-only audit log of signing decisions
+#[derive(Clone, Deserialize, Debug, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AuditLogConfig {
+    /// Path to the audit log file
+    pub path: PathBuf,
+
+    /// Rotate the log once it would exceed this size in bytes (default: never)
+    pub max_size: Option<u64>,
+
+    /// Number of rotated log files to keep, named `<path>.1` (most recent)
+    /// through `<path>.<max_files>` (default: 10)
+    pub max_files: Option<usize>,
+}
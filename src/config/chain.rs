@@ -1,27 +1,184 @@
 //! Chain configuration
 
+mod blocked_heights;
 mod hook;
+mod redis;
+mod rpc_endpoint;
 
-pub use self::hook::HookConfig;
-use crate::{chain, keyring};
+pub use self::{
+    blocked_heights::{BlockedHeights, HeightRange},
+    hook::HookConfig,
+    redis::RedisConfig,
+    rpc_endpoint::{HttpUrl, RpcEndpointConfig},
+};
+use super::audit::AuditLogConfig;
+use crate::{amino_types::SignedMsgType, chain, keyring};
 use serde::Deserialize;
 use std::path::PathBuf;
 
 /// Chain configuration
-#[derive(Deserialize, Debug)]
+#[derive(Clone, Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct ChainConfig {
     /// Chain ID of this Tendermint network/chain
     pub id: chain::Id,
 
+    /// Other chain IDs requests for this chain may bear (e.g. its ID before
+    /// a chain ID upgrade), which share its keys and consensus state. Sign
+    /// bytes always use the chain ID of the request.
+    #[serde(default)]
+    pub aliases: Vec<chain::Id>,
+
     /// Key serialization format configuration for this chain
     pub key_format: keyring::Format,
 
-    /// Path to chain-specific `priv_validator_state.json` file
+    /// Consensus key to sign with when several are configured for this chain
+    /// (e.g. during a key rotation handover), identified by its public key in
+    /// `key_format` (or a Fortanix DSM key ID or name). The others are kept
+    /// on standby for `tmkms rotate`.
+    pub active_key: Option<String>,
+
+    /// Backend used to persist consensus state (default: `json`)
+    #[serde(default)]
+    pub state_backend: StateBackend,
+
+    /// Path to chain-specific `priv_validator_state.json` file.
+    ///
+    /// When using the `sqlite` backend, this file is imported into the
+    /// database on first start if it exists.
     pub state_file: Option<PathBuf>,
 
+    /// Path to a Base64-encoded key (at least 32 bytes) used to authenticate
+    /// the state file with HMAC-SHA256, so state edited outside of tmkms is
+    /// refused when loaded (`json` backend only)
+    pub state_hmac_key_path: Option<PathBuf>,
+
+    /// Path to the SQLite database holding consensus state (`sqlite` backend)
+    pub state_db_path: Option<PathBuf>,
+
+    /// Redis connection parameters (`redis` backend)
+    pub state_redis: Option<RedisConfig>,
+
+    /// Refuse to start if no consensus state has been persisted for this
+    /// chain (e.g. its state file is missing) instead of starting from height
+    /// 0. Initialize the state with `tmkms state init` (default: false)
+    #[serde(default)]
+    pub strict_state: bool,
+
     /// User-specified command to run to obtain the current block height for
     /// this chain. This will be executed at launch time to populate the
     /// initial block height if configured
     pub state_hook: Option<HookConfig>,
+
+    /// Audit log of signing decisions for this chain (overrides the global
+    /// `[audit_log]` section)
+    pub audit_log: Option<AuditLogConfig>,
+
+    /// Types of consensus messages validators may request signatures for
+    /// (default: all)
+    pub allowed_msg_types: Option<Vec<MsgType>>,
+
+    /// Maximum difference in seconds between the timestamp of a vote or
+    /// proposal and the KMS host's clock (default: unchecked)
+    pub max_clock_skew_secs: Option<u64>,
+
+    /// Heights (e.g. `"123456"`) and inclusive ranges of heights (e.g.
+    /// `"200000-200100"`) which must never be signed, whatever the request
+    #[serde(default)]
+    pub blocked_heights: BlockedHeights,
+
+    /// Node RPC endpoint polled for the network's latest height, to warn when
+    /// the last signed height falls behind it or is implausibly ahead of it
+    /// (default: not polled)
+    pub rpc_endpoint: Option<RpcEndpointConfig>,
+
+    /// Refuse to sign votes whose validator address isn't the one derived
+    /// from this chain's consensus key (default: true)
+    #[serde(default = "default_enforce_validator_address")]
+    pub enforce_validator_address: bool,
+
+    /// Don't check this chain's keys when `tmkms start` starts up
+    /// (default: false)
+    #[serde(default)]
+    pub skip_selftest: bool,
+
+    /// How this chain's keys are checked when `tmkms start` starts up
+    /// (default: `sign`)
+    #[serde(default)]
+    pub selftest: SelfTest,
+}
+
+/// Types of consensus messages which can be signed
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum MsgType {
+    /// Prevote
+    #[serde(rename = "prevote")]
+    Prevote,
+
+    /// Precommit
+    #[serde(rename = "precommit")]
+    Precommit,
+
+    /// Block proposal
+    #[serde(rename = "proposal")]
+    Proposal,
+}
+
+impl From<SignedMsgType> for MsgType {
+    fn from(msg_type: SignedMsgType) -> MsgType {
+        match msg_type {
+            SignedMsgType::PreVote => MsgType::Prevote,
+            SignedMsgType::PreCommit => MsgType::Precommit,
+            SignedMsgType::Proposal => MsgType::Proposal,
+        }
+    }
+}
+
+/// Validator addresses are enforced unless disabled
+fn default_enforce_validator_address() -> bool {
+    true
+}
+
+/// Startup self-test of a chain's keys (see [`KeyRing::selftest`])
+///
+/// [`KeyRing::selftest`]: crate::keyring::KeyRing::selftest
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum SelfTest {
+    /// Sign a test message with each key and verify the signature
+    #[serde(rename = "sign")]
+    Sign,
+
+    /// Only check each provider is responding and the chain's public key is
+    /// consistent, without producing signatures with HSMs
+    #[serde(rename = "verify")]
+    Verify,
+}
+
+impl Default for SelfTest {
+    fn default() -> Self {
+        SelfTest::Sign
+    }
+}
+
+/// Consensus state storage backends
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum StateBackend {
+    /// JSON file per chain (i.e. `priv_validator_state.json`)
+    #[serde(rename = "json")]
+    Json,
+
+    /// SQLite database (requires the `sqlite` feature)
+    #[serde(rename = "sqlite")]
+    Sqlite,
+
+    /// Redis key shared by active/passive KMS instances (requires the `redis`
+    /// feature)
+    #[serde(rename = "redis")]
+    Redis,
+}
+
+impl Default for StateBackend {
+    fn default() -> Self {
+        StateBackend::Json
+    }
 }
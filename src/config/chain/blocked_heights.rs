@@ -0,0 +1,203 @@
+//! Heights which must never be signed for a chain (`blocked_heights`), e.g.
+//! heights of a chain abandoned after a halt-and-fork
+
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use crate::{
+    error::{Error, ErrorKind::ConfigError},
+    prelude::*,
+};
+
+/// Blocked height, or inclusive range of them, as configured (e.g. `"123456"`
+/// or `"200000-200100"`)
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub struct HeightRange {
+    /// First blocked height
+    pub start: u64,
+
+    /// Last blocked height
+    pub end: u64,
+}
+
+impl HeightRange {
+    /// Is the given height in this range?
+    pub fn contains(&self, height: u64) -> bool {
+        self.start <= height && height <= self.end
+    }
+}
+
+impl Display for HeightRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+impl FromStr for HeightRange {
+    type Err = Error;
+
+    fn from_str(rule: &str) -> Result<Self, Error> {
+        let parse = |height: &str| match height.trim().parse::<u64>() {
+            Ok(height) if height > 0 => Ok(height),
+            _ => Err(format_err!(
+                ConfigError,
+                "invalid blocked height `{}` (expected a height, e.g. \"123456\", or an \
+                 inclusive range, e.g. \"200000-200100\")",
+                rule
+            )),
+        };
+
+        let range = match rule.split_once('-') {
+            Some((start, end)) => HeightRange {
+                start: parse(start)?,
+                end: parse(end)?,
+            },
+            None => {
+                let height = parse(rule)?;
+                HeightRange {
+                    start: height,
+                    end: height,
+                }
+            }
+        };
+
+        if range.start > range.end {
+            fail!(
+                ConfigError,
+                "invalid blocked height range `{}` (it ends before it starts)",
+                rule
+            );
+        }
+
+        Ok(range)
+    }
+}
+
+/// Set of blocked heights: non-overlapping ranges, in ascending order
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BlockedHeights(Vec<HeightRange>);
+
+impl BlockedHeights {
+    /// Create a set of blocked heights from the given ranges, which must not
+    /// overlap
+    pub fn new(mut ranges: Vec<HeightRange>) -> Result<Self, Error> {
+        ranges.sort();
+
+        for pair in ranges.windows(2) {
+            if pair[1].start <= pair[0].end {
+                fail!(
+                    ConfigError,
+                    "blocked heights `{}` and `{}` overlap",
+                    pair[0],
+                    pair[1]
+                );
+            }
+        }
+
+        Ok(BlockedHeights(ranges))
+    }
+
+    /// Find the rule blocking the given height (if any)
+    pub fn find(&self, height: u64) -> Option<&HeightRange> {
+        // Index of the first range ending at or after the height
+        let i = self.0.partition_point(|range| range.end < height);
+        self.0.get(i).filter(|range| range.contains(height))
+    }
+
+    /// Get the blocked ranges, in ascending order
+    pub fn ranges(&self) -> &[HeightRange] {
+        &self.0
+    }
+
+    /// Are no heights blocked?
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Display for BlockedHeights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, range) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+
+            range.fmt(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockedHeights {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rules = Vec::<String>::deserialize(deserializer)?;
+        let mut ranges = Vec::with_capacity(rules.len());
+
+        for rule in &rules {
+            ranges.push(rule.parse().map_err(|e: Error| D::Error::custom(e))?);
+        }
+
+        BlockedHeights::new(ranges).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(rules: &[&str]) -> Result<BlockedHeights, String> {
+        let toml = format!("heights = {:?}", rules);
+
+        #[derive(Deserialize)]
+        struct Config {
+            heights: BlockedHeights,
+        }
+
+        toml::from_str::<Config>(&toml)
+            .map(|config| config.heights)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn range_edges() {
+        let blocked = parse(&["200000-200100", "123456", "200101-200101"]).unwrap();
+        assert_eq!(blocked.to_string(), "123456, 200000-200100, 200101");
+
+        for height in &[123456, 200000, 200001, 200099, 200100, 200101] {
+            assert!(blocked.find(*height).is_some(), "{} not blocked", height);
+        }
+
+        for height in &[1, 123455, 123457, 199999, 200102, u64::MAX] {
+            assert!(blocked.find(*height).is_none(), "{} blocked", height);
+        }
+
+        assert_eq!(blocked.find(200000).unwrap().to_string(), "200000-200100");
+        assert_eq!(blocked.find(123456).unwrap().to_string(), "123456");
+    }
+
+    #[test]
+    fn invalid_rules() {
+        for (rules, error) in &[
+            (&["abc"][..], "invalid blocked height `abc`"),
+            (&["0"], "invalid blocked height `0`"),
+            (&["-5"], "invalid blocked height `-5`"),
+            (&["100-"], "invalid blocked height `100-`"),
+            (&["1-2-3"], "invalid blocked height `1-2-3`"),
+            (&["200-100"], "`200-100` (it ends before it starts)"),
+            (&["100-200", "200-300"], "`100-200` and `200-300` overlap"),
+            (&["150", "100-200"], "`100-200` and `150` overlap"),
+            (&["7", "7"], "`7` and `7` overlap"),
+        ] {
+            let err = parse(rules).unwrap_err();
+            assert!(err.contains(error), "{:?}: {}", rules, err);
+        }
+    }
+}
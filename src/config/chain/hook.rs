@@ -2,7 +2,7 @@ use serde::Deserialize;
 use std::ffi::OsString;
 
 /// Configuration for a particular hook to invoke
-#[derive(Default, Deserialize, Debug)]
+#[derive(Clone, Default, Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct HookConfig {
     /// Command (with arguments) to invoke
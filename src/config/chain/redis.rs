@@ -0,0 +1,27 @@
+use serde::Deserialize;
+
+/// Connection parameters for the `redis` consensus state backend
+#[derive(Clone, Default, Deserialize, Debug, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RedisConfig {
+    /// Redis URL, e.g. `redis://127.0.0.1:6379/0` (use `rediss://` for TLS)
+    pub url: String,
+
+    /// Connect using TLS even if the URL scheme is `redis://` (default false)
+    #[serde(default)]
+    pub tls: bool,
+
+    /// Username to authenticate with (Redis 6+ ACLs)
+    pub username: Option<String>,
+
+    /// Password to authenticate with
+    pub password: Option<String>,
+
+    /// Prefix of the key the state is stored under, i.e. `<prefix>:<chain_id>`
+    /// (default `tmkms`). Instances sharing a key share a high-watermark.
+    pub key_prefix: Option<String>,
+
+    /// Timeout (in seconds) for connecting to Redis and for each request
+    /// (default 5)
+    pub timeout_secs: Option<u64>,
+}
@@ -0,0 +1,229 @@
+//! Node RPC endpoint polled for a chain's latest height (`rpc_endpoint`), to
+//! warn when the KMS's last signed height drifts from the network's
+
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{
+    error::{Error, ErrorKind::ConfigError},
+    prelude::*,
+};
+
+/// Default interval between polls of the node's `/status` (in seconds)
+pub const DEFAULT_POLL_INTERVAL_SECS: u64 = 30;
+
+/// Default number of heights the last signed height may fall behind the
+/// network's before a warning is logged
+pub const DEFAULT_MAX_LAG: u64 = 10;
+
+/// Default number of heights the last signed height may be ahead of the
+/// network's before a warning is logged (validators sign the height after the
+/// latest block, so it's normally 1 ahead)
+pub const DEFAULT_MAX_LEAD: u64 = 2;
+
+/// Default timeout for each poll (in seconds)
+pub const DEFAULT_TIMEOUT_SECS: u64 = 5;
+
+/// Node RPC endpoint polled for a chain's latest height. Purely advisory:
+/// failing to reach it never affects signing.
+#[derive(Clone, Deserialize, Debug, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RpcEndpointConfig {
+    /// URL of the node's RPC, e.g. `http://127.0.0.1:26657`
+    pub url: HttpUrl,
+
+    /// Interval between polls of the node's `/status` (in seconds, default 30)
+    pub poll_interval_secs: Option<u64>,
+
+    /// Warn when the last signed height is more than this many heights behind
+    /// the network's (default 10)
+    pub max_lag: Option<u64>,
+
+    /// Warn when the last signed height is more than this many heights ahead
+    /// of the network's (default 2)
+    pub max_lead: Option<u64>,
+
+    /// Timeout for connecting to the node and reading its response (in
+    /// seconds, default 5)
+    pub timeout_secs: Option<u64>,
+}
+
+impl RpcEndpointConfig {
+    /// Get the interval between polls
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(
+            self.poll_interval_secs
+                .unwrap_or(DEFAULT_POLL_INTERVAL_SECS),
+        )
+    }
+
+    /// Get the number of heights the last signed height may fall behind
+    pub fn max_lag(&self) -> u64 {
+        self.max_lag.unwrap_or(DEFAULT_MAX_LAG)
+    }
+
+    /// Get the number of heights the last signed height may be ahead
+    pub fn max_lead(&self) -> u64 {
+        self.max_lead.unwrap_or(DEFAULT_MAX_LEAD)
+    }
+
+    /// Get the timeout for each poll
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS))
+    }
+}
+
+/// `http://` URL (TLS isn't supported: poll a node on the same host or
+/// network as the KMS)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HttpUrl {
+    /// Host name or IP address (IPv6 addresses without brackets)
+    pub host: String,
+
+    /// Port (default 80)
+    pub port: u16,
+
+    /// Path the RPC is served under (empty, or starting with `/` without a
+    /// trailing one)
+    pub path: String,
+}
+
+impl HttpUrl {
+    /// Get the `host:port` authority of this URL
+    pub fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl Display for HttpUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}{}", self.authority(), self.path)
+    }
+}
+
+impl FromStr for HttpUrl {
+    type Err = Error;
+
+    fn from_str(url: &str) -> Result<Self, Error> {
+        let rest = match url.strip_prefix("http://") {
+            Some(rest) => rest,
+            None if url.starts_with("https://") => fail!(
+                ConfigError,
+                "invalid RPC endpoint `{}` (https:// isn't supported: use the node's \
+                 http:// address)",
+                url
+            ),
+            None => fail!(
+                ConfigError,
+                "invalid RPC endpoint `{}` (expected e.g. \"http://127.0.0.1:26657\")",
+                url
+            ),
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+
+        let invalid = || {
+            format_err!(
+                ConfigError,
+                "invalid RPC endpoint `{}` (expected e.g. \"http://127.0.0.1:26657\")",
+                url
+            )
+        };
+
+        // IPv6 addresses are bracketed, e.g. `[::1]:26657`
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(bracketed) => {
+                let (host, port) = bracketed.split_once(']').ok_or_else(invalid)?;
+                (host, port.strip_prefix(':'))
+            }
+            None => match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+
+        if host.is_empty() || host.contains('@') {
+            return Err(invalid().into());
+        }
+
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid())?,
+            None => 80,
+        };
+
+        Ok(HttpUrl {
+            host: host.to_owned(),
+            port,
+            path: path.trim_end_matches('/').to_owned(),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for HttpUrl {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(|e: Error| D::Error::custom(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_urls() {
+        for (url, host, port, path, display) in &[
+            (
+                "http://127.0.0.1:26657",
+                "127.0.0.1",
+                26657,
+                "",
+                "http://127.0.0.1:26657",
+            ),
+            (
+                "http://node.example.com/rpc/",
+                "node.example.com",
+                80,
+                "/rpc",
+                "http://node.example.com:80/rpc",
+            ),
+            (
+                "http://[::1]:26657/",
+                "::1",
+                26657,
+                "",
+                "http://[::1]:26657",
+            ),
+        ] {
+            let parsed = url.parse::<HttpUrl>().unwrap();
+            assert_eq!(parsed.host, *host);
+            assert_eq!(parsed.port, *port);
+            assert_eq!(parsed.path, *path);
+            assert_eq!(parsed.to_string(), *display);
+        }
+
+        for url in &[
+            "https://127.0.0.1:26657",
+            "tcp://127.0.0.1:26657",
+            "http://",
+            "http://:26657",
+            "http://127.0.0.1:port",
+            "http://user@127.0.0.1:26657",
+            "http://[::1:26657",
+        ] {
+            assert!(url.parse::<HttpUrl>().is_err(), "{} parsed", url);
+        }
+    }
+}
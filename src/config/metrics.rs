@@ -0,0 +1,12 @@
+//! Metrics configuration
+
+use serde::Deserialize;
+use std::net::SocketAddr;
+
+/// Prometheus metrics endpoint configuration
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Address to serve the `/metrics` endpoint on, e.g. `127.0.0.1:26660`
+    pub listen_addr: SocketAddr,
+}
@@ -1,24 +1,52 @@
 //! Cryptographic service providers: signing backends
 
+#[cfg(feature = "awskms")]
+pub mod awskms;
+#[cfg(feature = "azurekv")]
+pub mod azurekv;
 #[cfg(feature = "fortanixdsm")]
 pub mod fortanixdsm;
+#[cfg(feature = "gcpkms")]
+pub mod gcpkms;
 #[cfg(feature = "ledger")]
 pub mod ledgertm;
+#[cfg(feature = "testing")]
+pub mod mock;
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
 #[cfg(feature = "softsign")]
 pub mod softsign;
+#[cfg(feature = "threshold")]
+pub mod threshold;
+#[cfg(feature = "vault")]
+pub mod vault;
 #[cfg(feature = "yubihsm")]
 pub mod yubihsm;
 
+#[cfg(feature = "awskms")]
+use self::awskms::AwsKmsConfig;
+#[cfg(feature = "azurekv")]
+use self::azurekv::AzureKvConfig;
 #[cfg(feature = "fortanixdsm")]
 use self::fortanixdsm::FortanixDsmConfig;
+#[cfg(feature = "gcpkms")]
+use self::gcpkms::GcpKmsConfig;
 #[cfg(feature = "ledger")]
 use self::ledgertm::LedgerTendermintConfig;
+#[cfg(feature = "testing")]
+use self::mock::MockConfig;
+#[cfg(feature = "pkcs11")]
+use self::pkcs11::Pkcs11Config;
 #[cfg(feature = "softsign")]
 use self::softsign::SoftsignConfig;
+#[cfg(feature = "threshold")]
+use self::threshold::ThresholdConfig;
+#[cfg(feature = "vault")]
+use self::vault::VaultConfig;
 #[cfg(feature = "yubihsm")]
 use self::yubihsm::YubihsmConfig;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
 /// Provider configuration
@@ -44,11 +72,46 @@ pub struct ProviderConfig {
     #[cfg(feature = "fortanixdsm")]
     #[serde(default)]
     pub fortanixdsm: Vec<FortanixDsmConfig>,
+
+    /// AWS KMS provider configurations
+    #[cfg(feature = "awskms")]
+    #[serde(default)]
+    pub awskms: Vec<AwsKmsConfig>,
+
+    /// Azure Key Vault / Managed HSM provider configurations
+    #[cfg(feature = "azurekv")]
+    #[serde(default)]
+    pub azurekv: Vec<AzureKvConfig>,
+
+    /// Google Cloud KMS provider configurations
+    #[cfg(feature = "gcpkms")]
+    #[serde(default)]
+    pub gcpkms: Vec<GcpKmsConfig>,
+
+    /// HashiCorp Vault Transit provider configurations
+    #[cfg(feature = "vault")]
+    #[serde(default)]
+    pub vault: Vec<VaultConfig>,
+
+    /// PKCS#11 provider configurations
+    #[cfg(feature = "pkcs11")]
+    #[serde(default)]
+    pub pkcs11: Vec<Pkcs11Config>,
+
+    /// FROST threshold signer configurations (experimental)
+    #[cfg(feature = "threshold")]
+    #[serde(default)]
+    pub threshold: Vec<ThresholdConfig>,
+
+    /// Mock signer configurations (for testing only)
+    #[cfg(feature = "testing")]
+    #[serde(default)]
+    pub mock: Vec<MockConfig>,
 }
 
 /// Types of cryptographic keys
 // TODO(tarcieri): move this into a provider-agnostic module
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum KeyType {
     /// Account keys
     #[serde(rename = "account")]
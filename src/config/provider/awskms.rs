@@ -0,0 +1,39 @@
+//! Configuration for the AWS KMS backend
+
+use super::KeyType;
+use crate::chain;
+use serde::Deserialize;
+
+/// The (optional) `[providers.awskms]` config section
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AwsKmsConfig {
+    /// AWS region the keys live in, e.g. `us-east-1`
+    pub region: String,
+
+    /// Override the KMS endpoint (e.g. for VPC endpoints). Defaults to
+    /// `https://kms.<region>.amazonaws.com`
+    pub endpoint: Option<String>,
+
+    /// Maximum number of times to retry a throttled KMS request
+    pub max_retries: Option<u32>,
+
+    /// List of signing keys
+    #[serde(default)]
+    pub signing_keys: Vec<SigningKeyConfig>,
+}
+
+/// Signing key configuration
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SigningKeyConfig {
+    /// Chains this signing key is authorized to be used from
+    pub chain_ids: Vec<chain::Id>,
+
+    /// ARN (or ID/alias) of an `ECC_SECG_P256K1` KMS key
+    pub key_id: String,
+
+    /// Type of key
+    #[serde(default, rename = "type")]
+    pub key_type: KeyType,
+}
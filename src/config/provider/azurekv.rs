@@ -0,0 +1,93 @@
+//! Configuration for the Azure Key Vault / Managed HSM backend
+
+use super::KeyType;
+use crate::chain;
+use serde::Deserialize;
+
+/// The (optional) `[providers.azurekv]` config section
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AzureKvConfig {
+    /// URL of the key vault or Managed HSM, e.g.
+    /// `https://example.vault.azure.net` or
+    /// `https://example.managedhsm.azure.net`
+    pub vault_url: String,
+
+    /// How to authenticate to Azure Active Directory
+    pub auth: AzureKvAuth,
+
+    /// Azure AD authority host used for client secret authentication
+    /// (default `https://login.microsoftonline.com`)
+    pub authority_host: Option<String>,
+
+    /// Maximum number of times to retry a throttled or failed request
+    pub max_retries: Option<u32>,
+
+    /// List of signing keys
+    #[serde(default)]
+    pub signing_keys: Vec<SigningKeyConfig>,
+}
+
+/// Azure Active Directory authentication methods
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub enum AzureKvAuth {
+    /// Service principal with a client secret
+    ClientSecret {
+        /// Directory (tenant) ID
+        tenant_id: String,
+
+        /// Application (client) ID
+        client_id: String,
+
+        /// Client secret
+        client_secret: String,
+    },
+
+    /// Managed identity of the Azure VM tmkms is running on
+    ManagedIdentity {
+        /// Client ID of a user-assigned identity (if not using the
+        /// system-assigned one)
+        client_id: Option<String>,
+    },
+}
+
+impl std::fmt::Debug for AzureKvAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't leak credentials into logs
+        match self {
+            AzureKvAuth::ClientSecret {
+                tenant_id,
+                client_id,
+                ..
+            } => f
+                .debug_struct("ClientSecret")
+                .field("tenant_id", tenant_id)
+                .field("client_id", client_id)
+                .finish_non_exhaustive(),
+            AzureKvAuth::ManagedIdentity { client_id } => f
+                .debug_struct("ManagedIdentity")
+                .field("client_id", client_id)
+                .finish(),
+        }
+    }
+}
+
+/// Signing key configuration
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SigningKeyConfig {
+    /// Chains this signing key is authorized to be used from
+    pub chain_ids: Vec<chain::Id>,
+
+    /// Name of a `P-256K` (secp256k1) key in the vault
+    pub key_name: String,
+
+    /// Version of the key to sign with (default: the current version when
+    /// tmkms starts)
+    pub key_version: Option<String>,
+
+    /// Type of key
+    #[serde(default, rename = "type")]
+    pub key_type: KeyType,
+}
@@ -4,6 +4,7 @@ use super::KeyType;
 use crate::chain;
 use sdkms::api_model::SobjectDescriptor;
 use serde::Deserialize;
+use std::fmt;
 use uuid::Uuid;
 
 /// The (optional) `[providers.fortanixdsm]` config section
@@ -35,6 +36,17 @@ pub struct SigningKeyConfig {
     /// Type of key
     #[serde(default, rename = "type")]
     pub key_type: KeyType,
+
+    /// Is this the active consensus key of its chains (default true)?
+    /// Inactive keys are kept on standby for `tmkms rotate`. Ignored if the
+    /// chain selects its active key with `active_key`.
+    #[serde(default = "active_default")]
+    pub active: bool,
+}
+
+/// Keys are active unless configured otherwise
+fn active_default() -> bool {
+    true
 }
 
 /// A key (i.e. security object) stored in Fortanix DSM
@@ -48,6 +60,15 @@ pub enum KeyDescriptor {
     KeyName(String),
 }
 
+impl fmt::Display for KeyDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyDescriptor::KeyId(id) => id.fmt(f),
+            KeyDescriptor::KeyName(name) => name.fmt(f),
+        }
+    }
+}
+
 impl From<KeyDescriptor> for SobjectDescriptor {
     fn from(x: KeyDescriptor) -> Self {
         match x {
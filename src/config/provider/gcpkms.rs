@@ -0,0 +1,37 @@
+//! Configuration for the Google Cloud KMS backend
+
+use super::KeyType;
+use crate::chain;
+use serde::Deserialize;
+
+/// The (optional) `[providers.gcpkms]` config section
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GcpKmsConfig {
+    /// Override the Cloud KMS endpoint (e.g. for Private Service Connect).
+    /// Defaults to `https://cloudkms.googleapis.com`
+    pub endpoint: Option<String>,
+
+    /// Maximum number of times to retry a throttled or failed request
+    pub max_retries: Option<u32>,
+
+    /// List of signing keys
+    #[serde(default)]
+    pub signing_keys: Vec<SigningKeyConfig>,
+}
+
+/// Signing key configuration
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SigningKeyConfig {
+    /// Chains this signing key is authorized to be used from
+    pub chain_ids: Vec<chain::Id>,
+
+    /// Resource name of an `EC_SIGN_SECP256K1_SHA256` key version, i.e.
+    /// `projects/*/locations/*/keyRings/*/cryptoKeys/*/cryptoKeyVersions/*`
+    pub key_name: String,
+
+    /// Type of key
+    #[serde(default, rename = "type")]
+    pub key_type: KeyType,
+}
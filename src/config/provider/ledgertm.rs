@@ -9,4 +9,37 @@ use serde::Deserialize;
 pub struct LedgerTendermintConfig {
     /// Chains this signing key is authorized to be used from
     pub chain_ids: Vec<chain::Id>,
+
+    /// Minimum version of the Tendermint Validator app (e.g. "0.4.0")
+    pub min_app_version: Option<String>,
+
+    /// Transport to the Ledger: `usb` (default), or `tcp` for the Speculos
+    /// emulator
+    #[serde(default)]
+    pub transport: LedgerTransport,
+
+    /// Host of the Speculos APDU server (`tcp` transport only, defaults to
+    /// `127.0.0.1`)
+    pub host: Option<String>,
+
+    /// Port of the Speculos APDU server (`tcp` transport only, defaults to
+    /// 9999)
+    pub port: Option<u16>,
+}
+
+/// Transport to the Ledger
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LedgerTransport {
+    /// Ledger device plugged in over USB
+    Usb,
+
+    /// Speculos emulator, speaking its APDU-over-TCP protocol
+    Tcp,
+}
+
+impl Default for LedgerTransport {
+    fn default() -> Self {
+        LedgerTransport::Usb
+    }
 }
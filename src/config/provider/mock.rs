@@ -0,0 +1,41 @@
+//! Configuration for the mock signing provider (for testing only)
+
+use crate::chain;
+use serde::Deserialize;
+
+/// Seed the mock provider's key is derived from unless one is configured
+pub const DEFAULT_SEED: &str = "tmkms mock provider";
+
+/// Mock signing provider configuration
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MockConfig {
+    /// Chains this signing key is authorized to be used from
+    pub chain_ids: Vec<chain::Id>,
+
+    /// Label of the `[[validator]]` this key signs for, if the KMS signs for
+    /// several validators of its chains (default: their unlabelled
+    /// validator)
+    pub validator: Option<String>,
+
+    /// Seed the Ed25519 consensus key and the sequence of injected errors are
+    /// derived from (default: [`DEFAULT_SEED`])
+    pub seed: Option<String>,
+
+    /// Artificial latency added to each signing operation in milliseconds
+    /// (default 0)
+    #[serde(default)]
+    pub latency_ms: u64,
+
+    /// Probability from 0.0 to 1.0 that a signing operation fails, as a
+    /// transient HSM error would (default 0.0)
+    #[serde(default)]
+    pub error_probability: f64,
+}
+
+impl MockConfig {
+    /// Get the seed the key is derived from
+    pub fn seed(&self) -> &str {
+        self.seed.as_deref().unwrap_or(DEFAULT_SEED)
+    }
+}
@@ -0,0 +1,94 @@
+//! Configuration for the generic PKCS#11 signer
+
+use crate::{
+    chain,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use serde::Deserialize;
+use std::{env, fs, os::raw::c_ulong, path::PathBuf};
+use zeroize::Zeroizing;
+
+/// The (optional) `[providers.pkcs11]` config section
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Pkcs11Config {
+    /// Path to the PKCS#11 module (shared library) supplied by the HSM vendor
+    pub module: PathBuf,
+
+    /// ID of the slot containing the token (either this or `token_label` is
+    /// required)
+    pub slot: Option<c_ulong>,
+
+    /// Label of the token (either this or `slot` is required)
+    pub token_label: Option<String>,
+
+    /// Where to read the user PIN from
+    pub pin: PinSource,
+
+    /// List of signing keys on this token
+    #[serde(default)]
+    pub signing_keys: Vec<SigningKeyConfig>,
+}
+
+impl Pkcs11Config {
+    /// Describe which token this configuration refers to (for error messages)
+    pub fn token_description(&self) -> String {
+        match (&self.token_label, self.slot) {
+            (Some(label), _) => format!("token `{}`", label),
+            (None, Some(slot)) => format!("slot {}", slot),
+            (None, None) => "<unspecified token>".to_owned(),
+        }
+    }
+}
+
+/// Sources for the PKCS#11 user PIN
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub enum PinSource {
+    /// Read the PIN from the given environment variable
+    Env(String),
+
+    /// Read the PIN from the given file (trailing newlines are ignored)
+    File(PathBuf),
+}
+
+impl PinSource {
+    /// Load the PIN
+    pub fn load(&self) -> Result<Zeroizing<String>, Error> {
+        let pin = match self {
+            PinSource::Env(var) => env::var(var).map_err(|e| {
+                format_err!(
+                    ConfigError,
+                    "couldn't read PKCS#11 PIN from ${}: {}",
+                    var,
+                    e
+                )
+            })?,
+            PinSource::File(path) => fs::read_to_string(path).map_err(|e| {
+                format_err!(
+                    ConfigError,
+                    "couldn't read PKCS#11 PIN from {}: {}",
+                    path.display(),
+                    e
+                )
+            })?,
+        };
+
+        let pin = Zeroizing::new(pin);
+        Ok(Zeroizing::new(
+            pin.trim_end_matches(&['\r', '\n'][..]).to_owned(),
+        ))
+    }
+}
+
+/// Signing key configuration
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SigningKeyConfig {
+    /// Chains this signing key is authorized to be used from
+    pub chain_ids: Vec<chain::Id>,
+
+    /// Label (`CKA_LABEL`) of the Ed25519 private key object
+    pub key_label: String,
+}
@@ -6,7 +6,9 @@ use crate::{
     error::{Error, ErrorKind::ConfigError},
     prelude::*,
 };
-use serde::Deserialize;
+
+pub use crate::key_utils::KeyFormat;
+use serde::{de::Error as _, Deserialize, Deserializer};
 use std::{
     path::{Path, PathBuf},
     str::FromStr,
@@ -19,19 +21,49 @@ pub struct SoftsignConfig {
     /// Chains this signing key is authorized to be used from
     pub chain_ids: Vec<chain::Id>,
 
+    /// Label of the `[[validator]]` this consensus key signs for, if the KMS
+    /// signs for several validators of its chains (default: their
+    /// unlabelled validator)
+    pub validator: Option<String>,
+
     /// Type of key (account vs consensus, default consensus)
     #[serde(default)]
     pub key_type: KeyType,
 
+    /// Signature algorithm of the key (default `ed25519` for consensus
+    /// keys, and `secp256k1` for account keys)
+    pub key_algorithm: Option<KeyAlgorithm>,
+
     /// Private key file format
     pub key_format: Option<KeyFormat>,
 
-    /// Path to a file containing a cryptographic key
+    /// Path to a file containing a cryptographic key, or `env:NAME` to read
+    /// it from an environment variable, or `fd:N` to read it from an
+    /// inherited file descriptor (see [`SecretSource`])
+    ///
+    /// [`SecretSource`]: crate::key_utils::SecretSource
     // TODO: use `abscissa_core::Secret` to wrap this `PathBuf`
     pub path: SoftPrivateKey,
+
+    /// Path to a file containing the passphrase for an encrypted key.
+    ///
+    /// If unset, the `TMKMS_SOFTSIGN_PASSPHRASE` environment variable is
+    /// used, falling back to prompting on the terminal.
+    pub passphrase_file: Option<PathBuf>,
+}
+
+impl SoftsignConfig {
+    /// Get the signature algorithm of the configured key
+    pub fn key_algorithm(&self) -> KeyAlgorithm {
+        self.key_algorithm.unwrap_or(match self.key_type {
+            KeyType::Account => KeyAlgorithm::Secp256k1,
+            KeyType::Consensus => KeyAlgorithm::Ed25519,
+        })
+    }
 }
 
-/// Software-backed private key (stored in a file)
+/// Software-backed private key (stored in a file, an environment variable,
+/// or an inherited file descriptor)
 #[derive(Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct SoftPrivateKey(PathBuf);
@@ -43,34 +75,39 @@ impl AsRef<Path> for SoftPrivateKey {
     }
 }
 
-/// Private key format
-#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
-pub enum KeyFormat {
-    /// Base64-encoded
-    #[serde(rename = "base64")]
-    Base64,
+/// Signature algorithm of a key
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum KeyAlgorithm {
+    /// Ed25519
+    Ed25519,
 
-    /// JSON
-    #[serde(rename = "json")]
-    Json,
+    /// ECDSA/secp256k1
+    Secp256k1,
 }
 
-impl Default for KeyFormat {
-    fn default() -> Self {
-        KeyFormat::Base64
-    }
-}
-
-impl FromStr for KeyFormat {
+impl FromStr for KeyAlgorithm {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Error> {
-        let format = match s {
-            "base64" => KeyFormat::Base64,
-            "json" => KeyFormat::Json,
-            other => fail!(ConfigError, "invalid key format: {}", other),
+        let algorithm = match s {
+            "ed25519" => KeyAlgorithm::Ed25519,
+            "secp256k1" => KeyAlgorithm::Secp256k1,
+            "sr25519" => fail!(
+                ConfigError,
+                "sr25519 keys aren't supported: the privval protocol's public key can't \
+                 carry them, so no validator could be served with one"
+            ),
+            other => fail!(ConfigError, "invalid key algorithm: {}", other),
         };
 
-        Ok(format)
+        Ok(algorithm)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyAlgorithm {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(|e| D::Error::custom(format!("{}", e)))
     }
 }
@@ -0,0 +1,45 @@
+//! Configuration for the (experimental) FROST threshold signer
+
+use crate::chain;
+use serde::Deserialize;
+use std::path::PathBuf;
+use tendermint_config::net;
+
+/// The (optional) `[providers.threshold]` config section
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ThresholdConfig {
+    /// Chains this signing key is authorized to be used from
+    pub chain_ids: Vec<chain::Id>,
+
+    /// Path to this cosigner's key share (generated by
+    /// `tmkms threshold keygen`)
+    pub share: PathBuf,
+
+    /// Path to this cosigner's Secret Connection identity key, used to
+    /// authenticate to other cosigners
+    pub identity_key: PathBuf,
+
+    /// Address to listen for connections from other cosigners on, e.g.
+    /// `tcp://0.0.0.0:26670`
+    pub listen_addr: net::Address,
+
+    /// Other cosigners
+    pub peers: Vec<PeerConfig>,
+
+    /// Timeout for each round of a signing operation in milliseconds
+    /// (default 1000)
+    pub timeout_ms: Option<u64>,
+}
+
+/// Another cosigner
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PeerConfig {
+    /// Identifier of the cosigner's key share
+    pub id: u16,
+
+    /// Address of the cosigner, including its node ID, e.g.
+    /// `tcp://f88883b673fc69d7869cab098de3bafc2ff76eb8@10.0.0.2:26670`
+    pub addr: net::Address,
+}
@@ -0,0 +1,69 @@
+//! Configuration for the HashiCorp Vault Transit backend
+
+use crate::chain;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// The (optional) `[providers.vault]` config section
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct VaultConfig {
+    /// Vault API endpoint, e.g. `https://vault.example.com:8200`
+    pub api_endpoint: String,
+
+    /// How to authenticate to Vault
+    pub auth: VaultAuth,
+
+    /// PEM file containing the CA certificate(s) to trust for the Vault
+    /// server's TLS certificate (instead of the Web PKI roots)
+    pub ca_bundle: Option<PathBuf>,
+
+    /// Path the Transit secrets engine is mounted at (default `transit`)
+    pub mount: Option<String>,
+
+    /// List of signing keys
+    #[serde(default)]
+    pub signing_keys: Vec<SigningKeyConfig>,
+}
+
+/// Vault authentication methods
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub enum VaultAuth {
+    /// Token authentication
+    Token(String),
+
+    /// AppRole authentication
+    #[serde(rename = "approle")]
+    AppRole {
+        /// Role ID
+        role_id: String,
+
+        /// Secret ID
+        secret_id: String,
+    },
+}
+
+impl std::fmt::Debug for VaultAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't leak credentials into logs
+        match self {
+            VaultAuth::Token(_) => f.write_str("Token(...)"),
+            VaultAuth::AppRole { role_id, .. } => f
+                .debug_struct("AppRole")
+                .field("role_id", role_id)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// Signing key configuration
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SigningKeyConfig {
+    /// Chains this signing key is authorized to be used from
+    pub chain_ids: Vec<chain::Id>,
+
+    /// Name of an `ed25519` key in the Transit secrets engine
+    pub key_name: String,
+}
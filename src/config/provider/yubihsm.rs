@@ -26,6 +26,15 @@ pub struct YubihsmConfig {
     /// Serial number of the YubiHSM to connect to
     pub serial_number: Option<String>,
 
+    /// Number of times to retry a signature which failed with a transient
+    /// error (e.g. the connector or device being briefly unavailable)
+    #[serde(default = "sign_retries_default")]
+    pub sign_retries: u32,
+
+    /// Delay between retries of a signature (in milliseconds)
+    #[serde(default = "sign_retry_delay_ms_default")]
+    pub sign_retry_delay_ms: u64,
+
     /// Configuration for `yubihsm-connector` compatible HTTP server.
     #[cfg(feature = "yubihsm-server")]
     pub connector_server: Option<ConnectorServerConfig>,
@@ -129,6 +138,16 @@ fn usb_timeout_ms_default() -> u64 {
     1000
 }
 
+/// Default value for `YubihsmConfig::sign_retries`
+fn sign_retries_default() -> u32 {
+    2
+}
+
+/// Default value for `YubihsmConfig::sign_retry_delay_ms`
+fn sign_retry_delay_ms_default() -> u64 {
+    50
+}
+
 /// Configuration for `yubihsm-connector` compatible service
 #[cfg(feature = "yubihsm-server")]
 #[derive(Clone, Debug, Deserialize)]
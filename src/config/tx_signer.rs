@@ -1,10 +1,10 @@
 //! Transaction signer configuration
 
+use crate::chain;
 use hyper::http::Uri;
 use serde::{de, Deserialize};
 use std::path::PathBuf;
 use stdtx::{amino::TypeName, Address};
-use tendermint::chain;
 use tendermint_config::net;
 
 /// Transaction signer (`[tx_signer]`) configuration
@@ -0,0 +1,22 @@
+//! Transaction signing socket configuration
+
+use crate::chain;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Transaction signing socket (`[tx_signer_socket]`) configuration
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TxSignerSocketConfig {
+    /// Path of the Unix socket accepting transactions to sign (only
+    /// accessible to the user tmkms runs as)
+    pub path: PathBuf,
+
+    /// Chains whose transactions may be signed. Each must have exactly one
+    /// account key, distinct from its consensus key.
+    pub chain_ids: Vec<chain::Id>,
+
+    /// Type URLs of the messages a transaction may contain, e.g.
+    /// `/cosmos.slashing.v1beta1.MsgUnjail`
+    pub msg_types: Vec<String>,
+}
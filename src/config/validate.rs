@@ -0,0 +1,920 @@
+//! Validation of configuration files, reporting every problem found (rather
+//! than only the first) along with the TOML path of the offending key
+
+use super::{
+    chain::{BlockedHeights, StateBackend},
+    provider::ProviderConfig,
+    KmsConfig, ProtocolVersion, ValidatorAddr, ValidatorConfig,
+};
+use crate::{chain, connection::listener, error::Error, key_utils, privileges::Privileges};
+use serde::de::DeserializeOwned;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+#[cfg(any(
+    feature = "yubihsm",
+    feature = "fortanixdsm",
+    feature = "awskms",
+    feature = "azurekv",
+    feature = "gcpkms",
+    feature = "softsign"
+))]
+use super::provider::KeyType;
+
+/// Problem found in a configuration file
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    /// TOML path of the offending key, e.g. `validator[0].chain_id` (empty if
+    /// the problem isn't specific to a key)
+    pub path: String,
+
+    /// Description of the problem
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Create a new diagnostic for the key at the given path
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// Validate the contents of a configuration file, returning a diagnostic for
+/// each problem found.
+///
+/// Key files are checked to exist and parse. Keys held by signing providers
+/// (e.g. HSMs) are only loaded if `online` is set.
+pub fn validate(toml_string: &str, online: bool) -> Vec<Diagnostic> {
+    let value = match toml_string.parse::<toml::Value>() {
+        Ok(value) => value,
+        Err(e) => return vec![Diagnostic::new("", format!("invalid TOML: {}", e))],
+    };
+
+    // Check fields whose parse errors serde would otherwise report without
+    // saying which section they're in
+    let mut diagnostics = vec![];
+    check_field::<chain::Id>(&value, "chain", "id", &mut diagnostics);
+    check_field::<BlockedHeights>(&value, "chain", "blocked_heights", &mut diagnostics);
+    check_field::<chain::Id>(&value, "validator", "chain_id", &mut diagnostics);
+    check_field::<Vec<chain::Id>>(&value, "validator", "chain_ids", &mut diagnostics);
+    check_field::<ValidatorAddr>(&value, "validator", "addr", &mut diagnostics);
+    check_field::<ProtocolVersion>(&value, "validator", "protocol_version", &mut diagnostics);
+
+    if !diagnostics.is_empty() {
+        return diagnostics;
+    }
+
+    let config = match toml::from_str::<KmsConfig>(toml_string) {
+        Ok(config) => config,
+        Err(e) => return vec![Diagnostic::new("", e.to_string())],
+    };
+
+    check_config(&config, &mut diagnostics);
+
+    if online && diagnostics.is_empty() {
+        check_providers_online(&config, &mut diagnostics);
+    }
+
+    diagnostics
+}
+
+/// Check each `[[section]]`'s `key` (if present) parses as a `T`
+fn check_field<T: DeserializeOwned>(
+    value: &toml::Value,
+    section: &str,
+    key: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let tables = match value.get(section).and_then(toml::Value::as_array) {
+        Some(tables) => tables,
+        None => return,
+    };
+
+    for (i, table) in tables.iter().enumerate() {
+        if let Some(field) = table.get(key) {
+            if let Err(e) = T::deserialize(field.clone()) {
+                diagnostics.push(Diagnostic::new(
+                    format!("{}[{}].{}", section, i, key),
+                    e.to_string(),
+                ));
+            }
+        }
+    }
+}
+
+/// Cross-check the sections of a parsed configuration
+fn check_config(config: &KmsConfig, diagnostics: &mut Vec<Diagnostic>) {
+    let mut chains = BTreeMap::new();
+
+    for (i, chain_config) in config.chain.iter().enumerate() {
+        if chains.insert(&chain_config.id, i).is_some() {
+            diagnostics.push(Diagnostic::new(
+                format!("chain[{}].id", i),
+                format!("duplicate chain ID `{}`", chain_config.id),
+            ));
+        }
+
+        if let Some(endpoint) = &chain_config.rpc_endpoint {
+            if endpoint.poll_interval_secs == Some(0) {
+                diagnostics.push(Diagnostic::new(
+                    format!("chain[{}].rpc_endpoint.poll_interval_secs", i),
+                    "must be at least 1 second",
+                ));
+            }
+        }
+    }
+
+    let mut aliases = BTreeMap::new();
+
+    for (i, chain_config) in config.chain.iter().enumerate() {
+        for alias in &chain_config.aliases {
+            let other = match chains.get(alias) {
+                Some(&j) => Some(&config.chain[j].id),
+                None => aliases.insert(alias, &chain_config.id),
+            };
+
+            if let Some(other) = other {
+                diagnostics.push(Diagnostic::new(
+                    format!("chain[{}].aliases", i),
+                    format!(
+                        "`{}` is already the ID or an alias of chain `{}`",
+                        alias, other
+                    ),
+                ));
+            }
+        }
+    }
+
+    let mut state_files = BTreeMap::new();
+
+    for (i, chain_config) in config.chain.iter().enumerate() {
+        if chain_config.state_backend != StateBackend::Json {
+            continue;
+        }
+
+        let state_file = chain::state_file_path(chain_config);
+
+        if let Some(other) = state_files.insert(state_file.clone(), &chain_config.id) {
+            diagnostics.push(Diagnostic::new(
+                format!("chain[{}].state_file", i),
+                format!(
+                    "`{}` is also the state file of chain `{}` (each chain needs its own)",
+                    state_file.display(),
+                    other
+                ),
+            ));
+        }
+    }
+
+    // Validators signed for under a label keep their state in their own
+    // `state_file`, which must be the same in each of their sections
+    let mut labelled_state_files = BTreeMap::new();
+
+    for (i, validator) in config.validator.iter().enumerate() {
+        let (label, state_file) = match (&validator.label, &validator.state_file) {
+            (Some(label), Some(state_file)) => (label, state_file),
+            _ => continue,
+        };
+
+        let chain_config = match chains.get(&validator.chain_id) {
+            Some(&j) => &config.chain[j],
+            None => continue,
+        };
+
+        if chain_config.state_backend != StateBackend::Json {
+            diagnostics.push(Diagnostic::new(
+                format!("validator[{}].state_file", i),
+                format!(
+                    "labelled validators require chain `{}` to use `state_backend = \"json\"`",
+                    chain_config.id
+                ),
+            ));
+            continue;
+        }
+
+        match labelled_state_files.insert((&validator.chain_id, label), state_file) {
+            Some(other) if other == state_file => continue,
+            Some(other) => {
+                diagnostics.push(Diagnostic::new(
+                    format!("validator[{}].state_file", i),
+                    format!(
+                        "validator `{}` of chain `{}` already uses state file `{}`",
+                        label,
+                        validator.chain_id,
+                        other.display()
+                    ),
+                ));
+                continue;
+            }
+            None => (),
+        }
+
+        if let Some(other) = state_files.insert(state_file.clone(), &validator.chain_id) {
+            diagnostics.push(Diagnostic::new(
+                format!("validator[{}].state_file", i),
+                format!(
+                    "`{}` is also a state file of chain `{}` (each validator needs its own)",
+                    state_file.display(),
+                    other
+                ),
+            ));
+        }
+    }
+
+    if let Err(e) = Privileges::from_config(config) {
+        diagnostics.push(Diagnostic::new("", e.to_string()));
+    }
+
+    let known_chains = || {
+        let ids = chains
+            .keys()
+            .map(|id| format!("`{}`", id))
+            .collect::<Vec<_>>();
+
+        if ids.is_empty() {
+            "no chains are configured".to_owned()
+        } else {
+            format!("configured chains: {}", ids.join(", "))
+        }
+    };
+
+    let mut capture_dirs = BTreeMap::new();
+
+    for (i, validator) in config.validator.iter().enumerate() {
+        let key = if validator.chain_ids.len() > 1 {
+            "chain_ids"
+        } else {
+            "chain_id"
+        };
+
+        for chain_id in &validator.chain_ids {
+            if !chains.contains_key(chain_id) {
+                diagnostics.push(Diagnostic::new(
+                    format!("validator[{}].{}", i, key),
+                    format!(
+                        "no [[chain]] section with ID `{}` ({})",
+                        chain_id,
+                        known_chains()
+                    ),
+                ));
+            }
+        }
+
+        if validator.addr.is_grpc() != (validator.protocol_version == ProtocolVersion::Grpc) {
+            diagnostics.push(Diagnostic::new(
+                format!("validator[{}].protocol_version", i),
+                "`grpc://` addresses require `protocol_version = \"grpc\"` (and vice versa)",
+            ));
+        }
+
+        match (&validator.addr, &validator.secret_key) {
+            (ValidatorAddr::Tcp { .. }, None) | (ValidatorAddr::TcpListen { .. }, None) => {
+                diagnostics.push(Diagnostic::new(
+                    format!("validator[{}]", i),
+                    "`secret_key` is required for `tcp://` addresses",
+                ));
+            }
+            // Ignored (with a warning) by TLS validators
+            (ValidatorAddr::Tls { .. }, Some(_)) => (),
+            // Generated by `tmkms start`
+            (_, Some(path))
+                if validator.secret_key_write_if_missing
+                    && matches!(
+                        key_utils::SecretSource::parse(path),
+                        Ok(key_utils::SecretSource::File(_))
+                    )
+                    && !path.exists() => {}
+            (_, Some(path)) => {
+                let format = validator.secret_key_format.unwrap_or_default();
+                let passphrase_file = validator.secret_key_passphrase_file.as_deref();
+
+                check_secret_key(
+                    format!("validator[{}].secret_key", i),
+                    path,
+                    passphrase_file.map(|passphrase_file| {
+                        (
+                            format!("validator[{}].secret_key_passphrase_file", i),
+                            passphrase_file,
+                        )
+                    }),
+                    || key_utils::load_identity_key(path, format, passphrase_file),
+                    diagnostics,
+                );
+            }
+            (_, None) => {
+                let options = [
+                    ("secret_key_format", validator.secret_key_format.is_some()),
+                    (
+                        "secret_key_passphrase_file",
+                        validator.secret_key_passphrase_file.is_some(),
+                    ),
+                    (
+                        "secret_key_write_if_missing",
+                        validator.secret_key_write_if_missing,
+                    ),
+                ];
+
+                for (key, _) in options.iter().filter(|(_, set)| *set) {
+                    diagnostics.push(Diagnostic::new(
+                        format!("validator[{}].{}", i, key),
+                        "only used with `secret_key`",
+                    ));
+                }
+            }
+        }
+
+        if validator.keepalive_interval_secs.is_some()
+            && !matches!(
+                validator.addr,
+                ValidatorAddr::Tcp { .. }
+                    | ValidatorAddr::TcpListen { .. }
+                    | ValidatorAddr::Tls { .. }
+            )
+        {
+            diagnostics.push(Diagnostic::new(
+                format!("validator[{}].keepalive_interval_secs", i),
+                "only used with `tcp://`, `tcp-listen://` and `tls://` addresses",
+            ));
+        }
+
+        if let Some(dir) = &validator.protocol_capture_dir {
+            if validator.addr.is_grpc() {
+                diagnostics.push(Diagnostic::new(
+                    format!("validator[{}].protocol_capture_dir", i),
+                    "`grpc://` addresses don't support protocol captures",
+                ));
+            } else if let Some(other) = capture_dirs.insert(dir, i) {
+                diagnostics.push(Diagnostic::new(
+                    format!("validator[{}].protocol_capture_dir", i),
+                    format!(
+                        "`{}` is also the capture directory of validator[{}] (each validator needs its own)",
+                        dir.display(),
+                        other
+                    ),
+                ));
+            }
+        }
+
+        check_tls(i, validator, diagnostics);
+        check_socket_options(i, validator, diagnostics);
+    }
+
+    // Each chain (or labelled validator of a chain) must have exactly one
+    // consensus key, unless one of several is selected with `active_key`
+    let mut consensus_keys: BTreeMap<(&chain::Id, Option<&str>), &str> = BTreeMap::new();
+    let has_active_key = |chain_id: &chain::Id| {
+        chains
+            .get(chain_id)
+            .map_or(false, |&i| config.chain[i].active_key.is_some())
+    };
+
+    let claims = provider_claims(&config.providers);
+
+    for claim in &claims {
+        for chain_id in claim.chain_ids {
+            if !chains.contains_key(chain_id) {
+                diagnostics.push(Diagnostic::new(
+                    &claim.path,
+                    format!(
+                        "no [[chain]] section with ID `{}` ({})",
+                        chain_id,
+                        known_chains()
+                    ),
+                ));
+            } else if claim.validator.map_or(false, |label| {
+                !config
+                    .validator
+                    .iter()
+                    .any(|v| v.serves(chain_id) && v.label.as_deref() == Some(label))
+            }) {
+                diagnostics.push(Diagnostic::new(
+                    claim.path.replace(".chain_ids", ".validator"),
+                    format!(
+                        "no [[validator]] section for chain `{}` has label `{}`",
+                        chain_id,
+                        claim.validator.unwrap_or_default()
+                    ),
+                ));
+            } else if claim.consensus {
+                match consensus_keys.insert((chain_id, claim.validator), &claim.path) {
+                    Some(other) if !has_active_key(chain_id) => {
+                        diagnostics.push(Diagnostic::new(
+                            &claim.path,
+                            format!(
+                                "{} already has a consensus key from {} (select the one to sign with using the chain's `active_key`)",
+                                match claim.validator {
+                                    Some(label) => format!("validator `{}` of chain `{}`", label, chain_id),
+                                    None => format!("chain `{}`", chain_id),
+                                },
+                                other.trim_end_matches(".chain_ids")
+                            ),
+                        ));
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    for (label, chain_config) in config.signers() {
+        if consensus_keys.contains_key(&(&chain_config.id, label)) {
+            continue;
+        }
+
+        match label {
+            Some(label) => {
+                let i = config
+                    .validator
+                    .iter()
+                    .position(|v| v.serves(&chain_config.id) && v.label.as_deref() == Some(label))
+                    .expect("labelled validator");
+
+                diagnostics.push(Diagnostic::new(
+                    format!("validator[{}].label", i),
+                    format!(
+                        "no signing provider has a consensus key for validator `{}` of chain `{}` (add `validator = \"{}\"` to a provider's key)",
+                        label, chain_config.id, label
+                    ),
+                ));
+            }
+            None => {
+                let i = chains[&chain_config.id];
+
+                diagnostics.push(Diagnostic::new(
+                    format!("chain[{}].id", i),
+                    format!(
+                        "no signing provider has a consensus key for chain `{}` (add it to a provider's `chain_ids`)",
+                        chain_config.id
+                    ),
+                ));
+            }
+        }
+    }
+
+    check_provider_files(&config.providers, diagnostics);
+}
+
+/// Chains a signing provider's key is configured for
+struct Claim<'a> {
+    /// TOML path of the key's `chain_ids`
+    path: String,
+
+    /// Chain IDs
+    chain_ids: &'a [chain::Id],
+
+    /// Is the key a consensus key? (as opposed to an account key)
+    consensus: bool,
+
+    /// Label of the validator the key signs for (if any)
+    validator: Option<&'a str>,
+}
+
+impl<'a> Claim<'a> {
+    /// Create a claim for the `chain_ids` of the section at `path`
+    fn new(path: String, chain_ids: &'a [chain::Id], consensus: bool) -> Self {
+        Self {
+            path: format!("{}.chain_ids", path),
+            chain_ids,
+            consensus,
+            validator: None,
+        }
+    }
+
+    /// Set the label of the validator the key signs for
+    fn for_validator(mut self, validator: Option<&'a str>) -> Self {
+        self.validator = validator;
+        self
+    }
+}
+
+/// Get the chains each configured key is authorized for
+#[allow(unused_mut, unused_variables)]
+fn provider_claims(providers: &ProviderConfig) -> Vec<Claim<'_>> {
+    let mut claims = vec![];
+
+    #[cfg(feature = "softsign")]
+    for (i, config) in providers.softsign.iter().enumerate() {
+        let consensus = matches!(config.key_type, KeyType::Consensus);
+        let path = format!("providers.softsign[{}]", i);
+        claims.push(
+            Claim::new(path, &config.chain_ids, consensus)
+                .for_validator(config.validator.as_deref()),
+        );
+    }
+
+    #[cfg(feature = "yubihsm")]
+    for (i, config) in providers.yubihsm.iter().enumerate() {
+        for (j, key) in config.keys.iter().enumerate() {
+            let consensus = matches!(key.key_type, KeyType::Consensus);
+            let path = format!("providers.yubihsm[{}].keys[{}]", i, j);
+            claims.push(Claim::new(path, &key.chain_ids, consensus));
+        }
+    }
+
+    #[cfg(feature = "ledger")]
+    for (i, config) in providers.ledgertm.iter().enumerate() {
+        let path = format!("providers.ledgertm[{}]", i);
+        claims.push(Claim::new(path, &config.chain_ids, true));
+    }
+
+    #[cfg(feature = "fortanixdsm")]
+    for (i, config) in providers.fortanixdsm.iter().enumerate() {
+        // Standby keys don't sign until they're rotated to
+        for (j, key) in config
+            .signing_keys
+            .iter()
+            .enumerate()
+            .filter(|(_, key)| key.active)
+        {
+            let consensus = matches!(key.key_type, KeyType::Consensus);
+            let path = format!("providers.fortanixdsm[{}].signing_keys[{}]", i, j);
+            claims.push(Claim::new(path, &key.chain_ids, consensus));
+        }
+    }
+
+    #[cfg(feature = "awskms")]
+    for (i, config) in providers.awskms.iter().enumerate() {
+        for (j, key) in config.signing_keys.iter().enumerate() {
+            let consensus = matches!(key.key_type, KeyType::Consensus);
+            let path = format!("providers.awskms[{}].signing_keys[{}]", i, j);
+            claims.push(Claim::new(path, &key.chain_ids, consensus));
+        }
+    }
+
+    #[cfg(feature = "azurekv")]
+    for (i, config) in providers.azurekv.iter().enumerate() {
+        for (j, key) in config.signing_keys.iter().enumerate() {
+            let consensus = matches!(key.key_type, KeyType::Consensus);
+            let path = format!("providers.azurekv[{}].signing_keys[{}]", i, j);
+            claims.push(Claim::new(path, &key.chain_ids, consensus));
+        }
+    }
+
+    #[cfg(feature = "gcpkms")]
+    for (i, config) in providers.gcpkms.iter().enumerate() {
+        for (j, key) in config.signing_keys.iter().enumerate() {
+            let consensus = matches!(key.key_type, KeyType::Consensus);
+            let path = format!("providers.gcpkms[{}].signing_keys[{}]", i, j);
+            claims.push(Claim::new(path, &key.chain_ids, consensus));
+        }
+    }
+
+    #[cfg(feature = "vault")]
+    for (i, config) in providers.vault.iter().enumerate() {
+        for (j, key) in config.signing_keys.iter().enumerate() {
+            let path = format!("providers.vault[{}].signing_keys[{}]", i, j);
+            claims.push(Claim::new(path, &key.chain_ids, true));
+        }
+    }
+
+    #[cfg(feature = "pkcs11")]
+    for (i, config) in providers.pkcs11.iter().enumerate() {
+        for (j, key) in config.signing_keys.iter().enumerate() {
+            let path = format!("providers.pkcs11[{}].signing_keys[{}]", i, j);
+            claims.push(Claim::new(path, &key.chain_ids, true));
+        }
+    }
+
+    #[cfg(feature = "threshold")]
+    for (i, config) in providers.threshold.iter().enumerate() {
+        let path = format!("providers.threshold[{}]", i);
+        claims.push(Claim::new(path, &config.chain_ids, true));
+    }
+
+    #[cfg(feature = "testing")]
+    for (i, config) in providers.mock.iter().enumerate() {
+        let path = format!("providers.mock[{}]", i);
+        claims.push(
+            Claim::new(path, &config.chain_ids, true).for_validator(config.validator.as_deref()),
+        );
+    }
+
+    claims
+}
+
+/// Check the files referenced by signing providers exist, and that key files
+/// parse (without decrypting encrypted keys)
+#[allow(unused_variables)]
+fn check_provider_files(providers: &ProviderConfig, diagnostics: &mut Vec<Diagnostic>) {
+    #[cfg(feature = "softsign")]
+    for (i, config) in providers.softsign.iter().enumerate() {
+        use super::provider::softsign::KeyAlgorithm;
+        use crate::keyring::providers::softsign;
+
+        let passphrase_file = config.passphrase_file.as_deref().map(|passphrase_file| {
+            (
+                format!("providers.softsign[{}].passphrase_file", i),
+                passphrase_file,
+            )
+        });
+
+        check_secret_key(
+            format!("providers.softsign[{}].path", i),
+            config.path.as_ref(),
+            passphrase_file,
+            || match config.key_algorithm() {
+                KeyAlgorithm::Ed25519 => softsign::load_ed25519_key(config).map(drop),
+                KeyAlgorithm::Secp256k1 => softsign::load_secp256k1_key(config).map(drop),
+            },
+            diagnostics,
+        );
+    }
+
+    #[cfg(feature = "pkcs11")]
+    for (i, config) in providers.pkcs11.iter().enumerate() {
+        check_exists(
+            format!("providers.pkcs11[{}].module", i),
+            &config.module,
+            diagnostics,
+        );
+    }
+
+    #[cfg(feature = "vault")]
+    for (i, config) in providers.vault.iter().enumerate() {
+        if let Some(ca_bundle) = &config.ca_bundle {
+            check_exists(
+                format!("providers.vault[{}].ca_bundle", i),
+                ca_bundle,
+                diagnostics,
+            );
+        }
+    }
+
+    #[cfg(feature = "threshold")]
+    for (i, config) in providers.threshold.iter().enumerate() {
+        check_key(
+            format!("providers.threshold[{}].share", i),
+            crate::keyring::providers::threshold::share::load(&config.share),
+            diagnostics,
+        );
+
+        check_key(
+            format!("providers.threshold[{}].identity_key", i),
+            key_utils::load_base64_ed25519_key(&config.identity_key),
+            diagnostics,
+        );
+    }
+}
+
+/// Check the key at `key_path` can be loaded with `load`, reporting problems
+/// at the given TOML path. Keys passed in the environment or an inherited
+/// file descriptor can only be read once (by `tmkms start`), so are only
+/// checked to exist, and encrypted keys aren't decrypted (their
+/// `passphrase_file`, if any, is checked to exist instead).
+#[allow(unused_variables)]
+fn check_secret_key<T>(
+    path: String,
+    key_path: &Path,
+    passphrase_file: Option<(String, &Path)>,
+    load: impl FnOnce() -> Result<T, Error>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    match key_utils::SecretSource::parse(key_path) {
+        Ok(key_utils::SecretSource::File(_)) => (),
+        Ok(key_utils::SecretSource::Env(name)) => {
+            if std::env::var_os(name).is_none() {
+                let message = format!("environment variable {} is not set", name);
+                diagnostics.push(Diagnostic::new(path, message));
+            }
+
+            return;
+        }
+        Ok(key_utils::SecretSource::Fd(fd)) => {
+            if !Path::new(&format!("/dev/fd/{}", fd)).exists() {
+                let message = format!("file descriptor {} is not open", fd);
+                diagnostics.push(Diagnostic::new(path, message));
+            }
+
+            return;
+        }
+        Err(e) => {
+            diagnostics.push(Diagnostic::new(path, e.to_string()));
+            return;
+        }
+    }
+
+    let contents = match std::fs::read_to_string(key_path) {
+        Ok(contents) => zeroize::Zeroizing::new(contents),
+        Err(e) => {
+            let message = format!("couldn't read {}: {}", key_path.display(), e);
+            diagnostics.push(Diagnostic::new(path, message));
+            return;
+        }
+    };
+
+    // Sealed keys can only be unsealed with the TPM (and only once the KMS
+    // starts, so PCR changes are reported then)
+    #[cfg(feature = "tpm")]
+    if key_utils::sealed::SealedKey::parse(&contents).is_some() {
+        let device = key_utils::sealed::tpm::DEVICE_PATH;
+
+        if !Path::new(device).exists() {
+            let message = format!("sealed key, but there's no TPM at {}", device);
+            diagnostics.push(Diagnostic::new(path, message));
+        }
+
+        return;
+    }
+
+    #[cfg(feature = "softsign")]
+    if key_utils::encrypted::EncryptedKey::parse(&contents).is_some() {
+        if let Some((passphrase_path, passphrase_file)) = passphrase_file {
+            check_exists(passphrase_path, passphrase_file, diagnostics);
+        }
+
+        return;
+    }
+
+    check_key(path, load(), diagnostics);
+}
+
+/// Report an error loading the key at `path`
+fn check_key<T>(path: String, result: Result<T, Error>, diagnostics: &mut Vec<Diagnostic>) {
+    if let Err(e) = result {
+        diagnostics.push(Diagnostic::new(path, e.to_string()));
+    }
+}
+
+/// Check the TLS options of the given validator, which are required for
+/// `tls://` addresses and `grpc://` addresses other than loopback ones, and
+/// only used by them
+fn check_tls(i: usize, validator: &ValidatorConfig, diagnostics: &mut Vec<Diagnostic>) {
+    let options = [
+        ("tls_ca", &validator.tls_ca),
+        ("tls_client_cert", &validator.tls_client_cert),
+        ("tls_client_key", &validator.tls_client_key),
+    ];
+
+    if validator.addr.is_grpc() {
+        check_grpc_tls(i, validator, &options, diagnostics);
+        return;
+    }
+
+    if !matches!(validator.addr, ValidatorAddr::Tls { .. }) {
+        for (key, _) in options.iter().filter(|(_, value)| value.is_some()) {
+            diagnostics.push(Diagnostic::new(
+                format!("validator[{}].{}", i, key),
+                "only used with `tls://` and `grpc://` addresses",
+            ));
+        }
+
+        return;
+    }
+
+    // Certificates can only be verified for hostnames
+    for addr in validator.endpoints() {
+        match addr {
+            ValidatorAddr::Tls { host, .. } if host.parse::<IpAddr>().is_ok() => {
+                diagnostics.push(Diagnostic::new(
+                    format!("validator[{}].addr", i),
+                    "`tls://` addresses must use the hostname the validator's certificate is issued for, not an IP address",
+                ));
+            }
+            _ => (),
+        }
+    }
+
+    let mut missing = false;
+
+    for (key, _) in options.iter().filter(|(_, value)| value.is_none()) {
+        missing = true;
+        diagnostics.push(Diagnostic::new(
+            format!("validator[{}].{}", i, key),
+            "required for `tls://` addresses",
+        ));
+    }
+
+    #[cfg(feature = "tls")]
+    if !missing {
+        check_key(
+            format!("validator[{}]", i),
+            crate::connection::tls::client_config(validator),
+            diagnostics,
+        );
+    }
+
+    #[cfg(not(feature = "tls"))]
+    if !missing {
+        diagnostics.push(Diagnostic::new(
+            format!("validator[{}].addr", i),
+            "TLS support not enabled (rebuild with the `tls` cargo feature)",
+        ));
+    }
+}
+
+/// Check the mutual TLS options of the given `grpc://` validator: either all
+/// or none of them, and all of them unless it's bound to a loopback address
+fn check_grpc_tls(
+    i: usize,
+    validator: &ValidatorConfig,
+    options: &[(&str, &Option<PathBuf>)],
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let missing: Vec<_> = options
+        .iter()
+        .filter(|(_, value)| value.is_none())
+        .map(|(key, _)| key)
+        .collect();
+
+    if missing.len() == options.len() {
+        if !validator.addr.is_loopback_grpc() {
+            diagnostics.push(Diagnostic::new(
+                format!("validator[{}].addr", i),
+                "`grpc://` addresses other than loopback ones require mutual TLS \
+                 (`tls_ca`, `tls_client_cert` and `tls_client_key`)",
+            ));
+        }
+
+        return;
+    }
+
+    for key in &missing {
+        diagnostics.push(Diagnostic::new(
+            format!("validator[{}].{}", i, key),
+            "required for mutual TLS on `grpc://` addresses",
+        ));
+    }
+
+    #[cfg(feature = "grpc")]
+    if missing.is_empty() {
+        check_key(
+            format!("validator[{}]", i),
+            crate::connection::grpc::server_tls_config(validator),
+            diagnostics,
+        );
+    }
+}
+
+/// Check the socket ownership and permission options of the given validator,
+/// which are only used by `unix-listen://` addresses
+fn check_socket_options(i: usize, validator: &ValidatorConfig, diagnostics: &mut Vec<Diagnostic>) {
+    let is_set = [
+        ("socket_mode", validator.socket_mode.is_some()),
+        ("socket_owner", validator.socket_owner.is_some()),
+        ("socket_group", validator.socket_group.is_some()),
+    ];
+
+    if !matches!(validator.addr, ValidatorAddr::UnixListen { .. }) {
+        for (key, _) in is_set.iter().filter(|(_, set)| *set) {
+            diagnostics.push(Diagnostic::new(
+                format!("validator[{}].{}", i, key),
+                "only used with `unix-listen://` addresses (the validator creates `unix://` sockets)",
+            ));
+        }
+
+        return;
+    }
+
+    if let Some(owner) = &validator.socket_owner {
+        check_key(
+            format!("validator[{}].socket_owner", i),
+            listener::lookup_uid(owner),
+            diagnostics,
+        );
+    }
+
+    if let Some(group) = &validator.socket_group {
+        check_key(
+            format!("validator[{}].socket_group", i),
+            listener::lookup_gid(group),
+            diagnostics,
+        );
+    }
+}
+
+/// Check the file at `file` exists
+#[cfg(any(feature = "softsign", feature = "pkcs11", feature = "vault"))]
+fn check_exists(path: String, file: &Path, diagnostics: &mut Vec<Diagnostic>) {
+    if !file.exists() {
+        let message = format!("{} does not exist", file.display());
+        diagnostics.push(Diagnostic::new(path, message));
+    }
+}
+
+/// Load the keys of every signing provider, which may require connecting to
+/// HSMs or remote key management services
+fn check_providers_online(config: &KmsConfig, diagnostics: &mut Vec<Diagnostic>) {
+    if let Err(e) = chain::load_keys(config) {
+        diagnostics.push(Diagnostic::new(
+            "providers",
+            format!("couldn't load keys: {}", e),
+        ));
+    }
+}
@@ -1,42 +1,235 @@
 //! Validator configuration
 
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use tendermint::chain;
-use tendermint_config::net;
+mod addr;
+mod proxy;
+
+pub use self::{addr::ValidatorAddr, proxy::ProxyAddr};
+use super::ConfigOrigin;
+use crate::{chain, key_utils::KeyFormat};
+use crate::{
+    error::{Error, ErrorKind::ConfigError},
+    prelude::*,
+};
+use serde::{
+    de::{self, Error as _, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    convert::TryFrom,
+    fmt::{self, Display},
+    mem,
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
 use tendermint_p2p::secret_connection;
+use toml::Spanned;
+
+/// Default maximum size of a request from a validator in bytes (1 MiB)
+pub const DEFAULT_MAX_MSG_SIZE: usize = 1024 * 1024;
 
 /// Validator configuration
-#[derive(Clone, Debug, Deserialize, Serialize)]
-#[serde(deny_unknown_fields)]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "ValidatorToml")]
 pub struct ValidatorConfig {
-    /// Address of the validator (`tcp://` or `unix://`)
-    pub addr: net::Address,
+    /// Address of the validator (`tcp://`, `tls://`, `unix://`, `vsock://`, or
+    /// `grpc://`). If `addr` is configured as a list, this is its first entry.
+    pub addr: ValidatorAddr,
 
-    /// Chain ID of the Tendermint network this validator is part of
+    /// Further addresses to fail over to, in order, when the connection to
+    /// the current one is lost (the rest of `addr` if it's a list, e.g. of
+    /// several sentries' privval endpoints)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failover_addrs: Vec<ValidatorAddr>,
+
+    /// Chain ID of the Tendermint network this validator is part of (the
+    /// first of `chain_ids`), used for requests which don't include one
+    #[serde(skip_serializing)]
     pub chain_id: chain::Id,
 
+    /// Chain IDs of all networks served over this validator's connection.
+    ///
+    /// Configured as either `chain_id = "..."` or `chain_ids = [...]`, the
+    /// latter for privval endpoints which multiplex requests for several
+    /// chains. Each request is signed with the key and consensus state of
+    /// the chain it's for.
+    pub chain_ids: Vec<chain::Id>,
+
+    /// Label distinguishing this validator from others of the same chain
+    /// signed for by the KMS (e.g. on a devnet), which have their own keys
+    /// (from providers with a matching `validator` label) and consensus
+    /// state (in `state_file`). Validators without a label share their
+    /// chain's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    /// Path to the `priv_validator_state.json` file of a labelled validator
+    /// (required with `label`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_file: Option<PathBuf>,
+
     /// Automatically reconnect on error? (default: true)
     #[serde(default = "reconnect_default")]
     pub reconnect: bool,
 
-    /// Optional timeout value in seconds
+    /// Delay (in seconds) before the first reconnect attempt, doubled after
+    /// each consecutive failed attempt (default: 1).
+    ///
+    /// If none of the `reconnect_*` options are set, reconnects are attempted
+    /// every second indefinitely without backoff.
+    pub reconnect_initial_delay: Option<u64>,
+
+    /// Maximum delay (in seconds) between reconnect attempts (default: 60)
+    pub reconnect_max_delay: Option<u64>,
+
+    /// Maximum number of consecutive reconnect attempts before giving up
+    /// (default: 0, i.e. unlimited)
+    pub reconnect_max_attempts: Option<u32>,
+
+    /// Read/write timeout in seconds (default 10). A validator which doesn't
+    /// send a request (or ping) within this time is treated as failed and
+    /// reconnected to. May also be given as `timeout_secs`.
+    #[serde(alias = "timeout_secs")]
     pub timeout: Option<u16>,
 
-    /// Path to our Ed25519 identity key (if applicable)
+    /// Enable TCP keepalive on connections to this validator, probing the
+    /// connection after it's been idle this many seconds (and every this
+    /// many seconds thereafter) so connections silently dropped by
+    /// firewalls or NATs are detected and reconnected (default: disabled)
+    pub keepalive_interval_secs: Option<u16>,
+
+    /// Maximum size of a request in bytes (default 1 MiB). Requests declaring
+    /// a larger length are rejected and the connection dropped.
+    pub max_msg_size: Option<usize>,
+
+    /// SOCKS5 proxy to connect to `tcp://` validators through
+    /// (`socks5://[user:pass@]host:port`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<ProxyAddr>,
+
+    /// Have the proxy resolve the validator's host name, rather than
+    /// resolving it locally (default: false)
+    #[serde(default)]
+    pub resolve_via_proxy: bool,
+
+    /// Path to our Ed25519 identity key (if applicable), or `env:NAME` to
+    /// read it from an environment variable, or `fd:N` to read it from an
+    /// inherited file descriptor (see [`SecretSource`])
+    ///
+    /// [`SecretSource`]: crate::key_utils::SecretSource
     pub secret_key: Option<PathBuf>,
 
+    /// Format of `secret_key` (default: `base64`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_key_format: Option<KeyFormat>,
+
+    /// Path to a file containing the passphrase `secret_key` is encrypted
+    /// under (if it is). If unset, the `TMKMS_SOFTSIGN_PASSPHRASE`
+    /// environment variable is used, falling back to prompting on the
+    /// terminal.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_key_passphrase_file: Option<PathBuf>,
+
+    /// Generate `secret_key` (with permissions 0600) when `tmkms start`
+    /// finds no file there, rather than failing (default: false)
+    #[serde(default)]
+    pub secret_key_write_if_missing: bool,
+
+    /// Path to the PEM-encoded certificate (chain) presented to `tls://`
+    /// validators, or to clients of a `grpc://` listener
+    pub tls_client_cert: Option<PathBuf>,
+
+    /// Path to the PEM-encoded private key of `tls_client_cert`
+    pub tls_client_key: Option<PathBuf>,
+
+    /// Path to the PEM-encoded CA certificate(s) `tls://` validators' (or
+    /// `grpc://` clients') certificates must be issued by
+    pub tls_ca: Option<PathBuf>,
+
+    /// Permissions of the socket created for `unix-listen://` addresses
+    /// (e.g. `"0660"`)
+    pub socket_mode: Option<SocketMode>,
+
+    /// User (name or uid) to own the socket created for `unix-listen://`
+    /// addresses
+    pub socket_owner: Option<String>,
+
+    /// Group (name or gid) to own the socket created for `unix-listen://`
+    /// addresses
+    pub socket_group: Option<String>,
+
+    /// What to do if the validator's peer ID doesn't match the one in `addr`
+    /// (default: `enforce`)
+    #[serde(default)]
+    pub peer_id_verification: PeerIdVerification,
+
+    /// Which requests the KMS signs for this validator (default: `full`).
+    /// `voter` refuses proposals, e.g. for a backup signer which only keeps
+    /// votes flowing if the primary dies.
+    #[serde(default)]
+    pub role: ValidatorRole,
+
     /// Height at which to stop signing
     pub max_height: Option<tendermint::block::Height>,
 
+    /// Where `max_height` is set in the configuration file
+    #[serde(skip_serializing)]
+    pub max_height_origin: Option<ConfigOrigin>,
+
+    /// Height below which to refuse signing (e.g. the initial height of a
+    /// chain restarted from an export), even if the consensus state is lost
+    pub min_height: Option<tendermint::block::Height>,
+
+    /// Maximum number of signing requests per second accepted from this
+    /// validator, with bursts of up to this many (unlimited if absent).
+    /// Requests over the limit are refused without reaching the signing
+    /// provider.
+    pub max_requests_per_second: Option<u32>,
+
+    /// Drop the connection after this many consecutive requests are refused
+    /// for exceeding `max_requests_per_second` (default: never)
+    pub max_rate_limit_violations: Option<u32>,
+
+    /// Maximum number of requests from this validator queued at once
+    /// (default 1). Requests are handled strictly in the order they arrived,
+    /// and any arriving while the queue is full are refused.
+    pub request_queue_depth: Option<usize>,
+
+    /// Refuse signing requests which haven't been signed within this many
+    /// milliseconds of arriving (e.g. after waiting in the queue), rather
+    /// than signing them late (default: no deadline)
+    pub request_deadline_ms: Option<u64>,
+
+    /// Directory to write a capture of each request received from (and
+    /// response sent to) this validator to, for replaying with `tmkms
+    /// replay` (default: disabled)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol_capture_dir: Option<PathBuf>,
+
     /// Version of Secret Connection protocol to use when connecting
     pub protocol_version: ProtocolVersion,
+
+    /// Longest chain ID accepted for this validator (defaults to the limit
+    /// of its protocol version)
+    pub max_chain_id_length: Option<usize>,
 }
 
 /// Protocol version (based on the Tendermint version)
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 #[allow(non_camel_case_types)]
 pub enum ProtocolVersion {
+    /// CometBFT `PrivValidatorAPI` gRPC service
+    #[serde(rename = "grpc")]
+    Grpc,
+
+    /// CometBFT v0.38 (vote extensions)
+    #[serde(rename = "v0.38")]
+    V0_38,
+
+    /// Tendermint v0.37 (and CometBFT v0.37)
+    #[serde(rename = "v0.37")]
+    V0_37,
+
     /// Tendermint v0.34
     #[serde(rename = "v0.34")]
     V0_34,
@@ -50,23 +243,498 @@ pub enum ProtocolVersion {
     Legacy,
 }
 
+/// Handling of validators whose peer ID doesn't match the configured one
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum PeerIdVerification {
+    /// Refuse the connection
+    #[serde(rename = "enforce")]
+    Enforce,
+
+    /// Log a warning and carry on (for lab environments only)
+    #[serde(rename = "warn")]
+    Warn,
+}
+
+impl Default for PeerIdVerification {
+    fn default() -> Self {
+        PeerIdVerification::Enforce
+    }
+}
+
+/// Which requests the KMS signs for a validator
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ValidatorRole {
+    /// Sign votes only, refusing proposals
+    #[serde(rename = "voter")]
+    Voter,
+
+    /// Sign votes and proposals
+    #[serde(rename = "full")]
+    Full,
+}
+
+impl ValidatorRole {
+    /// Get the name of this role, as in the configuration file
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ValidatorRole::Voter => "voter",
+            ValidatorRole::Full => "full",
+        }
+    }
+
+    /// May proposals be signed?
+    pub fn signs_proposals(self) -> bool {
+        self == ValidatorRole::Full
+    }
+}
+
+impl Default for ValidatorRole {
+    fn default() -> Self {
+        ValidatorRole::Full
+    }
+}
+
+impl Display for ValidatorRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Permission bits of a UNIX domain socket, written in octal (e.g. `"0660"`)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SocketMode(u32);
+
+impl SocketMode {
+    /// Get the permission bits
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl Display for SocketMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04o}", self.0)
+    }
+}
+
+impl FromStr for SocketMode {
+    type Err = Error;
+
+    fn from_str(mode: &str) -> Result<Self, Error> {
+        match u32::from_str_radix(mode, 8) {
+            Ok(bits) if !mode.is_empty() && bits <= 0o777 => Ok(SocketMode(bits)),
+            _ => fail!(
+                ConfigError,
+                "invalid socket mode `{}` (expected octal permissions, e.g. \"0660\")",
+                mode
+            ),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SocketMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(|e| D::Error::custom(format!("{}", e)))
+    }
+}
+
+impl Serialize for SocketMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl ValidatorConfig {
+    /// Is this validator's connection used for the given chain?
+    pub fn serves(&self, chain_id: &chain::Id) -> bool {
+        self.chain_ids.contains(chain_id)
+    }
+
+    /// Get the maximum size of a request in bytes
+    pub fn max_msg_size(&self) -> usize {
+        self.max_msg_size.unwrap_or(DEFAULT_MAX_MSG_SIZE)
+    }
+
+    /// Get the time within which signing requests must be signed (if any)
+    pub fn request_deadline(&self) -> Option<Duration> {
+        self.request_deadline_ms.map(Duration::from_millis)
+    }
+
+    /// Get all of the validator's addresses, in failover order
+    pub fn endpoints(&self) -> Vec<ValidatorAddr> {
+        let mut endpoints = vec![self.addr.clone()];
+        endpoints.extend(self.failover_addrs.iter().cloned());
+        endpoints
+    }
+
+    /// Get the configuration for a session with the given one of the
+    /// validator's addresses
+    pub fn with_endpoint(&self, addr: &ValidatorAddr) -> Self {
+        Self {
+            addr: addr.clone(),
+            failover_addrs: vec![],
+            ..self.clone()
+        }
+    }
+}
+
+/// `[[validator]]` section as it appears in the configuration file, which
+/// names its chains with either `chain_id` or `chain_ids` (other fields are
+/// as in [`ValidatorConfig`])
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ValidatorToml {
+    #[serde(deserialize_with = "deserialize_addrs")]
+    addr: Vec<ValidatorAddr>,
+    chain_id: Option<chain::Id>,
+    #[serde(default)]
+    chain_ids: Vec<chain::Id>,
+    label: Option<String>,
+    state_file: Option<PathBuf>,
+    #[serde(default = "reconnect_default")]
+    reconnect: bool,
+    reconnect_initial_delay: Option<u64>,
+    reconnect_max_delay: Option<u64>,
+    reconnect_max_attempts: Option<u32>,
+    #[serde(alias = "timeout_secs")]
+    timeout: Option<u16>,
+    keepalive_interval_secs: Option<u16>,
+    max_msg_size: Option<usize>,
+    proxy: Option<ProxyAddr>,
+    #[serde(default)]
+    resolve_via_proxy: bool,
+    secret_key: Option<PathBuf>,
+    secret_key_format: Option<KeyFormat>,
+    secret_key_passphrase_file: Option<PathBuf>,
+    #[serde(default)]
+    secret_key_write_if_missing: bool,
+    tls_client_cert: Option<PathBuf>,
+    tls_client_key: Option<PathBuf>,
+    tls_ca: Option<PathBuf>,
+    socket_mode: Option<SocketMode>,
+    socket_owner: Option<String>,
+    socket_group: Option<String>,
+    #[serde(default)]
+    peer_id_verification: PeerIdVerification,
+    #[serde(default)]
+    role: ValidatorRole,
+    max_height: Option<Spanned<tendermint::block::Height>>,
+    min_height: Option<tendermint::block::Height>,
+    max_requests_per_second: Option<u32>,
+    max_rate_limit_violations: Option<u32>,
+    request_queue_depth: Option<usize>,
+    request_deadline_ms: Option<u64>,
+    protocol_capture_dir: Option<PathBuf>,
+    protocol_version: ProtocolVersion,
+    max_chain_id_length: Option<usize>,
+}
+
+impl TryFrom<ValidatorToml> for ValidatorConfig {
+    type Error = Error;
+
+    fn try_from(toml: ValidatorToml) -> Result<Self, Error> {
+        let chain_ids = match (toml.chain_id, toml.chain_ids) {
+            (Some(chain_id), chain_ids) if chain_ids.is_empty() => vec![chain_id],
+            (None, chain_ids) if !chain_ids.is_empty() => chain_ids,
+            (Some(_), _) => fail!(
+                ConfigError,
+                "set either `chain_id` or `chain_ids`, not both"
+            ),
+            (None, _) => fail!(ConfigError, "missing field `chain_id` (or `chain_ids`)"),
+        };
+
+        let max_chain_id_length = toml
+            .max_chain_id_length
+            .unwrap_or_else(|| toml.protocol_version.max_chain_id_length());
+
+        if max_chain_id_length == 0 || max_chain_id_length > chain::id::MAX_LENGTH {
+            fail!(
+                ConfigError,
+                "`max_chain_id_length` must be between 1 and {}",
+                chain::id::MAX_LENGTH
+            );
+        }
+
+        for (i, chain_id) in chain_ids.iter().enumerate() {
+            if chain_ids[..i].contains(chain_id) {
+                fail!(ConfigError, "chain ID `{}` listed more than once", chain_id);
+            }
+
+            if chain_id.len() > max_chain_id_length {
+                fail!(
+                    ConfigError,
+                    "chain ID `{}` is {} characters long, but at most {} are allowed with \
+                     protocol_version {} (raise `max_chain_id_length` if the chain allows longer IDs)",
+                    chain_id,
+                    chain_id.len(),
+                    max_chain_id_length,
+                    toml.protocol_version.as_str()
+                );
+            }
+        }
+
+        // Amino-encoded requests don't say which chain they're for
+        if chain_ids.len() > 1 && !toml.protocol_version.is_protobuf() {
+            fail!(
+                ConfigError,
+                "`chain_ids` with more than one chain requires protocol_version v0.34 or later"
+            );
+        }
+
+        match (&toml.label, &toml.state_file) {
+            (Some(label), Some(_)) if label.is_empty() => {
+                fail!(ConfigError, "`label` must not be empty")
+            }
+            (Some(_), Some(_)) if chain_ids.len() > 1 => fail!(
+                ConfigError,
+                "labelled validators can only serve one chain (use `chain_id`)"
+            ),
+            (Some(_), None) => fail!(
+                ConfigError,
+                "labelled validators need their own `state_file`"
+            ),
+            (None, Some(_)) => fail!(
+                ConfigError,
+                "`state_file` is only used by labelled validators (set `label`)"
+            ),
+            _ => (),
+        }
+
+        let mut addrs = toml.addr.into_iter();
+        let addr = match addrs.next() {
+            Some(addr) => addr,
+            None => fail!(ConfigError, "`addr` must list at least one address"),
+        };
+        let failover_addrs: Vec<_> = addrs.collect();
+
+        for (i, failover_addr) in failover_addrs.iter().enumerate() {
+            // Only addresses we dial can be failed over between: we can't
+            // choose which of several listeners a validator connects to
+            if addr.is_listener() || addr.is_grpc() {
+                fail!(
+                    ConfigError,
+                    "`addr` lists may only contain `tcp://`, `tls://`, `unix://` or `vsock://` addresses"
+                );
+            }
+
+            // Connection options (e.g. `secret_key`, `tls_*`) are shared
+            if mem::discriminant(failover_addr) != mem::discriminant(&addr) {
+                fail!(
+                    ConfigError,
+                    "all addresses in an `addr` list must use the same scheme"
+                );
+            }
+
+            if *failover_addr == addr || failover_addrs[..i].contains(failover_addr) {
+                fail!(
+                    ConfigError,
+                    "address `{}` listed more than once",
+                    failover_addr
+                );
+            }
+        }
+
+        if toml.proxy.is_some() && !matches!(addr, ValidatorAddr::Tcp { .. }) {
+            fail!(
+                ConfigError,
+                "`proxy` is only supported for `tcp://` validator addresses"
+            );
+        }
+
+        if toml.resolve_via_proxy && toml.proxy.is_none() {
+            fail!(ConfigError, "`resolve_via_proxy` requires `proxy`");
+        }
+
+        if toml.max_msg_size == Some(0) {
+            fail!(ConfigError, "`max_msg_size` must be greater than zero");
+        }
+
+        if toml.keepalive_interval_secs == Some(0) {
+            fail!(
+                ConfigError,
+                "`keepalive_interval_secs` must be greater than zero"
+            );
+        }
+
+        if toml.max_requests_per_second == Some(0) {
+            fail!(
+                ConfigError,
+                "`max_requests_per_second` must be greater than zero"
+            );
+        }
+
+        if toml.request_queue_depth == Some(0) {
+            fail!(
+                ConfigError,
+                "`request_queue_depth` must be greater than zero"
+            );
+        }
+
+        if toml.request_deadline_ms == Some(0) {
+            fail!(
+                ConfigError,
+                "`request_deadline_ms` must be greater than zero"
+            );
+        }
+
+        Ok(Self {
+            addr,
+            failover_addrs,
+            chain_id: chain_ids[0].clone(),
+            chain_ids,
+            label: toml.label,
+            state_file: toml.state_file,
+            reconnect: toml.reconnect,
+            reconnect_initial_delay: toml.reconnect_initial_delay,
+            reconnect_max_delay: toml.reconnect_max_delay,
+            reconnect_max_attempts: toml.reconnect_max_attempts,
+            timeout: toml.timeout,
+            keepalive_interval_secs: toml.keepalive_interval_secs,
+            max_msg_size: toml.max_msg_size,
+            proxy: toml.proxy,
+            resolve_via_proxy: toml.resolve_via_proxy,
+            secret_key: toml.secret_key,
+            secret_key_format: toml.secret_key_format,
+            secret_key_passphrase_file: toml.secret_key_passphrase_file,
+            secret_key_write_if_missing: toml.secret_key_write_if_missing,
+            tls_client_cert: toml.tls_client_cert,
+            tls_client_key: toml.tls_client_key,
+            tls_ca: toml.tls_ca,
+            socket_mode: toml.socket_mode,
+            socket_owner: toml.socket_owner,
+            socket_group: toml.socket_group,
+            peer_id_verification: toml.peer_id_verification,
+            role: toml.role,
+            max_height_origin: toml
+                .max_height
+                .as_ref()
+                .map(|max_height| ConfigOrigin::new(max_height.start())),
+            max_height: toml.max_height.map(Spanned::into_inner),
+            min_height: toml.min_height,
+            max_requests_per_second: toml.max_requests_per_second,
+            max_rate_limit_violations: toml.max_rate_limit_violations,
+            request_queue_depth: toml.request_queue_depth,
+            request_deadline_ms: toml.request_deadline_ms,
+            protocol_capture_dir: toml.protocol_capture_dir,
+            protocol_version: toml.protocol_version,
+            max_chain_id_length: toml.max_chain_id_length,
+        })
+    }
+}
+
 impl ProtocolVersion {
+    /// Every protocol version, newest first
+    pub const ALL: &'static [ProtocolVersion] = &[
+        ProtocolVersion::Grpc,
+        ProtocolVersion::V0_38,
+        ProtocolVersion::V0_37,
+        ProtocolVersion::V0_34,
+        ProtocolVersion::V0_33,
+        ProtocolVersion::Legacy,
+    ];
+
+    /// Get the name of this protocol version, as in the configuration file
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProtocolVersion::Grpc => "grpc",
+            ProtocolVersion::V0_38 => "v0.38",
+            ProtocolVersion::V0_37 => "v0.37",
+            ProtocolVersion::V0_34 => "v0.34",
+            ProtocolVersion::V0_33 => "v0.33",
+            ProtocolVersion::Legacy => "legacy",
+        }
+    }
+
+    /// Get the longest chain ID the validator accepts: `MaxChainIDLen`, which
+    /// every Tendermint and CometBFT release so far shares
+    pub fn max_chain_id_length(self) -> usize {
+        match self {
+            ProtocolVersion::Grpc
+            | ProtocolVersion::V0_38
+            | ProtocolVersion::V0_37
+            | ProtocolVersion::V0_34
+            | ProtocolVersion::V0_33
+            | ProtocolVersion::Legacy => chain::id::COMETBFT_MAX_LENGTH,
+        }
+    }
+
     /// Are messages encoded using Protocol Buffers?
     pub fn is_protobuf(self) -> bool {
         !matches!(self, ProtocolVersion::V0_33 | ProtocolVersion::Legacy)
     }
+
+    /// Do votes carry ABCI++ vote extensions?
+    pub fn has_vote_extensions(self) -> bool {
+        self == ProtocolVersion::V0_38
+    }
+
+    /// Does this protocol version include everything `other` does? (gRPC is
+    /// v0.34-compatible)
+    pub fn is_at_least(self, other: ProtocolVersion) -> bool {
+        self.release() >= other.release()
+    }
+
+    /// Position of this protocol version among Tendermint releases
+    fn release(self) -> u8 {
+        match self {
+            ProtocolVersion::Legacy => 0,
+            ProtocolVersion::V0_33 => 1,
+            ProtocolVersion::V0_34 | ProtocolVersion::Grpc => 2,
+            ProtocolVersion::V0_37 => 3,
+            ProtocolVersion::V0_38 => 4,
+        }
+    }
 }
 
 impl From<ProtocolVersion> for secret_connection::Version {
     fn from(version: ProtocolVersion) -> secret_connection::Version {
         match version {
-            ProtocolVersion::V0_34 => secret_connection::Version::V0_34,
+            // gRPC doesn't use Secret Connection, but is otherwise v0.34-compatible
+            ProtocolVersion::Grpc | ProtocolVersion::V0_34 => secret_connection::Version::V0_34,
+            // Neither Tendermint v0.37 nor CometBFT v0.38 changed the Secret
+            // Connection handshake
+            ProtocolVersion::V0_38 | ProtocolVersion::V0_37 => secret_connection::Version::V0_34,
             ProtocolVersion::V0_33 => secret_connection::Version::V0_33,
             ProtocolVersion::Legacy => secret_connection::Version::Legacy,
         }
     }
 }
 
+/// Deserialize `addr`, which is either a single address or a list of them
+fn deserialize_addrs<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<ValidatorAddr>, D::Error> {
+    struct AddrsVisitor;
+
+    impl<'de> Visitor<'de> for AddrsVisitor {
+        type Value = Vec<ValidatorAddr>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a validator address or a list of them")
+        }
+
+        fn visit_str<E: de::Error>(self, addr: &str) -> Result<Self::Value, E> {
+            addr.parse()
+                .map(|addr| vec![addr])
+                .map_err(|e| E::custom(format!("{}", e)))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut addrs = vec![];
+
+            while let Some(addr) = seq.next_element()? {
+                addrs.push(addr);
+            }
+
+            Ok(addrs)
+        }
+    }
+
+    deserializer.deserialize_any(AddrsVisitor)
+}
+
 /// Default value for the `ValidatorConfig` reconnect field
 fn reconnect_default() -> bool {
     true
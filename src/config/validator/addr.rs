@@ -0,0 +1,439 @@
+//! Validator addresses (`tcp://`, `tls://`, `unix://`, `vsock://`,
+//! `tcp-listen://`, `unix-listen://`, `vsock-listen://`, or `grpc://`)
+
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt::{self, Display},
+    net::IpAddr,
+    str::FromStr,
+};
+use tendermint::node;
+use tendermint_config::net;
+
+use crate::{
+    error::{Error, ErrorKind::ConfigError},
+    prelude::*,
+};
+
+/// URI prefix for gRPC listeners
+pub const GRPC_PREFIX: &str = "grpc://";
+
+/// URI prefix for TLS connections
+pub const TLS_PREFIX: &str = "tls://";
+
+/// URI prefix for TCP listeners
+pub const TCP_LISTEN_PREFIX: &str = "tcp-listen://";
+
+/// URI prefix for UNIX domain socket listeners
+pub const UNIX_LISTEN_PREFIX: &str = "unix-listen://";
+
+/// URI prefix for vsock connections
+pub const VSOCK_PREFIX: &str = "vsock://";
+
+/// URI prefix for vsock listeners
+pub const VSOCK_LISTEN_PREFIX: &str = "vsock-listen://";
+
+/// Address of a validator.
+///
+/// `tcp://` and `unix://` addresses are dialed by the KMS, whereas
+/// `tcp-listen://` and `unix-listen://` addresses are listened on by the KMS,
+/// which accepts a connection from the validator. `tls://` addresses are
+/// dialed like `tcp://` ones, but use mutual TLS rather than Secret
+/// Connection (e.g. for privval proxies). `vsock://` and
+/// `vsock-listen://` are their virtual socket equivalents, for use inside AWS
+/// Nitro Enclaves. `grpc://` addresses are also listened on, with the KMS
+/// serving the `PrivValidatorAPI` gRPC service.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ValidatorAddr {
+    /// TCP connections (encrypted with Secret Connection)
+    Tcp {
+        /// Remote peer ID
+        peer_id: Option<node::Id>,
+
+        /// Hostname or IP address
+        host: String,
+
+        /// Port
+        port: u16,
+    },
+
+    /// TCP connections encrypted with mutual TLS
+    Tls {
+        /// Hostname (which the validator's certificate must be valid for)
+        host: String,
+
+        /// Port
+        port: u16,
+    },
+
+    /// UNIX domain sockets
+    Unix {
+        /// Path to a UNIX domain socket path
+        path: String,
+    },
+
+    /// Virtual sockets (`AF_VSOCK`)
+    Vsock {
+        /// Context ID
+        cid: u32,
+
+        /// Port
+        port: u32,
+    },
+
+    /// TCP listen address (connections encrypted with Secret Connection)
+    TcpListen {
+        /// Expected peer ID of the validator dialing in
+        peer_id: Option<node::Id>,
+
+        /// Hostname or IP address to bind to
+        host: String,
+
+        /// Port to bind to
+        port: u16,
+    },
+
+    /// UNIX domain socket listen address
+    UnixListen {
+        /// Path to bind the UNIX domain socket to
+        path: String,
+    },
+
+    /// Virtual socket (`AF_VSOCK`) listen address
+    VsockListen {
+        /// Context ID to bind to
+        cid: u32,
+
+        /// Port to bind to
+        port: u32,
+    },
+
+    /// gRPC `PrivValidatorAPI` service listen address
+    Grpc {
+        /// Hostname or IP address to bind to
+        host: String,
+
+        /// Port to bind to
+        port: u16,
+    },
+}
+
+impl ValidatorAddr {
+    /// Is this the address of a gRPC listener?
+    pub fn is_grpc(&self) -> bool {
+        matches!(self, ValidatorAddr::Grpc { .. })
+    }
+
+    /// Is this the address of a gRPC listener bound to a loopback address
+    /// (i.e. `localhost` or a loopback IP address), which only local clients
+    /// can reach?
+    pub fn is_loopback_grpc(&self) -> bool {
+        match self {
+            ValidatorAddr::Grpc { host, .. } => match host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<IpAddr>()
+            {
+                Ok(ip) => ip.is_loopback(),
+                Err(_) => host == "localhost",
+            },
+            _ => false,
+        }
+    }
+
+    /// Is this the address of a listener the validator dials into?
+    pub fn is_listener(&self) -> bool {
+        matches!(
+            self,
+            ValidatorAddr::TcpListen { .. }
+                | ValidatorAddr::UnixListen { .. }
+                | ValidatorAddr::VsockListen { .. }
+        )
+    }
+}
+
+impl From<net::Address> for ValidatorAddr {
+    fn from(addr: net::Address) -> ValidatorAddr {
+        match addr {
+            net::Address::Tcp {
+                peer_id,
+                host,
+                port,
+            } => ValidatorAddr::Tcp {
+                peer_id,
+                host,
+                port,
+            },
+            net::Address::Unix { path } => ValidatorAddr::Unix { path },
+        }
+    }
+}
+
+impl Display for ValidatorAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidatorAddr::Tcp {
+                peer_id,
+                host,
+                port,
+            } => net::Address::Tcp {
+                peer_id: *peer_id,
+                host: host.clone(),
+                port: *port,
+            }
+            .fmt(f),
+            ValidatorAddr::Tls { host, port } => write!(f, "{}{}:{}", TLS_PREFIX, host, port),
+            ValidatorAddr::Unix { path } => net::Address::Unix { path: path.clone() }.fmt(f),
+            ValidatorAddr::Vsock { cid, port } => write!(f, "{}{}:{}", VSOCK_PREFIX, cid, port),
+            ValidatorAddr::TcpListen {
+                peer_id,
+                host,
+                port,
+            } => {
+                f.write_str(TCP_LISTEN_PREFIX)?;
+
+                if let Some(peer_id) = peer_id {
+                    write!(f, "{}@", peer_id)?;
+                }
+
+                write!(f, "{}:{}", host, port)
+            }
+            ValidatorAddr::UnixListen { path } => write!(f, "{}{}", UNIX_LISTEN_PREFIX, path),
+            ValidatorAddr::VsockListen { cid, port } => {
+                write!(f, "{}{}:{}", VSOCK_LISTEN_PREFIX, cid, port)
+            }
+            ValidatorAddr::Grpc { host, port } => write!(f, "{}{}:{}", GRPC_PREFIX, host, port),
+        }
+    }
+}
+
+impl FromStr for ValidatorAddr {
+    type Err = Error;
+
+    fn from_str(addr: &str) -> Result<Self, Error> {
+        if let Some(listen_addr) = addr.strip_prefix(GRPC_PREFIX) {
+            // Reuse the TCP address parser for the host/port portion
+            return match format!("{}{}", net::TCP_PREFIX, listen_addr).parse()? {
+                ValidatorAddr::Tcp {
+                    peer_id: None,
+                    host,
+                    port,
+                } => Ok(ValidatorAddr::Grpc { host, port }),
+                _ => fail!(
+                    ConfigError,
+                    "gRPC addresses can't include a peer ID: {}",
+                    addr
+                ),
+            };
+        }
+
+        if let Some(tls_addr) = addr.strip_prefix(TLS_PREFIX) {
+            // Validators are authenticated by their certificate, not peer ID
+            return match format!("{}{}", net::TCP_PREFIX, tls_addr).parse()? {
+                ValidatorAddr::Tcp {
+                    peer_id: None,
+                    host,
+                    port,
+                } => Ok(ValidatorAddr::Tls { host, port }),
+                _ => fail!(
+                    ConfigError,
+                    "TLS addresses can't include a peer ID: {}",
+                    addr
+                ),
+            };
+        }
+
+        if let Some(listen_addr) = addr.strip_prefix(TCP_LISTEN_PREFIX) {
+            return match format!("{}{}", net::TCP_PREFIX, listen_addr).parse()? {
+                ValidatorAddr::Tcp {
+                    peer_id,
+                    host,
+                    port,
+                } => Ok(ValidatorAddr::TcpListen {
+                    peer_id,
+                    host,
+                    port,
+                }),
+                _ => unreachable!(),
+            };
+        }
+
+        if let Some(path) = addr.strip_prefix(UNIX_LISTEN_PREFIX) {
+            if path.is_empty() {
+                fail!(ConfigError, "missing socket path: {}", addr);
+            }
+
+            return Ok(ValidatorAddr::UnixListen {
+                path: path.to_owned(),
+            });
+        }
+
+        if let Some(listen_addr) = addr.strip_prefix(VSOCK_LISTEN_PREFIX) {
+            let (cid, port) = parse_vsock_addr(listen_addr)?;
+            return Ok(ValidatorAddr::VsockListen { cid, port });
+        }
+
+        if let Some(vsock_addr) = addr.strip_prefix(VSOCK_PREFIX) {
+            let (cid, port) = parse_vsock_addr(vsock_addr)?;
+            return Ok(ValidatorAddr::Vsock { cid, port });
+        }
+
+        addr.parse::<net::Address>().map(Into::into).map_err(|e| {
+            format_err!(ConfigError, "invalid validator address: {}", e.detail()).into()
+        })
+    }
+}
+
+/// Parse the `<cid>:<port>` portion of a vsock address
+fn parse_vsock_addr(addr: &str) -> Result<(u32, u32), Error> {
+    let mut parts = addr.splitn(2, ':');
+
+    let cid = parts.next().and_then(|cid| cid.parse().ok());
+    let port = parts.next().and_then(|port| port.parse().ok());
+
+    match (cid, port) {
+        (Some(cid), Some(port)) => Ok((cid, port)),
+        _ => fail!(
+            ConfigError,
+            "invalid vsock address (expected `<cid>:<port>`): {}",
+            addr
+        ),
+    }
+}
+
+impl<'de> Deserialize<'de> for ValidatorAddr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::from_str(&String::deserialize(deserializer)?)
+            .map_err(|e| D::Error::custom(format!("{}", e)))
+    }
+}
+
+impl Serialize for ValidatorAddr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_grpc_addr() {
+        let addr = "grpc://127.0.0.1:26659".parse::<ValidatorAddr>().unwrap();
+
+        assert_eq!(
+            addr,
+            ValidatorAddr::Grpc {
+                host: "127.0.0.1".to_owned(),
+                port: 26659
+            }
+        );
+
+        assert_eq!(addr.to_string(), "grpc://127.0.0.1:26659");
+    }
+
+    #[test]
+    fn loopback_grpc_addrs() {
+        for addr in &[
+            "grpc://127.0.0.1:26659",
+            "grpc://localhost:26659",
+            "grpc://[::1]:26659",
+        ] {
+            assert!(addr.parse::<ValidatorAddr>().unwrap().is_loopback_grpc());
+        }
+
+        for addr in &[
+            "grpc://0.0.0.0:26659",
+            "grpc://10.0.0.1:26659",
+            "grpc://kms.example.com:26659",
+        ] {
+            assert!(!addr.parse::<ValidatorAddr>().unwrap().is_loopback_grpc());
+        }
+    }
+
+    #[test]
+    fn parse_grpc_addr_with_peer_id() {
+        assert!(
+            "grpc://f88883b673fc69d7869cab098de3bafc2ff76eb8@127.0.0.1:26659"
+                .parse::<ValidatorAddr>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parse_tls_addr() {
+        let addr = "tls://validator.example.com:26658"
+            .parse::<ValidatorAddr>()
+            .unwrap();
+
+        assert_eq!(
+            addr,
+            ValidatorAddr::Tls {
+                host: "validator.example.com".to_owned(),
+                port: 26658
+            }
+        );
+
+        assert!(!addr.is_listener());
+        assert_eq!(addr.to_string(), "tls://validator.example.com:26658");
+
+        assert!(
+            "tls://f88883b673fc69d7869cab098de3bafc2ff76eb8@validator.example.com:26658"
+                .parse::<ValidatorAddr>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parse_listen_addrs() {
+        for addr in &[
+            "tcp-listen://f88883b673fc69d7869cab098de3bafc2ff76eb8@0.0.0.0:26658",
+            "tcp-listen://0.0.0.0:26658",
+            "unix-listen:///tmp/tmkms.sock",
+            "vsock-listen://4294967295:26658",
+        ] {
+            let parsed = addr.parse::<ValidatorAddr>().unwrap();
+            assert!(parsed.is_listener());
+            assert_eq!(&parsed.to_string(), addr);
+        }
+
+        assert_eq!(
+            "unix-listen:///tmp/tmkms.sock"
+                .parse::<ValidatorAddr>()
+                .unwrap(),
+            ValidatorAddr::UnixListen {
+                path: "/tmp/tmkms.sock".to_owned()
+            }
+        );
+
+        assert!("unix-listen://".parse::<ValidatorAddr>().is_err());
+    }
+
+    #[test]
+    fn parse_tcp_and_unix_addrs() {
+        for addr in &[
+            "tcp://f88883b673fc69d7869cab098de3bafc2ff76eb8@127.0.0.1:26658",
+            "tcp://127.0.0.1:26658",
+            "unix:///tmp/tmkms.sock",
+            "vsock://3:26658",
+        ] {
+            assert_eq!(&addr.parse::<ValidatorAddr>().unwrap().to_string(), addr);
+        }
+    }
+
+    #[test]
+    fn parse_vsock_addrs() {
+        assert_eq!(
+            "vsock://3:26658".parse::<ValidatorAddr>().unwrap(),
+            ValidatorAddr::Vsock {
+                cid: 3,
+                port: 26658
+            }
+        );
+
+        for addr in &["vsock://", "vsock://3", "vsock://3:", "vsock://host:26658"] {
+            assert!(addr.parse::<ValidatorAddr>().is_err(), "{}", addr);
+        }
+    }
+}
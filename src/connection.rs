@@ -1,16 +1,65 @@
-//! Connections to a validator (TCP or Unix socket)
+//! Connections to a validator (TCP, TLS, Unix socket, vsock, or gRPC),
+//! either dialed by the KMS or accepted from the validator
 
-use std::io;
+use std::{
+    io,
+    net::{Shutdown, TcpStream},
+    os::unix::net::UnixStream,
+    time::Duration,
+};
 
 use tendermint_p2p::secret_connection::SecretConnection;
 
 use self::unix::UnixConnection;
 
+pub use self::listener::Listener;
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod listener;
+pub mod socks5;
 pub mod tcp;
+#[cfg(feature = "tls")]
+pub mod tls;
 pub mod unix;
+#[cfg(feature = "nitro")]
+#[allow(unsafe_code)]
+pub mod vsock;
+
+/// Default read/write timeout in seconds
+pub const DEFAULT_TIMEOUT: u16 = 10;
 
 /// Connections to a validator
 pub trait Connection: io::Read + io::Write + Sync + Send {}
 
 impl<T> Connection for SecretConnection<T> where T: io::Read + io::Write + Sync + Send {}
 impl<T> Connection for UnixConnection<T> where T: io::Read + io::Write + Sync + Send {}
+#[cfg(feature = "tls")]
+impl Connection for tls::TlsConnection {}
+#[cfg(feature = "nitro")]
+impl Connection for vsock::VsockConnection {}
+
+/// Handle used to interrupt a connection from another thread (e.g. on
+/// shutdown), causing blocked reads and writes to fail
+pub trait Interrupt: Send {
+    /// Interrupt the connection
+    fn interrupt(&self) -> io::Result<()>;
+}
+
+impl Interrupt for TcpStream {
+    fn interrupt(&self) -> io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+}
+
+impl Interrupt for UnixStream {
+    fn interrupt(&self) -> io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+}
+
+/// Get the read/write timeout to use for the given configured timeout (in
+/// seconds)
+pub(crate) fn timeout(secs: Option<u16>) -> Duration {
+    Duration::from_secs(secs.unwrap_or(DEFAULT_TIMEOUT).into())
+}
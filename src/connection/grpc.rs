@@ -0,0 +1,435 @@
+//! gRPC `PrivValidatorAPI` service.
+//!
+//! Unlike the TCP and Unix socket transports, where the KMS dials out to the
+//! validator, the gRPC transport listens on a local address and serves the
+//! `tendermint.privval.PrivValidatorAPI` service to the validator.
+//!
+//! Anyone who can reach the listener can request signatures, so it's only
+//! served in plaintext on loopback addresses. Elsewhere mutual TLS is
+//! required: the KMS presents `tls_client_cert` and only accepts clients
+//! presenting a certificate issued by `tls_ca`.
+
+use crate::{
+    client::Control,
+    config::{ValidatorAddr, ValidatorConfig},
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    rpc::Request,
+    session::{RequestHandler, RequestQueue},
+    shutdown,
+};
+use std::{
+    convert::{Infallible, TryFrom},
+    fs,
+    marker::PhantomData,
+    net::ToSocketAddrs,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tendermint_proto::privval::{
+    message::Sum, PubKeyRequest, PubKeyResponse, SignProposalRequest, SignVoteRequest,
+    SignedProposalResponse, SignedVoteResponse,
+};
+use tonic::{
+    body::BoxBody,
+    codec::ProstCodec,
+    codegen::{empty_body, http, Arc, Body, BoxFuture, Context, Poll, Service, StdError},
+    server::UnaryService,
+    transport::{
+        Certificate, Channel, ClientTlsConfig, Endpoint, Identity, NamedService, ServerTlsConfig,
+    },
+    Status,
+};
+
+/// Fully qualified name of the gRPC service
+pub const SERVICE_NAME: &str = "tendermint.privval.PrivValidatorAPI";
+
+/// How often to check whether the client has been stopped
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Path of the `GetPubKey` method
+const GET_PUB_KEY_PATH: &str = "/tendermint.privval.PrivValidatorAPI/GetPubKey";
+
+/// Path of the `SignVote` method
+const SIGN_VOTE_PATH: &str = "/tendermint.privval.PrivValidatorAPI/SignVote";
+
+/// Path of the `SignProposal` method
+const SIGN_PROPOSAL_PATH: &str = "/tendermint.privval.PrivValidatorAPI/SignProposal";
+
+/// Serve the `PrivValidatorAPI` on the `grpc://` address in the given
+/// validator configuration, blocking until the server exits or `control`
+/// stops the client
+pub fn serve(config: ValidatorConfig, control: Arc<Control>) -> Result<(), Error> {
+    let listen_addr = match &config.addr {
+        ValidatorAddr::Grpc { host, port } => (host.as_str(), *port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| format_err!(ConfigError, "couldn't resolve {}", &config.addr))?,
+        other => fail!(ConfigError, "not a gRPC address: {}", other),
+    };
+
+    let tls_config = server_tls_config(&config)?;
+    let mutual_tls = tls_config.is_some();
+
+    if !mutual_tls && !config.addr.is_loopback_grpc() {
+        fail!(
+            ConfigError,
+            "[{}@{}] refusing to serve {} on a non-loopback address without mutual TLS \
+             (configure `tls_ca`, `tls_client_cert` and `tls_client_key`)",
+            &config.chain_id,
+            &config.addr,
+            SERVICE_NAME
+        );
+    }
+
+    let mut builder = tonic::transport::Server::builder();
+
+    if let Some(tls_config) = tls_config {
+        builder = builder.tls_config(tls_config).map_err(|e| {
+            format_err!(
+                ConfigError,
+                "[{}@{}] invalid TLS certificate or key: {}",
+                &config.chain_id,
+                &config.addr,
+                e
+            )
+        })?;
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    info!(
+        "[{}@{}] serving {} on {}{}",
+        &config.chain_id,
+        &config.addr,
+        SERVICE_NAME,
+        listen_addr,
+        if mutual_tls { " (mutual TLS)" } else { "" }
+    );
+
+    let stopped = {
+        let control = control.clone();
+
+        async move {
+            while !control.is_stopped() {
+                tokio::time::sleep(STOP_POLL_INTERVAL).await;
+            }
+        }
+    };
+
+    let server = builder
+        .add_service(PrivValidatorApiServer::new(
+            RequestQueue::new(&config),
+            RequestHandler::new(config),
+            control,
+        ))
+        .serve_with_shutdown(listen_addr, stopped);
+
+    runtime
+        .block_on(server)
+        .map_err(|e| format_err!(IoError, "gRPC server error: {}", e))?;
+
+    Ok(())
+}
+
+/// Build the TLS configuration of the gRPC server for the given validator
+/// from its `tls_ca`, `tls_client_cert`, and `tls_client_key` files, if
+/// configured. Clients must present a certificate issued by `tls_ca`.
+pub fn server_tls_config(config: &ValidatorConfig) -> Result<Option<ServerTlsConfig>, Error> {
+    let (ca_path, cert_path, key_path) = match (
+        &config.tls_ca,
+        &config.tls_client_cert,
+        &config.tls_client_key,
+    ) {
+        (None, None, None) => return Ok(None),
+        (Some(ca), Some(cert), Some(key)) => (ca, cert, key),
+        _ => fail!(
+            ConfigError,
+            "`tls_ca`, `tls_client_cert`, and `tls_client_key` are all required for mutual TLS on {}",
+            config.addr
+        ),
+    };
+
+    Ok(Some(
+        ServerTlsConfig::new()
+            .identity(Identity::from_pem(
+                read_pem(cert_path)?,
+                read_pem(key_path)?,
+            ))
+            .client_ca_root(Certificate::from_pem(read_pem(ca_path)?)),
+    ))
+}
+
+/// Read a PEM file
+fn read_pem(path: &Path) -> Result<Vec<u8>, Error> {
+    fs::read(path)
+        .map_err(|e| format_err!(ConfigError, "couldn't read {}: {}", path.display(), e).into())
+}
+
+/// `PrivValidatorAPI` gRPC service backed by a [`RequestHandler`]
+#[derive(Clone)]
+pub struct PrivValidatorApiServer {
+    /// Queue in which in-flight requests wait their turn
+    queue: RequestQueue,
+
+    /// Request handler (shared between in-flight requests)
+    handler: Arc<Mutex<RequestHandler>>,
+
+    /// Control of the client serving this service
+    control: Arc<Control>,
+}
+
+impl PrivValidatorApiServer {
+    /// Create a new service which handles requests with the given handler,
+    /// in the order they arrived in the given queue
+    pub fn new(queue: RequestQueue, handler: RequestHandler, control: Arc<Control>) -> Self {
+        Self {
+            queue,
+            handler: Arc::new(Mutex::new(handler)),
+            control,
+        }
+    }
+
+    /// Build a unary service for a single RPC method
+    fn unary<Req, Resp>(
+        &self,
+        wrap: fn(Req) -> Sum,
+        unwrap: fn(Sum) -> Option<Resp>,
+    ) -> Unary<Req, Resp> {
+        Unary {
+            queue: self.queue.clone(),
+            handler: self.handler.clone(),
+            control: self.control.clone(),
+            wrap,
+            unwrap,
+            request: PhantomData,
+        }
+    }
+}
+
+impl<B> Service<http::Request<B>> for PrivValidatorApiServer
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        match req.uri().path() {
+            GET_PUB_KEY_PATH => {
+                let method = self.unary(Sum::PubKeyRequest, |msg| match msg {
+                    Sum::PubKeyResponse(resp) => Some(resp),
+                    _ => None,
+                });
+
+                Box::pin(async move { Ok(grpc_server().unary(method, req).await) })
+            }
+            SIGN_VOTE_PATH => {
+                let method = self.unary(Sum::SignVoteRequest, |msg| match msg {
+                    Sum::SignedVoteResponse(resp) => Some(resp),
+                    _ => None,
+                });
+
+                Box::pin(async move { Ok(grpc_server().unary(method, req).await) })
+            }
+            SIGN_PROPOSAL_PATH => {
+                let method = self.unary(Sum::SignProposalRequest, |msg| match msg {
+                    Sum::SignedProposalResponse(resp) => Some(resp),
+                    _ => None,
+                });
+
+                Box::pin(async move { Ok(grpc_server().unary(method, req).await) })
+            }
+            _ => Box::pin(async move {
+                // `Unimplemented` status, as returned by `tonic-build` services
+                Ok(http::Response::builder()
+                    .status(200)
+                    .header("grpc-status", "12")
+                    .header("content-type", "application/grpc")
+                    .body(empty_body())
+                    .unwrap())
+            }),
+        }
+    }
+}
+
+impl NamedService for PrivValidatorApiServer {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+/// Create a gRPC server codec wrapper for protobuf messages
+fn grpc_server<Req, Resp>() -> tonic::server::Grpc<ProstCodec<Resp, Req>>
+where
+    Req: prost::Message + Default + Send + 'static,
+    Resp: prost::Message + Send + 'static,
+{
+    tonic::server::Grpc::new(ProstCodec::default())
+}
+
+/// Unary RPC method which converts its request into a privval [`Sum`],
+/// handles it with the [`RequestHandler`], and converts the result back
+struct Unary<Req, Resp> {
+    /// Queue in which the request waits its turn
+    queue: RequestQueue,
+
+    /// Request handler
+    handler: Arc<Mutex<RequestHandler>>,
+
+    /// Control of the client serving this method
+    control: Arc<Control>,
+
+    /// Convert the gRPC request into a privval message
+    wrap: fn(Req) -> Sum,
+
+    /// Extract the gRPC response from a privval message
+    unwrap: fn(Sum) -> Option<Resp>,
+
+    /// Request type
+    request: PhantomData<fn(Req)>,
+}
+
+impl<Req, Resp> UnaryService<Req> for Unary<Req, Resp>
+where
+    Req: Send + 'static,
+    Resp: Send + 'static,
+{
+    type Response = Resp;
+    type Future = BoxFuture<tonic::Response<Resp>, Status>;
+
+    fn call(&mut self, request: tonic::Request<Req>) -> Self::Future {
+        // Requests join the queue as they arrive, rather than when a blocking
+        // thread picks them up
+        let ticket = self.queue.push(Instant::now());
+        let queue = self.queue.clone();
+        let handler = self.handler.clone();
+        let control = self.control.clone();
+        let msg = (self.wrap)(request.into_inner());
+        let unwrap = self.unwrap;
+
+        Box::pin(async move {
+            // Signing may block on hardware, so keep it off the async executor
+            let response = tokio::task::spawn_blocking(move || {
+                let _busy = control.heartbeat().busy();
+                let _in_flight = shutdown::begin_request()
+                    .ok_or_else(|| format_err!(ProtocolError, "shutting down"))?;
+
+                let request = Request::try_from(msg)?;
+
+                let ticket = match ticket {
+                    Some(ticket) => ticket,
+                    None => return queue.refusal(request).and_then(Sum::try_from),
+                };
+
+                ticket.wait();
+
+                let mut handler = handler
+                    .lock()
+                    .map_err(|_| format_err!(PoisonError, "request handler lock poisoned"))?;
+
+                handler.set_height_limits(
+                    control.min_height(),
+                    control.max_height(),
+                    control.max_height_origin(),
+                );
+                handler
+                    .handle(request, ticket.received_at())
+                    .and_then(Sum::try_from)
+            })
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+            unwrap(response)
+                .map(tonic::Response::new)
+                .ok_or_else(|| Status::internal("unexpected response type"))
+        })
+    }
+}
+
+/// `PrivValidatorAPI` gRPC client
+#[derive(Clone, Debug)]
+pub struct PrivValidatorApiClient {
+    /// gRPC client
+    inner: tonic::client::Grpc<Channel>,
+}
+
+impl PrivValidatorApiClient {
+    /// Connect to the `PrivValidatorAPI` service at the given URI
+    /// (e.g. `http://127.0.0.1:26659`)
+    pub async fn connect(uri: impl Into<String>) -> Result<Self, tonic::transport::Error> {
+        let channel = Endpoint::from_shared(uri.into())?.connect().await?;
+
+        Ok(Self {
+            inner: tonic::client::Grpc::new(channel),
+        })
+    }
+
+    /// Connect to the `PrivValidatorAPI` service at the given URI
+    /// (e.g. `https://kms.example.com:26659`) over mutual TLS
+    pub async fn connect_tls(
+        uri: impl Into<String>,
+        tls_config: ClientTlsConfig,
+    ) -> Result<Self, tonic::transport::Error> {
+        let channel = Endpoint::from_shared(uri.into())?
+            .tls_config(tls_config)?
+            .connect()
+            .await?;
+
+        Ok(Self {
+            inner: tonic::client::Grpc::new(channel),
+        })
+    }
+
+    /// Request the validator's consensus public key
+    pub async fn get_pub_key(&mut self, request: PubKeyRequest) -> Result<PubKeyResponse, Status> {
+        self.unary(request, GET_PUB_KEY_PATH).await
+    }
+
+    /// Request a signature for a vote
+    pub async fn sign_vote(
+        &mut self,
+        request: SignVoteRequest,
+    ) -> Result<SignedVoteResponse, Status> {
+        self.unary(request, SIGN_VOTE_PATH).await
+    }
+
+    /// Request a signature for a proposal
+    pub async fn sign_proposal(
+        &mut self,
+        request: SignProposalRequest,
+    ) -> Result<SignedProposalResponse, Status> {
+        self.unary(request, SIGN_PROPOSAL_PATH).await
+    }
+
+    /// Perform a unary RPC
+    async fn unary<Req, Resp>(&mut self, request: Req, path: &'static str) -> Result<Resp, Status>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Resp: prost::Message + Default + Send + Sync + 'static,
+    {
+        self.inner
+            .ready()
+            .await
+            .map_err(|e| Status::unknown(format!("service was not ready: {}", e)))?;
+
+        let response = self
+            .inner
+            .unary(
+                tonic::Request::new(request),
+                http::uri::PathAndQuery::from_static(path),
+                ProstCodec::default(),
+            )
+            .await?;
+
+        Ok(response.into_inner())
+    }
+}
@@ -0,0 +1,182 @@
+//! Listeners validators dial into (`tcp-listen://`, `unix-listen://`, and
+//! `vsock-listen://`)
+
+use std::{
+    ffi::CString,
+    fs, io,
+    net::TcpListener,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+};
+
+use crate::{
+    config::{ValidatorAddr, ValidatorConfig},
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    privileges,
+};
+
+/// Socket bound to a validator listen address, which is kept open across
+/// sessions so reconnecting is a matter of accepting the next connection
+pub enum Listener {
+    /// TCP listener
+    Tcp(TcpListener),
+
+    /// UNIX domain socket listener
+    Unix(UnixListener),
+
+    /// vsock listener
+    #[cfg(feature = "nitro")]
+    Vsock(super::vsock::VsockListener),
+}
+
+impl Listener {
+    /// Bind to the listen address of the given validator
+    pub fn bind(config: &ValidatorConfig) -> Result<Self, Error> {
+        let addr = &config.addr;
+
+        let listener = match addr {
+            ValidatorAddr::TcpListen { host, port, .. } => {
+                Listener::Tcp(TcpListener::bind((host.as_str(), *port))?)
+            }
+            ValidatorAddr::UnixListen { path } => {
+                let path = Path::new(path);
+                remove_stale_socket(path)?;
+                let listener = UnixListener::bind(path)?;
+                set_socket_permissions(path, config)?;
+                Listener::Unix(listener)
+            }
+            #[cfg(feature = "nitro")]
+            ValidatorAddr::VsockListen { cid, port } => {
+                Listener::Vsock(super::vsock::VsockListener::bind(*cid, *port)?)
+            }
+            #[cfg(not(feature = "nitro"))]
+            ValidatorAddr::VsockListen { .. } => fail!(
+                ConfigError,
+                "vsock support not enabled (rebuild with the `nitro` cargo feature): {}",
+                addr
+            ),
+            _ => fail!(ConfigError, "not a listen address: {}", addr),
+        };
+
+        info!("listening for validator connections on {}", addr);
+        Ok(listener)
+    }
+}
+
+/// Remove a socket left behind at the given path by a previous run, refusing
+/// to remove anything which isn't a socket, or a socket something is still
+/// listening on
+fn remove_stale_socket(path: &Path) -> Result<(), Error> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if UnixStream::connect(path).is_ok() {
+                fail!(
+                    IoError,
+                    "another process is already listening on {}",
+                    path.display()
+                );
+            }
+
+            Ok(fs::remove_file(path)?)
+        }
+        Ok(_) => fail!(
+            ConfigError,
+            "refusing to replace non-socket file with listener: {}",
+            path.display()
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Apply the `socket_owner`, `socket_group`, and `socket_mode` of the given
+/// validator to its newly bound socket
+#[allow(unsafe_code)]
+fn set_socket_permissions(path: &Path, config: &ValidatorConfig) -> Result<(), Error> {
+    let uid = config.socket_owner.as_deref().map(lookup_uid).transpose()?;
+    let gid = config.socket_group.as_deref().map(lookup_gid).transpose()?;
+
+    if uid.is_some() || gid.is_some() {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| format_err!(SocketPermissionError, "{}: {}", path.display(), e))?;
+
+        // `-1` (i.e. the maximum ID) leaves the owner or group unchanged
+        let rc = unsafe {
+            libc::chown(
+                c_path.as_ptr(),
+                uid.unwrap_or(libc::uid_t::MAX),
+                gid.unwrap_or(libc::gid_t::MAX),
+            )
+        };
+
+        if rc != 0 {
+            fail!(
+                SocketPermissionError,
+                "couldn't change ownership of {} to uid {}/gid {}: {}",
+                path.display(),
+                display_id(uid),
+                display_id(gid),
+                io::Error::last_os_error()
+            );
+        }
+    }
+
+    if let Some(mode) = config.socket_mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode.bits())).map_err(|e| {
+            format_err!(
+                SocketPermissionError,
+                "couldn't set mode {} on {}: {}",
+                mode,
+                path.display(),
+                e
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Display a uid or gid which is left unchanged if absent
+fn display_id(id: Option<u32>) -> String {
+    id.map_or_else(|| "(unchanged)".to_owned(), |id| id.to_string())
+}
+
+/// Look up the uid of the given user name (or numeric uid)
+pub fn lookup_uid(user: &str) -> Result<libc::uid_t, Error> {
+    privileges::lookup_uid(user).map_err(|e| format_err!(SocketPermissionError, e).into())
+}
+
+/// Look up the gid of the given group name (or numeric gid)
+pub fn lookup_gid(group: &str) -> Result<libc::gid_t, Error> {
+    privileges::lookup_gid(group).map_err(|e| format_err!(SocketPermissionError, e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_socket_removal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("validator.sock");
+
+        // Something is still listening on the socket
+        let listener = UnixListener::bind(&path).unwrap();
+        assert!(remove_stale_socket(&path).is_err());
+        assert!(path.exists());
+
+        // Left behind by a crashed listener
+        drop(listener);
+        remove_stale_socket(&path).unwrap();
+        assert!(!path.exists());
+
+        // Not a socket at all
+        fs::write(&path, b"not a socket").unwrap();
+        assert!(remove_stale_socket(&path).is_err());
+    }
+}
@@ -0,0 +1,457 @@
+//! SOCKS5 client (RFC 1928) for dialing validators through a proxy, with
+//! optional username/password authentication (RFC 1929).
+//!
+//! Failing to reach or negotiate with the proxy is a `ProxyError`, while the
+//! proxy failing to reach the validator is an `IoError`, as it would be when
+//! dialing the validator directly.
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use crate::{
+    config::validator::ProxyAddr,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+
+/// SOCKS protocol version
+const VERSION: u8 = 0x05;
+
+/// Username/password authentication subnegotiation version
+const AUTH_VERSION: u8 = 0x01;
+
+/// Authentication methods
+const NO_AUTH: u8 = 0x00;
+const USERNAME_PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE_METHODS: u8 = 0xFF;
+
+/// `CONNECT` command
+const CONNECT: u8 = 0x01;
+
+/// Address types
+const IPV4: u8 = 0x01;
+const DOMAIN_NAME: u8 = 0x03;
+const IPV6: u8 = 0x04;
+
+/// Open a TCP connection to the given host and port through a SOCKS5 proxy.
+///
+/// With `resolve_via_proxy`, the host name is sent to the proxy to resolve
+/// (as with `socks5h://` URLs), otherwise it's resolved locally and each of
+/// its addresses is tried in turn.
+pub fn connect(
+    proxy: &ProxyAddr,
+    host: &str,
+    port: u16,
+    resolve_via_proxy: bool,
+    timeout: Duration,
+) -> Result<TcpStream, Error> {
+    let ip = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>();
+
+    if let Ok(ip) = ip {
+        return connect_to(proxy, &Target::Ip(SocketAddr::new(ip, port)), timeout);
+    }
+
+    if resolve_via_proxy {
+        return connect_to(proxy, &Target::Domain(host, port), timeout);
+    }
+
+    let mut last_err = None;
+
+    for addr in (host, port).to_socket_addrs()? {
+        match connect_to(proxy, &Target::Ip(addr), timeout) {
+            Ok(socket) => return Ok(socket),
+            // The proxy itself failing won't be any different for another
+            // of the validator's addresses
+            Err(e) if *e.kind() == ProxyError => return Err(e),
+            Err(e) => last_err = Some(e),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| {
+        format_err!(
+            IoError,
+            "{}:{} did not resolve to any addresses",
+            host,
+            port
+        )
+        .into()
+    }))
+}
+
+/// Destination of a `CONNECT` request
+enum Target<'a> {
+    /// Address resolved by the KMS
+    Ip(SocketAddr),
+
+    /// Host name for the proxy to resolve, and port
+    Domain(&'a str, u16),
+}
+
+impl Target<'_> {
+    /// Encode the target as `ATYP | DST.ADDR | DST.PORT`
+    fn encode(&self) -> Result<Vec<u8>, Error> {
+        let (mut encoded, port) = match self {
+            Target::Ip(SocketAddr::V4(addr)) => {
+                let mut encoded = vec![IPV4];
+                encoded.extend_from_slice(&addr.ip().octets());
+                (encoded, addr.port())
+            }
+            Target::Ip(SocketAddr::V6(addr)) => {
+                let mut encoded = vec![IPV6];
+                encoded.extend_from_slice(&addr.ip().octets());
+                (encoded, addr.port())
+            }
+            Target::Domain(host, port) => {
+                if host.is_empty() || host.len() > 255 {
+                    fail!(
+                        ConfigError,
+                        "host name `{}` can't be resolved via a SOCKS5 proxy",
+                        host
+                    );
+                }
+
+                let mut encoded = vec![DOMAIN_NAME, host.len() as u8];
+                encoded.extend_from_slice(host.as_bytes());
+                (encoded, *port)
+            }
+        };
+
+        encoded.extend_from_slice(&port.to_be_bytes());
+        Ok(encoded)
+    }
+}
+
+impl fmt::Display for Target<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Ip(addr) => addr.fmt(f),
+            Target::Domain(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+/// Connect to the proxy and ask it to connect to the given target
+fn connect_to(
+    proxy: &ProxyAddr,
+    target: &Target<'_>,
+    timeout: Duration,
+) -> Result<TcpStream, Error> {
+    let mut socket = super::tcp::connect(&proxy.host, proxy.port, timeout)
+        .map_err(|e| format_err!(ProxyError, "couldn't connect to proxy {}: {}", proxy, e))?;
+
+    socket.set_read_timeout(Some(timeout))?;
+    socket.set_write_timeout(Some(timeout))?;
+
+    negotiate(&mut socket, proxy, target).map_err(|e| match e {
+        Negotiation::Proxy(e) => format_err!(ProxyError, "proxy {}: {}", proxy, e),
+        Negotiation::Target(e) => format_err!(
+            IoError,
+            "couldn't reach validator {} via proxy {}: {}",
+            target,
+            proxy,
+            e
+        ),
+    })?;
+
+    Ok(socket)
+}
+
+/// Reasons a SOCKS5 negotiation fails
+enum Negotiation {
+    /// The proxy failed (or refused us)
+    Proxy(String),
+
+    /// The proxy couldn't reach the target
+    Target(&'static str),
+}
+
+impl From<io::Error> for Negotiation {
+    fn from(e: io::Error) -> Self {
+        Negotiation::Proxy(e.to_string())
+    }
+}
+
+impl From<Error> for Negotiation {
+    fn from(e: Error) -> Self {
+        Negotiation::Proxy(e.to_string())
+    }
+}
+
+/// Authenticate with the proxy and have it connect to the target
+fn negotiate<S: Read + Write>(
+    socket: &mut S,
+    proxy: &ProxyAddr,
+    target: &Target<'_>,
+) -> Result<(), Negotiation> {
+    let methods: &[u8] = match proxy.credentials {
+        Some(_) => &[NO_AUTH, USERNAME_PASSWORD],
+        None => &[NO_AUTH],
+    };
+
+    let mut greeting = vec![VERSION, methods.len() as u8];
+    greeting.extend_from_slice(methods);
+    socket.write_all(&greeting)?;
+
+    let mut choice = [0u8; 2];
+    socket.read_exact(&mut choice)?;
+    check_version(choice[0])?;
+
+    match (choice[1], &proxy.credentials) {
+        (NO_AUTH, _) => (),
+        (USERNAME_PASSWORD, Some((username, password))) => {
+            let mut auth = vec![AUTH_VERSION, username.len() as u8];
+            auth.extend_from_slice(username.as_bytes());
+            auth.push(password.len() as u8);
+            auth.extend_from_slice(password.as_bytes());
+            socket.write_all(&auth)?;
+
+            let mut status = [0u8; 2];
+            socket.read_exact(&mut status)?;
+
+            if status[1] != 0 {
+                return Err(Negotiation::Proxy(
+                    "username/password authentication failed".to_owned(),
+                ));
+            }
+        }
+        (NO_ACCEPTABLE_METHODS, None) => {
+            return Err(Negotiation::Proxy(
+                "authentication required (set credentials in `proxy`)".to_owned(),
+            ))
+        }
+        (method, _) => {
+            return Err(Negotiation::Proxy(format!(
+                "no acceptable authentication method (proxy chose {:#04x})",
+                method
+            )))
+        }
+    }
+
+    let mut request = vec![VERSION, CONNECT, 0x00];
+    request.extend_from_slice(&target.encode()?);
+    socket.write_all(&request)?;
+
+    let mut reply = [0u8; 4];
+    socket.read_exact(&mut reply)?;
+    check_version(reply[0])?;
+
+    match reply[1] {
+        0x00 => (),
+        0x01 => {
+            return Err(Negotiation::Proxy(
+                "general SOCKS server failure".to_owned(),
+            ))
+        }
+        0x02 => {
+            return Err(Negotiation::Proxy(
+                "connection not allowed by ruleset".to_owned(),
+            ))
+        }
+        0x03 => return Err(Negotiation::Target("network unreachable")),
+        0x04 => return Err(Negotiation::Target("host unreachable")),
+        0x05 => return Err(Negotiation::Target("connection refused")),
+        0x06 => return Err(Negotiation::Target("TTL expired")),
+        0x07 => return Err(Negotiation::Proxy("command not supported".to_owned())),
+        0x08 => return Err(Negotiation::Proxy("address type not supported".to_owned())),
+        code => return Err(Negotiation::Proxy(format!("unknown reply {:#04x}", code))),
+    }
+
+    // Skip the address the proxy bound to connect to the target
+    let bound_len = match reply[3] {
+        IPV4 => 4,
+        IPV6 => 16,
+        DOMAIN_NAME => {
+            let mut len = [0u8; 1];
+            socket.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        atyp => {
+            return Err(Negotiation::Proxy(format!(
+                "unknown address type {:#04x} in reply",
+                atyp
+            )))
+        }
+    };
+
+    let mut bound = vec![0u8; bound_len + 2];
+    socket.read_exact(&mut bound)?;
+    Ok(())
+}
+
+/// Ensure the proxy speaks SOCKS5
+fn check_version(version: u8) -> Result<(), Negotiation> {
+    if version == VERSION {
+        Ok(())
+    } else {
+        Err(Negotiation::Proxy(format!(
+            "not a SOCKS5 proxy (version {:#04x})",
+            version
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    /// Start a mock SOCKS5 proxy which expects the given credentials (if
+    /// any), answers the `CONNECT` request with `reply` and then writes
+    /// `hello` as the target, returning its address along with the
+    /// destination it was asked to connect to
+    fn mock_proxy(
+        credentials: Option<(&'static str, &'static str)>,
+        reply: u8,
+    ) -> (ProxyAddr, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let proxy = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+
+            let mut greeting = [0u8; 2];
+            socket.read_exact(&mut greeting).unwrap();
+            let mut methods = vec![0u8; greeting[1] as usize];
+            socket.read_exact(&mut methods).unwrap();
+
+            match credentials {
+                Some((username, password)) => {
+                    assert!(methods.contains(&USERNAME_PASSWORD));
+                    socket.write_all(&[VERSION, USERNAME_PASSWORD]).unwrap();
+
+                    let mut version = [0u8; 1];
+                    socket.read_exact(&mut version).unwrap();
+                    assert_eq!(version[0], AUTH_VERSION);
+
+                    let mut read_field = || {
+                        let mut len = [0u8; 1];
+                        socket.read_exact(&mut len).unwrap();
+                        let mut field = vec![0u8; len[0] as usize];
+                        socket.read_exact(&mut field).unwrap();
+                        field
+                    };
+
+                    let (user, pass) = (read_field(), read_field());
+                    let valid = user == username.as_bytes() && pass == password.as_bytes();
+
+                    socket.write_all(&[AUTH_VERSION, !valid as u8]).unwrap();
+
+                    if !valid {
+                        return vec![];
+                    }
+                }
+                None => socket.write_all(&[VERSION, NO_AUTH]).unwrap(),
+            }
+
+            let mut request = [0u8; 5];
+            socket.read_exact(&mut request).unwrap();
+            let addr_len = match request[3] {
+                IPV4 => 3,
+                IPV6 => 15,
+                DOMAIN_NAME => request[4] as usize,
+                _ => panic!("bad address type"),
+            };
+            let mut rest = vec![0u8; addr_len + 2];
+            socket.read_exact(&mut rest).unwrap();
+
+            socket
+                .write_all(&[VERSION, reply, 0, IPV4, 10, 0, 0, 1, 0x04, 0x38])
+                .unwrap();
+
+            if reply == 0 {
+                socket.write_all(b"hello").unwrap();
+            }
+
+            let mut target = request[3..].to_vec();
+            target.extend_from_slice(&rest);
+            target
+        });
+
+        let proxy_addr = ProxyAddr {
+            host: "127.0.0.1".to_owned(),
+            port,
+            credentials: credentials.map(|(u, p)| (u.to_owned(), p.to_owned())),
+        };
+
+        (proxy_addr, proxy)
+    }
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn connect_resolving_via_proxy() {
+        let (proxy_addr, proxy) = mock_proxy(Some(("kms", "secret")), 0x00);
+        let mut socket = connect(&proxy_addr, "sentry.internal", 26658, true, TIMEOUT).unwrap();
+
+        let mut hello = [0u8; 5];
+        socket.read_exact(&mut hello).unwrap();
+        assert_eq!(&hello, b"hello");
+
+        let mut expected = vec![DOMAIN_NAME, 15];
+        expected.extend_from_slice(b"sentry.internal");
+        expected.extend_from_slice(&26658u16.to_be_bytes());
+        assert_eq!(proxy.join().unwrap(), expected);
+    }
+
+    #[test]
+    fn connect_ip() {
+        let (proxy_addr, proxy) = mock_proxy(None, 0x00);
+        connect(&proxy_addr, "10.1.2.3", 26658, true, TIMEOUT).unwrap();
+
+        assert_eq!(
+            proxy.join().unwrap(),
+            [IPV4, 10, 1, 2, 3, 0x68, 0x22].to_vec()
+        );
+    }
+
+    #[test]
+    fn proxy_failures_are_distinguished() {
+        let (proxy_addr, proxy) = mock_proxy(Some(("kms", "secret")), 0x00);
+        let wrong_password = ProxyAddr {
+            credentials: Some(("kms".to_owned(), "wrong".to_owned())),
+            ..proxy_addr
+        };
+
+        let err = connect(&wrong_password, "10.1.2.3", 26658, false, TIMEOUT)
+            .err()
+            .unwrap();
+        proxy.join().unwrap();
+        assert_eq!(*err.kind(), ProxyError);
+        assert!(err.to_string().contains("authentication failed"));
+        assert!(!err.to_string().contains("wrong"));
+
+        // The proxy can't reach the validator
+        let (proxy_addr, proxy) = mock_proxy(None, 0x05);
+        let err = connect(&proxy_addr, "10.1.2.3", 26658, false, TIMEOUT)
+            .err()
+            .unwrap();
+        proxy.join().unwrap();
+        assert_eq!(*err.kind(), IoError);
+        assert!(err
+            .to_string()
+            .contains("couldn't reach validator 10.1.2.3:26658 via proxy"));
+
+        // Nothing listening on the proxy's port
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let unreachable = ProxyAddr {
+            host: "127.0.0.1".to_owned(),
+            port,
+            credentials: None,
+        };
+        let err = connect(&unreachable, "10.1.2.3", 26658, false, TIMEOUT)
+            .err()
+            .unwrap();
+        assert_eq!(*err.kind(), ProxyError);
+    }
+}
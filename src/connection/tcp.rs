@@ -1,31 +1,98 @@
 //! TCP socket connection to a validator
 
-use std::{net::TcpStream, path::PathBuf, time::Duration};
+use std::{
+    io,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
 
+use ed25519_dalek as ed25519;
+use socket2::{SockRef, TcpKeepalive};
 use subtle::ConstantTimeEq;
+use subtle_encoding::hex;
 use tendermint::node;
 use tendermint_p2p::error::ErrorDetail as TmError;
 use tendermint_p2p::secret_connection::{self, PublicKey, SecretConnection};
 
+use super::socks5;
 use crate::{
+    config::{PeerIdVerification, ValidatorConfig},
     error::{Error, ErrorKind::*},
-    key_utils,
+    key_utils, metrics,
     prelude::*,
 };
 
-/// Default timeout in seconds
-const DEFAULT_TIMEOUT: u16 = 10;
+/// Number of unanswered TCP keepalive probes after which the connection is
+/// considered dead
+pub const KEEPALIVE_RETRIES: u32 = 3;
 
-/// Open a TCP socket connection encrypted with SecretConnection
+/// Open a TCP socket connection to the validator at the given host and port
+/// (through its `proxy`, if configured) encrypted with SecretConnection,
+/// returning it along with a handle to the socket which can be used to
+/// interrupt it
 pub fn open_secret_connection(
     host: &str,
     port: u16,
-    identity_key_path: &Option<PathBuf>,
     peer_id: &Option<node::Id>,
-    timeout: Option<u16>,
-    protocol_version: secret_connection::Version,
-) -> Result<SecretConnection<TcpStream>, Error> {
-    let identity_key_path = identity_key_path.as_ref().ok_or_else(|| {
+    config: &ValidatorConfig,
+) -> Result<(SecretConnection<TcpStream>, TcpStream), Error> {
+    let identity_key = load_identity_key(config, host, port)?;
+
+    let timeout = super::timeout(config.timeout);
+    let socket = match &config.proxy {
+        Some(proxy) => socks5::connect(proxy, host, port, config.resolve_via_proxy, timeout)?,
+        None => connect(host, port, timeout)?,
+    };
+    let interrupt = socket.try_clone()?;
+
+    let connection = handshake(
+        socket,
+        identity_key,
+        peer_id,
+        config.peer_id_verification,
+        timeout,
+        config.protocol_version.into(),
+        &format!("{}:{}", host, port),
+    )?;
+
+    Ok((connection, interrupt))
+}
+
+/// Accept a TCP connection from a validator on the given listener and
+/// encrypt it with SecretConnection, returning it along with a handle to the
+/// socket which can be used to interrupt it
+pub fn accept_secret_connection(
+    listener: &TcpListener,
+    peer_id: &Option<node::Id>,
+    config: &ValidatorConfig,
+) -> Result<(SecretConnection<TcpStream>, TcpStream), Error> {
+    let local_addr = listener.local_addr()?;
+    let identity_key = load_identity_key(config, &local_addr.ip().to_string(), local_addr.port())?;
+
+    let (socket, remote_addr) = listener.accept()?;
+    debug!("accepted connection on {} from {}", local_addr, remote_addr);
+    let interrupt = socket.try_clone()?;
+
+    let connection = handshake(
+        socket,
+        identity_key,
+        peer_id,
+        config.peer_id_verification,
+        super::timeout(config.timeout),
+        config.protocol_version.into(),
+        &remote_addr.to_string(),
+    )?;
+
+    Ok((connection, interrupt))
+}
+
+/// Load the KMS's Secret Connection identity key (`secret_key`)
+fn load_identity_key(
+    config: &ValidatorConfig,
+    host: &str,
+    port: u16,
+) -> Result<ed25519::Keypair, Error> {
+    let identity_key_path = config.secret_key.as_ref().ok_or_else(|| {
         format_err!(
             ConfigError,
             "config error: no `secret_key` for validator: {}:{}",
@@ -34,11 +101,26 @@ pub fn open_secret_connection(
         )
     })?;
 
-    let identity_key = key_utils::load_base64_ed25519_key(identity_key_path)?;
+    let identity_key = key_utils::load_identity_key(
+        identity_key_path,
+        config.secret_key_format.unwrap_or_default(),
+        config.secret_key_passphrase_file.as_deref(),
+    )?;
     info!("KMS node ID: {}", PublicKey::from(&identity_key));
+    Ok(identity_key)
+}
 
-    let socket = TcpStream::connect(format!("{}:{}", host, port))?;
-    let timeout = Duration::from_secs(timeout.unwrap_or(DEFAULT_TIMEOUT).into());
+/// Perform the Secret Connection handshake on the given socket, verifying the
+/// peer ID of the validator (`peer`) if one is expected
+pub fn handshake(
+    socket: TcpStream,
+    identity_key: ed25519::Keypair,
+    peer_id: &Option<node::Id>,
+    peer_id_verification: PeerIdVerification,
+    timeout: Duration,
+    protocol_version: secret_connection::Version,
+    peer: &str,
+) -> Result<SecretConnection<TcpStream>, Error> {
     socket.set_read_timeout(Some(timeout))?;
     socket.set_write_timeout(Some(timeout))?;
 
@@ -51,21 +133,199 @@ pub fn open_secret_connection(
             _ => fail!(ProtocolError, format!("{}", error)),
         },
     };
-    let actual_peer_id = connection.remote_pubkey().peer_id();
 
     // TODO(tarcieri): move this into `SecretConnection::new`
     if let Some(expected_peer_id) = peer_id {
-        if expected_peer_id.ct_eq(&actual_peer_id).unwrap_u8() == 0 {
-            fail!(
-                VerificationError,
-                "{}:{}: validator peer ID mismatch! (expected {}, got {})",
-                host,
-                port,
-                expected_peer_id,
-                actual_peer_id
+        verify_peer_id(
+            expected_peer_id,
+            connection.remote_pubkey(),
+            peer_id_verification,
+            peer,
+        )?;
+    }
+
+    Ok(connection)
+}
+
+/// Ensure the public key presented by the validator (`peer`) matches the
+/// expected peer ID, or only log a warning about it if verification is set to
+/// [`PeerIdVerification::Warn`]
+fn verify_peer_id(
+    expected_peer_id: &node::Id,
+    remote_pubkey: PublicKey,
+    peer_id_verification: PeerIdVerification,
+    peer: &str,
+) -> Result<(), Error> {
+    let actual_peer_id = remote_pubkey.peer_id();
+
+    if expected_peer_id.ct_eq(&actual_peer_id).unwrap_u8() == 1 {
+        return Ok(());
+    }
+
+    metrics::peer_id_mismatch(&expected_peer_id.to_string());
+
+    let remote_pubkey_hex = remote_pubkey
+        .ed25519()
+        .map(|pk| String::from_utf8(hex::encode_upper(pk.as_bytes())).unwrap())
+        .unwrap_or_default();
+
+    let message = format!(
+        "{}: validator peer ID mismatch! (configured {}, observed {} with public key {})",
+        peer, expected_peer_id, actual_peer_id, remote_pubkey_hex
+    );
+
+    match peer_id_verification {
+        PeerIdVerification::Enforce => fail!(VerificationError, message),
+        PeerIdVerification::Warn => {
+            warn!(
+                "{} (continuing as peer_id_verification = \"warn\")",
+                message
             );
+            Ok(())
         }
     }
+}
 
-    Ok(connection)
+/// Connect to the given host and port, trying each address it resolves to
+/// and giving up on each after the given timeout
+pub fn connect(host: &str, port: u16, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_err = None;
+
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(socket) => return Ok(socket),
+            Err(e) => last_err = Some(e),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{}:{} did not resolve to any addresses", host, port),
+        )
+    }))
+}
+
+/// Enable TCP keepalive on the given socket (if an interval is configured),
+/// sending a probe once it's been idle for `interval_secs` and again every
+/// `interval_secs` after that.
+///
+/// If [`KEEPALIVE_RETRIES`] probes go unanswered, the kernel resets the
+/// connection, failing any blocked read so the validator is reconnected to.
+pub fn set_keepalive(socket: &TcpStream, interval_secs: Option<u16>) -> io::Result<()> {
+    let interval = match interval_secs {
+        Some(secs) => Duration::from_secs(secs.into()),
+        None => return Ok(()),
+    };
+
+    let keepalive = TcpKeepalive::new()
+        .with_time(interval)
+        .with_interval(interval)
+        .with_retries(KEEPALIVE_RETRIES);
+
+    SockRef::from(socket).set_tcp_keepalive(&keepalive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::{OsRng, RngCore};
+    use std::thread;
+
+    /// Generate a random identity key
+    fn generate_key() -> ed25519::Keypair {
+        let mut bytes = [0u8; ed25519::SECRET_KEY_LENGTH];
+        OsRng.fill_bytes(&mut bytes);
+
+        let secret = ed25519::SecretKey::from_bytes(&bytes).unwrap();
+        let public = ed25519::PublicKey::from(&secret);
+        ed25519::Keypair { secret, public }
+    }
+
+    /// Perform a handshake with a validator presenting the given identity
+    /// while expecting `expected_peer_id`
+    fn handshake_with(
+        validator_key: ed25519::Keypair,
+        expected_peer_id: node::Id,
+        peer_id_verification: PeerIdVerification,
+    ) -> Result<SecretConnection<TcpStream>, Error> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let validator = thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            SecretConnection::new(socket, validator_key, secret_connection::Version::V0_34)
+                .map(|_| ())
+        });
+
+        let socket = TcpStream::connect(addr).unwrap();
+        let result = handshake(
+            socket,
+            generate_key(),
+            &Some(expected_peer_id),
+            peer_id_verification,
+            Duration::from_secs(5),
+            secret_connection::Version::V0_34,
+            &addr.to_string(),
+        );
+
+        validator.join().unwrap().unwrap();
+        result
+    }
+
+    #[test]
+    fn peer_id_mismatch_is_refused() {
+        let validator_key = generate_key();
+        let observed_peer_id = PublicKey::from(&validator_key).peer_id();
+        let observed_pubkey =
+            String::from_utf8(hex::encode_upper(validator_key.public.as_bytes())).unwrap();
+        let configured_peer_id = PublicKey::from(&generate_key()).peer_id();
+
+        let err = handshake_with(
+            validator_key,
+            configured_peer_id,
+            PeerIdVerification::Enforce,
+        )
+        .err()
+        .expect("mismatched peer ID accepted");
+
+        assert_eq!(*err.kind(), VerificationError);
+
+        let message = err.to_string();
+        assert!(message.contains(&format!("configured {}", configured_peer_id)));
+        assert!(message.contains(&format!("observed {}", observed_peer_id)));
+        assert!(message.contains(&observed_pubkey));
+    }
+
+    #[test]
+    fn peer_id_mismatch_is_allowed_with_warn() {
+        let validator_key = generate_key();
+        let configured_peer_id = PublicKey::from(&generate_key()).peer_id();
+
+        let connection =
+            handshake_with(validator_key, configured_peer_id, PeerIdVerification::Warn).unwrap();
+
+        assert_ne!(connection.remote_pubkey().peer_id(), configured_peer_id);
+    }
+
+    #[test]
+    fn keepalive_is_configured() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        set_keepalive(&socket, None).unwrap();
+        assert!(!SockRef::from(&socket).keepalive().unwrap());
+
+        // Set through a clone, as with the interrupt handle of a connection
+        set_keepalive(&socket.try_clone().unwrap(), Some(30)).unwrap();
+
+        let socket = SockRef::from(&socket);
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(30));
+        assert_eq!(
+            socket.keepalive_interval().unwrap(),
+            Duration::from_secs(30)
+        );
+        assert_eq!(socket.keepalive_retries().unwrap(), KEEPALIVE_RETRIES);
+    }
 }
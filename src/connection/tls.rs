@@ -0,0 +1,254 @@
+//! TCP connection to a validator encrypted with mutual TLS, for privval
+//! proxies which terminate TLS rather than Secret Connection
+
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{self, BufReader},
+    net::{IpAddr, TcpStream},
+    path::Path,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, ClientConnection, PrivateKey, RootCertStore, ServerName,
+    StreamOwned,
+};
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+use crate::{
+    config::ValidatorConfig,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+
+/// TLS connection to a validator
+pub type TlsConnection = StreamOwned<ClientConnection, TcpStream>;
+
+/// Open a TCP connection to the validator at the given host and port and
+/// complete the TLS handshake, returning the connection along with a handle
+/// to the socket which can be used to interrupt it.
+///
+/// The validator's certificate must be issued by `tls_ca` and valid for
+/// `host`, or the connection fails with a `TlsVerificationError`.
+pub fn open_tls_connection(
+    host: &str,
+    port: u16,
+    config: &ValidatorConfig,
+) -> Result<(TlsConnection, TcpStream), Error> {
+    let client_config = client_config(config)?;
+
+    let server_name = ServerName::try_from(host).map_err(|_| {
+        format_err!(
+            ConfigError,
+            "invalid hostname for TLS validator {}:{}",
+            host,
+            port
+        )
+    })?;
+
+    let timeout = super::timeout(config.timeout);
+    let mut socket = super::tcp::connect(host, port, timeout)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.set_write_timeout(Some(timeout))?;
+    let interrupt = socket.try_clone()?;
+
+    let mut connection = ClientConnection::new(Arc::new(client_config), server_name)
+        .map_err(|e| format_err!(ProtocolError, "{}:{}: {}", host, port, e))?;
+
+    // Complete the handshake now, so an untrusted validator is reported as
+    // such rather than as a failed request
+    while connection.is_handshaking() {
+        connection
+            .complete_io(&mut socket)
+            .map_err(|e| handshake_error(e, host, port))?;
+    }
+
+    Ok((StreamOwned::new(connection, socket), interrupt))
+}
+
+/// Build the TLS client configuration for the given validator from its
+/// `tls_ca`, `tls_client_cert`, and `tls_client_key` files
+pub fn client_config(config: &ValidatorConfig) -> Result<ClientConfig, Error> {
+    let (ca_path, cert_path, key_path) = match (
+        &config.tls_ca,
+        &config.tls_client_cert,
+        &config.tls_client_key,
+    ) {
+        (Some(ca), Some(cert), Some(key)) => (ca, cert, key),
+        _ => fail!(
+            ConfigError,
+            "`tls_ca`, `tls_client_cert`, and `tls_client_key` are required for {}",
+            config.addr
+        ),
+    };
+
+    let mut roots = RootCertStore::empty();
+
+    for cert in load_certs(ca_path)? {
+        roots.add(&cert).map_err(|e| {
+            format_err!(
+                ConfigError,
+                "invalid CA certificate in {}: {}",
+                ca_path.display(),
+                e
+            )
+        })?;
+    }
+
+    let verifier = SanReportingVerifier(WebPkiVerifier::new(roots, None));
+
+    ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_single_cert(load_certs(cert_path)?, load_private_key(key_path)?)
+        .map_err(|e| {
+            format_err!(
+                ConfigError,
+                "invalid TLS client certificate or key ({}, {}): {}",
+                cert_path.display(),
+                key_path.display(),
+                e
+            )
+            .into()
+        })
+}
+
+/// Load the PEM-encoded certificates in the given file
+fn load_certs(path: &Path) -> Result<Vec<Certificate>, Error> {
+    let certs = File::open(path)
+        .and_then(|file| rustls_pemfile::certs(&mut BufReader::new(file)))
+        .map_err(|e| format_err!(ConfigError, "couldn't read {}: {}", path.display(), e))?;
+
+    if certs.is_empty() {
+        fail!(ConfigError, "no certificates found in {}", path.display());
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Load the (first) PEM-encoded private key in the given file
+fn load_private_key(path: &Path) -> Result<PrivateKey, Error> {
+    let mut reader = BufReader::new(
+        File::open(path)
+            .map_err(|e| format_err!(ConfigError, "couldn't read {}: {}", path.display(), e))?,
+    );
+
+    loop {
+        match rustls_pemfile::read_one(&mut reader)
+            .map_err(|e| format_err!(ConfigError, "couldn't read {}: {}", path.display(), e))?
+        {
+            Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => fail!(ConfigError, "no private key found in {}", path.display()),
+        }
+    }
+}
+
+/// Convert an I/O error during the TLS handshake into an `Error`, which is a
+/// `TlsVerificationError` if the validator's certificate was rejected
+fn handshake_error(error: io::Error, host: &str, port: u16) -> Error {
+    let tls_error = error
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<rustls::Error>());
+
+    match tls_error {
+        Some(rustls::Error::InvalidCertificateData(reason)) => format_err!(
+            TlsVerificationError,
+            "validator {}:{} presented an untrusted certificate: {}",
+            host,
+            port,
+            reason
+        )
+        .into(),
+        Some(e) => format_err!(
+            ProtocolError,
+            "TLS handshake with {}:{} failed: {}",
+            host,
+            port,
+            e
+        )
+        .into(),
+        None => format_err!(
+            IoError,
+            "TLS handshake with {}:{} failed: {}",
+            host,
+            port,
+            error
+        )
+        .into(),
+    }
+}
+
+/// Server certificate verifier which includes the subject alternative names
+/// presented by the validator in verification errors, so a certificate
+/// issued for the wrong name can be told apart from one from the wrong CA
+struct SanReportingVerifier(WebPkiVerifier);
+
+impl ServerCertVerifier for SanReportingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.0
+            .verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            )
+            .map_err(|e| {
+                let reason = match e {
+                    rustls::Error::InvalidCertificateData(reason) => reason,
+                    other => other.to_string(),
+                };
+
+                rustls::Error::InvalidCertificateData(format!(
+                    "{} (presented SANs: [{}])",
+                    reason,
+                    subject_alt_names(end_entity).join(", ")
+                ))
+            })
+    }
+}
+
+/// Get the subject alternative names of the given certificate (e.g.
+/// `DNS:validator.example.com`), for error messages
+fn subject_alt_names(cert: &Certificate) -> Vec<String> {
+    let cert = match X509Certificate::from_der(&cert.0) {
+        Ok((_, cert)) => cert,
+        Err(_) => return vec!["<unparseable certificate>".to_owned()],
+    };
+
+    let sans = match cert.subject_alternative_name() {
+        Ok(Some(sans)) => sans,
+        _ => return vec![],
+    };
+
+    sans.value
+        .general_names
+        .iter()
+        .map(|name| match name {
+            GeneralName::DNSName(name) => format!("DNS:{}", name),
+            GeneralName::IPAddress(bytes) => match <[u8; 4]>::try_from(*bytes) {
+                Ok(ip) => format!("IP:{}", IpAddr::from(ip)),
+                Err(_) => match <[u8; 16]>::try_from(*bytes) {
+                    Ok(ip) => format!("IP:{}", IpAddr::from(ip)),
+                    Err(_) => name.to_string(),
+                },
+            },
+            other => other.to_string(),
+        })
+        .collect()
+}
@@ -2,6 +2,10 @@
 
 use std::io;
 use std::marker::{Send, Sync};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+
+use crate::{error::Error, privileges};
 
 /// Protocol implementation of the UNIX socket domain connection
 pub struct UnixConnection<IoHandler> {
@@ -18,6 +22,35 @@ where
     }
 }
 
+impl UnixConnection<UnixStream> {
+    /// Connect to the Unix domain socket at the given path, with the given
+    /// read/write timeout (in seconds)
+    pub fn connect(path: impl AsRef<Path>, timeout: Option<u16>) -> Result<Self, Error> {
+        let socket = UnixStream::connect(privileges::resolve(path.as_ref()))?;
+        Self::with_timeout(socket, timeout)
+    }
+
+    /// Accept a connection from a validator on the given listener, with the
+    /// given read/write timeout (in seconds)
+    pub fn accept(listener: &UnixListener, timeout: Option<u16>) -> Result<Self, Error> {
+        let (socket, _) = listener.accept()?;
+        Self::with_timeout(socket, timeout)
+    }
+
+    /// Get a handle which can be used to interrupt this connection
+    pub fn interrupt_handle(&self) -> io::Result<UnixStream> {
+        self.socket.try_clone()
+    }
+
+    /// Apply the given read/write timeout (in seconds) to the socket
+    fn with_timeout(socket: UnixStream, timeout: Option<u16>) -> Result<Self, Error> {
+        let timeout = super::timeout(timeout);
+        socket.set_read_timeout(Some(timeout))?;
+        socket.set_write_timeout(Some(timeout))?;
+        Ok(Self::new(socket))
+    }
+}
+
 impl<IoHandler> io::Read for UnixConnection<IoHandler>
 where
     IoHandler: io::Read + io::Write + Send + Sync,
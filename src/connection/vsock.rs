@@ -0,0 +1,198 @@
+//! Virtual socket (`AF_VSOCK`) connection to a validator.
+//!
+//! This is intended for running the KMS inside an AWS Nitro Enclave, whose
+//! only means of communication is a vsock to its parent instance. The parent
+//! proxies the privval protocol between the enclave and the validator.
+
+use std::{
+    io, mem,
+    os::{raw::c_int, unix::io::RawFd},
+    time::Duration,
+};
+
+use crate::{error::Error, prelude::*};
+
+/// Maximum number of pending connections on a listener
+const LISTEN_BACKLOG: c_int = 128;
+
+/// Virtual socket file descriptor, which is closed when dropped
+struct Socket(RawFd);
+
+impl Socket {
+    /// Create a new stream socket
+    fn new() -> io::Result<Self> {
+        let fd = cvt(unsafe {
+            libc::socket(libc::AF_VSOCK, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0)
+        })?;
+
+        Ok(Socket(fd))
+    }
+
+    /// Set the given timeout socket option (`SO_RCVTIMEO` or `SO_SNDTIMEO`)
+    fn set_timeout(&self, option: c_int, timeout: Duration) -> io::Result<()> {
+        let timeval = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        };
+
+        cvt(unsafe {
+            libc::setsockopt(
+                self.0,
+                libc::SOL_SOCKET,
+                option,
+                &timeval as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        })?;
+
+        Ok(())
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+/// Listener for vsock connections from the parent instance
+pub struct VsockListener {
+    socket: Socket,
+}
+
+impl VsockListener {
+    /// Bind to the given context ID and port
+    pub fn bind(cid: u32, port: u32) -> Result<Self, Error> {
+        let socket = Socket::new()?;
+        let addr = sockaddr(cid, port);
+
+        cvt(unsafe {
+            libc::bind(
+                socket.0,
+                &addr as *const libc::sockaddr_vm as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+            )
+        })?;
+
+        cvt(unsafe { libc::listen(socket.0, LISTEN_BACKLOG) })?;
+        Ok(Self { socket })
+    }
+
+    /// Accept a connection, returning the socket and the peer's context ID
+    fn accept(&self) -> io::Result<(Socket, u32)> {
+        let mut addr = sockaddr(0, 0);
+        let mut addr_len = mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t;
+
+        let fd = cvt(unsafe {
+            libc::accept4(
+                self.socket.0,
+                &mut addr as *mut libc::sockaddr_vm as *mut libc::sockaddr,
+                &mut addr_len,
+                libc::SOCK_CLOEXEC,
+            )
+        })?;
+
+        Ok((Socket(fd), addr.svm_cid))
+    }
+}
+
+/// Protocol implementation of the vsock connection
+pub struct VsockConnection {
+    socket: Socket,
+}
+
+impl VsockConnection {
+    /// Connect to the given context ID and port, with the given read/write
+    /// timeout (in seconds)
+    pub fn connect(cid: u32, port: u32, timeout: Option<u16>) -> Result<Self, Error> {
+        let socket = Socket::new()?;
+        let addr = sockaddr(cid, port);
+
+        cvt(unsafe {
+            libc::connect(
+                socket.0,
+                &addr as *const libc::sockaddr_vm as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+            )
+        })?;
+
+        Self::with_timeout(socket, timeout)
+    }
+
+    /// Accept a connection from a validator on the given listener, with the
+    /// given read/write timeout (in seconds)
+    pub fn accept(listener: &VsockListener, timeout: Option<u16>) -> Result<Self, Error> {
+        let (socket, cid) = listener.accept()?;
+        debug!("accepted vsock connection from CID {}", cid);
+        Self::with_timeout(socket, timeout)
+    }
+
+    /// Apply the given read/write timeout (in seconds) to the socket
+    fn with_timeout(socket: Socket, timeout: Option<u16>) -> Result<Self, Error> {
+        let timeout = super::timeout(timeout);
+        socket.set_timeout(libc::SO_RCVTIMEO, timeout)?;
+        socket.set_timeout(libc::SO_SNDTIMEO, timeout)?;
+        Ok(Self { socket })
+    }
+}
+
+impl io::Read for VsockConnection {
+    fn read(&mut self, data: &mut [u8]) -> Result<usize, io::Error> {
+        let len = unsafe {
+            libc::read(
+                self.socket.0,
+                data.as_mut_ptr() as *mut libc::c_void,
+                data.len(),
+            )
+        };
+
+        if len < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(len as usize)
+        }
+    }
+}
+
+impl io::Write for VsockConnection {
+    fn write(&mut self, data: &[u8]) -> Result<usize, io::Error> {
+        let len = unsafe {
+            libc::write(
+                self.socket.0,
+                data.as_ptr() as *const libc::c_void,
+                data.len(),
+            )
+        };
+
+        if len < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(len as usize)
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+}
+
+/// Build a vsock socket address
+fn sockaddr(cid: u32, port: u32) -> libc::sockaddr_vm {
+    // Zero the reserved fields
+    let mut addr: libc::sockaddr_vm = unsafe { mem::zeroed() };
+    addr.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+    addr.svm_cid = cid;
+    addr.svm_port = port;
+    addr
+}
+
+/// Convert the return value of a libc call into an `io::Result`
+fn cvt(ret: c_int) -> io::Result<c_int> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
@@ -0,0 +1,221 @@
+//! Control socket: a Unix domain socket accepting administrative commands
+//! (e.g. `tmkms rotate`) while the KMS is running.
+//!
+//! Each connection carries a single JSON-encoded [`Request`], terminated by
+//! the client shutting down its end for writing, which is answered with a
+//! single JSON-encoded [`Response`].
+
+use crate::{
+    chain,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{Read, Write},
+    net::Shutdown,
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+/// Maximum size of a request
+const MAX_REQUEST_SIZE: u64 = 4096;
+
+/// Timeout for reading a request or writing a response
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Command sent over the control socket
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Rotate a chain's active consensus key to one of its standby keys
+    RotateKey {
+        /// Chain whose consensus key is rotated
+        chain_id: chain::Id,
+
+        /// ID of the standby key to make active
+        key_id: String,
+
+        /// Permit rotating to a key with a different public key
+        allow_pubkey_change: bool,
+    },
+}
+
+/// Result of a [`Request`]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    /// Request succeeded
+    Ok {
+        /// Description of what was done
+        message: String,
+    },
+
+    /// Request failed
+    Error {
+        /// Reason the request failed
+        message: String,
+    },
+}
+
+/// Listen for requests on the control socket at the given path on a new
+/// thread. The socket is only accessible to the KMS's own user.
+pub fn spawn_server(path: &Path) -> Result<(), Error> {
+    let listener = bind(path)?;
+    info!("listening for admin commands on {}", path.display());
+
+    thread::Builder::new()
+        .name("control".to_owned())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.map_err(Error::from).and_then(handle_connection);
+
+                if let Err(e) = result {
+                    warn!("error serving control socket request: {}", e);
+                }
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Bind a Unix domain socket at the given path which is only accessible to
+/// the KMS's own user, replacing a socket left behind by a previous run
+pub(crate) fn bind(path: &Path) -> Result<UnixListener, Error> {
+    // Remove a socket left behind by a previous run (but nothing else)
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            fail!(
+                ConfigError,
+                "socket path {} exists and isn't a socket",
+                path.display()
+            );
+        }
+
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .map_err(|e| format_err!(IoError, "couldn't bind socket {}: {}", path.display(), e))?;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Send a request to the control socket at the given path, returning the
+/// KMS's response
+pub fn send(path: &Path, request: &Request) -> Result<Response, Error> {
+    let mut stream = UnixStream::connect(path).map_err(|e| {
+        format_err!(
+            IoError,
+            "couldn't connect to control socket {} (is tmkms running?): {}",
+            path.display(),
+            e
+        )
+    })?;
+
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    stream.write_all(&serde_json::to_vec(request)?)?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    Ok(serde_json::from_slice(&response)?)
+}
+
+/// Handle a single request to the control socket
+fn handle_connection(mut stream: UnixStream) -> Result<(), Error> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut request = Vec::new();
+    (&stream).take(MAX_REQUEST_SIZE).read_to_end(&mut request)?;
+
+    let response = match serde_json::from_slice(&request) {
+        Ok(request) => handle_request(request),
+        Err(e) => Response::Error {
+            message: format!("malformed request: {}", e),
+        },
+    };
+
+    stream.write_all(&serde_json::to_vec(&response)?)?;
+    Ok(())
+}
+
+/// Perform the given request
+fn handle_request(request: Request) -> Response {
+    match request {
+        Request::RotateKey {
+            chain_id,
+            key_id,
+            allow_pubkey_change,
+        } => match chain::rotate_consensus_key(&chain_id, &key_id, allow_pubkey_change) {
+            Ok(rotation) => {
+                let message = format!(
+                    "[{}] rotated consensus key from {} to {} ({})",
+                    chain_id, rotation.from, key_id, rotation.public_key
+                );
+
+                if rotation.pubkey_changed {
+                    warn!("{} - validator public key changed!", message);
+                } else {
+                    info!("{}", message);
+                }
+
+                Response::Ok { message }
+            }
+            Err(e) => {
+                warn!("[{}] couldn't rotate consensus key: {}", chain_id, e);
+                Response::Error {
+                    message: e.to_string(),
+                }
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate_unregistered_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        spawn_server(&path).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let request = Request::RotateKey {
+            chain_id: "control-test-chain".parse().unwrap(),
+            key_id: "backup".to_owned(),
+            allow_pubkey_change: false,
+        };
+
+        match send(&path, &request).unwrap() {
+            Response::Error { message } => assert!(message.contains("unregistered chain")),
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn request_json() {
+        let request: Request = serde_json::from_str(
+            r#"{"command":"rotate_key","chain_id":"cosmoshub-4","key_id":"backup","allow_pubkey_change":true}"#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            request,
+            Request::RotateKey { allow_pubkey_change: true, ref key_id, .. } if key_id == "backup"
+        ));
+    }
+}
@@ -17,6 +17,16 @@ pub enum ErrorKind {
     #[error("access denied")]
     AccessError,
 
+    /// Error in the AWS KMS provider
+    #[cfg(feature = "awskms")]
+    #[error("AWS KMS error")]
+    AwsKmsError,
+
+    /// Error in the Azure Key Vault provider
+    #[cfg(feature = "azurekv")]
+    #[error("Azure Key Vault error")]
+    AzureKvError,
+
     /// Invalid Chain ID
     #[error("chain ID error")]
     ChainIdError,
@@ -33,6 +43,11 @@ pub enum ErrorKind {
     #[error("requested signature above stop height")]
     ExceedMaxHeight,
 
+    /// Error in the Google Cloud KMS provider
+    #[cfg(feature = "gcpkms")]
+    #[error("Google Cloud KMS error")]
+    GcpKmsError,
+
     /// Cryptographic operation failed
     #[error("cryptographic error")]
     CryptoError,
@@ -47,7 +62,6 @@ pub enum ErrorKind {
     HookError,
 
     /// Error making an HTTP request
-    #[cfg(feature = "tx-signer")]
     #[error("HTTP error")]
     HttpError,
 
@@ -63,6 +77,11 @@ pub enum ErrorKind {
     #[error("I/O error")]
     IoError,
 
+    /// Request from a validator of an unknown type, or which couldn't be
+    /// decoded
+    #[error("malformed request")]
+    MalformedRequest,
+
     /// KMS internal panic
     #[error("internal crash")]
     PanicError,
@@ -71,14 +90,32 @@ pub enum ErrorKind {
     #[error("parse error")]
     ParseError,
 
+    /// Error in the PKCS#11 provider
+    #[cfg(feature = "pkcs11")]
+    #[error("PKCS#11 error")]
+    Pkcs11Error,
+
     /// KMS state has been poisoned
     #[error("internal state poisoned")]
     PoisonError,
 
+    /// Couldn't drop privileges after startup
+    #[error("couldn't drop privileges")]
+    PrivilegeError,
+
     /// Network protocol-related errors
     #[error("protocol error")]
     ProtocolError,
 
+    /// Couldn't connect to (or negotiate with) a SOCKS5 proxy
+    #[error("SOCKS5 proxy error")]
+    ProxyError,
+
+    /// Redis state store errors
+    #[cfg(feature = "redis")]
+    #[error("Redis error")]
+    RedisError,
+
     /// Serialization error
     #[error("serialization error")]
     SerializationError,
@@ -87,6 +124,19 @@ pub enum ErrorKind {
     #[error("signing operation failed")]
     SigningError,
 
+    /// Couldn't set the ownership or permissions of a listener's socket
+    #[error("couldn't set socket ownership or permissions")]
+    SocketPermissionError,
+
+    /// Another process holds the lock on a chain's consensus state
+    #[error("consensus state locked")]
+    StateLockError,
+
+    /// SQLite state store errors
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error")]
+    SqliteError,
+
     /// Error parsing/serializing a StdTx
     #[cfg(feature = "tx-signer")]
     #[error("stdtx error")]
@@ -96,6 +146,26 @@ pub enum ErrorKind {
     #[error("Tendermint error")]
     TendermintError,
 
+    /// Error in the threshold signing provider
+    #[cfg(feature = "threshold")]
+    #[error("threshold signing error")]
+    ThresholdError,
+
+    /// A TLS validator's certificate failed verification
+    #[cfg(feature = "tls")]
+    #[error("TLS certificate verification failed")]
+    TlsVerificationError,
+
+    /// Error sealing or unsealing a key with a TPM
+    #[cfg(feature = "tpm")]
+    #[error("TPM error")]
+    TpmError,
+
+    /// Error in the HashiCorp Vault provider
+    #[cfg(feature = "vault")]
+    #[error("Vault error")]
+    VaultError,
+
     /// Verification operation failed
     #[error("verification failed")]
     VerificationError,
@@ -111,6 +181,17 @@ impl ErrorKind {
     pub fn context(self, source: impl Into<BoxError>) -> Context<ErrorKind> {
         Context::new(self, Some(source.into()))
     }
+
+    /// Is this error unrecoverable, i.e. would reconnecting to the validator
+    /// only fail the same way?
+    pub fn is_fatal(self) -> bool {
+        match self {
+            ErrorKind::PoisonError | ErrorKind::SocketPermissionError => true,
+            #[cfg(feature = "tls")]
+            ErrorKind::TlsVerificationError => true,
+            _ => false,
+        }
+    }
 }
 
 /// Error type
@@ -164,14 +245,14 @@ impl From<Context<ErrorKind>> for Error {
     }
 }
 
-#[cfg(feature = "tx-signer")]
+#[cfg(any(feature = "alerts", feature = "tx-signer"))]
 impl From<hyper::Error> for Error {
     fn from(other: hyper::Error) -> Self {
         ErrorKind::HttpError.context(other).into()
     }
 }
 
-#[cfg(feature = "tx-signer")]
+#[cfg(any(feature = "alerts", feature = "tx-signer"))]
 impl From<hyper::http::Error> for Error {
     fn from(other: hyper::http::Error) -> Self {
         ErrorKind::HttpError.context(other).into()
@@ -220,6 +301,20 @@ impl From<serde_json::error::Error> for Error {
     }
 }
 
+#[cfg(feature = "redis")]
+impl From<redis::RedisError> for Error {
+    fn from(other: redis::RedisError) -> Self {
+        ErrorKind::RedisError.context(other).into()
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(other: rusqlite::Error) -> Self {
+        ErrorKind::SqliteError.context(other).into()
+    }
+}
+
 #[cfg(feature = "tx-signer")]
 impl From<stdtx::error::Report> for Error {
     fn from(other: stdtx::error::Report) -> Self {
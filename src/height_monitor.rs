@@ -0,0 +1,342 @@
+//! Advisory monitoring of how far each chain's last signed height is from the
+//! network's.
+//!
+//! Chains with an `rpc_endpoint` have a dedicated thread poll the node's
+//! `/status` for its latest block height, warning when the last signed
+//! height falls behind it by more than `max_lag` heights (e.g. the validator
+//! node is stuck) or is ahead of it by more than `max_lead` heights (e.g. the
+//! KMS was pointed at the wrong chain, or state was set to a bogus height).
+//! The node is only ever polled: failing to reach it never affects signing.
+
+use crate::{
+    chain,
+    config::{chain::RpcEndpointConfig, KmsConfig},
+    error::{Error, ErrorKind::*},
+    metrics,
+    prelude::*,
+};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    thread,
+};
+
+/// Maximum size of a `/status` response
+const MAX_RESPONSE_SIZE: u64 = 64 * 1024;
+
+/// Status of a node, as reported by its `/status`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct NodeStatus {
+    /// Latest block height
+    pub height: u64,
+
+    /// Is the node still catching up with the network? (its height isn't the
+    /// network's if so)
+    pub catching_up: bool,
+}
+
+/// How the last signed height compares to the network's
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Drift {
+    /// Within the configured bounds
+    InSync,
+
+    /// More than `max_lag` heights behind
+    Behind,
+
+    /// More than `max_lead` heights ahead
+    Ahead,
+}
+
+impl Drift {
+    /// Compare the last signed height to the network's, given the number of
+    /// heights the signed height may be behind or ahead of it
+    pub fn of(signed: u64, network: u64, max_lag: u64, max_lead: u64) -> Self {
+        if network > signed && network - signed > max_lag {
+            Drift::Behind
+        } else if signed > network && signed - network > max_lead {
+            Drift::Ahead
+        } else {
+            Drift::InSync
+        }
+    }
+}
+
+/// Spawn a thread polling the `rpc_endpoint` of each chain which has one
+pub fn spawn(config: &KmsConfig) -> Result<(), Error> {
+    for chain_config in &config.chain {
+        let endpoint = match &chain_config.rpc_endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None => continue,
+        };
+
+        if endpoint.poll_interval_secs == Some(0) {
+            fail!(
+                ConfigError,
+                "chain {}: `rpc_endpoint.poll_interval_secs` must be at least 1",
+                chain_config.id
+            );
+        }
+
+        info!(
+            "[{}] polling {} for the network's height every {:?}",
+            chain_config.id,
+            endpoint.url,
+            endpoint.poll_interval()
+        );
+
+        let chain_id = chain_config.id.clone();
+
+        thread::Builder::new()
+            .name(format!("{}-height", chain_id))
+            .spawn(move || {
+                let mut drifts = BTreeMap::new();
+
+                loop {
+                    poll(&chain_id, &endpoint, &mut drifts);
+                    thread::sleep(endpoint.poll_interval());
+                }
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Poll the given chain's node once, comparing its height with the last one
+/// signed for each of the chain's validators (tracking whether each has
+/// drifted in `drifts`, so warnings are only logged when that changes)
+fn poll(
+    chain_id: &chain::Id,
+    endpoint: &RpcEndpointConfig,
+    drifts: &mut BTreeMap<Option<String>, Drift>,
+) {
+    let status = match fetch_status(endpoint) {
+        Ok(status) => status,
+        Err(e) => {
+            warn!(
+                "[{}] couldn't get the network's height from {}: {}",
+                chain_id, endpoint.url, e
+            );
+            metrics::rpc_poll_failed(chain_id);
+            return;
+        }
+    };
+
+    metrics::network_height(chain_id, status.height);
+
+    if status.catching_up {
+        debug!(
+            "[{}] {} is catching up (at height {}): not comparing heights",
+            chain_id, endpoint.url, status.height
+        );
+        return;
+    }
+
+    let registry = chain::REGISTRY.get();
+
+    for chain in registry.chains().filter(|chain| &chain.id == chain_id) {
+        let signed = chain.state.lock().unwrap().consensus_state().height.value();
+        let lag = i64::try_from(status.height).unwrap_or(i64::MAX)
+            - i64::try_from(signed).unwrap_or(i64::MAX);
+
+        metrics::signed_height_lag(chain_id, chain.validator.as_deref(), lag);
+
+        let drift = Drift::of(
+            signed,
+            status.height,
+            endpoint.max_lag(),
+            endpoint.max_lead(),
+        );
+        let previous = drifts.insert(chain.validator.clone(), drift);
+
+        if previous == Some(drift) || (previous.is_none() && drift == Drift::InSync) {
+            continue;
+        }
+
+        match drift {
+            Drift::Behind => warn!(
+                "[{}] last signed height {} is {} behind the network's height {} \
+                 (is the validator node stuck?)",
+                chain.name(),
+                signed,
+                lag,
+                status.height
+            ),
+            Drift::Ahead => warn!(
+                "[{}] last signed height {} is {} ahead of the network's height {} \
+                 (is the KMS configured for the right chain, and its state correct?)",
+                chain.name(),
+                signed,
+                -lag,
+                status.height
+            ),
+            Drift::InSync => info!(
+                "[{}] last signed height {} is back in sync with the network's height {}",
+                chain.name(),
+                signed,
+                status.height
+            ),
+        }
+    }
+}
+
+/// Get the status of the node at the given endpoint
+pub fn fetch_status(endpoint: &RpcEndpointConfig) -> Result<NodeStatus, Error> {
+    let url = &endpoint.url;
+    let timeout = endpoint.timeout();
+
+    let addr = (url.host.as_str(), url.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| format_err!(HttpError, "couldn't resolve {}", url.host))?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    // HTTP/1.0, so the response is never chunked and ends when the
+    // connection is closed
+    write!(
+        stream,
+        "GET {}/status HTTP/1.0\r\nHost: {}\r\nAccept: application/json\r\n\r\n",
+        url.path,
+        url.authority()
+    )?;
+
+    let mut response = vec![];
+    stream.take(MAX_RESPONSE_SIZE).read_to_end(&mut response)?;
+    parse_response(&response)
+}
+
+/// Parse an HTTP response to a `/status` request
+fn parse_response(response: &[u8]) -> Result<NodeStatus, Error> {
+    let header_len = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| format_err!(HttpError, "malformed HTTP response"))?;
+
+    let status_line = response[..header_len]
+        .split(|&byte| byte == b'\r')
+        .next()
+        .map(String::from_utf8_lossy)
+        .unwrap_or_default();
+
+    match status_line.split_whitespace().nth(1) {
+        Some("200") => (),
+        Some(_) => fail!(HttpError, "unexpected HTTP response: {}", status_line),
+        None => fail!(HttpError, "malformed HTTP response"),
+    }
+
+    parse_status(&response[header_len + 4..])
+}
+
+/// Parse the body of a `/status` response (with or without its JSON-RPC
+/// envelope)
+fn parse_status(body: &[u8]) -> Result<NodeStatus, Error> {
+    let json: serde_json::Value = serde_json::from_slice(body)?;
+    let sync_info = &json.get("result").unwrap_or(&json)["sync_info"];
+
+    // Heights are strings in CometBFT's JSON
+    let height = match &sync_info["latest_block_height"] {
+        serde_json::Value::String(height) => height.parse().ok(),
+        height => height.as_u64(),
+    }
+    .ok_or_else(|| format_err!(HttpError, "no `sync_info.latest_block_height` in /status"))?;
+
+    Ok(NodeStatus {
+        height,
+        catching_up: sync_info["catching_up"].as_bool().unwrap_or(false),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn fetch_status_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut byte = [0u8];
+
+            while !request.ends_with(b"\r\n\r\n") {
+                stream.read_exact(&mut byte).unwrap();
+                request.push(byte[0]);
+            }
+
+            stream
+                .write_all(
+                    b"HTTP/1.0 200 OK\r\n\r\n\
+                    {\"result\":{\"sync_info\":{\"latest_block_height\":\"42\"}}}",
+                )
+                .unwrap();
+
+            String::from_utf8(request).unwrap()
+        });
+
+        let endpoint: RpcEndpointConfig =
+            toml::from_str(&format!("url = \"http://127.0.0.1:{}/rpc\"", port)).unwrap();
+
+        assert_eq!(
+            fetch_status(&endpoint).unwrap(),
+            NodeStatus {
+                height: 42,
+                catching_up: false
+            }
+        );
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("GET /rpc/status HTTP/1.0\r\n"));
+        assert!(request.contains(&format!("Host: 127.0.0.1:{}\r\n", port)));
+    }
+
+    #[test]
+    fn drift_bounds() {
+        // Validators sign the height after the latest block
+        assert_eq!(Drift::of(101, 100, 10, 2), Drift::InSync);
+        assert_eq!(Drift::of(102, 100, 10, 2), Drift::InSync);
+        assert_eq!(Drift::of(103, 100, 10, 2), Drift::Ahead);
+        assert_eq!(Drift::of(90, 100, 10, 2), Drift::InSync);
+        assert_eq!(Drift::of(89, 100, 10, 2), Drift::Behind);
+        assert_eq!(Drift::of(0, 100, 10, 2), Drift::Behind);
+    }
+
+    #[test]
+    fn parse_status_responses() {
+        let response = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n\
+            {\"jsonrpc\":\"2.0\",\"id\":-1,\"result\":{\"sync_info\":\
+            {\"latest_block_height\":\"12345\",\"catching_up\":false}}}";
+
+        assert_eq!(
+            parse_response(response).unwrap(),
+            NodeStatus {
+                height: 12345,
+                catching_up: false
+            }
+        );
+
+        let body = br#"{"sync_info":{"latest_block_height":678,"catching_up":true}}"#;
+        assert_eq!(
+            parse_status(body).unwrap(),
+            NodeStatus {
+                height: 678,
+                catching_up: true
+            }
+        );
+
+        for response in &[
+            &b"HTTP/1.0 500 Internal Server Error\r\n\r\n{}"[..],
+            b"HTTP/1.0 200 OK\r\n\r\n{\"result\":{}}",
+            b"HTTP/1.0 200 OK\r\n\r\nnot json",
+            b"garbage",
+        ] {
+            assert!(parse_response(response).is_err());
+        }
+    }
+}
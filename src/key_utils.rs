@@ -1,56 +1,401 @@
 //! Utilities
 
 use std::{
-    fs::{self, OpenOptions},
-    io::Write,
-    os::unix::fs::OpenOptionsExt,
-    path::Path,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    os::unix::{
+        fs::OpenOptionsExt,
+        io::{FromRawFd, RawFd},
+    },
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
 };
 
 use ed25519_dalek as ed25519;
-use ed25519_dalek::SECRET_KEY_LENGTH;
+use ed25519_dalek::{KEYPAIR_LENGTH, SECRET_KEY_LENGTH};
+use once_cell::sync::Lazy;
 use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
 use subtle_encoding::base64;
 use zeroize::Zeroizing;
 
 use crate::{
     error::{Error, ErrorKind::*},
     prelude::*,
+    Map,
 };
 
+#[cfg(feature = "softsign")]
+pub mod encrypted;
+#[allow(unsafe_code)]
+pub mod mlock;
+#[cfg(feature = "softsign")]
+pub mod mnemonic;
+#[cfg(feature = "tpm")]
+pub mod sealed;
+
 /// File permissions for secret data
 pub const SECRET_FILE_PERMS: u32 = 0o600;
 
+/// Type of Ed25519 keys in `priv_validator_key.json` files
+const JSON_ED25519_KEY_TYPE: &str = "tendermint/PrivKeyEd25519";
+
+/// Type of Ed25519 public keys in `priv_validator_key.json` files
+const JSON_ED25519_PUBKEY_TYPE: &str = "tendermint/PubKeyEd25519";
+
+/// Prefix of key paths which name an environment variable
+const ENV_SOURCE_PREFIX: &str = "env:";
+
+/// Prefix of key paths which name an inherited file descriptor
+const FD_SOURCE_PREFIX: &str = "fd:";
+
+/// Secrets read from environment variables and file descriptors, which can
+/// only be read once, kept so the configuration can be reloaded
+static READ_ONCE_SECRETS: Lazy<Mutex<Map<PathBuf, Zeroizing<String>>>> =
+    Lazy::new(|| Mutex::new(Map::new()));
+
+/// Where a secret (i.e. key) is loaded from, as given by its configured path
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SecretSource<'a> {
+    /// File at the given path
+    File(&'a Path),
+
+    /// Environment variable (`env:NAME`), which is removed from the
+    /// environment once read
+    Env(&'a str),
+
+    /// File descriptor inherited from the parent process (`fd:N`, e.g. passed
+    /// by a container runtime or systemd), which is closed once read
+    Fd(RawFd),
+}
+
+impl<'a> SecretSource<'a> {
+    /// Get the source of the secret at the given path: `env:NAME`,
+    /// `fd:N`, or otherwise a file
+    pub fn parse(path: &'a Path) -> Result<Self, Error> {
+        let path_str = match path.to_str() {
+            Some(path_str) => path_str,
+            None => return Ok(SecretSource::File(path)),
+        };
+
+        if let Some(name) = path_str.strip_prefix(ENV_SOURCE_PREFIX) {
+            if name.is_empty() || name.contains('=') {
+                fail!(ConfigError, "invalid environment variable name: `{}`", name);
+            }
+
+            Ok(SecretSource::Env(name))
+        } else if let Some(fd) = path_str.strip_prefix(FD_SOURCE_PREFIX) {
+            fd.parse()
+                .ok()
+                .filter(|&fd: &RawFd| fd >= 0)
+                .map(SecretSource::Fd)
+                .ok_or_else(|| format_err!(ConfigError, "invalid file descriptor: `{}`", fd).into())
+        } else {
+            Ok(SecretSource::File(path))
+        }
+    }
+
+    /// Read the secret from an environment variable or file descriptor,
+    /// removing or closing it
+    fn read_once(self) -> Result<Zeroizing<String>, Error> {
+        match self {
+            SecretSource::File(path) => read_file(path),
+            SecretSource::Env(name) => {
+                let value = Zeroizing::new(env::var(name).map_err(|e| {
+                    format_err!(
+                        ConfigError,
+                        "couldn't read key from environment variable {}: {}",
+                        name,
+                        e
+                    )
+                })?);
+
+                env::remove_var(name);
+                Ok(value)
+            }
+            SecretSource::Fd(fd) => {
+                let mut file = open_fd(fd)?;
+                let mut contents = Zeroizing::new(String::new());
+
+                file.read_to_string(&mut contents).map_err(|e| {
+                    format_err!(
+                        IoError,
+                        "couldn't read key from file descriptor {}: {}",
+                        fd,
+                        e
+                    )
+                })?;
+
+                Ok(contents)
+            }
+        }
+    }
+}
+
+/// Take ownership of the given inherited file descriptor (if it's open)
+#[allow(unsafe_code)]
+fn open_fd(fd: RawFd) -> Result<File, Error> {
+    // Owning a descriptor that isn't open (or closing it twice) is undefined
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        fail!(ConfigError, "file descriptor {} is not open", fd);
+    }
+
+    // The descriptor was passed to this process to read the key from, and
+    // nothing else reads it: it's only taken once (see `READ_ONCE_SECRETS`)
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Read the secret at the given path, which may name an environment variable
+/// or inherited file descriptor (see [`SecretSource`])
+pub fn read_secret(path: &Path) -> Result<Zeroizing<String>, Error> {
+    let source = SecretSource::parse(path)?;
+
+    if let SecretSource::File(path) = source {
+        return read_file(path);
+    }
+
+    let mut secrets = READ_ONCE_SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+
+    if let Some(secret) = secrets.get(path) {
+        return Ok(secret.clone());
+    }
+
+    let secret = source.read_once()?;
+    secrets.insert(path.to_owned(), secret.clone());
+    Ok(secret)
+}
+
+/// Read a key file
+fn read_file(path: &Path) -> Result<Zeroizing<String>, Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format_err!(IoError, "couldn't read key from {}: {}", path.display(), e))?;
+
+    Ok(Zeroizing::new(contents))
+}
+
+/// Private key format
+#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum KeyFormat {
+    /// Base64-encoded
+    #[serde(rename = "base64")]
+    Base64,
+
+    /// JSON
+    #[serde(rename = "json")]
+    Json,
+}
+
+impl Default for KeyFormat {
+    fn default() -> Self {
+        KeyFormat::Base64
+    }
+}
+
+impl FromStr for KeyFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let format = match s {
+            "base64" => KeyFormat::Base64,
+            "json" => KeyFormat::Json,
+            other => fail!(ConfigError, "invalid key format: {}", other),
+        };
+
+        Ok(format)
+    }
+}
+
+/// `priv_validator_key.json` file, as generated by Tendermint.
+///
+/// Fields are borrowed from the (zeroized) file contents so no copies of the
+/// private key are made while parsing.
+#[derive(Deserialize, Serialize)]
+struct PrivValidatorKeyJson<'a> {
+    /// Validator address
+    #[serde(default)]
+    address: &'a str,
+
+    /// Public key
+    #[serde(borrow)]
+    pub_key: JsonKey<'a>,
+
+    /// Private key
+    #[serde(borrow)]
+    priv_key: JsonKey<'a>,
+}
+
+/// Public or private key in a `priv_validator_key.json` file
+#[derive(Deserialize, Serialize)]
+struct JsonKey<'a> {
+    /// Key type
+    #[serde(rename = "type")]
+    key_type: &'a str,
+
+    /// Base64-encoded key
+    value: &'a str,
+}
+
 /// Load Base64-encoded secret data (i.e. key) from the given path
 pub fn load_base64_secret(path: impl AsRef<Path>) -> Result<Zeroizing<Vec<u8>>, Error> {
     // TODO(tarcieri): check file permissions are correct
-    let base64_data = Zeroizing::new(fs::read_to_string(path.as_ref()).map_err(|e| {
-        format_err!(
-            IoError,
-            "couldn't read key from {}: {}",
-            path.as_ref().display(),
-            e
-        )
-    })?);
+    let base64_data = read_secret(path.as_ref())?;
+
+    decode_base64_secret(path.as_ref(), &base64_data)
+}
+
+/// Load secret data from the given path, which is either Base64-encoded,
+/// encrypted under a passphrase (see [`encrypted`]), or sealed to a TPM (with
+/// the `tpm` feature, see `sealed`)
+#[cfg(feature = "softsign")]
+pub fn load_secret(
+    path: impl AsRef<Path>,
+    passphrase_file: Option<&Path>,
+) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let contents = read_secret(path.as_ref())?;
 
+    #[cfg(feature = "tpm")]
+    if let Some(key) = sealed::SealedKey::parse(&contents) {
+        return sealed::open_key_file(path.as_ref(), &key);
+    }
+
+    match encrypted::EncryptedKey::parse(&contents) {
+        Some(key) => encrypted::open_key_file(path.as_ref(), &key, passphrase_file),
+        None => decode_base64_secret(path.as_ref(), &contents),
+    }
+}
+
+/// Decode Base64-encoded secret data read from the given path
+fn decode_base64_secret(path: &Path, base64_data: &str) -> Result<Zeroizing<Vec<u8>>, Error> {
     // TODO(tarcieri): constant-time string trimming
-    let data = Zeroizing::new(base64::decode(base64_data.trim_end()).map_err(|e| {
-        format_err!(
-            IoError,
-            "can't decode key from `{}`: {}",
-            path.as_ref().display(),
-            e
-        )
-    })?);
+    let data =
+        Zeroizing::new(base64::decode(base64_data.trim_end()).map_err(|e| {
+            format_err!(IoError, "can't decode key from `{}`: {}", path.display(), e)
+        })?);
 
     Ok(data)
 }
 
 /// Load a Base64-encoded Ed25519 secret key
 pub fn load_base64_ed25519_key(path: impl AsRef<Path>) -> Result<ed25519::Keypair, Error> {
-    let key_bytes = load_base64_secret(path)?;
+    ed25519_keypair(&load_base64_secret(path)?)
+}
+
+/// Load an Ed25519 secret key which is either Base64-encoded or encrypted
+/// under a passphrase
+#[cfg(feature = "softsign")]
+pub fn load_ed25519_key(
+    path: impl AsRef<Path>,
+    passphrase_file: Option<&Path>,
+) -> Result<ed25519::Keypair, Error> {
+    ed25519_keypair(&load_secret(path, passphrase_file)?)
+}
+
+/// Load an Ed25519 key from a Tendermint `priv_validator_key.json` file
+pub fn load_json_ed25519_key(path: impl AsRef<Path>) -> Result<ed25519::Keypair, Error> {
+    let path = path.as_ref();
+
+    let json = read_secret(path)?;
+
+    let key: PrivValidatorKeyJson<'_> = serde_json::from_str(&json)
+        .map_err(|e| format_err!(ParseError, "couldn't parse {}: {}", path.display(), e))?;
+
+    if key.priv_key.key_type != JSON_ED25519_KEY_TYPE {
+        fail!(
+            InvalidKey,
+            "unsupported key type in {}: {} (expected {})",
+            path.display(),
+            key.priv_key.key_type,
+            JSON_ED25519_KEY_TYPE
+        );
+    }
+
+    let keypair_bytes = decode_base64_secret(path, key.priv_key.value)?;
+
+    if keypair_bytes.len() != KEYPAIR_LENGTH {
+        fail!(
+            InvalidKey,
+            "invalid Ed25519 keypair length in {}: {} (expected {})",
+            path.display(),
+            keypair_bytes.len(),
+            KEYPAIR_LENGTH
+        );
+    }
+
+    let keypair = ed25519_keypair(&keypair_bytes[..SECRET_KEY_LENGTH])?;
+
+    if keypair.public.as_bytes()[..] != keypair_bytes[SECRET_KEY_LENGTH..] {
+        fail!(
+            InvalidKey,
+            "public key in {} doesn't match its private key",
+            path.display()
+        );
+    }
+
+    if key.pub_key.key_type != JSON_ED25519_PUBKEY_TYPE
+        || base64::decode(key.pub_key.value).ok().as_deref() != Some(&keypair.public.as_bytes()[..])
+    {
+        fail!(
+            InvalidKey,
+            "`pub_key` in {} doesn't match its private key",
+            path.display()
+        );
+    }
+
+    Ok(keypair)
+}
+
+/// Store an Ed25519 key at the given path as a Tendermint
+/// `priv_validator_key.json` file
+pub fn write_json_ed25519_key(
+    path: impl AsRef<Path>,
+    keypair: &ed25519::Keypair,
+) -> Result<(), Error> {
+    let public_key = tendermint::PublicKey::from_raw_ed25519(keypair.public.as_bytes())
+        .expect("invalid Ed25519 public key");
+
+    let address = tendermint::account::Id::from(public_key).to_string();
+    let public_key_base64 = String::from_utf8(base64::encode(keypair.public.as_bytes())).unwrap();
+    let keypair_bytes = Zeroizing::new(keypair.to_bytes());
+    let keypair_base64 =
+        Zeroizing::new(String::from_utf8(base64::encode(keypair_bytes.as_ref())).unwrap());
+
+    let key = PrivValidatorKeyJson {
+        address: &address,
+        pub_key: JsonKey {
+            key_type: JSON_ED25519_PUBKEY_TYPE,
+            value: &public_key_base64,
+        },
+        priv_key: JsonKey {
+            key_type: JSON_ED25519_KEY_TYPE,
+            value: &keypair_base64,
+        },
+    };
+
+    let json = Zeroizing::new(serde_json::to_string_pretty(&key).unwrap());
+
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(SECRET_FILE_PERMS)
+        .open(path.as_ref())
+        .and_then(|mut file| file.write_all(json.as_bytes()))
+        .map_err(|e| {
+            format_err!(
+                IoError,
+                "couldn't write `{}`: {}",
+                path.as_ref().display(),
+                e
+            )
+            .into()
+        })
+}
 
-    let secret = ed25519::SecretKey::from_bytes(&*key_bytes)
+/// Parse an Ed25519 keypair from the given secret key bytes
+fn ed25519_keypair(key_bytes: &[u8]) -> Result<ed25519::Keypair, Error> {
+    let secret = ed25519::SecretKey::from_bytes(key_bytes)
         .map_err(|e| format_err!(InvalidKey, "invalid Ed25519 key: {}", e))?;
 
     let public = ed25519::PublicKey::from(&secret);
@@ -79,9 +424,299 @@ pub fn write_base64_secret(path: impl AsRef<Path>, data: &[u8]) -> Result<(), Er
         })
 }
 
+/// Encrypt secret data under the given passphrase and store it at the given path
+#[cfg(feature = "softsign")]
+pub fn write_encrypted_secret(
+    path: impl AsRef<Path>,
+    data: &[u8],
+    passphrase: &[u8],
+) -> Result<(), Error> {
+    let key = encrypted::EncryptedKey::seal(passphrase, data)?;
+    write_json_secret(path, &serde_json::to_string_pretty(&key).unwrap())
+}
+
+/// Seal secret data to the given PCRs of the host's TPM and store it at the
+/// given path
+#[cfg(feature = "tpm")]
+pub fn write_sealed_secret(path: impl AsRef<Path>, data: &[u8], pcrs: &[u8]) -> Result<(), Error> {
+    let device = Path::new(sealed::tpm::DEVICE_PATH);
+    let key = sealed::SealedKey::seal(device, data, pcrs)?;
+    write_json_secret(path, &serde_json::to_string_pretty(&key).unwrap())
+}
+
+/// Store a JSON key file (e.g. an encrypted key) at the given path
+#[cfg(feature = "softsign")]
+fn write_json_secret(path: impl AsRef<Path>, json: &str) -> Result<(), Error> {
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(SECRET_FILE_PERMS)
+        .open(path.as_ref())
+        .and_then(|mut file| file.write_all(json.as_bytes()))
+        .map_err(|e| {
+            format_err!(
+                IoError,
+                "couldn't write `{}`: {}",
+                path.as_ref().display(),
+                e
+            )
+            .into()
+        })
+}
+
 /// Generate a Secret Connection key at the given path
 pub fn generate_key(path: impl AsRef<Path>) -> Result<(), Error> {
     let mut secret_key = Zeroizing::new([0u8; SECRET_KEY_LENGTH]);
     OsRng.fill_bytes(&mut *secret_key);
     write_base64_secret(path, &*secret_key)
 }
+
+/// Load a Secret Connection identity key in the given format from the given
+/// path (which may name an environment variable or inherited file
+/// descriptor, see [`SecretSource`]). With the `softsign` feature, Base64
+/// keys may also be encrypted under a passphrase (see [`encrypted`]).
+pub fn load_identity_key(
+    path: &Path,
+    format: KeyFormat,
+    passphrase_file: Option<&Path>,
+) -> Result<ed25519::Keypair, Error> {
+    match format {
+        #[cfg(feature = "softsign")]
+        KeyFormat::Base64 => load_ed25519_key(path, passphrase_file),
+        #[cfg(not(feature = "softsign"))]
+        KeyFormat::Base64 => {
+            if passphrase_file.is_some() {
+                fail!(
+                    ConfigError,
+                    "encrypted keys require the `softsign` feature: {}",
+                    path.display()
+                );
+            }
+
+            load_base64_ed25519_key(path)
+        }
+        KeyFormat::Json => load_json_ed25519_key(path),
+    }
+}
+
+/// Generate a Secret Connection identity key in the given format at the
+/// given path unless a file already exists there, returning whether one was
+/// generated. Keys passed in the environment or an inherited file descriptor
+/// are never generated.
+pub fn write_identity_key_if_missing(path: &Path, format: KeyFormat) -> Result<bool, Error> {
+    if !matches!(SecretSource::parse(path)?, SecretSource::File(_)) || path.exists() {
+        return Ok(false);
+    }
+
+    let mut secret_key = Zeroizing::new([0u8; SECRET_KEY_LENGTH]);
+    OsRng.fill_bytes(&mut *secret_key);
+
+    match format {
+        KeyFormat::Base64 => write_base64_secret(path, &*secret_key)?,
+        KeyFormat::Json => write_json_ed25519_key(path, &ed25519_keypair(&*secret_key)?)?,
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zeroize::Zeroize;
+
+    /// Assert the given type is wiped by `Zeroize`
+    fn assert_zeroize<T: Zeroize>() {}
+
+    /// Assert the given value is wiped when it's dropped
+    fn assert_zeroizing<T: Zeroize>(_value: &Zeroizing<T>) {}
+
+    /// Write a `priv_validator_key.json` containing the given keypair bytes
+    fn write_json_key(dir: &Path, keypair_bytes: &[u8]) -> std::path::PathBuf {
+        let path = dir.join("priv_validator_key.json");
+        let public_key = String::from_utf8(base64::encode(&keypair_bytes[32..])).unwrap();
+        let value = String::from_utf8(base64::encode(keypair_bytes)).unwrap();
+
+        fs::write(
+            &path,
+            format!(
+                r#"{{"address":"","pub_key":{{"type":"{}","value":"{}"}},"priv_key":{{"type":"{}","value":"{}"}}}}"#,
+                JSON_ED25519_PUBKEY_TYPE, public_key, JSON_ED25519_KEY_TYPE, value
+            ),
+        )
+        .unwrap();
+
+        path
+    }
+
+    #[test]
+    fn secret_types_implement_zeroize() {
+        assert_zeroize::<ed25519::SecretKey>();
+        assert_zeroize::<Vec<u8>>();
+        assert_zeroize::<String>();
+        assert_zeroize::<[u8; SECRET_KEY_LENGTH]>();
+    }
+
+    #[test]
+    fn decode_buffers_are_zeroizing() {
+        let mut decoded = decode_base64_secret(Path::new("test.key"), "AQID\n").unwrap();
+        assert_zeroizing(&decoded);
+        assert_eq!(decoded.as_slice(), &[1, 2, 3]);
+
+        decoded.zeroize();
+        assert!(decoded.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn secret_sources() {
+        assert_eq!(
+            SecretSource::parse(Path::new("env:TMKMS_SIGNING_KEY")).unwrap(),
+            SecretSource::Env("TMKMS_SIGNING_KEY")
+        );
+        assert_eq!(
+            SecretSource::parse(Path::new("fd:3")).unwrap(),
+            SecretSource::Fd(3)
+        );
+        assert_eq!(
+            SecretSource::parse(Path::new("keys/signing.key")).unwrap(),
+            SecretSource::File(Path::new("keys/signing.key"))
+        );
+        assert!(SecretSource::parse(Path::new("env:")).is_err());
+        assert!(SecretSource::parse(Path::new("fd:three")).is_err());
+    }
+
+    #[test]
+    fn load_key_from_env() {
+        let name = "TMKMS_KEY_UTILS_TEST_KEY";
+        let path = PathBuf::from(format!("env:{}", name));
+        env::set_var(name, "AQID\n");
+
+        assert_eq!(load_base64_secret(&path).unwrap().as_slice(), &[1, 2, 3]);
+        assert!(env::var_os(name).is_none());
+
+        // Reloading the configuration reuses the value read
+        assert_eq!(load_base64_secret(&path).unwrap().as_slice(), &[1, 2, 3]);
+
+        let unset = Path::new("env:TMKMS_KEY_UTILS_TEST_UNSET");
+        assert_eq!(*load_base64_secret(unset).unwrap_err().kind(), ConfigError);
+    }
+
+    #[test]
+    fn load_key_from_fd() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = ed25519_keypair(&[0x42; SECRET_KEY_LENGTH]).unwrap();
+        let path = write_json_key(dir.path(), &keypair.to_bytes());
+
+        let fd = std::os::unix::io::IntoRawFd::into_raw_fd(File::open(&path).unwrap());
+        let source = PathBuf::from(format!("fd:{}", fd));
+
+        let loaded = load_json_ed25519_key(&source).unwrap();
+        assert_eq!(loaded.public, keypair.public);
+    }
+
+    #[test]
+    fn load_json_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = ed25519_keypair(&[0x42; SECRET_KEY_LENGTH]).unwrap();
+        let path = write_json_key(dir.path(), &keypair.to_bytes());
+
+        let loaded = load_json_ed25519_key(&path).unwrap();
+        assert_eq!(loaded.public, keypair.public);
+        assert_eq!(loaded.secret.as_bytes(), keypair.secret.as_bytes());
+    }
+
+    #[test]
+    fn load_json_key_with_mismatched_public_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut keypair_bytes = ed25519_keypair(&[0x42; SECRET_KEY_LENGTH])
+            .unwrap()
+            .to_bytes();
+
+        keypair_bytes[KEYPAIR_LENGTH - 1] ^= 1;
+        let path = write_json_key(dir.path(), &keypair_bytes);
+
+        assert_eq!(
+            *load_json_ed25519_key(&path).unwrap_err().kind(),
+            InvalidKey
+        );
+    }
+
+    #[test]
+    fn load_json_key_with_mismatched_pub_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = ed25519_keypair(&[0x42; SECRET_KEY_LENGTH]).unwrap();
+        let path = write_json_key(dir.path(), &keypair.to_bytes());
+        let other = ed25519_keypair(&[0x43; SECRET_KEY_LENGTH]).unwrap();
+
+        let json = fs::read_to_string(&path).unwrap().replace(
+            &String::from_utf8(base64::encode(keypair.public.as_bytes())).unwrap(),
+            &String::from_utf8(base64::encode(other.public.as_bytes())).unwrap(),
+        );
+        fs::write(&path, json).unwrap();
+
+        assert_eq!(
+            *load_json_ed25519_key(&path).unwrap_err().kind(),
+            InvalidKey
+        );
+    }
+
+    #[test]
+    fn write_json_key_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("priv_validator_key.json");
+        let keypair = ed25519_keypair(&[0x42; SECRET_KEY_LENGTH]).unwrap();
+
+        write_json_ed25519_key(&path, &keypair).unwrap();
+
+        let loaded = load_json_ed25519_key(&path).unwrap();
+        assert_eq!(loaded.public, keypair.public);
+        assert_eq!(loaded.secret.as_bytes(), keypair.secret.as_bytes());
+    }
+
+    #[test]
+    fn write_identity_key_only_if_missing() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+
+        for (name, format) in &[
+            ("identity.key", KeyFormat::Base64),
+            ("identity.json", KeyFormat::Json),
+        ] {
+            let path = dir.path().join(name);
+
+            assert!(write_identity_key_if_missing(&path, *format).unwrap());
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, SECRET_FILE_PERMS);
+
+            let contents = fs::read(&path).unwrap();
+            let keypair = load_identity_key(&path, *format, None).unwrap();
+
+            // Existing keys are left as they are
+            assert!(!write_identity_key_if_missing(&path, *format).unwrap());
+            assert_eq!(fs::read(&path).unwrap(), contents);
+            assert_eq!(
+                load_identity_key(&path, *format, None).unwrap().public,
+                keypair.public
+            );
+        }
+
+        let env = Path::new("env:TMKMS_KEY_UTILS_TEST_IDENTITY_UNSET");
+        assert!(!write_identity_key_if_missing(env, KeyFormat::Base64).unwrap());
+    }
+
+    #[cfg(feature = "softsign")]
+    #[test]
+    fn load_encrypted_identity_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("identity.key");
+        let passphrase_file = dir.path().join("passphrase");
+        let keypair = ed25519_keypair(&[0x42; SECRET_KEY_LENGTH]).unwrap();
+
+        write_encrypted_secret(&path, keypair.secret.as_bytes(), b"correct horse").unwrap();
+        fs::write(&passphrase_file, "correct horse\n").unwrap();
+
+        let loaded = load_identity_key(&path, KeyFormat::Base64, Some(&passphrase_file)).unwrap();
+        assert_eq!(loaded.public, keypair.public);
+    }
+}
@@ -0,0 +1,267 @@
+//! Passphrase-encrypted secret key files.
+//!
+//! Keys are sealed with ChaCha20Poly1305 under a key derived from a passphrase
+//! using Argon2id, and stored as a JSON envelope which carries the KDF
+//! parameters, salt, and nonce alongside the ciphertext.
+
+use std::{env, fs, path::Path};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{Aead, NewAead, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use subtle_encoding::base64;
+use zeroize::Zeroizing;
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+
+/// Environment variable containing the passphrase for encrypted softsign keys
+pub const PASSPHRASE_ENV_VAR: &str = "TMKMS_SOFTSIGN_PASSPHRASE";
+
+/// Identifier of the envelope format (also authenticated as associated data)
+const FORMAT: &str = "tmkms-argon2id-chacha20poly1305-v1";
+
+/// Argon2id memory cost in KiB (64 MiB)
+const M_COST: u32 = 65536;
+
+/// Argon2id number of iterations
+const T_COST: u32 = 3;
+
+/// Argon2id degree of parallelism
+const P_COST: u32 = 1;
+
+/// Size of the Argon2id salt
+const SALT_SIZE: usize = 16;
+
+/// Size of the ChaCha20Poly1305 nonce
+const NONCE_SIZE: usize = 12;
+
+/// Encrypted key file envelope
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptedKey {
+    /// Envelope format identifier
+    format: String,
+
+    /// Argon2id memory cost in KiB
+    m_cost: u32,
+
+    /// Argon2id number of iterations
+    t_cost: u32,
+
+    /// Argon2id degree of parallelism
+    p_cost: u32,
+
+    /// Base64-encoded Argon2id salt
+    salt: String,
+
+    /// Base64-encoded ChaCha20Poly1305 nonce
+    nonce: String,
+
+    /// Base64-encoded ciphertext (including the Poly1305 tag)
+    ciphertext: String,
+}
+
+impl EncryptedKey {
+    /// Parse an encrypted key envelope, returning `None` if the given file
+    /// contents aren't one (e.g. a plaintext Base64 key)
+    pub fn parse(contents: &str) -> Option<Self> {
+        if !contents.trim_start().starts_with('{') {
+            return None;
+        }
+
+        serde_json::from_str::<Self>(contents)
+            .ok()
+            .filter(|key| key.format == FORMAT)
+    }
+
+    /// Encrypt the given secret under the given passphrase
+    pub fn seal(passphrase: &[u8], secret: &[u8]) -> Result<Self, Error> {
+        Self::seal_with_params(passphrase, secret, M_COST, T_COST, P_COST)
+    }
+
+    /// Encrypt the given secret using the given Argon2id parameters
+    fn seal_with_params(
+        passphrase: &[u8],
+        secret: &[u8],
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    ) -> Result<Self, Error> {
+        let mut salt = [0u8; SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+
+        let key = derive_key(passphrase, &salt, m_cost, t_cost, p_cost)?;
+
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&*key))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: secret,
+                    aad: FORMAT.as_bytes(),
+                },
+            )
+            .map_err(|_| format_err!(CryptoError, "key encryption failed"))?;
+
+        Ok(Self {
+            format: FORMAT.to_owned(),
+            m_cost,
+            t_cost,
+            p_cost,
+            salt: encode(&salt),
+            nonce: encode(&nonce),
+            ciphertext: encode(&ciphertext),
+        })
+    }
+
+    /// Decrypt the secret using the given passphrase
+    pub fn open(&self, passphrase: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let salt = decode("salt", &self.salt)?;
+        let nonce = decode("nonce", &self.nonce)?;
+        let ciphertext = decode("ciphertext", &self.ciphertext)?;
+
+        if nonce.len() != NONCE_SIZE {
+            fail!(InvalidKey, "malformed encrypted key: bad nonce length");
+        }
+
+        let key = derive_key(passphrase, &salt, self.m_cost, self.t_cost, self.p_cost)?;
+
+        ChaCha20Poly1305::new(Key::from_slice(&*key))
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: self.format.as_bytes(),
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| {
+                format_err!(
+                    InvalidKey,
+                    "couldn't decrypt key: wrong passphrase or corrupted key file"
+                )
+                .into()
+            })
+    }
+}
+
+/// Decrypt the encrypted key at the given path, reading its passphrase from
+/// (in order of preference) `passphrase_file`, the `TMKMS_SOFTSIGN_PASSPHRASE`
+/// environment variable, or an interactive prompt
+pub fn open_key_file(
+    path: &Path,
+    key: &EncryptedKey,
+    passphrase_file: Option<&Path>,
+) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let passphrase = read_passphrase(path, passphrase_file)?;
+
+    key.open(passphrase.as_bytes())
+        .map_err(|e| format_err!(InvalidKey, "{}: {}", path.display(), e).into())
+}
+
+/// Read the passphrase for the encrypted key at the given path
+fn read_passphrase(
+    path: &Path,
+    passphrase_file: Option<&Path>,
+) -> Result<Zeroizing<String>, Error> {
+    if let Some(passphrase_file) = passphrase_file {
+        let passphrase = Zeroizing::new(fs::read_to_string(passphrase_file).map_err(|e| {
+            format_err!(
+                IoError,
+                "couldn't read passphrase from {}: {}",
+                passphrase_file.display(),
+                e
+            )
+        })?);
+
+        // Ignore the trailing newline most editors add
+        return Ok(Zeroizing::new(
+            passphrase.trim_end_matches(&['\r', '\n'][..]).to_owned(),
+        ));
+    }
+
+    if let Ok(passphrase) = env::var(PASSPHRASE_ENV_VAR) {
+        return Ok(Zeroizing::new(passphrase));
+    }
+
+    prompt(&format!("Passphrase for {}: ", path.display()))
+}
+
+/// Prompt for a passphrase on the terminal
+pub fn prompt(prompt: &str) -> Result<Zeroizing<String>, Error> {
+    rpassword::prompt_password(prompt)
+        .map(Zeroizing::new)
+        .map_err(|e| format_err!(IoError, "couldn't read passphrase: {}", e).into())
+}
+
+/// Derive a ChaCha20Poly1305 key from the given passphrase with Argon2id
+fn derive_key(
+    passphrase: &[u8],
+    salt: &[u8],
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+) -> Result<Zeroizing<[u8; 32]>, Error> {
+    let params = Params::new(m_cost, t_cost, p_cost, Some(32))
+        .map_err(|e| format_err!(InvalidKey, "invalid Argon2id parameters: {}", e))?;
+
+    let mut key = Zeroizing::new([0u8; 32]);
+
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase, salt, &mut *key)
+        .map_err(|e| format_err!(CryptoError, "key derivation failed: {}", e))?;
+
+    Ok(key)
+}
+
+/// Encode the given bytes as Base64
+fn encode(bytes: &[u8]) -> String {
+    String::from_utf8(base64::encode(bytes)).unwrap()
+}
+
+/// Decode the given Base64 field of an encrypted key
+fn decode(field: &str, data: &str) -> Result<Vec<u8>, Error> {
+    base64::decode(data)
+        .map_err(|e| format_err!(InvalidKey, "malformed encrypted key {}: {}", field, e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap Argon2id parameters to keep tests fast
+    fn seal(passphrase: &[u8], secret: &[u8]) -> EncryptedKey {
+        EncryptedKey::seal_with_params(passphrase, secret, 64, 1, 1).unwrap()
+    }
+
+    #[test]
+    fn seal_and_open() {
+        let key = seal(b"correct horse", &[42u8; 32]);
+        let json = serde_json::to_string(&key).unwrap();
+
+        let parsed = EncryptedKey::parse(&json).unwrap();
+        assert_eq!(&*parsed.open(b"correct horse").unwrap(), &[42u8; 32]);
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let key = seal(b"correct horse", &[42u8; 32]);
+        let err = key.open(b"battery staple").unwrap_err();
+        assert_eq!(*err.kind(), InvalidKey);
+    }
+
+    #[test]
+    fn plaintext_keys_not_parsed() {
+        assert!(EncryptedKey::parse("WyBUaGlzIGlzIG5vdCBhbiBlbnZlbG9wZSBd").is_none());
+        assert!(EncryptedKey::parse("{\"format\": \"other\"}").is_none());
+    }
+}
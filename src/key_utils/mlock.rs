@@ -0,0 +1,51 @@
+//! Locking long-lived secret keys into memory with `mlock(2)`, so they're
+//! never written to swap.
+//!
+//! Pages are never unlocked: keys are expected to live as long as the
+//! process, and several keys may share a page.
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use std::{
+    io,
+    mem::size_of,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Is locking keys into memory enabled?
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Enable or disable locking keys into memory (enabled by default)
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Lock the pages holding the given value into memory (if enabled).
+///
+/// The value should be on the heap (e.g. in a `Box` or `Arc`) so it doesn't
+/// move after being locked.
+pub fn lock<T>(value: &T) -> Result<(), Error> {
+    let len = size_of::<T>();
+
+    if !ENABLED.load(Ordering::SeqCst) || len == 0 {
+        return Ok(());
+    }
+
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let start = value as *const T as usize;
+    let page_start = start & !(page_size - 1);
+
+    let rc = unsafe { libc::mlock(page_start as *const libc::c_void, start + len - page_start) };
+
+    if rc != 0 {
+        fail!(
+            IoError,
+            "couldn't lock key into memory: {} (raise RLIMIT_MEMLOCK, or set `mlock = false` in tmkms.toml)",
+            io::Error::last_os_error()
+        );
+    }
+
+    Ok(())
+}
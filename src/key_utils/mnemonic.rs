@@ -0,0 +1,236 @@
+//! Ed25519 keys derived from BIP39 mnemonic phrases.
+//!
+//! The mnemonic is turned into a seed as described in BIP39, and the key is
+//! derived from the seed along a (hardened) derivation path using SLIP-0010,
+//! which is how wallets derive Ed25519 keys from the same mnemonics.
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use ed25519_dalek as ed25519;
+use hkd32::mnemonic;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha512;
+use zeroize::Zeroizing;
+
+/// Default derivation path: the Cosmos coin type, with every level hardened
+/// as SLIP-0010 requires for Ed25519
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/118'/0'/0'/0'";
+
+/// Number of PBKDF2 rounds used to compute BIP39 seeds
+const PBKDF2_ROUNDS: u32 = 2048;
+
+/// HMAC key used to compute the SLIP-0010 master key of Ed25519 keys
+const SLIP10_ED25519_KEY: &[u8] = b"ed25519 seed";
+
+/// Bit set in hardened derivation path indexes
+const HARDENED: u32 = 1 << 31;
+
+/// Derive an Ed25519 key from the given mnemonic phrase (validated against
+/// the English wordlist), BIP39 passphrase, and derivation path
+pub fn derive_ed25519_key(
+    phrase: &str,
+    passphrase: &str,
+    path: &str,
+) -> Result<ed25519::Keypair, Error> {
+    let path = parse_derivation_path(path)?;
+    let seed = seed(phrase, passphrase)?;
+    let secret_bytes = slip10_ed25519(seed.as_ref(), &path);
+
+    let secret = ed25519::SecretKey::from_bytes(secret_bytes.as_ref())
+        .map_err(|e| format_err!(InvalidKey, "invalid Ed25519 key: {}", e))?;
+    let public = ed25519::PublicKey::from(&secret);
+
+    Ok(ed25519::Keypair { secret, public })
+}
+
+/// Parse a derivation path (e.g. `m/44'/118'/0'/0'/0'`) into its indexes,
+/// all of which must be hardened
+pub fn parse_derivation_path(path: &str) -> Result<Vec<u32>, Error> {
+    let mut components = path.split('/');
+
+    if components.next() != Some("m") {
+        fail!(
+            ConfigError,
+            "invalid derivation path `{}` (must start with `m/`)",
+            path
+        );
+    }
+
+    components
+        .map(|component| {
+            let index = component
+                .strip_suffix('\'')
+                .or_else(|| component.strip_suffix('h'))
+                .ok_or_else(|| {
+                    format_err!(
+                        ConfigError,
+                        "invalid derivation path `{}`: `{}` isn't hardened \
+                         (Ed25519 keys only support hardened derivation)",
+                        path,
+                        component
+                    )
+                })?;
+
+            match index.parse::<u32>() {
+                Ok(index) if index < HARDENED => Ok(index | HARDENED),
+                _ => fail!(
+                    ConfigError,
+                    "invalid derivation path `{}`: bad index `{}`",
+                    path,
+                    component
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Compute the BIP39 seed of the given mnemonic phrase and passphrase
+fn seed(phrase: &str, passphrase: &str) -> Result<Zeroizing<[u8; 64]>, Error> {
+    let words = Zeroizing::new(phrase.split_whitespace().collect::<Vec<_>>().join(" "));
+
+    let phrase = mnemonic::Phrase::new(&*words, mnemonic::Language::English).map_err(|_| {
+        format_err!(
+            InvalidKey,
+            "invalid mnemonic (expected 24 English BIP39 words with a valid checksum)"
+        )
+    })?;
+
+    let salt = Zeroizing::new(format!("mnemonic{}", passphrase));
+    let mut seed = Zeroizing::new([0u8; 64]);
+
+    pbkdf2::pbkdf2::<Hmac<Sha512>>(
+        phrase.phrase().as_bytes(),
+        salt.as_bytes(),
+        PBKDF2_ROUNDS,
+        seed.as_mut(),
+    );
+
+    Ok(seed)
+}
+
+/// Derive the SLIP-0010 Ed25519 secret key of the given seed along the given
+/// (hardened) path
+fn slip10_ed25519(seed: &[u8], path: &[u32]) -> Zeroizing<[u8; 32]> {
+    let mut node = hmac_sha512(SLIP10_ED25519_KEY, &[seed]);
+
+    for index in path {
+        let (key, chain_code) = node.split_at(32);
+        node = hmac_sha512(chain_code, &[&[0], key, &index.to_be_bytes()]);
+    }
+
+    let mut secret = Zeroizing::new([0u8; 32]);
+    secret.copy_from_slice(&node[..32]);
+    secret
+}
+
+/// Compute HMAC-SHA512 of the concatenation of the given data
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> Zeroizing<[u8; 64]> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts all key sizes");
+
+    for chunk in data {
+        mac.update(chunk);
+    }
+
+    let mut output = Zeroizing::new([0u8; 64]);
+    output.copy_from_slice(&mac.finalize().into_bytes());
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use subtle_encoding::{base64, hex};
+
+    /// BIP39 test vector mnemonic
+    const ABANDON_MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon \
+        abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+        abandon abandon abandon abandon abandon abandon art";
+
+    /// Another BIP39 test vector mnemonic
+    const LEGAL_MNEMONIC: &str = "legal winner thank year wave sausage worth useful legal \
+        winner thank year wave sausage worth useful legal winner thank year wave sausage \
+        worth title";
+
+    /// Base64-encoded public key derived from the given mnemonic
+    fn derived_pubkey(phrase: &str, passphrase: &str, path: &str) -> String {
+        let keypair = derive_ed25519_key(phrase, passphrase, path).unwrap();
+        String::from_utf8(base64::encode(keypair.public.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn bip39_seed() {
+        // From the BIP39 test vectors
+        let seed = seed(ABANDON_MNEMONIC, "TREZOR").unwrap();
+        assert_eq!(
+            hex::encode(&seed[..]),
+            b"bda85446c68413707090a52022edd26a1c9462295029f2e60cd7c4f2bbd3097170af7a4d73245cafa9c3cca8d561a7c3de6f5d4a10be8ed2a5e608d68f92fcc8"
+        );
+    }
+
+    #[test]
+    fn slip10_vectors() {
+        // SLIP-0010 test vector 1 for Ed25519
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+
+        assert_eq!(
+            hex::encode(&slip10_ed25519(&seed, &[])[..]),
+            b"2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7"
+        );
+        assert_eq!(
+            hex::encode(&slip10_ed25519(&seed, &[HARDENED, 1 | HARDENED])[..]),
+            b"b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2"
+        );
+    }
+
+    #[test]
+    fn mnemonic_vectors() {
+        assert_eq!(
+            derived_pubkey(ABANDON_MNEMONIC, "", DEFAULT_DERIVATION_PATH),
+            "GISCfDMksTRUvnfXZW0N3rO7PQd0vP8PLFR1FdKyAVw="
+        );
+        assert_eq!(
+            derived_pubkey(ABANDON_MNEMONIC, "TREZOR", DEFAULT_DERIVATION_PATH),
+            "f0O0w9aJK3HwLr+MQEnWxBFtz6MEH13u2DPnDQN/0So="
+        );
+        assert_eq!(
+            derived_pubkey(LEGAL_MNEMONIC, "", DEFAULT_DERIVATION_PATH),
+            "9YoPttiuBO4Zj8oIU4UymiHW/3WBC8OCd1fsXEC0eQA="
+        );
+        assert_eq!(
+            derived_pubkey(ABANDON_MNEMONIC, "", "m/44'/118'/1'/0'/0'"),
+            "icR3L47HcDC3KeqcK0tet71C2PM3zQXU5KUn/U6DRkQ="
+        );
+
+        // Extra whitespace (e.g. line breaks) between words is ignored
+        assert_eq!(
+            derived_pubkey(
+                &ABANDON_MNEMONIC.replace(' ', "\n  "),
+                "",
+                DEFAULT_DERIVATION_PATH
+            ),
+            "GISCfDMksTRUvnfXZW0N3rO7PQd0vP8PLFR1FdKyAVw="
+        );
+    }
+
+    #[test]
+    fn invalid_mnemonics() {
+        let bad_checksum = ABANDON_MNEMONIC.replace("art", "abandon");
+        assert!(derive_ed25519_key(&bad_checksum, "", DEFAULT_DERIVATION_PATH).is_err());
+        assert!(derive_ed25519_key("not a mnemonic", "", DEFAULT_DERIVATION_PATH).is_err());
+    }
+
+    #[test]
+    fn derivation_paths() {
+        assert_eq!(
+            parse_derivation_path("m/44'/118h/0'").unwrap(),
+            vec![44 | HARDENED, 118 | HARDENED, HARDENED]
+        );
+        assert!(parse_derivation_path("m").unwrap().is_empty());
+
+        for invalid in &["44'/118'", "m/44'/118", "m/x'", "m/2147483648'", "m//0'"] {
+            assert!(parse_derivation_path(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Secret key files sealed to a TPM 2.0.
+//!
+//! The key is stored in a TPM object which the TPM only unseals while the
+//! given PCRs have the values they had when it was sealed, so a copy of the
+//! file is useless on another host (or after the boot chain is tampered
+//! with). Keys are only unsealed into memory at startup, and signing is still
+//! performed in software.
+
+pub mod tpm;
+
+use self::tpm::{Tpm, PCR_COUNT};
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use subtle_encoding::base64;
+use zeroize::Zeroizing;
+
+/// Identifier of the sealed key file format
+const FORMAT: &str = "tmkms-tpm2-sealed-v1";
+
+/// Sealed key file
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SealedKey {
+    /// File format identifier
+    format: String,
+
+    /// SHA-256 PCRs the key is sealed to
+    pcrs: Vec<u8>,
+
+    /// Base64-encoded public area of the sealed object (`TPM2B_PUBLIC`)
+    public: String,
+
+    /// Base64-encoded private area of the sealed object (`TPM2B_PRIVATE`),
+    /// which is encrypted under the TPM's storage root key
+    private: String,
+}
+
+impl SealedKey {
+    /// Parse a sealed key file, returning `None` if the given file contents
+    /// aren't one (e.g. a plaintext Base64 key)
+    pub fn parse(contents: &str) -> Option<Self> {
+        if !contents.trim_start().starts_with('{') {
+            return None;
+        }
+
+        serde_json::from_str::<Self>(contents)
+            .ok()
+            .filter(|key| key.format == FORMAT)
+    }
+
+    /// Seal the given secret to the current values of the given PCRs with
+    /// the TPM at `device`
+    pub fn seal(device: &Path, secret: &[u8], pcrs: &[u8]) -> Result<Self, Error> {
+        let (public, private) = Tpm::open(device)?.seal(secret, pcrs)?;
+
+        Ok(Self {
+            format: FORMAT.to_owned(),
+            pcrs: pcrs.to_vec(),
+            public: encode(&public),
+            private: encode(&private),
+        })
+    }
+
+    /// Unseal the secret with the TPM at `device`
+    pub fn open(&self, device: &Path) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let public = decode("public", &self.public)?;
+        let private = decode("private", &self.private)?;
+
+        Tpm::open(device)?.unseal(&public, &private, &self.pcrs)
+    }
+
+    /// Get the PCRs the key is sealed to
+    pub fn pcrs(&self) -> &[u8] {
+        &self.pcrs
+    }
+}
+
+/// Unseal the sealed key at the given path with the TPM
+pub fn open_key_file(path: &Path, key: &SealedKey) -> Result<Zeroizing<Vec<u8>>, Error> {
+    key.open(Path::new(tpm::DEVICE_PATH))
+        .map_err(|e| format_err!(TpmError, "couldn't unseal {}: {}", path.display(), e).into())
+}
+
+/// Parse a comma-separated list of PCRs (e.g. `0,2,4`)
+pub fn parse_pcrs(pcrs: &str) -> Result<Vec<u8>, Error> {
+    let mut parsed = pcrs
+        .split(',')
+        .map(|pcr| {
+            pcr.trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|&pcr| pcr < PCR_COUNT)
+                .ok_or_else(|| {
+                    format_err!(
+                        ConfigError,
+                        "invalid PCR `{}` (must be 0-{})",
+                        pcr.trim(),
+                        PCR_COUNT - 1
+                    )
+                    .into()
+                })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    parsed.sort_unstable();
+    parsed.dedup();
+    Ok(parsed)
+}
+
+/// Encode the given bytes as Base64
+fn encode(bytes: &[u8]) -> String {
+    String::from_utf8(base64::encode(bytes)).unwrap()
+}
+
+/// Decode the given Base64 field of a sealed key
+fn decode(field: &str, data: &str) -> Result<Vec<u8>, Error> {
+    base64::decode(data)
+        .map_err(|e| format_err!(InvalidKey, "malformed sealed key {}: {}", field, e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sealed_key() {
+        let json =
+            r#"{"format":"tmkms-tpm2-sealed-v1","pcrs":[0,2,4],"public":"AAE=","private":"AAI="}"#;
+        let key = SealedKey::parse(json).unwrap();
+        assert_eq!(key.pcrs(), &[0, 2, 4]);
+
+        assert!(SealedKey::parse("WyBUaGlzIGlzIG5vdCBhbiBlbnZlbG9wZSBd").is_none());
+        assert!(SealedKey::parse(r#"{"format": "other"}"#).is_none());
+    }
+
+    #[test]
+    fn parse_pcr_list() {
+        assert_eq!(parse_pcrs("4, 0,2,0").unwrap(), [0, 2, 4]);
+        assert!(parse_pcrs("0,24").is_err());
+        assert!(parse_pcrs("").is_err());
+    }
+
+    #[test]
+    fn missing_tpm() {
+        let key = SealedKey::parse(
+            r#"{"format":"tmkms-tpm2-sealed-v1","pcrs":[7],"public":"AAE=","private":"AAI="}"#,
+        )
+        .unwrap();
+
+        let err = key.open(Path::new("/nonexistent/tpmrm0")).unwrap_err();
+        assert_eq!(*err.kind(), TpmError);
+        assert!(err.to_string().contains("couldn't open TPM device"));
+    }
+}
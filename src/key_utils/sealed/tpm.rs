@@ -0,0 +1,562 @@
+//! Minimal TPM 2.0 client.
+//!
+//! Speaks the TPM 2.0 command protocol (TCG TPM 2.0 Library, Part 3) directly
+//! over the kernel's resource manager device, implementing only the commands
+//! needed to seal data to the values of a set of SHA-256 PCRs and unseal it:
+//! the data is stored in a keyed hash object under the owner hierarchy's
+//! storage root key, which can only be used with a policy session in which
+//! the PCRs still have the same values.
+//!
+//! Transient objects and sessions are flushed by the resource manager when
+//! the device is closed, i.e. when a [`Tpm`] is dropped.
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use rand_core::{OsRng, RngCore};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::Path,
+};
+use zeroize::Zeroizing;
+
+/// Path to the kernel's TPM resource manager device
+pub const DEVICE_PATH: &str = "/dev/tpmrm0";
+
+/// Number of PCRs in a bank
+pub const PCR_COUNT: u8 = 24;
+
+/// Maximum size of the data which can be sealed (`MAX_SYM_DATA`)
+pub const MAX_SEALED_SIZE: usize = 128;
+
+/// Maximum size of a TPM response
+const MAX_RESPONSE_SIZE: usize = 4096;
+
+/// Command/response tags
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_ST_SESSIONS: u16 = 0x8002;
+
+/// Command codes
+const TPM_CC_CREATE_PRIMARY: u32 = 0x0000_0131;
+const TPM_CC_CREATE: u32 = 0x0000_0153;
+const TPM_CC_LOAD: u32 = 0x0000_0157;
+const TPM_CC_UNSEAL: u32 = 0x0000_015E;
+const TPM_CC_START_AUTH_SESSION: u32 = 0x0000_0176;
+const TPM_CC_POLICY_PCR: u32 = 0x0000_017F;
+const TPM_CC_POLICY_GET_DIGEST: u32 = 0x0000_0189;
+
+/// Handles
+const TPM_RH_OWNER: u32 = 0x4000_0001;
+const TPM_RH_NULL: u32 = 0x4000_0007;
+const TPM_RS_PW: u32 = 0x4000_0009;
+
+/// Algorithms
+const TPM_ALG_AES: u16 = 0x0006;
+const TPM_ALG_KEYEDHASH: u16 = 0x0008;
+const TPM_ALG_SHA256: u16 = 0x000B;
+const TPM_ALG_NULL: u16 = 0x0010;
+const TPM_ALG_ECC: u16 = 0x0023;
+const TPM_ALG_CFB: u16 = 0x0043;
+const TPM_ECC_NIST_P256: u16 = 0x0003;
+
+/// Session types
+const TPM_SE_POLICY: u8 = 0x01;
+const TPM_SE_TRIAL: u8 = 0x03;
+
+/// Session attribute keeping the session open after the command
+const TPMA_SESSION_CONTINUE_SESSION: u8 = 0x01;
+
+/// Object attributes
+const TPMA_OBJECT_FIXED_TPM: u32 = 1 << 1;
+const TPMA_OBJECT_FIXED_PARENT: u32 = 1 << 4;
+const TPMA_OBJECT_SENSITIVE_DATA_ORIGIN: u32 = 1 << 5;
+const TPMA_OBJECT_USER_WITH_AUTH: u32 = 1 << 6;
+const TPMA_OBJECT_ADMIN_WITH_POLICY: u32 = 1 << 7;
+const TPMA_OBJECT_NO_DA: u32 = 1 << 10;
+const TPMA_OBJECT_RESTRICTED: u32 = 1 << 16;
+const TPMA_OBJECT_DECRYPT: u32 = 1 << 17;
+
+/// Size of the nonce used to start sessions
+const NONCE_SIZE: usize = 32;
+
+/// Connection to a TPM
+pub struct Tpm {
+    /// Resource manager device
+    device: File,
+}
+
+impl Tpm {
+    /// Open the TPM at the given device path
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let device = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| {
+                format_err!(
+                    TpmError,
+                    "couldn't open TPM device {}: {} (is there a TPM 2.0 with the kernel's \
+                     resource manager, and can this user access it?)",
+                    path.display(),
+                    e
+                )
+            })?;
+
+        Ok(Self { device })
+    }
+
+    /// Seal the given data to the current values of the given SHA-256 PCRs,
+    /// returning the sealed object's public and private areas
+    pub fn seal(&mut self, data: &[u8], pcrs: &[u8]) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        if data.len() > MAX_SEALED_SIZE {
+            fail!(
+                TpmError,
+                "can't seal {} bytes (at most {} can be sealed)",
+                data.len(),
+                MAX_SEALED_SIZE
+            );
+        }
+
+        let parent = self.create_primary()?;
+
+        // Compute the digest of the PCR policy the object requires
+        let trial_session = self.start_auth_session(TPM_SE_TRIAL)?;
+        self.policy_pcr(trial_session, pcrs)?;
+        let policy_digest = self.policy_get_digest(trial_session)?;
+
+        let mut sensitive = Zeroizing::new(Vec::new());
+        put_sized(&mut sensitive, &[]); // userAuth
+        put_sized(&mut sensitive, data);
+
+        let mut public = Vec::new();
+        put_u16(&mut public, TPM_ALG_KEYEDHASH);
+        put_u16(&mut public, TPM_ALG_SHA256);
+        put_u32(
+            &mut public,
+            TPMA_OBJECT_FIXED_TPM | TPMA_OBJECT_FIXED_PARENT | TPMA_OBJECT_ADMIN_WITH_POLICY,
+        );
+        put_sized(&mut public, &policy_digest);
+        put_u16(&mut public, TPM_ALG_NULL); // scheme
+        put_sized(&mut public, &[]); // unique
+
+        let mut command = Command::new(TPM_ST_SESSIONS, TPM_CC_CREATE);
+        command.u32(parent);
+        command.password_auth();
+        command.sized(&sensitive);
+        command.sized(&public);
+        command.sized(&[]); // outsideInfo
+        command.u32(0); // creationPCR
+
+        let response = self.execute("TPM2_Create", command)?;
+        let mut response = Response::new(&response);
+        response.u32()?; // parameterSize
+        let private = response.sized()?.to_vec();
+        let public = response.sized()?.to_vec();
+
+        Ok((public, private))
+    }
+
+    /// Unseal the data in the sealed object with the given public and private
+    /// areas, which was sealed to the given PCRs
+    pub fn unseal(
+        &mut self,
+        public: &[u8],
+        private: &[u8],
+        pcrs: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let parent = self.create_primary()?;
+
+        let mut command = Command::new(TPM_ST_SESSIONS, TPM_CC_LOAD);
+        command.u32(parent);
+        command.password_auth();
+        command.sized(private);
+        command.sized(public);
+
+        let (rc, response) = self.transmit(command)?;
+
+        if rc.is_integrity_failure() {
+            fail!(
+                TpmError,
+                "the key was sealed by a different TPM (or with a different owner \
+                 hierarchy seed, e.g. after the TPM was cleared), or is corrupt ({})",
+                rc
+            );
+        } else if !rc.is_success() {
+            fail!(TpmError, "TPM2_Load failed: {}", rc);
+        }
+
+        let item = Response::new(&response).u32()?;
+
+        let session = self.start_auth_session(TPM_SE_POLICY)?;
+        self.policy_pcr(session, pcrs)?;
+
+        let mut command = Command::new(TPM_ST_SESSIONS, TPM_CC_UNSEAL);
+        command.u32(item);
+        command.auth(session, 0);
+
+        let (rc, response) = self.transmit(command)?;
+
+        if rc.is_policy_failure() {
+            fail!(
+                TpmError,
+                "PCRs {} no longer have the values the key was sealed to, e.g. after a \
+                 firmware, bootloader or boot configuration change ({}): if the change was \
+                 expected, seal the key again from its backup with `tmkms softsign seal`",
+                format_pcrs(pcrs),
+                rc
+            );
+        } else if !rc.is_success() {
+            fail!(TpmError, "TPM2_Unseal failed: {}", rc);
+        }
+
+        let mut response = Response::new(&response);
+        response.u32()?; // parameterSize
+        Ok(Zeroizing::new(response.sized()?.to_vec()))
+    }
+
+    /// Create the owner hierarchy's (ECC P-256) storage root key, which is
+    /// derived from the hierarchy's seed and so the same every time it's
+    /// created, returning its handle
+    fn create_primary(&mut self) -> Result<u32, Error> {
+        let mut public = Vec::new();
+        put_u16(&mut public, TPM_ALG_ECC);
+        put_u16(&mut public, TPM_ALG_SHA256);
+        put_u32(
+            &mut public,
+            TPMA_OBJECT_FIXED_TPM
+                | TPMA_OBJECT_FIXED_PARENT
+                | TPMA_OBJECT_SENSITIVE_DATA_ORIGIN
+                | TPMA_OBJECT_USER_WITH_AUTH
+                | TPMA_OBJECT_NO_DA
+                | TPMA_OBJECT_RESTRICTED
+                | TPMA_OBJECT_DECRYPT,
+        );
+        put_sized(&mut public, &[]); // authPolicy
+        put_u16(&mut public, TPM_ALG_AES);
+        put_u16(&mut public, 128);
+        put_u16(&mut public, TPM_ALG_CFB);
+        put_u16(&mut public, TPM_ALG_NULL); // scheme
+        put_u16(&mut public, TPM_ECC_NIST_P256);
+        put_u16(&mut public, TPM_ALG_NULL); // kdf
+        put_sized(&mut public, &[]); // unique.x
+        put_sized(&mut public, &[]); // unique.y
+
+        let mut command = Command::new(TPM_ST_SESSIONS, TPM_CC_CREATE_PRIMARY);
+        command.u32(TPM_RH_OWNER);
+        command.password_auth();
+        command.sized(&[0, 0, 0, 0]); // inSensitive (empty userAuth and data)
+        command.sized(&public);
+        command.sized(&[]); // outsideInfo
+        command.u32(0); // creationPCR
+
+        let response = self.execute("TPM2_CreatePrimary", command)?;
+        Response::new(&response).u32()
+    }
+
+    /// Start a session of the given type, returning its handle
+    fn start_auth_session(&mut self, session_type: u8) -> Result<u32, Error> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+
+        let mut command = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_START_AUTH_SESSION);
+        command.u32(TPM_RH_NULL); // tpmKey
+        command.u32(TPM_RH_NULL); // bind
+        command.sized(&nonce);
+        command.sized(&[]); // encryptedSalt
+        command.u8(session_type);
+        command.u16(TPM_ALG_NULL); // symmetric
+        command.u16(TPM_ALG_SHA256);
+
+        let response = self.execute("TPM2_StartAuthSession", command)?;
+        Response::new(&response).u32()
+    }
+
+    /// Require the given PCRs to have their current values in the given
+    /// policy session
+    fn policy_pcr(&mut self, session: u32, pcrs: &[u8]) -> Result<(), Error> {
+        let mut command = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_POLICY_PCR);
+        command.u32(session);
+        command.sized(&[]); // pcrDigest (i.e. that of the current values)
+        command.pcr_selection(pcrs);
+
+        self.execute("TPM2_PolicyPCR", command).map(drop)
+    }
+
+    /// Get the policy digest of the given session
+    fn policy_get_digest(&mut self, session: u32) -> Result<Vec<u8>, Error> {
+        let mut command = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_POLICY_GET_DIGEST);
+        command.u32(session);
+
+        let response = self.execute("TPM2_PolicyGetDigest", command)?;
+        Ok(Response::new(&response).sized()?.to_vec())
+    }
+
+    /// Execute the given command, failing unless it succeeds
+    fn execute(&mut self, name: &str, command: Command) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let (rc, response) = self.transmit(command)?;
+
+        if !rc.is_success() {
+            fail!(TpmError, "{} failed: {}", name, rc);
+        }
+
+        Ok(response)
+    }
+
+    /// Send the given command, returning the response code along with the
+    /// rest of the response
+    fn transmit(&mut self, command: Command) -> Result<(ResponseCode, Zeroizing<Vec<u8>>), Error> {
+        let command = command.finish();
+
+        self.device
+            .write_all(&command)
+            .map_err(|e| format_err!(TpmError, "error sending TPM command: {}", e))?;
+
+        let mut response = Zeroizing::new(vec![0u8; MAX_RESPONSE_SIZE]);
+        let len = self
+            .device
+            .read(&mut response)
+            .map_err(|e| format_err!(TpmError, "error reading TPM response: {}", e))?;
+
+        if len < 10 {
+            fail!(TpmError, "truncated TPM response ({} bytes)", len);
+        }
+
+        let rc = ResponseCode(u32::from_be_bytes([
+            response[6],
+            response[7],
+            response[8],
+            response[9],
+        ]));
+
+        Ok((rc, Zeroizing::new(response[10..len].to_vec())))
+    }
+}
+
+/// TPM command being marshalled
+struct Command {
+    /// Command bytes, starting with the header
+    buf: Zeroizing<Vec<u8>>,
+}
+
+impl Command {
+    /// Start a command with the given tag and command code
+    fn new(tag: u16, code: u32) -> Self {
+        let mut buf = Zeroizing::new(Vec::new());
+        put_u16(&mut buf, tag);
+        put_u32(&mut buf, 0); // commandSize, set by `finish`
+        put_u32(&mut buf, code);
+        Self { buf }
+    }
+
+    /// Append a `u8`
+    fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    /// Append a `u16`
+    fn u16(&mut self, value: u16) {
+        put_u16(&mut self.buf, value);
+    }
+
+    /// Append a `u32`
+    fn u32(&mut self, value: u32) {
+        put_u32(&mut self.buf, value);
+    }
+
+    /// Append a sized buffer (`TPM2B`)
+    fn sized(&mut self, bytes: &[u8]) {
+        put_sized(&mut self.buf, bytes);
+    }
+
+    /// Append an authorization area authorizing the command with the (empty)
+    /// password of its handle
+    fn password_auth(&mut self) {
+        self.auth(TPM_RS_PW, TPMA_SESSION_CONTINUE_SESSION);
+    }
+
+    /// Append an authorization area authorizing the command with the given
+    /// session (with an empty nonce and HMAC)
+    fn auth(&mut self, session: u32, attributes: u8) {
+        self.u32(4 + 2 + 1 + 2); // authorizationSize
+        self.u32(session);
+        self.sized(&[]); // nonce
+        self.u8(attributes);
+        self.sized(&[]); // hmac
+    }
+
+    /// Append a selection of the given SHA-256 PCRs (`TPML_PCR_SELECTION`)
+    fn pcr_selection(&mut self, pcrs: &[u8]) {
+        let mut select = [0u8; (PCR_COUNT / 8) as usize];
+
+        for &pcr in pcrs {
+            select[(pcr / 8) as usize] |= 1 << (pcr % 8);
+        }
+
+        self.u32(1); // count
+        self.u16(TPM_ALG_SHA256);
+        self.u8(select.len() as u8);
+        self.buf.extend_from_slice(&select);
+    }
+
+    /// Get the bytes of the command
+    fn finish(mut self) -> Zeroizing<Vec<u8>> {
+        let size = (self.buf.len() as u32).to_be_bytes();
+        self.buf[2..6].copy_from_slice(&size);
+        self.buf
+    }
+}
+
+/// Parser of the parameters of a TPM response
+struct Response<'a> {
+    /// Remaining bytes
+    bytes: &'a [u8],
+}
+
+impl<'a> Response<'a> {
+    /// Parse the given response (after its header)
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Take the given number of bytes
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < len {
+            fail!(TpmError, "truncated TPM response");
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    /// Take a `u32`
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Take a sized buffer (`TPM2B`)
+    fn sized(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.take(2)?;
+        let len = u16::from_be_bytes([len[0], len[1]]);
+        self.take(len as usize)
+    }
+}
+
+/// TPM response code (`TPM_RC`)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ResponseCode(u32);
+
+impl ResponseCode {
+    /// Format-one error code of a policy check failing (`TPM_RC_POLICY_FAIL`)
+    const POLICY_FAIL: u32 = 0x01D;
+
+    /// Format-one error code of an integrity check failing (`TPM_RC_INTEGRITY`)
+    const INTEGRITY: u32 = 0x01F;
+
+    /// Did the command succeed?
+    fn is_success(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Get the error number of a format-one response code (which carries the
+    /// parameter, handle or session it refers to in its other bits)
+    fn format_one_error(self) -> Option<u32> {
+        if self.0 & 0x080 != 0 {
+            Some(self.0 & 0x03F)
+        } else {
+            None
+        }
+    }
+
+    /// Did a policy session not satisfy the object's policy?
+    fn is_policy_failure(self) -> bool {
+        self.format_one_error() == Some(Self::POLICY_FAIL)
+    }
+
+    /// Did an object fail its integrity check?
+    fn is_integrity_failure(self) -> bool {
+        self.format_one_error() == Some(Self::INTEGRITY)
+    }
+}
+
+impl fmt::Display for ResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TPM_RC 0x{:03X}", self.0)
+    }
+}
+
+/// Format a list of PCRs, e.g. `0,2,4`
+pub fn format_pcrs(pcrs: &[u8]) -> String {
+    pcrs.iter()
+        .map(|pcr| pcr.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Append a big-endian `u16`
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Append a big-endian `u32`
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Append a sized buffer (`TPM2B`)
+fn put_sized(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_u16(buf, bytes.len() as u16);
+    buf.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marshal_policy_pcr() {
+        let mut command = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_POLICY_PCR);
+        command.u32(0x0300_0000);
+        command.sized(&[]);
+        command.pcr_selection(&[0, 2, 4, 23]);
+
+        assert_eq!(
+            &*command.finish(),
+            &[
+                0x80, 0x01, 0x00, 0x00, 0x00, 0x1A, 0x00, 0x00, 0x01, 0x7F, // header
+                0x03, 0x00, 0x00, 0x00, // policySession
+                0x00, 0x00, // pcrDigest
+                0x00, 0x00, 0x00, 0x01, 0x00, 0x0B, 0x03, 0x15, 0x00, 0x80, // pcrs
+            ][..]
+        );
+    }
+
+    #[test]
+    fn classify_response_codes() {
+        // Policy failure in the first session
+        assert!(ResponseCode(0x89D).is_policy_failure());
+        assert!(!ResponseCode(0x89D).is_integrity_failure());
+
+        // Integrity failure of the first parameter
+        assert!(ResponseCode(0x1DF).is_integrity_failure());
+
+        // Format-zero codes (e.g. `TPM_RC_PCR_CHANGED`)
+        assert!(!ResponseCode(0x128).is_policy_failure());
+        assert!(!ResponseCode(0x128).is_success());
+        assert_eq!(ResponseCode(0x128).to_string(), "TPM_RC 0x128");
+    }
+
+    #[test]
+    fn parse_response() {
+        let bytes = [0x00, 0x00, 0x00, 0x05, 0x00, 0x02, 0xAB, 0xCD];
+        let mut response = Response::new(&bytes);
+
+        assert_eq!(response.u32().unwrap(), 5);
+        assert_eq!(response.sized().unwrap(), &[0xAB, 0xCD]);
+        assert!(response.u32().is_err());
+    }
+}
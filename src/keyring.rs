@@ -5,10 +5,13 @@ pub mod ed25519;
 pub mod format;
 pub mod providers;
 
-pub use self::{format::Format, providers::SigningProvider};
+pub use self::{
+    format::Format,
+    providers::{KeyType, SignerProvider, SigningProvider},
+};
 use crate::{
     chain,
-    config::provider::ProviderConfig,
+    config::{chain::SelfTest, provider::ProviderConfig},
     error::{Error, ErrorKind::*},
     prelude::*,
     Map,
@@ -18,42 +21,108 @@ use tendermint::{account, TendermintKey};
 /// File encoding for software-backed secret keys
 pub type SecretKeyEncoding = subtle_encoding::Base64;
 
+/// Message signed to test keys (e.g. by `tmkms doctor --sign-test`), which
+/// isn't a valid consensus message
+pub const TEST_VECTOR: &[u8] = b"tmkms signing provider test vector";
+
+/// Outcome of checking a key with [`KeyRing::healthcheck`]
+#[derive(Debug)]
+pub struct KeyHealth {
+    /// Provider of the key (see [`SignerProvider::describe`])
+    pub provider: String,
+
+    /// Public key of the key (in the keyring's format)
+    pub public_key: String,
+
+    /// Was a test signature produced and verified?
+    pub sign_tested: bool,
+
+    /// Result of the check
+    pub result: Result<(), Error>,
+}
+
+/// Key in a keyring, as listed by [`KeyRing::keys`]
+pub struct KeyEntry<'a> {
+    /// Public key of the key
+    pub public_key: TendermintKey,
+
+    /// Provider of the key
+    pub provider: &'a dyn SignerProvider,
+
+    /// Is the key a standby consensus key (rather than an active one)?
+    pub standby: bool,
+}
+
 /// Signing keyring
+#[derive(Clone)]
 pub struct KeyRing {
+    /// Chain the keyring's keys sign for
+    chain_id: chain::Id,
+
     /// ECDSA keys in the keyring
     ecdsa_keys: Map<TendermintKey, ecdsa::Signer>,
 
     /// Ed25519 keys in the keyring
     ed25519_keys: Map<TendermintKey, ed25519::Signer>,
 
+    /// Standby Ed25519 consensus keys (by key ID) which the active consensus
+    /// key can be rotated to
+    standby_keys: Map<String, ed25519::Signer>,
+
+    /// ID of the active consensus key (if it can be rotated)
+    active_key_id: Option<String>,
+
+    /// ID (or public key) of the consensus key selected as active by the
+    /// chain's `active_key` setting, overriding how keys were added
+    selected_key: Option<String>,
+
     /// Formatting configuration when displaying keys (e.g. bech32)
     format: Format,
 }
 
 impl KeyRing {
-    /// Create a new keyring
-    pub fn new(format: Format) -> Self {
+    /// Create a new keyring for the given chain
+    pub fn new(chain_id: chain::Id, format: Format) -> Self {
         Self {
+            chain_id,
             ecdsa_keys: Map::new(),
             ed25519_keys: Map::new(),
+            standby_keys: Map::new(),
+            active_key_id: None,
+            selected_key: None,
             format,
         }
     }
 
+    /// Select the consensus key with the given ID or public key as the
+    /// active one (i.e. the chain's `active_key`), with any other consensus
+    /// keys subsequently added kept on standby
+    pub fn with_active_key(mut self, key_id: Option<String>) -> Self {
+        self.selected_key = key_id;
+        self
+    }
+
     /// Add na ECDSA key to the keyring, returning an error if we already have a
     /// signer registered for the given public key
     pub fn add_ecdsa(&mut self, signer: ecdsa::Signer) -> Result<(), Error> {
-        let provider = signer.provider();
+        let provider = signer.provider().describe();
         let public_key = signer.public_key();
         let public_key_serialized = self.format.serialize(public_key);
         let key_type = match public_key {
             TendermintKey::AccountKey(_) => "account",
-            TendermintKey::ConsensusKey(_) => unimplemented!(
-                "ECDSA consensus keys unsupported: {:?}",
-                public_key_serialized
-            ),
+            TendermintKey::ConsensusKey(_) => "consensus",
         };
 
+        // Rotation is only supported between Ed25519 consensus keys
+        if matches!(public_key, TendermintKey::ConsensusKey(_)) && self.has_consensus_key() {
+            fail!(
+                InvalidKey,
+                "[keyring:{}] chain already has a consensus key (only Ed25519 consensus keys can be kept on standby): {}",
+                provider,
+                public_key_serialized
+            );
+        }
+
         info!(
             "[keyring:{}] added {} ECDSA key: {}",
             provider, key_type, public_key_serialized
@@ -65,7 +134,7 @@ impl KeyRing {
                 "[keyring:{}] duplicate key {} already registered as {}",
                 provider,
                 public_key_serialized,
-                other.provider(),
+                other.provider().describe(),
             )
         } else {
             Ok(())
@@ -75,7 +144,7 @@ impl KeyRing {
     /// Add a key to the keyring, returning an error if we already have a
     /// signer registered for the given public key
     pub fn add_ed25519(&mut self, signer: ed25519::Signer) -> Result<(), Error> {
-        let provider = signer.provider();
+        let provider = signer.provider().describe();
         let public_key = signer.public_key();
         let public_key_serialized = self.format.serialize(public_key);
         let key_type = match public_key {
@@ -97,13 +166,175 @@ impl KeyRing {
                 "[keyring:{}] duplicate key {} already registered as {}",
                 provider,
                 public_key_serialized,
-                other.provider(),
+                other.provider().describe(),
             )
         } else {
             Ok(())
         }
     }
 
+    /// Add an Ed25519 consensus key, identified by its public key (in this
+    /// keyring's format) for rotation.
+    ///
+    /// It's the active key unless another key is selected as active (see
+    /// [`KeyRing::with_active_key`]). Without such a selection it must be
+    /// the only consensus key, since which one to sign with would otherwise
+    /// be ambiguous.
+    pub fn add_consensus_ed25519(&mut self, signer: ed25519::Signer) -> Result<(), Error> {
+        let key_id = self.format.serialize(signer.public_key());
+        self.add_rotatable_ed25519(&key_id, signer, true)
+    }
+
+    /// Add an Ed25519 consensus key identified by the given key ID, which can
+    /// be rotated to and from at runtime (see [`KeyRing::rotate_consensus_key`]).
+    ///
+    /// The active key is added like any other key, while standby keys are
+    /// only kept for rotating to. If a key is selected as active (see
+    /// [`KeyRing::with_active_key`]), that overrides `active`.
+    pub fn add_rotatable_ed25519(
+        &mut self,
+        key_id: &str,
+        signer: ed25519::Signer,
+        active: bool,
+    ) -> Result<(), Error> {
+        let active = match &self.selected_key {
+            Some(selected) => {
+                selected == key_id || *selected == self.format.serialize(signer.public_key())
+            }
+            None => active,
+        };
+
+        if self.active_key_id.as_deref() == Some(key_id) || self.standby_keys.contains_key(key_id) {
+            fail!(
+                InvalidKey,
+                "[keyring:{}] duplicate key ID: {}",
+                signer.provider().describe(),
+                key_id
+            );
+        }
+
+        if !active {
+            info!(
+                "[keyring:{}] added standby consensus key {}: {}",
+                signer.provider().describe(),
+                key_id,
+                self.format.serialize(signer.public_key())
+            );
+
+            self.standby_keys.insert(key_id.to_owned(), signer);
+            return Ok(());
+        }
+
+        if let Some(other) = &self.active_key_id {
+            fail!(
+                InvalidKey,
+                "[keyring:{}] both {} and {} are active consensus keys (select one with the chain's `active_key`)",
+                signer.provider().describe(),
+                other,
+                key_id
+            );
+        }
+
+        if self.has_consensus_key() {
+            fail!(
+                InvalidKey,
+                "[keyring:{}] chain already has a consensus key which can't be kept on standby: {}",
+                signer.provider().describe(),
+                key_id
+            );
+        }
+
+        self.add_ed25519(signer)?;
+        self.active_key_id = Some(key_id.to_owned());
+        Ok(())
+    }
+
+    /// Get the ID of the active consensus key (if it can be rotated)
+    pub fn active_key_id(&self) -> Option<&str> {
+        self.active_key_id.as_deref()
+    }
+
+    /// Get the consensus key selected as active (see
+    /// [`KeyRing::with_active_key`]) if it doesn't match any key in the
+    /// keyring
+    pub fn unmatched_active_key(&self) -> Option<&str> {
+        let selected = self.selected_key.as_deref()?;
+
+        let matched = self.active_key_id.is_some()
+            || self
+                .ecdsa_consensus_keys()
+                .any(|(key, _)| self.format.serialize(*key) == selected);
+
+        if matched {
+            None
+        } else {
+            Some(selected)
+        }
+    }
+
+    /// Does the keyring have an (active) consensus key?
+    fn has_consensus_key(&self) -> bool {
+        !self.ed25519_keys.is_empty() || self.ecdsa_consensus_keys().next().is_some()
+    }
+
+    /// Create a copy of this keyring whose active consensus key is the
+    /// standby key with the given ID, with the current active key becoming a
+    /// standby key.
+    ///
+    /// Refused if the new key's public key differs from the active one's
+    /// (i.e. the one the chain expects) unless `allow_pubkey_change` is set.
+    pub fn rotate_consensus_key(
+        &self,
+        key_id: &str,
+        allow_pubkey_change: bool,
+    ) -> Result<KeyRing, Error> {
+        let active_key_id = self
+            .active_key_id
+            .as_ref()
+            .ok_or_else(|| format_err!(InvalidKey, "no rotatable consensus key in keyring"))?;
+
+        if active_key_id == key_id {
+            fail!(InvalidKey, "key {} is already active", key_id);
+        }
+
+        let new_signer = self
+            .standby_keys
+            .get(key_id)
+            .ok_or_else(|| format_err!(InvalidKey, "no standby consensus key with ID {}", key_id))?
+            .clone();
+
+        let old_public_key = self.default_ed25519_pubkey()?;
+        let new_public_key = new_signer.public_key();
+
+        if new_public_key != old_public_key && !allow_pubkey_change {
+            fail!(
+                InvalidKey,
+                "key {} has a different public key than the active key {} ({} vs {}); \
+                 rotating to it changes the chain's validator key (use --allow-pubkey-change)",
+                key_id,
+                active_key_id,
+                self.format.serialize(new_public_key),
+                self.format.serialize(old_public_key)
+            );
+        }
+
+        let mut keyring = self.clone();
+        let old_signer = keyring.ed25519_keys.remove(&old_public_key).unwrap();
+        keyring.standby_keys.remove(key_id);
+        keyring
+            .standby_keys
+            .insert(active_key_id.clone(), old_signer);
+        keyring.ed25519_keys.insert(new_public_key, new_signer);
+        keyring.active_key_id = Some(key_id.to_owned());
+
+        Ok(keyring)
+    }
+
+    /// Get the formatting configuration used when displaying keys
+    pub fn format(&self) -> &Format {
+        &self.format
+    }
+
     /// Get the default Ed25519 (i.e. consensus) public key for this keyring
     pub fn default_ed25519_pubkey(&self) -> Result<TendermintKey, Error> {
         let mut keys = self.ed25519_keys.keys();
@@ -115,6 +346,28 @@ impl KeyRing {
         }
     }
 
+    /// Get the default consensus public key for this keyring, which is either
+    /// an Ed25519 key or an ECDSA (secp256k1) key
+    pub fn default_consensus_pubkey(&self) -> Result<TendermintKey, Error> {
+        let mut keys = self
+            .ed25519_keys
+            .keys()
+            .chain(self.ecdsa_consensus_keys().map(|(key, _)| key));
+
+        match (keys.next(), keys.next()) {
+            (Some(key), None) => Ok(*key),
+            _ => fail!(InvalidKey, "expected only one key in keyring"),
+        }
+    }
+
+    /// Get the address of the validator whose consensus key is in the keyring
+    /// (i.e. the one votes are expected to be from)
+    pub fn consensus_address(&self) -> Result<account::Id, Error> {
+        Ok(account::Id::from(
+            *self.default_consensus_pubkey()?.public_key(),
+        ))
+    }
+
     /// Get ECDSA public key bytes for a given account ID
     pub fn get_account_pubkey(&self, account_id: account::Id) -> Option<tendermint::PublicKey> {
         for key in self.ecdsa_keys.keys() {
@@ -128,6 +381,34 @@ impl KeyRing {
         None
     }
 
+    /// Get the public key of the only account key in the keyring, which must
+    /// not also be registered as a consensus key
+    pub fn account_pubkey(&self) -> Result<tendermint::PublicKey, Error> {
+        let mut account_keys = self.ecdsa_keys.keys().filter_map(|key| match key {
+            TendermintKey::AccountKey(pk) => Some(*pk),
+            TendermintKey::ConsensusKey(_) => None,
+        });
+
+        let public_key = match (account_keys.next(), account_keys.next()) {
+            (Some(public_key), None) => public_key,
+            (None, _) => fail!(InvalidKey, "no account key in keyring"),
+            _ => fail!(InvalidKey, "expected only one account key in keyring"),
+        };
+
+        if self
+            .ecdsa_keys
+            .contains_key(&TendermintKey::ConsensusKey(public_key))
+        {
+            fail!(
+                InvalidKey,
+                "account key {} is also a consensus key",
+                self.format.serialize(TendermintKey::AccountKey(public_key))
+            );
+        }
+
+        Ok(public_key)
+    }
+
     /// Sign a message using ECDSA
     pub fn sign_ecdsa(
         &self,
@@ -137,7 +418,7 @@ impl KeyRing {
         for (key, signer) in &self.ecdsa_keys {
             if let TendermintKey::AccountKey(pk) = key {
                 if account_id == account::Id::from(*pk) {
-                    return signer.sign(msg);
+                    return signer.sign(&self.chain_id, msg);
                 }
             }
         }
@@ -158,7 +439,11 @@ impl KeyRing {
     ) -> Result<ed25519::Signature, Error> {
         let signer = match public_key {
             Some(public_key) => self.ed25519_keys.get(public_key).ok_or_else(|| {
-                format_err!(InvalidKey, "not in keyring: {}", public_key.to_bech32(""))
+                format_err!(
+                    InvalidKey,
+                    "not in keyring: {}",
+                    self.format.serialize(*public_key)
+                )
             })?,
             None => {
                 let mut vals = self.ed25519_keys.values();
@@ -172,7 +457,156 @@ impl KeyRing {
             }
         };
 
-        signer.sign(msg)
+        signer.sign(&self.chain_id, msg)
+    }
+
+    /// Sign a consensus message (i.e. a vote or proposal) using the only
+    /// consensus key in the keyring, returning the raw signature bytes
+    pub fn sign_consensus(&self, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut ecdsa_signers = self.ecdsa_consensus_keys().map(|(_, signer)| signer);
+
+        match (ecdsa_signers.next(), ecdsa_signers.next()) {
+            (None, _) => Ok(self.sign_ed25519(None, msg)?.as_ref().to_vec()),
+            (Some(signer), None) if self.ed25519_keys.is_empty() => {
+                Ok(signer.sign(&self.chain_id, msg)?.as_ref().to_vec())
+            }
+            _ => fail!(SigningError, "expected only one key in keyring"),
+        }
+    }
+
+    /// Get the provider of the only consensus key in the keyring (i.e. the
+    /// one [`KeyRing::sign_consensus`] signs with)
+    pub fn consensus_provider(&self) -> Result<&dyn SignerProvider, Error> {
+        let mut ecdsa_signers = self.ecdsa_consensus_keys().map(|(_, signer)| signer);
+
+        match (ecdsa_signers.next(), ecdsa_signers.next()) {
+            (None, _) => {
+                let mut ed25519_signers = self.ed25519_keys.values();
+
+                match (ed25519_signers.next(), ed25519_signers.next()) {
+                    (Some(signer), None) => Ok(signer.provider()),
+                    (None, _) => fail!(InvalidKey, "keyring is empty"),
+                    _ => fail!(SigningError, "expected only one key in keyring"),
+                }
+            }
+            (Some(signer), None) if self.ed25519_keys.is_empty() => Ok(signer.provider()),
+            _ => fail!(SigningError, "expected only one key in keyring"),
+        }
+    }
+
+    /// List the keys in the keyring: Ed25519 keys (active, then standby),
+    /// then ECDSA keys
+    pub fn keys(&self) -> Vec<KeyEntry<'_>> {
+        let active = self.ed25519_keys.values().map(|signer| (signer, false));
+        let standby = self.standby_keys.values().map(|signer| (signer, true));
+
+        let ed25519_keys = active.chain(standby).map(|(signer, standby)| KeyEntry {
+            public_key: signer.public_key(),
+            provider: signer.provider(),
+            standby,
+        });
+
+        let ecdsa_keys = self.ecdsa_keys.values().map(|signer| KeyEntry {
+            public_key: signer.public_key(),
+            provider: signer.provider(),
+            standby: false,
+        });
+
+        ed25519_keys.chain(ecdsa_keys).collect()
+    }
+
+    /// Check the provider of each key in the keyring is working (see
+    /// [`SignerProvider::healthcheck`]).
+    ///
+    /// If `sign_test` is set, [`TEST_VECTOR`] is also signed with each key
+    /// and the signature verified. Software keys are always test signed,
+    /// since it's free, whereas Ledger keys never are, since the Tendermint
+    /// app only signs consensus messages.
+    pub fn healthcheck(&self, sign_test: bool) -> Vec<KeyHealth> {
+        let ed25519_keys = self
+            .ed25519_keys
+            .values()
+            .chain(self.standby_keys.values())
+            .map(|signer| {
+                let public_key = self.format.serialize(signer.public_key());
+                check_key(signer.provider(), public_key, sign_test, || {
+                    signer.sign_test(&self.chain_id)
+                })
+            });
+
+        let ecdsa_keys = self.ecdsa_keys.values().map(|signer| {
+            let public_key = self.format.serialize(signer.public_key());
+            check_key(signer.provider(), public_key, sign_test, || {
+                signer.sign_test(&self.chain_id)
+            })
+        });
+
+        ed25519_keys.chain(ecdsa_keys).collect()
+    }
+
+    /// Run the startup self-test on the keys in the keyring, returning a
+    /// summary of what was checked.
+    ///
+    /// Each key is checked as by [`KeyRing::healthcheck`], test signing with
+    /// it for [`SelfTest::Sign`], and a keyring holding consensus keys must
+    /// have a single active one (i.e. the public key validators are sent).
+    pub fn selftest(&self, mode: SelfTest) -> Result<String, Error> {
+        if self.has_consensus_key() {
+            self.default_consensus_pubkey()?;
+        }
+
+        let keys = self.healthcheck(mode == SelfTest::Sign);
+
+        if keys.is_empty() {
+            fail!(InvalidKey, "keyring is empty");
+        }
+
+        for key in &keys {
+            if let Err(e) = &key.result {
+                fail!(*e.kind(), "{} {}: {}", key.provider, key.public_key, e);
+            }
+        }
+
+        Ok(format!(
+            "{} key(s) checked, {} test signature(s) verified",
+            keys.len(),
+            keys.iter().filter(|key| key.sign_tested).count()
+        ))
+    }
+
+    /// Iterate over the ECDSA consensus keys in the keyring
+    fn ecdsa_consensus_keys(&self) -> impl Iterator<Item = (&TendermintKey, &ecdsa::Signer)> {
+        self.ecdsa_keys
+            .iter()
+            .filter(|(key, _)| matches!(key, TendermintKey::ConsensusKey(_)))
+    }
+}
+
+/// Check a key of the given provider, test signing with it using `test` if
+/// applicable (see [`KeyRing::healthcheck`])
+fn check_key(
+    provider: &dyn SignerProvider,
+    public_key: String,
+    sign_test: bool,
+    test: impl FnOnce() -> Result<(), Error>,
+) -> KeyHealth {
+    let sign_tested = match provider.kind() {
+        #[cfg(feature = "softsign")]
+        SigningProvider::SoftSign => true,
+        #[cfg(feature = "ledger")]
+        SigningProvider::LedgerTm => false,
+        _ => sign_test,
+    };
+
+    let result = provider
+        .healthcheck()
+        .and_then(|()| if sign_tested { test() } else { Ok(()) });
+
+    KeyHealth {
+        provider: provider.describe(),
+        public_key,
+        sign_tested,
+        result,
     }
 }
 
@@ -190,5 +624,304 @@ pub fn load_config(registry: &mut chain::Registry, config: &ProviderConfig) -> R
     #[cfg(feature = "fortanixdsm")]
     providers::fortanixdsm::init(registry, &config.fortanixdsm)?;
 
+    #[cfg(feature = "awskms")]
+    providers::awskms::init(registry, &config.awskms)?;
+
+    #[cfg(feature = "azurekv")]
+    providers::azurekv::init(registry, &config.azurekv)?;
+
+    #[cfg(feature = "gcpkms")]
+    providers::gcpkms::init(registry, &config.gcpkms)?;
+
+    #[cfg(feature = "vault")]
+    providers::vault::init(registry, &config.vault)?;
+
+    #[cfg(feature = "pkcs11")]
+    providers::pkcs11::init(registry, &config.pkcs11)?;
+
+    #[cfg(feature = "threshold")]
+    providers::threshold::init(registry, &config.threshold)?;
+
+    #[cfg(feature = "testing")]
+    providers::mock::init(registry, &config.mock)?;
+
+    for chain in registry.chains() {
+        if let Some(key_id) = chain.keyring.unmatched_active_key() {
+            fail!(
+                InvalidKey,
+                "`active_key` {} of chain {} doesn't match any of its consensus keys",
+                key_id,
+                chain.name()
+            );
+        }
+    }
+
+    // Validators of the same chain sharing a key would sign conflicting
+    // messages with it, as each has its own double signing protection
+    let mut consensus_keys = Map::new();
+
+    for chain in registry.chains() {
+        if let Ok(public_key) = chain.keyring.default_consensus_pubkey() {
+            if let Some(other) =
+                consensus_keys.insert((&chain.id, public_key.public_key().to_bytes()), chain)
+            {
+                fail!(
+                    InvalidKey,
+                    "chains {} and {} share the consensus key {}",
+                    other.name(),
+                    chain.name(),
+                    chain.keyring.format().serialize(public_key)
+                );
+            }
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Get the ID of the chain test keyrings are for
+    fn test_chain_id() -> chain::Id {
+        "test-chain".parse().unwrap()
+    }
+
+    /// Create a signer for the Ed25519 key derived from the given byte
+    fn test_signer(seed: u8) -> ed25519::Signer {
+        let secret = ed25519::SecretKey::from_bytes(&[seed; 32]).unwrap();
+        let public = ed25519::PublicKey::from(&secret);
+        let keypair = ed25519::Keypair { secret, public };
+        let public_key = TendermintKey::ConsensusKey(keypair.public.into());
+        ed25519::Signer::new(SigningProvider::Custom, public_key, Box::new(keypair))
+    }
+
+    #[test]
+    fn account_key_distinct_from_consensus_key() {
+        let signing_key = k256::ecdsa::SigningKey::from_bytes(&[1; 32]).unwrap();
+        let public_key =
+            tendermint::PublicKey::from_raw_secp256k1(&signing_key.verifying_key().to_bytes())
+                .unwrap();
+
+        let mut keyring = KeyRing::new(test_chain_id(), Format::HEX);
+        assert!(keyring.account_pubkey().is_err());
+
+        keyring
+            .add_ecdsa(ecdsa::Signer::new(
+                SigningProvider::Custom,
+                TendermintKey::AccountKey(public_key),
+                Box::new(signing_key.clone()),
+            ))
+            .unwrap();
+        assert_eq!(keyring.account_pubkey().unwrap(), public_key);
+
+        // The same key must not also sign consensus messages
+        keyring
+            .add_ecdsa(ecdsa::Signer::new(
+                SigningProvider::Custom,
+                TendermintKey::ConsensusKey(public_key),
+                Box::new(signing_key),
+            ))
+            .unwrap();
+        assert!(keyring.account_pubkey().is_err());
+    }
+
+    #[test]
+    fn rotate_consensus_key() {
+        let (primary, backup) = (test_signer(1), test_signer(2));
+        let mut keyring = KeyRing::new(test_chain_id(), Format::HEX);
+        keyring
+            .add_rotatable_ed25519("backup", backup.clone(), false)
+            .unwrap();
+        keyring
+            .add_rotatable_ed25519("primary", primary.clone(), true)
+            .unwrap();
+        assert_eq!(
+            keyring.default_ed25519_pubkey().unwrap(),
+            primary.public_key()
+        );
+
+        // Rotating to a key with a different public key must be permitted
+        assert!(keyring.rotate_consensus_key("backup", false).is_err());
+        assert!(keyring.rotate_consensus_key("primary", true).is_err());
+        assert!(keyring.rotate_consensus_key("missing", true).is_err());
+
+        let rotated = keyring.rotate_consensus_key("backup", true).unwrap();
+        assert_eq!(rotated.active_key_id(), Some("backup"));
+        assert_eq!(
+            rotated.default_ed25519_pubkey().unwrap(),
+            backup.public_key()
+        );
+
+        // The original keyring is unchanged, and the old key is on standby
+        assert_eq!(keyring.active_key_id(), Some("primary"));
+        let restored = rotated.rotate_consensus_key("primary", true).unwrap();
+        assert_eq!(
+            restored.default_ed25519_pubkey().unwrap(),
+            primary.public_key()
+        );
+    }
+
+    #[test]
+    fn select_active_consensus_key() {
+        let (old, new) = (test_signer(1), test_signer(2));
+        let new_id = Format::HEX.serialize(new.public_key());
+
+        // Two consensus keys without a selection are ambiguous
+        let mut keyring = KeyRing::new(test_chain_id(), Format::HEX);
+        keyring.add_consensus_ed25519(old.clone()).unwrap();
+        assert!(keyring.add_consensus_ed25519(new.clone()).is_err());
+
+        let mut keyring =
+            KeyRing::new(test_chain_id(), Format::HEX).with_active_key(Some(new_id.clone()));
+        assert_eq!(keyring.unmatched_active_key(), Some(new_id.as_str()));
+        keyring.add_consensus_ed25519(old.clone()).unwrap();
+        keyring.add_consensus_ed25519(new.clone()).unwrap();
+        assert_eq!(keyring.unmatched_active_key(), None);
+        assert_eq!(keyring.active_key_id(), Some(new_id.as_str()));
+        assert_eq!(
+            keyring.default_consensus_pubkey().unwrap(),
+            new.public_key()
+        );
+
+        // The other key is on standby, identified by its public key
+        let old_id = Format::HEX.serialize(old.public_key());
+        let rotated = keyring.rotate_consensus_key(&old_id, true).unwrap();
+        assert_eq!(
+            rotated.default_consensus_pubkey().unwrap(),
+            old.public_key()
+        );
+    }
+
+    #[test]
+    fn reject_duplicate_and_multiple_active_keys() {
+        let mut keyring = KeyRing::new(test_chain_id(), Format::HEX);
+        keyring
+            .add_rotatable_ed25519("a", test_signer(1), true)
+            .unwrap();
+        assert!(keyring
+            .add_rotatable_ed25519("a", test_signer(2), false)
+            .is_err());
+        assert!(keyring
+            .add_rotatable_ed25519("b", test_signer(3), true)
+            .is_err());
+    }
+
+    #[test]
+    fn selftest() {
+        let keyring = KeyRing::new(test_chain_id(), Format::HEX);
+        assert!(keyring.selftest(SelfTest::Sign).is_err());
+
+        let mut keyring = KeyRing::new(test_chain_id(), Format::HEX);
+        keyring.add_consensus_ed25519(test_signer(1)).unwrap();
+        assert_eq!(
+            keyring.selftest(SelfTest::Sign).unwrap(),
+            "1 key(s) checked, 1 test signature(s) verified"
+        );
+        assert_eq!(
+            keyring.selftest(SelfTest::Verify).unwrap(),
+            "1 key(s) checked, 0 test signature(s) verified"
+        );
+
+        // A key which doesn't match its reported public key fails to verify
+        let mut keyring = KeyRing::new(test_chain_id(), Format::HEX);
+        let secret = ed25519::SecretKey::from_bytes(&[1; 32]).unwrap();
+        let public = ed25519::PublicKey::from(&secret);
+        let public_key = test_signer(2).public_key();
+        keyring
+            .add_consensus_ed25519(ed25519::Signer::new(
+                SigningProvider::Custom,
+                public_key,
+                Box::new(ed25519::Keypair { secret, public }),
+            ))
+            .unwrap();
+        assert_eq!(
+            *keyring.selftest(SelfTest::Sign).unwrap_err().kind(),
+            SigningError
+        );
+        assert!(keyring.selftest(SelfTest::Verify).is_ok());
+    }
+
+    /// Provider supplied by an application, signing with an Ed25519 key and
+    /// recording the chains it's asked to sign for
+    struct TestProvider {
+        keypair: ed25519::Keypair,
+        healthy: bool,
+        chain_ids: Mutex<Vec<chain::Id>>,
+    }
+
+    impl SignerProvider for TestProvider {
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        fn key_types(&self) -> Vec<KeyType> {
+            vec![KeyType::Ed25519]
+        }
+
+        fn public_key(&self, _chain_id: &chain::Id) -> Result<TendermintKey, Error> {
+            Ok(TendermintKey::ConsensusKey(self.keypair.public.into()))
+        }
+
+        fn sign(&self, chain_id: &chain::Id, msg: &[u8]) -> Result<Vec<u8>, Error> {
+            self.chain_ids.lock().unwrap().push(chain_id.clone());
+            Ok(
+                signature::Signer::<ed25519::Signature>::sign(&self.keypair, msg)
+                    .to_bytes()
+                    .to_vec(),
+            )
+        }
+
+        fn healthcheck(&self) -> Result<(), Error> {
+            if self.healthy {
+                Ok(())
+            } else {
+                fail!(SigningError, "HSM unreachable")
+            }
+        }
+    }
+
+    #[test]
+    fn custom_provider() {
+        let secret = ed25519::SecretKey::from_bytes(&[1; 32]).unwrap();
+        let public = ed25519::PublicKey::from(&secret);
+        let provider = Arc::new(TestProvider {
+            keypair: ed25519::Keypair { secret, public },
+            healthy: true,
+            chain_ids: Mutex::new(vec![]),
+        });
+
+        let public_key = provider.public_key(&test_chain_id()).unwrap();
+        assert!(ecdsa::Signer::from_provider(provider.clone(), public_key).is_err());
+
+        let signer = ed25519::Signer::from_provider(provider.clone(), public_key).unwrap();
+        assert_eq!(signer.provider().describe(), "test");
+        assert_eq!(signer.provider().kind(), SigningProvider::Custom);
+
+        let mut keyring = KeyRing::new(test_chain_id(), Format::HEX);
+        keyring.add_consensus_ed25519(signer).unwrap();
+        assert_eq!(keyring.consensus_provider().unwrap().name(), "test");
+
+        // Signatures are produced by the provider, for the keyring's chain
+        let signature = keyring.sign_consensus(b"vote").unwrap();
+        assert_eq!(signature, provider.sign(&test_chain_id(), b"vote").unwrap());
+        assert_eq!(provider.chain_ids.lock().unwrap()[0], test_chain_id());
+        assert!(keyring.selftest(SelfTest::Sign).is_ok());
+
+        // Health checks are the provider's
+        let unhealthy = Arc::new(TestProvider {
+            keypair: ed25519::Keypair::from_bytes(&provider.keypair.to_bytes()).unwrap(),
+            healthy: false,
+            chain_ids: Mutex::new(vec![]),
+        });
+        let mut keyring = KeyRing::new(test_chain_id(), Format::HEX);
+        keyring
+            .add_consensus_ed25519(ed25519::Signer::from_provider(unhealthy, public_key).unwrap())
+            .unwrap();
+        let keys = keyring.healthcheck(false);
+        assert_eq!(keys[0].provider, "test");
+        assert!(keys[0].result.is_err());
+    }
+}
@@ -3,57 +3,102 @@
 pub use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature};
 
 use crate::{
+    chain,
     error::{Error, ErrorKind::*},
-    keyring::SigningProvider,
+    keyring::{
+        providers::{BuiltinKey, KeyType, SignerProvider},
+        SigningProvider, TEST_VECTOR,
+    },
     prelude::*,
 };
+use signature::Verifier;
 use std::sync::Arc;
 use tendermint::TendermintKey;
 
-#[allow(clippy::redundant_allocation)]
-
 /// Ed25519 signer
 #[derive(Clone)]
 pub struct Signer {
     /// Provider for this signer
-    provider: SigningProvider,
+    provider: Arc<dyn SignerProvider>,
 
     /// Tendermint public key
     public_key: TendermintKey,
-
-    /// Signer trait object
-    signer: Arc<Box<dyn signature::Signer<Signature> + Send + Sync>>,
 }
 
 impl Signer {
-    /// Create a new signer
+    /// Create a new signer for a key of a built-in provider
     pub fn new(
         provider: SigningProvider,
         public_key: TendermintKey,
         signer: Box<dyn signature::Signer<Signature> + Send + Sync>,
     ) -> Self {
+        let key = BuiltinKey::new(provider, KeyType::Ed25519, public_key, signer);
+
         Self {
-            provider,
+            provider: Arc::new(key),
             public_key,
-            signer: Arc::new(signer),
         }
     }
 
+    /// Create a signer for the given public key of a provider (i.e. the one
+    /// it has for the chain the signer is added to)
+    pub fn from_provider(
+        provider: Arc<dyn SignerProvider>,
+        public_key: TendermintKey,
+    ) -> Result<Self, Error> {
+        if public_key.public_key().ed25519().is_none()
+            || !provider.key_types().contains(&KeyType::Ed25519)
+        {
+            fail!(
+                InvalidKey,
+                "[keyring:{}] not an Ed25519 key",
+                provider.describe()
+            );
+        }
+
+        Ok(Self {
+            provider,
+            public_key,
+        })
+    }
+
     /// Get the Tendermint public key for this signer
     pub fn public_key(&self) -> TendermintKey {
         self.public_key
     }
 
     /// Get the provider for this signer
-    pub fn provider(&self) -> SigningProvider {
-        self.provider
+    pub fn provider(&self) -> &dyn SignerProvider {
+        self.provider.as_ref()
+    }
+
+    /// Sign the given message for the given chain using this signer
+    pub fn sign(&self, chain_id: &chain::Id, msg: &[u8]) -> Result<Signature, Error> {
+        let signature = self.provider.sign(chain_id, msg)?;
+
+        Ok(Signature::from_bytes(&signature).map_err(|e| {
+            format_err!(
+                SigningError,
+                "[keyring:{}] invalid Ed25519 signature: {}",
+                self.provider.describe(),
+                e
+            )
+        })?)
     }
 
-    /// Sign the given message using this signer
-    pub fn sign(&self, msg: &[u8]) -> Result<Signature, Error> {
-        Ok(self
-            .signer
-            .try_sign(msg)
-            .map_err(|e| format_err!(SigningError, "{}", e))?)
+    /// Sign [`TEST_VECTOR`] for the given chain and verify the signature with
+    /// this signer's public key
+    pub fn sign_test(&self, chain_id: &chain::Id) -> Result<(), Error> {
+        let signature = self.sign(chain_id, TEST_VECTOR)?;
+
+        let public_key = self
+            .public_key
+            .public_key()
+            .ed25519()
+            .ok_or_else(|| format_err!(InvalidKey, "not an Ed25519 key"))?;
+
+        public_key
+            .verify(TEST_VECTOR, &signature)
+            .map_err(|e| format_err!(SigningError, "test signature failed to verify: {}", e).into())
     }
 }
@@ -1,22 +1,55 @@
 //! Chain-specific key configuration
+//!
+//! Every public key displayed by the KMS (at startup, by `tmkms pubkey`, and in
+//! logs and error messages) is serialized using the chain's [`Format`], and
+//! operator-supplied keys are parsed with [`Format::parse`].
 
-use cosmrs::crypto::PublicKey;
+pub mod bech32;
+
+pub use self::bech32::Checksum;
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use cosmrs::crypto::PublicKey as CosmosPublicKey;
 use serde::Deserialize;
-use subtle_encoding::bech32;
-use tendermint::TendermintKey;
+use subtle_encoding::{base64, hex};
+use tendermint::{PublicKey, TendermintKey};
+
+/// Amino prefix of Bech32-encoded Ed25519 keys
+const AMINO_ED25519_PREFIX: [u8; 5] = [0x16, 0x24, 0xDE, 0x64, 0x20];
+
+/// Amino prefix of Bech32-encoded secp256k1 keys
+const AMINO_SECP256K1_PREFIX: [u8; 5] = [0xEB, 0x5A, 0xE9, 0x87, 0x21];
+
+/// Prefix of `did:key` keys (using the base58btc multibase encoding)
+const DID_KEY_PREFIX: &str = "did:key:z";
+
+/// Multicodec prefix of Ed25519 keys in `did:key` keys
+const MULTICODEC_ED25519: [u8; 2] = [0xED, 0x01];
+
+/// Multicodec prefix of secp256k1 keys in `did:key` keys
+const MULTICODEC_SECP256K1: [u8; 2] = [0xE7, 0x01];
 
 /// Options for how keys for this chain are represented
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
 #[serde(tag = "type")]
 pub enum Format {
     /// Use the Bech32 serialization format with the given key prefixes
     #[serde(rename = "bech32")]
     Bech32 {
         /// Prefix to use for Account keys
+        #[serde(deserialize_with = "bech32::deserialize_prefix")]
         account_key_prefix: String,
 
         /// Prefix to use for Consensus keys
+        #[serde(deserialize_with = "bech32::deserialize_prefix")]
         consensus_key_prefix: String,
+
+        /// Checksum variant: `bech32` (default) or `bech32m`
+        #[serde(default)]
+        checksum: Checksum,
     },
 
     /// JSON-encoded Cosmos protobuf representation of keys
@@ -25,24 +58,519 @@ pub enum Format {
 
     /// Hex is a baseline representation
     #[serde(rename = "hex")]
-    Hex,
+    Hex {
+        /// Case of the hex digits (uppercase by default)
+        #[serde(default)]
+        case: HexCase,
+
+        /// Prefix keys with `0x`
+        #[serde(default)]
+        prefix_0x: bool,
+    },
+
+    /// Base64 encoding of the raw key bytes (as in `priv_validator_key.json`)
+    #[serde(rename = "base64")]
+    Base64,
+
+    /// `did:key` identifiers, i.e. `did:key:z` followed by the base58btc
+    /// encoding of the key's multicodec-prefixed bytes
+    #[serde(rename = "did-key")]
+    DidKey,
+}
+
+/// Case of hex-encoded keys
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HexCase {
+    /// Uppercase hex digits (e.g. `9F86D08`)
+    Upper,
+
+    /// Lowercase hex digits (e.g. `9f86d08`)
+    Lower,
+}
+
+impl Default for HexCase {
+    fn default() -> Self {
+        HexCase::Upper
+    }
 }
 
 impl Format {
+    /// Uppercase hex without a prefix
+    pub const HEX: Format = Format::Hex {
+        case: HexCase::Upper,
+        prefix_0x: false,
+    };
+
+    /// Bech32 format for the given consensus key prefix, deriving the account
+    /// key prefix from it (e.g. `cosmospub` from `cosmosvalconspub`)
+    pub fn bech32(consensus_key_prefix: &str) -> Format {
+        let account_key_prefix = match consensus_key_prefix.strip_suffix("valconspub") {
+            Some(hrp) => format!("{}pub", hrp),
+            None => consensus_key_prefix.to_owned(),
+        };
+
+        Format::Bech32 {
+            account_key_prefix,
+            consensus_key_prefix: consensus_key_prefix.to_owned(),
+            checksum: Checksum::default(),
+        }
+    }
+
+    /// Use the given checksum variant, if this is a Bech32 format
+    pub fn with_checksum(self, checksum: Checksum) -> Format {
+        match self {
+            Format::Bech32 {
+                account_key_prefix,
+                consensus_key_prefix,
+                ..
+            } => Format::Bech32 {
+                account_key_prefix,
+                consensus_key_prefix,
+                checksum,
+            },
+            format => format,
+        }
+    }
+
+    /// Detect the format of a serialized public key, so operator-supplied
+    /// keys can be given in any format (falls back to Base64, which is then
+    /// rejected by [`Format::parse`] if the key isn't valid Base64 either)
+    pub fn detect(encoded: &str) -> Format {
+        let encoded = encoded.trim();
+
+        if encoded.starts_with(DID_KEY_PREFIX) {
+            return Format::DidKey;
+        }
+
+        if encoded.starts_with('{') {
+            return Format::CosmosJson;
+        }
+
+        let (digits, prefix_0x) = match encoded.strip_prefix("0x") {
+            Some(digits) => (digits, true),
+            None => (encoded, false),
+        };
+
+        // Raw Ed25519 keys are 32 bytes and compressed secp256k1 keys 33 bytes
+        if (prefix_0x || matches!(digits.len(), 64 | 66))
+            && digits.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            let case = if digits.chars().any(|c| c.is_ascii_lowercase()) {
+                HexCase::Lower
+            } else {
+                HexCase::Upper
+            };
+
+            return Format::Hex { case, prefix_0x };
+        }
+
+        match bech32::decode(encoded) {
+            Ok((prefix, _, checksum)) => Format::bech32(&prefix).with_checksum(checksum),
+            Err(_) => Format::Base64,
+        }
+    }
+
     /// Serialize a `TendermintKey` according to chain-specific rules
     pub fn serialize(&self, public_key: TendermintKey) -> String {
         match self {
             Format::Bech32 {
                 account_key_prefix,
                 consensus_key_prefix,
+                checksum,
             } => match public_key {
-                TendermintKey::AccountKey(pk) => {
-                    bech32::encode(account_key_prefix, tendermint::account::Id::from(pk))
+                TendermintKey::AccountKey(pk) => bech32::encode(
+                    account_key_prefix,
+                    tendermint::account::Id::from(pk).as_bytes(),
+                    *checksum,
+                ),
+                TendermintKey::ConsensusKey(pk) => {
+                    bech32::encode(consensus_key_prefix, &to_amino_bytes(pk), *checksum)
                 }
-                TendermintKey::ConsensusKey(pk) => pk.to_bech32(consensus_key_prefix),
             },
-            Format::CosmosJson => PublicKey::from(*public_key.public_key()).to_json(),
-            Format::Hex => public_key.to_hex(),
+            Format::CosmosJson => CosmosPublicKey::from(*public_key.public_key()).to_json(),
+            Format::Hex { case, prefix_0x } => {
+                let bytes = public_key.public_key().to_bytes();
+                let encoded = match case {
+                    HexCase::Upper => hex::encode_upper(bytes),
+                    HexCase::Lower => hex::encode(bytes),
+                };
+
+                format!(
+                    "{}{}",
+                    if *prefix_0x { "0x" } else { "" },
+                    String::from_utf8(encoded).unwrap()
+                )
+            }
+            Format::Base64 => {
+                String::from_utf8(base64::encode(public_key.public_key().to_bytes())).unwrap()
+            }
+            Format::DidKey => {
+                let public_key = public_key.public_key();
+                let mut bytes = match public_key {
+                    PublicKey::Ed25519(_) => MULTICODEC_ED25519.to_vec(),
+                    _ => MULTICODEC_SECP256K1.to_vec(),
+                };
+
+                bytes.extend(public_key.to_bytes());
+                format!("{}{}", DID_KEY_PREFIX, bs58::encode(bytes).into_string())
+            }
         }
     }
+
+    /// Parse a public key serialized in this format.
+    ///
+    /// Bech32 account keys are serialized as account addresses (i.e. hashes
+    /// of the key), so only Bech32 consensus keys can be parsed.
+    pub fn parse(&self, encoded: &str) -> Result<PublicKey, Error> {
+        let encoded = encoded.trim();
+
+        match self {
+            Format::Bech32 {
+                account_key_prefix,
+                consensus_key_prefix,
+                checksum,
+            } => {
+                let (prefix, bytes, encoded_checksum) = split_bech32(encoded)?;
+
+                if &prefix == account_key_prefix && &prefix != consensus_key_prefix {
+                    fail!(
+                        InvalidKey,
+                        "`{}` keys are account addresses, not public keys",
+                        prefix
+                    );
+                }
+
+                if &prefix != consensus_key_prefix {
+                    fail!(
+                        InvalidKey,
+                        "expected Bech32 prefix `{}`, got `{}`",
+                        consensus_key_prefix,
+                        prefix
+                    );
+                }
+
+                if encoded_checksum != *checksum {
+                    fail!(
+                        InvalidKey,
+                        "expected a {} checksum, got {}",
+                        checksum,
+                        encoded_checksum
+                    );
+                }
+
+                from_amino_bytes(&bytes, encoded)
+            }
+            Format::CosmosJson => CosmosPublicKey::from_json(encoded)
+                .map(Into::into)
+                .map_err(|e| format_err!(InvalidKey, "invalid Cosmos JSON key: {}", e).into()),
+            Format::Hex { prefix_0x, .. } => {
+                let digits = if *prefix_0x {
+                    encoded.strip_prefix("0x").ok_or_else(|| {
+                        format_err!(InvalidKey, "hex key is missing `0x` prefix: {}", encoded)
+                    })?
+                } else {
+                    encoded
+                };
+
+                // Either case is accepted regardless of the configured one
+                let bytes = hex::decode(digits.to_ascii_lowercase())
+                    .map_err(|e| format_err!(InvalidKey, "invalid hex key: {}", e))?;
+
+                from_raw_bytes(&bytes)
+            }
+            Format::Base64 => {
+                let bytes = base64::decode(encoded)
+                    .map_err(|e| format_err!(InvalidKey, "invalid Base64 key: {}", e))?;
+
+                from_raw_bytes(&bytes)
+            }
+            Format::DidKey => {
+                let bytes = encoded
+                    .strip_prefix(DID_KEY_PREFIX)
+                    .and_then(|multibase| bs58::decode(multibase).into_vec().ok())
+                    .ok_or_else(|| format_err!(InvalidKey, "invalid did:key: {}", encoded))?;
+
+                if let Some(key) = bytes.strip_prefix(&MULTICODEC_ED25519) {
+                    PublicKey::from_raw_ed25519(key)
+                } else if let Some(key) = bytes.strip_prefix(&MULTICODEC_SECP256K1) {
+                    PublicKey::from_raw_secp256k1(key)
+                } else {
+                    None
+                }
+                .ok_or_else(|| format_err!(InvalidKey, "unsupported did:key: {}", encoded).into())
+            }
+        }
+    }
+}
+
+/// Decode a Bech32 consensus key with any prefix, returning the prefix along
+/// with the key (e.g. to derive a [`Format::Bech32`] from it)
+pub fn decode_bech32(encoded: &str) -> Result<(String, PublicKey), Error> {
+    let encoded = encoded.trim();
+    let (prefix, bytes, _) = split_bech32(encoded)?;
+    Ok((prefix, from_amino_bytes(&bytes, encoded)?))
+}
+
+/// Split a Bech32 (or Bech32m) string into its prefix, data and checksum
+/// variant
+fn split_bech32(encoded: &str) -> Result<(String, Vec<u8>, Checksum), Error> {
+    bech32::decode(encoded).map_err(|e| format_err!(InvalidKey, "invalid Bech32 key: {}", e).into())
+}
+
+/// Get the Amino-prefixed key bytes of a Bech32 consensus key
+fn to_amino_bytes(public_key: PublicKey) -> Vec<u8> {
+    let mut bytes = match public_key {
+        PublicKey::Ed25519(_) => AMINO_ED25519_PREFIX.to_vec(),
+        _ => AMINO_SECP256K1_PREFIX.to_vec(),
+    };
+
+    bytes.extend(public_key.to_bytes());
+    bytes
+}
+
+/// Parse the Amino-prefixed key bytes of a Bech32 consensus key
+fn from_amino_bytes(bytes: &[u8], encoded: &str) -> Result<PublicKey, Error> {
+    if let Some(key) = bytes.strip_prefix(&AMINO_ED25519_PREFIX) {
+        PublicKey::from_raw_ed25519(key)
+    } else if let Some(key) = bytes.strip_prefix(&AMINO_SECP256K1_PREFIX) {
+        PublicKey::from_raw_secp256k1(key)
+    } else {
+        None
+    }
+    .ok_or_else(|| format_err!(InvalidKey, "invalid Bech32 key: {}", encoded).into())
+}
+
+/// Parse raw public key bytes, inferring the key type from their length
+fn from_raw_bytes(bytes: &[u8]) -> Result<PublicKey, Error> {
+    match bytes.len() {
+        32 => PublicKey::from_raw_ed25519(bytes),
+        33 => PublicKey::from_raw_secp256k1(bytes),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        format_err!(
+            InvalidKey,
+            "invalid public key ({} bytes; expected 32-byte Ed25519 or 33-byte secp256k1)",
+            bytes.len()
+        )
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ed25519_key() -> PublicKey {
+        PublicKey::from_raw_ed25519(&[
+            0x62, 0x53, 0x13, 0xc5, 0x2f, 0x7b, 0xd1, 0x6b, 0x23, 0x0e, 0xfc, 0xd0, 0x05, 0xb8,
+            0x83, 0xc1, 0x5a, 0x13, 0xe5, 0x31, 0x10, 0x77, 0x4c, 0x8e, 0x7c, 0x86, 0x0f, 0x2e,
+            0xd5, 0xae, 0x5a, 0xd8,
+        ])
+        .unwrap()
+    }
+
+    fn secp256k1_key() -> PublicKey {
+        let signing_key = k256::ecdsa::SigningKey::from_bytes(&[0x42; 32]).unwrap();
+        PublicKey::from_raw_secp256k1(&signing_key.verifying_key().to_bytes()).unwrap()
+    }
+
+    fn formats() -> Vec<Format> {
+        vec![
+            Format::Bech32 {
+                account_key_prefix: "cosmospub".to_owned(),
+                consensus_key_prefix: "cosmosvalconspub".to_owned(),
+                checksum: Checksum::Bech32,
+            },
+            Format::Bech32 {
+                account_key_prefix: "cosmospub".to_owned(),
+                consensus_key_prefix: "cosmosvalconspub".to_owned(),
+                checksum: Checksum::Bech32m,
+            },
+            Format::CosmosJson,
+            Format::HEX,
+            Format::Hex {
+                case: HexCase::Lower,
+                prefix_0x: true,
+            },
+            Format::Base64,
+            Format::DidKey,
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        for format in formats() {
+            for public_key in [ed25519_key(), secp256k1_key()] {
+                let encoded = format.serialize(TendermintKey::ConsensusKey(public_key));
+                assert_eq!(format.parse(&encoded).unwrap(), public_key, "{}", encoded);
+            }
+        }
+    }
+
+    #[test]
+    fn hex_options() {
+        let key = TendermintKey::ConsensusKey(ed25519_key());
+        assert!(Format::HEX.serialize(key).starts_with("625313C5"));
+
+        let format = Format::Hex {
+            case: HexCase::Lower,
+            prefix_0x: true,
+        };
+        assert!(format.serialize(key).starts_with("0x625313c5"));
+
+        // The prefix is required when configured
+        assert!(format.parse(&Format::HEX.serialize(key)).is_err());
+    }
+
+    #[test]
+    fn did_key() {
+        let key = TendermintKey::ConsensusKey(ed25519_key());
+        assert!(Format::DidKey.serialize(key).starts_with("did:key:z6Mk"));
+    }
+
+    #[test]
+    fn bech32_account_keys_are_addresses() {
+        let format = &formats()[0];
+        let account_key = format.serialize(TendermintKey::AccountKey(secp256k1_key()));
+        assert!(format.parse(&account_key).is_err());
+    }
+
+    #[test]
+    fn detect() {
+        for format in formats() {
+            for public_key in [ed25519_key(), secp256k1_key()] {
+                let encoded = format.serialize(TendermintKey::ConsensusKey(public_key));
+                assert_eq!(Format::detect(&encoded), format, "{}", encoded);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_bech32_with_any_prefix() {
+        let key = TendermintKey::ConsensusKey(ed25519_key());
+        let encoded = Format::bech32("osmovalconspub").serialize(key);
+        assert_eq!(
+            decode_bech32(&encoded).unwrap(),
+            ("osmovalconspub".to_owned(), ed25519_key())
+        );
+
+        assert_eq!(
+            Format::bech32("osmovalconspub"),
+            Format::Bech32 {
+                account_key_prefix: "osmopub".to_owned(),
+                consensus_key_prefix: "osmovalconspub".to_owned(),
+                checksum: Checksum::Bech32,
+            }
+        );
+    }
+
+    #[test]
+    fn bech32_checksum_variants() {
+        let key = ed25519_key();
+        let bech32 = &formats()[0];
+        let bech32m = &formats()[1];
+
+        // Bech32 keys are encoded as by Tendermint
+        assert_eq!(
+            bech32.serialize(TendermintKey::ConsensusKey(key)),
+            key.to_bech32("cosmosvalconspub")
+        );
+
+        // Keys are only parsed with the configured checksum
+        let encoded = bech32m.serialize(TendermintKey::ConsensusKey(key));
+        assert_ne!(encoded, key.to_bech32("cosmosvalconspub"));
+        assert_eq!(bech32m.parse(&encoded).unwrap(), key);
+
+        let err = bech32.parse(&encoded).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("expected a bech32 checksum, got bech32m"));
+    }
+
+    #[test]
+    fn bech32_prefix_with_digits() {
+        let format = Format::Bech32 {
+            account_key_prefix: "c4epub".to_owned(),
+            consensus_key_prefix: "c4evalconspub".to_owned(),
+            checksum: Checksum::Bech32m,
+        };
+
+        for public_key in [ed25519_key(), secp256k1_key()] {
+            let encoded = format.serialize(TendermintKey::ConsensusKey(public_key));
+            assert!(encoded.starts_with("c4evalconspub1"), "{}", encoded);
+            assert_eq!(format.parse(&encoded).unwrap(), public_key);
+            assert_eq!(
+                Format::detect(&encoded),
+                Format::bech32("c4evalconspub").with_checksum(Checksum::Bech32m)
+            );
+        }
+    }
+
+    #[test]
+    fn deserialize_bech32() {
+        let format: Format = toml::from_str(
+            r#"
+            type = "bech32"
+            account_key_prefix = "c4epub"
+            consensus_key_prefix = "c4evalconspub"
+            checksum = "bech32m"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            format,
+            Format::bech32("c4evalconspub").with_checksum(Checksum::Bech32m)
+        );
+
+        // The checksum defaults to Bech32
+        let format: Format = toml::from_str(
+            r#"
+            type = "bech32"
+            account_key_prefix = "cosmospub"
+            consensus_key_prefix = "cosmosvalconspub"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(format, Format::bech32("cosmosvalconspub"));
+
+        let err = toml::from_str::<Format>(
+            r#"
+            type = "bech32"
+            account_key_prefix = "cosmospub"
+            consensus_key_prefix = "Cosmos valconspub"
+            "#,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains(
+                "invalid Bech32 prefix `Cosmos valconspub`: character 1 ('C') is uppercase"
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn deserialize_hex_defaults() {
+        let format: Format = toml::from_str(r#"type = "hex""#).unwrap();
+        assert_eq!(format, Format::HEX);
+
+        let format: Format = toml::from_str(
+            r#"
+            type = "hex"
+            case = "lower"
+            prefix_0x = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            format,
+            Format::Hex {
+                case: HexCase::Lower,
+                prefix_0x: true
+            }
+        );
+    }
 }
@@ -0,0 +1,319 @@
+//! Bech32 ([BIP-173]) and Bech32m ([BIP-350]) encoding.
+//!
+//! Unlike Bitcoin addresses, keys are encoded with any human-readable part
+//! (e.g. `cosmosvalconspub`) and aren't limited to 90 characters, as
+//! Bech32-encoded secp256k1 keys with long prefixes exceed it.
+//!
+//! [BIP-173]: https://github.com/bitcoin/bips/blob/master/bip-0173.mediawiki
+//! [BIP-350]: https://github.com/bitcoin/bips/blob/master/bip-0350.mediawiki
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+
+/// Characters of the data part, indexed by their 5-bit value
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Separator between the human-readable and data parts
+const SEPARATOR: char = '1';
+
+/// Number of characters of the checksum
+const CHECKSUM_SIZE: usize = 6;
+
+/// Maximum length of a human-readable part
+const MAX_PREFIX_LENGTH: usize = 83;
+
+/// Generator coefficients of the checksum's BCH code
+const GENERATOR: [u32; 5] = [
+    0x3b6a_57b2,
+    0x2650_8e6d,
+    0x1ea1_19fa,
+    0x3d42_33dd,
+    0x2a14_62b3,
+];
+
+/// Checksum variant
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Checksum {
+    /// Original Bech32 checksum (BIP-173), as used by Cosmos SDK chains
+    Bech32,
+
+    /// Bech32m checksum (BIP-350)
+    Bech32m,
+}
+
+impl Default for Checksum {
+    fn default() -> Self {
+        Checksum::Bech32
+    }
+}
+
+impl Checksum {
+    /// Constant the checksum polynomial is XORed with
+    fn constant(self) -> u32 {
+        match self {
+            Checksum::Bech32 => 1,
+            Checksum::Bech32m => 0x2bc8_30a3,
+        }
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Checksum::Bech32 => "bech32",
+            Checksum::Bech32m => "bech32m",
+        })
+    }
+}
+
+/// Check the given human-readable part is valid: 1 to 83 lowercase ASCII
+/// characters in the range 33-126
+pub fn validate_prefix(prefix: &str) -> Result<(), String> {
+    if prefix.is_empty() {
+        return Err("it's empty".to_owned());
+    }
+
+    if prefix.len() > MAX_PREFIX_LENGTH {
+        return Err(format!(
+            "it's {} characters long (at most {} are allowed)",
+            prefix.len(),
+            MAX_PREFIX_LENGTH
+        ));
+    }
+
+    for (i, c) in prefix.chars().enumerate() {
+        if !('!'..='~').contains(&c) {
+            return Err(format!(
+                "character {} ({:?}) isn't printable ASCII",
+                i + 1,
+                c
+            ));
+        }
+
+        if c.is_ascii_uppercase() {
+            return Err(format!(
+                "character {} ({:?}) is uppercase (prefixes must be lowercase)",
+                i + 1,
+                c
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Deserialize a human-readable part, checking it's valid
+pub fn deserialize_prefix<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let prefix = String::deserialize(deserializer)?;
+
+    validate_prefix(&prefix).map_err(|e| {
+        serde::de::Error::custom(format!("invalid Bech32 prefix `{}`: {}", prefix, e))
+    })?;
+
+    Ok(prefix)
+}
+
+/// Encode the given data with the given human-readable part and checksum
+pub fn encode(prefix: &str, data: &[u8], checksum: Checksum) -> String {
+    let mut values = convert_bits(data, 8, 5, true).expect("padded conversion can't fail");
+    let polymod = polymod(prefix, &values, &[0; CHECKSUM_SIZE]) ^ checksum.constant();
+
+    for i in 0..CHECKSUM_SIZE {
+        values.push(((polymod >> (5 * (5 - i))) & 0x1f) as u8);
+    }
+
+    let mut encoded = String::with_capacity(prefix.len() + 1 + values.len());
+    encoded.push_str(prefix);
+    encoded.push(SEPARATOR);
+    encoded.extend(values.iter().map(|&v| CHARSET[v as usize] as char));
+    encoded
+}
+
+/// Decode the given string, returning its human-readable part, its data and
+/// the variant of its checksum
+pub fn decode(encoded: &str) -> Result<(String, Vec<u8>, Checksum), Error> {
+    if encoded.chars().any(|c| c.is_ascii_lowercase())
+        && encoded.chars().any(|c| c.is_ascii_uppercase())
+    {
+        fail!(InvalidKey, "invalid Bech32: mixed case");
+    }
+
+    let encoded = encoded.to_ascii_lowercase();
+
+    let (prefix, data) = encoded
+        .rsplit_once(SEPARATOR)
+        .ok_or_else(|| format_err!(InvalidKey, "invalid Bech32: missing separator"))?;
+
+    validate_prefix(prefix)
+        .map_err(|e| format_err!(InvalidKey, "invalid Bech32 prefix `{}`: {}", prefix, e))?;
+
+    if data.len() < CHECKSUM_SIZE {
+        fail!(InvalidKey, "invalid Bech32: too short for a checksum");
+    }
+
+    let values = data
+        .bytes()
+        .map(|b| CHARSET.iter().position(|&c| c == b).map(|v| v as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| format_err!(InvalidKey, "invalid Bech32: invalid character"))?;
+
+    let checksum = match polymod(prefix, &values, &[]) {
+        c if c == Checksum::Bech32.constant() => Checksum::Bech32,
+        c if c == Checksum::Bech32m.constant() => Checksum::Bech32m,
+        _ => fail!(InvalidKey, "invalid Bech32: checksum mismatch"),
+    };
+
+    let data = convert_bits(&values[..values.len() - CHECKSUM_SIZE], 5, 8, false)
+        .ok_or_else(|| format_err!(InvalidKey, "invalid Bech32: invalid padding"))?;
+
+    Ok((prefix.to_owned(), data, checksum))
+}
+
+/// Compute the checksum polynomial of the given human-readable part followed
+/// by the given 5-bit values
+fn polymod(prefix: &str, values: &[u8], suffix: &[u8]) -> u32 {
+    let expanded_prefix = prefix
+        .bytes()
+        .map(|b| b >> 5)
+        .chain(Some(0))
+        .chain(prefix.bytes().map(|b| b & 0x1f));
+
+    expanded_prefix
+        .chain(values.iter().copied())
+        .chain(suffix.iter().copied())
+        .fold(1, |chk, value| {
+            let top = chk >> 25;
+            let chk = ((chk & 0x01ff_ffff) << 5) ^ u32::from(value);
+
+            GENERATOR
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| (top >> i) & 1 == 1)
+                .fold(chk, |chk, (_, g)| chk ^ g)
+        })
+}
+
+/// Regroup the bits of the given values from `from`-bit groups to `to`-bit
+/// groups, padding the last group with zeros if `pad` is set (otherwise
+/// `None` is returned if the leftover bits aren't zero padding)
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc = 0u32;
+    let mut bits = 0u32;
+    let mut result = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+    let max = (1u32 << to) - 1;
+
+    for &value in data {
+        acc = (acc << from) | u32::from(value);
+        bits += from;
+
+        while bits >= to {
+            bits -= to;
+            result.push(((acc >> bits) & max) as u8);
+        }
+    }
+
+    if pad {
+        if bits > 0 {
+            result.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || (acc << (to - bits)) & max != 0 {
+        return None;
+    }
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bip_test_vectors() {
+        // Valid strings from BIP-173 and BIP-350
+        for (encoded, checksum) in &[
+            ("a12uel5l", Checksum::Bech32),
+            (
+                "abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw",
+                Checksum::Bech32,
+            ),
+            ("a1lqfn3a", Checksum::Bech32m),
+            (
+                "abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx",
+                Checksum::Bech32m,
+            ),
+            ("?1v759aa", Checksum::Bech32m),
+        ] {
+            let (prefix, data, detected) = decode(encoded).unwrap();
+            assert_eq!(detected, *checksum, "{}", encoded);
+
+            // Only strings whose data is a whole number of bytes round-trip
+            if convert_bits(&data, 8, 5, true).unwrap().len() + CHECKSUM_SIZE
+                == encoded.len() - prefix.len() - 1
+            {
+                assert_eq!(encode(&prefix, &data, *checksum), *encoded);
+            }
+        }
+
+        // Corrupted checksums and mixed case are refused
+        assert!(decode("a1lqfn3b").is_err());
+        assert!(decode("A12UEL5L").is_ok());
+        assert!(decode("A12uEL5L").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = [0x16, 0x24, 0xDE, 0x64, 0x20, 0x01, 0x02, 0x03];
+
+        for checksum in &[Checksum::Bech32, Checksum::Bech32m] {
+            let encoded = encode("cosmosvalconspub", &data, *checksum);
+            assert_eq!(
+                decode(&encoded).unwrap(),
+                ("cosmosvalconspub".to_owned(), data.to_vec(), *checksum)
+            );
+        }
+
+        // Bech32 matches the encoding used elsewhere
+        assert_eq!(
+            encode("cosmos", &data, Checksum::Bech32),
+            subtle_encoding::bech32::encode("cosmos", data)
+        );
+    }
+
+    #[test]
+    fn prefix_with_digits() {
+        // Including the separator, which is the last `1` of the string
+        let data = [0xAB; 20];
+
+        for prefix in &["c4e", "rollapp1valcons", "1"] {
+            let encoded = encode(prefix, &data, Checksum::Bech32m);
+            let (decoded_prefix, decoded, _) = decode(&encoded).unwrap();
+            assert_eq!(decoded_prefix, *prefix);
+            assert_eq!(decoded, data);
+        }
+    }
+
+    #[test]
+    fn invalid_prefixes() {
+        assert!(validate_prefix("cosmosvalconspub").is_ok());
+        assert!(validate_prefix("c4e-valcons?").is_ok());
+
+        assert_eq!(validate_prefix("").unwrap_err(), "it's empty");
+        assert_eq!(
+            validate_prefix("Cosmos").unwrap_err(),
+            "character 1 ('C') is uppercase (prefixes must be lowercase)"
+        );
+        assert_eq!(
+            validate_prefix("cosmos valcons").unwrap_err(),
+            "character 7 (' ') isn't printable ASCII"
+        );
+        assert!(validate_prefix(&"a".repeat(84)).is_err());
+    }
+}
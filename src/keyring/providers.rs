@@ -12,7 +12,159 @@ pub mod yubihsm;
 #[cfg(feature = "fortanixdsm")]
 pub mod fortanixdsm;
 
+#[cfg(feature = "awskms")]
+pub mod awskms;
+
+#[cfg(feature = "azurekv")]
+pub mod azurekv;
+
+#[cfg(feature = "gcpkms")]
+pub mod gcpkms;
+
+#[cfg(feature = "vault")]
+pub mod vault;
+
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
+
+#[cfg(feature = "threshold")]
+pub mod threshold;
+
+#[cfg(feature = "testing")]
+pub mod mock;
+
+use crate::{
+    chain,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
 use std::fmt::{self, Display};
+use tendermint::TendermintKey;
+
+/// Signing key provider (i.e. backend) which keys in a
+/// [`KeyRing`](crate::keyring::KeyRing) sign with.
+///
+/// All signatures are produced through this trait: the keys of the built-in
+/// providers (see [`SigningProvider`]) implement it, and applications using
+/// the KMS as a library can implement it for their own backends, adding them
+/// to a chain registry with
+/// [`Registry::add_provider`](crate::chain::Registry::add_provider) (see
+/// [`crate::signer`]).
+pub trait SignerProvider: Send + Sync {
+    /// Short name of the provider (e.g. `yubihsm`), used in logs and metrics
+    fn name(&self) -> &str;
+
+    /// Description of the provider's key for log messages (e.g. which HSM
+    /// slot it's in)
+    fn describe(&self) -> String {
+        self.name().to_owned()
+    }
+
+    /// Types of key the provider signs with
+    fn key_types(&self) -> Vec<KeyType>;
+
+    /// Get the public key the provider signs for the given chain with
+    fn public_key(&self, chain_id: &chain::Id) -> Result<TendermintKey, Error>;
+
+    /// Sign the given message for the given chain, returning the raw
+    /// signature bytes (64 bytes for either type of key; secp256k1
+    /// signatures are the `r || s` of an ECDSA/SHA-256 signature)
+    fn sign(&self, chain_id: &chain::Id, msg: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Check the provider is still able to sign, beyond having loaded its
+    /// keys (e.g. that its HSM session or device is responding)
+    fn healthcheck(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Built-in provider this is ([`SigningProvider::Custom`] for providers
+    /// supplied by an application)
+    fn kind(&self) -> SigningProvider {
+        SigningProvider::Custom
+    }
+}
+
+/// Type of key a [`SignerProvider`] signs with
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum KeyType {
+    /// Ed25519 (consensus keys)
+    Ed25519,
+
+    /// secp256k1 ECDSA (account keys, or consensus keys of some chains)
+    Secp256k1,
+}
+
+impl Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyType::Ed25519 => f.write_str("Ed25519"),
+            KeyType::Secp256k1 => f.write_str("ECDSA"),
+        }
+    }
+}
+
+/// Key of a built-in provider, which signs with a [`signature::Signer`]
+pub(crate) struct BuiltinKey<S> {
+    /// Provider the key belongs to
+    kind: SigningProvider,
+
+    /// Type of the key
+    key_type: KeyType,
+
+    /// Tendermint public key
+    public_key: TendermintKey,
+
+    /// Signer trait object
+    signer: Box<dyn signature::Signer<S> + Send + Sync>,
+}
+
+impl<S> BuiltinKey<S> {
+    /// Create a new key of the given provider
+    pub(crate) fn new(
+        kind: SigningProvider,
+        key_type: KeyType,
+        public_key: TendermintKey,
+        signer: Box<dyn signature::Signer<S> + Send + Sync>,
+    ) -> Self {
+        Self {
+            kind,
+            key_type,
+            public_key,
+            signer,
+        }
+    }
+}
+
+impl<S: signature::Signature> SignerProvider for BuiltinKey<S> {
+    fn name(&self) -> &str {
+        self.kind.as_str()
+    }
+
+    fn key_types(&self) -> Vec<KeyType> {
+        vec![self.key_type]
+    }
+
+    fn public_key(&self, _chain_id: &chain::Id) -> Result<TendermintKey, Error> {
+        Ok(self.public_key)
+    }
+
+    fn sign(&self, _chain_id: &chain::Id, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        let signature = self
+            .signer
+            .try_sign(msg)
+            .map_err(|e| format_err!(SigningError, "{}", e))?;
+
+        Ok(signature.as_ref().to_vec())
+    }
+
+    fn healthcheck(&self) -> Result<(), Error> {
+        self.kind.healthcheck()
+    }
+
+    fn kind(&self) -> SigningProvider {
+        self.kind
+    }
+}
 
 /// Enumeration of signing key providers
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
@@ -32,22 +184,100 @@ pub enum SigningProvider {
     /// Fortanix DSM signer
     #[cfg(feature = "fortanixdsm")]
     FortanixDsm,
+
+    /// AWS KMS signer
+    #[cfg(feature = "awskms")]
+    AwsKms,
+
+    /// Azure Key Vault / Managed HSM signer
+    #[cfg(feature = "azurekv")]
+    AzureKv,
+
+    /// Google Cloud KMS signer
+    #[cfg(feature = "gcpkms")]
+    GcpKms,
+
+    /// HashiCorp Vault Transit signer
+    #[cfg(feature = "vault")]
+    Vault,
+
+    /// Generic PKCS#11 signer
+    #[cfg(feature = "pkcs11")]
+    Pkcs11,
+
+    /// FROST threshold signer (experimental)
+    #[cfg(feature = "threshold")]
+    Threshold,
+
+    /// Mock signer (for testing only)
+    #[cfg(feature = "testing")]
+    Mock,
+
+    /// Signer supplied by an application embedding the KMS
+    /// (see [`crate::signer`])
+    Custom,
 }
 
-impl Display for SigningProvider {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl SigningProvider {
+    /// Check the provider is still able to sign, beyond having loaded its
+    /// keys (e.g. that its HSM session or device is responding)
+    pub fn healthcheck(&self) -> Result<(), Error> {
+        #[cfg(feature = "yubihsm")]
+        if *self == SigningProvider::Yubihsm {
+            return yubihsm::healthcheck();
+        }
+
+        #[cfg(feature = "ledger")]
+        if *self == SigningProvider::LedgerTm {
+            return ledgertm::healthcheck();
+        }
+
+        Ok(())
+    }
+
+    /// Get the name of this provider (i.e. its section in `[providers]`)
+    pub fn as_str(&self) -> &'static str {
         match self {
             #[cfg(feature = "yubihsm")]
-            SigningProvider::Yubihsm => write!(f, "yubihsm"),
+            SigningProvider::Yubihsm => "yubihsm",
 
             #[cfg(feature = "ledger")]
-            SigningProvider::LedgerTm => write!(f, "ledgertm"),
+            SigningProvider::LedgerTm => "ledgertm",
 
             #[cfg(feature = "softsign")]
-            SigningProvider::SoftSign => write!(f, "softsign"),
+            SigningProvider::SoftSign => "softsign",
 
             #[cfg(feature = "fortanixdsm")]
-            SigningProvider::FortanixDsm => write!(f, "fortanixdsm"),
+            SigningProvider::FortanixDsm => "fortanixdsm",
+
+            #[cfg(feature = "awskms")]
+            SigningProvider::AwsKms => "awskms",
+
+            #[cfg(feature = "azurekv")]
+            SigningProvider::AzureKv => "azurekv",
+
+            #[cfg(feature = "gcpkms")]
+            SigningProvider::GcpKms => "gcpkms",
+
+            #[cfg(feature = "vault")]
+            SigningProvider::Vault => "vault",
+
+            #[cfg(feature = "pkcs11")]
+            SigningProvider::Pkcs11 => "pkcs11",
+
+            #[cfg(feature = "threshold")]
+            SigningProvider::Threshold => "threshold",
+
+            #[cfg(feature = "testing")]
+            SigningProvider::Mock => "mock",
+
+            SigningProvider::Custom => "custom",
         }
     }
 }
+
+impl Display for SigningProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
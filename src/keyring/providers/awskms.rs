@@ -0,0 +1,173 @@
+//! AWS KMS signing provider
+//!
+//! Signs using secp256k1 (`ECC_SECG_P256K1`) keys which never leave KMS, for
+//! either account or consensus keys.
+
+mod client;
+mod credentials;
+mod sigv4;
+
+use self::client::KmsClient;
+use crate::{
+    chain,
+    config::provider::{
+        awskms::{AwsKmsConfig, SigningKeyConfig},
+        KeyType,
+    },
+    error::{Error, ErrorKind::*},
+    keyring::{self, SigningProvider},
+    prelude::*,
+};
+use k256::{
+    ecdsa::{Error as SignError, Signature as EcdsaSignature, VerifyingKey},
+    pkcs8::DecodePublicKey,
+};
+use sha2::{Digest, Sha256};
+use signature::Signer;
+use std::{sync::Arc, time::Instant};
+use tendermint::{PublicKey, TendermintKey};
+
+/// Create AWS KMS backed signer objects from the given configuration
+pub fn init(registry: &mut chain::Registry, configs: &[AwsKmsConfig]) -> Result<(), Error> {
+    if configs.is_empty() {
+        return Ok(());
+    }
+
+    for config in configs {
+        let client = Arc::new(KmsClient::new(config)?);
+
+        for key in &config.signing_keys {
+            add_key(registry, key, client.clone())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Add a KMS signing key to the keyrings of the chains it's configured for
+fn add_key(
+    registry: &mut chain::Registry,
+    config: &SigningKeyConfig,
+    client: Arc<KmsClient>,
+) -> Result<(), Error> {
+    let (signing_key, verifying_key) = SigningKey::new(client, &config.key_id)?;
+
+    let public_key = match config.key_type {
+        KeyType::Account => TendermintKey::AccountKey(PublicKey::from(verifying_key)),
+        KeyType::Consensus => TendermintKey::ConsensusKey(PublicKey::from(verifying_key)),
+    };
+
+    let signer =
+        keyring::ecdsa::Signer::new(SigningProvider::AwsKms, public_key, Box::new(signing_key));
+
+    for chain_id in &config.chain_ids {
+        match config.key_type {
+            KeyType::Account => registry.add_account_key(chain_id, signer.clone())?,
+            KeyType::Consensus => registry.add_ecdsa_consensus_key(chain_id, signer.clone())?,
+        }
+    }
+
+    Ok(())
+}
+
+/// secp256k1 signing key stored in AWS KMS
+struct SigningKey {
+    /// KMS client
+    client: Arc<KmsClient>,
+
+    /// ARN (or ID/alias) of the key
+    key_id: String,
+}
+
+impl SigningKey {
+    /// Look up the given KMS key, returning a signer for it along with its
+    /// public key
+    fn new(client: Arc<KmsClient>, key_id: &str) -> Result<(Self, VerifyingKey), Error> {
+        let public_key_der = client.get_public_key(key_id)?;
+
+        let public_key = k256::PublicKey::from_public_key_der(&public_key_der).map_err(|e| {
+            format_err!(
+                AwsKmsError,
+                "failed to parse secp256k1 public key for {}: {}",
+                key_id,
+                e
+            )
+        })?;
+
+        let signing_key = SigningKey {
+            client,
+            key_id: key_id.to_owned(),
+        };
+
+        Ok((signing_key, public_key.into()))
+    }
+}
+
+impl Signer<EcdsaSignature> for SigningKey {
+    fn try_sign(&self, msg: &[u8]) -> Result<EcdsaSignature, SignError> {
+        let started_at = Instant::now();
+
+        let der_signature = self
+            .client
+            .sign_digest(&self.key_id, &Sha256::digest(msg))
+            .map_err(SignError::from_source)?;
+
+        info!(
+            "[keyring:awskms] signed with {} in {} ms",
+            self.key_id,
+            started_at.elapsed().as_millis()
+        );
+
+        parse_signature(&der_signature)
+    }
+}
+
+/// Parse an ASN.1 DER signature returned by KMS into a fixed-width signature,
+/// normalizing `s` into the lower half of the curve order as Tendermint and
+/// Cosmos require (KMS makes no guarantees about which `s` it returns)
+fn parse_signature(der_signature: &[u8]) -> Result<EcdsaSignature, SignError> {
+    let signature = EcdsaSignature::from_der(der_signature)?;
+    Ok(signature.normalize_s().unwrap_or(signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::SigningKey as SoftSigningKey;
+    use rand_core::OsRng;
+    use signature::Verifier;
+
+    #[test]
+    fn parse_high_s_signature() {
+        let signing_key = SoftSigningKey::random(&mut OsRng);
+        let msg = b"tmkms awskms test";
+
+        // Software signatures are already low-s normalized
+        let low_s: EcdsaSignature = signing_key.sign(msg);
+        let high_s =
+            EcdsaSignature::from_scalars(low_s.r().to_bytes(), (-*low_s.s()).to_bytes()).unwrap();
+        assert!(high_s.normalize_s().is_some());
+
+        let parsed = parse_signature(high_s.to_der().as_bytes()).unwrap();
+        assert_eq!(parsed, low_s);
+        assert_eq!(parsed.as_ref().len(), 64);
+
+        signing_key.verifying_key().verify(msg, &parsed).unwrap();
+    }
+
+    #[test]
+    fn parse_low_s_signature() {
+        let signing_key = SoftSigningKey::random(&mut OsRng);
+        let signature: EcdsaSignature = signing_key.sign(b"tmkms awskms test");
+
+        assert_eq!(
+            parse_signature(signature.to_der().as_bytes()).unwrap(),
+            signature
+        );
+    }
+
+    #[test]
+    fn reject_malformed_signature() {
+        assert!(parse_signature(b"not a DER signature").is_err());
+    }
+}
@@ -0,0 +1,423 @@
+//! Minimal AWS KMS client for the KMS JSON API
+
+use super::{credentials::CredentialsProvider, sigv4};
+use crate::{
+    config::provider::awskms::AwsKmsConfig,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use chrono::Utc;
+use hyper::{
+    body::Bytes,
+    client::HttpConnector,
+    http::{header, StatusCode, Uri},
+    Body, Request,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use rand_core::{OsRng, RngCore};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+use subtle_encoding::base64;
+use tokio::runtime::{self, Runtime};
+
+/// HTTP client used for requests to KMS and the credential endpoints
+pub type HttpClient = hyper::Client<HttpsConnector<HttpConnector>>;
+
+/// Default number of times to retry a throttled request
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// KMS key spec for secp256k1 keys
+pub const SECP256K1_KEY_SPEC: &str = "ECC_SECG_P256K1";
+
+/// Service name used when signing requests
+const SERVICE: &str = "kms";
+
+/// Content type of KMS API requests
+const CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+/// Timeout for a single request to KMS
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay before the first retry (doubled on each subsequent retry)
+const RETRY_BASE_DELAY_MS: u64 = 50;
+
+/// Maximum delay between retries
+const RETRY_MAX_DELAY_MS: u64 = 1000;
+
+/// Error types returned by KMS which are worth retrying
+const RETRYABLE_ERRORS: &[&str] = &[
+    "ThrottlingException",
+    "KMSInternalException",
+    "DependencyTimeoutException",
+];
+
+/// AWS KMS client.
+///
+/// Requests are performed on the client's own Tokio runtime, with callers
+/// blocking on a channel for the result, so the client can be used from both
+/// synchronous and asynchronous contexts.
+pub struct KmsClient {
+    /// Runtime which performs requests
+    runtime: Runtime,
+
+    /// State shared with in-flight requests
+    inner: Arc<Inner>,
+}
+
+impl KmsClient {
+    /// Create a new KMS client from the given configuration
+    pub fn new(config: &AwsKmsConfig) -> Result<Self, Error> {
+        let endpoint = config
+            .endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://kms.{}.amazonaws.com/", config.region))
+            .parse::<Uri>()
+            .map_err(|e| format_err!(ConfigError, "invalid AWS KMS endpoint: {}", e))?;
+
+        let host = endpoint
+            .authority()
+            .ok_or_else(|| format_err!(ConfigError, "AWS KMS endpoint has no host: {}", endpoint))?
+            .to_string();
+
+        let runtime = runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("awskms")
+            .enable_all()
+            .build()?;
+
+        let connector = HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        let inner = Inner {
+            http: hyper::Client::builder().build(connector),
+            endpoint,
+            host,
+            region: config.region.clone(),
+            credentials: CredentialsProvider::default(),
+            max_retries: config.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+        };
+
+        Ok(Self {
+            runtime,
+            inner: Arc::new(inner),
+        })
+    }
+
+    /// Get the ASN.1 DER-encoded public key of the given KMS key, ensuring
+    /// it's a secp256k1 signing key
+    pub fn get_public_key(&self, key_id: &str) -> Result<Vec<u8>, Error> {
+        let response: GetPublicKeyResponse =
+            self.call("GetPublicKey", &GetPublicKeyRequest { key_id })?;
+
+        if response.key_spec.as_deref() != Some(SECP256K1_KEY_SPEC) {
+            fail!(
+                AwsKmsError,
+                "KMS key {} has key spec {:?} (expected {})",
+                key_id,
+                response.key_spec.unwrap_or_default(),
+                SECP256K1_KEY_SPEC
+            );
+        }
+
+        if response.key_usage.as_deref() != Some("SIGN_VERIFY") {
+            fail!(
+                AwsKmsError,
+                "KMS key {} isn't a signing key (key usage: {:?})",
+                key_id,
+                response.key_usage.unwrap_or_default()
+            );
+        }
+
+        decode("PublicKey", &response.public_key)
+    }
+
+    /// Sign the given SHA-256 digest with the given KMS key, returning an
+    /// ASN.1 DER-encoded ECDSA signature
+    pub fn sign_digest(&self, key_id: &str, digest: &[u8]) -> Result<Vec<u8>, Error> {
+        let request = SignRequest {
+            key_id,
+            message: String::from_utf8(base64::encode(digest)).unwrap(),
+            message_type: "DIGEST",
+            signing_algorithm: "ECDSA_SHA_256",
+        };
+
+        let response: SignResponse = self.call("Sign", &request)?;
+        decode("Signature", &response.signature)
+    }
+
+    /// Call the given KMS API action
+    fn call<Req, Resp>(&self, action: &'static str, request: &Req) -> Result<Resp, Error>
+    where
+        Req: Serialize,
+        Resp: DeserializeOwned,
+    {
+        let body = serde_json::to_vec(request)?;
+        let inner = Arc::clone(&self.inner);
+        let (tx, rx) = mpsc::channel();
+
+        self.runtime.spawn(async move {
+            let _ = tx.send(inner.call(action, body).await);
+        });
+
+        let response = rx
+            .recv()
+            .map_err(|_| format_err!(AwsKmsError, "{} request was aborted", action))??;
+
+        Ok(serde_json::from_slice(&response)?)
+    }
+}
+
+/// Client state shared with in-flight requests
+struct Inner {
+    /// HTTP client
+    http: HttpClient,
+
+    /// KMS endpoint
+    endpoint: Uri,
+
+    /// Host (i.e. authority) of the KMS endpoint
+    host: String,
+
+    /// AWS region
+    region: String,
+
+    /// Source of credentials for signing requests
+    credentials: CredentialsProvider,
+
+    /// Maximum number of times to retry a throttled request
+    max_retries: u32,
+}
+
+impl Inner {
+    /// Call the given KMS API action, retrying with backoff if throttled
+    async fn call(&self, action: &str, body: Vec<u8>) -> Result<Bytes, Error> {
+        let mut retries = 0;
+
+        loop {
+            match self.request(action, &body).await {
+                Ok(response) => return Ok(response),
+                Err(Failure::Retryable(e)) if retries < self.max_retries => {
+                    retries += 1;
+                    let delay = retry_delay(retries);
+
+                    warn!(
+                        "[keyring:awskms] {}; retry {}/{} in {} ms",
+                        e,
+                        retries,
+                        self.max_retries,
+                        delay.as_millis()
+                    );
+
+                    tokio::time::sleep(delay).await;
+                }
+                Err(Failure::Retryable(e)) | Err(Failure::Fatal(e)) => return Err(e),
+            }
+        }
+    }
+
+    /// Make a single signed request to the KMS API
+    async fn request(&self, action: &str, body: &[u8]) -> Result<Bytes, Failure> {
+        let credentials = self
+            .credentials
+            .credentials(&self.http)
+            .await
+            .map_err(Failure::Fatal)?;
+
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let target = format!("TrentService.{}", action);
+
+        // Headers to sign, sorted by name
+        let mut headers = vec![
+            ("content-type", CONTENT_TYPE),
+            ("host", self.host.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+
+        if let Some(token) = &credentials.session_token {
+            headers.push(("x-amz-security-token", token.as_str()));
+        }
+
+        headers.push(("x-amz-target", target.as_str()));
+
+        let authorization = sigv4::authorization(
+            &credentials,
+            &self.region,
+            SERVICE,
+            &amz_date,
+            &headers,
+            body,
+        );
+
+        let mut request = Request::post(self.endpoint.clone());
+
+        for (name, value) in &headers {
+            request = request.header(*name, *value);
+        }
+
+        let request = request
+            .header(header::AUTHORIZATION, authorization)
+            .body(Body::from(body.to_vec()))
+            .unwrap();
+
+        let (status, response) = send(&self.http, request, REQUEST_TIMEOUT)
+            .await
+            .map_err(Failure::Retryable)?;
+
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let error = serde_json::from_slice::<ErrorResponse>(&response).unwrap_or_default();
+        let error_type = error.error_type.rsplit('#').next().unwrap_or_default();
+
+        let e = format_err!(
+            AwsKmsError,
+            "KMS {} failed with HTTP {}: {} {}",
+            action,
+            status,
+            error_type,
+            error.message
+        )
+        .into();
+
+        if status == StatusCode::TOO_MANY_REQUESTS
+            || status.is_server_error()
+            || RETRYABLE_ERRORS.contains(&error_type)
+        {
+            Err(Failure::Retryable(e))
+        } else {
+            Err(Failure::Fatal(e))
+        }
+    }
+}
+
+/// Failed KMS request
+enum Failure {
+    /// Throttling or transient error which is worth retrying
+    Retryable(Error),
+
+    /// Any other error
+    Fatal(Error),
+}
+
+/// Send an HTTP request, returning the response status and body
+pub async fn send(
+    http: &HttpClient,
+    request: Request<Body>,
+    timeout: Duration,
+) -> Result<(StatusCode, Bytes), Error> {
+    let uri = request.uri().clone();
+
+    let result = tokio::time::timeout(timeout, async {
+        let response = http.request(request).await?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        Ok::<_, hyper::Error>((status, body))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(response)) => Ok(response),
+        Ok(Err(e)) => fail!(AwsKmsError, "request to {} failed: {}", uri, e),
+        Err(_) => fail!(
+            AwsKmsError,
+            "request to {} timed out after {:?}",
+            uri,
+            timeout
+        ),
+    }
+}
+
+/// Delay before the given retry, using exponential backoff with jitter
+fn retry_delay(retry: u32) -> Duration {
+    let exponent = retry.saturating_sub(1).min(16);
+    let delay_ms = (RETRY_BASE_DELAY_MS << exponent).min(RETRY_MAX_DELAY_MS);
+
+    // "Equal jitter", as used for validator reconnects
+    let half_ms = delay_ms / 2;
+    Duration::from_millis(half_ms + OsRng.next_u64() % (half_ms + 1))
+}
+
+/// Decode a Base64-encoded field of a KMS response
+fn decode(field: &str, data: &str) -> Result<Vec<u8>, Error> {
+    base64::decode(data)
+        .map_err(|e| format_err!(AwsKmsError, "malformed {} in KMS response: {}", field, e).into())
+}
+
+/// `GetPublicKey` request
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetPublicKeyRequest<'a> {
+    key_id: &'a str,
+}
+
+/// `GetPublicKey` response
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetPublicKeyResponse {
+    public_key: String,
+    key_spec: Option<String>,
+    key_usage: Option<String>,
+}
+
+/// `Sign` request
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SignRequest<'a> {
+    key_id: &'a str,
+    message: String,
+    message_type: &'static str,
+    signing_algorithm: &'static str,
+}
+
+/// `Sign` response
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SignResponse {
+    signature: String,
+}
+
+/// Error response
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ErrorResponse {
+    #[serde(rename = "__type")]
+    error_type: String,
+
+    #[serde(alias = "Message")]
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_backs_off() {
+        for (retry, max_ms) in &[(1, 50), (2, 100), (3, 200), (5, 800), (6, 1000), (40, 1000)] {
+            let delay = retry_delay(*retry);
+            let max = Duration::from_millis(*max_ms);
+            assert!(delay >= max / 2 && delay <= max, "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn parse_error_response() {
+        let error = serde_json::from_slice::<ErrorResponse>(
+            br#"{"__type":"com.amazonaws.kms#ThrottlingException","message":"Rate exceeded"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            error.error_type.rsplit('#').next(),
+            Some("ThrottlingException")
+        );
+        assert_eq!(error.message, "Rate exceeded");
+    }
+}
@@ -0,0 +1,339 @@
+//! AWS credentials, loaded using the standard AWS credential provider chain:
+//!
+//! 1. `AWS_ACCESS_KEY_ID`/`AWS_SECRET_ACCESS_KEY` (and `AWS_SESSION_TOKEN`)
+//!    environment variables
+//! 2. Shared credentials file (`AWS_SHARED_CREDENTIALS_FILE`, or
+//!    `~/.aws/credentials`) using the `AWS_PROFILE` profile (or `default`)
+//! 3. ECS container credentials (`AWS_CONTAINER_CREDENTIALS_RELATIVE_URI`)
+//! 4. EC2 instance metadata service (IMDSv2)
+
+use super::client::{send, HttpClient};
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use chrono::{DateTime, Duration, Utc};
+use hyper::{Body, Method, Request};
+use serde::Deserialize;
+use std::{env, fs, path::PathBuf, sync::Mutex, time::Duration as StdDuration};
+use zeroize::Zeroizing;
+
+/// Base URI of the ECS container credentials endpoint
+const ECS_CREDENTIALS_URI: &str = "http://169.254.170.2";
+
+/// Base URI of the EC2 instance metadata service
+const IMDS_URI: &str = "http://169.254.169.254";
+
+/// Timeout for requests to the ECS/EC2 metadata endpoints
+const METADATA_TIMEOUT: StdDuration = StdDuration::from_secs(2);
+
+/// Refresh temporary credentials this long before they expire
+const EXPIRY_MARGIN_SECS: i64 = 300;
+
+/// AWS credentials
+#[derive(Clone)]
+pub struct Credentials {
+    /// Access key ID
+    pub access_key_id: String,
+
+    /// Secret access key
+    pub secret_access_key: Zeroizing<String>,
+
+    /// Session token (for temporary credentials)
+    pub session_token: Option<Zeroizing<String>>,
+
+    /// Expiration time (for temporary credentials)
+    pub expiration: Option<DateTime<Utc>>,
+}
+
+impl Credentials {
+    /// Create new credentials
+    pub fn new(
+        access_key_id: impl Into<String>,
+        secret_access_key: impl Into<String>,
+        session_token: Option<String>,
+        expiration: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            access_key_id: access_key_id.into(),
+            secret_access_key: Zeroizing::new(secret_access_key.into()),
+            session_token: session_token.map(Zeroizing::new),
+            expiration,
+        }
+    }
+
+    /// Do these credentials need to be refreshed?
+    fn needs_refresh(&self) -> bool {
+        self.expiration.map_or(false, |expiration| {
+            expiration - Duration::seconds(EXPIRY_MARGIN_SECS) <= Utc::now()
+        })
+    }
+}
+
+/// Credentials provider which caches credentials until they expire
+#[derive(Default)]
+pub struct CredentialsProvider {
+    /// Most recently loaded credentials
+    cached: Mutex<Option<Credentials>>,
+}
+
+impl CredentialsProvider {
+    /// Get credentials, loading them from the provider chain if we don't have
+    /// any (or they're about to expire)
+    pub async fn credentials(&self, http: &HttpClient) -> Result<Credentials, Error> {
+        if let Some(credentials) = self.cached.lock().unwrap().as_ref() {
+            if !credentials.needs_refresh() {
+                return Ok(credentials.clone());
+            }
+        }
+
+        let credentials = load(http).await?;
+        *self.cached.lock().unwrap() = Some(credentials.clone());
+        Ok(credentials)
+    }
+}
+
+/// Load credentials from the standard provider chain
+async fn load(http: &HttpClient) -> Result<Credentials, Error> {
+    if let Some(credentials) = from_env() {
+        debug!("[keyring:awskms] using credentials from environment");
+        return Ok(credentials);
+    }
+
+    if let Some(credentials) = from_shared_file()? {
+        debug!("[keyring:awskms] using credentials from shared credentials file");
+        return Ok(credentials);
+    }
+
+    if let Ok(relative_uri) = env::var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI") {
+        debug!("[keyring:awskms] using ECS container credentials");
+        return from_ecs(http, &relative_uri).await;
+    }
+
+    debug!("[keyring:awskms] using EC2 instance metadata credentials");
+    from_imds(http).await.map_err(|e| {
+        format_err!(
+            AwsKmsError,
+            "no AWS credentials found in environment, shared credentials file, \
+             or instance metadata ({})",
+            e
+        )
+        .into()
+    })
+}
+
+/// Load credentials from environment variables
+fn from_env() -> Option<Credentials> {
+    let access_key_id = env::var("AWS_ACCESS_KEY_ID").ok()?;
+    let secret_access_key = env::var("AWS_SECRET_ACCESS_KEY").ok()?;
+    let session_token = env::var("AWS_SESSION_TOKEN").ok();
+
+    Some(Credentials::new(
+        access_key_id,
+        secret_access_key,
+        session_token,
+        None,
+    ))
+}
+
+/// Load credentials from the shared credentials file, if it exists
+fn from_shared_file() -> Result<Option<Credentials>, Error> {
+    let path = match env::var_os("AWS_SHARED_CREDENTIALS_FILE") {
+        Some(path) => PathBuf::from(path),
+        None => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".aws").join("credentials"),
+            None => return Ok(None),
+        },
+    };
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = Zeroizing::new(fs::read_to_string(&path).map_err(|e| {
+        format_err!(
+            IoError,
+            "couldn't read AWS credentials from {}: {}",
+            path.display(),
+            e
+        )
+    })?);
+
+    let profile = env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_owned());
+    Ok(parse_shared_file(&contents, &profile))
+}
+
+/// Parse the given profile's credentials out of a shared credentials file
+fn parse_shared_file(contents: &str, profile: &str) -> Option<Credentials> {
+    let mut in_profile = false;
+    let mut access_key_id = None;
+    let mut secret_access_key = None;
+    let mut session_token = None;
+
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_profile = section.trim() == profile;
+            continue;
+        }
+
+        if !in_profile {
+            continue;
+        }
+
+        if let Some((key, value)) = line.split_once('=') {
+            let value = Some(value.trim().to_owned());
+
+            match key.trim() {
+                "aws_access_key_id" => access_key_id = value,
+                "aws_secret_access_key" => secret_access_key = value,
+                "aws_session_token" => session_token = value,
+                _ => (),
+            }
+        }
+    }
+
+    Some(Credentials::new(
+        access_key_id?,
+        secret_access_key?,
+        session_token,
+        None,
+    ))
+}
+
+/// Temporary credentials served by the ECS/EC2 metadata endpoints
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MetadataCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    token: Option<String>,
+    expiration: Option<String>,
+}
+
+impl From<MetadataCredentials> for Credentials {
+    fn from(creds: MetadataCredentials) -> Credentials {
+        Credentials::new(
+            creds.access_key_id,
+            creds.secret_access_key,
+            creds.token,
+            creds
+                .expiration
+                .and_then(|expiration| DateTime::parse_from_rfc3339(&expiration).ok())
+                .map(Into::into),
+        )
+    }
+}
+
+/// Load credentials from the ECS container credentials endpoint
+async fn from_ecs(http: &HttpClient, relative_uri: &str) -> Result<Credentials, Error> {
+    let request = Request::get(format!("{}{}", ECS_CREDENTIALS_URI, relative_uri))
+        .body(Body::empty())
+        .unwrap();
+
+    let body = metadata_request(http, request).await?;
+    Ok(serde_json::from_slice::<MetadataCredentials>(&body)?.into())
+}
+
+/// Load credentials from the EC2 instance metadata service (IMDSv2)
+async fn from_imds(http: &HttpClient) -> Result<Credentials, Error> {
+    let request = Request::builder()
+        .method(Method::PUT)
+        .uri(format!("{}/latest/api/token", IMDS_URI))
+        .header("x-aws-ec2-metadata-token-ttl-seconds", "21600")
+        .body(Body::empty())
+        .unwrap();
+
+    let token = String::from_utf8_lossy(&metadata_request(http, request).await?).into_owned();
+
+    let imds_get = |path: String| {
+        Request::get(format!(
+            "{}/latest/meta-data/iam/security-credentials/{}",
+            IMDS_URI, path
+        ))
+        .header("x-aws-ec2-metadata-token", token.as_str())
+        .body(Body::empty())
+        .unwrap()
+    };
+
+    let roles = metadata_request(http, imds_get(String::new())).await?;
+    let role = String::from_utf8_lossy(&roles)
+        .lines()
+        .next()
+        .map(str::to_owned)
+        .ok_or_else(|| format_err!(AwsKmsError, "no IAM role attached to instance"))?;
+
+    let body = metadata_request(http, imds_get(role)).await?;
+    Ok(serde_json::from_slice::<MetadataCredentials>(&body)?.into())
+}
+
+/// Make a request to a metadata endpoint, returning the response body
+async fn metadata_request(http: &HttpClient, request: Request<Body>) -> Result<Vec<u8>, Error> {
+    let uri = request.uri().clone();
+    let (status, body) = send(http, request, METADATA_TIMEOUT).await?;
+
+    if !status.is_success() {
+        fail!(AwsKmsError, "{} returned HTTP {}", uri, status);
+    }
+
+    Ok(body.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE_FILE: &str = r#"
+# comment
+[default]
+aws_access_key_id = AKIDDEFAULT
+aws_secret_access_key = default-secret
+
+[validator]
+aws_access_key_id=AKIDVALIDATOR
+aws_secret_access_key=validator-secret
+aws_session_token = validator-token
+
+[incomplete]
+aws_access_key_id = AKIDINCOMPLETE
+"#;
+
+    #[test]
+    fn parse_default_profile() {
+        let creds = parse_shared_file(EXAMPLE_FILE, "default").unwrap();
+        assert_eq!(creds.access_key_id, "AKIDDEFAULT");
+        assert_eq!(creds.secret_access_key.as_str(), "default-secret");
+        assert!(creds.session_token.is_none());
+    }
+
+    #[test]
+    fn parse_named_profile() {
+        let creds = parse_shared_file(EXAMPLE_FILE, "validator").unwrap();
+        assert_eq!(creds.access_key_id, "AKIDVALIDATOR");
+        assert_eq!(creds.secret_access_key.as_str(), "validator-secret");
+        assert_eq!(
+            creds.session_token.as_ref().map(|t| t.as_str()),
+            Some("validator-token")
+        );
+    }
+
+    #[test]
+    fn parse_missing_or_incomplete_profile() {
+        assert!(parse_shared_file(EXAMPLE_FILE, "nonexistent").is_none());
+        assert!(parse_shared_file(EXAMPLE_FILE, "incomplete").is_none());
+    }
+
+    #[test]
+    fn refresh_before_expiration() {
+        let expiring = Credentials::new("a", "b", None, Some(Utc::now() + Duration::seconds(60)));
+        assert!(expiring.needs_refresh());
+
+        let fresh = Credentials::new("a", "b", None, Some(Utc::now() + Duration::hours(1)));
+        assert!(!fresh.needs_refresh());
+
+        assert!(!Credentials::new("a", "b", None, None).needs_refresh());
+    }
+}
@@ -0,0 +1,143 @@
+//! AWS Signature Version 4 request signing
+
+use super::credentials::Credentials;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+use subtle_encoding::hex;
+
+/// HMAC-SHA-256
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signature algorithm identifier
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// Compute the `Authorization` header for a `POST /` request.
+///
+/// `headers` are the (lowercase) names and values of the headers to sign,
+/// sorted by name, and `amz_date` is the value of the `x-amz-date` header.
+pub fn authorization(
+    credentials: &Credentials,
+    region: &str,
+    service: &str,
+    amz_date: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> String {
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+
+    let canonical_headers = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect::<String>();
+
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "POST\n/\n\n{}\n{}\n{}",
+        canonical_headers,
+        signed_headers,
+        hex_sha256(body)
+    );
+
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        amz_date,
+        scope,
+        hex_sha256(canonical_request.as_bytes())
+    );
+
+    let key = signing_key(&credentials.secret_access_key, date, region, service);
+    let signature = hex::encode(hmac(&key, string_to_sign.as_bytes()));
+
+    format!(
+        "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+        ALGORITHM,
+        credentials.access_key_id,
+        scope,
+        signed_headers,
+        String::from_utf8(signature).unwrap()
+    )
+}
+
+/// Derive the signing key for the given date, region, and service
+fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac(
+        format!("AWS4{}", secret_access_key).as_bytes(),
+        date.as_bytes(),
+    );
+    let key = hmac(&key, region.as_bytes());
+    let key = hmac(&key, service.as_bytes());
+    hmac(&key, b"aws4_request")
+}
+
+/// Compute HMAC-SHA-256 of the given data
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Compute the hex-encoded SHA-256 digest of the given data
+fn hex_sha256(data: &[u8]) -> String {
+    String::from_utf8(hex::encode(Sha256::digest(data))).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Example credentials from the AWS documentation
+    fn example_credentials() -> Credentials {
+        Credentials::new(
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn derive_signing_key() {
+        // https://docs.aws.amazon.com/general/latest/gr/signature-v4-examples.html
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+
+        assert_eq!(
+            hex::encode(key),
+            b"f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn post_vanilla() {
+        // `post-vanilla` from the AWS Signature Version 4 test suite
+        let authorization = authorization(
+            &example_credentials(),
+            "us-east-1",
+            "service",
+            "20150830T123600Z",
+            &[
+                ("host", "example.amazonaws.com"),
+                ("x-amz-date", "20150830T123600Z"),
+            ],
+            b"",
+        );
+
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b"
+        );
+    }
+}
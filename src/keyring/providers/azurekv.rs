@@ -0,0 +1,174 @@
+//! Azure Key Vault / Managed HSM signing provider
+//!
+//! Signs using secp256k1 (`P-256K`) keys which never leave the vault, for
+//! either account or consensus keys (Key Vault doesn't support Ed25519).
+
+mod client;
+mod token;
+
+use self::client::KeyVaultClient;
+use crate::{
+    chain,
+    config::provider::{
+        azurekv::{AzureKvConfig, SigningKeyConfig},
+        KeyType,
+    },
+    error::{Error, ErrorKind::*},
+    keyring::{self, SigningProvider},
+    prelude::*,
+};
+use k256::ecdsa::{Error as SignError, Signature as EcdsaSignature, VerifyingKey};
+use sha2::{Digest, Sha256};
+use signature::Signer;
+use std::{sync::Arc, time::Instant};
+use tendermint::{PublicKey, TendermintKey};
+
+/// Create Azure Key Vault backed signer objects from the given configuration
+pub fn init(registry: &mut chain::Registry, configs: &[AzureKvConfig]) -> Result<(), Error> {
+    if configs.is_empty() {
+        return Ok(());
+    }
+
+    for config in configs {
+        let client = Arc::new(KeyVaultClient::new(config)?);
+
+        for key in &config.signing_keys {
+            add_key(registry, key, client.clone())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Add a Key Vault signing key to the keyrings of the chains it's configured
+/// for
+fn add_key(
+    registry: &mut chain::Registry,
+    config: &SigningKeyConfig,
+    client: Arc<KeyVaultClient>,
+) -> Result<(), Error> {
+    let (signing_key, verifying_key) =
+        SigningKey::new(client, &config.key_name, config.key_version.as_deref())?;
+
+    let public_key = match config.key_type {
+        KeyType::Account => TendermintKey::AccountKey(PublicKey::from(verifying_key)),
+        KeyType::Consensus => TendermintKey::ConsensusKey(PublicKey::from(verifying_key)),
+    };
+
+    let signer =
+        keyring::ecdsa::Signer::new(SigningProvider::AzureKv, public_key, Box::new(signing_key));
+
+    for chain_id in &config.chain_ids {
+        match config.key_type {
+            KeyType::Account => registry.add_account_key(chain_id, signer.clone())?,
+            KeyType::Consensus => registry.add_ecdsa_consensus_key(chain_id, signer.clone())?,
+        }
+    }
+
+    Ok(())
+}
+
+/// secp256k1 signing key stored in Azure Key Vault
+struct SigningKey {
+    /// Key Vault client
+    client: Arc<KeyVaultClient>,
+
+    /// Name of the key
+    key_name: String,
+
+    /// Version of the key to sign with (i.e. the one whose public key was
+    /// registered in the keyring)
+    key_version: String,
+}
+
+impl SigningKey {
+    /// Look up the given key, returning a signer for it along with its public
+    /// key
+    fn new(
+        client: Arc<KeyVaultClient>,
+        key_name: &str,
+        key_version: Option<&str>,
+    ) -> Result<(Self, VerifyingKey), Error> {
+        let (key_version, public_key) = client.get_key(key_name, key_version)?;
+
+        let verifying_key = VerifyingKey::from_sec1_bytes(&public_key).map_err(|e| {
+            format_err!(
+                AzureKvError,
+                "failed to parse secp256k1 public key for {}: {}",
+                key_name,
+                e
+            )
+        })?;
+
+        info!(
+            "[keyring:azurekv] using version {} of key {}",
+            key_version, key_name
+        );
+
+        let signing_key = SigningKey {
+            client,
+            key_name: key_name.to_owned(),
+            key_version,
+        };
+
+        Ok((signing_key, verifying_key))
+    }
+}
+
+impl Signer<EcdsaSignature> for SigningKey {
+    fn try_sign(&self, msg: &[u8]) -> Result<EcdsaSignature, SignError> {
+        let started_at = Instant::now();
+
+        let signature = self
+            .client
+            .sign_digest(&self.key_name, &self.key_version, &Sha256::digest(msg))
+            .map_err(SignError::from_source)?;
+
+        info!(
+            "[keyring:azurekv] signed with {} in {} ms",
+            self.key_name,
+            started_at.elapsed().as_millis()
+        );
+
+        parse_signature(&signature)
+    }
+}
+
+/// Parse a raw (`r || s`) signature returned by Key Vault, normalizing `s`
+/// into the lower half of the curve order as Tendermint and Cosmos require
+/// (Key Vault makes no guarantees about which `s` it returns)
+fn parse_signature(signature: &[u8]) -> Result<EcdsaSignature, SignError> {
+    let signature = EcdsaSignature::try_from(signature)?;
+    Ok(signature.normalize_s().unwrap_or(signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::SigningKey as SoftSigningKey;
+    use rand_core::OsRng;
+    use signature::Verifier;
+
+    #[test]
+    fn parse_high_s_signature() {
+        let signing_key = SoftSigningKey::random(&mut OsRng);
+        let msg = b"tmkms azurekv test";
+
+        // Software signatures are already low-s normalized
+        let low_s: EcdsaSignature = signing_key.sign(msg);
+        let high_s =
+            EcdsaSignature::from_scalars(low_s.r().to_bytes(), (-*low_s.s()).to_bytes()).unwrap();
+        assert!(high_s.normalize_s().is_some());
+
+        let parsed = parse_signature(high_s.as_ref()).unwrap();
+        assert_eq!(parsed, low_s);
+
+        signing_key.verifying_key().verify(msg, &parsed).unwrap();
+    }
+
+    #[test]
+    fn reject_malformed_signature() {
+        assert!(parse_signature(&[0u8; 63]).is_err());
+        assert!(parse_signature(&[0u8; 64]).is_err());
+    }
+}
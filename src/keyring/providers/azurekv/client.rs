@@ -0,0 +1,532 @@
+//! Minimal Azure Key Vault client for the keys REST API (which is also served
+//! by Managed HSM)
+
+use super::token::{TokenProvider, DEFAULT_AUTHORITY_HOST};
+use crate::{
+    config::provider::azurekv::AzureKvConfig,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use hyper::{
+    body::Bytes,
+    client::HttpConnector,
+    header,
+    http::{Method, StatusCode},
+    Body, Request,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use rand_core::{OsRng, RngCore};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+use subtle_encoding::base64;
+use tokio::runtime::{self, Runtime};
+use url::Url;
+
+/// HTTP client used for requests to Key Vault and Azure AD
+pub type HttpClient = hyper::Client<HttpsConnector<HttpConnector>>;
+
+/// Default number of times to retry a throttled or failed request
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Key Vault REST API version
+const API_VERSION: &str = "7.4";
+
+/// JSON Web Key curve name of secp256k1
+const SECP256K1_CURVE: &str = "P-256K";
+
+/// Signature algorithm: ECDSA over secp256k1 with SHA-256
+const SIGNING_ALGORITHM: &str = "ES256K";
+
+/// Timeout for a single request to Key Vault
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay before the first retry (doubled on each subsequent retry)
+const RETRY_BASE_DELAY_MS: u64 = 50;
+
+/// Maximum delay between retries
+const RETRY_MAX_DELAY_MS: u64 = 1000;
+
+/// Azure Key Vault client.
+///
+/// Requests are performed on the client's own Tokio runtime, with callers
+/// blocking on a channel for the result.
+pub struct KeyVaultClient {
+    /// Runtime which performs requests
+    runtime: Runtime,
+
+    /// State shared with in-flight requests
+    inner: Arc<Inner>,
+}
+
+impl KeyVaultClient {
+    /// Create a new Key Vault client from the given configuration
+    pub fn new(config: &AzureKvConfig) -> Result<Self, Error> {
+        let vault_url = config.vault_url.trim_end_matches('/').to_owned();
+        let resource = token_resource(&vault_url)?;
+
+        let runtime = runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("azurekv")
+            .enable_all()
+            .build()?;
+
+        let connector = HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        let authority_host = config
+            .authority_host
+            .as_deref()
+            .unwrap_or(DEFAULT_AUTHORITY_HOST);
+
+        let inner = Inner {
+            http: hyper::Client::builder().build(connector),
+            vault_url,
+            tokens: TokenProvider::new(config.auth.clone(), authority_host, resource),
+            max_retries: config.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+        };
+
+        Ok(Self {
+            runtime,
+            inner: Arc::new(inner),
+        })
+    }
+
+    /// Get the given version of a key (or its current version if none is
+    /// given), ensuring it's a secp256k1 signing key. Returns the key's
+    /// version along with its SEC1-encoded (uncompressed) public key.
+    pub fn get_key(
+        &self,
+        key_name: &str,
+        key_version: Option<&str>,
+    ) -> Result<(String, Vec<u8>), Error> {
+        let path = match key_version {
+            Some(version) => format!("keys/{}/{}", key_name, version),
+            None => format!("keys/{}", key_name),
+        };
+
+        let response: KeyBundle = self.call(Method::GET, path, None)?;
+        let key = response.key;
+
+        if key.crv.as_deref() != Some(SECP256K1_CURVE) {
+            fail!(
+                AzureKvError,
+                "key {} has curve {:?} (expected {})",
+                key_name,
+                key.crv.unwrap_or_default(),
+                SECP256K1_CURVE
+            );
+        }
+
+        if !key.key_ops.iter().any(|op| op == "sign") {
+            fail!(
+                AzureKvError,
+                "key {} isn't permitted to sign (key_ops: {:?})",
+                key_name,
+                key.key_ops
+            );
+        }
+
+        if !response.attributes.enabled {
+            fail!(AzureKvError, "key {} is disabled", key_name);
+        }
+
+        let version = key_version_from_kid(&key.kid).ok_or_else(|| {
+            format_err!(AzureKvError, "malformed key ID in response: {}", key.kid)
+        })?;
+
+        let mut public_key = vec![0x04];
+        public_key.extend_from_slice(&decode("x", key.x.as_deref().unwrap_or_default())?);
+        public_key.extend_from_slice(&decode("y", key.y.as_deref().unwrap_or_default())?);
+
+        Ok((version.to_owned(), public_key))
+    }
+
+    /// Sign the given SHA-256 digest with the given version of a key,
+    /// returning the raw (`r || s`) ECDSA signature
+    pub fn sign_digest(
+        &self,
+        key_name: &str,
+        key_version: &str,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let body = json!({
+            "alg": SIGNING_ALGORITHM,
+            "value": base64url_encode(digest),
+        });
+
+        let response: KeyOperationResult = self.call(
+            Method::POST,
+            format!("keys/{}/{}/sign", key_name, key_version),
+            Some(body),
+        )?;
+
+        decode("value", &response.value)
+    }
+
+    /// Call the given Key Vault API endpoint
+    fn call<T>(
+        &self,
+        method: Method,
+        path: String,
+        body: Option<serde_json::Value>,
+    ) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let inner = Arc::clone(&self.inner);
+        let (tx, rx) = mpsc::channel();
+
+        self.runtime.spawn(async move {
+            let _ = tx.send(inner.call(method, &path, body).await);
+        });
+
+        let response = rx
+            .recv()
+            .map_err(|_| format_err!(AzureKvError, "Key Vault request was aborted"))??;
+
+        Ok(serde_json::from_slice(&response)?)
+    }
+}
+
+/// Client state shared with in-flight requests
+struct Inner {
+    /// HTTP client
+    http: HttpClient,
+
+    /// Vault URL (without a trailing slash)
+    vault_url: String,
+
+    /// Source of access tokens
+    tokens: TokenProvider,
+
+    /// Maximum number of times to retry a throttled or failed request
+    max_retries: u32,
+}
+
+impl Inner {
+    /// Call the given Key Vault API endpoint, retrying with backoff if
+    /// throttled or on transient errors.
+    ///
+    /// Retries happen within a single signing request (and sign the same
+    /// digest), so they're invisible to double signing protection.
+    async fn call(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<Bytes, Error> {
+        let mut retries = 0;
+
+        loop {
+            match self.request(method.clone(), path, body.as_ref()).await {
+                Ok(response) => return Ok(response),
+                Err(Failure::Retryable(e)) if retries < self.max_retries => {
+                    retries += 1;
+                    let delay = retry_delay(retries);
+
+                    warn!(
+                        "[keyring:azurekv] {}; retry {}/{} in {} ms",
+                        e,
+                        retries,
+                        self.max_retries,
+                        delay.as_millis()
+                    );
+
+                    tokio::time::sleep(delay).await;
+                }
+                Err(Failure::Retryable(e)) | Err(Failure::Fatal(e)) => return Err(e),
+            }
+        }
+    }
+
+    /// Make a single request to the Key Vault API
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<Bytes, Failure> {
+        let token = self
+            .tokens
+            .token(&self.http)
+            .await
+            .map_err(Failure::Fatal)?;
+
+        let uri = format!("{}/{}?api-version={}", self.vault_url, path, API_VERSION);
+
+        let body = match body {
+            Some(body) => {
+                Body::from(serde_json::to_vec(body).map_err(|e| Failure::Fatal(e.into()))?)
+            }
+            None => Body::empty(),
+        };
+
+        let request = Request::builder()
+            .method(method)
+            .uri(&uri)
+            .header(
+                header::AUTHORIZATION,
+                format!("Bearer {}", token.token.as_str()),
+            )
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .unwrap();
+
+        let (status, response) = send(&self.http, request, REQUEST_TIMEOUT)
+            .await
+            .map_err(Failure::Retryable)?;
+
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let error = serde_json::from_slice::<ErrorResponse>(&response)
+            .unwrap_or_default()
+            .error;
+
+        let e = format_err!(
+            AzureKvError,
+            "Key Vault {} failed with HTTP {}: {} {}",
+            path,
+            status,
+            error.code,
+            error.message
+        )
+        .into();
+
+        if status == StatusCode::UNAUTHORIZED {
+            // Token was revoked or expired early: get a new one and try again
+            self.tokens.invalidate();
+            Err(Failure::Retryable(e))
+        } else if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            Err(Failure::Retryable(e))
+        } else {
+            Err(Failure::Fatal(e))
+        }
+    }
+}
+
+/// Failed Key Vault request
+enum Failure {
+    /// Throttling or transient error which is worth retrying
+    Retryable(Error),
+
+    /// Any other error
+    Fatal(Error),
+}
+
+/// Send an HTTP request, returning the response status and body
+pub async fn send(
+    http: &HttpClient,
+    request: Request<Body>,
+    timeout: Duration,
+) -> Result<(StatusCode, Bytes), Error> {
+    // Don't log query strings (which may identify managed identities)
+    let uri = request.uri().path().to_owned();
+
+    let result = tokio::time::timeout(timeout, async {
+        let response = http.request(request).await?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        Ok::<_, hyper::Error>((status, body))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(response)) => Ok(response),
+        Ok(Err(e)) => fail!(AzureKvError, "request to {} failed: {}", uri, e),
+        Err(_) => fail!(
+            AzureKvError,
+            "request to {} timed out after {:?}",
+            uri,
+            timeout
+        ),
+    }
+}
+
+/// Resource to request access tokens for, derived from the vault URL by
+/// dropping the vault name, e.g. `https://vault.azure.net` for
+/// `https://example.vault.azure.net` (which also covers Managed HSM and the
+/// national clouds)
+fn token_resource(vault_url: &str) -> Result<String, Error> {
+    let url = Url::parse(vault_url)
+        .map_err(|e| format_err!(ConfigError, "invalid Azure Key Vault `vault_url`: {}", e))?;
+
+    match url.host_str().and_then(|host| host.split_once('.')) {
+        Some((_, domain)) if !domain.is_empty() => Ok(format!("https://{}", domain)),
+        _ => fail!(
+            ConfigError,
+            "Azure Key Vault `vault_url` must include the vault name, e.g. \
+             https://example.vault.azure.net: {}",
+            vault_url
+        ),
+    }
+}
+
+/// Extract the version from a key ID (`<vault>/keys/<name>/<version>`)
+fn key_version_from_kid(kid: &str) -> Option<&str> {
+    let (rest, version) = kid.rsplit_once('/')?;
+    let (rest, _name) = rest.rsplit_once('/')?;
+
+    if rest.ends_with("/keys") && !version.is_empty() {
+        Some(version)
+    } else {
+        None
+    }
+}
+
+/// Delay before the given retry, using exponential backoff with jitter
+fn retry_delay(retry: u32) -> Duration {
+    let exponent = retry.saturating_sub(1).min(16);
+    let delay_ms = (RETRY_BASE_DELAY_MS << exponent).min(RETRY_MAX_DELAY_MS);
+
+    // "Equal jitter", as used for validator reconnects
+    let half_ms = delay_ms / 2;
+    Duration::from_millis(half_ms + OsRng.next_u64() % (half_ms + 1))
+}
+
+/// Encode data as unpadded Base64url, as used throughout the Key Vault API
+fn base64url_encode(data: &[u8]) -> String {
+    String::from_utf8(base64::encode(data))
+        .unwrap()
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_")
+}
+
+/// Decode a Base64url-encoded field of a Key Vault response
+fn decode(field: &str, data: &str) -> Result<Vec<u8>, Error> {
+    let mut standard = data.replace('-', "+").replace('_', "/");
+
+    while standard.len() % 4 != 0 {
+        standard.push('=');
+    }
+
+    base64::decode(standard).map_err(|e| {
+        format_err!(
+            AzureKvError,
+            "malformed {} in Key Vault response: {}",
+            field,
+            e
+        )
+        .into()
+    })
+}
+
+/// Key bundle returned by `GET keys/<name>[/<version>]`
+#[derive(Deserialize)]
+struct KeyBundle {
+    key: JsonWebKey,
+    attributes: KeyAttributes,
+}
+
+/// JSON Web Key
+#[derive(Deserialize)]
+struct JsonWebKey {
+    kid: String,
+    crv: Option<String>,
+    #[serde(default)]
+    key_ops: Vec<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+/// Key attributes
+#[derive(Deserialize)]
+struct KeyAttributes {
+    enabled: bool,
+}
+
+/// `sign` response
+#[derive(Deserialize)]
+struct KeyOperationResult {
+    value: String,
+}
+
+/// Error response
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+/// Error details
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ErrorDetail {
+    code: String,
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_backs_off() {
+        for (retry, max_ms) in &[(1, 50), (2, 100), (3, 200), (5, 800), (6, 1000), (40, 1000)] {
+            let delay = retry_delay(*retry);
+            let max = Duration::from_millis(*max_ms);
+            assert!(delay >= max / 2 && delay <= max, "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn token_resource_from_vault_url() {
+        for (vault_url, resource) in &[
+            ("https://example.vault.azure.net", "https://vault.azure.net"),
+            (
+                "https://example.managedhsm.azure.net/",
+                "https://managedhsm.azure.net",
+            ),
+            ("https://example.vault.azure.cn", "https://vault.azure.cn"),
+        ] {
+            assert_eq!(token_resource(vault_url).unwrap(), *resource);
+        }
+
+        assert!(token_resource("https://localhost").is_err());
+        assert!(token_resource("not a URL").is_err());
+    }
+
+    #[test]
+    fn parse_key_version() {
+        assert_eq!(
+            key_version_from_kid(
+                "https://example.vault.azure.net/keys/validator/78deebed173b48e48f55abf87ed4cf71"
+            ),
+            Some("78deebed173b48e48f55abf87ed4cf71")
+        );
+        assert_eq!(
+            key_version_from_kid("https://example.vault.azure.net/keys/validator"),
+            None
+        );
+    }
+
+    #[test]
+    fn base64url_round_trip() {
+        let data = [0xfb, 0xff, 0xbf, 0x01];
+        let encoded = base64url_encode(&data);
+        assert_eq!(encoded, "-_-_AQ");
+        assert_eq!(decode("value", &encoded).unwrap(), data);
+        assert!(decode("value", "!!!").is_err());
+    }
+
+    #[test]
+    fn parse_error_response() {
+        let error = serde_json::from_slice::<ErrorResponse>(
+            br#"{"error":{"code":"Throttled","message":"Request was not processed because too many requests were received."}}"#,
+        )
+        .unwrap()
+        .error;
+
+        assert_eq!(error.code, "Throttled");
+        assert!(error.message.starts_with("Request was not processed"));
+    }
+}
@@ -0,0 +1,243 @@
+//! Azure Active Directory access tokens, obtained either with a service
+//! principal's client secret (OAuth 2.0 client credentials grant) or from the
+//! managed identity endpoint of the Azure instance metadata service
+
+use super::client::{send, HttpClient};
+use crate::{
+    config::provider::azurekv::AzureKvAuth,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use chrono::{DateTime, Duration, Utc};
+use hyper::{header, Body, Request};
+use serde::{Deserialize, Deserializer};
+use std::{sync::Mutex, time::Duration as StdDuration};
+use url::form_urlencoded;
+use zeroize::Zeroizing;
+
+/// Default Azure AD authority host
+pub const DEFAULT_AUTHORITY_HOST: &str = "https://login.microsoftonline.com";
+
+/// Managed identity endpoint of the Azure instance metadata service
+const IMDS_TOKEN_URI: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// API version of the managed identity endpoint
+const IMDS_API_VERSION: &str = "2018-02-01";
+
+/// Timeout for requests to Azure AD and the metadata service
+const TOKEN_TIMEOUT: StdDuration = StdDuration::from_secs(5);
+
+/// Refresh tokens this long before they expire
+const EXPIRY_MARGIN_SECS: i64 = 300;
+
+/// Azure AD access token
+#[derive(Clone)]
+pub struct AccessToken {
+    /// Bearer token
+    pub token: Zeroizing<String>,
+
+    /// Expiration time
+    pub expires_at: DateTime<Utc>,
+}
+
+impl AccessToken {
+    /// Does this token need to be refreshed?
+    fn needs_refresh(&self) -> bool {
+        self.expires_at - Duration::seconds(EXPIRY_MARGIN_SECS) <= Utc::now()
+    }
+}
+
+/// Token provider which caches the access token until it's about to expire
+pub struct TokenProvider {
+    /// How to authenticate
+    auth: AzureKvAuth,
+
+    /// Azure AD authority host (without a trailing slash)
+    authority_host: String,
+
+    /// Resource the token is for, e.g. `https://vault.azure.net`
+    resource: String,
+
+    /// Most recently obtained token
+    cached: Mutex<Option<AccessToken>>,
+}
+
+impl TokenProvider {
+    /// Create a new token provider for the given resource
+    pub fn new(auth: AzureKvAuth, authority_host: &str, resource: String) -> Self {
+        Self {
+            auth,
+            authority_host: authority_host.trim_end_matches('/').to_owned(),
+            resource,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Get an access token, obtaining a new one if we don't have one (or it's
+    /// about to expire)
+    pub async fn token(&self, http: &HttpClient) -> Result<AccessToken, Error> {
+        if let Some(token) = self.cached.lock().unwrap().as_ref() {
+            if !token.needs_refresh() {
+                return Ok(token.clone());
+            }
+        }
+
+        let token = self.request_token(http).await?;
+
+        debug!(
+            "[keyring:azurekv] obtained access token for {} (expires {})",
+            self.resource, token.expires_at
+        );
+
+        *self.cached.lock().unwrap() = Some(token.clone());
+        Ok(token)
+    }
+
+    /// Discard the cached token (e.g. after it was rejected)
+    pub fn invalidate(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+
+    /// Request a new access token from Azure AD
+    async fn request_token(&self, http: &HttpClient) -> Result<AccessToken, Error> {
+        let request = match &self.auth {
+            AzureKvAuth::ClientSecret {
+                tenant_id,
+                client_id,
+                client_secret,
+            } => {
+                let body = Zeroizing::new(
+                    form_urlencoded::Serializer::new(String::new())
+                        .append_pair("grant_type", "client_credentials")
+                        .append_pair("client_id", client_id)
+                        .append_pair("client_secret", client_secret)
+                        .append_pair("scope", &format!("{}/.default", self.resource))
+                        .finish(),
+                );
+
+                Request::post(format!(
+                    "{}/{}/oauth2/v2.0/token",
+                    self.authority_host, tenant_id
+                ))
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(body.as_bytes().to_vec()))
+                .unwrap()
+            }
+            AzureKvAuth::ManagedIdentity { client_id } => {
+                let mut query = form_urlencoded::Serializer::new(String::new());
+                query
+                    .append_pair("api-version", IMDS_API_VERSION)
+                    .append_pair("resource", &self.resource);
+
+                if let Some(client_id) = client_id {
+                    query.append_pair("client_id", client_id);
+                }
+
+                Request::get(format!("{}?{}", IMDS_TOKEN_URI, query.finish()))
+                    .header("Metadata", "true")
+                    .body(Body::empty())
+                    .unwrap()
+            }
+        };
+
+        let (status, body) = send(http, request, TOKEN_TIMEOUT).await?;
+
+        if !status.is_success() {
+            let error = serde_json::from_slice::<TokenErrorResponse>(&body).unwrap_or_default();
+
+            fail!(
+                AzureKvError,
+                "couldn't obtain Azure AD access token (HTTP {}): {} {}",
+                status,
+                error.error,
+                error.error_description
+            );
+        }
+
+        Ok(serde_json::from_slice::<TokenResponse>(&body)?.into())
+    }
+}
+
+/// Token endpoint response
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+
+    /// Lifetime of the token in seconds (a number from Azure AD, but a string
+    /// from the metadata service)
+    #[serde(deserialize_with = "deserialize_seconds")]
+    expires_in: i64,
+}
+
+impl From<TokenResponse> for AccessToken {
+    fn from(response: TokenResponse) -> AccessToken {
+        AccessToken {
+            token: Zeroizing::new(response.access_token),
+            expires_at: Utc::now() + Duration::seconds(response.expires_in),
+        }
+    }
+}
+
+/// Token endpoint error response
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct TokenErrorResponse {
+    error: String,
+    error_description: String,
+}
+
+/// Deserialize a number of seconds given as either a number or a string
+fn deserialize_seconds<'de, D>(deserializer: D) -> Result<i64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Seconds {
+        Number(i64),
+        String(String),
+    }
+
+    match Seconds::deserialize(deserializer)? {
+        Seconds::Number(secs) => Ok(secs),
+        Seconds::String(secs) => secs.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_aad_token_response() {
+        let response: TokenResponse = serde_json::from_str(
+            r#"{"token_type":"Bearer","expires_in":3599,"ext_expires_in":3599,"access_token":"eyJ0eXAi"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(response.access_token, "eyJ0eXAi");
+        assert_eq!(response.expires_in, 3599);
+    }
+
+    #[test]
+    fn parse_imds_token_response() {
+        let response: TokenResponse = serde_json::from_str(
+            r#"{"access_token":"eyJ0eXAi","expires_in":"86399","expires_on":"1700000000",
+                "resource":"https://vault.azure.net","token_type":"Bearer"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(response.expires_in, 86399);
+    }
+
+    #[test]
+    fn refresh_before_expiration() {
+        let token = |expires_in| AccessToken {
+            token: Zeroizing::new("token".to_owned()),
+            expires_at: Utc::now() + expires_in,
+        };
+
+        assert!(token(Duration::seconds(60)).needs_refresh());
+        assert!(!token(Duration::hours(1)).needs_refresh());
+    }
+}
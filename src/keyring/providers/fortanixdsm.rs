@@ -179,6 +179,14 @@ fn add_key(
     config: &SigningKeyConfig,
     client: Arc<SdkmsClient>,
 ) -> Result<(), Error> {
+    if !config.active && matches!(config.key_type, KeyType::Account) {
+        fail!(
+            ConfigError,
+            "Fortanix DSM account key {} can't be inactive (only consensus keys can be rotated)",
+            config.key
+        );
+    }
+
     let (signing_key, public_key) =
         SigningKey::new(client, config.key.clone(), config.key_type.clone())?;
 
@@ -199,8 +207,16 @@ fn add_key(
                 public_key,
                 Box::new(signing_key),
             );
+            // Keys are identified by their DSM key ID or name for rotation
+            let key_id = config.key.to_string();
+
             for chain_id in &config.chain_ids {
-                registry.add_consensus_key(chain_id, signer.clone())?;
+                registry.add_rotatable_consensus_key(
+                    chain_id,
+                    &key_id,
+                    signer.clone(),
+                    config.active,
+                )?;
             }
         }
     }
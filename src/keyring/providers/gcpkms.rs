@@ -0,0 +1,202 @@
+//! Google Cloud KMS signing provider
+//!
+//! Signs using secp256k1 (`EC_SIGN_SECP256K1_SHA256`, HSM protection level)
+//! keys which never leave Cloud KMS, for either account or consensus keys.
+//!
+//! Public keys are fetched once at startup and kept in the keyring, so only
+//! signing requests go to Cloud KMS.
+
+mod client;
+mod credentials;
+
+use self::client::KmsClient;
+use crate::{
+    chain,
+    config::provider::{
+        gcpkms::{GcpKmsConfig, SigningKeyConfig},
+        KeyType,
+    },
+    error::{Error, ErrorKind::*},
+    keyring::{self, SigningProvider},
+    prelude::*,
+};
+use k256::{
+    ecdsa::{Error as SignError, Signature as EcdsaSignature, VerifyingKey},
+    pkcs8::DecodePublicKey,
+};
+use sha2::{Digest, Sha256};
+use signature::Signer;
+use std::{sync::Arc, time::Instant};
+use subtle_encoding::base64;
+use tendermint::{PublicKey, TendermintKey};
+
+/// Create Cloud KMS backed signer objects from the given configuration
+pub fn init(registry: &mut chain::Registry, configs: &[GcpKmsConfig]) -> Result<(), Error> {
+    if configs.is_empty() {
+        return Ok(());
+    }
+
+    for config in configs {
+        let client = Arc::new(KmsClient::new(config)?);
+
+        for key in &config.signing_keys {
+            add_key(registry, key, client.clone())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Add a Cloud KMS signing key to the keyrings of the chains it's configured
+/// for
+fn add_key(
+    registry: &mut chain::Registry,
+    config: &SigningKeyConfig,
+    client: Arc<KmsClient>,
+) -> Result<(), Error> {
+    let (signing_key, verifying_key) = SigningKey::new(client, &config.key_name)?;
+
+    let public_key = match config.key_type {
+        KeyType::Account => TendermintKey::AccountKey(PublicKey::from(verifying_key)),
+        KeyType::Consensus => TendermintKey::ConsensusKey(PublicKey::from(verifying_key)),
+    };
+
+    let signer =
+        keyring::ecdsa::Signer::new(SigningProvider::GcpKms, public_key, Box::new(signing_key));
+
+    for chain_id in &config.chain_ids {
+        match config.key_type {
+            KeyType::Account => registry.add_account_key(chain_id, signer.clone())?,
+            KeyType::Consensus => registry.add_ecdsa_consensus_key(chain_id, signer.clone())?,
+        }
+    }
+
+    Ok(())
+}
+
+/// secp256k1 signing key stored in Cloud KMS
+struct SigningKey {
+    /// Cloud KMS client
+    client: Arc<KmsClient>,
+
+    /// Resource name of the key version
+    key_name: String,
+}
+
+impl SigningKey {
+    /// Look up the given key version, returning a signer for it along with
+    /// its public key
+    fn new(client: Arc<KmsClient>, key_name: &str) -> Result<(Self, VerifyingKey), Error> {
+        let public_key = parse_public_key(&client.get_public_key(key_name)?).map_err(|e| {
+            format_err!(
+                GcpKmsError,
+                "failed to parse secp256k1 public key for {}: {}",
+                key_name,
+                e
+            )
+        })?;
+
+        let signing_key = SigningKey {
+            client,
+            key_name: key_name.to_owned(),
+        };
+
+        Ok((signing_key, public_key))
+    }
+}
+
+impl Signer<EcdsaSignature> for SigningKey {
+    fn try_sign(&self, msg: &[u8]) -> Result<EcdsaSignature, SignError> {
+        let started_at = Instant::now();
+
+        let der_signature = self
+            .client
+            .sign_digest(&self.key_name, &Sha256::digest(msg))
+            .map_err(SignError::from_source)?;
+
+        info!(
+            "[keyring:gcpkms] signed with {} in {} ms",
+            self.key_name,
+            started_at.elapsed().as_millis()
+        );
+
+        parse_signature(&der_signature)
+    }
+}
+
+/// Parse a PEM-encoded SubjectPublicKeyInfo as returned by Cloud KMS
+fn parse_public_key(pem: &str) -> Result<VerifyingKey, String> {
+    let base64_der = pem
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+        .collect::<String>();
+
+    let der = base64::decode(base64_der).map_err(|e| e.to_string())?;
+
+    k256::PublicKey::from_public_key_der(&der)
+        .map(Into::into)
+        .map_err(|e| e.to_string())
+}
+
+/// Parse an ASN.1 DER signature returned by Cloud KMS into a fixed-width
+/// signature, normalizing `s` into the lower half of the curve order as
+/// Tendermint and Cosmos require
+fn parse_signature(der_signature: &[u8]) -> Result<EcdsaSignature, SignError> {
+    let signature = EcdsaSignature::from_der(der_signature)?;
+    Ok(signature.normalize_s().unwrap_or(signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::{ecdsa::SigningKey as SoftSigningKey, elliptic_curve::sec1::ToEncodedPoint};
+    use rand_core::OsRng;
+    use signature::Verifier;
+    use subtle_encoding::hex;
+
+    #[test]
+    fn parse_pem_public_key() {
+        let verifying_key = SoftSigningKey::random(&mut OsRng).verifying_key();
+        // SubjectPublicKeyInfo header for an uncompressed secp256k1 point
+        let mut der = hex::decode("3056301006072a8648ce3d020106052b8104000a034200").unwrap();
+        der.extend_from_slice(
+            k256::PublicKey::from(&verifying_key)
+                .to_encoded_point(false)
+                .as_bytes(),
+        );
+
+        let base64_der = String::from_utf8(base64::encode(&der)).unwrap();
+        let pem = format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n{}\n-----END PUBLIC KEY-----\n",
+            &base64_der[..64],
+            &base64_der[64..]
+        );
+
+        assert_eq!(parse_public_key(&pem).unwrap(), verifying_key);
+        assert!(parse_public_key("-----BEGIN PUBLIC KEY-----\nAQID\n").is_err());
+    }
+
+    #[test]
+    fn parse_high_s_signature() {
+        let signing_key = SoftSigningKey::random(&mut OsRng);
+        let msg = b"tmkms gcpkms test";
+
+        // Software signatures are already low-s normalized
+        let low_s: EcdsaSignature = signing_key.sign(msg);
+        let high_s =
+            EcdsaSignature::from_scalars(low_s.r().to_bytes(), (-*low_s.s()).to_bytes()).unwrap();
+        assert!(high_s.normalize_s().is_some());
+
+        let parsed = parse_signature(high_s.to_der().as_bytes()).unwrap();
+        assert_eq!(parsed, low_s);
+        assert_eq!(parsed.as_ref().len(), 64);
+
+        signing_key.verifying_key().verify(msg, &parsed).unwrap();
+    }
+
+    #[test]
+    fn reject_malformed_signature() {
+        assert!(parse_signature(b"not a DER signature").is_err());
+    }
+}
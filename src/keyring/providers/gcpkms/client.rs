@@ -0,0 +1,379 @@
+//! Minimal Google Cloud KMS client for the Cloud KMS REST API
+
+use super::credentials::TokenProvider;
+use crate::{
+    config::provider::gcpkms::GcpKmsConfig,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use hyper::{
+    body::Bytes,
+    client::HttpConnector,
+    header,
+    http::{Method, StatusCode, Uri},
+    Body, Request,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use rand_core::{OsRng, RngCore};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use std::{
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+use subtle_encoding::base64;
+use tokio::runtime::{self, Runtime};
+
+/// HTTP client used for requests to Cloud KMS and the token endpoints
+pub type HttpClient = hyper::Client<HttpsConnector<HttpConnector>>;
+
+/// Default Cloud KMS endpoint
+pub const DEFAULT_ENDPOINT: &str = "https://cloudkms.googleapis.com";
+
+/// Default number of times to retry a throttled or failed request
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Cloud KMS algorithm of secp256k1 signing keys
+pub const SECP256K1_ALGORITHM: &str = "EC_SIGN_SECP256K1_SHA256";
+
+/// Timeout for a single request to Cloud KMS
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay before the first retry (doubled on each subsequent retry)
+const RETRY_BASE_DELAY_MS: u64 = 50;
+
+/// Maximum delay between retries
+const RETRY_MAX_DELAY_MS: u64 = 1000;
+
+/// Google Cloud KMS client.
+///
+/// Requests are performed on the client's own Tokio runtime, with callers
+/// blocking on a channel for the result.
+pub struct KmsClient {
+    /// Runtime which performs requests
+    runtime: Runtime,
+
+    /// State shared with in-flight requests
+    inner: Arc<Inner>,
+}
+
+impl KmsClient {
+    /// Create a new Cloud KMS client from the given configuration
+    pub fn new(config: &GcpKmsConfig) -> Result<Self, Error> {
+        let endpoint = config
+            .endpoint
+            .as_deref()
+            .unwrap_or(DEFAULT_ENDPOINT)
+            .trim_end_matches('/')
+            .to_owned();
+
+        endpoint
+            .parse::<Uri>()
+            .map_err(|e| format_err!(ConfigError, "invalid Cloud KMS endpoint: {}", e))?;
+
+        let runtime = runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("gcpkms")
+            .enable_all()
+            .build()?;
+
+        let connector = HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        let inner = Inner {
+            http: hyper::Client::builder().build(connector),
+            endpoint,
+            tokens: TokenProvider::from_environment()?,
+            max_retries: config.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+        };
+
+        Ok(Self {
+            runtime,
+            inner: Arc::new(inner),
+        })
+    }
+
+    /// Get the PEM-encoded public key of the given key version, ensuring
+    /// it's a secp256k1 signing key
+    pub fn get_public_key(&self, key_name: &str) -> Result<String, Error> {
+        let response: PublicKeyResponse =
+            self.call(Method::GET, format!("{}/publicKey", key_name), None)?;
+
+        if response.algorithm != SECP256K1_ALGORITHM {
+            fail!(
+                GcpKmsError,
+                "Cloud KMS key {} has algorithm {:?} (expected {})",
+                key_name,
+                response.algorithm,
+                SECP256K1_ALGORITHM
+            );
+        }
+
+        Ok(response.pem)
+    }
+
+    /// Sign the given SHA-256 digest with the given key version, returning an
+    /// ASN.1 DER-encoded ECDSA signature
+    pub fn sign_digest(&self, key_name: &str, digest: &[u8]) -> Result<Vec<u8>, Error> {
+        let body = json!({
+            "digest": { "sha256": String::from_utf8(base64::encode(digest)).unwrap() },
+        });
+
+        let response: AsymmetricSignResponse = self.call(
+            Method::POST,
+            format!("{}:asymmetricSign", key_name),
+            Some(body),
+        )?;
+
+        base64::decode(&response.signature).map_err(|e| {
+            format_err!(
+                GcpKmsError,
+                "malformed signature in Cloud KMS response: {}",
+                e
+            )
+            .into()
+        })
+    }
+
+    /// Call the given Cloud KMS API method
+    fn call<T>(
+        &self,
+        method: Method,
+        path: String,
+        body: Option<serde_json::Value>,
+    ) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let inner = Arc::clone(&self.inner);
+        let (tx, rx) = mpsc::channel();
+
+        self.runtime.spawn(async move {
+            let _ = tx.send(inner.call(method, &path, body).await);
+        });
+
+        let response = rx
+            .recv()
+            .map_err(|_| format_err!(GcpKmsError, "Cloud KMS request was aborted"))??;
+
+        Ok(serde_json::from_slice(&response)?)
+    }
+}
+
+/// Client state shared with in-flight requests
+struct Inner {
+    /// HTTP client
+    http: HttpClient,
+
+    /// Cloud KMS endpoint (without a trailing slash)
+    endpoint: String,
+
+    /// Source of access tokens
+    tokens: TokenProvider,
+
+    /// Maximum number of times to retry a throttled or failed request
+    max_retries: u32,
+}
+
+impl Inner {
+    /// Call the given Cloud KMS API method, retrying with backoff if
+    /// throttled or on transient errors.
+    ///
+    /// Retries happen within a single signing request (and sign the same
+    /// digest), so they're invisible to double signing protection.
+    async fn call(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<Bytes, Error> {
+        let mut retries = 0;
+
+        loop {
+            match self.request(method.clone(), path, body.as_ref()).await {
+                Ok(response) => return Ok(response),
+                Err(Failure::Retryable(e)) if retries < self.max_retries => {
+                    retries += 1;
+                    let delay = retry_delay(retries);
+
+                    warn!(
+                        "[keyring:gcpkms] {}; retry {}/{} in {} ms",
+                        e,
+                        retries,
+                        self.max_retries,
+                        delay.as_millis()
+                    );
+
+                    tokio::time::sleep(delay).await;
+                }
+                Err(Failure::Retryable(e)) | Err(Failure::Fatal(e)) => return Err(e),
+            }
+        }
+    }
+
+    /// Make a single request to the Cloud KMS API
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<Bytes, Failure> {
+        let token = self
+            .tokens
+            .token(&self.http)
+            .await
+            .map_err(Failure::Fatal)?;
+
+        let body = match body {
+            Some(body) => Body::from(body.to_string()),
+            None => Body::empty(),
+        };
+
+        let request = Request::builder()
+            .method(method)
+            .uri(format!("{}/v1/{}", self.endpoint, path))
+            .header(
+                header::AUTHORIZATION,
+                format!("Bearer {}", token.token.as_str()),
+            )
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .unwrap();
+
+        let (status, response) = send(&self.http, request, REQUEST_TIMEOUT)
+            .await
+            .map_err(Failure::Retryable)?;
+
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let error = serde_json::from_slice::<ErrorResponse>(&response)
+            .unwrap_or_default()
+            .error;
+
+        let e = format_err!(
+            GcpKmsError,
+            "Cloud KMS {} failed with HTTP {}: {} {}",
+            path,
+            status,
+            error.status,
+            error.message
+        )
+        .into();
+
+        if status == StatusCode::UNAUTHORIZED {
+            // Token was revoked or expired early: get a new one and try again
+            self.tokens.invalidate();
+            Err(Failure::Retryable(e))
+        } else if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            Err(Failure::Retryable(e))
+        } else {
+            Err(Failure::Fatal(e))
+        }
+    }
+}
+
+/// Failed Cloud KMS request
+enum Failure {
+    /// Throttling or transient error which is worth retrying
+    Retryable(Error),
+
+    /// Any other error
+    Fatal(Error),
+}
+
+/// Send an HTTP request, returning the response status and body
+pub async fn send(
+    http: &HttpClient,
+    request: Request<Body>,
+    timeout: Duration,
+) -> Result<(StatusCode, Bytes), Error> {
+    let uri = request.uri().clone();
+
+    let result = tokio::time::timeout(timeout, async {
+        let response = http.request(request).await?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        Ok::<_, hyper::Error>((status, body))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(response)) => Ok(response),
+        Ok(Err(e)) => fail!(GcpKmsError, "request to {} failed: {}", uri, e),
+        Err(_) => fail!(
+            GcpKmsError,
+            "request to {} timed out after {:?}",
+            uri,
+            timeout
+        ),
+    }
+}
+
+/// Delay before the given retry, using exponential backoff with jitter
+fn retry_delay(retry: u32) -> Duration {
+    let exponent = retry.saturating_sub(1).min(16);
+    let delay_ms = (RETRY_BASE_DELAY_MS << exponent).min(RETRY_MAX_DELAY_MS);
+
+    // "Equal jitter", as used for validator reconnects
+    let half_ms = delay_ms / 2;
+    Duration::from_millis(half_ms + OsRng.next_u64() % (half_ms + 1))
+}
+
+/// `publicKey` response
+#[derive(Deserialize)]
+struct PublicKeyResponse {
+    pem: String,
+    algorithm: String,
+}
+
+/// `asymmetricSign` response
+#[derive(Deserialize)]
+struct AsymmetricSignResponse {
+    signature: String,
+}
+
+/// Error response
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+/// Error details
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ErrorDetail {
+    message: String,
+    status: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_backs_off() {
+        for (retry, max_ms) in &[(1, 50), (2, 100), (3, 200), (5, 800), (6, 1000), (40, 1000)] {
+            let delay = retry_delay(*retry);
+            let max = Duration::from_millis(*max_ms);
+            assert!(delay >= max / 2 && delay <= max, "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn parse_error_response() {
+        let error = serde_json::from_slice::<ErrorResponse>(
+            br#"{"error": {"code": 429, "message": "Quota exceeded.", "status": "RESOURCE_EXHAUSTED"}}"#,
+        )
+        .unwrap()
+        .error;
+
+        assert_eq!(error.status, "RESOURCE_EXHAUSTED");
+        assert_eq!(error.message, "Quota exceeded.");
+    }
+}
@@ -3,11 +3,15 @@
 mod client;
 mod error;
 mod signer;
+mod speculos;
 
-use self::signer::Ed25519LedgerTmAppSigner;
+use self::{
+    client::{Endpoint, Version},
+    signer::Ed25519LedgerTmAppSigner,
+};
 use crate::{
     chain,
-    config::provider::ledgertm::LedgerTendermintConfig,
+    config::provider::ledgertm::{LedgerTendermintConfig, LedgerTransport},
     error::{Error, ErrorKind::*},
     keyring::{
         ed25519::{self, Signer},
@@ -15,8 +19,19 @@ use crate::{
     },
     prelude::*,
 };
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
 use tendermint::{PublicKey, TendermintKey};
 
+/// Host of the Speculos APDU server if none is configured
+const DEFAULT_SPECULOS_HOST: &str = "127.0.0.1";
+
+/// Port of the Speculos APDU server if none is configured
+const DEFAULT_SPECULOS_PORT: u16 = 9999;
+
+/// Signer created by [`init`] (kept for health checks)
+static SIGNER: Lazy<Mutex<Option<Ed25519LedgerTmAppSigner>>> = Lazy::new(Default::default);
+
 /// Create Ledger Tendermint signer object from the given configuration
 pub fn init(
     chain_registry: &mut chain::Registry,
@@ -34,20 +49,76 @@ pub fn init(
         );
     }
 
-    let provider = Ed25519LedgerTmAppSigner::connect().map_err(|_| Error::from(SigningError))?;
+    let config = &ledgertm_configs[0];
+
+    let min_version = match &config.min_app_version {
+        Some(version) => version.parse::<Version>().map_err(|_| {
+            format_err!(
+                ConfigError,
+                "invalid [providers.ledgertm] min_app_version: {:?} (expected e.g. \"0.4.0\")",
+                version
+            )
+        })?,
+        None => Version::default(),
+    };
+
+    let endpoint = endpoint(config)?;
+
+    // Check the right app is open before any validator connection is made
+    let provider = Ed25519LedgerTmAppSigner::connect(endpoint.clone(), min_version)
+        .map_err(|e| format_err!(SigningError, "Ledger ({}): {}", endpoint, e))?;
 
     let public_key = PublicKey::from_raw_ed25519(ed25519::PublicKey::from(&provider).as_bytes())
         .expect("invalid Ed25519 public key");
 
+    *SIGNER.lock().unwrap() = Some(provider.clone());
+
     let signer = Signer::new(
         SigningProvider::LedgerTm,
         TendermintKey::ConsensusKey(public_key),
         Box::new(provider),
     );
 
-    for chain_id in &ledgertm_configs[0].chain_ids {
+    for chain_id in &config.chain_ids {
         chain_registry.add_consensus_key(chain_id, signer.clone())?;
     }
 
     Ok(())
 }
+
+/// Get where the Ledger is reached from the given configuration
+fn endpoint(config: &LedgerTendermintConfig) -> Result<Endpoint, Error> {
+    match config.transport {
+        LedgerTransport::Usb => {
+            if config.host.is_some() || config.port.is_some() {
+                fail!(
+                    ConfigError,
+                    "[providers.ledgertm] host and port require transport = \"tcp\""
+                );
+            }
+
+            Ok(Endpoint::Usb)
+        }
+        LedgerTransport::Tcp => {
+            let host = config.host.as_deref().unwrap_or(DEFAULT_SPECULOS_HOST);
+            let port = config.port.unwrap_or(DEFAULT_SPECULOS_PORT);
+
+            // Bracket IPv6 addresses
+            Ok(Endpoint::Tcp(if host.contains(':') {
+                format!("[{}]:{}", host, port)
+            } else {
+                format!("{}:{}", host, port)
+            }))
+        }
+    }
+}
+
+/// Check the Tendermint Validator app is still open and responding
+pub fn healthcheck() -> Result<(), Error> {
+    match SIGNER.lock().unwrap().as_ref() {
+        Some(signer) => signer
+            .check()
+            .map_err(|e| format_err!(SigningError, "Ledger: {}", e).into()),
+        None => fail!(SigningError, "Ledger: not connected"),
+    }
+}
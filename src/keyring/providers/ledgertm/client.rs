@@ -16,27 +16,85 @@
 *  limitations under the License.
 ********************************************************************************/
 
-use super::error::Error;
+use super::{error::Error, speculos::Speculos};
 use ledger::{ApduAnswer, ApduCommand};
+use std::{fmt, str::FromStr};
 
 const CLA: u8 = 0x56;
 const INS_PUBLIC_KEY_ED25519: u8 = 0x01;
 const INS_SIGN_ED25519: u8 = 0x02;
+const INS_GET_VERSION: u8 = 0x00;
+
+/// Class and instruction of the command answered by the device (whichever app
+/// is open) with the name and version of the open app
+const CLA_APP_INFO: u8 = 0xB0;
+const INS_APP_INFO: u8 = 0x01;
+
+/// Name of the Tendermint Validator app, as reported by the device
+pub const APP_NAME: &str = "Tendermint";
 
 const USER_MESSAGE_CHUNK_SIZE: usize = 250;
 
-#[allow(dead_code)]
-const INS_GET_VERSION: u8 = 0x00;
+/// Transport APDUs are exchanged with the Ledger over
+pub(super) trait Transport {
+    /// Send a command and wait for its answer
+    fn exchange(&self, command: ApduCommand) -> Result<ApduAnswer, Error>;
+}
+
+impl Transport for ledger::LedgerApp {
+    fn exchange(&self, command: ApduCommand) -> Result<ApduAnswer, Error> {
+        Ok(ledger::LedgerApp::exchange(self, command)?)
+    }
+}
+
+/// Where the Ledger is reached
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Endpoint {
+    /// Ledger plugged in over USB
+    Usb,
+
+    /// Speculos emulator's APDU server at the given `host:port`
+    Tcp(String),
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Usb => f.write_str("USB"),
+            Endpoint::Tcp(addr) => write!(f, "Speculos at {}", addr),
+        }
+    }
+}
+
+/// Connection to a Ledger over one of the supported transports
+pub(super) enum Device {
+    /// Ledger plugged in over USB
+    Usb(ledger::LedgerApp),
 
-pub(super) struct TendermintValidatorApp {
-    app: ledger::LedgerApp,
+    /// Speculos emulator
+    Tcp(Speculos),
+}
+
+impl Transport for Device {
+    fn exchange(&self, command: ApduCommand) -> Result<ApduAnswer, Error> {
+        match self {
+            Device::Usb(app) => Transport::exchange(app, command),
+            Device::Tcp(speculos) => speculos.exchange(command),
+        }
+    }
+}
+
+pub(super) struct TendermintValidatorApp<T: Transport = Device> {
+    app: T,
 }
 
 // TODO(tarcieri): check this is actually sound?!
 #[allow(unsafe_code)]
 unsafe impl Send for TendermintValidatorApp {}
 
+/// Version of the Tendermint Validator app
 #[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
 pub struct Version {
     mode: u8,
     major: u8,
@@ -44,14 +102,117 @@ pub struct Version {
     patch: u8,
 }
 
+impl Version {
+    /// Create a version from its major, minor and patch numbers
+    pub fn new(major: u8, minor: u8, patch: u8) -> Self {
+        Version {
+            mode: 0,
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Is this version at least the given one? (the mode is ignored)
+    pub fn is_at_least(&self, other: &Version) -> bool {
+        (self.major, self.minor, self.patch) >= (other.major, other.minor, other.patch)
+    }
+}
+
+impl Default for Version {
+    /// Oldest version of the app with the signing protocol tmkms uses
+    fn default() -> Self {
+        Version::new(0, 4, 0)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let numbers = s
+            .trim_start_matches('v')
+            .split('.')
+            .map(|n| n.parse::<u8>().map_err(|_| Error::InvalidVersion))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match numbers.as_slice() {
+            [major, minor, patch] => Ok(Version::new(*major, *minor, *patch)),
+            _ => Err(Error::InvalidVersion),
+        }
+    }
+}
+
 impl TendermintValidatorApp {
-    pub fn connect() -> Result<Self, Error> {
-        let app = ledger::LedgerApp::new()?;
+    pub fn connect(endpoint: &Endpoint) -> Result<Self, Error> {
+        let app = match endpoint {
+            Endpoint::Usb => Device::Usb(ledger::LedgerApp::new()?),
+            Endpoint::Tcp(addr) => Device::Tcp(Speculos::connect(addr)?),
+        };
+
         Ok(TendermintValidatorApp { app })
     }
+}
+
+impl<T: Transport> TendermintValidatorApp<T> {
+    /// Check the Tendermint Validator app is open on the device, and is at
+    /// least the given version
+    pub fn check_app(&self, min_version: &Version) -> Result<(), Error> {
+        let name = self.app_name()?;
+
+        if name != APP_NAME {
+            return Err(Error::WrongApp {
+                required: *min_version,
+                found: name,
+            });
+        }
+
+        let version = self.version()?;
+
+        if !version.is_at_least(min_version) {
+            return Err(Error::OutdatedApp {
+                required: *min_version,
+                found: version,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Get the name of the app open on the device
+    pub fn app_name(&self) -> Result<String, Error> {
+        let command = ApduCommand {
+            cla: CLA_APP_INFO,
+            ins: INS_APP_INFO,
+            p1: 0x00,
+            p2: 0x00,
+            length: 0,
+            data: Vec::new(),
+        };
+
+        let response = self.app.exchange(command)?;
+
+        if response.retcode != 0x9000 {
+            return Err(Error::Ledger(ledger::map_apdu_error(response.retcode)));
+        }
+
+        // format (1 byte), then the length-prefixed name and version
+        let name = response
+            .data
+            .get(1)
+            .and_then(|&len| response.data.get(2..2 + len as usize))
+            .ok_or(Error::InvalidVersion)?;
+
+        Ok(String::from_utf8_lossy(name).into_owned())
+    }
 
     /// Get version
-    #[allow(dead_code)]
     pub fn version(&self) -> Result<Version, Error> {
         let command = ApduCommand {
             cla: CLA,
@@ -65,7 +226,7 @@ impl TendermintValidatorApp {
         let response = self.app.exchange(command)?;
 
         // TODO: this is just temporary, ledger errors should check for 0x9000
-        if response.retcode != 0x9000 {
+        if response.retcode != 0x9000 || response.data.len() < 4 {
             return Err(Error::InvalidVersion);
         }
 
@@ -90,25 +251,19 @@ impl TendermintValidatorApp {
             data: Vec::new(),
         };
 
-        match self.app.exchange(command) {
-            Ok(response) => {
-                if response.retcode != 0x9000 {
-                    println!("WARNING: retcode={:X?}", response.retcode);
-                }
+        let response = self.app.exchange(command)?;
 
-                if response.data.len() != 32 {
-                    return Err(Error::InvalidPk);
-                }
+        if response.retcode != 0x9000 {
+            println!("WARNING: retcode={:X?}", response.retcode);
+        }
 
-                let mut array = [0u8; 32];
-                array.copy_from_slice(&response.data[..32]);
-                Ok(array)
-            }
-            Err(err) => {
-                // TODO: Friendly error
-                Err(Error::Ledger(err))
-            }
+        if response.data.len() != 32 {
+            return Err(Error::InvalidPk);
         }
+
+        let mut array = [0u8; 32];
+        array.copy_from_slice(&response.data[..32]);
+        Ok(array)
     }
 
     /// Sign message
@@ -165,10 +320,11 @@ mod tests {
     use std::sync::Mutex;
     use std::time::Instant;
 
-    use super::{Error, TendermintValidatorApp};
+    use super::{Endpoint, Error, TendermintValidatorApp, Transport, Version, APP_NAME, CLA};
+    use ledger::{ApduAnswer, ApduCommand};
 
     static APP: Lazy<Mutex<TendermintValidatorApp>> =
-        Lazy::new(|| Mutex::new(TendermintValidatorApp::connect().unwrap()));
+        Lazy::new(|| Mutex::new(TendermintValidatorApp::connect(&Endpoint::Usb).unwrap()));
 
     fn get_fake_proposal(index: u64, round: i64) -> Vec<u8> {
         use byteorder::{LittleEndian, WriteBytesExt};
@@ -197,6 +353,88 @@ mod tests {
         message
     }
 
+    /// Transport to a mock device with the given app open
+    struct MockTransport {
+        /// Name of the open app
+        name: &'static str,
+
+        /// Version of the open app
+        version: [u8; 3],
+    }
+
+    impl Transport for MockTransport {
+        fn exchange(&self, command: ApduCommand) -> Result<ApduAnswer, Error> {
+            let mut data = vec![];
+
+            let retcode = match (command.cla, command.ins) {
+                (0xB0, 0x01) => {
+                    let version = format!(
+                        "{}.{}.{}",
+                        self.version[0], self.version[1], self.version[2]
+                    );
+
+                    data.push(1);
+                    data.push(self.name.len() as u8);
+                    data.extend_from_slice(self.name.as_bytes());
+                    data.push(version.len() as u8);
+                    data.extend_from_slice(version.as_bytes());
+                    0x9000
+                }
+                (CLA, 0x00) if self.name == APP_NAME => {
+                    data.push(0xFF);
+                    data.extend_from_slice(&self.version);
+                    0x9000
+                }
+                // other apps don't know our class
+                _ => 0x6E00,
+            };
+
+            Ok(ApduAnswer { data, retcode })
+        }
+    }
+
+    /// Check the app open on a mock device against the given minimum version
+    fn check_app(name: &'static str, version: [u8; 3], min_version: &str) -> Result<(), Error> {
+        let app = TendermintValidatorApp {
+            app: MockTransport { name, version },
+        };
+
+        app.check_app(&min_version.parse().unwrap())
+    }
+
+    #[test]
+    fn check_app_accepts_supported_version() {
+        assert!(check_app(APP_NAME, [0, 4, 0], "0.4.0").is_ok());
+        assert!(check_app(APP_NAME, [1, 0, 2], "0.9.1").is_ok());
+    }
+
+    #[test]
+    fn check_app_rejects_other_apps() {
+        let err = check_app("Cosmos", [2, 34, 0], "0.4.0").unwrap_err();
+        assert!(matches!(err, Error::WrongApp { .. }));
+        assert_eq!(
+            err.to_string(),
+            "Tendermint Validator app >= 0.4.0 required, found Cosmos app"
+        );
+    }
+
+    #[test]
+    fn check_app_rejects_outdated_versions() {
+        let err = check_app(APP_NAME, [0, 3, 9], "0.4.0").unwrap_err();
+        assert!(matches!(err, Error::OutdatedApp { .. }));
+        assert_eq!(
+            err.to_string(),
+            "Tendermint Validator app >= 0.4.0 required, found version 0.3.9"
+        );
+    }
+
+    #[test]
+    fn parse_version() {
+        assert_eq!("v0.10.2".parse::<Version>().unwrap().to_string(), "0.10.2");
+        assert!("0.4".parse::<Version>().is_err());
+        assert!("0.4.x".parse::<Version>().is_err());
+    }
+
     #[test]
     #[ignore]
     fn version() {
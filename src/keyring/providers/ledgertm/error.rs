@@ -1,5 +1,7 @@
 //! Ledger errors
 
+use super::client::Version;
+use std::io;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -7,6 +9,15 @@ pub enum Error {
     #[error("This version is not supported")]
     InvalidVersion,
 
+    #[error("Tendermint Validator app >= {required} required, found {found} app")]
+    WrongApp { required: Version, found: String },
+
+    #[error("Tendermint Validator app >= {required} required, found version {found}")]
+    OutdatedApp { required: Version, found: Version },
+
+    #[error("reconnected Ledger has a different public key than the one tmkms started with")]
+    PublicKeyChanged,
+
     #[error("message cannot be empty")]
     InvalidEmptyMessage,
 
@@ -22,8 +33,19 @@ pub enum Error {
     #[error("received an invalid signature")]
     InvalidSignature,
 
-    #[error("ledger error")]
+    #[error("ledger error: {0}")]
     Ledger(ledger::Error),
+
+    #[error("Speculos connection error: {0}")]
+    Io(io::Error),
+}
+
+impl Error {
+    /// Did communicating with the device fail (e.g. because it was unplugged,
+    /// or the connection to the emulator was lost)?
+    pub fn is_disconnected(&self) -> bool {
+        matches!(self, Error::Ledger(_) | Error::Io(_))
+    }
 }
 
 impl From<ledger::Error> for Error {
@@ -31,3 +53,9 @@ impl From<ledger::Error> for Error {
         Error::Ledger(err)
     }
 }
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
@@ -14,51 +14,124 @@
 *  limitations under the License.
 ********************************************************************************/
 
-use super::client::TendermintValidatorApp;
-use crate::keyring::ed25519::{PublicKey, Signature};
+use super::{
+    client::{Endpoint, TendermintValidatorApp, Version},
+    error::Error as LedgerError,
+};
+use crate::{
+    keyring::ed25519::{PublicKey, Signature},
+    prelude::*,
+};
 use signature::{Error, Signer};
 use std::sync::{Arc, Mutex};
 
 /// ed25519 signature provider for the Ledger Tendermint Validator app
+#[derive(Clone)]
 pub(super) struct Ed25519LedgerTmAppSigner {
-    app: Arc<Mutex<TendermintValidatorApp>>,
+    /// Connection to the app (`None` if reconnecting to it failed)
+    app: Arc<Mutex<Option<TendermintValidatorApp>>>,
+
+    /// Where the Ledger is reached
+    endpoint: Endpoint,
+
+    /// Minimum version of the app
+    min_version: Version,
+
+    /// Public key of the app when tmkms started
+    public_key: [u8; 32],
 }
 
 impl Ed25519LedgerTmAppSigner {
-    /// Create a new Ed25519 signer based on Ledger Nano S - Tendermint Validator app
-    pub fn connect() -> Result<Self, Error> {
-        let validator_app = TendermintValidatorApp::connect().map_err(Error::from_source)?;
-        let app = Arc::new(Mutex::new(validator_app));
-        Ok(Ed25519LedgerTmAppSigner { app })
+    /// Create a new Ed25519 signer based on Ledger Nano S - Tendermint Validator app,
+    /// failing unless the app is open and at least the given version
+    pub fn connect(endpoint: Endpoint, min_version: Version) -> Result<Self, LedgerError> {
+        let validator_app = TendermintValidatorApp::connect(&endpoint)?;
+        validator_app.check_app(&min_version)?;
+        let public_key = validator_app.public_key()?;
+
+        Ok(Ed25519LedgerTmAppSigner {
+            app: Arc::new(Mutex::new(Some(validator_app))),
+            endpoint,
+            min_version,
+            public_key,
+        })
+    }
+
+    /// Check the app is still open and responding
+    pub fn check(&self) -> Result<(), LedgerError> {
+        match self.app.lock().unwrap().as_ref() {
+            Some(validator_app) => validator_app.check_app(&self.min_version),
+            None => Err(LedgerError::Ledger(ledger::Error::DeviceNotFound)),
+        }
+    }
+
+    /// Reconnect to the app, e.g. after the Ledger was unplugged and plugged
+    /// back in (re-enumerating it on USB) or the emulator restarted, checking
+    /// the app again
+    fn reconnect(&self, app: &mut Option<TendermintValidatorApp>) -> Result<(), LedgerError> {
+        // Drop the old connection first, so the device is re-enumerated
+        *app = None;
+
+        let validator_app = TendermintValidatorApp::connect(&self.endpoint)?;
+        validator_app.check_app(&self.min_version)?;
+
+        if validator_app.public_key()? != self.public_key {
+            return Err(LedgerError::PublicKeyChanged);
+        }
+
+        *app = Some(validator_app);
+        Ok(())
     }
 }
 
 impl From<&Ed25519LedgerTmAppSigner> for PublicKey {
     /// Returns the public key that corresponds to the Tendermint Validator app connected to this signer
     fn from(signer: &Ed25519LedgerTmAppSigner) -> PublicKey {
-        let app = signer.app.lock().unwrap();
-        PublicKey::from_bytes(&app.public_key().unwrap()).expect("invalid Ed25519 public key")
+        PublicKey::from_bytes(&signer.public_key).expect("invalid Ed25519 public key")
     }
 }
 
 impl Signer<Signature> for Ed25519LedgerTmAppSigner {
     /// c: Compute a compact, fixed-sized signature of the given amino/json vote
     fn try_sign(&self, msg: &[u8]) -> Result<Signature, Error> {
-        let app = self.app.lock().unwrap();
-        let sig = app.sign(msg).map_err(Error::from_source)?;
+        let mut app = self.app.lock().unwrap();
+
+        let result = match app.as_ref() {
+            Some(validator_app) => validator_app.sign(msg),
+            None => Err(LedgerError::Ledger(ledger::Error::DeviceNotFound)),
+        };
+
+        let sig = match result {
+            // Communicating with the device failed: it may have been unplugged
+            // (or the emulator stopped) or the app closed, so reconnect and
+            // check the app before retrying
+            Err(e) if e.is_disconnected() => {
+                warn!("[keyring:ledgertm] {}; reconnecting to Ledger", e);
+
+                self.reconnect(&mut app).map_err(|e| {
+                    error!("[keyring:ledgertm] couldn't reconnect to Ledger: {}", e);
+                    Error::from_source(e)
+                })?;
+
+                app.as_ref().unwrap().sign(msg)
+            }
+            other => other,
+        }
+        .map_err(Error::from_source)?;
+
         Ok(Signature::from(sig))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Ed25519LedgerTmAppSigner, PublicKey};
+    use super::{Ed25519LedgerTmAppSigner, Endpoint, PublicKey, Version};
     use signature::Signer;
 
     #[test]
     #[ignore]
     fn public_key() {
-        let signer = Ed25519LedgerTmAppSigner::connect().unwrap();
+        let signer = Ed25519LedgerTmAppSigner::connect(Endpoint::Usb, Version::default()).unwrap();
         let pk = PublicKey::from(&signer);
         println!("PK {:0X?}", pk);
     }
@@ -66,7 +139,7 @@ mod tests {
     #[test]
     #[ignore]
     fn sign() {
-        let signer = Ed25519LedgerTmAppSigner::connect().unwrap();
+        let signer = Ed25519LedgerTmAppSigner::connect(Endpoint::Usb, Version::default()).unwrap();
 
         // Sign message1
         let some_message1 = [
@@ -87,7 +160,7 @@ mod tests {
     #[test]
     #[ignore]
     fn sign2() {
-        let signer = Ed25519LedgerTmAppSigner::connect().unwrap();
+        let signer = Ed25519LedgerTmAppSigner::connect(Endpoint::Usb, Version::default()).unwrap();
 
         // Sign message1
         let some_message1 = [
@@ -123,7 +196,7 @@ mod tests {
     #[test]
     #[ignore]
     fn sign_many() {
-        let signer = Ed25519LedgerTmAppSigner::connect().unwrap();
+        let signer = Ed25519LedgerTmAppSigner::connect(Endpoint::Usb, Version::default()).unwrap();
 
         // Get public key to initialize
         let pk = PublicKey::from(&signer);
@@ -0,0 +1,149 @@
+//! Transport to the [Speculos] Ledger emulator over its APDU-over-TCP
+//! protocol: each command is sent as a big-endian 32-bit length followed by
+//! the APDU, and answered with a big-endian 32-bit length, the response data
+//! and the 2-byte status word.
+//!
+//! [Speculos]: https://github.com/LedgerHQ/speculos
+
+use super::{client::Transport, error::Error};
+use ledger::{ApduAnswer, ApduCommand};
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+/// How long to wait for the emulator to answer a command (it may be waiting
+/// for a button press)
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest response accepted from the emulator
+const MAX_RESPONSE_SIZE: usize = 0x1_0000;
+
+/// Connection to a Speculos APDU server
+pub(super) struct Speculos {
+    stream: TcpStream,
+}
+
+impl Speculos {
+    /// Connect to the APDU server at the given `host:port`
+    pub fn connect(addr: &str) -> Result<Self, Error> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        Ok(Speculos { stream })
+    }
+}
+
+impl Transport for Speculos {
+    fn exchange(&self, command: ApduCommand) -> Result<ApduAnswer, Error> {
+        let mut stream = &self.stream;
+        stream.write_all(&encode(&command))?;
+
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+
+        if len > MAX_RESPONSE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("response too large ({} bytes)", len),
+            )
+            .into());
+        }
+
+        let mut data = vec![0u8; len + 2];
+        stream.read_exact(&mut data)?;
+        let retcode = u16::from_be_bytes([data[len], data[len + 1]]);
+        data.truncate(len);
+
+        Ok(ApduAnswer { data, retcode })
+    }
+}
+
+/// Encode a command as a length-prefixed APDU, laid out as it's sent over USB
+fn encode(command: &ApduCommand) -> Vec<u8> {
+    let mut apdu = vec![
+        command.cla,
+        command.ins,
+        command.p1,
+        command.p2,
+        command.length,
+    ];
+    apdu.extend_from_slice(&command.data);
+
+    let mut packet = (apdu.len() as u32).to_be_bytes().to_vec();
+    packet.extend_from_slice(&apdu);
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    /// Start a mock APDU server answering a single command with the given
+    /// data and status word (or closing the connection, if `None`), and
+    /// return its address along with the command it received
+    fn mock_server(answer: Option<(Vec<u8>, u16)>) -> (String, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut len = [0u8; 4];
+            stream.read_exact(&mut len).unwrap();
+            let mut apdu = vec![0u8; u32::from_be_bytes(len) as usize];
+            stream.read_exact(&mut apdu).unwrap();
+
+            if let Some((data, retcode)) = answer {
+                let mut response = (data.len() as u32).to_be_bytes().to_vec();
+                response.extend_from_slice(&data);
+                response.extend_from_slice(&retcode.to_be_bytes());
+                stream.write_all(&response).unwrap();
+            }
+
+            apdu
+        });
+
+        (addr, server)
+    }
+
+    fn command() -> ApduCommand {
+        ApduCommand {
+            cla: 0x56,
+            ins: 0x02,
+            p1: 0x01,
+            p2: 0x01,
+            length: 3,
+            data: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn exchange() {
+        let (addr, server) = mock_server(Some((vec![0xAA; 64], 0x9000)));
+        let answer = Speculos::connect(&addr)
+            .unwrap()
+            .exchange(command())
+            .unwrap();
+
+        assert_eq!(answer.data, vec![0xAA; 64]);
+        assert_eq!(answer.retcode, 0x9000);
+        assert_eq!(server.join().unwrap(), [0x56, 0x02, 0x01, 0x01, 3, 1, 2, 3]);
+    }
+
+    #[test]
+    fn connection_lost() {
+        let (addr, server) = mock_server(None);
+        let err = Speculos::connect(&addr)
+            .unwrap()
+            .exchange(command())
+            .err()
+            .expect("exchange should fail");
+
+        server.join().unwrap();
+        assert!(err.is_disconnected());
+    }
+}
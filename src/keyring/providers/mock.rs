@@ -0,0 +1,181 @@
+//! Mock signing provider, which behaves like a slow and flaky HSM without
+//! needing one: for testing configurations (and applications embedding the
+//! KMS) end-to-end.
+//!
+//! Only available with the `testing` cargo feature. Its key is derived from a
+//! seed in the configuration file, so it's neither secret nor random: never
+//! use it to sign for a live network.
+
+use crate::{
+    chain,
+    config::provider::mock::MockConfig,
+    error::{Error, ErrorKind::*},
+    keyring::{self, SigningProvider},
+    prelude::*,
+};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer};
+use sha2::{Digest, Sha256};
+use std::{sync::Mutex, thread, time::Duration};
+use tendermint::TendermintKey;
+
+/// Create mock signer objects from the given configuration
+pub fn init(registry: &mut chain::Registry, configs: &[MockConfig]) -> Result<(), Error> {
+    if configs.is_empty() {
+        return Ok(());
+    }
+
+    warn!("[[providers.mock]] is for testing only: its keys are derived from a known seed");
+
+    for config in configs {
+        if !(0.0..=1.0).contains(&config.error_probability) {
+            fail!(
+                ConfigError,
+                "[[providers.mock]] `error_probability` must be between 0.0 and 1.0"
+            );
+        }
+
+        let signing_key = SigningKey::new(config);
+        let consensus_pubkey = TendermintKey::ConsensusKey(signing_key.keypair.public.into());
+
+        let signer = keyring::ed25519::Signer::new(
+            SigningProvider::Mock,
+            consensus_pubkey,
+            Box::new(signing_key),
+        );
+
+        for chain_id in &config.chain_ids {
+            registry.add_validator_consensus_key(
+                chain_id,
+                config.validator.as_deref(),
+                signer.clone(),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Ed25519 signing key which signs after an artificial delay, and fails at
+/// random
+struct SigningKey {
+    /// Key derived from the configured seed
+    keypair: Keypair,
+
+    /// Delay before each signing operation
+    latency: Duration,
+
+    /// Probability that a signing operation fails
+    error_probability: f64,
+
+    /// State of the (seeded) random number generator deciding which
+    /// signing operations fail
+    rng: Mutex<u64>,
+}
+
+impl SigningKey {
+    /// Create the signing key for the given configuration
+    fn new(config: &MockConfig) -> Self {
+        let secret = SecretKey::from_bytes(&Sha256::digest(config.seed().as_bytes())).unwrap();
+        let public = PublicKey::from(&secret);
+
+        let rng_seed = Sha256::new()
+            .chain(b"errors:")
+            .chain(config.seed().as_bytes())
+            .finalize();
+
+        let mut rng = [0u8; 8];
+        rng.copy_from_slice(&rng_seed[..8]);
+
+        Self {
+            keypair: Keypair { secret, public },
+            latency: Duration::from_millis(config.latency_ms),
+            error_probability: config.error_probability,
+            rng: Mutex::new(u64::from_le_bytes(rng)),
+        }
+    }
+
+    /// Decide whether the next signing operation fails
+    fn inject_error(&self) -> bool {
+        if self.error_probability <= 0.0 {
+            return false;
+        }
+
+        // SplitMix64: the sequence of failures only depends on the seed
+        let mut state = self.rng.lock().unwrap();
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        ((z >> 11) as f64 / (1u64 << 53) as f64) < self.error_probability
+    }
+}
+
+impl Signer<Signature> for SigningKey {
+    fn try_sign(&self, msg: &[u8]) -> Result<Signature, signature::Error> {
+        thread::sleep(self.latency);
+
+        if self.inject_error() {
+            return Err(signature::Error::from_source(
+                "injected mock provider error".to_owned(),
+            ));
+        }
+
+        self.keypair.try_sign(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use signature::Verifier;
+
+    fn config(error_probability: f64) -> MockConfig {
+        MockConfig {
+            chain_ids: vec![],
+            validator: None,
+            seed: None,
+            latency_ms: 0,
+            error_probability,
+        }
+    }
+
+    #[test]
+    fn key_is_derived_from_seed() {
+        let signing_key = SigningKey::new(&config(0.0));
+        assert_eq!(
+            signing_key.keypair.public,
+            SigningKey::new(&config(0.0)).keypair.public
+        );
+
+        let signature = signing_key.try_sign(b"hello").unwrap();
+        assert!(signing_key
+            .keypair
+            .public
+            .verify(b"hello", &signature)
+            .is_ok());
+
+        let other = SigningKey::new(&MockConfig {
+            seed: Some("other".to_owned()),
+            ..config(0.0)
+        });
+        assert_ne!(signing_key.keypair.public, other.keypair.public);
+    }
+
+    #[test]
+    fn injected_errors_are_deterministic() {
+        let outcomes = |signing_key: SigningKey| {
+            (0..100)
+                .map(|_| signing_key.try_sign(b"hello").is_ok())
+                .collect::<Vec<_>>()
+        };
+
+        let first = outcomes(SigningKey::new(&config(0.5)));
+        assert_eq!(first, outcomes(SigningKey::new(&config(0.5))));
+        assert!(first.contains(&true) && first.contains(&false));
+
+        assert!(!outcomes(SigningKey::new(&config(1.0))).contains(&true));
+        assert!(!outcomes(SigningKey::new(&config(0.0))).contains(&false));
+    }
+}
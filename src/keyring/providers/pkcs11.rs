@@ -0,0 +1,97 @@
+//! Generic PKCS#11 signing provider
+//!
+//! Signs using Ed25519 (`CKK_EC_EDWARDS`) keys held by any HSM with a PKCS#11
+//! module, e.g. SafeNet Luna, AWS CloudHSM, or SoftHSM.
+
+mod ffi;
+#[allow(unsafe_code)]
+mod module;
+#[allow(unsafe_code)]
+mod token;
+
+pub use self::{
+    module::{Module, Slot},
+    token::{KeyInfo, Token},
+};
+use crate::{
+    chain,
+    config::provider::pkcs11::{Pkcs11Config, SigningKeyConfig},
+    error::{Error, ErrorKind::*},
+    keyring::{self, SigningProvider},
+    prelude::*,
+};
+use ed25519_dalek::{Signature, Signer};
+use std::sync::Arc;
+use tendermint::{PublicKey, TendermintKey};
+
+/// Create PKCS#11 backed signer objects from the given configuration
+pub fn init(registry: &mut chain::Registry, configs: &[Pkcs11Config]) -> Result<(), Error> {
+    if configs.is_empty() {
+        return Ok(());
+    }
+
+    for config in configs {
+        let token = Arc::new(Token::open(config)?);
+
+        for key in &config.signing_keys {
+            add_key(registry, key, token.clone())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Add a PKCS#11 signing key to the keyrings of the chains it's configured for
+fn add_key(
+    registry: &mut chain::Registry,
+    config: &SigningKeyConfig,
+    token: Arc<Token>,
+) -> Result<(), Error> {
+    let public_key = token.public_key(&config.key_label)?;
+
+    let public_key = PublicKey::from_raw_ed25519(&public_key).ok_or_else(|| {
+        format_err!(
+            Pkcs11Error,
+            "invalid Ed25519 public key labeled `{}` on PKCS#11 {}",
+            config.key_label,
+            token.description()
+        )
+    })?;
+
+    let signing_key = SigningKey {
+        token,
+        key_label: config.key_label.clone(),
+    };
+
+    let signer = keyring::ed25519::Signer::new(
+        SigningProvider::Pkcs11,
+        TendermintKey::ConsensusKey(public_key),
+        Box::new(signing_key),
+    );
+
+    for chain_id in &config.chain_ids {
+        registry.add_consensus_key(chain_id, signer.clone())?;
+    }
+
+    Ok(())
+}
+
+/// Ed25519 signing key stored on a PKCS#11 token
+struct SigningKey {
+    /// Token holding the key
+    token: Arc<Token>,
+
+    /// Label of the private key object
+    key_label: String,
+}
+
+impl Signer<Signature> for SigningKey {
+    fn try_sign(&self, msg: &[u8]) -> Result<Signature, signature::Error> {
+        let signature = self
+            .token
+            .sign(&self.key_label, msg)
+            .map_err(signature::Error::from_source)?;
+
+        Signature::from_bytes(&signature)
+    }
+}
@@ -0,0 +1,278 @@
+//! Minimal raw bindings to the PKCS#11 (Cryptoki) v2.40 C API.
+//!
+//! Only the parts of the API needed to log in, locate keys, and sign are
+//! defined. Structures use the default (unpacked) layout used by PKCS#11
+//! modules on Unix platforms.
+
+#![allow(non_camel_case_types, non_snake_case)]
+
+use std::os::raw::{c_uchar, c_ulong, c_void};
+
+pub type CK_BYTE = c_uchar;
+pub type CK_BBOOL = CK_BYTE;
+pub type CK_ULONG = c_ulong;
+pub type CK_FLAGS = CK_ULONG;
+pub type CK_RV = CK_ULONG;
+pub type CK_SLOT_ID = CK_ULONG;
+pub type CK_SESSION_HANDLE = CK_ULONG;
+pub type CK_OBJECT_HANDLE = CK_ULONG;
+pub type CK_OBJECT_CLASS = CK_ULONG;
+pub type CK_KEY_TYPE = CK_ULONG;
+pub type CK_ATTRIBUTE_TYPE = CK_ULONG;
+pub type CK_MECHANISM_TYPE = CK_ULONG;
+pub type CK_USER_TYPE = CK_ULONG;
+
+pub const CK_TRUE: CK_BBOOL = 1;
+
+pub const CKR_OK: CK_RV = 0x0000;
+pub const CKR_DEVICE_ERROR: CK_RV = 0x0030;
+pub const CKR_DEVICE_REMOVED: CK_RV = 0x0032;
+pub const CKR_FUNCTION_NOT_SUPPORTED: CK_RV = 0x0054;
+pub const CKR_KEY_HANDLE_INVALID: CK_RV = 0x0060;
+pub const CKR_SESSION_CLOSED: CK_RV = 0x00B0;
+pub const CKR_SESSION_HANDLE_INVALID: CK_RV = 0x00B3;
+pub const CKR_TOKEN_NOT_PRESENT: CK_RV = 0x00E0;
+pub const CKR_USER_ALREADY_LOGGED_IN: CK_RV = 0x0100;
+pub const CKR_USER_NOT_LOGGED_IN: CK_RV = 0x0101;
+pub const CKR_CRYPTOKI_ALREADY_INITIALIZED: CK_RV = 0x0191;
+
+pub const CKF_OS_LOCKING_OK: CK_FLAGS = 0x0002;
+pub const CKF_SERIAL_SESSION: CK_FLAGS = 0x0004;
+
+pub const CKU_USER: CK_USER_TYPE = 1;
+
+pub const CKO_PUBLIC_KEY: CK_OBJECT_CLASS = 2;
+pub const CKO_PRIVATE_KEY: CK_OBJECT_CLASS = 3;
+
+pub const CKK_EC_EDWARDS: CK_KEY_TYPE = 0x0040;
+
+pub const CKA_CLASS: CK_ATTRIBUTE_TYPE = 0x0000;
+pub const CKA_LABEL: CK_ATTRIBUTE_TYPE = 0x0003;
+pub const CKA_KEY_TYPE: CK_ATTRIBUTE_TYPE = 0x0100;
+pub const CKA_ID: CK_ATTRIBUTE_TYPE = 0x0102;
+pub const CKA_EC_POINT: CK_ATTRIBUTE_TYPE = 0x0181;
+
+pub const CKM_EDDSA: CK_MECHANISM_TYPE = 0x1057;
+
+/// Value of `ulValueLen` for attributes which can't be read
+pub const CK_UNAVAILABLE_INFORMATION: CK_ULONG = !0;
+
+/// Name of the only symbol looked up in the module
+pub const GET_FUNCTION_LIST: &[u8] = b"C_GetFunctionList\0";
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CK_VERSION {
+    pub major: CK_BYTE,
+    pub minor: CK_BYTE,
+}
+
+#[repr(C)]
+pub struct CK_C_INITIALIZE_ARGS {
+    pub CreateMutex: *mut c_void,
+    pub DestroyMutex: *mut c_void,
+    pub LockMutex: *mut c_void,
+    pub UnlockMutex: *mut c_void,
+    pub flags: CK_FLAGS,
+    pub pReserved: *mut c_void,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct CK_TOKEN_INFO {
+    pub label: [CK_BYTE; 32],
+    pub manufacturerID: [CK_BYTE; 32],
+    pub model: [CK_BYTE; 16],
+    pub serialNumber: [CK_BYTE; 16],
+    pub flags: CK_FLAGS,
+    pub ulMaxSessionCount: CK_ULONG,
+    pub ulSessionCount: CK_ULONG,
+    pub ulMaxRwSessionCount: CK_ULONG,
+    pub ulRwSessionCount: CK_ULONG,
+    pub ulMaxPinLen: CK_ULONG,
+    pub ulMinPinLen: CK_ULONG,
+    pub ulTotalPublicMemory: CK_ULONG,
+    pub ulFreePublicMemory: CK_ULONG,
+    pub ulTotalPrivateMemory: CK_ULONG,
+    pub ulFreePrivateMemory: CK_ULONG,
+    pub hardwareVersion: CK_VERSION,
+    pub firmwareVersion: CK_VERSION,
+    pub utcTime: [CK_BYTE; 16],
+}
+
+#[repr(C)]
+pub struct CK_ATTRIBUTE {
+    pub type_: CK_ATTRIBUTE_TYPE,
+    pub pValue: *mut c_void,
+    pub ulValueLen: CK_ULONG,
+}
+
+#[repr(C)]
+pub struct CK_MECHANISM {
+    pub mechanism: CK_MECHANISM_TYPE,
+    pub pParameter: *mut c_void,
+    pub ulParameterLen: CK_ULONG,
+}
+
+type Unused = Option<unsafe extern "C" fn()>;
+
+/// Leading portion of `CK_FUNCTION_LIST`, up to and including `C_Sign`.
+///
+/// The function list is only ever accessed through the pointer returned by
+/// the module, so the remaining entries can be omitted.
+#[repr(C)]
+pub struct CK_FUNCTION_LIST {
+    pub version: CK_VERSION,
+    pub C_Initialize: Option<unsafe extern "C" fn(pInitArgs: *mut c_void) -> CK_RV>,
+    pub C_Finalize: Option<unsafe extern "C" fn(pReserved: *mut c_void) -> CK_RV>,
+    pub C_GetInfo: Unused,
+    pub C_GetFunctionList: Unused,
+    pub C_GetSlotList: Option<
+        unsafe extern "C" fn(
+            tokenPresent: CK_BBOOL,
+            pSlotList: *mut CK_SLOT_ID,
+            pulCount: *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub C_GetSlotInfo: Unused,
+    pub C_GetTokenInfo:
+        Option<unsafe extern "C" fn(slotID: CK_SLOT_ID, pInfo: *mut CK_TOKEN_INFO) -> CK_RV>,
+    pub C_GetMechanismList: Unused,
+    pub C_GetMechanismInfo: Unused,
+    pub C_InitToken: Unused,
+    pub C_InitPIN: Unused,
+    pub C_SetPIN: Unused,
+    pub C_OpenSession: Option<
+        unsafe extern "C" fn(
+            slotID: CK_SLOT_ID,
+            flags: CK_FLAGS,
+            pApplication: *mut c_void,
+            Notify: *mut c_void,
+            phSession: *mut CK_SESSION_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub C_CloseSession: Option<unsafe extern "C" fn(hSession: CK_SESSION_HANDLE) -> CK_RV>,
+    pub C_CloseAllSessions: Unused,
+    pub C_GetSessionInfo: Unused,
+    pub C_GetOperationState: Unused,
+    pub C_SetOperationState: Unused,
+    pub C_Login: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            userType: CK_USER_TYPE,
+            pPin: *const CK_BYTE,
+            ulPinLen: CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub C_Logout: Unused,
+    pub C_CreateObject: Unused,
+    pub C_CopyObject: Unused,
+    pub C_DestroyObject: Unused,
+    pub C_GetObjectSize: Unused,
+    pub C_GetAttributeValue: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            hObject: CK_OBJECT_HANDLE,
+            pTemplate: *mut CK_ATTRIBUTE,
+            ulCount: CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub C_SetAttributeValue: Unused,
+    pub C_FindObjectsInit: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            pTemplate: *mut CK_ATTRIBUTE,
+            ulCount: CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub C_FindObjects: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            phObject: *mut CK_OBJECT_HANDLE,
+            ulMaxObjectCount: CK_ULONG,
+            pulObjectCount: *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub C_FindObjectsFinal: Option<unsafe extern "C" fn(hSession: CK_SESSION_HANDLE) -> CK_RV>,
+    pub C_EncryptInit: Unused,
+    pub C_Encrypt: Unused,
+    pub C_EncryptUpdate: Unused,
+    pub C_EncryptFinal: Unused,
+    pub C_DecryptInit: Unused,
+    pub C_Decrypt: Unused,
+    pub C_DecryptUpdate: Unused,
+    pub C_DecryptFinal: Unused,
+    pub C_DigestInit: Unused,
+    pub C_Digest: Unused,
+    pub C_DigestUpdate: Unused,
+    pub C_DigestKey: Unused,
+    pub C_DigestFinal: Unused,
+    pub C_SignInit: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            pMechanism: *mut CK_MECHANISM,
+            hKey: CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub C_Sign: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            pData: *mut CK_BYTE,
+            ulDataLen: CK_ULONG,
+            pSignature: *mut CK_BYTE,
+            pulSignatureLen: *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+}
+
+/// Signature of `C_GetFunctionList`
+pub type C_GetFunctionList =
+    unsafe extern "C" fn(ppFunctionList: *mut *mut CK_FUNCTION_LIST) -> CK_RV;
+
+/// Name of a return value, for error messages
+pub fn rv_name(rv: CK_RV) -> &'static str {
+    match rv {
+        CKR_OK => "CKR_OK",
+        0x0001 => "CKR_CANCEL",
+        0x0002 => "CKR_HOST_MEMORY",
+        0x0003 => "CKR_SLOT_ID_INVALID",
+        0x0005 => "CKR_GENERAL_ERROR",
+        0x0006 => "CKR_FUNCTION_FAILED",
+        0x0007 => "CKR_ARGUMENTS_BAD",
+        0x0012 => "CKR_ATTRIBUTE_TYPE_INVALID",
+        CKR_DEVICE_ERROR => "CKR_DEVICE_ERROR",
+        0x0031 => "CKR_DEVICE_MEMORY",
+        CKR_DEVICE_REMOVED => "CKR_DEVICE_REMOVED",
+        CKR_FUNCTION_NOT_SUPPORTED => "CKR_FUNCTION_NOT_SUPPORTED",
+        CKR_KEY_HANDLE_INVALID => "CKR_KEY_HANDLE_INVALID",
+        0x0068 => "CKR_KEY_FUNCTION_NOT_PERMITTED",
+        0x0070 => "CKR_MECHANISM_INVALID",
+        0x0082 => "CKR_OBJECT_HANDLE_INVALID",
+        0x0090 => "CKR_OPERATION_ACTIVE",
+        0x00A0 => "CKR_PIN_INCORRECT",
+        0x00A4 => "CKR_PIN_LOCKED",
+        CKR_SESSION_CLOSED => "CKR_SESSION_CLOSED",
+        CKR_SESSION_HANDLE_INVALID => "CKR_SESSION_HANDLE_INVALID",
+        CKR_TOKEN_NOT_PRESENT => "CKR_TOKEN_NOT_PRESENT",
+        CKR_USER_ALREADY_LOGGED_IN => "CKR_USER_ALREADY_LOGGED_IN",
+        CKR_USER_NOT_LOGGED_IN => "CKR_USER_NOT_LOGGED_IN",
+        0x0150 => "CKR_BUFFER_TOO_SMALL",
+        0x0190 => "CKR_CRYPTOKI_NOT_INITIALIZED",
+        CKR_CRYPTOKI_ALREADY_INITIALIZED => "CKR_CRYPTOKI_ALREADY_INITIALIZED",
+        _ => "unknown error",
+    }
+}
+
+/// Is the given return value an indication that the session is no longer
+/// usable and must be reopened (and logged into again)?
+pub fn is_session_error(rv: CK_RV) -> bool {
+    matches!(
+        rv,
+        CKR_DEVICE_ERROR
+            | CKR_DEVICE_REMOVED
+            | CKR_SESSION_CLOSED
+            | CKR_SESSION_HANDLE_INVALID
+            | CKR_TOKEN_NOT_PRESENT
+            | CKR_USER_NOT_LOGGED_IN
+    )
+}
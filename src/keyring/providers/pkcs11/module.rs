@@ -0,0 +1,234 @@
+//! Loading PKCS#11 modules
+
+use super::ffi::{self, CK_RV, CK_SLOT_ID, CK_TOKEN_INFO};
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use std::{
+    ffi::{CStr, CString},
+    fmt,
+    os::{raw::c_void, unix::ffi::OsStrExt},
+    path::Path,
+    ptr,
+};
+
+/// A loaded and initialized PKCS#11 module.
+///
+/// Modules are never unloaded: they're kept around for the lifetime of the
+/// process, as are the sessions opened through them.
+pub struct Module {
+    /// Function list returned by `C_GetFunctionList`
+    functions: &'static ffi::CK_FUNCTION_LIST,
+}
+
+// PKCS#11 modules are initialized with `CKF_OS_LOCKING_OK`, which permits
+// calls from multiple threads
+unsafe impl Send for Module {}
+unsafe impl Sync for Module {}
+
+impl Module {
+    /// Load the PKCS#11 module at the given path and initialize it
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+            format_err!(
+                ConfigError,
+                "invalid PKCS#11 module path: {}",
+                path.display()
+            )
+        })?;
+
+        let functions = unsafe {
+            let handle = libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+
+            if handle.is_null() {
+                fail!(
+                    Pkcs11Error,
+                    "couldn't load PKCS#11 module {}: {}",
+                    path.display(),
+                    dlerror()
+                );
+            }
+
+            let symbol = libc::dlsym(handle, ffi::GET_FUNCTION_LIST.as_ptr() as *const _);
+
+            if symbol.is_null() {
+                fail!(
+                    Pkcs11Error,
+                    "{} is not a PKCS#11 module: {}",
+                    path.display(),
+                    dlerror()
+                );
+            }
+
+            let get_function_list: ffi::C_GetFunctionList = std::mem::transmute(symbol);
+            let mut functions = ptr::null_mut();
+            check("C_GetFunctionList", get_function_list(&mut functions))?;
+
+            match functions.as_ref() {
+                Some(functions) => functions,
+                None => fail!(
+                    Pkcs11Error,
+                    "PKCS#11 module {} returned no function list",
+                    path.display()
+                ),
+            }
+        };
+
+        let module = Self { functions };
+
+        let mut init_args = ffi::CK_C_INITIALIZE_ARGS {
+            CreateMutex: ptr::null_mut(),
+            DestroyMutex: ptr::null_mut(),
+            LockMutex: ptr::null_mut(),
+            UnlockMutex: ptr::null_mut(),
+            flags: ffi::CKF_OS_LOCKING_OK,
+            pReserved: ptr::null_mut(),
+        };
+
+        let rv = unsafe {
+            (module.function("C_Initialize", module.functions.C_Initialize)?)(
+                &mut init_args as *mut _ as *mut c_void,
+            )
+        };
+
+        // Several `[[providers.pkcs11]]` sections may share the same module
+        if rv != ffi::CKR_CRYPTOKI_ALREADY_INITIALIZED {
+            check("C_Initialize", rv)?;
+        }
+
+        Ok(module)
+    }
+
+    /// Get the function list of this module
+    pub fn functions(&self) -> &ffi::CK_FUNCTION_LIST {
+        self.functions
+    }
+
+    /// Get a function from the function list, failing if the module
+    /// doesn't implement it
+    pub fn function<F>(&self, name: &'static str, function: Option<F>) -> Result<F, CallError> {
+        function.ok_or(CallError {
+            function: name,
+            rv: ffi::CKR_FUNCTION_NOT_SUPPORTED,
+        })
+    }
+
+    /// List the slots which have a token present
+    pub fn slots(&self) -> Result<Vec<Slot>, Error> {
+        let get_slot_list = self.function("C_GetSlotList", self.functions.C_GetSlotList)?;
+        let get_token_info = self.function("C_GetTokenInfo", self.functions.C_GetTokenInfo)?;
+
+        let mut count = 0;
+        check("C_GetSlotList", unsafe {
+            get_slot_list(ffi::CK_TRUE, ptr::null_mut(), &mut count)
+        })?;
+
+        let mut slot_ids = vec![0; count as usize];
+        check("C_GetSlotList", unsafe {
+            get_slot_list(ffi::CK_TRUE, slot_ids.as_mut_ptr(), &mut count)
+        })?;
+        slot_ids.truncate(count as usize);
+
+        let mut slots = Vec::with_capacity(slot_ids.len());
+
+        for id in slot_ids {
+            let mut info = std::mem::MaybeUninit::<CK_TOKEN_INFO>::zeroed();
+            check("C_GetTokenInfo", unsafe {
+                get_token_info(id, info.as_mut_ptr())
+            })?;
+            let info = unsafe { info.assume_init() };
+
+            slots.push(Slot {
+                id,
+                token_label: padded_string(&info.label),
+                manufacturer: padded_string(&info.manufacturerID),
+                model: padded_string(&info.model),
+                serial_number: padded_string(&info.serialNumber),
+            });
+        }
+
+        Ok(slots)
+    }
+}
+
+/// Slot containing a token
+#[derive(Clone, Debug)]
+pub struct Slot {
+    /// Slot ID
+    pub id: CK_SLOT_ID,
+
+    /// Label of the token in the slot
+    pub token_label: String,
+
+    /// Manufacturer of the token
+    pub manufacturer: String,
+
+    /// Model of the token
+    pub model: String,
+
+    /// Serial number of the token
+    pub serial_number: String,
+}
+
+/// Failed call to a PKCS#11 function
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CallError {
+    /// Name of the function
+    pub function: &'static str,
+
+    /// Return value of the function
+    pub rv: CK_RV,
+}
+
+impl CallError {
+    /// Does this error indicate the session must be reopened?
+    pub fn is_session_error(&self) -> bool {
+        ffi::is_session_error(self.rv)
+    }
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} failed: {} (0x{:08x})",
+            self.function,
+            ffi::rv_name(self.rv),
+            self.rv
+        )
+    }
+}
+
+impl From<CallError> for Error {
+    fn from(e: CallError) -> Error {
+        format_err!(Pkcs11Error, "{}", e).into()
+    }
+}
+
+/// Check the return value of a PKCS#11 function
+pub fn check(function: &'static str, rv: CK_RV) -> Result<(), CallError> {
+    if rv == ffi::CKR_OK {
+        Ok(())
+    } else {
+        Err(CallError { function, rv })
+    }
+}
+
+/// Convert a blank-padded PKCS#11 string to a `String`
+pub fn padded_string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches(&[' ', '\0'][..])
+        .to_owned()
+}
+
+/// Get the most recent dynamic linker error
+unsafe fn dlerror() -> String {
+    let e = libc::dlerror();
+
+    if e.is_null() {
+        "unknown error".to_owned()
+    } else {
+        CStr::from_ptr(e).to_string_lossy().into_owned()
+    }
+}
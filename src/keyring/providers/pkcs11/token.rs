@@ -0,0 +1,574 @@
+//! Sessions with a PKCS#11 token
+
+use super::{
+    ffi::{self, CK_ATTRIBUTE_TYPE, CK_OBJECT_HANDLE, CK_SESSION_HANDLE, CK_ULONG},
+    module::{check, CallError, Module},
+};
+use crate::{
+    config::provider::pkcs11::Pkcs11Config,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    Map,
+};
+use std::{os::raw::c_void, ptr, sync::Mutex};
+use zeroize::Zeroizing;
+
+/// Size of an Ed25519 public key
+const PUBLIC_KEY_SIZE: usize = 32;
+
+/// Size of an Ed25519 signature
+const SIGNATURE_SIZE: usize = 64;
+
+/// Logged-in session with a PKCS#11 token.
+///
+/// A single session is opened at startup and shared by all keys on the
+/// token. If the HSM invalidates it (e.g. after a failover or restart), it's
+/// reopened and logged into again transparently.
+pub struct Token {
+    /// PKCS#11 module
+    module: Module,
+
+    /// Slot containing the token
+    slot: ffi::CK_SLOT_ID,
+
+    /// Human-readable description of the token (for error messages)
+    description: String,
+
+    /// User PIN
+    pin: Zeroizing<String>,
+
+    /// Current session
+    session: Mutex<Session>,
+}
+
+/// Current session with the token
+struct Session {
+    /// Session handle, if the session is open
+    handle: Option<CK_SESSION_HANDLE>,
+
+    /// Private key handles found during this session, by label
+    keys: Map<String, CK_OBJECT_HANDLE>,
+}
+
+/// Information about an Ed25519 key on the token
+#[derive(Clone, Debug)]
+pub struct KeyInfo {
+    /// Label of the private key
+    pub label: String,
+
+    /// ID of the private key (`CKA_ID`)
+    pub id: Vec<u8>,
+
+    /// Public key, if a matching public key object exists
+    pub public_key: Option<[u8; PUBLIC_KEY_SIZE]>,
+}
+
+impl Token {
+    /// Load the configured module, locate the token, and log into it
+    pub fn open(config: &Pkcs11Config) -> Result<Self, Error> {
+        let module = Module::load(&config.module)?;
+        let description = config.token_description();
+        let slot = find_slot(&module, config)?;
+
+        let token = Self {
+            module,
+            slot,
+            description,
+            pin: config.pin.load()?,
+            session: Mutex::new(Session {
+                handle: None,
+                keys: Map::new(),
+            }),
+        };
+
+        token.with_session(|_| Ok(()))?;
+        Ok(token)
+    }
+
+    /// Get a description of the token, e.g. for use in error messages
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Look up the Ed25519 private key with the given label, returning the
+    /// corresponding public key
+    pub fn public_key(&self, label: &str) -> Result<[u8; PUBLIC_KEY_SIZE], Error> {
+        self.with_session(|session| {
+            if find_private_key(&self.module, session.handle()?, label)?.is_none() {
+                return Ok(None);
+            }
+
+            Ok(Some(find_public_key(
+                &self.module,
+                session.handle()?,
+                label,
+            )?))
+        })?
+        .ok_or_else(|| {
+            format_err!(
+                Pkcs11Error,
+                "no Ed25519 private key labeled `{}` on PKCS#11 {} (slot {})",
+                label,
+                self.description,
+                self.slot
+            )
+        })?
+        .ok_or_else(|| {
+            format_err!(
+                Pkcs11Error,
+                "no Ed25519 public key labeled `{}` on PKCS#11 {} (slot {})",
+                label,
+                self.description,
+                self.slot
+            )
+            .into()
+        })
+    }
+
+    /// List all Ed25519 private keys on the token
+    pub fn list_keys(&self) -> Result<Vec<KeyInfo>, Error> {
+        self.with_session(|session| {
+            let handle = session.handle()?;
+            let mut template = key_template(ffi::CKO_PRIVATE_KEY);
+            let mut keys = vec![];
+
+            for object in find_objects(&self.module, handle, &mut template.attributes())? {
+                let label = get_attribute(&self.module, handle, object, ffi::CKA_LABEL)?
+                    .map(|label| String::from_utf8_lossy(&label).into_owned())
+                    .unwrap_or_default();
+
+                let id =
+                    get_attribute(&self.module, handle, object, ffi::CKA_ID)?.unwrap_or_default();
+
+                let public_key = find_public_key(&self.module, handle, &label)?;
+
+                keys.push(KeyInfo {
+                    label,
+                    id,
+                    public_key,
+                });
+            }
+
+            Ok(keys)
+        })
+    }
+
+    /// Sign the given message with the Ed25519 private key with the given
+    /// label
+    pub fn sign(&self, label: &str, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        self.with_session(|session| {
+            let handle = session.handle()?;
+
+            let key = match session.keys.get(label) {
+                Some(key) => *key,
+                None => {
+                    let key = find_private_key(&self.module, handle, label)?.ok_or(CallError {
+                        function: "C_FindObjects",
+                        rv: ffi::CKR_KEY_HANDLE_INVALID,
+                    })?;
+
+                    session.keys.insert(label.to_owned(), key);
+                    key
+                }
+            };
+
+            sign(&self.module, handle, key, msg)
+        })
+    }
+
+    /// Run the given function with the current session, (re)opening it and
+    /// logging in if necessary, and retrying once if the session turns out
+    /// to have been invalidated
+    fn with_session<T>(
+        &self,
+        mut f: impl FnMut(&mut Session) -> Result<T, CallError>,
+    ) -> Result<T, Error> {
+        let mut session = self.session.lock().unwrap();
+        let mut reopened = false;
+
+        loop {
+            if session.handle.is_none() {
+                session.keys.clear();
+                session.handle = Some(self.login()?);
+                reopened = true;
+            }
+
+            match f(&mut session) {
+                Err(e) if e.is_session_error() && !reopened => {
+                    warn!(
+                        "[keyring:pkcs11] session with {} lost ({}); logging in again",
+                        self.description, e
+                    );
+
+                    self.close(&mut session);
+                }
+                result => {
+                    return result.map_err(|e| {
+                        format_err!(Pkcs11Error, "PKCS#11 {}: {}", self.description, e).into()
+                    })
+                }
+            }
+        }
+    }
+
+    /// Open a new session and log into it
+    fn login(&self) -> Result<CK_SESSION_HANDLE, Error> {
+        let functions = self.module.functions();
+        let open_session = self
+            .module
+            .function("C_OpenSession", functions.C_OpenSession)?;
+        let login = self.module.function("C_Login", functions.C_Login)?;
+
+        let mut handle = 0;
+
+        check("C_OpenSession", unsafe {
+            open_session(
+                self.slot,
+                ffi::CKF_SERIAL_SESSION,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut handle,
+            )
+        })
+        .map_err(|e| {
+            format_err!(
+                Pkcs11Error,
+                "couldn't open session with PKCS#11 {} (slot {}): {}",
+                self.description,
+                self.slot,
+                e
+            )
+        })?;
+
+        let rv = unsafe {
+            login(
+                handle,
+                ffi::CKU_USER,
+                self.pin.as_ptr(),
+                self.pin.len() as CK_ULONG,
+            )
+        };
+
+        // Login state is shared by all of an application's sessions
+        if rv != ffi::CKR_USER_ALREADY_LOGGED_IN {
+            check("C_Login", rv).map_err(|e| {
+                format_err!(
+                    Pkcs11Error,
+                    "couldn't log into PKCS#11 {} (slot {}): {}",
+                    self.description,
+                    self.slot,
+                    e
+                )
+            })?;
+        }
+
+        Ok(handle)
+    }
+
+    /// Close the current session (if it's still open)
+    fn close(&self, session: &mut Session) {
+        if let Some(handle) = session.handle.take() {
+            if let Some(close_session) = self.module.functions().C_CloseSession {
+                // The session is likely already gone, so ignore errors
+                unsafe {
+                    close_session(handle);
+                }
+            }
+        }
+
+        session.keys.clear();
+    }
+}
+
+impl Session {
+    /// Get the session handle
+    fn handle(&self) -> Result<CK_SESSION_HANDLE, CallError> {
+        self.handle.ok_or(CallError {
+            function: "C_OpenSession",
+            rv: ffi::CKR_SESSION_HANDLE_INVALID,
+        })
+    }
+}
+
+/// Find the slot configured by either `slot` or `token_label`
+fn find_slot(module: &Module, config: &Pkcs11Config) -> Result<ffi::CK_SLOT_ID, Error> {
+    let slots = module.slots()?;
+
+    let slot = match (config.slot, &config.token_label) {
+        (Some(_), Some(_)) => fail!(
+            ConfigError,
+            "only one of `slot` or `token_label` can be set for [[providers.pkcs11]]"
+        ),
+        (None, None) => fail!(
+            ConfigError,
+            "either `slot` or `token_label` must be set for [[providers.pkcs11]]"
+        ),
+        (Some(id), None) => slots.iter().find(|slot| slot.id == id),
+        (None, Some(label)) => slots.iter().find(|slot| &slot.token_label == label),
+    };
+
+    match slot {
+        Some(slot) => Ok(slot.id),
+        None => {
+            let available = slots
+                .iter()
+                .map(|slot| format!("slot {} (`{}`)", slot.id, slot.token_label))
+                .collect::<Vec<_>>();
+
+            fail!(
+                Pkcs11Error,
+                "no PKCS#11 {} found in {} (available: {})",
+                config.token_description(),
+                config.module.display(),
+                if available.is_empty() {
+                    "none".to_owned()
+                } else {
+                    available.join(", ")
+                }
+            )
+        }
+    }
+}
+
+/// Search template for Ed25519 keys
+struct KeyTemplate {
+    class: ffi::CK_OBJECT_CLASS,
+    key_type: ffi::CK_KEY_TYPE,
+    label: Option<Vec<u8>>,
+}
+
+impl KeyTemplate {
+    /// Get the template's attributes
+    fn attributes(&mut self) -> Vec<ffi::CK_ATTRIBUTE> {
+        let mut attributes = vec![
+            attribute(ffi::CKA_CLASS, &mut self.class),
+            attribute(ffi::CKA_KEY_TYPE, &mut self.key_type),
+        ];
+
+        if let Some(label) = &mut self.label {
+            attributes.push(ffi::CK_ATTRIBUTE {
+                type_: ffi::CKA_LABEL,
+                pValue: label.as_mut_ptr() as *mut c_void,
+                ulValueLen: label.len() as CK_ULONG,
+            });
+        }
+
+        attributes
+    }
+}
+
+/// Create a template matching Ed25519 keys of the given class
+fn key_template(class: ffi::CK_OBJECT_CLASS) -> KeyTemplate {
+    KeyTemplate {
+        class,
+        key_type: ffi::CKK_EC_EDWARDS,
+        label: None,
+    }
+}
+
+/// Create an attribute pointing to the given `CK_ULONG` value
+fn attribute(type_: CK_ATTRIBUTE_TYPE, value: &mut CK_ULONG) -> ffi::CK_ATTRIBUTE {
+    ffi::CK_ATTRIBUTE {
+        type_,
+        pValue: value as *mut CK_ULONG as *mut c_void,
+        ulValueLen: CK_ULONG::from(CK_ULONG::BITS / 8),
+    }
+}
+
+/// Find the Ed25519 private key with the given label
+fn find_private_key(
+    module: &Module,
+    session: CK_SESSION_HANDLE,
+    label: &str,
+) -> Result<Option<CK_OBJECT_HANDLE>, CallError> {
+    let mut template = key_template(ffi::CKO_PRIVATE_KEY);
+    template.label = Some(label.as_bytes().to_vec());
+
+    Ok(find_objects(module, session, &mut template.attributes())?
+        .first()
+        .cloned())
+}
+
+/// Find the Ed25519 public key with the given label and read its value
+fn find_public_key(
+    module: &Module,
+    session: CK_SESSION_HANDLE,
+    label: &str,
+) -> Result<Option<[u8; PUBLIC_KEY_SIZE]>, CallError> {
+    let mut template = key_template(ffi::CKO_PUBLIC_KEY);
+    template.label = Some(label.as_bytes().to_vec());
+
+    let object = match find_objects(module, session, &mut template.attributes())?.first() {
+        Some(object) => *object,
+        None => return Ok(None),
+    };
+
+    Ok(get_attribute(module, session, object, ffi::CKA_EC_POINT)?
+        .as_deref()
+        .and_then(decode_ec_point))
+}
+
+/// Find all objects matching the given template
+fn find_objects(
+    module: &Module,
+    session: CK_SESSION_HANDLE,
+    template: &mut [ffi::CK_ATTRIBUTE],
+) -> Result<Vec<CK_OBJECT_HANDLE>, CallError> {
+    let functions = module.functions();
+    let find_objects_init = module.function("C_FindObjectsInit", functions.C_FindObjectsInit)?;
+    let find_objects = module.function("C_FindObjects", functions.C_FindObjects)?;
+    let find_objects_final = module.function("C_FindObjectsFinal", functions.C_FindObjectsFinal)?;
+
+    check("C_FindObjectsInit", unsafe {
+        find_objects_init(session, template.as_mut_ptr(), template.len() as CK_ULONG)
+    })?;
+
+    let mut objects = vec![];
+    let mut batch = [0; 16];
+
+    let result = loop {
+        let mut count = 0;
+
+        if let Err(e) = check("C_FindObjects", unsafe {
+            find_objects(
+                session,
+                batch.as_mut_ptr(),
+                batch.len() as CK_ULONG,
+                &mut count,
+            )
+        }) {
+            break Err(e);
+        }
+
+        if count == 0 {
+            break Ok(());
+        }
+
+        objects.extend_from_slice(&batch[..count as usize]);
+    };
+
+    let final_rv = unsafe { find_objects_final(session) };
+    result?;
+    check("C_FindObjectsFinal", final_rv)?;
+
+    Ok(objects)
+}
+
+/// Read an attribute of an object, returning `None` if it's unavailable
+fn get_attribute(
+    module: &Module,
+    session: CK_SESSION_HANDLE,
+    object: CK_OBJECT_HANDLE,
+    type_: CK_ATTRIBUTE_TYPE,
+) -> Result<Option<Vec<u8>>, CallError> {
+    let get_attribute_value = module.function(
+        "C_GetAttributeValue",
+        module.functions().C_GetAttributeValue,
+    )?;
+
+    let mut attribute = ffi::CK_ATTRIBUTE {
+        type_,
+        pValue: ptr::null_mut(),
+        ulValueLen: 0,
+    };
+
+    let rv = unsafe { get_attribute_value(session, object, &mut attribute, 1) };
+
+    if attribute.ulValueLen == ffi::CK_UNAVAILABLE_INFORMATION {
+        return Ok(None);
+    }
+
+    check("C_GetAttributeValue", rv)?;
+
+    let mut value = vec![0u8; attribute.ulValueLen as usize];
+    attribute.pValue = value.as_mut_ptr() as *mut c_void;
+
+    check("C_GetAttributeValue", unsafe {
+        get_attribute_value(session, object, &mut attribute, 1)
+    })?;
+
+    value.truncate(attribute.ulValueLen as usize);
+    Ok(Some(value))
+}
+
+/// Compute a (pure) Ed25519 signature over the given message
+fn sign(
+    module: &Module,
+    session: CK_SESSION_HANDLE,
+    key: CK_OBJECT_HANDLE,
+    msg: &[u8],
+) -> Result<Vec<u8>, CallError> {
+    let functions = module.functions();
+    let sign_init = module.function("C_SignInit", functions.C_SignInit)?;
+    let sign = module.function("C_Sign", functions.C_Sign)?;
+
+    let mut mechanism = ffi::CK_MECHANISM {
+        mechanism: ffi::CKM_EDDSA,
+        pParameter: ptr::null_mut(),
+        ulParameterLen: 0,
+    };
+
+    check("C_SignInit", unsafe {
+        sign_init(session, &mut mechanism, key)
+    })?;
+
+    let mut data = msg.to_vec();
+    let mut signature = vec![0u8; SIGNATURE_SIZE];
+    let mut signature_len = signature.len() as CK_ULONG;
+
+    check("C_Sign", unsafe {
+        sign(
+            session,
+            data.as_mut_ptr(),
+            data.len() as CK_ULONG,
+            signature.as_mut_ptr(),
+            &mut signature_len,
+        )
+    })?;
+
+    signature.truncate(signature_len as usize);
+    Ok(signature)
+}
+
+/// Decode a `CKA_EC_POINT` value into an Ed25519 public key.
+///
+/// PKCS#11 specifies this as a DER-encoded `OCTET STRING`, however some
+/// modules return the raw 32-byte key instead, so both are accepted.
+pub fn decode_ec_point(ec_point: &[u8]) -> Option<[u8; PUBLIC_KEY_SIZE]> {
+    let bytes = match ec_point {
+        [0x04, 0x20, rest @ ..] if rest.len() == PUBLIC_KEY_SIZE => rest,
+        bytes => bytes,
+    };
+
+    let mut public_key = [0u8; PUBLIC_KEY_SIZE];
+
+    if bytes.len() == PUBLIC_KEY_SIZE {
+        public_key.copy_from_slice(bytes);
+        Some(public_key)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_der_ec_point() {
+        let mut ec_point = vec![0x04, 0x20];
+        ec_point.extend_from_slice(&[0x42; 32]);
+        assert_eq!(decode_ec_point(&ec_point), Some([0x42; 32]));
+    }
+
+    #[test]
+    fn decode_raw_ec_point() {
+        assert_eq!(decode_ec_point(&[0x04; 32]), Some([0x04; 32]));
+    }
+
+    #[test]
+    fn reject_invalid_ec_point() {
+        assert_eq!(decode_ec_point(&[0x04, 0x20, 0x01]), None);
+        assert_eq!(decode_ec_point(&[]), None);
+    }
+}
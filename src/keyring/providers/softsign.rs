@@ -1,11 +1,11 @@
-//! ed25519-dalek software-based signer
+//! Software-based signer using ed25519-dalek (Ed25519) or k256 (secp256k1)
 //!
 //! This is mainly intended for testing/CI. Ideally real validators will use HSMs.
 
 use crate::{
     chain,
     config::provider::{
-        softsign::{KeyFormat, SoftsignConfig},
+        softsign::{KeyAlgorithm, KeyFormat, SoftsignConfig},
         KeyType,
     },
     error::{Error, ErrorKind::*},
@@ -15,60 +15,92 @@ use crate::{
 };
 use ed25519_dalek as ed25519;
 use k256::ecdsa;
-use tendermint::{PrivateKey, TendermintKey};
-use tendermint_config::PrivValidatorKey;
+use tendermint::TendermintKey;
 
-/// Create software-backed Ed25519 signer objects from the given configuration
+/// Create software-backed Ed25519 and secp256k1 signer objects from the given
+/// configuration
 pub fn init(chain_registry: &mut chain::Registry, configs: &[SoftsignConfig]) -> Result<(), Error> {
     if configs.is_empty() {
         return Ok(());
     }
 
-    let mut loaded_consensus_key = false;
-
     for config in configs {
-        match config.key_type {
-            KeyType::Account => {
-                let signer = load_secp256k1_key(config)?;
+        match (&config.key_type, config.key_algorithm()) {
+            (KeyType::Account, _) if config.validator.is_some() => fail!(
+                ConfigError,
+                "[[providers.softsign]] `validator` only applies to consensus keys"
+            ),
+            (KeyType::Account, KeyAlgorithm::Secp256k1) => {
+                let signer = Box::new(load_secp256k1_key(config)?);
+                key_utils::mlock::lock(&*signer)?;
+
                 let public_key =
                     tendermint::PublicKey::from_raw_secp256k1(&signer.verifying_key().to_bytes())
                         .unwrap();
 
                 let account_pubkey = TendermintKey::AccountKey(public_key);
 
-                let signer = keyring::ecdsa::Signer::new(
-                    SigningProvider::SoftSign,
-                    account_pubkey,
-                    Box::new(signer),
-                );
+                let signer =
+                    keyring::ecdsa::Signer::new(SigningProvider::SoftSign, account_pubkey, signer);
 
                 for chain_id in &config.chain_ids {
                     chain_registry.add_account_key(chain_id, signer.clone())?;
                 }
             }
-            KeyType::Consensus => {
-                if loaded_consensus_key {
-                    fail!(
-                        ConfigError,
-                        "only one [[providers.softsign]] consensus key allowed"
+            (KeyType::Account, KeyAlgorithm::Ed25519) => fail!(
+                ConfigError,
+                "[[providers.softsign]] account keys must be secp256k1"
+            ),
+            // Each chain may have its own consensus key (e.g. Interchain
+            // Security consumer chains), and more than one only if the
+            // chain selects its `active_key`
+            (KeyType::Consensus, key_algorithm) => match key_algorithm {
+                KeyAlgorithm::Ed25519 => {
+                    let signing_key = Box::new(load_ed25519_key(config)?);
+                    key_utils::mlock::lock(&*signing_key)?;
+
+                    let consensus_pubkey = TendermintKey::ConsensusKey(signing_key.public.into());
+
+                    let signer = keyring::ed25519::Signer::new(
+                        SigningProvider::SoftSign,
+                        consensus_pubkey,
+                        signing_key,
                     );
+
+                    for chain_id in &config.chain_ids {
+                        chain_registry.add_validator_consensus_key(
+                            chain_id,
+                            config.validator.as_deref(),
+                            signer.clone(),
+                        )?;
+                    }
                 }
+                KeyAlgorithm::Secp256k1 => {
+                    let signing_key = Box::new(load_secp256k1_key(config)?);
+                    key_utils::mlock::lock(&*signing_key)?;
 
-                loaded_consensus_key = true;
+                    let public_key = tendermint::PublicKey::from_raw_secp256k1(
+                        &signing_key.verifying_key().to_bytes(),
+                    )
+                    .unwrap();
 
-                let signing_key = load_ed25519_key(config)?;
-                let consensus_pubkey = TendermintKey::ConsensusKey(signing_key.public.into());
+                    let consensus_pubkey = TendermintKey::ConsensusKey(public_key);
 
-                let signer = keyring::ed25519::Signer::new(
-                    SigningProvider::SoftSign,
-                    consensus_pubkey,
-                    Box::new(signing_key),
-                );
+                    let signer = keyring::ecdsa::Signer::new(
+                        SigningProvider::SoftSign,
+                        consensus_pubkey,
+                        signing_key,
+                    );
 
-                for chain_id in &config.chain_ids {
-                    chain_registry.add_consensus_key(chain_id, signer.clone())?;
+                    for chain_id in &config.chain_ids {
+                        chain_registry.add_validator_ecdsa_consensus_key(
+                            chain_id,
+                            config.validator.as_deref(),
+                            signer.clone(),
+                        )?;
+                    }
                 }
-            }
+            },
         }
     }
 
@@ -76,47 +108,32 @@ pub fn init(chain_registry: &mut chain::Registry, configs: &[SoftsignConfig]) ->
 }
 
 /// Load an Ed25519 key according to the provided configuration
-fn load_ed25519_key(config: &SoftsignConfig) -> Result<ed25519::Keypair, Error> {
+pub(crate) fn load_ed25519_key(config: &SoftsignConfig) -> Result<ed25519::Keypair, Error> {
     let key_format = config.key_format.as_ref().cloned().unwrap_or_default();
 
     match key_format {
-        KeyFormat::Base64 => key_utils::load_base64_ed25519_key(&config.path),
-        KeyFormat::Json => {
-            let private_key = PrivValidatorKey::load_json_file(&config.path)
-                .map_err(|e| {
-                    format_err!(
-                        ConfigError,
-                        "couldn't load `{}`: {}",
-                        config.path.as_ref().display(),
-                        e
-                    )
-                })?
-                .priv_key;
-
-            if let PrivateKey::Ed25519(pk) = private_key {
-                Ok(pk)
-            } else {
-                unreachable!("unsupported priv_validator.json algorithm");
-            }
+        KeyFormat::Base64 => {
+            key_utils::load_ed25519_key(&config.path, config.passphrase_file.as_deref())
         }
+        KeyFormat::Json => key_utils::load_json_ed25519_key(&config.path),
     }
 }
 
 /// Load a secp256k1 (ECDSA) key according to the provided configuration
-fn load_secp256k1_key(config: &SoftsignConfig) -> Result<ecdsa::SigningKey, Error> {
+pub(crate) fn load_secp256k1_key(config: &SoftsignConfig) -> Result<ecdsa::SigningKey, Error> {
     if config.key_format.unwrap_or_default() != KeyFormat::Base64 {
         fail!(
             ConfigError,
-            "[[providers.softsign]] account keys must be `base64` encoded"
+            "[[providers.softsign]] secp256k1 keys must be `base64` encoded"
         );
     }
 
-    let key_bytes = key_utils::load_base64_secret(&config.path)?;
+    let key_bytes = key_utils::load_secret(&config.path, config.passphrase_file.as_deref())?;
 
     let secret_key = ecdsa::SigningKey::from_bytes(key_bytes.as_slice()).map_err(|e| {
         format_err!(
             ConfigError,
-            "can't decode account key base64 from {}: {}",
+            "can't decode secp256k1 key base64 from {}: {}",
             config.path.as_ref().display(),
             e
         )
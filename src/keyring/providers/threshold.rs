@@ -0,0 +1,283 @@
+//! FROST threshold signing provider (experimental)
+//!
+//! Several KMS instances ("cosigners") each hold a share of an Ed25519
+//! consensus key, produced by `tmkms threshold keygen`. Whichever cosigner
+//! receives a signing request from its validator coordinates a two-round
+//! FROST signing operation with enough other cosigners to meet the
+//! threshold. Every cosigner enforces its own double-signing protection
+//! before contributing a signature share.
+
+pub mod frost;
+pub mod share;
+
+mod guard;
+mod peer;
+mod protocol;
+mod server;
+
+use self::{
+    frost::KeyShare,
+    peer::Peer,
+    protocol::{Request, Response},
+    server::Server,
+};
+use crate::{
+    chain,
+    config::provider::threshold::ThresholdConfig,
+    error::{Error, ErrorKind::*},
+    key_utils,
+    keyring::{self, SigningProvider},
+    prelude::*,
+};
+use ed25519_dalek::{Signature, Signer};
+use rand_core::{OsRng, RngCore};
+use std::{
+    collections::BTreeMap,
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+use tendermint::{PublicKey, TendermintKey};
+use tendermint_config::net;
+
+/// Default timeout for each round of a signing operation in milliseconds
+pub const DEFAULT_TIMEOUT_MS: u64 = 1000;
+
+/// Create threshold signer objects from the given configuration
+pub fn init(registry: &mut chain::Registry, configs: &[ThresholdConfig]) -> Result<(), Error> {
+    if configs.is_empty() {
+        return Ok(());
+    }
+
+    warn!("[keyring:threshold] the threshold signing provider is EXPERIMENTAL!");
+
+    for config in configs {
+        add_key(registry, config)?;
+    }
+
+    Ok(())
+}
+
+/// Start the cosigner server for the given key share and add the group key
+/// to the keyrings of the chains it's configured for
+fn add_key(registry: &mut chain::Registry, config: &ThresholdConfig) -> Result<(), Error> {
+    let key_share = Arc::new(share::load(&config.share)?);
+    let identity_key = Arc::new(key_utils::load_base64_ed25519_key(&config.identity_key)?);
+    key_utils::mlock::lock(&*key_share)?;
+    key_utils::mlock::lock(&*identity_key)?;
+    let timeout = Duration::from_millis(config.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS));
+
+    let mut peers = vec![];
+
+    for peer_config in &config.peers {
+        if peer_config.id == key_share.identifier
+            || !key_share.verifying_shares.contains_key(&peer_config.id)
+        {
+            fail!(
+                ConfigError,
+                "invalid cosigner ID in {}: {}",
+                config.share.display(),
+                peer_config.id
+            );
+        }
+
+        peers.push(Arc::new(Peer::new(
+            peer_config,
+            identity_key.clone(),
+            timeout,
+        )?));
+    }
+
+    if peers.len() + 1 < usize::from(key_share.threshold) {
+        fail!(
+            ConfigError,
+            "threshold of {} can't be met with {} configured peers",
+            key_share.threshold,
+            peers.len()
+        );
+    }
+
+    let (host, port) = match &config.listen_addr {
+        net::Address::Tcp { host, port, .. } => (host.as_str(), *port),
+        other => fail!(
+            ConfigError,
+            "cosigner listen address must be `tcp://<host>:<port>` (got {})",
+            other
+        ),
+    };
+
+    Server::bind(
+        host,
+        port,
+        key_share.clone(),
+        identity_key,
+        peers
+            .iter()
+            .map(|peer| (peer.peer_id(), peer.identifier))
+            .collect(),
+        config.chain_ids.clone(),
+        timeout,
+    )?
+    .spawn();
+
+    info!(
+        "[keyring:threshold] cosigner {} ({}-of-{}) listening on {}:{}",
+        key_share.identifier,
+        key_share.threshold,
+        key_share.verifying_shares.len(),
+        host,
+        port
+    );
+
+    let public_key =
+        PublicKey::from_raw_ed25519(key_share.group_public_key.compress().as_bytes()).unwrap();
+
+    let signing_key = SigningKey {
+        key_share,
+        peers,
+        timeout,
+    };
+
+    let signer = keyring::ed25519::Signer::new(
+        SigningProvider::Threshold,
+        TendermintKey::ConsensusKey(public_key),
+        Box::new(signing_key),
+    );
+
+    for chain_id in &config.chain_ids {
+        registry.add_consensus_key(chain_id, signer.clone())?;
+    }
+
+    Ok(())
+}
+
+/// Group signing key, used to coordinate signing operations with other
+/// cosigners
+struct SigningKey {
+    /// Our key share
+    key_share: Arc<KeyShare>,
+
+    /// Other cosigners
+    peers: Vec<Arc<Peer>>,
+
+    /// Timeout for each round of a signing operation
+    timeout: Duration,
+}
+
+impl SigningKey {
+    /// Coordinate a signing operation
+    fn sign(&self, msg: &[u8]) -> Result<[u8; 64], Error> {
+        let mut session_id = [0u8; 16];
+        OsRng.fill_bytes(&mut session_id);
+
+        let (nonces, own_commitment) = frost::commit(&self.key_share);
+
+        // Round one: collect commitments from the first cosigners to respond
+        let needed = usize::from(self.key_share.threshold) - 1;
+        let mut commitments = vec![own_commitment];
+        let mut signers = vec![];
+
+        let responses = self.broadcast(&self.peers, &Request::Commit { session_id });
+        let deadline = Instant::now() + self.timeout;
+
+        while signers.len() < needed {
+            let (peer, response) = recv(&responses, deadline).ok_or_else(|| {
+                format_err!(
+                    ThresholdError,
+                    "only {} of {} required cosigners responded",
+                    signers.len() + 1,
+                    self.key_share.threshold
+                )
+            })?;
+
+            match response {
+                Ok(Response::Commitment(commitment))
+                    if commitment.identifier == peer.identifier =>
+                {
+                    commitments.push(commitment);
+                    signers.push(peer);
+                }
+                Ok(_) => warn!(
+                    "[keyring:threshold] unexpected response from cosigner {}",
+                    peer.identifier
+                ),
+                Err(e) => warn!("[keyring:threshold] {}", e),
+            }
+        }
+
+        // Round two: collect signature shares from every cosigner in the
+        // signing set
+        let request = Request::Sign {
+            session_id,
+            sign_bytes: msg.to_vec(),
+            commitments: commitments.clone(),
+        };
+
+        let responses = self.broadcast(&signers, &request);
+        let deadline = Instant::now() + self.timeout;
+        let mut shares = BTreeMap::new();
+
+        while shares.len() < signers.len() {
+            let (peer, response) = recv(&responses, deadline).ok_or_else(|| {
+                format_err!(
+                    ThresholdError,
+                    "timed out waiting for signature shares ({} of {} received)",
+                    shares.len(),
+                    signers.len()
+                )
+            })?;
+
+            match response? {
+                Response::SignatureShare { share } => {
+                    shares.insert(peer.identifier, share);
+                }
+                _ => fail!(
+                    ThresholdError,
+                    "unexpected response from cosigner {}",
+                    peer.identifier
+                ),
+            }
+        }
+
+        let own_share = frost::sign(&self.key_share, nonces, &commitments, msg)?;
+        shares.insert(self.key_share.identifier, own_share);
+
+        frost::aggregate(&self.key_share, &commitments, &shares, msg)
+    }
+
+    /// Send a request to each of the given cosigners in parallel
+    fn broadcast(&self, peers: &[Arc<Peer>], request: &Request) -> mpsc::Receiver<PeerResponse> {
+        let (sender, receiver) = mpsc::channel();
+
+        for peer in peers {
+            let peer = peer.clone();
+            let request = request.clone();
+            let sender = sender.clone();
+
+            thread::spawn(move || {
+                let response = peer.request(&request);
+
+                // The coordinator may have stopped listening
+                let _ = sender.send((peer, response));
+            });
+        }
+
+        receiver
+    }
+}
+
+impl Signer<Signature> for SigningKey {
+    fn try_sign(&self, msg: &[u8]) -> Result<Signature, signature::Error> {
+        let signature = self.sign(msg).map_err(signature::Error::from_source)?;
+        Signature::from_bytes(&signature)
+    }
+}
+
+/// Response from a cosigner
+type PeerResponse = (Arc<Peer>, Result<Response, Error>);
+
+/// Receive the next response before the given deadline
+fn recv(responses: &mpsc::Receiver<PeerResponse>, deadline: Instant) -> Option<PeerResponse> {
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    responses.recv_timeout(timeout).ok()
+}
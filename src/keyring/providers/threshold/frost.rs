@@ -0,0 +1,527 @@
+//! FROST(Ed25519, SHA-512) threshold signatures as specified in RFC 9591.
+//!
+//! Signatures produced by a quorum of cosigners are ordinary Ed25519
+//! signatures under the group public key, so validators can't tell the
+//! difference. Keys are split by a trusted dealer (see [`split`]).
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use curve25519_dalek::{
+    constants::ED25519_BASEPOINT_TABLE,
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+    traits::Identity,
+};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use std::collections::BTreeMap;
+use zeroize::Zeroize;
+
+/// Context string of the FROST(Ed25519, SHA-512) ciphersuite
+const CONTEXT_STRING: &[u8] = b"FROST-ED25519-SHA512-v1";
+
+/// Identifier of a cosigner (i.e. the x-coordinate of its share)
+pub type Identifier = u16;
+
+/// Share of a group signing key held by a single cosigner
+#[derive(Clone)]
+pub struct KeyShare {
+    /// Identifier of this cosigner
+    pub identifier: Identifier,
+
+    /// Number of cosigners required to produce a signature
+    pub threshold: u16,
+
+    /// Secret signing share
+    pub signing_share: Scalar,
+
+    /// Group public key
+    pub group_public_key: EdwardsPoint,
+
+    /// Public verifying shares of all cosigners
+    pub verifying_shares: BTreeMap<Identifier, EdwardsPoint>,
+}
+
+impl Drop for KeyShare {
+    fn drop(&mut self) {
+        self.signing_share.zeroize();
+    }
+}
+
+/// Secret nonces generated for a single signing operation. These must never
+/// be used more than once.
+pub struct SigningNonces {
+    hiding: Scalar,
+    binding: Scalar,
+}
+
+impl SigningNonces {
+    /// Compute the public commitment to these nonces
+    fn commitment(&self, identifier: Identifier) -> Commitment {
+        Commitment {
+            identifier,
+            hiding: (&self.hiding * &ED25519_BASEPOINT_TABLE)
+                .compress()
+                .to_bytes(),
+            binding: (&self.binding * &ED25519_BASEPOINT_TABLE)
+                .compress()
+                .to_bytes(),
+        }
+    }
+}
+
+impl Drop for SigningNonces {
+    fn drop(&mut self) {
+        self.hiding.zeroize();
+        self.binding.zeroize();
+    }
+}
+
+/// Public commitment to a cosigner's nonces
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Commitment {
+    /// Identifier of the cosigner
+    pub identifier: Identifier,
+
+    /// Commitment to the hiding nonce
+    pub hiding: [u8; 32],
+
+    /// Commitment to the binding nonce
+    pub binding: [u8; 32],
+}
+
+/// Split a secret scalar into `count` shares, any `threshold` of which can
+/// produce signatures
+pub fn split(secret: &Scalar, threshold: u16, count: u16) -> Result<Vec<KeyShare>, Error> {
+    if threshold < 2 || threshold > count {
+        fail!(
+            ThresholdError,
+            "invalid threshold: {}-of-{} (threshold must be at least 2 and at most the number of shares)",
+            threshold,
+            count
+        );
+    }
+
+    // f(x) = secret + a_1 x + ... + a_{t-1} x^{t-1}
+    let mut coefficients = vec![*secret];
+    coefficients.extend((1..threshold).map(|_| random_scalar()));
+
+    let signing_shares = (1..=count)
+        .map(|identifier| {
+            let x = Scalar::from(u64::from(identifier));
+            let share = coefficients
+                .iter()
+                .rev()
+                .fold(Scalar::zero(), |acc, coefficient| acc * x + coefficient);
+            (identifier, share)
+        })
+        .collect::<Vec<_>>();
+
+    for coefficient in &mut coefficients {
+        coefficient.zeroize();
+    }
+
+    let group_public_key = secret * &ED25519_BASEPOINT_TABLE;
+
+    let verifying_shares = signing_shares
+        .iter()
+        .map(|(identifier, share)| (*identifier, share * &ED25519_BASEPOINT_TABLE))
+        .collect::<BTreeMap<_, _>>();
+
+    Ok(signing_shares
+        .into_iter()
+        .map(|(identifier, signing_share)| KeyShare {
+            identifier,
+            threshold,
+            signing_share,
+            group_public_key,
+            verifying_shares: verifying_shares.clone(),
+        })
+        .collect())
+}
+
+/// Round one: generate nonces and the corresponding public commitment
+pub fn commit(key_share: &KeyShare) -> (SigningNonces, Commitment) {
+    let nonces = SigningNonces {
+        hiding: nonce_generate(&key_share.signing_share),
+        binding: nonce_generate(&key_share.signing_share),
+    };
+
+    let commitment = nonces.commitment(key_share.identifier);
+    (nonces, commitment)
+}
+
+/// Round two: compute this cosigner's signature share over the given
+/// message, consuming its nonces
+pub fn sign(
+    key_share: &KeyShare,
+    nonces: SigningNonces,
+    commitments: &[Commitment],
+    msg: &[u8],
+) -> Result<[u8; 32], Error> {
+    check_signing_set(key_share, commitments)?;
+
+    let own_commitment = commitments
+        .iter()
+        .find(|c| c.identifier == key_share.identifier)
+        .ok_or_else(|| {
+            format_err!(
+                ThresholdError,
+                "signing set doesn't include cosigner {}",
+                key_share.identifier
+            )
+        })?;
+
+    if *own_commitment != nonces.commitment(key_share.identifier) {
+        fail!(
+            ThresholdError,
+            "commitment for cosigner {} doesn't match its nonces",
+            key_share.identifier
+        );
+    }
+
+    let signing_package = SigningPackage::new(&key_share.group_public_key, commitments, msg)?;
+    let rho = signing_package.binding_factor(key_share.identifier);
+    let lambda = signing_package.lagrange_coefficient(key_share.identifier);
+
+    let share = nonces.hiding
+        + nonces.binding * rho
+        + lambda * key_share.signing_share * signing_package.challenge;
+
+    Ok(share.to_bytes())
+}
+
+/// Aggregate signature shares into an Ed25519 signature, verifying each
+/// share against the signer's verifying share
+pub fn aggregate(
+    key_share: &KeyShare,
+    commitments: &[Commitment],
+    shares: &BTreeMap<Identifier, [u8; 32]>,
+    msg: &[u8],
+) -> Result<[u8; 64], Error> {
+    check_signing_set(key_share, commitments)?;
+
+    let signing_package = SigningPackage::new(&key_share.group_public_key, commitments, msg)?;
+    let mut z = Scalar::zero();
+
+    for (identifier, commitment) in &signing_package.commitments {
+        let share = shares
+            .get(identifier)
+            .and_then(|share| Scalar::from_canonical_bytes(*share))
+            .ok_or_else(|| {
+                format_err!(
+                    ThresholdError,
+                    "missing or malformed signature share from cosigner {}",
+                    identifier
+                )
+            })?;
+
+        let verifying_share = key_share
+            .verifying_shares
+            .get(identifier)
+            .ok_or_else(|| format_err!(ThresholdError, "unknown cosigner: {}", identifier))?;
+
+        let rho = signing_package.binding_factor(*identifier);
+        let lambda = signing_package.lagrange_coefficient(*identifier);
+
+        // z_i * B == D_i + rho_i * E_i + c * lambda_i * Y_i
+        let expected = commitment.0
+            + commitment.1 * rho
+            + verifying_share * (signing_package.challenge * lambda);
+
+        if &share * &ED25519_BASEPOINT_TABLE != expected {
+            fail!(
+                ThresholdError,
+                "invalid signature share from cosigner {}",
+                identifier
+            );
+        }
+
+        z += share;
+    }
+
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(signing_package.group_commitment.compress().as_bytes());
+    signature[32..].copy_from_slice(z.as_bytes());
+    Ok(signature)
+}
+
+/// Ensure the signing set is made up of at least `threshold` known cosigners
+fn check_signing_set(key_share: &KeyShare, commitments: &[Commitment]) -> Result<(), Error> {
+    if commitments.len() < usize::from(key_share.threshold) {
+        fail!(
+            ThresholdError,
+            "signing set has {} cosigners (threshold: {})",
+            commitments.len(),
+            key_share.threshold
+        );
+    }
+
+    for commitment in commitments {
+        if !key_share
+            .verifying_shares
+            .contains_key(&commitment.identifier)
+        {
+            fail!(
+                ThresholdError,
+                "unknown cosigner in signing set: {}",
+                commitment.identifier
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Commitments and message for a particular signing operation, along with
+/// the values derived from them
+struct SigningPackage {
+    /// Decoded commitments, by cosigner identifier
+    commitments: BTreeMap<Identifier, (EdwardsPoint, EdwardsPoint)>,
+
+    /// Binding factors, by cosigner identifier
+    binding_factors: BTreeMap<Identifier, Scalar>,
+
+    /// Group commitment (`R`)
+    group_commitment: EdwardsPoint,
+
+    /// Challenge (`c`)
+    challenge: Scalar,
+}
+
+impl SigningPackage {
+    /// Compute the binding factors, group commitment, and challenge for the
+    /// given commitments and message
+    fn new(
+        group_public_key: &EdwardsPoint,
+        commitments: &[Commitment],
+        msg: &[u8],
+    ) -> Result<Self, Error> {
+        let mut decoded = BTreeMap::new();
+
+        for commitment in commitments {
+            let hiding = decode_point(&commitment.hiding)?;
+            let binding = decode_point(&commitment.binding)?;
+
+            if decoded
+                .insert(commitment.identifier, (hiding, binding))
+                .is_some()
+            {
+                fail!(
+                    ThresholdError,
+                    "duplicate commitment from cosigner {}",
+                    commitment.identifier
+                );
+            }
+        }
+
+        let group_public_key_bytes = group_public_key.compress().to_bytes();
+
+        // encode_group_commitment_list (commitments sorted by identifier)
+        let mut encoded_commitments = vec![];
+        for (identifier, (hiding, binding)) in &decoded {
+            encoded_commitments.extend_from_slice(identifier_scalar(*identifier).as_bytes());
+            encoded_commitments.extend_from_slice(hiding.compress().as_bytes());
+            encoded_commitments.extend_from_slice(binding.compress().as_bytes());
+        }
+
+        let mut rho_input_prefix = group_public_key_bytes.to_vec();
+        rho_input_prefix.extend_from_slice(&hash(b"msg", &[msg]));
+        rho_input_prefix.extend_from_slice(&hash(b"com", &[&encoded_commitments]));
+
+        let binding_factors = decoded
+            .keys()
+            .map(|identifier| {
+                let rho = hash_to_scalar(
+                    b"rho",
+                    &[&rho_input_prefix, identifier_scalar(*identifier).as_bytes()],
+                );
+                (*identifier, rho)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let group_commitment = decoded
+            .iter()
+            .fold(EdwardsPoint::identity(), |acc, (identifier, (d, e))| {
+                acc + d + e * binding_factors[identifier]
+            });
+
+        let challenge = Scalar::from_hash(
+            Sha512::new()
+                .chain(group_commitment.compress().as_bytes())
+                .chain(group_public_key_bytes)
+                .chain(msg),
+        );
+
+        Ok(Self {
+            commitments: decoded,
+            binding_factors,
+            group_commitment,
+            challenge,
+        })
+    }
+
+    /// Get the binding factor of the given cosigner
+    fn binding_factor(&self, identifier: Identifier) -> Scalar {
+        self.binding_factors[&identifier]
+    }
+
+    /// Compute the Lagrange coefficient of the given cosigner with respect
+    /// to the signing set
+    fn lagrange_coefficient(&self, identifier: Identifier) -> Scalar {
+        let x_i = identifier_scalar(identifier);
+        let mut numerator = Scalar::one();
+        let mut denominator = Scalar::one();
+
+        for other in self.commitments.keys().filter(|&&id| id != identifier) {
+            let x_j = identifier_scalar(*other);
+            numerator *= x_j;
+            denominator *= x_j - x_i;
+        }
+
+        numerator * denominator.invert()
+    }
+}
+
+/// Generate a nonce, hedging the system RNG with the secret share
+fn nonce_generate(secret: &Scalar) -> Scalar {
+    let mut random_bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut random_bytes);
+    let nonce = hash_to_scalar(b"nonce", &[&random_bytes, secret.as_bytes()]);
+    random_bytes.zeroize();
+    nonce
+}
+
+/// Generate a uniformly random scalar
+pub fn random_scalar() -> Scalar {
+    let mut bytes = [0u8; 64];
+    OsRng.fill_bytes(&mut bytes);
+    let scalar = Scalar::from_bytes_mod_order_wide(&bytes);
+    bytes.zeroize();
+    scalar
+}
+
+/// Decode a point, rejecting the identity and points of small order
+pub fn decode_point(bytes: &[u8; 32]) -> Result<EdwardsPoint, Error> {
+    CompressedEdwardsY(*bytes)
+        .decompress()
+        .filter(|point| !point.is_small_order())
+        .ok_or_else(|| format_err!(ThresholdError, "invalid curve point").into())
+}
+
+/// Serialize an identifier as a scalar
+fn identifier_scalar(identifier: Identifier) -> Scalar {
+    Scalar::from(u64::from(identifier))
+}
+
+/// Domain-separated SHA-512 hash (`H1`, `H3`, `H4`, `H5`)
+fn hash(tag: &[u8], inputs: &[&[u8]]) -> [u8; 64] {
+    let mut hasher = Sha512::new().chain(CONTEXT_STRING).chain(tag);
+
+    for input in inputs {
+        hasher.update(input);
+    }
+
+    let mut digest = [0u8; 64];
+    digest.copy_from_slice(&hasher.finalize());
+    digest
+}
+
+/// Domain-separated hash to a scalar
+fn hash_to_scalar(tag: &[u8], inputs: &[&[u8]]) -> Scalar {
+    Scalar::from_bytes_mod_order_wide(&hash(tag, inputs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{ExpandedSecretKey, PublicKey, SecretKey, Signature, Verifier};
+
+    const MSG: &[u8] = b"tmkms threshold test";
+
+    /// Sign `MSG` with the given subset of shares
+    fn threshold_sign(shares: &[KeyShare], signers: &[Identifier]) -> Result<[u8; 64], Error> {
+        let signers = shares
+            .iter()
+            .filter(|share| signers.contains(&share.identifier))
+            .collect::<Vec<_>>();
+
+        let (nonces, commitments): (Vec<_>, Vec<_>) =
+            signers.iter().map(|share| commit(share)).unzip();
+
+        let mut signature_shares = BTreeMap::new();
+
+        for (share, nonces) in signers.iter().zip(nonces) {
+            signature_shares.insert(share.identifier, sign(share, nonces, &commitments, MSG)?);
+        }
+
+        aggregate(signers[0], &commitments, &signature_shares, MSG)
+    }
+
+    #[test]
+    fn signatures_verify_as_ed25519() {
+        let shares = split(&random_scalar(), 2, 3).unwrap();
+        let public_key =
+            PublicKey::from_bytes(shares[0].group_public_key.compress().as_bytes()).unwrap();
+
+        for signers in &[[1, 2], [1, 3], [2, 3]] {
+            let signature = threshold_sign(&shares, signers).unwrap();
+            public_key
+                .verify(MSG, &Signature::from_bytes(&signature).unwrap())
+                .unwrap();
+        }
+
+        let signature = threshold_sign(&shares, &[1, 2, 3]).unwrap();
+        public_key
+            .verify(MSG, &Signature::from_bytes(&signature).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn split_existing_ed25519_key() {
+        let secret_key = SecretKey::from_bytes(&[0x42; 32]).unwrap();
+        let public_key = PublicKey::from(&secret_key);
+        let expanded = ExpandedSecretKey::from(&secret_key).to_bytes();
+
+        let mut scalar_bytes = [0u8; 32];
+        scalar_bytes.copy_from_slice(&expanded[..32]);
+        let shares = split(&Scalar::from_bytes_mod_order(scalar_bytes), 3, 5).unwrap();
+
+        assert_eq!(
+            shares[0].group_public_key.compress().as_bytes(),
+            public_key.as_bytes()
+        );
+
+        let signature = threshold_sign(&shares, &[2, 4, 5]).unwrap();
+        public_key
+            .verify(MSG, &Signature::from_bytes(&signature).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn reject_invalid_share() {
+        let shares = split(&random_scalar(), 2, 3).unwrap();
+        let (nonces1, commitment1) = commit(&shares[0]);
+        let (nonces2, commitment2) = commit(&shares[1]);
+        let commitments = [commitment1, commitment2];
+
+        let mut signature_shares = BTreeMap::new();
+        signature_shares.insert(1, sign(&shares[0], nonces1, &commitments, MSG).unwrap());
+        signature_shares.insert(
+            2,
+            sign(&shares[1], nonces2, &commitments, b"other").unwrap(),
+        );
+
+        let err = aggregate(&shares[0], &commitments, &signature_shares, MSG).unwrap_err();
+        assert_eq!(*err.kind(), ThresholdError);
+    }
+
+    #[test]
+    fn reject_invalid_threshold() {
+        assert!(split(&random_scalar(), 1, 3).is_err());
+        assert!(split(&random_scalar(), 4, 3).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+//! Checks performed by a cosigner before contributing a signature share.
+//!
+//! Cosigners don't trust the coordinator's view of the consensus state:
+//! they decode the canonical sign bytes themselves and enforce their own
+//! double-signing protection, exactly as if they'd received the request from
+//! a validator directly.
+
+use crate::{
+    amino_types::SignedMsgType,
+    chain::{self, state::Step},
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    rpc::v0_38::CanonicalVoteExtension,
+};
+use prost::Message;
+use std::convert::TryFrom;
+use tendermint::{block, consensus};
+use tendermint_proto::types::{CanonicalProposal, CanonicalVote};
+
+/// Message a cosigner has been asked to sign
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SignBytes {
+    /// Proposal or vote
+    Consensus {
+        /// Chain the message is for
+        chain_id: chain::Id,
+
+        /// Consensus state implied by signing the message
+        state: consensus::State,
+    },
+
+    /// Vote extension
+    VoteExtension {
+        /// Chain the message is for
+        chain_id: chain::Id,
+
+        /// Height of the extended precommit
+        height: block::Height,
+
+        /// Round of the extended precommit
+        round: block::Round,
+    },
+}
+
+impl SignBytes {
+    /// Parse canonical Protobuf-encoded sign bytes, ensuring they re-encode
+    /// to exactly the same bytes
+    pub fn parse(sign_bytes: &[u8]) -> Result<Self, Error> {
+        if let Ok(proposal) = CanonicalProposal::decode_length_delimited(sign_bytes) {
+            if proposal.r#type == SignedMsgType::Proposal.to_u32() as i32
+                && encode(&proposal) == sign_bytes
+            {
+                return Ok(SignBytes::Consensus {
+                    chain_id: parse_chain_id(&proposal.chain_id)?,
+                    state: consensus_state(
+                        proposal.height,
+                        proposal.round,
+                        Step::Propose,
+                        proposal.block_id,
+                    )?,
+                });
+            }
+        }
+
+        if let Ok(vote) = CanonicalVote::decode_length_delimited(sign_bytes) {
+            let step = if vote.r#type == SignedMsgType::PreVote.to_u32() as i32 {
+                Some(Step::Prevote)
+            } else if vote.r#type == SignedMsgType::PreCommit.to_u32() as i32 {
+                Some(Step::Precommit)
+            } else {
+                None
+            };
+
+            if let Some(step) = step.filter(|_| encode(&vote) == sign_bytes) {
+                return Ok(SignBytes::Consensus {
+                    chain_id: parse_chain_id(&vote.chain_id)?,
+                    state: consensus_state(vote.height, vote.round, step, vote.block_id)?,
+                });
+            }
+        }
+
+        if let Ok(extension) = CanonicalVoteExtension::decode_length_delimited(sign_bytes) {
+            if encode(&extension) == sign_bytes {
+                return Ok(SignBytes::VoteExtension {
+                    chain_id: parse_chain_id(&extension.chain_id)?,
+                    height: parse_height(extension.height)?,
+                    round: parse_round(extension.round)?,
+                });
+            }
+        }
+
+        fail!(
+            ThresholdError,
+            "cosigners only sign canonical Protobuf-encoded proposals, votes, and vote extensions"
+        );
+    }
+
+    /// Get the chain this message is for
+    pub fn chain_id(&self) -> &chain::Id {
+        match self {
+            SignBytes::Consensus { chain_id, .. } | SignBytes::VoteExtension { chain_id, .. } => {
+                chain_id
+            }
+        }
+    }
+
+    /// Check the message against the chain's consensus state, updating it
+    /// for proposals and votes. Fails if signing would be a double sign.
+    pub fn authorize(&self, chain: &chain::Chain) -> Result<(), Error> {
+        let mut chain_state = chain.state.lock().unwrap();
+
+        match self {
+            SignBytes::Consensus { state, .. } => {
+                chain_state
+                    .update_consensus_state(state.clone())
+                    .map_err(|e| {
+                        format_err!(
+                            DoubleSign,
+                            "[{}] refusing to sign at h/r/s {}: {}",
+                            chain.id,
+                            state,
+                            e
+                        )
+                    })?;
+            }
+            SignBytes::VoteExtension { height, round, .. } => {
+                // Extensions are only signed immediately after the precommit
+                // for a block they extend
+                let state = chain_state.consensus_state();
+
+                if state.height != *height
+                    || state.round != *round
+                    || state.step != Step::Precommit.value()
+                    || state.block_id.is_none()
+                {
+                    fail!(
+                        DoubleSign,
+                        "[{}] refusing to sign vote extension at {}/{}: last signed {}",
+                        chain.id,
+                        height,
+                        round,
+                        state
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Re-encode a message in its canonical length-delimited form
+fn encode<M: Message>(message: &M) -> Vec<u8> {
+    let mut bytes = vec![];
+    message.encode_length_delimited(&mut bytes).unwrap();
+    bytes
+}
+
+/// Build the consensus state implied by signing a proposal or vote
+fn consensus_state(
+    height: i64,
+    round: i64,
+    step: Step,
+    block_id: Option<tendermint_proto::types::CanonicalBlockId>,
+) -> Result<consensus::State, Error> {
+    let block_id = block_id
+        .map(block::Id::try_from)
+        .transpose()
+        .map_err(|e| format_err!(ThresholdError, "invalid block ID: {}", e))?;
+
+    Ok(consensus::State {
+        height: parse_height(height)?,
+        round: parse_round(round)?,
+        step: step.value(),
+        block_id,
+    })
+}
+
+/// Parse a chain ID
+fn parse_chain_id(chain_id: &str) -> Result<chain::Id, Error> {
+    chain_id
+        .parse()
+        .map_err(|e| format_err!(ThresholdError, "invalid chain ID: {}", e).into())
+}
+
+/// Parse a block height
+fn parse_height(height: i64) -> Result<block::Height, Error> {
+    block::Height::try_from(height)
+        .map_err(|e| format_err!(ThresholdError, "invalid height: {}", e).into())
+}
+
+/// Parse a consensus round
+fn parse_round(round: i64) -> Result<block::Round, Error> {
+    i32::try_from(round)
+        .ok()
+        .and_then(|round| block::Round::try_from(round).ok())
+        .ok_or_else(|| format_err!(ThresholdError, "invalid round: {}", round).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        amino_types::{
+            block_id::{BlockId, PartsSetHeader},
+            vote::{SignVoteRequest, Vote},
+            SignableMsg,
+        },
+        config::validator::ProtocolVersion,
+    };
+
+    const CHAIN_ID: &str = "test-chain-4UIOL";
+
+    fn precommit(extension: &[u8]) -> SignVoteRequest {
+        SignVoteRequest {
+            vote: Some(Vote {
+                vote_type: SignedMsgType::PreCommit.to_u32(),
+                height: 12345,
+                round: 2,
+                block_id: Some(BlockId {
+                    hash: vec![0xAB; 32],
+                    parts_header: Some(PartsSetHeader::new(1, vec![0xCD; 32])),
+                }),
+                extension: extension.to_vec(),
+                ..Default::default()
+            }),
+            chain_id: CHAIN_ID.to_owned(),
+        }
+    }
+
+    #[test]
+    fn parse_vote() {
+        let mut sign_bytes = vec![];
+        precommit(b"")
+            .sign_bytes(
+                CHAIN_ID.parse().unwrap(),
+                ProtocolVersion::V0_38,
+                &mut sign_bytes,
+            )
+            .unwrap();
+
+        match SignBytes::parse(&sign_bytes).unwrap() {
+            SignBytes::Consensus { chain_id, state } => {
+                assert_eq!(chain_id.as_str(), CHAIN_ID);
+                assert_eq!(state.height.value(), 12345);
+                assert_eq!(state.round.value(), 2);
+                assert_eq!(state.step, Step::Precommit.value());
+                assert!(state.block_id.is_some());
+            }
+            other => panic!("unexpected sign bytes: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_vote_extension() {
+        let mut sign_bytes = vec![];
+        assert!(precommit(b"extension")
+            .extension_sign_bytes(
+                CHAIN_ID.parse().unwrap(),
+                ProtocolVersion::V0_38,
+                &mut sign_bytes
+            )
+            .unwrap());
+
+        assert_eq!(
+            SignBytes::parse(&sign_bytes).unwrap(),
+            SignBytes::VoteExtension {
+                chain_id: CHAIN_ID.parse().unwrap(),
+                height: 12345u32.into(),
+                round: 2u16.into(),
+            }
+        );
+    }
+
+    #[test]
+    fn reject_arbitrary_bytes() {
+        assert!(SignBytes::parse(b"not a vote").is_err());
+
+        let mut sign_bytes = vec![];
+        precommit(b"")
+            .sign_bytes(
+                CHAIN_ID.parse().unwrap(),
+                ProtocolVersion::V0_34,
+                &mut sign_bytes,
+            )
+            .unwrap();
+        sign_bytes.push(0);
+        assert!(SignBytes::parse(&sign_bytes).is_err());
+
+        // Legacy Amino-encoded sign bytes aren't supported
+        let mut sign_bytes = vec![];
+        precommit(b"")
+            .sign_bytes(
+                CHAIN_ID.parse().unwrap(),
+                ProtocolVersion::Legacy,
+                &mut sign_bytes,
+            )
+            .unwrap();
+        assert!(SignBytes::parse(&sign_bytes).is_err());
+    }
+}
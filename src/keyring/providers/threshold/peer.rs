@@ -0,0 +1,169 @@
+//! Connections to other cosigners
+
+use super::{
+    frost::Identifier,
+    protocol::{self, Request, Response},
+};
+use crate::{
+    config::{provider::threshold::PeerConfig, PeerIdVerification},
+    connection::tcp,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use ed25519_dalek as ed25519;
+use std::{
+    net::TcpStream,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tendermint::node;
+use tendermint_config::net;
+use tendermint_p2p::secret_connection::{self, SecretConnection};
+use zeroize::Zeroizing;
+
+/// Another cosigner, which we connect to as the coordinator of a signing
+/// operation
+pub struct Peer {
+    /// Identifier of the cosigner's key share
+    pub identifier: Identifier,
+
+    /// Hostname or IP address of the cosigner
+    host: String,
+
+    /// Port of the cosigner
+    port: u16,
+
+    /// Expected node ID of the cosigner
+    peer_id: node::Id,
+
+    /// Our own Secret Connection identity key
+    identity_key: Arc<ed25519::Keypair>,
+
+    /// Connect/read/write timeout
+    timeout: Duration,
+
+    /// Open connection to the cosigner (if any)
+    connection: Mutex<Option<SecretConnection<TcpStream>>>,
+}
+
+impl Peer {
+    /// Create a new peer from the given configuration
+    pub fn new(
+        config: &PeerConfig,
+        identity_key: Arc<ed25519::Keypair>,
+        timeout: Duration,
+    ) -> Result<Self, Error> {
+        match &config.addr {
+            net::Address::Tcp {
+                peer_id: Some(peer_id),
+                host,
+                port,
+            } => Ok(Self {
+                identifier: config.id,
+                host: host.clone(),
+                port: *port,
+                peer_id: *peer_id,
+                identity_key,
+                timeout,
+                connection: Mutex::new(None),
+            }),
+            other => fail!(
+                ConfigError,
+                "cosigner {} address must be `tcp://<node ID>@<host>:<port>` (got {})",
+                config.id,
+                other
+            ),
+        }
+    }
+
+    /// Get the node ID of this cosigner
+    pub fn peer_id(&self) -> node::Id {
+        self.peer_id
+    }
+
+    /// Send a request to the cosigner and wait for its response.
+    ///
+    /// If a previously opened connection turns out to be broken, it's
+    /// discarded and the request is retried once on a fresh connection.
+    pub fn request(&self, request: &Request) -> Result<Response, Error> {
+        let mut connection = self.connection.lock().unwrap();
+        let reused = connection.is_some();
+
+        let result = self.exchange(&mut connection, request);
+
+        let result = match result {
+            Err(e) if reused => {
+                debug!(
+                    "[threshold] reconnecting to cosigner {} ({}:{}): {}",
+                    self.identifier, self.host, self.port, e
+                );
+                self.exchange(&mut connection, request)
+            }
+            other => other,
+        };
+
+        match result? {
+            Response::Error { message } => fail!(
+                ThresholdError,
+                "cosigner {} ({}:{}) refused request: {}",
+                self.identifier,
+                self.host,
+                self.port,
+                message
+            ),
+            response => Ok(response),
+        }
+    }
+
+    /// Send a request and read the response, opening a connection first if
+    /// necessary. The connection is discarded on error.
+    fn exchange(
+        &self,
+        connection: &mut Option<SecretConnection<TcpStream>>,
+        request: &Request,
+    ) -> Result<Response, Error> {
+        if connection.is_none() {
+            *connection = Some(self.connect()?);
+        }
+
+        let conn = connection.as_mut().unwrap();
+        let result =
+            protocol::write_message(conn, request).and_then(|_| protocol::read_message(conn));
+
+        if result.is_err() {
+            *connection = None;
+        }
+
+        result
+    }
+
+    /// Open a new connection to the cosigner
+    fn connect(&self) -> Result<SecretConnection<TcpStream>, Error> {
+        let socket = tcp::connect(&self.host, self.port, self.timeout).map_err(|e| {
+            format_err!(
+                ThresholdError,
+                "couldn't connect to cosigner {} ({}:{}): {}",
+                self.identifier,
+                self.host,
+                self.port,
+                e
+            )
+        })?;
+
+        tcp::handshake(
+            socket,
+            clone_keypair(&self.identity_key),
+            &Some(self.peer_id),
+            PeerIdVerification::Enforce,
+            self.timeout,
+            secret_connection::Version::V0_34,
+            &format!("{}:{}", self.host, self.port),
+        )
+    }
+}
+
+/// Make an owned copy of an identity key (which `SecretConnection` consumes)
+pub(super) fn clone_keypair(keypair: &ed25519::Keypair) -> ed25519::Keypair {
+    let bytes = Zeroizing::new(keypair.to_bytes());
+    ed25519::Keypair::from_bytes(&*bytes).unwrap()
+}
@@ -0,0 +1,171 @@
+//! Messages exchanged between cosigners.
+//!
+//! Messages are JSON-encoded and prefixed with their length as a 32-bit
+//! big endian integer. They're sent over Secret Connection, which
+//! authenticates cosigners by their identity keys.
+
+use super::frost::Commitment;
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use tendermint_p2p::secret_connection::DATA_MAX_SIZE;
+
+/// Maximum size of a message
+pub const MAX_MESSAGE_SIZE: usize = 65536;
+
+/// Request from the coordinating cosigner
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Request {
+    /// Round one: generate nonces for a new signing session and return
+    /// commitments to them
+    Commit {
+        /// Random ID of the signing session, chosen by the coordinator
+        session_id: [u8; 16],
+    },
+
+    /// Round two: sign the given message using the nonces generated for the
+    /// given session
+    Sign {
+        /// ID of the signing session
+        session_id: [u8; 16],
+
+        /// Canonical sign bytes of the message to sign
+        sign_bytes: Vec<u8>,
+
+        /// Commitments of all cosigners in the signing set
+        commitments: Vec<Commitment>,
+    },
+}
+
+/// Response from a cosigner
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    /// Commitments to the cosigner's nonces
+    Commitment(Commitment),
+
+    /// Signature share
+    SignatureShare {
+        /// Serialized signature share scalar
+        share: [u8; 32],
+    },
+
+    /// The cosigner refused or failed to process the request
+    Error {
+        /// Description of the error
+        message: String,
+    },
+}
+
+/// Write a length-prefixed message
+pub fn write_message<W: Write, M: Serialize>(writer: &mut W, message: &M) -> Result<(), Error> {
+    let bytes = serde_json::to_vec(message)?;
+
+    if bytes.len() > MAX_MESSAGE_SIZE {
+        fail!(
+            ThresholdError,
+            "message too large: {} bytes (max {})",
+            bytes.len(),
+            MAX_MESSAGE_SIZE
+        );
+    }
+
+    let mut frame = (bytes.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(&bytes);
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read a length-prefixed message
+pub fn read_message<R: Read, M: DeserializeOwned>(reader: &mut R) -> Result<M, Error> {
+    // Secret Connection needs room for an entire frame on every read, so read
+    // frame-sized chunks until the whole message has arrived
+    let mut bytes = vec![];
+    let mut chunk = vec![0u8; DATA_MAX_SIZE];
+
+    let len = loop {
+        let nbytes = reader.read(&mut chunk)?;
+
+        if nbytes == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        bytes.extend_from_slice(&chunk[..nbytes]);
+
+        if bytes.len() < 4 {
+            continue;
+        }
+
+        let mut len = [0u8; 4];
+        len.copy_from_slice(&bytes[..4]);
+        let len = u32::from_be_bytes(len) as usize;
+
+        if len > MAX_MESSAGE_SIZE {
+            fail!(
+                ThresholdError,
+                "message too large: {} bytes (max {})",
+                len,
+                MAX_MESSAGE_SIZE
+            );
+        }
+
+        if bytes.len() >= 4 + len {
+            break len;
+        }
+    };
+
+    if bytes.len() != 4 + len {
+        fail!(ThresholdError, "unexpected data after cosigner message");
+    }
+
+    serde_json::from_slice(&bytes[4..])
+        .map_err(|e| format_err!(ThresholdError, "malformed cosigner message: {}", e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut buf = vec![];
+        let request = Request::Sign {
+            session_id: [1; 16],
+            sign_bytes: b"sign bytes".to_vec(),
+            commitments: vec![Commitment {
+                identifier: 2,
+                hiding: [3; 32],
+                binding: [4; 32],
+            }],
+        };
+
+        write_message(&mut buf, &request).unwrap();
+
+        match read_message(&mut buf.as_slice()).unwrap() {
+            Request::Sign {
+                session_id,
+                sign_bytes,
+                commitments,
+            } => {
+                assert_eq!(session_id, [1; 16]);
+                assert_eq!(sign_bytes, b"sign bytes");
+                assert_eq!(commitments[0].identifier, 2);
+            }
+            other => panic!("unexpected request: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reject_oversized_message() {
+        let mut frame = (MAX_MESSAGE_SIZE as u32 + 1).to_be_bytes().to_vec();
+        frame.extend_from_slice(b"{}");
+
+        let err = read_message::<_, Request>(&mut frame.as_slice()).unwrap_err();
+        assert_eq!(*err.kind(), ThresholdError);
+    }
+}
@@ -0,0 +1,251 @@
+//! Server which answers requests from coordinating cosigners
+
+use super::{
+    frost::{self, Identifier, KeyShare, SigningNonces},
+    guard::SignBytes,
+    peer::clone_keypair,
+    protocol::{self, Request, Response},
+};
+use crate::{
+    chain,
+    config::PeerIdVerification,
+    connection::tcp,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use ed25519_dalek as ed25519;
+use std::{
+    collections::VecDeque,
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+use tendermint::node;
+use tendermint_p2p::secret_connection::{self, SecretConnection};
+
+/// Maximum number of signing sessions a coordinator may have outstanding on
+/// a single connection. Nonces for older sessions are discarded.
+const MAX_PENDING_SESSIONS: usize = 16;
+
+/// Cosigner server
+pub struct Server {
+    /// Listener for connections from other cosigners
+    listener: TcpListener,
+
+    /// Our key share
+    key_share: Arc<KeyShare>,
+
+    /// Our Secret Connection identity key
+    identity_key: Arc<ed25519::Keypair>,
+
+    /// Node IDs of cosigners allowed to connect, along with their identifiers
+    peers: Vec<(node::Id, Identifier)>,
+
+    /// Chains we're allowed to sign for
+    chain_ids: Vec<chain::Id>,
+
+    /// Handshake timeout
+    timeout: Duration,
+}
+
+impl Server {
+    /// Bind a new server to the given host and port
+    pub fn bind(
+        host: &str,
+        port: u16,
+        key_share: Arc<KeyShare>,
+        identity_key: Arc<ed25519::Keypair>,
+        peers: Vec<(node::Id, Identifier)>,
+        chain_ids: Vec<chain::Id>,
+        timeout: Duration,
+    ) -> Result<Self, Error> {
+        let listener = TcpListener::bind((host, port)).map_err(|e| {
+            format_err!(
+                ThresholdError,
+                "couldn't listen for cosigners on {}:{}: {}",
+                host,
+                port,
+                e
+            )
+        })?;
+
+        Ok(Self {
+            listener,
+            key_share,
+            identity_key,
+            peers,
+            chain_ids,
+            timeout,
+        })
+    }
+
+    /// Accept connections from other cosigners in a background thread
+    pub fn spawn(self) {
+        thread::spawn(move || {
+            let server = Arc::new(self);
+
+            for socket in server.listener.incoming() {
+                match socket {
+                    Ok(socket) => {
+                        let server = server.clone();
+                        thread::spawn(move || server.handle_connection(socket));
+                    }
+                    Err(e) => warn!("[threshold] error accepting cosigner connection: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Handle requests from a single coordinator until it disconnects
+    fn handle_connection(&self, socket: TcpStream) {
+        let remote_addr = socket
+            .peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|_| "unknown".to_owned());
+
+        let (mut connection, identifier) = match self.accept(socket, &remote_addr) {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!(
+                    "[threshold] rejected connection from {}: {}",
+                    remote_addr, e
+                );
+                return;
+            }
+        };
+
+        debug!(
+            "[threshold] cosigner {} connected from {}",
+            identifier, remote_addr
+        );
+
+        let mut pending = VecDeque::new();
+
+        loop {
+            let request = match protocol::read_message(&mut connection) {
+                Ok(request) => request,
+                Err(e) => {
+                    debug!("[threshold] cosigner {} disconnected: {}", identifier, e);
+                    return;
+                }
+            };
+
+            let response = self
+                .handle_request(request, &mut pending)
+                .unwrap_or_else(|e| {
+                    warn!(
+                        "[threshold] refused request from cosigner {}: {}",
+                        identifier, e
+                    );
+                    Response::Error {
+                        message: e.to_string(),
+                    }
+                });
+
+            if let Err(e) = protocol::write_message(&mut connection, &response) {
+                debug!("[threshold] cosigner {} disconnected: {}", identifier, e);
+                return;
+            }
+        }
+    }
+
+    /// Perform the Secret Connection handshake, ensuring the remote party is
+    /// a configured cosigner
+    fn accept(
+        &self,
+        socket: TcpStream,
+        remote_addr: &str,
+    ) -> Result<(SecretConnection<TcpStream>, Identifier), Error> {
+        // Coordinators keep connections open between signing operations, so
+        // the read timeout only applies to the handshake
+        let control = socket.try_clone()?;
+
+        let connection = tcp::handshake(
+            socket,
+            clone_keypair(&self.identity_key),
+            &None,
+            PeerIdVerification::Enforce,
+            self.timeout,
+            secret_connection::Version::V0_34,
+            remote_addr,
+        )?;
+
+        control.set_read_timeout(None)?;
+
+        let peer_id = connection.remote_pubkey().peer_id();
+
+        let identifier = self
+            .peers
+            .iter()
+            .find(|(id, _)| *id == peer_id)
+            .map(|(_, identifier)| *identifier)
+            .ok_or_else(|| {
+                format_err!(VerificationError, "unknown cosigner node ID: {}", peer_id)
+            })?;
+
+        Ok((connection, identifier))
+    }
+
+    /// Handle a request from a coordinator
+    fn handle_request(
+        &self,
+        request: Request,
+        pending: &mut VecDeque<([u8; 16], SigningNonces)>,
+    ) -> Result<Response, Error> {
+        match request {
+            Request::Commit { session_id } => {
+                let (nonces, commitment) = frost::commit(&self.key_share);
+
+                if pending.len() >= MAX_PENDING_SESSIONS {
+                    pending.pop_front();
+                }
+
+                pending.push_back((session_id, nonces));
+                Ok(Response::Commitment(commitment))
+            }
+            Request::Sign {
+                session_id,
+                sign_bytes,
+                commitments,
+            } => {
+                // Nonces are single-use: remove them before doing anything else
+                let position = pending
+                    .iter()
+                    .position(|(id, _)| *id == session_id)
+                    .ok_or_else(|| format_err!(ThresholdError, "unknown signing session"))?;
+
+                let (_, nonces) = pending.remove(position).unwrap();
+
+                self.authorize(&sign_bytes)?;
+
+                let share = frost::sign(&self.key_share, nonces, &commitments, &sign_bytes)?;
+                Ok(Response::SignatureShare { share })
+            }
+        }
+    }
+
+    /// Enforce our own double-signing protection for the given sign bytes
+    fn authorize(&self, sign_bytes: &[u8]) -> Result<(), Error> {
+        let msg = SignBytes::parse(sign_bytes)?;
+
+        if !self.chain_ids.contains(msg.chain_id()) {
+            fail!(
+                ThresholdError,
+                "not authorized to sign for chain: {}",
+                msg.chain_id()
+            );
+        }
+
+        let registry = chain::REGISTRY.get();
+
+        let chain = registry
+            .get_chain(msg.chain_id())
+            .ok_or_else(|| format_err!(ThresholdError, "unknown chain: {}", msg.chain_id()))?;
+
+        msg.authorize(chain)?;
+
+        debug!("[threshold] authorized signature share: {:?}", msg);
+        Ok(())
+    }
+}
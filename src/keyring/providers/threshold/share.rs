@@ -0,0 +1,196 @@
+//! Key share files produced by `tmkms threshold keygen`
+
+use super::frost::{self, Identifier, KeyShare};
+use crate::{
+    error::{Error, ErrorKind::*},
+    key_utils::SECRET_FILE_PERMS,
+    prelude::*,
+};
+use curve25519_dalek::{constants::ED25519_BASEPOINT_TABLE, scalar::Scalar};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+use subtle_encoding::base64;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Serialized key share
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ShareFile {
+    /// Identifier of the cosigner holding this share
+    identifier: Identifier,
+
+    /// Number of cosigners required to produce a signature
+    threshold: u16,
+
+    /// Base64-encoded group public key
+    group_public_key: String,
+
+    /// Base64-encoded secret signing share
+    signing_share: String,
+
+    /// Base64-encoded verifying shares of all cosigners
+    verifying_shares: BTreeMap<Identifier, String>,
+}
+
+impl Drop for ShareFile {
+    fn drop(&mut self) {
+        self.signing_share.zeroize();
+    }
+}
+
+/// Load a key share from the given path
+pub fn load(path: &Path) -> Result<KeyShare, Error> {
+    let contents = Zeroizing::new(fs::read_to_string(path).map_err(|e| {
+        format_err!(
+            IoError,
+            "couldn't read key share from {}: {}",
+            path.display(),
+            e
+        )
+    })?);
+
+    let file: ShareFile = serde_json::from_str(&contents).map_err(|e| {
+        format_err!(
+            ParseError,
+            "malformed key share in {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+
+    let signing_share = Scalar::from_canonical_bytes(decode32(path, &file.signing_share)?)
+        .ok_or_else(|| format_err!(InvalidKey, "invalid signing share in {}", path.display()))?;
+
+    let mut verifying_shares = BTreeMap::new();
+
+    for (identifier, verifying_share) in &file.verifying_shares {
+        verifying_shares.insert(
+            *identifier,
+            frost::decode_point(&decode32(path, verifying_share)?)?,
+        );
+    }
+
+    let key_share = KeyShare {
+        identifier: file.identifier,
+        threshold: file.threshold,
+        signing_share,
+        group_public_key: frost::decode_point(&decode32(path, &file.group_public_key)?)?,
+        verifying_shares,
+    };
+
+    let own_verifying_share = key_share.verifying_shares.get(&key_share.identifier);
+
+    if own_verifying_share != Some(&(&key_share.signing_share * &ED25519_BASEPOINT_TABLE)) {
+        fail!(
+            InvalidKey,
+            "signing share in {} doesn't match its verifying share",
+            path.display()
+        );
+    }
+
+    if usize::from(key_share.threshold) > key_share.verifying_shares.len() {
+        fail!(
+            InvalidKey,
+            "key share in {} has a threshold of {} but only {} cosigners",
+            path.display(),
+            key_share.threshold,
+            key_share.verifying_shares.len()
+        );
+    }
+
+    Ok(key_share)
+}
+
+/// Write a key share to the given path
+pub fn write(path: &Path, key_share: &KeyShare) -> Result<(), Error> {
+    let file = ShareFile {
+        identifier: key_share.identifier,
+        threshold: key_share.threshold,
+        group_public_key: encode(key_share.group_public_key.compress().as_bytes()),
+        signing_share: encode(key_share.signing_share.as_bytes()),
+        verifying_shares: key_share
+            .verifying_shares
+            .iter()
+            .map(|(identifier, point)| (*identifier, encode(point.compress().as_bytes())))
+            .collect(),
+    };
+
+    let json = Zeroizing::new(serde_json::to_string_pretty(&file).unwrap());
+
+    OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .mode(SECRET_FILE_PERMS)
+        .open(path)
+        .and_then(|mut f| f.write_all(json.as_bytes()))
+        .map_err(|e| format_err!(IoError, "couldn't write `{}`: {}", path.display(), e).into())
+}
+
+/// Base64-encode the given data
+fn encode(bytes: &[u8]) -> String {
+    String::from_utf8(base64::encode(bytes)).unwrap()
+}
+
+/// Decode a Base64-encoded 32-byte value
+fn decode32(path: &Path, data: &str) -> Result<[u8; 32], Error> {
+    let bytes = Zeroizing::new(base64::decode(data).map_err(|e| {
+        format_err!(
+            ParseError,
+            "malformed key share in {}: {}",
+            path.display(),
+            e
+        )
+    })?);
+
+    if bytes.len() != 32 {
+        fail!(
+            ParseError,
+            "malformed key share in {}: expected 32-byte values",
+            path.display()
+        );
+    }
+
+    let mut array = [0u8; 32];
+    array.copy_from_slice(&bytes);
+    Ok(array)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let shares = frost::split(&frost::random_scalar(), 2, 3).unwrap();
+
+        for share in &shares {
+            let path = dir.path().join(format!("share-{}.json", share.identifier));
+            write(&path, share).unwrap();
+
+            let loaded = load(&path).unwrap();
+            assert_eq!(loaded.identifier, share.identifier);
+            assert_eq!(loaded.threshold, 2);
+            assert_eq!(loaded.signing_share, share.signing_share);
+            assert_eq!(loaded.group_public_key, share.group_public_key);
+            assert_eq!(loaded.verifying_shares, share.verifying_shares);
+        }
+    }
+
+    #[test]
+    fn reject_mismatched_share() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut shares = frost::split(&frost::random_scalar(), 2, 3).unwrap();
+        shares[0].signing_share = shares[1].signing_share;
+
+        let path = dir.path().join("share-1.json");
+        write(&path, &shares[0]).unwrap();
+        assert_eq!(*load(&path).err().unwrap().kind(), InvalidKey);
+    }
+}
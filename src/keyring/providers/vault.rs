@@ -0,0 +1,101 @@
+//! HashiCorp Vault Transit signing provider
+//!
+//! Signs using `ed25519` keys held by Vault's Transit secrets engine, so raw
+//! consensus keys never need to be present on the KMS host.
+
+mod client;
+
+use self::client::VaultClient;
+use crate::{
+    chain,
+    config::provider::vault::{SigningKeyConfig, VaultConfig},
+    error::{Error, ErrorKind::*},
+    keyring::{self, SigningProvider},
+    prelude::*,
+};
+use ed25519_dalek::{Signature, Signer};
+use std::sync::Arc;
+use tendermint::{PublicKey, TendermintKey};
+
+/// Create Vault Transit backed signer objects from the given configuration
+pub fn init(registry: &mut chain::Registry, configs: &[VaultConfig]) -> Result<(), Error> {
+    if configs.is_empty() {
+        return Ok(());
+    }
+
+    for config in configs {
+        let client = Arc::new(VaultClient::new(config)?);
+
+        for key in &config.signing_keys {
+            add_key(registry, key, client.clone())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Add a Transit signing key to the keyrings of the chains it's configured for
+fn add_key(
+    registry: &mut chain::Registry,
+    config: &SigningKeyConfig,
+    client: Arc<VaultClient>,
+) -> Result<(), Error> {
+    let (key_version, public_key) = client.public_key(&config.key_name).map_err(|e| {
+        format_err!(
+            VaultError,
+            "couldn't get Transit key `{}`: {}",
+            config.key_name,
+            e
+        )
+    })?;
+
+    let public_key = PublicKey::from_raw_ed25519(&public_key).ok_or_else(|| {
+        format_err!(
+            VaultError,
+            "invalid Ed25519 public key for Transit key `{}`",
+            config.key_name
+        )
+    })?;
+
+    let signing_key = SigningKey {
+        client,
+        key_name: config.key_name.clone(),
+        key_version,
+    };
+
+    let signer = keyring::ed25519::Signer::new(
+        SigningProvider::Vault,
+        TendermintKey::ConsensusKey(public_key),
+        Box::new(signing_key),
+    );
+
+    for chain_id in &config.chain_ids {
+        registry.add_consensus_key(chain_id, signer.clone())?;
+    }
+
+    Ok(())
+}
+
+/// Ed25519 signing key stored in Vault's Transit secrets engine
+struct SigningKey {
+    /// Vault client
+    client: Arc<VaultClient>,
+
+    /// Name of the Transit key
+    key_name: String,
+
+    /// Version of the Transit key to sign with (i.e. the one whose public key
+    /// was registered in the keyring)
+    key_version: u64,
+}
+
+impl Signer<Signature> for SigningKey {
+    fn try_sign(&self, msg: &[u8]) -> Result<Signature, signature::Error> {
+        let signature = self
+            .client
+            .sign(&self.key_name, self.key_version, msg)
+            .map_err(signature::Error::from_source)?;
+
+        Signature::from_bytes(&signature)
+    }
+}
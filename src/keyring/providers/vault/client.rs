@@ -0,0 +1,554 @@
+//! Minimal HashiCorp Vault client for the Transit secrets engine
+
+use crate::{
+    config::provider::vault::{VaultAuth, VaultConfig},
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use hyper::{
+    body::Bytes,
+    client::HttpConnector,
+    http::{Method, StatusCode, Uri},
+    Body, Request,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    future::Future,
+    io::BufReader,
+    path::Path,
+    sync::{mpsc, Arc, RwLock},
+    time::Duration,
+};
+use subtle_encoding::base64;
+use tokio::runtime::{self, Runtime};
+use zeroize::Zeroizing;
+
+/// HTTP client used for requests to Vault
+type HttpClient = hyper::Client<HttpsConnector<HttpConnector>>;
+
+/// Default mount path of the Transit secrets engine
+pub const DEFAULT_MOUNT: &str = "transit";
+
+/// Header containing the Vault token
+const TOKEN_HEADER: &str = "x-vault-token";
+
+/// Timeout for a single request to Vault
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait before trying again after failing to renew the token
+const RENEW_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Vault client.
+///
+/// Requests are performed on the client's own Tokio runtime (which also runs
+/// the background token renewal task), with callers blocking on a channel for
+/// the result.
+pub struct VaultClient {
+    /// Runtime which performs requests
+    runtime: Runtime,
+
+    /// State shared with in-flight requests
+    inner: Arc<Inner>,
+}
+
+impl VaultClient {
+    /// Create a new Vault client, logging in and starting background token
+    /// renewal
+    pub fn new(config: &VaultConfig) -> Result<Self, Error> {
+        let endpoint = config.api_endpoint.trim_end_matches('/').to_owned();
+
+        endpoint
+            .parse::<Uri>()
+            .map_err(|e| format_err!(ConfigError, "invalid Vault `api_endpoint`: {}", e))?;
+
+        let connector = match &config.ca_bundle {
+            Some(ca_bundle) => HttpsConnectorBuilder::new().with_tls_config(tls_config(ca_bundle)?),
+            None => HttpsConnectorBuilder::new().with_webpki_roots(),
+        }
+        .https_or_http()
+        .enable_http1()
+        .build();
+
+        let runtime = runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("vault")
+            .enable_all()
+            .build()?;
+
+        let token = match &config.auth {
+            VaultAuth::Token(token) => token.clone(),
+            VaultAuth::AppRole { .. } => String::new(),
+        };
+
+        let inner = Arc::new(Inner {
+            http: hyper::Client::builder().build(connector),
+            endpoint,
+            mount: config
+                .mount
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MOUNT.to_owned()),
+            auth: config.auth.clone(),
+            token: RwLock::new(Zeroizing::new(token)),
+        });
+
+        let client = Self { runtime, inner };
+        let lease = client.block_on(|inner| async move { inner.login().await })?;
+
+        match lease.ttl {
+            None => debug!("[keyring:vault] token doesn't expire"),
+            Some(ttl) if !lease.renewable && !client.inner.auth.is_app_role() => warn!(
+                "[keyring:vault] token isn't renewable and will expire in {}s",
+                ttl.as_secs()
+            ),
+            Some(_) => {
+                client
+                    .runtime
+                    .spawn(maintain_token(Arc::clone(&client.inner), lease));
+            }
+        }
+
+        Ok(client)
+    }
+
+    /// Get the latest version of the given Transit key along with its
+    /// (Ed25519) public key
+    pub fn public_key(&self, key_name: &str) -> Result<(u64, Vec<u8>), Error> {
+        let key_name = key_name.to_owned();
+
+        let info: KeyInfo = self.block_on(|inner| async move {
+            inner
+                .request(Method::GET, &inner.transit_path("keys", &key_name), None)
+                .await
+        })?;
+
+        if info.key_type != "ed25519" {
+            fail!(
+                VaultError,
+                "Transit key has type {:?} (expected \"ed25519\")",
+                info.key_type
+            );
+        }
+
+        let public_key = info
+            .keys
+            .get(&info.latest_version.to_string())
+            .map(|version| version.public_key.as_str())
+            .ok_or_else(|| {
+                format_err!(
+                    VaultError,
+                    "no public key for version {} of Transit key",
+                    info.latest_version
+                )
+            })?;
+
+        let public_key = base64::decode(public_key)
+            .map_err(|e| format_err!(VaultError, "malformed Transit public key: {}", e))?;
+
+        Ok((info.latest_version, public_key))
+    }
+
+    /// Sign the given message with the given version of a Transit key,
+    /// returning the raw signature
+    pub fn sign(&self, key_name: &str, key_version: u64, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        let path = self.inner.transit_path("sign", key_name);
+
+        let body = json!({
+            "input": String::from_utf8(base64::encode(msg)).unwrap(),
+            "key_version": key_version,
+        });
+
+        let response: SignResponse =
+            self.block_on(
+                |inner| async move { inner.request(Method::POST, &path, Some(body)).await },
+            )?;
+
+        let (version, signature) = decode_signature(&response.signature)?;
+
+        if version != key_version {
+            fail!(
+                VaultError,
+                "Transit signed with key version {} (expected {})",
+                version,
+                key_version
+            );
+        }
+
+        Ok(signature)
+    }
+
+    /// Run the given request on the client's runtime, blocking until it
+    /// completes
+    fn block_on<F, Fut, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(Arc<Inner>) -> Fut,
+        Fut: Future<Output = Result<T, Error>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let request = f(Arc::clone(&self.inner));
+        let (tx, rx) = mpsc::channel();
+
+        self.runtime.spawn(async move {
+            let _ = tx.send(request.await);
+        });
+
+        rx.recv()
+            .map_err(|_| format_err!(VaultError, "Vault request was aborted"))?
+    }
+}
+
+/// Client state shared with in-flight requests
+struct Inner {
+    /// HTTP client
+    http: HttpClient,
+
+    /// Vault API endpoint (without a trailing slash)
+    endpoint: String,
+
+    /// Mount path of the Transit secrets engine
+    mount: String,
+
+    /// Authentication method
+    auth: VaultAuth,
+
+    /// Current Vault token
+    token: RwLock<Zeroizing<String>>,
+}
+
+impl Inner {
+    /// Path to the given Transit endpoint for the given key
+    fn transit_path(&self, endpoint: &str, key_name: &str) -> String {
+        format!("{}/{}/{}", self.mount, endpoint, key_name)
+    }
+
+    /// Log in (for AppRole auth) or look up our token (for token auth),
+    /// returning the token's lease
+    async fn login(&self) -> Result<Lease, Error> {
+        match &self.auth {
+            VaultAuth::Token(_) => {
+                let info: TokenInfo = self
+                    .request(Method::GET, "auth/token/lookup-self", None)
+                    .await?;
+
+                Ok(Lease::new(info.ttl, info.renewable))
+            }
+            VaultAuth::AppRole { role_id, secret_id } => {
+                let body = json!({ "role_id": role_id, "secret_id": secret_id });
+                let auth = self.auth_request("auth/approle/login", body).await?;
+                info!("[keyring:vault] logged in with AppRole {}", role_id);
+                Ok(auth)
+            }
+        }
+    }
+
+    /// Renew our token if possible, or else log in again (for AppRole auth)
+    async fn refresh_token(&self, renewable: bool) -> Result<Lease, Error> {
+        if renewable {
+            match self.auth_request("auth/token/renew-self", json!({})).await {
+                Ok(lease) => return Ok(lease),
+                Err(e) if self.auth.is_app_role() => {
+                    warn!(
+                        "[keyring:vault] token renewal failed ({}); logging in again",
+                        e
+                    )
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.login().await
+    }
+
+    /// Make a request which returns a new token, storing it and returning
+    /// its lease
+    async fn auth_request(&self, path: &str, body: serde_json::Value) -> Result<Lease, Error> {
+        let response: AuthResponse = self.raw_request(Method::POST, path, Some(body)).await?;
+
+        *self.token.write().unwrap() = Zeroizing::new(response.auth.client_token.clone());
+        Ok(Lease::new(
+            response.auth.lease_duration,
+            response.auth.renewable,
+        ))
+    }
+
+    /// Make a request to the Vault API, returning the response's `data`
+    async fn request<T>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let response: DataResponse<T> = self.raw_request(method, path, body).await?;
+        Ok(response.data)
+    }
+
+    /// Make a request to the Vault API, returning the entire response
+    async fn raw_request<T>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let uri = format!("{}/v1/{}", self.endpoint, path);
+
+        let mut request = Request::builder().method(method).uri(&uri);
+
+        let token = self.token.read().unwrap().clone();
+        if !token.is_empty() {
+            request = request.header(TOKEN_HEADER, token.as_str());
+        }
+
+        let body = match body {
+            Some(body) => Body::from(serde_json::to_vec(&body)?),
+            None => Body::empty(),
+        };
+
+        let (status, response) = send(&self.http, request.body(body).unwrap()).await?;
+
+        if !status.is_success() {
+            let errors = serde_json::from_slice::<ErrorResponse>(&response)
+                .map(|response| response.errors.join("; "))
+                .unwrap_or_default();
+
+            fail!(VaultError, "{} returned HTTP {}: {}", path, status, errors);
+        }
+
+        Ok(serde_json::from_slice(&response)?)
+    }
+}
+
+impl VaultAuth {
+    /// Is this AppRole authentication?
+    fn is_app_role(&self) -> bool {
+        matches!(self, VaultAuth::AppRole { .. })
+    }
+}
+
+/// Lease on a Vault token
+#[derive(Copy, Clone, Debug)]
+struct Lease {
+    /// Time until the token expires (`None` if it doesn't)
+    ttl: Option<Duration>,
+
+    /// Can the token be renewed?
+    renewable: bool,
+}
+
+impl Lease {
+    /// Create a lease from the TTL (in seconds, 0 for none) reported by Vault
+    fn new(ttl: u64, renewable: bool) -> Self {
+        Self {
+            ttl: if ttl == 0 {
+                None
+            } else {
+                Some(Duration::from_secs(ttl))
+            },
+            renewable,
+        }
+    }
+}
+
+/// Keep our token alive: renew it (or log in again) once two thirds of its
+/// TTL have elapsed
+async fn maintain_token(inner: Arc<Inner>, mut lease: Lease) {
+    while let Some(ttl) = lease.ttl {
+        tokio::time::sleep(ttl * 2 / 3).await;
+
+        loop {
+            match inner.refresh_token(lease.renewable).await {
+                Ok(new_lease) => {
+                    debug!("[keyring:vault] refreshed token (TTL: {:?})", new_lease.ttl);
+                    lease = new_lease;
+                    break;
+                }
+                Err(e) => {
+                    error!("[keyring:vault] couldn't refresh token: {}", e);
+                    tokio::time::sleep(RENEW_RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+}
+
+/// Send an HTTP request, returning the response status and body
+async fn send(http: &HttpClient, request: Request<Body>) -> Result<(StatusCode, Bytes), Error> {
+    let uri = request.uri().clone();
+
+    let result = tokio::time::timeout(REQUEST_TIMEOUT, async {
+        let response = http.request(request).await?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        Ok::<_, hyper::Error>((status, body))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(response)) => Ok(response),
+        Ok(Err(e)) => fail!(VaultError, "request to {} failed: {}", uri, e),
+        Err(_) => fail!(VaultError, "request to {} timed out", uri),
+    }
+}
+
+/// Build a TLS configuration which trusts the CA certificates in the given
+/// PEM file
+fn tls_config(ca_bundle: &Path) -> Result<rustls::ClientConfig, Error> {
+    let certs = File::open(ca_bundle)
+        .and_then(|file| rustls_pemfile::certs(&mut BufReader::new(file)))
+        .map_err(|e| {
+            format_err!(
+                ConfigError,
+                "couldn't read Vault CA bundle {}: {}",
+                ca_bundle.display(),
+                e
+            )
+        })?;
+
+    if certs.is_empty() {
+        fail!(
+            ConfigError,
+            "no certificates found in Vault CA bundle {}",
+            ca_bundle.display()
+        );
+    }
+
+    let mut roots = rustls::RootCertStore::empty();
+
+    for cert in certs {
+        roots.add(&rustls::Certificate(cert)).map_err(|e| {
+            format_err!(
+                ConfigError,
+                "invalid certificate in Vault CA bundle {}: {}",
+                ca_bundle.display(),
+                e
+            )
+        })?;
+    }
+
+    Ok(rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth())
+}
+
+/// Decode a Transit signature envelope (`vault:v<version>:<base64>`),
+/// returning the key version and raw signature
+pub fn decode_signature(envelope: &str) -> Result<(u64, Vec<u8>), Error> {
+    let malformed = || format_err!(VaultError, "malformed Transit signature: {}", envelope);
+
+    let (version, signature) = envelope
+        .strip_prefix("vault:v")
+        .and_then(|rest| rest.split_once(':'))
+        .ok_or_else(malformed)?;
+
+    let version = version.parse().map_err(|_| malformed())?;
+    let signature = base64::decode(signature).map_err(|_| malformed())?;
+
+    Ok((version, signature))
+}
+
+/// Response containing `data`
+#[derive(Deserialize)]
+struct DataResponse<T> {
+    data: T,
+}
+
+/// `auth/token/lookup-self` response data
+#[derive(Deserialize)]
+struct TokenInfo {
+    ttl: u64,
+    renewable: bool,
+}
+
+/// Response containing a new token
+#[derive(Deserialize)]
+struct AuthResponse {
+    auth: AuthInfo,
+}
+
+/// New token and its lease
+#[derive(Deserialize)]
+struct AuthInfo {
+    client_token: String,
+    lease_duration: u64,
+    renewable: bool,
+}
+
+/// `transit/keys/<key>` response data
+#[derive(Deserialize)]
+struct KeyInfo {
+    #[serde(rename = "type")]
+    key_type: String,
+    latest_version: u64,
+    keys: BTreeMap<String, KeyVersion>,
+}
+
+/// Version of a Transit key
+#[derive(Deserialize)]
+struct KeyVersion {
+    public_key: String,
+}
+
+/// `transit/sign/<key>` response data
+#[derive(Deserialize)]
+struct SignResponse {
+    signature: String,
+}
+
+/// Error response
+#[derive(Deserialize)]
+struct ErrorResponse {
+    errors: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_signature_envelope() {
+        let (version, signature) = decode_signature("vault:v2:AQID").unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(signature, [1, 2, 3]);
+    }
+
+    #[test]
+    fn reject_malformed_envelopes() {
+        for envelope in &[
+            "AQID",
+            "vault:AQID",
+            "vault:vX:AQID",
+            "vault:v1:!!!",
+            "vault:v1",
+        ] {
+            assert!(decode_signature(envelope).is_err(), "{}", envelope);
+        }
+    }
+
+    #[test]
+    fn parse_key_info() {
+        let info: DataResponse<KeyInfo> = serde_json::from_str(
+            r#"{"data": {"type": "ed25519", "latest_version": 2, "name": "validator",
+                "keys": {"1": {"public_key": "AQID", "creation_time": "2022-01-01T00:00:00Z"},
+                         "2": {"public_key": "BAUG", "creation_time": "2022-02-01T00:00:00Z"}}}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(info.data.key_type, "ed25519");
+        assert_eq!(info.data.keys["2"].public_key, "BAUG");
+    }
+
+    #[test]
+    fn zero_ttl_never_expires() {
+        assert!(Lease::new(0, false).ttl.is_none());
+        assert_eq!(Lease::new(60, true).ttl, Some(Duration::from_secs(60)));
+    }
+}
@@ -1,5 +1,8 @@
 //! YubiHSM2 signing provider
 
+mod session;
+
+use self::session::{HsmSessions, Retries, Sessions};
 use crate::{
     chain,
     config::provider::{
@@ -10,6 +13,7 @@ use crate::{
     keyring::{self, SigningProvider},
     prelude::*,
 };
+use std::time::Duration;
 use tendermint::TendermintKey;
 
 /// Create hardware-backed YubiHSM signer objects from the given configuration
@@ -30,38 +34,58 @@ pub fn init(
         );
     }
 
+    let retries = Retries {
+        max: yubihsm_configs[0].sign_retries,
+        delay: Duration::from_millis(yubihsm_configs[0].sign_retry_delay_ms),
+    };
+
     for config in &yubihsm_configs[0].keys {
         match config.key_type {
-            KeyType::Account => add_account_key(chain_registry, config)?,
-            KeyType::Consensus => add_consensus_key(chain_registry, config)?,
+            KeyType::Account => add_account_key(chain_registry, config, retries)?,
+            KeyType::Consensus => add_consensus_key(chain_registry, config, retries)?,
         }
     }
 
     Ok(())
 }
 
+/// Check the HSM is responding to requests made over the global client's
+/// session
+pub fn healthcheck() -> Result<(), Error> {
+    crate::yubihsm::client()
+        .device_info()
+        .map_err(|e| format_err!(YubihsmError, "couldn't get device info: {}", e))?;
+
+    Ok(())
+}
+
 /// Add an account key (ECDSA/secp256k1) to the keychain
 fn add_account_key(
     chain_registry: &mut chain::Registry,
     config: &SigningKeyConfig,
+    retries: Retries,
 ) -> Result<(), Error> {
-    let signer = yubihsm::ecdsa::Signer::create(crate::yubihsm::client().clone(), config.key)
-        .map_err(|_| {
-            format_err!(
-                InvalidKey,
-                "YubiHSM key ID 0x{:04x} is not a valid ECDSA signing key",
-                config.key
-            )
-        })?;
+    let sessions = HsmSessions::new(
+        config.key,
+        yubihsm::ecdsa::Signer::<k256::Secp256k1>::create,
+    );
+
+    let current = sessions.signer().map_err(|_| {
+        format_err!(
+            InvalidKey,
+            "YubiHSM key ID 0x{:04x} is not a valid ECDSA signing key",
+            config.key
+        )
+    })?;
 
     let public_key =
-        tendermint::PublicKey::from_raw_secp256k1(signer.public_key().compress().as_bytes())
+        tendermint::PublicKey::from_raw_secp256k1(current.1.public_key().compress().as_bytes())
             .expect("invalid secp256k1 key");
 
     let signer = keyring::ecdsa::Signer::new(
         SigningProvider::Yubihsm,
         TendermintKey::AccountKey(public_key),
-        Box::new(signer),
+        Box::new(session::Signer::new(sessions, current, retries)),
     );
 
     for chain_id in &config.chain_ids {
@@ -75,23 +99,25 @@ fn add_account_key(
 fn add_consensus_key(
     chain_registry: &mut chain::Registry,
     config: &SigningKeyConfig,
+    retries: Retries,
 ) -> Result<(), Error> {
-    let signer = yubihsm::ed25519::Signer::create(crate::yubihsm::client().clone(), config.key)
-        .map_err(|_| {
-            format_err!(
-                InvalidKey,
-                "YubiHSM key ID 0x{:04x} is not a valid Ed25519 signing key",
-                config.key
-            )
-        })?;
-
-    let public_key = tendermint::PublicKey::from_raw_ed25519(signer.public_key().as_bytes())
+    let sessions = HsmSessions::new(config.key, yubihsm::ed25519::Signer::create);
+
+    let current = sessions.signer().map_err(|_| {
+        format_err!(
+            InvalidKey,
+            "YubiHSM key ID 0x{:04x} is not a valid Ed25519 signing key",
+            config.key
+        )
+    })?;
+
+    let public_key = tendermint::PublicKey::from_raw_ed25519(current.1.public_key().as_bytes())
         .expect("invalid Ed25519 key");
 
     let signer = keyring::ed25519::Signer::new(
         SigningProvider::Yubihsm,
         TendermintKey::ConsensusKey(public_key),
-        Box::new(signer),
+        Box::new(session::Signer::new(sessions, current, retries)),
     );
 
     for chain_id in &config.chain_ids {
@@ -0,0 +1,452 @@
+//! Recovery from lost YubiHSM sessions.
+//!
+//! If `yubihsm-connector` restarts (or the HSM is reset), the HSM forgets the
+//! session the global client authenticated, but the client keeps using it and
+//! every signature fails. Signers here reopen and re-authenticate the session
+//! when signing fails with a session error, and retry.
+//!
+//! Signatures which fail for a transient reason before reaching the HSM (e.g.
+//! `yubihsm-connector` responding 502, or the device being busy) are retried
+//! a bounded number of times first, so a single hiccup doesn't cost a block.
+//! Errors which leave it unclear whether the HSM produced a signature are
+//! never retried.
+
+use crate::{
+    error::{Error, ErrorKind::YubihsmError},
+    metrics,
+    prelude::*,
+};
+use signature::Signature;
+use std::{error::Error as _, sync::Mutex, thread, time::Duration};
+use yubihsm::{client, connector, device, object, Client};
+
+/// Maximum number of times to re-authenticate the session before failing a
+/// signature
+pub const MAX_REAUTH_ATTEMPTS: u32 = 3;
+
+/// Delay before the first re-authentication attempt (doubled after each
+/// failed attempt)
+const REAUTH_DELAY: Duration = Duration::from_millis(500);
+
+/// Bounded retries of signatures which failed with a transient error
+#[derive(Copy, Clone, Debug)]
+pub struct Retries {
+    /// Maximum number of times to retry a signature
+    pub max: u32,
+
+    /// Delay between retries
+    pub delay: Duration,
+}
+
+/// Source of signers bound to an authenticated HSM session
+pub trait Sessions: Send + Sync {
+    /// Signer using a session
+    type Signer: Send;
+
+    /// Create a signer using the current session, returned along with the
+    /// session's generation
+    fn signer(&self) -> Result<(u64, Self::Signer), Error>;
+
+    /// Reopen and re-authenticate the session, if it's still the one of the
+    /// given generation
+    fn reopen(&self, generation: u64) -> Result<(), Error>;
+}
+
+/// Sessions of the global YubiHSM client for a particular key
+pub struct HsmSessions<S> {
+    /// ID of the signing key
+    key_id: object::Id,
+
+    /// Create a signer for the key from a client
+    create: fn(Client, object::Id) -> Result<S, signature::Error>,
+}
+
+impl<S> HsmSessions<S> {
+    /// Create sessions for the given key, creating signers with `create`
+    pub fn new(
+        key_id: object::Id,
+        create: fn(Client, object::Id) -> Result<S, signature::Error>,
+    ) -> Self {
+        Self { key_id, create }
+    }
+}
+
+impl<S: Send> Sessions for HsmSessions<S> {
+    type Signer = S;
+
+    fn signer(&self) -> Result<(u64, S), Error> {
+        let (generation, client) = crate::yubihsm::client_with_generation();
+        let signer =
+            (self.create)(client, self.key_id).map_err(|e| format_err!(YubihsmError, "{}", e))?;
+
+        Ok((generation, signer))
+    }
+
+    fn reopen(&self, generation: u64) -> Result<(), Error> {
+        crate::yubihsm::reopen_client(generation)
+    }
+}
+
+/// Signer which re-authenticates its session and retries when signing fails
+/// because the session was lost
+pub struct Signer<T: Sessions> {
+    /// Sessions the signer is created from
+    sessions: T,
+
+    /// Current signer and the generation of its session
+    current: Mutex<(u64, T::Signer)>,
+
+    /// Delay before the first re-authentication attempt
+    reauth_delay: Duration,
+
+    /// Retries of signatures which failed with a transient error
+    retries: Retries,
+}
+
+impl<T: Sessions> Signer<T> {
+    /// Create a signer from its sessions and the current signer
+    pub fn new(sessions: T, current: (u64, T::Signer), retries: Retries) -> Self {
+        Self {
+            sessions,
+            current: Mutex::new(current),
+            reauth_delay: REAUTH_DELAY,
+            retries,
+        }
+    }
+}
+
+impl<T, S> signature::Signer<S> for Signer<T>
+where
+    T: Sessions,
+    T::Signer: signature::Signer<S>,
+    S: Signature,
+{
+    fn try_sign(&self, msg: &[u8]) -> Result<S, signature::Error> {
+        let mut current = self.current.lock().unwrap();
+        let mut attempts = 0;
+        let mut retries = 0;
+
+        loop {
+            let error = match current.1.try_sign(msg) {
+                Ok(signature) => {
+                    if attempts > 0 {
+                        info!(
+                            "[keyring:yubihsm] re-authenticated HSM session after {} attempt(s); signing resumed",
+                            attempts
+                        );
+                        metrics::provider_session_recovered("yubihsm");
+                    }
+
+                    return Ok(signature);
+                }
+                Err(e) => e,
+            };
+
+            if retries < self.retries.max && is_transient_error(&error) {
+                retries += 1;
+
+                warn!(
+                    "[keyring:yubihsm] transient error signing ({}); retrying (attempt {}/{})",
+                    error, retries, self.retries.max
+                );
+
+                metrics::provider_sign_retried("yubihsm");
+                thread::sleep(self.retries.delay);
+                continue;
+            }
+
+            // Transient errors which persist are handled like a lost session
+            if attempts == MAX_REAUTH_ATTEMPTS || !is_session_error(&error) {
+                return Err(error);
+            }
+
+            thread::sleep(self.reauth_delay * 2u32.pow(attempts));
+            attempts += 1;
+
+            warn!(
+                "[keyring:yubihsm] HSM session lost ({}); re-authenticating (attempt {}/{})",
+                error, attempts, MAX_REAUTH_ATTEMPTS
+            );
+
+            let generation = current.0;
+
+            match self
+                .sessions
+                .reopen(generation)
+                .and_then(|()| self.sessions.signer())
+            {
+                Ok(signer) => *current = signer,
+                Err(e) => warn!("[keyring:yubihsm] error re-authenticating session: {}", e),
+            }
+        }
+    }
+}
+
+/// Did signing fail for a transient reason before the request reached the HSM,
+/// i.e. is it safe to retry as-is?
+fn is_transient_error(error: &signature::Error) -> bool {
+    matches!(
+        find_source::<connector::Error>(error).map(connector::Error::kind),
+        Some(connector::ErrorKind::DeviceBusyError)
+            | Some(connector::ErrorKind::HttpError)
+            | Some(connector::ErrorKind::IoError)
+            | Some(connector::ErrorKind::UsbError)
+    )
+}
+
+/// Did signing fail in a way that leaves it unclear whether the HSM produced
+/// a signature (e.g. its response was lost or garbled)?
+fn is_ambiguous_error(error: &signature::Error) -> bool {
+    if let Some(connector_error) = find_source::<connector::Error>(error) {
+        return matches!(connector_error.kind(), connector::ErrorKind::ResponseError);
+    }
+
+    matches!(
+        find_source::<client::Error>(error).map(client::Error::kind),
+        Some(client::ErrorKind::ResponseError)
+    )
+}
+
+/// Did signing fail because the session with the HSM was lost (as opposed to
+/// e.g. the key lacking the required capabilities)?
+fn is_session_error(error: &signature::Error) -> bool {
+    if is_ambiguous_error(error) {
+        return false;
+    }
+
+    if find_source::<connector::Error>(error).is_some() {
+        return true;
+    }
+
+    let client_error = match find_source::<client::Error>(error) {
+        Some(client_error) => client_error,
+        None => return false,
+    };
+
+    match client_error.kind() {
+        client::ErrorKind::DeviceError => matches!(
+            client_error.device_error(),
+            Some(device::ErrorKind::InvalidSession)
+                | Some(device::ErrorKind::SessionFailed)
+                | Some(device::ErrorKind::AuthenticationFailed)
+        ),
+        _ => true,
+    }
+}
+
+/// Find an error of the given type in the chain of sources of a signing error
+fn find_source<E: std::error::Error + 'static>(error: &signature::Error) -> Option<&E> {
+    let mut source = error.source();
+
+    while let Some(error) = source {
+        if let Some(error) = error.downcast_ref::<E>() {
+            return Some(error);
+        }
+
+        source = error.source();
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek as ed25519;
+    use signature::Signer as _;
+    use std::sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    };
+
+    /// Mock connector whose session can be dropped, as happens when
+    /// `yubihsm-connector` restarts
+    #[derive(Default)]
+    struct MockConnector {
+        /// Generation of the open session
+        generation: AtomicU64,
+
+        /// Has the session been dropped?
+        dropped: AtomicBool,
+
+        /// Number of upcoming re-authentication attempts which will fail
+        failing_reauths: AtomicU32,
+
+        /// Number of successful re-authentications
+        reauths: AtomicU32,
+
+        /// Error returned by signers instead of a signature, if any
+        error: Mutex<Option<client::ErrorKind>>,
+
+        /// Connector errors returned by upcoming signing attempts
+        connector_errors: Mutex<Vec<connector::ErrorKind>>,
+
+        /// Number of signing attempts
+        attempts: AtomicU32,
+    }
+
+    impl MockConnector {
+        /// Drop the session mid-stream
+        fn drop_session(&self) {
+            self.dropped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Signer using a mock session
+    struct MockSigner {
+        /// Connector the session was opened with
+        connector: Arc<MockConnector>,
+
+        /// Generation of the session
+        generation: u64,
+    }
+
+    impl signature::Signer<ed25519::Signature> for MockSigner {
+        fn try_sign(&self, _msg: &[u8]) -> Result<ed25519::Signature, signature::Error> {
+            self.connector.attempts.fetch_add(1, Ordering::SeqCst);
+
+            if let Some(kind) = self.connector.connector_errors.lock().unwrap().pop() {
+                return Err(signature::Error::from_source(connector::Error::from(kind)));
+            }
+
+            if let Some(kind) = *self.connector.error.lock().unwrap() {
+                return Err(client::Error::from(kind).into());
+            }
+
+            if self.connector.dropped.load(Ordering::SeqCst)
+                || self.connector.generation.load(Ordering::SeqCst) != self.generation
+            {
+                return Err(client::Error::from(client::ErrorKind::ClosedSessionError).into());
+            }
+
+            ed25519::Signature::from_bytes(&[0u8; 64])
+        }
+    }
+
+    impl Sessions for Arc<MockConnector> {
+        type Signer = MockSigner;
+
+        fn signer(&self) -> Result<(u64, MockSigner), Error> {
+            let generation = self.generation.load(Ordering::SeqCst);
+
+            Ok((
+                generation,
+                MockSigner {
+                    connector: self.clone(),
+                    generation,
+                },
+            ))
+        }
+
+        fn reopen(&self, generation: u64) -> Result<(), Error> {
+            if self
+                .failing_reauths
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok()
+            {
+                fail!(YubihsmError, "connector unavailable");
+            }
+
+            if self.generation.load(Ordering::SeqCst) == generation {
+                self.generation.fetch_add(1, Ordering::SeqCst);
+                self.dropped.store(false, Ordering::SeqCst);
+                self.reauths.fetch_add(1, Ordering::SeqCst);
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Create a signer using sessions of the given mock connector
+    fn signer(connector: &Arc<MockConnector>) -> Signer<Arc<MockConnector>> {
+        let current = connector.signer().unwrap();
+
+        Signer {
+            sessions: connector.clone(),
+            current: Mutex::new(current),
+            reauth_delay: Duration::from_millis(0),
+            retries: Retries {
+                max: 2,
+                delay: Duration::from_millis(0),
+            },
+        }
+    }
+
+    #[test]
+    fn reauthenticates_dropped_session() {
+        let connector = Arc::new(MockConnector::default());
+        let signer = signer(&connector);
+
+        let _: ed25519::Signature = signer.sign(b"before");
+        connector.drop_session();
+
+        let signature: Result<ed25519::Signature, _> = signer.try_sign(b"after");
+        assert!(signature.is_ok());
+        assert_eq!(connector.reauths.load(Ordering::SeqCst), 1);
+
+        // The new session is kept
+        let _: ed25519::Signature = signer.sign(b"again");
+        assert_eq!(connector.reauths.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn reauthentication_attempts_are_bounded() {
+        let connector = Arc::new(MockConnector::default());
+        let signer = signer(&connector);
+
+        connector.drop_session();
+        connector
+            .failing_reauths
+            .store(MAX_REAUTH_ATTEMPTS, Ordering::SeqCst);
+
+        let signature: Result<ed25519::Signature, _> = signer.try_sign(b"msg");
+        assert!(signature.is_err());
+        assert_eq!(connector.failing_reauths.load(Ordering::SeqCst), 0);
+
+        // Once the connector is back, signing recovers
+        let signature: Result<ed25519::Signature, _> = signer.try_sign(b"msg");
+        assert!(signature.is_ok());
+        assert_eq!(connector.reauths.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn other_errors_are_not_retried() {
+        let connector = Arc::new(MockConnector::default());
+        let signer = signer(&connector);
+
+        *connector.error.lock().unwrap() = Some(client::ErrorKind::DeviceError);
+
+        let signature: Result<ed25519::Signature, _> = signer.try_sign(b"msg");
+        assert!(signature.is_err());
+        assert_eq!(connector.reauths.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let connector = Arc::new(MockConnector::default());
+        let signer = signer(&connector);
+
+        *connector.connector_errors.lock().unwrap() = vec![
+            connector::ErrorKind::DeviceBusyError,
+            connector::ErrorKind::HttpError,
+        ];
+
+        let signature: Result<ed25519::Signature, _> = signer.try_sign(b"msg");
+        assert!(signature.is_ok());
+        assert_eq!(connector.attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(connector.reauths.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn ambiguous_errors_are_not_retried() {
+        let connector = Arc::new(MockConnector::default());
+        let signer = signer(&connector);
+
+        *connector.connector_errors.lock().unwrap() = vec![connector::ErrorKind::ResponseError];
+
+        let signature: Result<ed25519::Signature, _> = signer.try_sign(b"msg");
+        assert!(signature.is_err());
+        assert_eq!(connector.attempts.load(Ordering::SeqCst), 1);
+        assert_eq!(connector.reauths.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,154 @@
+//! Signing latency: the time taken by the signing provider to produce each
+//! signature, excluding network I/O with the validator, so the latency of a
+//! provider (e.g. an HSM) can be told apart from that of the sentry.
+//!
+//! Each measurement is recorded in the `signing_latency_seconds` metric,
+//! logged as a warning if it exceeds `slow_sign_threshold_ms`, and added to a
+//! window of recent signatures whose p50/p99 are logged on shutdown and on
+//! `SIGUSR1`.
+
+use crate::{chain, metrics, prelude::*, Map};
+use once_cell::sync::Lazy;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Number of recent signatures (per chain and provider) percentiles are
+/// computed over
+pub const WINDOW_SIZE: usize = 1024;
+
+/// Signatures taking longer than this many milliseconds are logged as
+/// warnings (0 if disabled)
+static SLOW_SIGN_THRESHOLD: AtomicU64 = AtomicU64::new(0);
+
+/// Recent signing latencies by chain and provider
+static WINDOWS: Lazy<Mutex<Map<(chain::Id, String), Window>>> =
+    Lazy::new(|| Mutex::new(Map::new()));
+
+/// Set the latency above which signatures are logged as warnings (disabled
+/// if `None`)
+pub fn set_slow_sign_threshold(threshold_ms: Option<u64>) {
+    SLOW_SIGN_THRESHOLD.store(threshold_ms.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Record the time taken by a signing provider to produce a signature
+pub fn record(chain_id: &chain::Id, provider: &str, latency: Duration) {
+    metrics::signing_latency(chain_id, provider, latency);
+
+    let threshold_ms = SLOW_SIGN_THRESHOLD.load(Ordering::Relaxed);
+
+    if threshold_ms > 0 && latency > Duration::from_millis(threshold_ms) {
+        warn!(
+            chain_id = %chain_id,
+            provider = %provider,
+            "[{}] slow signature from {}: {} ms (threshold {} ms)",
+            chain_id,
+            provider,
+            latency.as_millis(),
+            threshold_ms
+        );
+    }
+
+    WINDOWS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry((chain_id.clone(), provider.to_owned()))
+        .or_default()
+        .push(latency);
+}
+
+/// Log the p50/p99 signing latency of recent signatures, for each chain and
+/// provider
+pub fn log_summary() {
+    let windows = WINDOWS.lock().unwrap_or_else(|e| e.into_inner());
+
+    if windows.is_empty() {
+        info!("signing latency: no signatures yet");
+    }
+
+    for ((chain_id, provider), window) in windows.iter() {
+        let (p50, p99) = match (window.percentile(50), window.percentile(99)) {
+            (Some(p50), Some(p99)) => (p50, p99),
+            _ => continue,
+        };
+
+        info!(
+            chain_id = %chain_id,
+            provider = %provider,
+            "[{}] signing latency ({}): p50 {:.1} ms, p99 {:.1} ms (last {} signatures)",
+            chain_id,
+            provider,
+            p50.as_secs_f64() * 1000.0,
+            p99.as_secs_f64() * 1000.0,
+            window.samples.len()
+        );
+    }
+}
+
+/// Latencies of the last [`WINDOW_SIZE`] signatures
+#[derive(Debug, Default)]
+struct Window {
+    samples: VecDeque<Duration>,
+}
+
+impl Window {
+    /// Add a latency, evicting the oldest one if the window is full
+    fn push(&mut self, latency: Duration) {
+        if self.samples.len() == WINDOW_SIZE {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(latency);
+    }
+
+    /// Get the given percentile (nearest rank) of the latencies in the window
+    fn percentile(&self, percentile: usize) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let mut sorted = self.samples.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+
+        let rank = (percentile * sorted.len() + 99) / 100;
+        Some(sorted[rank.max(1) - 1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        let mut window = Window::default();
+        assert_eq!(window.percentile(50), None);
+
+        for millis in (1..=100).rev() {
+            window.push(Duration::from_millis(millis));
+        }
+
+        assert_eq!(window.percentile(50), Some(Duration::from_millis(50)));
+        assert_eq!(window.percentile(99), Some(Duration::from_millis(99)));
+        assert_eq!(window.percentile(100), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn window_evicts_oldest() {
+        let mut window = Window::default();
+
+        window.push(Duration::from_secs(10));
+
+        for _ in 0..WINDOW_SIZE {
+            window.push(Duration::from_millis(1));
+        }
+
+        assert_eq!(window.samples.len(), WINDOW_SIZE);
+        assert_eq!(window.percentile(100), Some(Duration::from_millis(1)));
+    }
+}
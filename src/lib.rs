@@ -7,30 +7,57 @@
     feature = "softsign",
     feature = "yubihsm",
     feature = "ledger",
-    feature = "fortanixdsm"
+    feature = "fortanixdsm",
+    feature = "awskms",
+    feature = "azurekv",
+    feature = "gcpkms",
+    feature = "vault",
+    feature = "pkcs11",
+    feature = "threshold",
+    feature = "testing"
 )))]
 compile_error!(
     "please enable one of the following backends with cargo's --features argument: \
-     yubihsm, ledgertm, softsign, fortanixdsm (e.g. --features=yubihsm)"
+     yubihsm, ledgertm, softsign, fortanixdsm, awskms, azurekv, gcpkms, vault, pkcs11, threshold \
+     (e.g. --features=yubihsm)"
 );
 
+pub mod alerts;
 pub mod amino_types;
 pub mod application;
+pub mod audit;
+pub mod banner;
+pub mod build_info;
+pub mod capture;
 pub mod chain;
 pub mod client;
 pub mod commands;
 pub mod config;
 pub mod connection;
+pub mod control;
 pub mod error;
+pub mod height_monitor;
 pub mod key_utils;
 pub mod keyring;
+pub mod latency;
+pub mod logging;
+pub mod metrics;
 pub mod prelude;
+pub mod privileges;
 pub mod rpc;
 pub mod session;
+#[allow(unsafe_code)]
+pub mod shutdown;
+pub mod signer;
+pub mod status;
+pub mod systemd;
 
 #[cfg(feature = "tx-signer")]
 pub mod tx_signer;
 
+pub mod tx_signer_socket;
+pub mod verify;
+
 #[cfg(feature = "yubihsm")]
 pub mod yubihsm;
 
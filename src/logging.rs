@@ -0,0 +1,330 @@
+//! Log output: human-readable lines (the default) or one JSON object per
+//! line for log pipelines.
+//!
+//! Signing events carry the chain ID, height, round, step, and message type
+//! as structured fields (see [`SIGNING_FIELDS`]). They're already part of the
+//! message of plain log lines, so the plain format omits them, while the JSON
+//! format emits them as fields of their own.
+
+use chrono::{SecondsFormat, Utc};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    fmt, io,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::{
+    field::RecordFields,
+    fmt::{
+        format::{Format, FormatEvent, FormatFields, Writer},
+        writer::BoxMakeWriter,
+        FmtContext,
+    },
+    registry::LookupSpan,
+    EnvFilter, FmtSubscriber,
+};
+
+/// Structured fields attached to signing events
+pub const SIGNING_FIELDS: &[&str] = &["chain_id", "height", "round", "step", "msg_type"];
+
+/// Is JSON log output enabled?
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Log output format
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[serde(rename = "plain")]
+    Plain,
+
+    /// One JSON object per line
+    #[serde(rename = "json")]
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        LogFormat::Plain
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "plain" => Ok(LogFormat::Plain),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!(
+                "invalid log format: {} (must be 'plain' or 'json')",
+                other
+            )),
+        }
+    }
+}
+
+/// Install the global log subscriber, logging events enabled by the given
+/// filter (e.g. `info`) to stdout, or to stderr if the command's output on
+/// stdout is machine-readable
+pub fn init(
+    filter: &str,
+    ansi: bool,
+    stderr: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Convert events from the `log` crate into `tracing` events
+    tracing_log::LogTracer::init()?;
+
+    let writer = if stderr {
+        BoxMakeWriter::new(io::stderr)
+    } else {
+        BoxMakeWriter::new(io::stdout)
+    };
+
+    let subscriber = FmtSubscriber::builder()
+        .with_ansi(ansi)
+        .with_writer(writer)
+        .with_env_filter(EnvFilter::new(filter))
+        .fmt_fields(PlainFields)
+        .event_format(EventFormat::default())
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+    Ok(())
+}
+
+/// Set the format of subsequent log output
+pub fn set_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+/// Event formatter which writes events in the current [`LogFormat`]
+#[derive(Default)]
+struct EventFormat {
+    /// Formatter for plain log lines
+    plain: Format,
+}
+
+impl<S, N> FormatEvent<S, N> for EventFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        if !JSON.load(Ordering::Relaxed) {
+            return self.plain.format_event(ctx, writer, event);
+        }
+
+        writeln!(writer, "{}", json_line(event))
+    }
+}
+
+/// Format an event as a JSON object
+fn json_line(event: &Event<'_>) -> Value {
+    let normalized = event.normalized_metadata();
+    let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+
+    let mut object = Map::new();
+    object.insert(
+        "timestamp".to_owned(),
+        Utc::now()
+            .to_rfc3339_opts(SecondsFormat::Micros, true)
+            .into(),
+    );
+    object.insert("level".to_owned(), metadata.level().as_str().into());
+    object.insert("target".to_owned(), metadata.target().into());
+
+    event.record(&mut JsonVisitor(&mut object));
+    Value::Object(object)
+}
+
+/// Visitor which adds the fields of an event to a JSON object
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl JsonVisitor<'_> {
+    fn insert(&mut self, field: &Field, value: Value) {
+        // Metadata of events from the `log` crate (already normalized)
+        if !field.name().starts_with("log.") {
+            self.0.insert(field.name().to_owned(), value);
+        }
+    }
+}
+
+impl Visit for JsonVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{:?}", value).into());
+    }
+}
+
+/// Field formatter for plain log lines, which omits [`SIGNING_FIELDS`]
+/// (already part of the messages of signing events)
+struct PlainFields;
+
+impl<'writer> FormatFields<'writer> for PlainFields {
+    fn format_fields<R: RecordFields>(&self, writer: Writer<'writer>, fields: R) -> fmt::Result {
+        let mut visitor = PlainVisitor {
+            writer,
+            is_empty: true,
+            result: Ok(()),
+        };
+
+        fields.record(&mut visitor);
+        visitor.result
+    }
+}
+
+/// Visitor which writes fields as `name=value` (and messages as is)
+struct PlainVisitor<'writer> {
+    writer: Writer<'writer>,
+    is_empty: bool,
+    result: fmt::Result,
+}
+
+impl Visit for PlainVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.record_debug(field, &format_args!("{}", value))
+        } else {
+            self.record_debug(field, &value)
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let name = field.name();
+
+        if self.result.is_err() || name.starts_with("log.") || SIGNING_FIELDS.contains(&name) {
+            return;
+        }
+
+        if !self.is_empty {
+            self.result = write!(self.writer, " ");
+        }
+
+        self.is_empty = false;
+
+        self.result = self.result.and_then(|()| {
+            if name == "message" {
+                write!(self.writer, "{:?}", value)
+            } else {
+                write!(self.writer, "{}={:?}", name, value)
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+    use tracing_subscriber::fmt::MakeWriter;
+
+    /// Log output captured in memory
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Output {
+        type Writer = Output;
+
+        fn make_writer(&'a self) -> Output {
+            self.clone()
+        }
+    }
+
+    /// Log a signing event with the given format, returning the output
+    fn log_signing_event(format: LogFormat) -> String {
+        let output = Output::default();
+
+        let subscriber = FmtSubscriber::builder()
+            .with_ansi(false)
+            .with_writer(output.clone())
+            .fmt_fields(PlainFields)
+            .event_format(EventFormat::default())
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            set_format(format);
+            tracing::info!(
+                chain_id = "test-chain",
+                height = 42i64,
+                round = 1i64,
+                step = 2u64,
+                msg_type = "Prevote",
+                "[test-chain@tcp://127.0.0.1:26658] signed Prevote:ABCDEF at h/r/s 42/1/2 (0 ms)"
+            );
+            set_format(LogFormat::Plain);
+        });
+
+        let bytes = output.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn signing_events() {
+        // Both formats are tested together as the format is global
+        let line = log_signing_event(LogFormat::Json);
+        let json: Value = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["target"], module_path!());
+        assert_eq!(json["chain_id"], "test-chain");
+        assert_eq!(json["height"], 42);
+        assert_eq!(json["round"], 1);
+        assert_eq!(json["step"], 2);
+        assert_eq!(json["msg_type"], "Prevote");
+        assert!(json["message"]
+            .as_str()
+            .unwrap()
+            .contains("signed Prevote:ABCDEF at h/r/s 42/1/2"));
+        assert!(json["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert_eq!(line.lines().count(), 1);
+
+        let line = log_signing_event(LogFormat::Plain);
+        assert!(line.ends_with("signed Prevote:ABCDEF at h/r/s 42/1/2 (0 ms)\n"));
+        assert!(!line.contains("chain_id="));
+    }
+
+    #[test]
+    fn parse_log_format() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!("plain".parse::<LogFormat>().unwrap(), LogFormat::Plain);
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+}
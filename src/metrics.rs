@@ -0,0 +1,547 @@
+//! Prometheus metrics for signing activity.
+//!
+//! Metrics are always collected, but only exposed if a `[metrics]` section is
+//! present in `tmkms.toml`, in which case they're served over HTTP at
+//! `/metrics` by a dedicated thread.
+
+use crate::{
+    alerts,
+    amino_types::SignedMsgType,
+    chain,
+    config::MetricsConfig,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+/// Namespace (i.e. name prefix) for all metrics
+const NAMESPACE: &str = "tmkms";
+
+/// Timeout for reading a request from (and writing a response to) a scraper
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Registry containing all of the KMS's metrics
+static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+/// Signed votes (prevotes and precommits)
+static SIGNED_VOTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new("signed_votes_total", "Number of votes signed").namespace(NAMESPACE),
+        &["chain_id"],
+    ))
+});
+
+/// Signed proposals
+static SIGNED_PROPOSALS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new("signed_proposals_total", "Number of proposals signed").namespace(NAMESPACE),
+        &["chain_id"],
+    ))
+});
+
+/// Signing requests which were refused
+static REFUSED_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "refused_requests_total",
+            "Number of signing requests refused, by reason",
+        )
+        .namespace(NAMESPACE),
+        &["chain_id", "reason"],
+    ))
+});
+
+/// Requests from validators which couldn't be decoded or were malformed
+static MALFORMED_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "malformed_requests_total",
+            "Number of requests from validators which were of an unknown type or malformed",
+        )
+        .namespace(NAMESPACE),
+        &["chain_id", "validator"],
+    ))
+});
+
+/// Validator connections which were reset due to an error
+static CONNECTION_RESETS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "connection_resets_total",
+            "Number of validator connections reset due to an error",
+        )
+        .namespace(NAMESPACE),
+        &["chain_id", "validator"],
+    ))
+});
+
+/// Validator clients restarted after crashing
+static CLIENT_RESTARTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "client_restarts_total",
+            "Number of validator clients restarted after crashing",
+        )
+        .namespace(NAMESPACE),
+        &["chain_id", "validator"],
+    ))
+});
+
+/// Alerts dropped because the alert queue was full
+static ALERTS_DROPPED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "alerts_dropped_total",
+            "Number of alerts dropped because the alert queue was full, by event",
+        )
+        .namespace(NAMESPACE),
+        &["event"],
+    ))
+});
+
+/// Signing provider sessions re-established after being lost
+static PROVIDER_SESSION_RECOVERIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "provider_session_recoveries_total",
+            "Number of signing provider sessions re-established after being lost",
+        )
+        .namespace(NAMESPACE),
+        &["provider"],
+    ))
+});
+
+/// Signatures retried by signing providers after a transient error
+static PROVIDER_SIGN_RETRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "provider_sign_retries_total",
+            "Number of signatures retried by signing providers after a transient error",
+        )
+        .namespace(NAMESPACE),
+        &["provider"],
+    ))
+});
+
+/// Validator connections whose peer ID didn't match the configured one
+static PEER_ID_MISMATCHES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "peer_id_mismatches_total",
+            "Number of validator connections whose peer ID didn't match the configured one",
+        )
+        .namespace(NAMESPACE),
+        &["expected_peer_id"],
+    ))
+});
+
+/// Heights which can still be signed before each validator's `max_height`
+static MAX_HEIGHT_REMAINING: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register(IntGaugeVec::new(
+        Opts::new(
+            "max_height_remaining",
+            "Number of heights which can still be signed before the validator's max_height",
+        )
+        .namespace(NAMESPACE),
+        &["chain_id", "validator"],
+    ))
+});
+
+/// Requests queued on each validator connection
+static REQUEST_QUEUE_DEPTH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register(IntGaugeVec::new(
+        Opts::new(
+            "request_queue_depth",
+            "Number of requests from the validator queued for handling",
+        )
+        .namespace(NAMESPACE),
+        &["chain_id", "validator"],
+    ))
+});
+
+/// Latest block height reported by each chain's `rpc_endpoint`
+static NETWORK_HEIGHT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register(IntGaugeVec::new(
+        Opts::new(
+            "network_height",
+            "Latest block height reported by the chain's rpc_endpoint",
+        )
+        .namespace(NAMESPACE),
+        &["chain_id"],
+    ))
+});
+
+/// Heights the last signed height is behind the network's (negative if ahead)
+static SIGNED_HEIGHT_LAG: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register(IntGaugeVec::new(
+        Opts::new(
+            "signed_height_lag",
+            "Number of heights the last signed height is behind the network's (negative if ahead)",
+        )
+        .namespace(NAMESPACE),
+        &["chain_id", "label"],
+    ))
+});
+
+/// Failed polls of each chain's `rpc_endpoint`
+static RPC_POLL_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "rpc_poll_failures_total",
+            "Number of failed polls of the chain's rpc_endpoint for its latest height",
+        )
+        .namespace(NAMESPACE),
+        &["chain_id"],
+    ))
+});
+
+/// Time spent in the signing provider
+static SIGNING_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "signing_latency_seconds",
+            "Time taken by the signing provider to produce a signature",
+        )
+        .namespace(NAMESPACE)
+        .buckets(vec![
+            0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+        ]),
+        &["chain_id", "provider"],
+    ))
+});
+
+/// Reasons a signing request was refused
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RefusalReason {
+    /// Attempted double sign
+    DoubleSign,
+
+    /// Requested height exceeds the configured `max_height`
+    MaxHeight,
+
+    /// Requested height is below the configured `min_height`
+    MinHeight,
+
+    /// Requested height is in the chain's `blocked_heights`
+    BlockedHeight,
+
+    /// Requested message type isn't in the chain's `allowed_msg_types`
+    MsgType,
+
+    /// Requested message type isn't signed for the validator's `role` (e.g.
+    /// a proposal for a `voter`)
+    Role,
+
+    /// Requested chain ID doesn't match the validator's
+    ChainIdMismatch,
+
+    /// Request's timestamp is missing, negative, or too far from the host's
+    /// clock (per the chain's `max_clock_skew_secs`)
+    ClockSkew,
+
+    /// Validator exceeded its `max_requests_per_second`
+    RateLimited,
+
+    /// Requested step is before the last one signed at the same height/round
+    StepRegression,
+
+    /// Vote's validator address isn't that of the chain's consensus key
+    ValidatorAddress,
+
+    /// Request wasn't signed within the validator's `request_deadline_ms`
+    Deadline,
+
+    /// Request arrived while the validator's `request_queue_depth` others
+    /// were queued
+    QueueFull,
+}
+
+impl RefusalReason {
+    /// Get the label value for this reason
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RefusalReason::DoubleSign => "double_sign",
+            RefusalReason::MaxHeight => "max_height",
+            RefusalReason::MinHeight => "min_height",
+            RefusalReason::BlockedHeight => "blocked_height",
+            RefusalReason::MsgType => "msg_type",
+            RefusalReason::Role => "role",
+            RefusalReason::ChainIdMismatch => "chain_id_mismatch",
+            RefusalReason::ClockSkew => "clock_skew",
+            RefusalReason::RateLimited => "rate_limited",
+            RefusalReason::StepRegression => "step_regression",
+            RefusalReason::ValidatorAddress => "validator_address",
+            RefusalReason::Deadline => "deadline",
+            RefusalReason::QueueFull => "queue_full",
+        }
+    }
+}
+
+/// Record a successfully signed message of the given type
+pub fn signed(chain_id: &chain::Id, msg_type: SignedMsgType) {
+    let counter = match msg_type {
+        SignedMsgType::Proposal => &SIGNED_PROPOSALS,
+        SignedMsgType::PreVote | SignedMsgType::PreCommit => &SIGNED_VOTES,
+    };
+
+    counter.with_label_values(&[chain_id.as_str()]).inc();
+}
+
+/// Record a refused signing request
+pub fn refused(chain_id: &chain::Id, reason: RefusalReason) {
+    REFUSED_REQUESTS
+        .with_label_values(&[chain_id.as_str(), reason.as_str()])
+        .inc();
+}
+
+/// Record a request from a validator which was of an unknown type or malformed
+pub fn malformed_request(chain_id: &chain::Id, validator: &str) {
+    MALFORMED_REQUESTS
+        .with_label_values(&[chain_id.as_str(), validator])
+        .inc();
+}
+
+/// Record a validator connection being reset due to an error
+pub fn connection_reset(chain_id: &chain::Id, validator: &str) {
+    CONNECTION_RESETS
+        .with_label_values(&[chain_id.as_str(), validator])
+        .inc();
+}
+
+/// Record a validator client being restarted after crashing
+pub fn client_restarted(chain_id: &chain::Id, validator: &str) {
+    CLIENT_RESTARTS
+        .with_label_values(&[chain_id.as_str(), validator])
+        .inc();
+}
+
+/// Record an alert being dropped
+pub fn alert_dropped(event: alerts::Event) {
+    ALERTS_DROPPED.with_label_values(&[event.as_str()]).inc();
+}
+
+/// Record a signing provider session being re-established after it was lost
+pub fn provider_session_recovered(provider: &str) {
+    PROVIDER_SESSION_RECOVERIES
+        .with_label_values(&[provider])
+        .inc();
+}
+
+/// Record a signing provider retrying a signature after a transient error
+pub fn provider_sign_retried(provider: &str) {
+    PROVIDER_SIGN_RETRIES.with_label_values(&[provider]).inc();
+}
+
+/// Record a validator connection whose peer ID didn't match the configured one
+pub fn peer_id_mismatch(expected_peer_id: &str) {
+    PEER_ID_MISMATCHES
+        .with_label_values(&[expected_peer_id])
+        .inc();
+}
+
+/// Record the number of heights which can still be signed before the given
+/// validator's `max_height` (`None` if it has none)
+pub fn max_height_remaining(chain_id: &chain::Id, validator: &str, remaining: Option<u64>) {
+    let labels = [chain_id.as_str(), validator];
+
+    match remaining {
+        Some(remaining) => MAX_HEIGHT_REMAINING
+            .with_label_values(&labels)
+            .set(i64::try_from(remaining).unwrap_or(i64::MAX)),
+        None => {
+            // Fails if the gauge was never set, which is fine
+            let _ = MAX_HEIGHT_REMAINING.remove_label_values(&labels);
+        }
+    }
+}
+
+/// Record the number of requests queued on the given validator's connection
+pub fn request_queue_depth(chain_id: &chain::Id, validator: &str, depth: usize) {
+    REQUEST_QUEUE_DEPTH
+        .with_label_values(&[chain_id.as_str(), validator])
+        .set(i64::try_from(depth).unwrap_or(i64::MAX));
+}
+
+/// Record the latest block height reported by the given chain's
+/// `rpc_endpoint`
+pub fn network_height(chain_id: &chain::Id, height: u64) {
+    NETWORK_HEIGHT
+        .with_label_values(&[chain_id.as_str()])
+        .set(i64::try_from(height).unwrap_or(i64::MAX));
+}
+
+/// Record the number of heights the last signed height of the given chain (as
+/// signed for by the validator with the given label, if any) is behind the
+/// network's (negative if ahead)
+pub fn signed_height_lag(chain_id: &chain::Id, label: Option<&str>, lag: i64) {
+    SIGNED_HEIGHT_LAG
+        .with_label_values(&[chain_id.as_str(), label.unwrap_or("")])
+        .set(lag);
+}
+
+/// Record a failed poll of the given chain's `rpc_endpoint`
+pub fn rpc_poll_failed(chain_id: &chain::Id) {
+    RPC_POLL_FAILURES
+        .with_label_values(&[chain_id.as_str()])
+        .inc();
+}
+
+/// Record the time taken by the signing provider
+pub fn signing_latency(chain_id: &chain::Id, provider: &str, latency: Duration) {
+    SIGNING_LATENCY
+        .with_label_values(&[chain_id.as_str(), provider])
+        .observe(latency.as_secs_f64());
+}
+
+/// Encode all metrics in the Prometheus text exposition format
+pub fn encode() -> String {
+    // Ensure all metrics are registered, even if they haven't been used yet
+    Lazy::force(&SIGNED_VOTES);
+    Lazy::force(&SIGNED_PROPOSALS);
+    Lazy::force(&REFUSED_REQUESTS);
+    Lazy::force(&CONNECTION_RESETS);
+    Lazy::force(&CLIENT_RESTARTS);
+    Lazy::force(&ALERTS_DROPPED);
+    Lazy::force(&PROVIDER_SESSION_RECOVERIES);
+    Lazy::force(&PROVIDER_SIGN_RETRIES);
+    Lazy::force(&PEER_ID_MISMATCHES);
+    Lazy::force(&MAX_HEIGHT_REMAINING);
+    Lazy::force(&REQUEST_QUEUE_DEPTH);
+    Lazy::force(&NETWORK_HEIGHT);
+    Lazy::force(&SIGNED_HEIGHT_LAG);
+    Lazy::force(&RPC_POLL_FAILURES);
+    Lazy::force(&SIGNING_LATENCY);
+
+    let mut buffer = vec![];
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .expect("error encoding metrics");
+
+    String::from_utf8(buffer).expect("metrics aren't valid UTF-8")
+}
+
+/// Spawn a thread serving the `/metrics` endpoint on the configured address
+pub fn spawn_server(config: &MetricsConfig) -> Result<(), Error> {
+    let listener = TcpListener::bind(config.listen_addr).map_err(|e| {
+        format_err!(
+            IoError,
+            "couldn't bind metrics listener to {}: {}",
+            config.listen_addr,
+            e
+        )
+    })?;
+
+    info!("serving metrics on http://{}/metrics", config.listen_addr);
+
+    thread::Builder::new()
+        .name("metrics".to_owned())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.and_then(handle_connection);
+
+                if let Err(e) = result {
+                    debug!("error serving metrics: {}", e);
+                }
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Handle a single HTTP request to the metrics endpoint
+fn handle_connection(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+
+    let mut request_line = String::new();
+    BufReader::new((&stream).take(8192)).read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", encode()),
+        (Some("GET"), _) => ("404 Not Found", String::new()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        TextEncoder::new().format_type(),
+        body.len(),
+        body
+    )?;
+
+    stream.flush()
+}
+
+/// Register a metric in the KMS's registry
+fn register<M>(metric: prometheus::Result<M>) -> M
+where
+    M: prometheus::core::Collector + Clone + 'static,
+{
+    let metric = metric.expect("invalid metric");
+
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("metric registered twice");
+
+    metric
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_labeled_metrics() {
+        let chain_id = "metrics-test-chain".parse().unwrap();
+
+        signed(&chain_id, SignedMsgType::Proposal);
+        signed(&chain_id, SignedMsgType::PreVote);
+        signed(&chain_id, SignedMsgType::PreCommit);
+        refused(&chain_id, RefusalReason::DoubleSign);
+        refused(&chain_id, RefusalReason::Role);
+        connection_reset(&chain_id, "tcp://127.0.0.1:26658");
+        client_restarted(&chain_id, "tcp://127.0.0.1:26658");
+        signing_latency(&chain_id, "metrics-test-provider", Duration::from_millis(3));
+        alert_dropped(alerts::Event::ProviderError);
+        provider_session_recovered("metrics-test-provider");
+        provider_sign_retried("metrics-test-provider");
+        peer_id_mismatch("metrics-test-peer");
+        network_height(&chain_id, 1000);
+        signed_height_lag(&chain_id, Some("metrics-test-label"), -1);
+        rpc_poll_failed(&chain_id);
+
+        let metrics = encode();
+
+        for expected in &[
+            "tmkms_signed_proposals_total{chain_id=\"metrics-test-chain\"} 1",
+            "tmkms_signed_votes_total{chain_id=\"metrics-test-chain\"} 2",
+            "tmkms_refused_requests_total{chain_id=\"metrics-test-chain\",reason=\"double_sign\"} 1",
+            "tmkms_refused_requests_total{chain_id=\"metrics-test-chain\",reason=\"role\"} 1",
+            "tmkms_connection_resets_total{chain_id=\"metrics-test-chain\",validator=\"tcp://127.0.0.1:26658\"} 1",
+            "tmkms_client_restarts_total{chain_id=\"metrics-test-chain\",validator=\"tcp://127.0.0.1:26658\"} 1",
+            "tmkms_signing_latency_seconds_count{chain_id=\"metrics-test-chain\",provider=\"metrics-test-provider\"} 1",
+            "tmkms_alerts_dropped_total{event=\"provider_error\"} 1",
+            "tmkms_provider_session_recoveries_total{provider=\"metrics-test-provider\"} 1",
+            "tmkms_provider_sign_retries_total{provider=\"metrics-test-provider\"} 1",
+            "tmkms_peer_id_mismatches_total{expected_peer_id=\"metrics-test-peer\"} 1",
+            "tmkms_network_height{chain_id=\"metrics-test-chain\"} 1000",
+            "tmkms_signed_height_lag{chain_id=\"metrics-test-chain\",label=\"metrics-test-label\"} -1",
+            "tmkms_rpc_poll_failures_total{chain_id=\"metrics-test-chain\"} 1",
+        ] {
+            assert!(metrics.contains(expected), "missing {} in:\n{}", expected, metrics);
+        }
+    }
+}
@@ -0,0 +1,498 @@
+//! Dropping root privileges once the KMS has started.
+//!
+//! `tmkms start` may need root to open HSM device nodes or bind privileged
+//! ports, but shouldn't keep it. With `run_as_user`/`run_as_group` (and
+//! optionally `chroot_dir`) configured, privileges are dropped after listener
+//! sockets are bound, keys are loaded, and HSM sessions are established.
+//!
+//! Once chrooted, absolute paths of files written at runtime (consensus
+//! state, audit logs, the status file) are translated to the same file
+//! inside the chroot with [`resolve`], so they must lie inside `chroot_dir`.
+
+use crate::{
+    config::KmsConfig,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use once_cell::sync::OnceCell;
+use std::{
+    borrow::Cow,
+    env,
+    ffi::CString,
+    io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    ptr,
+};
+
+/// Directory the KMS has been chrooted into (if any)
+static CHROOT_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// Size of the buffer for the strings in `passwd` and `group` entries
+const LOOKUP_BUFFER_SIZE: usize = 16384;
+
+/// Privileges to drop to once the KMS has started
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Privileges {
+    /// User ID to switch to
+    pub uid: Option<libc::uid_t>,
+
+    /// Group ID to switch to (supplementary groups are cleared)
+    pub gid: Option<libc::gid_t>,
+
+    /// Directory to chroot into
+    pub chroot_dir: Option<PathBuf>,
+}
+
+impl Privileges {
+    /// Get the privileges configured with `run_as_user`, `run_as_group`, and
+    /// `chroot_dir`, looking up user and group names (which must happen
+    /// before entering the chroot, where `/etc/passwd` may not exist)
+    pub fn from_config(config: &KmsConfig) -> Result<Self, Error> {
+        if config.run_as_user.is_some() && config.run_as_group.is_none() {
+            fail!(ConfigError, "`run_as_user` requires `run_as_group`");
+        }
+
+        if let Some(dir) = &config.chroot_dir {
+            if !dir.is_absolute() {
+                fail!(
+                    ConfigError,
+                    "`chroot_dir` must be an absolute path: {}",
+                    dir.display()
+                );
+            }
+
+            if let Some(path) = runtime_paths(config)
+                .into_iter()
+                .find(|path| path.is_absolute() && !path.starts_with(dir))
+            {
+                fail!(
+                    ConfigError,
+                    "{} is written to after startup, so it must be inside `chroot_dir` ({})",
+                    path.display(),
+                    dir.display()
+                );
+            }
+        }
+
+        Ok(Self {
+            uid: config
+                .run_as_user
+                .as_deref()
+                .map(|user| lookup_uid(user).map_err(privilege_error))
+                .transpose()?,
+            gid: config
+                .run_as_group
+                .as_deref()
+                .map(|group| lookup_gid(group).map_err(privilege_error))
+                .transpose()?,
+            chroot_dir: config.chroot_dir.clone(),
+        })
+    }
+
+    /// Are any privileges to be dropped?
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Drop privileges: enter the chroot (if any), then clear supplementary
+    /// groups and switch group and user ID. Any failure is fatal.
+    pub fn apply(&self) -> Result<(), Error> {
+        self.apply_with(&mut Libc)
+    }
+
+    /// Drop privileges using the given system calls.
+    ///
+    /// The chroot comes first as it requires root, and the group ID is set
+    /// before the user ID for the same reason. Regaining root afterwards must
+    /// fail, or privileges weren't actually dropped.
+    fn apply_with(&self, sys: &mut impl Syscalls) -> Result<(), Error> {
+        if let Some(dir) = &self.chroot_dir {
+            // Keep relative paths working if the working directory is inside
+            // the chroot
+            let cwd = sys.current_dir()?;
+
+            sys.chroot(dir)
+                .map_err(|e| format_err!(PrivilegeError, "chroot to {}: {}", dir.display(), e))?;
+
+            let new_cwd = rebase(&cwd, dir).unwrap_or_else(|| {
+                warn!(
+                    "working directory {} is outside `chroot_dir`: relative paths are now relative to {}",
+                    cwd.display(),
+                    dir.display()
+                );
+                PathBuf::from("/")
+            });
+
+            sys.chdir(&new_cwd).map_err(|e| {
+                format_err!(PrivilegeError, "chdir to {}: {}", new_cwd.display(), e)
+            })?;
+        }
+
+        if let Some(gid) = self.gid {
+            sys.setgroups(&[])
+                .map_err(|e| format_err!(PrivilegeError, "clearing supplementary groups: {}", e))?;
+
+            sys.setgid(gid)
+                .map_err(|e| format_err!(PrivilegeError, "setgid to {}: {}", gid, e))?;
+        }
+
+        if let Some(uid) = self.uid {
+            sys.setuid(uid)
+                .map_err(|e| format_err!(PrivilegeError, "setuid to {}: {}", uid, e))?;
+
+            if uid != 0 && sys.setuid(0).is_ok() {
+                fail!(
+                    PrivilegeError,
+                    "still able to regain root after switching to uid {}",
+                    uid
+                );
+            }
+        }
+
+        info!(
+            "dropped privileges (uid: {}, gid: {}, chroot: {})",
+            display_id(self.uid),
+            display_id(self.gid),
+            self.chroot_dir
+                .as_ref()
+                .map_or_else(|| "none".to_owned(), |dir| dir.display().to_string())
+        );
+
+        Ok(())
+    }
+}
+
+/// Translate an absolute path to the same file inside the chroot the KMS has
+/// entered (if any). Other paths are returned as-is.
+pub fn resolve(path: &Path) -> Cow<'_, Path> {
+    match CHROOT_DIR.get().and_then(|dir| rebase(path, dir)) {
+        Some(path) => Cow::Owned(path),
+        None => Cow::Borrowed(path),
+    }
+}
+
+/// Translate an absolute path inside `root` to the same path relative to it
+/// (i.e. as seen from inside a chroot to `root`)
+fn rebase(path: &Path, root: &Path) -> Option<PathBuf> {
+    path.strip_prefix(root)
+        .ok()
+        .filter(|_| path.is_absolute())
+        .map(|rest| Path::new("/").join(rest))
+}
+
+/// Paths of the files the KMS writes to after startup
+fn runtime_paths(config: &KmsConfig) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = config
+        .signers()
+        .iter()
+        .filter(|(_, chain)| chain.state_backend == crate::config::chain::StateBackend::Json)
+        .map(|(_, chain)| crate::chain::state_file_path(chain))
+        .collect();
+
+    paths.extend(
+        config
+            .chain
+            .iter()
+            .filter_map(|chain| chain.audit_log.as_ref())
+            .chain(config.audit_log.as_ref())
+            .map(|audit_log| audit_log.path.clone()),
+    );
+
+    paths.extend(
+        config
+            .validator
+            .iter()
+            .filter_map(|validator| validator.protocol_capture_dir.clone()),
+    );
+
+    paths.extend(config.status_file.clone());
+    paths
+}
+
+/// Look up the uid of the given user name (or numeric uid)
+#[allow(unsafe_code)]
+pub fn lookup_uid(user: &str) -> Result<libc::uid_t, String> {
+    if let Ok(uid) = user.parse() {
+        return Ok(uid);
+    }
+
+    let name = CString::new(user).map_err(|_| format!("invalid user name `{}`", user))?;
+    let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; LOOKUP_BUFFER_SIZE];
+    let mut result = ptr::null_mut();
+
+    let rc = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut entry,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+
+    if rc != 0 || result.is_null() {
+        return Err(format!("no such user `{}`", user));
+    }
+
+    Ok(entry.pw_uid)
+}
+
+/// Look up the gid of the given group name (or numeric gid)
+#[allow(unsafe_code)]
+pub fn lookup_gid(group: &str) -> Result<libc::gid_t, String> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+
+    let name = CString::new(group).map_err(|_| format!("invalid group name `{}`", group))?;
+    let mut entry: libc::group = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; LOOKUP_BUFFER_SIZE];
+    let mut result = ptr::null_mut();
+
+    let rc = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut entry,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+
+    if rc != 0 || result.is_null() {
+        return Err(format!("no such group `{}`", group));
+    }
+
+    Ok(entry.gr_gid)
+}
+
+/// Create a `PrivilegeError` with the given message
+fn privilege_error(msg: String) -> Error {
+    format_err!(PrivilegeError, msg).into()
+}
+
+/// Display a uid or gid which is left unchanged if absent
+fn display_id(id: Option<u32>) -> String {
+    id.map_or_else(|| "(unchanged)".to_owned(), |id| id.to_string())
+}
+
+/// System calls used to drop privileges
+trait Syscalls {
+    /// Get the current working directory
+    fn current_dir(&mut self) -> io::Result<PathBuf>;
+
+    /// Change the root directory
+    fn chroot(&mut self, dir: &Path) -> io::Result<()>;
+
+    /// Change the working directory
+    fn chdir(&mut self, dir: &Path) -> io::Result<()>;
+
+    /// Set the supplementary group IDs
+    fn setgroups(&mut self, groups: &[libc::gid_t]) -> io::Result<()>;
+
+    /// Set the (real, effective, and saved) group ID
+    fn setgid(&mut self, gid: libc::gid_t) -> io::Result<()>;
+
+    /// Set the (real, effective, and saved) user ID
+    fn setuid(&mut self, uid: libc::uid_t) -> io::Result<()>;
+}
+
+/// System calls of the running process (glibc applies the credential
+/// changes to all of its threads)
+struct Libc;
+
+impl Syscalls for Libc {
+    fn current_dir(&mut self) -> io::Result<PathBuf> {
+        env::current_dir()
+    }
+
+    #[allow(unsafe_code)]
+    fn chroot(&mut self, dir: &Path) -> io::Result<()> {
+        let c_dir = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        check(unsafe { libc::chroot(c_dir.as_ptr()) })?;
+
+        // Paths are translated from now on, as the next write may already
+        // happen on another thread
+        CHROOT_DIR.set(dir.to_owned()).ok();
+        Ok(())
+    }
+
+    fn chdir(&mut self, dir: &Path) -> io::Result<()> {
+        env::set_current_dir(dir)
+    }
+
+    #[allow(unsafe_code)]
+    fn setgroups(&mut self, groups: &[libc::gid_t]) -> io::Result<()> {
+        check(unsafe { libc::setgroups(groups.len() as _, groups.as_ptr()) })
+    }
+
+    #[allow(unsafe_code)]
+    fn setgid(&mut self, gid: libc::gid_t) -> io::Result<()> {
+        check(unsafe { libc::setgid(gid) })
+    }
+
+    #[allow(unsafe_code)]
+    fn setuid(&mut self, uid: libc::uid_t) -> io::Result<()> {
+        check(unsafe { libc::setuid(uid) })
+    }
+}
+
+/// Convert the return code of a system call into a result
+fn check(rc: libc::c_int) -> io::Result<()> {
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// System calls of a process running as root, recording the calls made
+    #[derive(Default)]
+    struct MockSyscalls {
+        /// Calls made so far
+        calls: Vec<String>,
+
+        /// Call which fails (if any)
+        failing: Option<&'static str>,
+
+        /// Current user ID
+        uid: libc::uid_t,
+
+        /// Ignore `setuid`, as if privileges couldn't actually be dropped
+        ignore_setuid: bool,
+    }
+
+    impl MockSyscalls {
+        fn call(&mut self, name: &'static str, call: String) -> io::Result<()> {
+            self.calls.push(call);
+
+            if self.failing == Some(name) {
+                Err(io::Error::from_raw_os_error(libc::EPERM))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl Syscalls for MockSyscalls {
+        fn current_dir(&mut self) -> io::Result<PathBuf> {
+            Ok(PathBuf::from("/var/lib/tmkms/state"))
+        }
+
+        fn chroot(&mut self, dir: &Path) -> io::Result<()> {
+            self.call("chroot", format!("chroot {}", dir.display()))
+        }
+
+        fn chdir(&mut self, dir: &Path) -> io::Result<()> {
+            self.call("chdir", format!("chdir {}", dir.display()))
+        }
+
+        fn setgroups(&mut self, groups: &[libc::gid_t]) -> io::Result<()> {
+            self.call("setgroups", format!("setgroups {:?}", groups))
+        }
+
+        fn setgid(&mut self, gid: libc::gid_t) -> io::Result<()> {
+            self.call("setgid", format!("setgid {}", gid))
+        }
+
+        fn setuid(&mut self, uid: libc::uid_t) -> io::Result<()> {
+            self.call("setuid", format!("setuid {}", uid))?;
+
+            if self.uid != 0 && uid != self.uid {
+                return Err(io::Error::from_raw_os_error(libc::EPERM));
+            }
+
+            if !self.ignore_setuid {
+                self.uid = uid;
+            }
+
+            Ok(())
+        }
+    }
+
+    fn privileges() -> Privileges {
+        Privileges {
+            uid: Some(1000),
+            gid: Some(1001),
+            chroot_dir: Some(PathBuf::from("/var/lib/tmkms")),
+        }
+    }
+
+    #[test]
+    fn drops_in_order() {
+        let mut sys = MockSyscalls::default();
+        privileges().apply_with(&mut sys).unwrap();
+
+        assert_eq!(
+            sys.calls,
+            [
+                "chroot /var/lib/tmkms",
+                "chdir /state",
+                "setgroups []",
+                "setgid 1001",
+                "setuid 1000",
+                "setuid 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn failure_stops_dropping() {
+        for (failing, calls) in [("chroot", 1), ("setgroups", 3), ("setgid", 4)] {
+            let mut sys = MockSyscalls {
+                failing: Some(failing),
+                ..Default::default()
+            };
+
+            let err = privileges().apply_with(&mut sys).unwrap_err();
+            assert_eq!(*err.kind(), PrivilegeError);
+            assert_eq!(sys.calls.len(), calls, "{}: {:?}", failing, sys.calls);
+        }
+    }
+
+    #[test]
+    fn regaining_root_is_fatal() {
+        let mut sys = MockSyscalls {
+            ignore_setuid: true,
+            ..Default::default()
+        };
+
+        let err = privileges().apply_with(&mut sys).unwrap_err();
+        assert!(err.to_string().contains("still able to regain root"));
+    }
+
+    #[test]
+    fn chroot_only() {
+        let privileges = Privileges {
+            chroot_dir: Some(PathBuf::from("/srv/tmkms")),
+            ..Default::default()
+        };
+
+        let mut sys = MockSyscalls::default();
+        privileges.apply_with(&mut sys).unwrap();
+
+        // The working directory is outside the chroot
+        assert_eq!(sys.calls, ["chroot /srv/tmkms", "chdir /"]);
+    }
+
+    #[test]
+    fn paths_inside_chroot() {
+        let root = Path::new("/var/lib/tmkms");
+
+        assert_eq!(
+            rebase(Path::new("/var/lib/tmkms/state/cosmoshub.json"), root).unwrap(),
+            Path::new("/state/cosmoshub.json")
+        );
+        assert_eq!(rebase(root, root).unwrap(), Path::new("/"));
+        assert!(rebase(Path::new("/var/lib/tmkms2/state.json"), root).is_none());
+        assert!(rebase(Path::new("state.json"), root).is_none());
+    }
+}
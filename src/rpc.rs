@@ -3,7 +3,10 @@
 // TODO: docs for everything
 #![allow(missing_docs)]
 
-use std::io::Read;
+use std::{
+    error::Error as _,
+    io::{Read, Write},
+};
 
 use bytes_v0_5::Bytes;
 use prost::Message as _;
@@ -11,8 +14,10 @@ use prost_amino::{encoding::decode_varint, Message as _};
 use tendermint_p2p::secret_connection::DATA_MAX_SIZE;
 use tendermint_proto as proto;
 
+pub mod v0_38;
+
 use crate::{
-    amino_types,
+    amino_types::{self, RemoteError, SignableMsg as _},
     config::validator::ProtocolVersion,
     error::{Error, ErrorKind},
     prelude::*,
@@ -28,82 +33,230 @@ pub enum Request {
 
     // PingRequest is a PrivValidatorSocket message to keep the connection alive.
     ReplyPing(amino_types::PingRequest),
+
+    /// Request of a type the KMS doesn't support (or doesn't know), which is
+    /// refused without dropping the connection
+    Unsupported(UnsupportedRequest),
+}
+
+/// Protobuf request the KMS doesn't support
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct UnsupportedRequest {
+    /// Field number of the request in `tendermint.privval.Message`
+    pub tag: u32,
+
+    /// Type URL of the request, if it's of a known type
+    pub type_url: Option<&'static str>,
+
+    /// Field number of the response which reports errors for this request,
+    /// if there is one. Requests of unknown types get no response at all.
+    pub response_tag: Option<u32>,
+}
+
+impl UnsupportedRequest {
+    /// Describe the request's type
+    pub fn describe(&self) -> String {
+        match self.type_url {
+            Some(type_url) => type_url.to_owned(),
+            None => format!("unknown message type (field {})", self.tag),
+        }
+    }
 }
 
 impl Request {
-    /// Read a request from the given readable
-    pub fn read(conn: &mut impl Read, protocol_version: ProtocolVersion) -> Result<Self, Error> {
-        let msg = read_msg(conn)?;
+    /// Read a request of at most `max_msg_size` bytes from the given readable
+    /// with the given reader.
+    ///
+    /// Messages of an unknown (or unsupported) type, or which can't be
+    /// decoded, fail with a [`ErrorKind::MalformedRequest`] error describing
+    /// the message's declared length and first bytes.
+    pub fn read(
+        reader: &mut MsgReader,
+        conn: &mut impl Read,
+        protocol_version: ProtocolVersion,
+        max_msg_size: usize,
+    ) -> Result<Self, Error> {
+        Self::from_msg(&reader.read_msg(conn, max_msg_size)?, protocol_version)
+    }
+
+    /// Decode a length-prefixed request read with [`MsgReader`], failing with
+    /// a [`ErrorKind::MalformedRequest`] error as [`Request::read`] does
+    pub fn from_msg(msg: &[u8], protocol_version: ProtocolVersion) -> Result<Self, Error> {
+        Self::decode(msg, protocol_version).map_err(|e| {
+            let reason = e
+                .source()
+                .map_or_else(|| e.kind().to_string(), ToString::to_string);
+
+            format_err!(
+                ErrorKind::MalformedRequest,
+                "{} (declared length: {} bytes, prefix: {})",
+                reason,
+                declared_len(msg),
+                hex_prefix(msg)
+            )
+            .into()
+        })
+    }
+
+    /// Chain ID the request names (if any, i.e. Protobuf-encoded requests
+    /// other than pings)
+    pub fn chain_id(&self) -> Option<&str> {
+        match self {
+            Request::SignProposal(req) => req.chain_id(),
+            Request::SignVote(req) => req.chain_id(),
+            Request::ShowPublicKey(req) => req.chain_id(),
+            Request::ReplyPing(_) | Request::Unsupported(_) => None,
+        }
+    }
 
+    /// Decode a length-prefixed request
+    fn decode(msg: &[u8], protocol_version: ProtocolVersion) -> Result<Self, Error> {
         if protocol_version.is_protobuf() {
-            // Parse Protobuf-encoded request message
-            let msg = proto::privval::Message::decode_length_delimited(msg.as_ref())
-                .map_err(|e| {
-                    format_err!(ErrorKind::ProtocolError, "malformed message packet: {}", e)
-                })?
-                .sum;
-
-            // TODO(tarcieri): transition natively to protobuf types
-            match msg {
-                Some(proto::privval::message::Sum::SignVoteRequest(req)) => {
-                    Ok(Request::SignVote(amino_types::SignVoteRequest {
-                        vote: req.vote.map(|vote| amino_types::Vote {
-                            vote_type: vote.r#type as u32,
-                            height: vote.height,
-                            round: vote.round as i64,
-                            block_id: vote.block_id.map(Into::into),
-                            timestamp: vote.timestamp.map(|ts| amino_types::TimeMsg {
-                                seconds: ts.seconds,
-                                nanos: ts.nanos,
-                            }),
-                            validator_address: vote.validator_address,
-                            validator_index: vote.validator_index as i64,
-                            signature: vote.signature,
-                        }),
-                    }))
-                }
-                Some(proto::privval::message::Sum::SignProposalRequest(req)) => {
-                    Ok(Request::SignProposal(amino_types::SignProposalRequest {
-                        proposal: req.proposal.map(|proposal| amino_types::Proposal {
-                            msg_type: proposal.r#type as u32,
-                            height: proposal.height,
-                            round: proposal.round as i64,
-                            pol_round: proposal.pol_round as i64,
-                            block_id: proposal.block_id.map(Into::into),
-                            timestamp: proposal.timestamp.map(|ts| amino_types::TimeMsg {
-                                seconds: ts.seconds,
-                                nanos: ts.nanos,
-                            }),
-                            signature: proposal.signature,
-                        }),
-                    }))
-                }
-                Some(proto::privval::message::Sum::PubKeyRequest(_)) => {
-                    Ok(Request::ShowPublicKey(amino_types::PubKeyRequest {}))
-                }
-                Some(proto::privval::message::Sum::PingRequest(_)) => {
-                    Ok(Request::ReplyPing(amino_types::PingRequest {}))
-                }
-                _ => fail!(ErrorKind::ProtocolError, "invalid RPC message: {:?}", msg),
+            // Check the whole message is well-formed before dispatching on
+            // its type (unknown message types are skipped by the decoder)
+            proto::privval::Message::decode_length_delimited(msg).map_err(|e| {
+                format_err!(
+                    ErrorKind::MalformedRequest,
+                    "undecodable Protobuf message: {}",
+                    e
+                )
+            })?;
+
+            let tag = match sum_tag(msg)? {
+                Some(tag) => tag,
+                None => fail!(ErrorKind::MalformedRequest, "empty request"),
+            };
+
+            match MessageType::find(tag, protocol_version) {
+                Some(MessageType {
+                    kind: MessageKind::Request(decode),
+                    ..
+                }) => decode(msg, protocol_version),
+                Some(MessageType {
+                    kind: MessageKind::Unsupported { response_tag },
+                    type_url,
+                    ..
+                }) => Ok(Request::Unsupported(UnsupportedRequest {
+                    tag,
+                    type_url: Some(type_url),
+                    response_tag: Some(*response_tag),
+                })),
+                Some(
+                    message_type @ MessageType {
+                        kind: MessageKind::Response,
+                        ..
+                    },
+                ) => fail!(
+                    ErrorKind::MalformedRequest,
+                    "unsupported request type: {}",
+                    message_type.name()
+                ),
+                None => Ok(Request::Unsupported(UnsupportedRequest {
+                    tag,
+                    type_url: None,
+                    response_tag: None,
+                })),
             }
         } else {
-            let amino_prefix = parse_amino_prefix(&msg)?;
+            let amino_prefix = parse_amino_prefix(msg)?;
 
             if amino_prefix == *amino_types::vote::AMINO_PREFIX {
-                let req = amino_types::SignVoteRequest::decode(msg.as_ref())?;
+                let req = amino_types::SignVoteRequest::decode(msg)?;
                 Ok(Request::SignVote(req))
             } else if amino_prefix == *amino_types::proposal::AMINO_PREFIX {
-                let req = amino_types::SignProposalRequest::decode(msg.as_ref())?;
+                let req = amino_types::SignProposalRequest::decode(msg)?;
                 Ok(Request::SignProposal(req))
             } else if amino_prefix == *amino_types::ed25519::AMINO_PREFIX {
-                let req = amino_types::PubKeyRequest::decode(msg.as_ref())?;
+                let req = amino_types::PubKeyRequest::decode(msg)?;
                 Ok(Request::ShowPublicKey(req))
             } else if amino_prefix == *amino_types::ping::AMINO_PREFIX {
-                let req = amino_types::PingRequest::decode(msg.as_ref())?;
+                let req = amino_types::PingRequest::decode(msg)?;
                 Ok(Request::ReplyPing(req))
             } else {
-                fail!(ErrorKind::ProtocolError, "received unknown RPC message");
+                fail!(
+                    ErrorKind::MalformedRequest,
+                    "unknown request type (Amino prefix: {})",
+                    encode_hex(&amino_prefix)
+                );
+            }
+        }
+    }
+
+    /// Fill in the vote extension of a `SignVoteRequest` from a CometBFT
+    /// v0.38 message (a no-op for all other requests)
+    fn read_vote_extension(&mut self, msg: &[u8]) -> Result<(), Error> {
+        if let Request::SignVote(req) = self {
+            let extension = v0_38::Message::decode_length_delimited(msg)?
+                .sign_vote_request
+                .and_then(|req| req.vote)
+                .map(|vote| vote.extension)
+                .unwrap_or_default();
+
+            if let Some(vote) = req.vote.as_mut() {
+                vote.extension = extension;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<proto::privval::message::Sum> for Request {
+    type Error = Error;
+
+    fn try_from(msg: proto::privval::message::Sum) -> Result<Self, Error> {
+        // TODO(tarcieri): transition natively to protobuf types
+        match msg {
+            proto::privval::message::Sum::SignVoteRequest(req) => {
+                Ok(Request::SignVote(amino_types::SignVoteRequest {
+                    vote: req.vote.map(|vote| amino_types::Vote {
+                        vote_type: vote.r#type as u32,
+                        height: vote.height,
+                        round: vote.round as i64,
+                        block_id: vote.block_id.map(Into::into),
+                        timestamp: vote.timestamp.map(|ts| amino_types::TimeMsg {
+                            seconds: ts.seconds,
+                            nanos: ts.nanos,
+                        }),
+                        validator_address: vote.validator_address,
+                        validator_index: vote.validator_index as i64,
+                        signature: vote.signature,
+                        extension: vec![],
+                        extension_signature: vec![],
+                    }),
+                    chain_id: req.chain_id,
+                }))
+            }
+            proto::privval::message::Sum::SignProposalRequest(req) => {
+                Ok(Request::SignProposal(amino_types::SignProposalRequest {
+                    proposal: req.proposal.map(|proposal| amino_types::Proposal {
+                        msg_type: proposal.r#type as u32,
+                        height: proposal.height,
+                        round: proposal.round as i64,
+                        pol_round: proposal.pol_round as i64,
+                        block_id: proposal.block_id.map(Into::into),
+                        timestamp: proposal.timestamp.map(|ts| amino_types::TimeMsg {
+                            seconds: ts.seconds,
+                            nanos: ts.nanos,
+                        }),
+                        signature: proposal.signature,
+                    }),
+                    chain_id: req.chain_id,
+                }))
             }
+            proto::privval::message::Sum::PubKeyRequest(req) => {
+                Ok(Request::ShowPublicKey(amino_types::PubKeyRequest {
+                    chain_id: req.chain_id,
+                }))
+            }
+            proto::privval::message::Sum::PingRequest(_) => {
+                Ok(Request::ReplyPing(amino_types::PingRequest {}))
+            }
+            other => fail!(
+                ErrorKind::MalformedRequest,
+                "unsupported request type: {}",
+                sum_name(&other)
+            ),
         }
     }
 }
@@ -115,81 +268,485 @@ pub enum Response {
     SignedVote(amino_types::SignedVoteResponse),
     SignedProposal(amino_types::SignedProposalResponse),
     Ping(amino_types::PingResponse),
-    PublicKey(amino_types::PubKeyResponse),
+    PublicKey(tendermint::PublicKey),
+
+    /// Public key request refused with the given error (e.g. for a chain the
+    /// validator isn't configured for)
+    PublicKeyRefused(RemoteError),
+
+    /// Error response to an unsupported request (empty when the request's
+    /// type is unknown, as there's no response type to use then)
+    Unsupported(UnsupportedRequest),
 }
 
 impl Response {
     /// Encode response to bytes
     pub fn encode(self, protocol_version: ProtocolVersion) -> Result<Vec<u8>, Error> {
         let mut buf = Vec::new();
+        self.encode_into(protocol_version, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Encode response to bytes, appending them to the given buffer (so a
+    /// connection's responses are encoded into the same buffer)
+    pub fn encode_into(
+        self,
+        protocol_version: ProtocolVersion,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        if let Response::Unsupported(req) = self {
+            if let (Some(response_tag), Some(type_url)) = (req.response_tag, req.type_url) {
+                let response = ErrorResponse {
+                    error: Some(RemoteError::unsupported_request(type_url).into()),
+                };
+
+                let body_len = prost::encoding::message::encoded_len(response_tag, &response);
+                prost::encode_length_delimiter(body_len, buf)?;
+                prost::encoding::message::encode(response_tag, &response, buf);
+            }
+
+            return Ok(());
+        }
+
+        if protocol_version.has_vote_extensions() {
+            if let Response::SignedVote(resp) = self {
+                let signed_vote_response = v0_38::SignedVoteResponse {
+                    vote: resp.vote.map(Into::into),
+                    error: resp.err.map(Into::into),
+                };
+
+                v0_38::Message {
+                    sign_vote_request: None,
+                    signed_vote_response: Some(signed_vote_response),
+                }
+                .encode_length_delimited(buf)?;
+
+                return Ok(());
+            }
+        }
+
         if protocol_version.is_protobuf() {
-            let msg = match self {
-                Response::SignedVote(resp) => proto::privval::message::Sum::SignedVoteResponse(
-                    proto::privval::SignedVoteResponse {
-                        vote: resp.vote.map(|vote| proto::types::Vote {
-                            r#type: vote.vote_type as i32,
-                            height: vote.height,
-                            round: vote.round as i32,
-                            block_id: vote.block_id.map(Into::into),
-                            timestamp: vote.timestamp.map(Into::into),
-                            validator_address: vote.validator_address,
-                            validator_index: vote.validator_index as i32,
-                            signature: vote.signature,
-                        }),
-                        error: None,
-                    },
-                ),
-                Response::SignedProposal(resp) => {
-                    proto::privval::message::Sum::SignedProposalResponse(
-                        proto::privval::SignedProposalResponse {
-                            proposal: resp.proposal.map(|proposal| proto::types::Proposal {
-                                r#type: proposal.msg_type as i32,
-                                height: proposal.height,
-                                round: proposal.round as i32,
-                                pol_round: proposal.pol_round as i32,
-                                block_id: proposal.block_id.map(Into::into),
-                                timestamp: proposal.timestamp.map(Into::into),
-                                signature: proposal.signature,
-                            }),
-                            error: None,
-                        },
-                    )
+            let msg = proto::privval::message::Sum::try_from(self)?;
+            proto::privval::Message { sum: Some(msg) }.encode_length_delimited(buf)?;
+        } else {
+            match self {
+                Response::SignedProposal(sp) => sp.encode(buf)?,
+                Response::SignedVote(sv) => sv.encode(buf)?,
+                Response::Ping(ping) => ping.encode(buf)?,
+                Response::PublicKey(pk @ tendermint::PublicKey::Ed25519(_)) => {
+                    amino_types::PubKeyResponse::from(pk).encode(buf)?
                 }
-                Response::Ping(_) => {
-                    proto::privval::message::Sum::PingResponse(proto::privval::PingResponse {})
+                Response::PublicKey(_) => fail!(
+                    ErrorKind::ProtocolError,
+                    "secp256k1 consensus keys require a Protobuf-based protocol version"
+                ),
+                // Amino public key responses can't carry an error, so the
+                // connection is dropped instead
+                Response::PublicKeyRefused(err) => fail!(
+                    ErrorKind::ProtocolError,
+                    "public key request refused: {}",
+                    err.description
+                ),
+                // Amino requests are never unsupported
+                Response::Unsupported(_) => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<Response> for proto::privval::message::Sum {
+    type Error = Error;
+
+    fn try_from(response: Response) -> Result<proto::privval::message::Sum, Error> {
+        Ok(match response {
+            Response::SignedVote(resp) => proto::privval::message::Sum::SignedVoteResponse(
+                proto::privval::SignedVoteResponse {
+                    vote: resp.vote.map(|vote| proto::types::Vote {
+                        r#type: vote.vote_type as i32,
+                        height: vote.height,
+                        round: vote.round as i32,
+                        block_id: vote.block_id.map(Into::into),
+                        timestamp: vote.timestamp.map(Into::into),
+                        validator_address: vote.validator_address,
+                        validator_index: vote.validator_index as i32,
+                        signature: vote.signature,
+                    }),
+                    error: resp.err.map(Into::into),
+                },
+            ),
+            Response::SignedProposal(resp) => proto::privval::message::Sum::SignedProposalResponse(
+                proto::privval::SignedProposalResponse {
+                    proposal: resp.proposal.map(|proposal| proto::types::Proposal {
+                        r#type: proposal.msg_type as i32,
+                        height: proposal.height,
+                        round: proposal.round as i32,
+                        pol_round: proposal.pol_round as i32,
+                        block_id: proposal.block_id.map(Into::into),
+                        timestamp: proposal.timestamp.map(Into::into),
+                        signature: proposal.signature,
+                    }),
+                    error: resp.err.map(Into::into),
+                },
+            ),
+            Response::Ping(_) => {
+                proto::privval::message::Sum::PingResponse(proto::privval::PingResponse {})
+            }
+            Response::PublicKey(pk) => {
+                proto::privval::message::Sum::PubKeyResponse(proto::privval::PubKeyResponse {
+                    pub_key: Some(pk.into()),
+                    error: None,
+                })
+            }
+            Response::PublicKeyRefused(err) => {
+                proto::privval::message::Sum::PubKeyResponse(proto::privval::PubKeyResponse {
+                    pub_key: None,
+                    error: Some(err.into()),
+                })
+            }
+            Response::Unsupported(req) => fail!(
+                ErrorKind::ProtocolError,
+                "no response type for {}",
+                req.describe()
+            ),
+        })
+    }
+}
+
+/// Any `tendermint.privval` response message with only its `error` field set
+/// (which is field 2 in all of them)
+#[derive(Clone, PartialEq, prost_derive::Message)]
+struct ErrorResponse {
+    #[prost(message, optional, tag = "2")]
+    error: Option<proto::privval::RemoteSignerError>,
+}
+
+/// Type of a `tendermint.privval.Message`
+struct MessageType {
+    /// Field number of the message in the `sum` oneof
+    tag: u32,
+
+    /// Type URL of the message
+    type_url: &'static str,
+
+    /// Oldest protocol version which has this message
+    since: ProtocolVersion,
+
+    /// How the KMS handles the message
+    kind: MessageKind,
+}
+
+/// How the KMS handles a type of message
+enum MessageKind {
+    /// Request handled by the KMS, and its decoder
+    Request(fn(&[u8], ProtocolVersion) -> Result<Request, Error>),
+
+    /// Request the KMS doesn't support, refused with an error set in the
+    /// response message with the given field number
+    Unsupported { response_tag: u32 },
+
+    /// Response from the KMS, which is never a valid request
+    Response,
+}
+
+/// Types of `tendermint.privval.Message`s. Supporting a new message only
+/// takes an entry here (along with its decoder).
+const MESSAGE_TYPES: &[MessageType] = &[
+    MessageType {
+        tag: 1,
+        type_url: "/tendermint.privval.PubKeyRequest",
+        since: ProtocolVersion::V0_34,
+        kind: MessageKind::Request(decode_request),
+    },
+    MessageType {
+        tag: 2,
+        type_url: "/tendermint.privval.PubKeyResponse",
+        since: ProtocolVersion::V0_34,
+        kind: MessageKind::Response,
+    },
+    MessageType {
+        tag: 3,
+        type_url: "/tendermint.privval.SignVoteRequest",
+        since: ProtocolVersion::V0_34,
+        kind: MessageKind::Request(decode_sign_vote_request),
+    },
+    MessageType {
+        tag: 4,
+        type_url: "/tendermint.privval.SignedVoteResponse",
+        since: ProtocolVersion::V0_34,
+        kind: MessageKind::Response,
+    },
+    MessageType {
+        tag: 5,
+        type_url: "/tendermint.privval.SignProposalRequest",
+        since: ProtocolVersion::V0_34,
+        kind: MessageKind::Request(decode_request),
+    },
+    MessageType {
+        tag: 6,
+        type_url: "/tendermint.privval.SignedProposalResponse",
+        since: ProtocolVersion::V0_34,
+        kind: MessageKind::Response,
+    },
+    MessageType {
+        tag: 7,
+        type_url: "/tendermint.privval.PingRequest",
+        since: ProtocolVersion::V0_34,
+        kind: MessageKind::Request(decode_request),
+    },
+    MessageType {
+        tag: 8,
+        type_url: "/tendermint.privval.PingResponse",
+        since: ProtocolVersion::V0_34,
+        kind: MessageKind::Response,
+    },
+    // Added in CometBFT v1.0, which otherwise speaks the v0.38 protocol
+    MessageType {
+        tag: 9,
+        type_url: "/cometbft.privval.v1.SignBytesRequest",
+        since: ProtocolVersion::V0_38,
+        kind: MessageKind::Unsupported { response_tag: 10 },
+    },
+    MessageType {
+        tag: 10,
+        type_url: "/cometbft.privval.v1.SignBytesResponse",
+        since: ProtocolVersion::V0_38,
+        kind: MessageKind::Response,
+    },
+];
+
+impl MessageType {
+    /// Find the type of message with the given field number in the given
+    /// protocol version
+    fn find(tag: u32, protocol_version: ProtocolVersion) -> Option<&'static MessageType> {
+        MESSAGE_TYPES.iter().find(|message_type| {
+            message_type.tag == tag && protocol_version.is_at_least(message_type.since)
+        })
+    }
+
+    /// Name of the message type, without its package
+    fn name(&self) -> &'static str {
+        self.type_url.rsplit('.').next().unwrap_or(self.type_url)
+    }
+}
+
+/// Decode a request of a type `tendermint-proto` knows about
+fn decode_request(msg: &[u8], _protocol_version: ProtocolVersion) -> Result<Request, Error> {
+    match proto::privval::Message::decode_length_delimited(msg)?.sum {
+        Some(sum) => Request::try_from(sum),
+        None => fail!(ErrorKind::MalformedRequest, "unknown request type"),
+    }
+}
+
+/// Decode a `SignVoteRequest`, including its vote extension (if any)
+fn decode_sign_vote_request(
+    msg: &[u8],
+    protocol_version: ProtocolVersion,
+) -> Result<Request, Error> {
+    let mut request = decode_request(msg, protocol_version)?;
+
+    if protocol_version.has_vote_extensions() {
+        request.read_vote_extension(msg)?;
+    }
+
+    Ok(request)
+}
+
+/// Get the field number of the `sum` member set in a length-prefixed
+/// `tendermint.privval.Message` (if any)
+fn sum_tag(msg: &[u8]) -> Result<Option<u32>, Error> {
+    let mut buf = msg;
+    prost::decode_length_delimiter(&mut buf)?;
+
+    if buf.is_empty() {
+        return Ok(None);
+    }
+
+    let (tag, _) = prost::encoding::decode_key(&mut buf)?;
+    Ok(Some(tag))
+}
+
+/// Maximum length of a varint-encoded `u64`
+const MAX_VARINT_LEN: usize = 10;
+
+/// Reader of length-prefixed messages from a connection.
+///
+/// The connection is read from a frame at a time (i.e. `DATA_MAX_SIZE` bytes,
+/// as a Secret Connection can't be read from into a smaller buffer), so a
+/// read may return bytes past the end of the message being read (i.e. the
+/// start of the next one): these are kept for the next message.
+// TODO(tarcieri): extract this into Secret Connection
+#[derive(Debug, Default)]
+pub struct MsgReader {
+    /// Bytes read from the connection which aren't part of a message returned
+    /// yet
+    buffered: Vec<u8>,
+}
+
+impl MsgReader {
+    /// Create a reader with nothing buffered
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a length-prefixed message of at most `max_msg_size` bytes (not
+    /// counting its length prefix) from the given connection, returning it
+    /// along with its length prefix.
+    ///
+    /// Once its length prefix has been read, the connection is read from
+    /// until the declared length has been read or the read times out.
+    pub fn read_msg(
+        &mut self,
+        conn: &mut impl Read,
+        max_msg_size: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let mut msg = Vec::new();
+        self.read_msg_into(conn, max_msg_size, &mut msg)?;
+        Ok(msg)
+    }
+
+    /// Read a length-prefixed message as [`MsgReader::read_msg`] does, into
+    /// the given buffer (replacing its contents), so a connection's messages
+    /// are read without allocating once its buffers have grown to fit them
+    pub fn read_msg_into(
+        &mut self,
+        conn: &mut impl Read,
+        max_msg_size: usize,
+        msg: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        loop {
+            if let Some(msg_len) = parse_length_prefix(&self.buffered, max_msg_size)? {
+                if self.buffered.len() >= msg_len {
+                    msg.clear();
+                    msg.extend_from_slice(&self.buffered[..msg_len]);
+                    self.buffered.drain(..msg_len);
+                    return Ok(());
                 }
-                Response::PublicKey(pk) => {
-                    let pk = proto::crypto::PublicKey {
-                        sum: Some(proto::crypto::public_key::Sum::Ed25519(pk.pub_key_ed25519)),
-                    };
-
-                    proto::privval::message::Sum::PubKeyResponse(proto::privval::PubKeyResponse {
-                        pub_key: Some(pk),
-                        error: None,
-                    })
+
+                self.buffered.reserve(msg_len - self.buffered.len());
+            }
+
+            // Frames are read straight into the end of the buffer
+            let buffered_len = self.buffered.len();
+            self.buffered.resize(buffered_len + DATA_MAX_SIZE, 0);
+
+            let frame_len = match conn.read(&mut self.buffered[buffered_len..]) {
+                Ok(frame_len) => frame_len,
+                Err(e) => {
+                    self.buffered.truncate(buffered_len);
+                    return Err(e.into());
                 }
             };
 
-            proto::privval::Message { sum: Some(msg) }.encode_length_delimited(&mut buf)?;
-        } else {
-            match self {
-                Response::SignedProposal(sp) => sp.encode(&mut buf)?,
-                Response::SignedVote(sv) => sv.encode(&mut buf)?,
-                Response::Ping(ping) => ping.encode(&mut buf)?,
-                Response::PublicKey(pk) => pk.encode(&mut buf)?,
+            self.buffered.truncate(buffered_len + frame_len);
+
+            if frame_len == 0 {
+                fail!(
+                    ErrorKind::ProtocolError,
+                    "connection closed after {} bytes of message",
+                    self.buffered.len()
+                );
             }
         }
-        Ok(buf)
     }
 }
 
-/// Read a message from a Secret Connection
+/// Write a length-prefixed message to the given connection, a frame (i.e.
+/// `DATA_MAX_SIZE` bytes) at a time, as a Secret Connection mangles writes
+/// spanning several frames
 // TODO(tarcieri): extract this into Secret Connection
-fn read_msg(conn: &mut impl Read) -> Result<Vec<u8>, Error> {
-    let mut buf = vec![0; DATA_MAX_SIZE];
-    let buf_read = conn.read(&mut buf)?;
-    buf.truncate(buf_read);
-    Ok(buf)
+pub fn write_msg(conn: &mut impl Write, msg: &[u8]) -> Result<(), Error> {
+    for frame in msg.chunks(DATA_MAX_SIZE) {
+        conn.write_all(frame)?;
+    }
+
+    Ok(())
+}
+
+/// Parse the varint length prefix at the start of the given buffer, returning
+/// the length of the entire message (including its prefix), or `None` if the
+/// prefix is incomplete
+fn parse_length_prefix(buf: &[u8], max_msg_size: usize) -> Result<Option<usize>, Error> {
+    let mut len = 0u64;
+
+    for (i, byte) in buf.iter().enumerate() {
+        if i == MAX_VARINT_LEN {
+            break;
+        }
+
+        len |= u64::from(byte & 0x7f) << (7 * i);
+
+        if len > max_msg_size as u64 {
+            fail!(
+                ErrorKind::ProtocolError,
+                "message too large (declared length exceeds max_msg_size of {} bytes)",
+                max_msg_size
+            );
+        }
+
+        if byte & 0x80 == 0 {
+            return Ok(Some(i + 1 + len as usize));
+        }
+    }
+
+    if buf.len() >= MAX_VARINT_LEN {
+        fail!(ErrorKind::ProtocolError, "malformed message length prefix");
+    }
+
+    Ok(None)
+}
+
+/// Maximum number of bytes of a malformed message included in errors
+const MALFORMED_PREFIX_LEN: usize = 16;
+
+/// Get the length a length-prefixed message declares (excluding the prefix)
+fn declared_len(msg: &[u8]) -> usize {
+    match parse_length_prefix(msg, usize::MAX) {
+        Ok(Some(len)) => len - varint_len(msg),
+        _ => 0,
+    }
+}
+
+/// Get the length of the varint at the start of the given buffer
+fn varint_len(buf: &[u8]) -> usize {
+    buf.iter()
+        .take(MAX_VARINT_LEN)
+        .position(|byte| byte & 0x80 == 0)
+        .map_or(buf.len().min(MAX_VARINT_LEN), |i| i + 1)
+}
+
+/// Hex encode the first bytes of a message's body (after its length prefix)
+fn hex_prefix(msg: &[u8]) -> String {
+    let body = &msg[varint_len(msg)..];
+    let prefix = encode_hex(&body[..body.len().min(MALFORMED_PREFIX_LEN)]);
+
+    if body.len() > MALFORMED_PREFIX_LEN {
+        format!("{}...", prefix)
+    } else {
+        prefix
+    }
+}
+
+/// Encode the given bytes as uppercase hex
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+/// Get the name of a Protobuf `privval` message type
+fn sum_name(sum: &proto::privval::message::Sum) -> &'static str {
+    use proto::privval::message::Sum;
+
+    match sum {
+        Sum::PubKeyRequest(_) => "PubKeyRequest",
+        Sum::PubKeyResponse(_) => "PubKeyResponse",
+        Sum::SignVoteRequest(_) => "SignVoteRequest",
+        Sum::SignedVoteResponse(_) => "SignedVoteResponse",
+        Sum::SignProposalRequest(_) => "SignProposalRequest",
+        Sum::SignedProposalResponse(_) => "SignedProposalResponse",
+        Sum::PingRequest(_) => "PingRequest",
+        Sum::PingResponse(_) => "PingResponse",
+    }
 }
 
 /// Parse the Amino prefix from a message
@@ -206,3 +763,341 @@ fn parse_amino_prefix(packet: &[u8]) -> Result<Vec<u8>, Error> {
 
     Ok(amino_buf[..4].into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::io;
+
+    /// Maximum message size used in tests
+    const MAX_MSG_SIZE: usize = 4096;
+
+    /// Readable which returns the given data in frames of the given size
+    /// (like a Secret Connection), then the given error (if any) or EOF
+    struct Frames {
+        data: Vec<u8>,
+        frame_size: usize,
+        error: Option<io::ErrorKind>,
+    }
+
+    impl Frames {
+        fn new(data: Vec<u8>, frame_size: usize) -> Self {
+            Self {
+                data,
+                frame_size,
+                error: None,
+            }
+        }
+    }
+
+    impl Read for Frames {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.data.is_empty() {
+                return match self.error {
+                    Some(kind) => Err(kind.into()),
+                    None => Ok(0),
+                };
+            }
+
+            let n = self.frame_size.min(self.data.len()).min(buf.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data.drain(..n);
+            Ok(n)
+        }
+    }
+
+    /// Encode the given message with a varint length prefix
+    fn length_prefixed(body: &[u8]) -> Vec<u8> {
+        let mut msg = Vec::new();
+        prost::encoding::encode_varint(body.len() as u64, &mut msg);
+        msg.extend_from_slice(body);
+        msg
+    }
+
+    fn read(data: Vec<u8>, frame_size: usize) -> Result<Vec<u8>, Error> {
+        MsgReader::new().read_msg(&mut Frames::new(data, frame_size), MAX_MSG_SIZE)
+    }
+
+    fn assert_protocol_error(result: Result<Vec<u8>, Error>) {
+        assert_eq!(*result.unwrap_err().kind(), ErrorKind::ProtocolError);
+    }
+
+    #[test]
+    fn reads_message_split_across_frames() {
+        let msg = length_prefixed(&[0x42; 3000]);
+        assert_eq!(read(msg.clone(), DATA_MAX_SIZE).unwrap(), msg);
+        assert_eq!(read(msg.clone(), 1).unwrap(), msg);
+    }
+
+    #[test]
+    fn writes_message_a_frame_at_a_time() {
+        /// Writable which records the size of each write
+        #[derive(Default)]
+        struct Writes(Vec<usize>);
+
+        impl Write for Writes {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.push(buf.len());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut conn = Writes::default();
+        write_msg(&mut conn, &length_prefixed(&[0x42; 2500])).unwrap();
+        assert_eq!(
+            conn.0,
+            [DATA_MAX_SIZE, DATA_MAX_SIZE, 2502 - 2 * DATA_MAX_SIZE]
+        );
+    }
+
+    #[test]
+    fn rejects_oversized_length_before_reading_body() {
+        // Only the prefix is sent: the declared length alone must be rejected
+        let mut msg = vec![];
+        prost::encoding::encode_varint(u64::from(u32::MAX), &mut msg);
+
+        let mut conn = Frames::new(msg, DATA_MAX_SIZE);
+        conn.error = Some(io::ErrorKind::TimedOut);
+        assert_eq!(
+            *MsgReader::new()
+                .read_msg(&mut conn, MAX_MSG_SIZE)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::ProtocolError
+        );
+
+        assert_protocol_error(read(length_prefixed(&[0; MAX_MSG_SIZE + 1]), 1));
+        assert!(read(length_prefixed(&[0; MAX_MSG_SIZE]), 1).is_ok());
+    }
+
+    #[test]
+    fn rejects_overlong_varint() {
+        assert_protocol_error(read(vec![0x80; MAX_VARINT_LEN + 1], DATA_MAX_SIZE));
+    }
+
+    #[test]
+    fn rejects_truncated_message() {
+        let mut msg = length_prefixed(&[0x42; 100]);
+        msg.truncate(50);
+        assert_protocol_error(read(msg, DATA_MAX_SIZE));
+    }
+
+    #[test]
+    fn truncated_message_times_out() {
+        let mut msg = length_prefixed(&[0x42; 100]);
+        msg.truncate(50);
+
+        let mut conn = Frames::new(msg, DATA_MAX_SIZE);
+        conn.error = Some(io::ErrorKind::TimedOut);
+        assert_eq!(
+            *MsgReader::new()
+                .read_msg(&mut conn, MAX_MSG_SIZE)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::IoError
+        );
+    }
+
+    #[test]
+    fn reads_messages_sharing_frames() {
+        let msgs = [
+            length_prefixed(&[0x42; 10]),
+            length_prefixed(&[0x43; 2000]),
+            length_prefixed(&[]),
+            length_prefixed(&[0x44; 300]),
+        ];
+
+        for &frame_size in &[1, 7, 100, DATA_MAX_SIZE] {
+            let mut conn = Frames::new(msgs.concat(), frame_size);
+            let mut reader = MsgReader::new();
+
+            for msg in &msgs {
+                assert_eq!(&reader.read_msg(&mut conn, MAX_MSG_SIZE).unwrap(), msg);
+            }
+
+            assert!(reader.buffered.is_empty());
+            assert_protocol_error(reader.read_msg(&mut conn, MAX_MSG_SIZE));
+        }
+    }
+
+    #[test]
+    fn reads_messages_into_reused_buffer() {
+        let msgs = [length_prefixed(&[0x42; 2000]), length_prefixed(&[0x43; 10])];
+        let mut conn = Frames::new(msgs.concat(), DATA_MAX_SIZE);
+        let mut reader = MsgReader::new();
+        let mut buf = vec![];
+
+        for msg in &msgs {
+            reader
+                .read_msg_into(&mut conn, MAX_MSG_SIZE, &mut buf)
+                .unwrap();
+            assert_eq!(&buf, msg);
+        }
+
+        // The buffer is only ever grown
+        assert!(buf.capacity() >= msgs[0].len());
+    }
+
+    /// Decode the given message body, expecting it to be malformed
+    fn malformed(body: &[u8], protocol_version: ProtocolVersion) -> String {
+        let mut conn = Frames::new(length_prefixed(body), DATA_MAX_SIZE);
+        let err = Request::read(
+            &mut MsgReader::new(),
+            &mut conn,
+            protocol_version,
+            MAX_MSG_SIZE,
+        )
+        .unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::MalformedRequest);
+        err.to_string()
+    }
+
+    #[test]
+    fn rejects_unknown_amino_request() {
+        let body = [0xde, 0xad, 0xbe, 0xef, 0x01, 0x02];
+        let err = malformed(&body, ProtocolVersion::Legacy);
+        assert!(err.contains("unknown request type (Amino prefix: DEADBEEF)"));
+        assert!(err.contains("declared length: 6 bytes"));
+        assert!(err.contains("prefix: DEADBEEF0102"));
+
+        // Responses aren't requests
+        let mut response = vec![];
+        amino_types::PingResponse {}.encode(&mut response).unwrap();
+        assert!(malformed(&response[1..], ProtocolVersion::Legacy).contains("unknown request"));
+    }
+
+    #[test]
+    fn rejects_unsupported_protobuf_request() {
+        let msg = proto::privval::Message {
+            sum: Some(proto::privval::message::Sum::PingResponse(
+                proto::privval::PingResponse {},
+            )),
+        };
+
+        let err = malformed(&msg.encode_to_vec(), ProtocolVersion::V0_34);
+        assert!(err.contains("unsupported request type: PingResponse"));
+
+        let err = malformed(&[], ProtocolVersion::V0_34);
+        assert!(err.contains("empty request (declared length: 0 bytes, prefix: )"));
+
+        let garbage = [0xff; 40];
+        let err = malformed(&garbage, ProtocolVersion::V0_34);
+        assert!(err.contains("undecodable Protobuf message"));
+        assert!(err.contains(&format!("prefix: {}...", "FF".repeat(MALFORMED_PREFIX_LEN))));
+    }
+
+    /// Decode the given message body as a request of the given protocol version
+    fn decode(body: &[u8], protocol_version: ProtocolVersion) -> Request {
+        let mut conn = Frames::new(length_prefixed(body), DATA_MAX_SIZE);
+        Request::read(
+            &mut MsgReader::new(),
+            &mut conn,
+            protocol_version,
+            MAX_MSG_SIZE,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn refuses_unsupported_protobuf_request() {
+        // `cometbft.privval.v1.SignBytesRequest` (field 9)
+        let sign_bytes = [0x4a, 0x05, 0x0a, 0x03, b'a', b'b', b'c'];
+
+        let req = match decode(&sign_bytes, ProtocolVersion::V0_38) {
+            Request::Unsupported(req) => req,
+            other => panic!("unexpected request: {:?}", other),
+        };
+        assert_eq!(req.type_url, Some("/cometbft.privval.v1.SignBytesRequest"));
+        assert_eq!(req.response_tag, Some(10));
+
+        // The error is reported in a `SignBytesResponse` (field 10)
+        let response = Response::Unsupported(req)
+            .encode(ProtocolVersion::V0_38)
+            .unwrap();
+        let mut body = &response[1..];
+        assert_eq!(response[0] as usize, body.len());
+        assert_eq!(prost::encoding::decode_key(&mut body).unwrap().0, 10);
+
+        let error = ErrorResponse::decode_length_delimited(body)
+            .unwrap()
+            .error
+            .unwrap();
+        assert_eq!(
+            error.code,
+            amino_types::RemoteErrorCode::UnsupportedRequestError as i32
+        );
+        assert_eq!(
+            error.description,
+            "unsupported message: /cometbft.privval.v1.SignBytesRequest"
+        );
+
+        // Messages newer than the protocol version are of unknown types
+        let req = match decode(&sign_bytes, ProtocolVersion::V0_34) {
+            Request::Unsupported(req) => req,
+            other => panic!("unexpected request: {:?}", other),
+        };
+        assert_eq!(req.describe(), "unknown message type (field 9)");
+        assert!(Response::Unsupported(req)
+            .encode(ProtocolVersion::V0_34)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn ignores_unknown_protobuf_request() {
+        // Unknown message types are skipped by the Protobuf decoder, but
+        // still recognized by their field number
+        match decode(&[0xfa, 0x01, 0x00], ProtocolVersion::V0_38) {
+            Request::Unsupported(req) => {
+                assert_eq!(req.tag, 31);
+                assert_eq!(req.type_url, None);
+                assert_eq!(req.response_tag, None);
+            }
+            other => panic!("unexpected request: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn message_types_are_unique() {
+        for (i, message_type) in MESSAGE_TYPES.iter().enumerate() {
+            assert!(MESSAGE_TYPES[..i]
+                .iter()
+                .all(|other| other.tag != message_type.tag));
+        }
+    }
+
+    #[test]
+    fn malformed_length_prefixes_never_panic() {
+        let mut rng = StdRng::seed_from_u64(0x746d_6b6d);
+
+        for _ in 0..10_000 {
+            let len = rng.gen_range(0, 32);
+            let mut data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+
+            // Bias towards continuation bits to exercise long varints
+            if rng.gen() {
+                for byte in data.iter_mut().take(rng.gen_range(0, 12)) {
+                    *byte |= 0x80;
+                }
+            }
+
+            let frame_size = rng.gen_range(1, DATA_MAX_SIZE + 1);
+
+            if let Ok(msg) = read(data.clone(), frame_size) {
+                assert!(msg.len() <= MAX_VARINT_LEN + MAX_MSG_SIZE);
+                assert!(data.starts_with(&msg));
+            }
+
+            for &protocol_version in &[ProtocolVersion::Legacy, ProtocolVersion::V0_34] {
+                let mut conn = Frames::new(data.clone(), frame_size);
+                let mut reader = MsgReader::new();
+                let _ = Request::read(&mut reader, &mut conn, protocol_version, MAX_MSG_SIZE);
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! CometBFT v0.38 privval messages which carry ABCI++ vote extensions.
+//!
+//! These aren't (yet) available from `tendermint-proto`, so they're defined
+//! here using the same field numbers as CometBFT's `.proto` definitions.
+//! Only the messages which differ from Tendermint v0.34 are included.
+
+use prost_derive::Message;
+use tendermint_proto as proto;
+
+use crate::amino_types;
+
+/// `tendermint.privval.Message` restricted to the vote-related variants.
+///
+/// A `oneof` with a single member set is encoded identically to the
+/// corresponding optional field, so this type is wire-compatible with the
+/// full message for these variants.
+#[derive(Clone, PartialEq, Message)]
+pub struct Message {
+    #[prost(message, optional, tag = "3")]
+    pub sign_vote_request: Option<SignVoteRequest>,
+    #[prost(message, optional, tag = "4")]
+    pub signed_vote_response: Option<SignedVoteResponse>,
+}
+
+/// `tendermint.privval.SignVoteRequest`
+#[derive(Clone, PartialEq, Message)]
+pub struct SignVoteRequest {
+    #[prost(message, optional, tag = "1")]
+    pub vote: Option<Vote>,
+    #[prost(string, tag = "2")]
+    pub chain_id: String,
+}
+
+/// `tendermint.privval.SignedVoteResponse`
+#[derive(Clone, PartialEq, Message)]
+pub struct SignedVoteResponse {
+    #[prost(message, optional, tag = "1")]
+    pub vote: Option<Vote>,
+    #[prost(message, optional, tag = "2")]
+    pub error: Option<proto::privval::RemoteSignerError>,
+}
+
+/// `tendermint.types.Vote` including vote extensions
+#[derive(Clone, PartialEq, Message)]
+pub struct Vote {
+    #[prost(int32, tag = "1")]
+    pub r#type: i32,
+    #[prost(int64, tag = "2")]
+    pub height: i64,
+    #[prost(int32, tag = "3")]
+    pub round: i32,
+    #[prost(message, optional, tag = "4")]
+    pub block_id: Option<proto::types::BlockId>,
+    #[prost(message, optional, tag = "5")]
+    pub timestamp: Option<proto::google::protobuf::Timestamp>,
+    #[prost(bytes = "vec", tag = "6")]
+    pub validator_address: Vec<u8>,
+    #[prost(int32, tag = "7")]
+    pub validator_index: i32,
+    #[prost(bytes = "vec", tag = "8")]
+    pub signature: Vec<u8>,
+    #[prost(bytes = "vec", tag = "9")]
+    pub extension: Vec<u8>,
+    #[prost(bytes = "vec", tag = "10")]
+    pub extension_signature: Vec<u8>,
+}
+
+impl From<amino_types::Vote> for Vote {
+    fn from(vote: amino_types::Vote) -> Vote {
+        Vote {
+            r#type: vote.vote_type as i32,
+            height: vote.height,
+            round: vote.round as i32,
+            block_id: vote.block_id.map(Into::into),
+            timestamp: vote.timestamp.map(Into::into),
+            validator_address: vote.validator_address,
+            validator_index: vote.validator_index as i32,
+            signature: vote.signature,
+            extension: vote.extension,
+            extension_signature: vote.extension_signature,
+        }
+    }
+}
+
+/// `tendermint.types.CanonicalVoteExtension`: the sign bytes of a vote
+/// extension
+#[derive(Clone, PartialEq, Message)]
+pub struct CanonicalVoteExtension {
+    #[prost(bytes = "vec", tag = "1")]
+    pub extension: Vec<u8>,
+    #[prost(sfixed64, tag = "2")]
+    pub height: i64,
+    #[prost(sfixed64, tag = "3")]
+    pub round: i64,
+    #[prost(string, tag = "4")]
+    pub chain_id: String,
+}
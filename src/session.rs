@@ -1,34 +1,140 @@
 //! A session with a validator node
 
+#[cfg(feature = "async")]
+pub(crate) mod async_loop;
+mod queue;
+mod rate_limit;
+
+pub use self::queue::{RequestQueue, Ticket};
+use self::rate_limit::RateLimiter;
 use crate::{
+    alerts::{self, Alert},
     amino_types::{
-        PingResponse, PubKeyRequest, PubKeyResponse, RemoteError, SignedMsgType, TendermintRequest,
+        PingResponse, PubKeyRequest, RemoteError, SignedMsgType, TendermintRequest, TimeMsg,
+    },
+    audit,
+    capture::{self, Capture},
+    chain::{
+        self,
+        state::{self, SignedPayload, StateErrorKind, Step},
+        Chain,
     },
-    chain::{self, state::StateErrorKind, Chain},
-    config::ValidatorConfig,
-    connection::{tcp, unix::UnixConnection, Connection},
+    client::Control,
+    config::{chain::MsgType, ConfigOrigin, ValidatorAddr, ValidatorConfig},
+    connection::{tcp, unix::UnixConnection, Connection, Interrupt, Listener},
     error::{Error, ErrorKind::*},
+    latency,
+    metrics::{self, RefusalReason},
     prelude::*,
-    rpc::{Request, Response},
+    rpc::{self, MsgReader, Request, Response},
+    shutdown, status, Map,
 };
-use std::{fmt::Debug, os::unix::net::UnixStream, time::Instant};
-use tendermint::consensus;
-use tendermint_config::net;
+use std::{
+    fmt::Debug,
+    mem,
+    time::{Instant, SystemTime},
+};
+use subtle_encoding::hex;
+use tendermint::{block, consensus, time::ParseTimestamp};
+use tendermint_p2p::secret_connection::DATA_MAX_SIZE;
+
+/// Number of heights between logs of how many heights are left before a
+/// validator's `max_height`
+pub const MAX_HEIGHT_LOG_INTERVAL: u64 = 100;
+
+/// Number of heights left before `max_height` at which they're logged as a
+/// warning
+pub const MAX_HEIGHT_WARN_REMAINING: u64 = 1000;
+
+/// Number of heights left before `max_height` at which they're logged as an
+/// error
+pub const MAX_HEIGHT_ERROR_REMAINING: u64 = 100;
+
+/// Environment variable used by tests to artificially delay signing
+/// operations (in milliseconds, debug builds only)
+#[cfg(debug_assertions)]
+pub const SIGNING_DELAY_ENV_VAR: &str = "TMKMS_TEST_SIGNING_DELAY_MS";
+
+#[cfg(feature = "nitro")]
+use crate::connection::vsock::VsockConnection;
+
+#[cfg(feature = "tls")]
+use crate::connection::tls;
+
+/// Log an event about a signing request, with the chain ID and the request's
+/// message type, height, round, and step as structured fields (see
+/// [`crate::logging::SIGNING_FIELDS`])
+macro_rules! signing_event {
+    ($level:ident, $chain_id:expr, $request:expr, $($arg:tt)+) => {{
+        let (msg_type, state) = match parse_request($request) {
+            Ok((msg_type, state)) => (Some(msg_type), Some(state)),
+            Err(_) => (None, None),
+        };
+
+        $level!(
+            chain_id = %$chain_id,
+            msg_type = msg_type.map(SignedMsgType::as_str),
+            height = state.as_ref().map(|s| s.height.value()),
+            round = state.as_ref().map(|s| s.round.value()),
+            step = state.as_ref().map(|s| s.step),
+            $($arg)+
+        )
+    }};
+}
 
 /// Encrypted session with a validator node
 pub struct Session {
-    /// Validator configuration options
-    config: ValidatorConfig,
+    /// Handler for incoming requests
+    handler: RequestHandler,
 
     /// TCP connection to a validator node
     connection: Box<dyn Connection>,
+
+    /// Reader of the messages sent over the connection
+    reader: MsgReader,
+
+    /// Handle used to interrupt the connection (if supported)
+    interrupt: Option<Box<dyn Interrupt>>,
+
+    /// Queue of the requests read from the connection
+    queue: RequestQueue,
+
+    /// Capture of the requests and responses (if `protocol_capture_dir` is
+    /// configured)
+    capture: Option<Capture>,
+
+    /// Buffer the message of the request being handled is read into
+    msg: Vec<u8>,
+
+    /// Buffer the response to the request being handled is encoded into
+    response: Vec<u8>,
 }
 
 impl Session {
+    /// Create a session with a validator over an already established
+    /// connection
+    pub fn new(config: ValidatorConfig, connection: Box<dyn Connection>) -> Self {
+        let capture = config.protocol_capture_dir.as_deref().map(Capture::new);
+        let queue = RequestQueue::new(&config);
+
+        Self {
+            handler: RequestHandler::new(config),
+            connection,
+            reader: MsgReader::new(),
+            interrupt: None,
+            queue,
+            capture,
+            msg: Vec::with_capacity(DATA_MAX_SIZE),
+            response: Vec::with_capacity(DATA_MAX_SIZE),
+        }
+    }
+
     /// Open a session using the given validator configuration
     pub fn open(config: ValidatorConfig) -> Result<Self, Error> {
+        let interrupt: Option<Box<dyn Interrupt>>;
+
         let connection: Box<dyn Connection> = match &config.addr {
-            net::Address::Tcp {
+            ValidatorAddr::Tcp {
                 peer_id,
                 host,
                 port,
@@ -38,14 +144,7 @@ impl Session {
                     &config.chain_id, &config.addr
                 );
 
-                let conn = tcp::open_secret_connection(
-                    host,
-                    *port,
-                    &config.secret_key,
-                    peer_id,
-                    config.timeout,
-                    config.protocol_version.into(),
-                )?;
+                let (conn, socket) = tcp::open_secret_connection(host, *port, peer_id, &config)?;
 
                 info!(
                     "[{}@{}] connected to validator successfully",
@@ -62,53 +161,400 @@ impl Session {
                     );
                 }
 
+                tcp::set_keepalive(&socket, config.keepalive_interval_secs)?;
+                interrupt = Some(Box::new(socket));
                 Box::new(conn)
             }
-            net::Address::Unix { path } => {
-                if let Some(timeout) = config.timeout {
-                    warn!("timeouts not supported with Unix sockets: {}", timeout);
+            #[cfg(feature = "tls")]
+            ValidatorAddr::Tls { host, port } => {
+                debug!(
+                    "[{}@{}] connecting to validator...",
+                    &config.chain_id, &config.addr
+                );
+
+                if config.secret_key.is_some() {
+                    warn!(
+                        "[{}@{}] `secret_key` is ignored for TLS validators",
+                        &config.chain_id, &config.addr
+                    );
                 }
 
+                let (conn, socket) = tls::open_tls_connection(host, *port, &config)?;
+
+                info!(
+                    "[{}@{}] connected to validator successfully",
+                    &config.chain_id, &config.addr
+                );
+
+                tcp::set_keepalive(&socket, config.keepalive_interval_secs)?;
+                interrupt = Some(Box::new(socket));
+                Box::new(conn)
+            }
+            #[cfg(not(feature = "tls"))]
+            ValidatorAddr::Tls { .. } => fail!(
+                ConfigError,
+                "[{}@{}] TLS support not enabled (rebuild with the `tls` cargo feature)",
+                &config.chain_id,
+                &config.addr
+            ),
+            ValidatorAddr::Unix { path } => {
                 debug!(
                     "{}: Connecting to socket at {}...",
                     &config.chain_id, &config.addr
                 );
 
-                let socket = UnixStream::connect(path)?;
-                let conn = UnixConnection::new(socket);
+                let conn = UnixConnection::connect(path, config.timeout)?;
+
+                info!(
+                    "[{}@{}] connected to validator successfully",
+                    &config.chain_id, &config.addr
+                );
+
+                interrupt = Some(Box::new(conn.interrupt_handle()?));
+                Box::new(conn)
+            }
+            #[cfg(feature = "nitro")]
+            ValidatorAddr::Vsock { cid, port } => {
+                debug!(
+                    "[{}@{}] connecting to validator...",
+                    &config.chain_id, &config.addr
+                );
+
+                let conn = VsockConnection::connect(*cid, *port, config.timeout)?;
 
                 info!(
                     "[{}@{}] connected to validator successfully",
                     &config.chain_id, &config.addr
                 );
 
+                interrupt = None;
                 Box::new(conn)
             }
+            #[cfg(not(feature = "nitro"))]
+            ValidatorAddr::Vsock { .. } => fail!(
+                ConfigError,
+                "[{}@{}] vsock support not enabled (rebuild with the `nitro` cargo feature)",
+                &config.chain_id,
+                &config.addr
+            ),
+            ValidatorAddr::TcpListen { .. }
+            | ValidatorAddr::UnixListen { .. }
+            | ValidatorAddr::VsockListen { .. } => fail!(
+                ConfigError,
+                "[{}@{}] listen addresses are accepted on, not dialed",
+                &config.chain_id,
+                &config.addr
+            ),
+            ValidatorAddr::Grpc { .. } => fail!(
+                ConfigError,
+                "[{}@{}] gRPC addresses are served, not dialed",
+                &config.chain_id,
+                &config.addr
+            ),
         };
 
-        Ok(Self { config, connection })
+        Ok(Self {
+            interrupt,
+            ..Self::new(config, connection)
+        })
     }
 
-    /// Main request loop
-    pub fn request_loop(&mut self) -> Result<(), Error> {
-        while self.handle_request()? {}
+    /// Accept a session from a validator dialing into the given listener
+    pub fn accept(config: ValidatorConfig, listener: &Listener) -> Result<Self, Error> {
+        debug!(
+            "[{}@{}] waiting for validator to connect...",
+            &config.chain_id, &config.addr
+        );
+
+        let interrupt: Option<Box<dyn Interrupt>>;
+
+        let connection: Box<dyn Connection> = match (&config.addr, listener) {
+            (ValidatorAddr::TcpListen { peer_id, .. }, Listener::Tcp(listener)) => {
+                let (conn, socket) = tcp::accept_secret_connection(listener, peer_id, &config)?;
+
+                if peer_id.is_none() {
+                    warn!(
+                        "[{}@{}]: unverified validator peer ID! ({})",
+                        &config.chain_id,
+                        &config.addr,
+                        conn.remote_pubkey().peer_id()
+                    );
+                }
+
+                tcp::set_keepalive(&socket, config.keepalive_interval_secs)?;
+                interrupt = Some(Box::new(socket));
+                Box::new(conn)
+            }
+            (ValidatorAddr::UnixListen { .. }, Listener::Unix(listener)) => {
+                let conn = UnixConnection::accept(listener, config.timeout)?;
+                interrupt = Some(Box::new(conn.interrupt_handle()?));
+                Box::new(conn)
+            }
+            #[cfg(feature = "nitro")]
+            (ValidatorAddr::VsockListen { .. }, Listener::Vsock(listener)) => {
+                interrupt = None;
+                Box::new(VsockConnection::accept(listener, config.timeout)?)
+            }
+            _ => fail!(
+                ConfigError,
+                "[{}@{}] can't accept validator connections on this address",
+                &config.chain_id,
+                &config.addr
+            ),
+        };
+
+        info!(
+            "[{}@{}] validator connected successfully",
+            &config.chain_id, &config.addr
+        );
+
+        Ok(Self {
+            interrupt,
+            ..Self::new(config, connection)
+        })
+    }
+
+    /// Take the handle used to interrupt this session's connection (if
+    /// supported), e.g. so it can be stopped while waiting for a request
+    pub fn take_interrupt(&mut self) -> Option<Box<dyn Interrupt>> {
+        self.interrupt.take()
+    }
+
+    /// Main request loop, which runs until the client is stopped
+    pub fn request_loop(&mut self, control: &Control) -> Result<(), Error> {
+        while !control.is_stopped() && self.handle_request(control)? {}
         Ok(())
     }
 
     /// Handle an incoming request from the validator
-    fn handle_request(&mut self) -> Result<bool, Error> {
-        let request = Request::read(&mut self.connection, self.config.protocol_version)?;
+    fn handle_request(&mut self, control: &Control) -> Result<bool, Error> {
+        // The session's buffers are reused for every request, so handling
+        // one doesn't allocate them once they've grown to fit its messages
+        let mut msg = mem::take(&mut self.msg);
+
+        let result = self
+            .read_request(&mut msg)
+            .and_then(|(request, received_at)| self.respond(&msg, request, received_at, control));
+
+        self.msg = msg;
+        result
+    }
+
+    /// Read the next request from the validator into the given buffer,
+    /// returning it along with when it arrived
+    fn read_request(&mut self, msg: &mut Vec<u8>) -> Result<(Request, Instant), Error> {
+        let protocol_version = self.handler.config().protocol_version;
+        let max_msg_size = self.handler.config().max_msg_size();
+
+        if let Err(e) = self
+            .reader
+            .read_msg_into(&mut self.connection, max_msg_size, msg)
+        {
+            self.read_failed(&e, None)?;
+            return Err(e);
+        }
+
+        match Request::from_msg(msg, protocol_version) {
+            Ok(request) => Ok((request, Instant::now())),
+            Err(e) => {
+                self.read_failed(&e, Some(msg))?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Record a request which couldn't be read (capturing the message it was
+    /// read from, if any)
+    fn read_failed(&mut self, e: &Error, msg: Option<&[u8]>) -> Result<(), Error> {
+        let config = self.handler.config();
+        let protocol_version = config.protocol_version;
+
+        if let (Some(capture), Some(msg)) = (self.capture.as_mut(), msg) {
+            let entry = capture::Entry::malformed(
+                &config.chain_id,
+                config.label.as_deref(),
+                protocol_version,
+                e,
+            );
+
+            capture.record(entry, msg, None)?;
+        }
+
+        // There's no way to tell the validator which request failed,
+        // so the connection is dropped (and reconnected) instead
+        if *e.kind() == MalformedRequest {
+            warn!(
+                chain_id = %config.chain_id,
+                "[{}@{}] {} (protocol_version: {}): dropping connection",
+                &config.chain_id,
+                &config.addr,
+                e,
+                protocol_version.as_str()
+            );
+
+            metrics::malformed_request(&config.chain_id, &config.addr.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Handle a request read from the validator and write its response,
+    /// returning whether to carry on reading requests
+    fn respond(
+        &mut self,
+        msg: &[u8],
+        request: Request,
+        received_at: Instant,
+        control: &Control,
+    ) -> Result<bool, Error> {
+        let protocol_version = self.handler.config().protocol_version;
+        let _busy = control.heartbeat().busy();
+
+        // Pick up any height limit changes from a configuration reload
+        self.handler.set_height_limits(
+            control.min_height(),
+            control.max_height(),
+            control.max_height_origin(),
+        );
+
+        // Shutdown is deferred until the response has been written
+        let _in_flight = match shutdown::begin_request() {
+            Some(in_flight) => in_flight,
+            None => return Ok(false),
+        };
+
+        let mut entry = self.capture.as_ref().map(|_| {
+            let config = self.handler.config();
+            capture::Entry::new(
+                &self.handler.signing_chain_id(request.chain_id()),
+                config.label.as_deref(),
+                protocol_version,
+                &request,
+            )
+        });
+
+        // Requests read from the connection are handled one at a time, so
+        // they never wait here, but go through the queue like those of
+        // transports which handle them concurrently (i.e. gRPC)
+        let response = match self.queue.push(received_at) {
+            Some(ticket) => {
+                ticket.wait();
+                self.handler.handle(request, ticket.received_at())?
+            }
+            None => self.queue.refusal(request)?,
+        };
+
+        if let Some(entry) = entry.as_mut() {
+            entry.set_response(&response)?;
+        }
+
+        let mut response_bytes = mem::take(&mut self.response);
+        response_bytes.clear();
+        response.encode_into(protocol_version, &mut response_bytes)?;
+
+        // Requests of unknown types get no response (there's no message type
+        // to send one in), but the connection is kept
+        let has_response = !response_bytes.is_empty();
+
+        if let (Some(capture), Some(entry)) = (self.capture.as_mut(), entry) {
+            let response_bytes = Some(response_bytes.as_slice()).filter(|_| has_response);
+            capture.record(entry, msg, response_bytes)?;
+        }
+
+        if has_response {
+            rpc::write_msg(&mut self.connection, &response_bytes)?;
+        }
+
+        self.response = response_bytes;
+        Ok(!shutdown::requested())
+    }
+}
+
+/// Transport-independent handler for requests from a validator
+pub struct RequestHandler {
+    /// Validator configuration options
+    config: ValidatorConfig,
+
+    /// Limit on the rate of signing requests (if configured)
+    rate_limiter: Option<RateLimiter>,
+
+    /// Height at which the number of heights left before `max_height` was
+    /// last logged, by chain
+    max_height_logged: Map<chain::Id, u64>,
+
+    /// Buffer the bytes to sign for a request are encoded into
+    sign_bytes: Vec<u8>,
+
+    /// Buffer the bytes to sign for a vote's extension are encoded into
+    extension_sign_bytes: Vec<u8>,
+}
+
+impl RequestHandler {
+    /// Create a new request handler for the given validator configuration
+    pub fn new(config: ValidatorConfig) -> Self {
+        let rate_limiter = config
+            .max_requests_per_second
+            .map(|max| RateLimiter::new(max, Instant::now()));
+
+        Self {
+            config,
+            rate_limiter,
+            max_height_logged: Map::new(),
+            sign_bytes: Vec::with_capacity(DATA_MAX_SIZE),
+            extension_sign_bytes: Vec::with_capacity(DATA_MAX_SIZE),
+        }
+    }
+
+    /// Get the validator configuration for this handler
+    pub fn config(&self) -> &ValidatorConfig {
+        &self.config
+    }
+
+    /// Update the minimum and maximum block heights this handler will sign at
+    /// (and where the maximum is set in the configuration file)
+    pub fn set_height_limits(
+        &mut self,
+        min_height: Option<block::Height>,
+        max_height: Option<block::Height>,
+        max_height_origin: Option<ConfigOrigin>,
+    ) {
+        self.config.min_height = min_height;
+        self.config.max_height = max_height;
+        self.config.max_height_origin = max_height_origin;
+    }
+
+    /// Handle a request which arrived at `received_at`, producing the
+    /// response to send to the validator
+    pub fn handle(&mut self, request: Request, received_at: Instant) -> Result<Response, Error> {
         debug!(
             "[{}@{}] received request: {:?}",
             &self.config.chain_id, &self.config.addr, &request
         );
 
+        status::request_received(&self.config.chain_ids, &self.config.addr.to_string());
+
         let response = match request {
-            Request::SignProposal(req) => self.sign(req)?,
-            Request::SignVote(req) => self.sign(req)?,
+            Request::SignProposal(req) => self.sign(req, received_at)?,
+            Request::SignVote(req) => self.sign(req, received_at)?,
             // non-signable requests:
             Request::ReplyPing(_) => Response::Ping(PingResponse {}),
             Request::ShowPublicKey(ref req) => self.get_public_key(req)?,
+            Request::Unsupported(req) => {
+                warn!(
+                    chain_id = %self.config.chain_id,
+                    "[{}@{}] unsupported request: {}{}",
+                    &self.config.chain_id,
+                    &self.config.addr,
+                    req.describe(),
+                    if req.response_tag.is_some() {
+                        ""
+                    } else {
+                        " (ignored)"
+                    }
+                );
+
+                Response::Unsupported(req)
+            }
         };
 
         debug!(
@@ -116,142 +562,946 @@ impl Session {
             &self.config.chain_id, &self.config.addr, &response
         );
 
-        let response_bytes = response.encode(self.config.protocol_version)?;
-        self.connection.write_all(&response_bytes)?;
-
-        Ok(true)
+        Ok(response)
     }
 
     /// Perform a digital signature operation
-    fn sign<R>(&mut self, mut request: R) -> Result<Response, Error>
+    fn sign<R>(&mut self, request: R, received_at: Instant) -> Result<Response, Error>
     where
-        R: TendermintRequest + Debug,
+        R: TendermintRequest + Clone + Debug,
     {
-        request
-            .validate()
-            .map_err(|e| format_err!(SigningError, "failed to validate request: {}", e))?;
+        // The handler's buffers are reused for the bytes to sign of every
+        // request
+        let mut to_sign = mem::take(&mut self.sign_bytes);
+        let mut extension_to_sign = mem::take(&mut self.extension_sign_bytes);
+        to_sign.clear();
+        extension_to_sign.clear();
 
-        self.check_max_height(&mut request)?;
+        let result = self.sign_into(request, received_at, &mut to_sign, &mut extension_to_sign);
 
-        let registry = chain::REGISTRY.get();
+        self.sign_bytes = to_sign;
+        self.extension_sign_bytes = extension_to_sign;
+        result
+    }
+
+    /// Perform a digital signature operation, encoding the bytes to sign
+    /// into the given (empty) buffers
+    fn sign_into<R>(
+        &mut self,
+        mut request: R,
+        received_at: Instant,
+        to_sign: &mut Vec<u8>,
+        extension_to_sign: &mut Vec<u8>,
+    ) -> Result<Response, Error>
+    where
+        R: TendermintRequest + Clone + Debug,
+    {
+        let chain_id = match self.request_chain_id(request.chain_id()) {
+            Some(chain_id) => chain_id.clone(),
+            None => {
+                let requested = request.chain_id().unwrap_or_default();
+
+                signing_event!(
+                    error,
+                    self.config.chain_id,
+                    &request,
+                    "[{}@{}] refusing to sign for chain ID: {}",
+                    &self.config.chain_id,
+                    &self.config.addr,
+                    requested
+                );
+
+                let remote_err = self.refuse_chain_id(requested);
+                status::refused(&self.config.chain_id);
+                return Ok(request.build_response(Some(remote_err)));
+            }
+        };
+
+        // Requests of a known type can be refused rather than dropping the
+        // connection, since the validator knows which request it's for
+        if let Err(e) = request.validate() {
+            signing_event!(
+                warn,
+                chain_id,
+                &request,
+                "[{}@{}] malformed request: {}",
+                &chain_id,
+                &self.config.addr,
+                e
+            );
+
+            metrics::malformed_request(&chain_id, &self.config.addr.to_string());
+            status::refused(&chain_id);
+            return Ok(request.build_response(Some(RemoteError::malformed_request(e))));
+        }
+
+        // Rate limited requests are refused before the registry is locked or
+        // the audit log written, so a flood of them costs as little as possible
+        if let Some(remote_err) = self.check_rate_limit(&chain_id, &request)? {
+            status::refused(&chain_id);
+            return Ok(request.build_response(Some(remote_err)));
+        }
+
+        // The registry is only locked to look the chain up, so reloads (and
+        // other chains) never wait on this chain's signing provider or I/O
+        let chain = match chain::REGISTRY
+            .get()
+            .validator_chain(&chain_id, self.config.label.as_deref())
+        {
+            Some(chain) => chain,
+            None => {
+                signing_event!(
+                    error,
+                    chain_id,
+                    &request,
+                    "[{}@{}] chain missing from registry!",
+                    &chain_id,
+                    &self.config.addr
+                );
+
+                let remote_err = RemoteError::unknown_chain_id(chain_id.as_str());
+                status::refused(&chain_id);
+                return Ok(request.build_response(Some(remote_err)));
+            }
+        };
+        let chain = chain.as_ref();
 
-        let chain = registry
-            .get_chain(&self.config.chain_id)
-            .unwrap_or_else(|| {
-                panic!("chain '{}' missing from registry!", &self.config.chain_id);
-            });
+        // Blocked heights are refused before any other check, whatever else
+        // is wrong with the request
+        if let Some(remote_err) = self
+            .check_blocked_height(chain, &request)
+            .or_else(|| self.check_role(chain, &request))
+            .or_else(|| self.check_chain_id(chain, &request))
+            .or_else(|| self.check_msg_type(chain, &request))
+            .or_else(|| self.check_max_height(chain, &request))
+            .or_else(|| self.check_min_height(chain, &request))
+            .or_else(|| self.check_clock_skew(chain, &request))
+            .or_else(|| self.check_validator_address(chain, &request))
+            .or_else(|| self.check_deadline(chain, &request, received_at))
+        {
+            self.audit(chain, &request, |id, msg_type, state| {
+                audit::Entry::refused(id, msg_type, state, &remote_err.description)
+            })?;
 
-        if let Some(remote_err) = self.update_consensus_state(chain, &request)? {
-            // In the event of double signing we send a response to notify the validator
+            status::refused(&chain.id);
             return Ok(request.build_response(Some(remote_err)));
         }
 
-        let mut to_sign = vec![];
-        request.sign_bytes(
-            self.config.chain_id.clone(),
-            self.config.protocol_version,
-            &mut to_sign,
-        )?;
+        // Requests bearing an alias of the chain are signed with the alias
+        let sign_chain_id = chain.signing_id(request.chain_id());
+
+        request.sign_bytes(sign_chain_id.clone(), self.config.protocol_version, to_sign)?;
+
+        let signature = match self.update_consensus_state(chain, &mut request, to_sign)? {
+            StateUpdate::Refuse(remote_err) => {
+                self.audit(chain, &request, |id, msg_type, state| {
+                    audit::Entry::refused(id, msg_type, state, &remote_err.description)
+                })?;
+
+                // In the event of double signing we send a response to notify the validator
+                status::refused(&chain.id);
+                return Ok(request.build_response(Some(remote_err)));
+            }
+            StateUpdate::Resend(signature) => {
+                signing_event!(
+                    info,
+                    chain.id,
+                    &request,
+                    "[{}@{}] re-sending signature for retried request",
+                    &chain.id,
+                    &self.config.addr
+                );
+
+                signature
+            }
+            StateUpdate::Sign => {
+                let started_at = Instant::now();
+
+                #[cfg(debug_assertions)]
+                signing_delay();
 
-        let started_at = Instant::now();
+                // TODO(ismail): figure out which key to use here instead of taking the only key
+                let signature = match sign_consensus(chain, to_sign) {
+                    Ok(signature) => signature,
+                    Err(e) => return self.signing_error(chain, request, e),
+                };
 
-        // TODO(ismail): figure out which key to use here instead of taking the only key
-        let signature = chain.keyring.sign_ed25519(None, &to_sign)?;
+                chain
+                    .state
+                    .lock()
+                    .unwrap()
+                    .record_signature(to_sign, &signature);
+
+                self.log_signing_request(chain, &request, to_sign, started_at)
+                    .unwrap();
+
+                signature
+            }
+        };
 
-        self.log_signing_request(&request, started_at).unwrap();
         request.set_signature(&signature);
 
+        // Vote extensions are signed with the same key as the vote itself
+        if request.extension_sign_bytes(
+            sign_chain_id,
+            self.config.protocol_version,
+            extension_to_sign,
+        )? {
+            match sign_consensus(chain, extension_to_sign) {
+                Ok(signature) => request.set_extension_signature(&signature),
+                Err(e) => return self.signing_error(chain, request, e),
+            }
+        }
+
+        // Signatures are only released once they've been audited
+        self.audit(chain, &request, |id, msg_type, state| {
+            let public_key = chain
+                .keyring
+                .default_consensus_pubkey()
+                .ok()
+                .map(|public_key| chain.keyring.format().serialize(public_key));
+
+            audit::Entry::signed(id, msg_type, state, &signature, to_sign, public_key)
+        })?;
+
+        if let Some(msg_type) = request.msg_type() {
+            metrics::signed(&chain.id, msg_type);
+        }
+
+        if let Ok((_, state)) = parse_request(&request) {
+            status::signed(&chain.id, &state);
+            self.record_max_height_budget(&chain.id, state.height.value());
+        }
+
         Ok(request.build_response(None))
     }
 
+    /// If signing requests are rate limited, ensure this one is within the
+    /// limit. Fails (dropping the connection) once more than
+    /// `max_rate_limit_violations` consecutive requests have been refused.
+    fn check_rate_limit<R>(
+        &mut self,
+        chain_id: &chain::Id,
+        request: &R,
+    ) -> Result<Option<RemoteError>, Error>
+    where
+        R: TendermintRequest + Debug,
+    {
+        let rate_limiter = match self.rate_limiter.as_mut() {
+            Some(rate_limiter) => rate_limiter,
+            None => return Ok(None),
+        };
+
+        let now = Instant::now();
+
+        if rate_limiter.try_acquire(now) {
+            return Ok(None);
+        }
+
+        metrics::refused(chain_id, RefusalReason::RateLimited);
+
+        let max_requests_per_second = rate_limiter.max_requests_per_second();
+        let violations = rate_limiter.violations();
+
+        if let Some(max_violations) = self.config.max_rate_limit_violations {
+            if violations > max_violations {
+                fail!(
+                    ProtocolError,
+                    "[{}@{}] dropping connection after {} consecutive rate limited requests",
+                    chain_id,
+                    &self.config.addr,
+                    violations
+                );
+            }
+        }
+
+        // Only warn periodically, as logging every refusal would defeat the
+        // purpose of refusing them
+        if let Some(refused) = rate_limiter.take_warning(now) {
+            signing_event!(
+                warn,
+                chain_id,
+                request,
+                "[{}@{}] refused {} signing request(s) exceeding {} per second",
+                chain_id,
+                &self.config.addr,
+                refused,
+                max_requests_per_second
+            );
+        }
+
+        Ok(Some(RemoteError::rate_limited(max_requests_per_second)))
+    }
+
+    /// Get the ID of the chain a request is for: the one it names (or has an
+    /// alias it names) if this validator serves it, or the validator's
+    /// (first) chain if it names none. Requests naming any other chain are
+    /// for none of this validator's chains (`None`), and are refused.
+    pub(crate) fn request_chain_id(&self, requested: Option<&str>) -> Option<&chain::Id> {
+        match requested {
+            Some(requested) => self
+                .config
+                .chain_ids
+                .iter()
+                .find(|chain_id| chain_id.as_str() == requested)
+                .or_else(|| self.alias_chain_id(requested)),
+            None => Some(&self.config.chain_id),
+        }
+    }
+
+    /// Get the chain ID a request is signed with: the alias it names if it
+    /// names one of its chain's, otherwise the ID of the chain it's for (or
+    /// the validator's chain, for requests which will be refused)
+    pub(crate) fn signing_chain_id(&self, requested: Option<&str>) -> chain::Id {
+        let chain_id = self
+            .request_chain_id(requested)
+            .unwrap_or(&self.config.chain_id);
+
+        chain::REGISTRY
+            .get()
+            .get_validator_chain(chain_id, self.config.label.as_deref())
+            .map(|chain| chain.signing_id(requested))
+            .unwrap_or_else(|| chain_id.clone())
+    }
+
+    /// Get the ID of the chain served by this validator which has the given
+    /// alias (if any), noting the alias the first time it's seen
+    fn alias_chain_id(&self, alias: &str) -> Option<&chain::Id> {
+        let registry = chain::REGISTRY.get();
+        let chain = registry.chains().find(|chain| {
+            chain.has_alias(alias) && chain.validator.as_deref() == self.config.label.as_deref()
+        })?;
+
+        let chain_id = self
+            .config
+            .chain_ids
+            .iter()
+            .find(|chain_id| **chain_id == chain.id)?;
+
+        chain::note_alias(chain_id, &chain.signing_id(Some(alias)));
+        Some(chain_id)
+    }
+
+    /// If the request includes a chain ID, ensure it's one of the chains this
+    /// validator is configured for (or an alias of the chain it's for)
+    fn check_chain_id<R>(&self, chain: &Chain, request: &R) -> Option<RemoteError>
+    where
+        R: TendermintRequest + Debug,
+    {
+        let requested = request.chain_id()?;
+
+        if chain.has_alias(requested)
+            || self
+                .config
+                .chain_ids
+                .iter()
+                .any(|chain_id| chain_id.as_str() == requested)
+        {
+            return None;
+        }
+
+        signing_event!(
+            error,
+            self.config.chain_id,
+            request,
+            "[{}@{}] refusing to sign for chain ID: {}",
+            &self.config.chain_id,
+            &self.config.addr,
+            requested
+        );
+
+        Some(self.refuse_chain_id(requested))
+    }
+
+    /// Build the error refusing a request for the given chain ID, which isn't
+    /// one of this validator's
+    fn refuse_chain_id(&self, requested: &str) -> RemoteError {
+        metrics::refused(&self.config.chain_id, RefusalReason::ChainIdMismatch);
+
+        let expected = self
+            .config
+            .chain_ids
+            .iter()
+            .map(chain::Id::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        RemoteError::chain_id_mismatch(&expected, requested)
+    }
+
+    /// If the chain restricts which message types may be signed, ensure the
+    /// request's type is allowed
+    fn check_msg_type<R>(&self, chain: &Chain, request: &R) -> Option<RemoteError>
+    where
+        R: TendermintRequest + Debug,
+    {
+        let allowed_msg_types = chain.allowed_msg_types.as_ref()?;
+        let msg_type = request.msg_type()?;
+
+        if allowed_msg_types.contains(&MsgType::from(msg_type)) {
+            return None;
+        }
+
+        signing_event!(
+            error,
+            chain.id,
+            request,
+            "[{}@{}] refusing to sign {:?}: not in the chain's allowed_msg_types",
+            &chain.id,
+            &self.config.addr,
+            msg_type
+        );
+
+        metrics::refused(&chain.id, RefusalReason::MsgType);
+
+        Some(RemoteError::msg_type_not_allowed(msg_type))
+    }
+
+    /// Ensure the validator's `role` signs the request's message type (i.e.
+    /// a `voter` never signs proposals)
+    fn check_role<R>(&self, chain: &Chain, request: &R) -> Option<RemoteError>
+    where
+        R: TendermintRequest + Debug,
+    {
+        let msg_type = request.msg_type()?;
+
+        if msg_type != SignedMsgType::Proposal || self.config.role.signs_proposals() {
+            return None;
+        }
+
+        signing_event!(
+            error,
+            chain.id,
+            request,
+            "[{}@{}] refusing to sign {:?}: validator role is \"{}\"",
+            &chain.id,
+            &self.config.addr,
+            msg_type,
+            self.config.role
+        );
+
+        metrics::refused(&chain.id, RefusalReason::Role);
+
+        Some(RemoteError::role_not_allowed(self.config.role, msg_type))
+    }
+
     /// If a max block height is configured, ensure the block we're signing
     /// doesn't exceed it
-    fn check_max_height<R>(&mut self, request: &mut R) -> Result<(), Error>
+    fn check_max_height<R>(&self, chain: &Chain, request: &R) -> Option<RemoteError>
     where
         R: TendermintRequest + Debug,
     {
-        if let Some(max_height) = self.config.max_height {
-            if let Some(height) = request.height() {
-                if height > max_height.value() as i64 {
-                    fail!(
-                        ExceedMaxHeight,
-                        "attempted to sign at height {} which is greater than {}",
-                        height,
-                        max_height,
-                    );
-                }
+        let max_height = self.config.max_height?;
+        let height = request.height()?;
+
+        if height <= max_height.value() as i64 {
+            return None;
+        }
+
+        signing_event!(
+            error,
+            chain.id,
+            request,
+            "[{}@{}] attempted to sign at height {} which is greater than max_height {}{}",
+            &chain.id,
+            &self.config.addr,
+            height,
+            max_height,
+            self.max_height_origin()
+        );
+
+        metrics::refused(&chain.id, RefusalReason::MaxHeight);
+
+        Some(RemoteError::exceed_max_height(height, max_height.value()))
+    }
+
+    /// If a max block height is configured, record how many heights are left
+    /// before it after signing at the given height, logging them once every
+    /// [`MAX_HEIGHT_LOG_INTERVAL`] heights (as a warning or error once few
+    /// are left)
+    fn record_max_height_budget(&mut self, chain_id: &chain::Id, height: u64) {
+        let addr = self.config.addr.to_string();
+        let remaining = self
+            .config
+            .max_height
+            .map(|max_height| max_height.value().saturating_sub(height));
+
+        metrics::max_height_remaining(chain_id, &addr, remaining);
+        status::max_height_remaining(chain_id, &addr, remaining);
+
+        let remaining = match remaining {
+            Some(remaining) => remaining,
+            None => return,
+        };
+
+        let due = match self.max_height_logged.get(chain_id) {
+            Some(&logged) => height < logged || height - logged >= MAX_HEIGHT_LOG_INTERVAL,
+            None => true,
+        };
+
+        if !due {
+            return;
+        }
+
+        self.max_height_logged.insert(chain_id.clone(), height);
+
+        let max_height = self.config.max_height.unwrap();
+        let origin = self.max_height_origin();
+
+        if remaining <= MAX_HEIGHT_ERROR_REMAINING {
+            error!(
+                "[{}@{}] only {} heights left before max_height {}{}: signing stops after it",
+                chain_id, &self.config.addr, remaining, max_height, origin
+            );
+        } else if remaining <= MAX_HEIGHT_WARN_REMAINING {
+            warn!(
+                "[{}@{}] {} heights left before max_height {}{}",
+                chain_id, &self.config.addr, remaining, max_height, origin
+            );
+        } else {
+            info!(
+                "[{}@{}] {} heights left before max_height {}{}",
+                chain_id, &self.config.addr, remaining, max_height, origin
+            );
+        }
+    }
+
+    /// Describe where `max_height` is set in the configuration file (if
+    /// known), for log messages
+    fn max_height_origin(&self) -> String {
+        self.config
+            .max_height_origin
+            .as_ref()
+            .map(|origin| format!(" (set at {})", origin))
+            .unwrap_or_default()
+    }
+
+    /// If a min block height is configured, ensure the block we're signing
+    /// isn't below it
+    fn check_min_height<R>(&self, chain: &Chain, request: &R) -> Option<RemoteError>
+    where
+        R: TendermintRequest + Debug,
+    {
+        let min_height = self.config.min_height?;
+        let height = request.height()?;
+
+        if height >= min_height.value() as i64 {
+            return None;
+        }
+
+        signing_event!(
+            error,
+            chain.id,
+            request,
+            "[{}@{}] attempted to sign at height {} which is less than {}",
+            &chain.id,
+            &self.config.addr,
+            height,
+            min_height
+        );
+
+        metrics::refused(&chain.id, RefusalReason::MinHeight);
+
+        Some(RemoteError::below_min_height(height, min_height.value()))
+    }
+
+    /// Ensure the request isn't for one of the chain's `blocked_heights`
+    fn check_blocked_height<R>(&self, chain: &Chain, request: &R) -> Option<RemoteError>
+    where
+        R: TendermintRequest + Debug,
+    {
+        let height = request.height()?;
+        let rule = chain.blocked_heights.find(u64::try_from(height).ok()?)?;
+
+        signing_event!(
+            error,
+            chain.id,
+            request,
+            "[{}@{}] refusing to sign at height {}: blocked by blocked_heights rule \"{}\"",
+            &chain.id,
+            &self.config.addr,
+            height,
+            rule
+        );
+
+        metrics::refused(&chain.id, RefusalReason::BlockedHeight);
+
+        Some(RemoteError::blocked_height(height, rule))
+    }
+
+    /// If a max clock skew is configured, ensure the timestamp of the vote or
+    /// proposal we're signing is close to the host's clock
+    fn check_clock_skew<R>(&self, chain: &Chain, request: &R) -> Option<RemoteError>
+    where
+        R: TendermintRequest + Debug,
+    {
+        let max_clock_skew = chain.max_clock_skew?;
+
+        let skew = request
+            .timestamp()
+            .and_then(|timestamp| timestamp.skew_from(SystemTime::now()));
+
+        let remote_err = match skew {
+            Some(skew) if skew <= max_clock_skew => return None,
+            Some(skew) => {
+                signing_event!(
+                    error,
+                    chain.id,
+                    request,
+                    "[{}@{}] refusing to sign: timestamp differs from host clock by {:?} \
+                     (max_clock_skew_secs = {})",
+                    &chain.id,
+                    &self.config.addr,
+                    skew,
+                    max_clock_skew.as_secs()
+                );
+
+                RemoteError::clock_skew(skew, max_clock_skew)
             }
+            None => {
+                signing_event!(
+                    error,
+                    chain.id,
+                    request,
+                    "[{}@{}] refusing to sign: missing or negative timestamp {:?}",
+                    &chain.id,
+                    &self.config.addr,
+                    request.timestamp()
+                );
+
+                RemoteError::invalid_timestamp()
+            }
+        };
+
+        metrics::refused(&chain.id, RefusalReason::ClockSkew);
+
+        Some(remote_err)
+    }
+
+    /// Unless disabled for the chain, ensure the vote we're signing is from the
+    /// validator whose consensus key we hold
+    fn check_validator_address<R>(&self, chain: &Chain, request: &R) -> Option<RemoteError>
+    where
+        R: TendermintRequest + Debug,
+    {
+        if !chain.enforce_validator_address {
+            return None;
         }
 
-        Ok(())
+        let requested = request.validator_address()?;
+
+        // Keyrings without a single consensus key fail to sign anyway
+        let expected = chain.keyring.consensus_address().ok()?;
+
+        if expected.as_bytes() == requested {
+            return None;
+        }
+
+        let requested = String::from_utf8(hex::encode_upper(requested)).unwrap();
+
+        signing_event!(
+            error,
+            chain.id,
+            request,
+            "[{}@{}] refusing to sign: vote is from validator {}, but our consensus key's \
+             address is {} (misconfigured validator? set enforce_validator_address = false \
+             if the chain derives addresses differently)",
+            &chain.id,
+            &self.config.addr,
+            requested,
+            expected
+        );
+
+        metrics::refused(&chain.id, RefusalReason::ValidatorAddress);
+
+        Some(RemoteError::validator_address_mismatch(
+            &expected.to_string(),
+            &requested,
+        ))
+    }
+
+    /// If a request deadline is configured, ensure the request is still within
+    /// it (e.g. after waiting in the queue, or for the chain's lock)
+    fn check_deadline<R>(
+        &self,
+        chain: &Chain,
+        request: &R,
+        received_at: Instant,
+    ) -> Option<RemoteError>
+    where
+        R: TendermintRequest + Debug,
+    {
+        let deadline = self.config.request_deadline()?;
+        let waited = received_at.elapsed();
+
+        if waited <= deadline {
+            return None;
+        }
+
+        signing_event!(
+            warn,
+            chain.id,
+            request,
+            "[{}@{}] dropping request received {:?} ago (request_deadline_ms: {})",
+            &chain.id,
+            &self.config.addr,
+            waited,
+            deadline.as_millis()
+        );
+
+        metrics::refused(&chain.id, RefusalReason::Deadline);
+        status::dropped(
+            std::slice::from_ref(&chain.id),
+            &self.config.addr.to_string(),
+        );
+
+        Some(RemoteError::deadline_exceeded(waited, deadline))
+    }
+
+    /// Log an error from the signing provider and build a response which
+    /// reports it to the validator
+    fn signing_error<R>(&self, chain: &Chain, request: R, err: Error) -> Result<Response, Error>
+    where
+        R: TendermintRequest + Debug,
+    {
+        signing_event!(
+            error,
+            chain.id,
+            &request,
+            "[{}@{}] signing failed: {}",
+            &chain.id,
+            &self.config.addr,
+            err
+        );
+
+        alerts::raise(Alert::new(
+            alerts::Event::ProviderError,
+            &chain.id,
+            &self.config.addr,
+            format!("signing failed: {}", err),
+        ));
+
+        self.audit(chain, &request, |id, msg_type, state| {
+            audit::Entry::refused(id, msg_type, state, format!("signing failed: {}", err))
+        })?;
+
+        status::refused(&chain.id);
+        Ok(request.build_response(Some(RemoteError::signing_error(err))))
+    }
+
+    /// Record a signing decision in the chain's audit log (if enabled)
+    fn audit<R, F>(&self, chain: &Chain, request: &R, entry: F) -> Result<(), Error>
+    where
+        R: TendermintRequest + Debug,
+        F: FnOnce(&chain::Id, Option<SignedMsgType>, Option<&consensus::State>) -> audit::Entry,
+    {
+        let audit_log = match &chain.audit_log {
+            Some(audit_log) => audit_log,
+            None => return Ok(()),
+        };
+
+        let (msg_type, state) = match parse_request(request) {
+            Ok((msg_type, state)) => (Some(msg_type), Some(state)),
+            Err(_) => (request.msg_type(), None),
+        };
+
+        audit_log.append(entry(&chain.id, msg_type, state.as_ref()))
     }
 
     /// Update our local knowledge of the chain's consensus state, detecting
-    /// attempted double signing and sending a response in the event it happens
+    /// attempted double signing and sending a response in the event it happens.
+    ///
+    /// A retry of the payload last signed is answered with the original
+    /// signature (if still known). If it differs only in its timestamp, the
+    /// request and bytes to sign are reset to the original timestamp.
     fn update_consensus_state<R>(
         &mut self,
         chain: &Chain,
-        request: &R,
-    ) -> Result<Option<RemoteError>, Error>
+        request: &mut R,
+        to_sign: &mut Vec<u8>,
+    ) -> Result<StateUpdate, Error>
     where
-        R: TendermintRequest + Debug,
+        R: TendermintRequest + Clone + Debug,
     {
         let (msg_type, request_state) = parse_request(request)?;
 
         let mut chain_state = chain.state.lock().unwrap();
 
-        match chain_state.update_consensus_state(request_state.clone()) {
-            Ok(()) => Ok(None),
+        if let Some(timestamp) = chain_state
+            .signed_payload()
+            .and_then(|payload| payload.timestamp)
+        {
+            let timestamp = TimeMsg::from(timestamp);
+
+            if request.timestamp().as_ref() != Some(&timestamp) {
+                let mut retry = request.clone();
+                retry.set_timestamp(timestamp);
+
+                let mut retry_bytes = vec![];
+                retry.sign_bytes(
+                    chain.signing_id(request.chain_id()),
+                    self.config.protocol_version,
+                    &mut retry_bytes,
+                )?;
+
+                if chain_state.signed_payload().map(|p| &p.sign_bytes) == Some(&retry_bytes) {
+                    *request = retry;
+                    to_sign.clone_from(&retry_bytes);
+                }
+            }
+        }
+
+        let payload = SignedPayload {
+            sign_bytes: to_sign.clone(),
+            timestamp: request
+                .timestamp()
+                .and_then(|timestamp| timestamp.parse_timestamp().ok()),
+            msg_type: Some(msg_type),
+        };
+
+        match chain_state.update_signed_state(request_state.clone(), payload) {
+            Ok(Some(signature)) => Ok(StateUpdate::Resend(signature)),
+            Ok(None) => Ok(StateUpdate::Sign),
             Err(e) if e.kind() == StateErrorKind::DoubleSign => {
                 // Report double signing error back to the validator
                 let original_block_id = chain_state.consensus_state().block_id_prefix();
+                let block_id = request_state.block_id_prefix();
 
-                error!(
-                    "[{}@{}] attempted double sign {:?} at h/r/s: {} ({} != {})",
-                    &self.config.chain_id,
+                let conflict = if original_block_id == block_id {
+                    format!("conflicting payload for {}", block_id)
+                } else {
+                    format!("{} != {}", original_block_id, block_id)
+                };
+
+                let message = format!(
+                    "attempted double sign {:?} at h/r/s: {} ({})",
+                    msg_type, request_state, conflict
+                );
+
+                signing_event!(
+                    error,
+                    chain.id,
+                    request,
+                    "[{}@{}] {}",
+                    &chain.id,
                     &self.config.addr,
-                    msg_type,
-                    request_state,
-                    original_block_id,
-                    request_state.block_id_prefix()
+                    message
                 );
 
+                metrics::refused(&chain.id, RefusalReason::DoubleSign);
+
+                alerts::raise(Alert::new(
+                    alerts::Event::DoubleSign,
+                    &chain.id,
+                    &self.config.addr,
+                    message,
+                ));
+
                 let remote_err = RemoteError::double_sign(request_state.height.into());
-                Ok(Some(remote_err))
+                Ok(StateUpdate::Refuse(remote_err))
+            }
+            Err(e) if e.kind() == StateErrorKind::StepRegression => {
+                // Steps may arrive out of order now and then (e.g. a late
+                // proposal after a precommit), so they're refused without
+                // dropping the connection
+                signing_event!(
+                    warn,
+                    chain.id,
+                    request,
+                    "[{}@{}] refusing to sign {:?}: {}",
+                    &chain.id,
+                    &self.config.addr,
+                    msg_type,
+                    e
+                );
+
+                metrics::refused(&chain.id, RefusalReason::StepRegression);
+
+                Ok(StateUpdate::Refuse(RemoteError::step_regression(
+                    request_state.height.into(),
+                    request_state.round.value().into(),
+                    chain_state.consensus_state().step,
+                    request_state.step,
+                )))
+            }
+            Err(e) if e.kind() != StateErrorKind::SyncError => {
+                // Height/round regressions are refused by resetting the
+                // connection, but are just as alarming
+                alerts::raise(Alert::new(
+                    alerts::Event::DoubleSign,
+                    &chain.id,
+                    &self.config.addr,
+                    format!("refused {:?} at h/r/s {}: {}", msg_type, request_state, e),
+                ));
+
+                Err(e.into())
             }
             Err(e) => Err(e.into()),
         }
     }
 
-    /// Get the public key for (the only) public key in the keyring
-    fn get_public_key(&mut self, _request: &PubKeyRequest) -> Result<Response, Error> {
+    /// Get the public key for (the only) public key in the keyring of the
+    /// chain the request is for
+    fn get_public_key(&mut self, request: &PubKeyRequest) -> Result<Response, Error> {
+        let chain_id = match self.request_chain_id(request.chain_id()) {
+            Some(chain_id) => chain_id,
+            None => {
+                let requested = request.chain_id().unwrap_or_default();
+
+                warn!(
+                    "[{}@{}] refusing public key request for chain ID: {}",
+                    &self.config.chain_id, &self.config.addr, requested
+                );
+
+                return Ok(Response::PublicKeyRefused(self.refuse_chain_id(requested)));
+            }
+        };
         let registry = chain::REGISTRY.get();
 
-        let chain = registry
-            .get_chain(&self.config.chain_id)
-            .unwrap_or_else(|| {
-                panic!("chain '{}' missing from registry!", &self.config.chain_id);
-            });
+        // The chain may have been removed by a reload since this session began
+        let chain = match registry.get_validator_chain(chain_id, self.config.label.as_deref()) {
+            Some(chain) => chain,
+            None => {
+                error!(
+                    "[{}@{}] chain missing from registry!",
+                    chain_id, &self.config.addr
+                );
 
-        Ok(Response::PublicKey(PubKeyResponse::from(
-            *chain.keyring.default_ed25519_pubkey()?,
-        )))
+                return Ok(Response::PublicKeyRefused(RemoteError::unknown_chain_id(
+                    chain_id.as_str(),
+                )));
+            }
+        };
+
+        Ok(Response::PublicKey(
+            *chain.keyring.default_consensus_pubkey()?,
+        ))
     }
 
     /// Write an INFO logline about a signing request
-    fn log_signing_request<R>(&self, request: &R, started_at: Instant) -> Result<(), Error>
+    fn log_signing_request<R>(
+        &self,
+        chain: &Chain,
+        request: &R,
+        sign_bytes: &[u8],
+        started_at: Instant,
+    ) -> Result<(), Error>
     where
         R: TendermintRequest + Debug,
     {
         let (msg_type, request_state) = parse_request(request)?;
 
-        info!(
-            "[{}@{}] signed {:?}:{} at h/r/s {} ({} ms)",
-            &self.config.chain_id,
+        signing_event!(
+            info,
+            chain.id,
+            request,
+            "[{}@{}] signed {:?}:{} at h/r/s {} (sign bytes SHA-256 {}) ({} ms)",
+            &chain.id,
             &self.config.addr,
             msg_type,
             request_state.block_id_prefix(),
             request_state,
+            state::sign_bytes_hash(sign_bytes),
             started_at.elapsed().as_millis(),
         );
 
@@ -259,6 +1509,41 @@ impl Session {
     }
 }
 
+/// Outcome of checking a signing request against the chain's consensus state
+enum StateUpdate {
+    /// Sign the request
+    Sign,
+
+    /// The request is a retry of the payload last signed: respond with the
+    /// original signature
+    Resend(Vec<u8>),
+
+    /// Refuse the request (i.e. it's a double sign or a step regression)
+    Refuse(RemoteError),
+}
+
+/// Sign a consensus message with the chain's keyring, recording the time
+/// taken by the signing provider (and nothing else)
+fn sign_consensus(chain: &Chain, msg: &[u8]) -> Result<Vec<u8>, Error> {
+    let provider = chain.keyring.consensus_provider()?;
+    let started_at = Instant::now();
+    let signature = chain.keyring.sign_consensus(msg)?;
+    latency::record(&chain.id, provider.name(), started_at.elapsed());
+    Ok(signature)
+}
+
+/// Sleep for the delay given in [`SIGNING_DELAY_ENV_VAR`] (if any)
+#[cfg(debug_assertions)]
+fn signing_delay() {
+    let delay = std::env::var(SIGNING_DELAY_ENV_VAR)
+        .ok()
+        .and_then(|millis| millis.parse().ok());
+
+    if let Some(millis) = delay {
+        std::thread::sleep(std::time::Duration::from_millis(millis));
+    }
+}
+
 /// Parse the consensus state from an incoming request
 // TODO(tarcieri): fix the upstream Amino parser to do this correctly for us
 fn parse_request<R>(request: &R) -> Result<(SignedMsgType, consensus::State), Error>
@@ -273,11 +1558,7 @@ where
         .consensus_state()
         .ok_or_else(|| format_err!(ProtocolError, "no consensus state in request"))?;
 
-    consensus_state.step = match msg_type {
-        SignedMsgType::Proposal => 0,
-        SignedMsgType::PreVote => 1,
-        SignedMsgType::PreCommit => 2,
-    };
+    consensus_state.step = Step::from(msg_type).value();
 
     Ok((msg_type, consensus_state))
 }
@@ -0,0 +1,111 @@
+//! Session request loop for clients run on the async runtime (`async` cargo
+//! feature)
+//!
+//! Connections are still read and written with blocking I/O, which (along
+//! with signing, which may block on an HSM) runs on the runtime's blocking
+//! thread pool, while the loop itself waits for the next request, the
+//! connection's idle timeout, and shutdown at once. As with threaded
+//! clients, a client which is stopped (e.g. because its validator was
+//! removed from the configuration) exits once its next request is handled.
+
+use super::Session;
+use crate::{
+    client::Control,
+    connection,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    shutdown,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::task::{self, JoinError};
+
+/// How often to check whether the client has been stopped (or shutdown
+/// requested)
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+impl Session {
+    /// Handle requests from the validator until the client is stopped, the
+    /// connection fails, or the validator sends no request within the
+    /// connection's timeout
+    pub async fn run(mut self, control: Arc<Control>) -> Result<(), Error> {
+        let idle_timeout = connection::timeout(self.handler.config().timeout);
+
+        while !control.is_stopped() {
+            let mut read = task::spawn_blocking(move || {
+                let mut msg = std::mem::take(&mut self.msg);
+                let request = self.read_request(&mut msg);
+                (self, msg, request)
+            });
+
+            let (session, msg, request) = tokio::select! {
+                result = &mut read => result.map_err(from_join_error)?,
+                _ = shutdown_requested() => {
+                    // Unblock the read, which fails once interrupted
+                    control.interrupt();
+                    let _ = read.await;
+                    return Ok(());
+                }
+                _ = tokio::time::sleep(idle_timeout) => {
+                    control.interrupt_session();
+                    let _ = read.await;
+                    fail!(
+                        IoError,
+                        "no request from validator in {}s: dropping connection",
+                        idle_timeout.as_secs()
+                    );
+                }
+            };
+
+            self = session;
+            let (request, received_at) = match request {
+                Ok(request) => request,
+                Err(e) => {
+                    self.msg = msg;
+                    return Err(e);
+                }
+            };
+
+            let responded = {
+                let control = control.clone();
+
+                task::spawn_blocking(move || {
+                    let result = self.respond(&msg, request, received_at, &control);
+                    self.msg = msg;
+                    (self, result)
+                })
+            };
+
+            let (session, result) = responded.await.map_err(from_join_error)?;
+            self = session;
+
+            if !result? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Wait until shutdown is requested (see [`shutdown`])
+async fn shutdown_requested() {
+    while !shutdown::requested() {
+        tokio::time::sleep(STOP_POLL_INTERVAL).await;
+    }
+}
+
+/// Wait until the given client is stopped
+pub(crate) async fn stopped(control: &Control) {
+    while !control.is_stopped() {
+        tokio::time::sleep(STOP_POLL_INTERVAL).await;
+    }
+}
+
+/// Convert the error of a blocking task which panicked (or was cancelled)
+pub(crate) fn from_join_error(e: JoinError) -> Error {
+    if e.is_panic() {
+        Error::from_panic(e.into_panic())
+    } else {
+        format_err!(PanicError, "task cancelled: {}", e).into()
+    }
+}
@@ -0,0 +1,302 @@
+//! Per-connection FIFO queue of requests from a validator
+
+use crate::{
+    amino_types::{RemoteError, TendermintRequest},
+    chain,
+    config::ValidatorConfig,
+    error::{Error, ErrorKind::*},
+    metrics::{self, RefusalReason},
+    prelude::*,
+    rpc::{Request, Response},
+    status,
+};
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::Instant,
+};
+
+/// Default maximum number of requests queued at once, i.e. one at a time
+pub const DEFAULT_DEPTH: usize = 1;
+
+/// FIFO queue of the requests from a validator connection: requests are
+/// handled strictly in the order they arrived, and any arriving while
+/// `request_queue_depth` others are queued are dropped
+#[derive(Clone, Debug)]
+pub struct RequestQueue {
+    /// State shared with the queue's tickets
+    inner: Arc<Inner>,
+}
+
+/// State of a [`RequestQueue`]
+#[derive(Debug)]
+struct Inner {
+    /// Maximum number of requests queued at once (including the one being
+    /// handled)
+    max_depth: usize,
+
+    /// Chains served by the validator
+    chain_ids: Vec<chain::Id>,
+
+    /// Address of the validator
+    addr: String,
+
+    /// Ticket numbers of the requests at the front and back of the queue
+    positions: Mutex<Positions>,
+
+    /// Notified when the request at the front of the queue is done
+    turn: Condvar,
+}
+
+/// Ticket numbers of the requests at the front and back of the queue
+#[derive(Debug, Default)]
+struct Positions {
+    /// Ticket of the request being handled (or next to be)
+    front: u64,
+
+    /// Ticket to give the next request to arrive
+    back: u64,
+
+    /// Tickets of requests which left the queue before their turn (e.g.
+    /// because the validator gave up on them)
+    abandoned: BTreeSet<u64>,
+}
+
+impl Positions {
+    /// Number of requests in the queue
+    fn depth(&self) -> usize {
+        (self.back - self.front) as usize - self.abandoned.len()
+    }
+
+    /// Move on to the next request which hasn't been abandoned
+    fn advance(&mut self) {
+        self.front += 1;
+
+        while self.abandoned.remove(&self.front) {
+            self.front += 1;
+        }
+    }
+}
+
+/// Place of a request in a [`RequestQueue`], which it leaves when dropped
+#[derive(Debug)]
+pub struct Ticket {
+    /// Queue the request is in
+    queue: Arc<Inner>,
+
+    /// Number of this ticket
+    number: u64,
+
+    /// When the request arrived
+    received_at: Instant,
+}
+
+impl RequestQueue {
+    /// Create a queue for the requests from the given validator
+    pub fn new(config: &ValidatorConfig) -> Self {
+        Self::with_depth(
+            config.request_queue_depth.unwrap_or(DEFAULT_DEPTH),
+            config.chain_ids.clone(),
+            config.addr.to_string(),
+        )
+    }
+
+    /// Create a queue holding at most `max_depth` requests
+    fn with_depth(max_depth: usize, chain_ids: Vec<chain::Id>, addr: String) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                max_depth,
+                chain_ids,
+                addr,
+                positions: Mutex::new(Positions::default()),
+                turn: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Add a request which arrived at `received_at` to the back of the
+    /// queue, or return `None` if the queue is full (i.e. the request should
+    /// be dropped, which is recorded as a refusal for the validator's chain)
+    pub fn push(&self, received_at: Instant) -> Option<Ticket> {
+        let mut positions = self.inner.lock();
+
+        if positions.depth() >= self.inner.max_depth {
+            drop(positions);
+
+            warn!(
+                chain_id = %self.inner.chain_ids[0],
+                "[{}@{}] request queue full ({} requests queued): dropping request",
+                &self.inner.chain_ids[0],
+                &self.inner.addr,
+                self.inner.max_depth
+            );
+
+            self.inner.dropped(RefusalReason::QueueFull);
+            return None;
+        }
+
+        let number = positions.back;
+        positions.back += 1;
+        self.inner.record_depth(positions.depth());
+
+        Some(Ticket {
+            queue: self.inner.clone(),
+            number,
+            received_at,
+        })
+    }
+
+    /// Build the response refusing a request dropped because the queue was
+    /// full. Only signing requests can be refused, so it's an error (which
+    /// drops the connection) for any other request.
+    pub fn refusal(&self, request: Request) -> Result<Response, Error> {
+        let remote_err = RemoteError::queue_full(self.inner.max_depth);
+
+        match request {
+            Request::SignProposal(req) => Ok(req.build_response(Some(remote_err))),
+            Request::SignVote(req) => Ok(req.build_response(Some(remote_err))),
+            _ => fail!(ProtocolError, "{}", remote_err.description),
+        }
+    }
+}
+
+impl Inner {
+    /// Lock the queue's positions
+    fn lock(&self) -> MutexGuard<'_, Positions> {
+        // Positions are always consistent, even if a holder panicked
+        self.positions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record the number of queued requests
+    fn record_depth(&self, depth: usize) {
+        metrics::request_queue_depth(&self.chain_ids[0], &self.addr, depth);
+        status::queue_depth(&self.chain_ids, &self.addr, depth);
+    }
+
+    /// Record a request being dropped for the given reason
+    fn dropped(&self, reason: RefusalReason) {
+        metrics::refused(&self.chain_ids[0], reason);
+        status::refused(&self.chain_ids[0]);
+        status::dropped(&self.chain_ids, &self.addr);
+    }
+}
+
+impl Ticket {
+    /// Get when the request arrived
+    pub fn received_at(&self) -> Instant {
+        self.received_at
+    }
+
+    /// Block until every request which arrived before this one is done
+    pub fn wait(&self) {
+        let positions = self.queue.lock();
+
+        let _positions = self
+            .queue
+            .turn
+            .wait_while(positions, |positions| positions.front != self.number)
+            .unwrap_or_else(|e| e.into_inner());
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut positions = self.queue.lock();
+
+        if positions.front == self.number {
+            positions.advance();
+        } else {
+            positions.abandoned.insert(self.number);
+        }
+
+        self.queue.record_depth(positions.depth());
+        drop(positions);
+
+        self.queue.turn.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    fn queue(max_depth: usize) -> RequestQueue {
+        RequestQueue::with_depth(
+            max_depth,
+            vec![chain::Id::try_from("queue-test-chain").unwrap()],
+            "unix:///tmp/queue-test.sock".to_owned(),
+        )
+    }
+
+    #[test]
+    fn drops_requests_when_full() {
+        let queue = queue(2);
+        let now = Instant::now();
+
+        let first = queue.push(now).unwrap();
+        let second = queue.push(now).unwrap();
+        assert!(queue.push(now).is_none());
+
+        drop(first);
+        let _third = queue.push(now).unwrap();
+        assert!(queue.push(now).is_none());
+
+        // Requests leaving before their turn make room too
+        drop(second);
+        assert!(queue.push(now).is_some());
+    }
+
+    #[test]
+    fn handles_requests_in_order() {
+        let queue = queue(3);
+        let handled = Arc::new(Mutex::new(vec![]));
+
+        let first = queue.push(Instant::now()).unwrap();
+        let tickets: Vec<_> = (1..3)
+            .map(|i| (i, queue.push(Instant::now()).unwrap()))
+            .collect();
+
+        // Later requests are started first, but still wait their turn
+        let threads: Vec<_> = tickets
+            .into_iter()
+            .rev()
+            .map(|(i, ticket)| {
+                let handled = handled.clone();
+                let thread = thread::spawn(move || {
+                    ticket.wait();
+                    handled.lock().unwrap().push(i);
+                });
+
+                thread::sleep(Duration::from_millis(10));
+                thread
+            })
+            .collect();
+
+        first.wait();
+        handled.lock().unwrap().push(0);
+        drop(first);
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(*handled.lock().unwrap(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn skips_abandoned_requests() {
+        let queue = queue(3);
+        let now = Instant::now();
+
+        let first = queue.push(now).unwrap();
+        let second = queue.push(now).unwrap();
+        let third = queue.push(now).unwrap();
+
+        drop(second);
+        drop(first);
+
+        // Returns immediately, as the request before it was abandoned
+        third.wait();
+    }
+}
@@ -0,0 +1,136 @@
+//! Token bucket limiting the rate of signing requests from a validator
+
+use std::time::{Duration, Instant};
+
+/// Minimum time between warnings about refused requests
+pub const WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Token bucket which refills at `max_requests_per_second`, holding at most
+/// that many tokens (i.e. a second's worth of requests may arrive at once)
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Maximum number of requests per second
+    max_requests_per_second: u32,
+
+    /// Tokens currently in the bucket
+    tokens: f64,
+
+    /// When the bucket was last refilled
+    refilled_at: Instant,
+
+    /// Number of consecutive requests refused
+    violations: u32,
+
+    /// Number of requests refused since the last warning
+    unreported: u64,
+
+    /// When the last warning was logged
+    warned_at: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter with a full bucket
+    pub fn new(max_requests_per_second: u32, now: Instant) -> Self {
+        Self {
+            max_requests_per_second,
+            tokens: f64::from(max_requests_per_second),
+            refilled_at: now,
+            violations: 0,
+            unreported: 0,
+            warned_at: None,
+        }
+    }
+
+    /// Get the maximum number of requests per second
+    pub fn max_requests_per_second(&self) -> u32 {
+        self.max_requests_per_second
+    }
+
+    /// Take a token for a request arriving at the given time, returning
+    /// `false` if the bucket is empty (i.e. the request should be refused)
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        let rate = f64::from(self.max_requests_per_second);
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate).min(rate);
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.violations = 0;
+            true
+        } else {
+            self.violations = self.violations.saturating_add(1);
+            self.unreported += 1;
+            false
+        }
+    }
+
+    /// Get the number of consecutive requests refused
+    pub fn violations(&self) -> u32 {
+        self.violations
+    }
+
+    /// If a warning about refused requests is due (at most one per
+    /// [`WARNING_INTERVAL`]), get the number refused since the last one
+    pub fn take_warning(&mut self, now: Instant) -> Option<u64> {
+        if self.unreported == 0 {
+            return None;
+        }
+
+        if let Some(warned_at) = self.warned_at {
+            if now.saturating_duration_since(warned_at) < WARNING_INTERVAL {
+                return None;
+            }
+        }
+
+        self.warned_at = Some(now);
+        Some(std::mem::take(&mut self.unreported))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_requests_over_the_limit() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(5, start);
+
+        for _ in 0..5 {
+            assert!(limiter.try_acquire(start));
+        }
+
+        assert!(!limiter.try_acquire(start));
+        assert!(!limiter.try_acquire(start));
+        assert_eq!(limiter.violations(), 2);
+
+        // A token is added every 200 ms
+        assert!(limiter.try_acquire(start + Duration::from_millis(200)));
+        assert_eq!(limiter.violations(), 0);
+        assert!(!limiter.try_acquire(start + Duration::from_millis(300)));
+
+        // Refills never exceed a second's worth of requests
+        let later = start + Duration::from_secs(60);
+        for _ in 0..5 {
+            assert!(limiter.try_acquire(later));
+        }
+        assert!(!limiter.try_acquire(later));
+    }
+
+    #[test]
+    fn throttles_warnings() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(1, start);
+        assert_eq!(limiter.take_warning(start), None);
+
+        assert!(limiter.try_acquire(start));
+        assert!(!limiter.try_acquire(start));
+        assert_eq!(limiter.take_warning(start), Some(1));
+
+        assert!(!limiter.try_acquire(start));
+        assert!(!limiter.try_acquire(start));
+        assert_eq!(limiter.take_warning(start + Duration::from_secs(1)), None);
+        assert_eq!(limiter.take_warning(start + WARNING_INTERVAL), Some(2));
+    }
+}
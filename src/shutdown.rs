@@ -0,0 +1,211 @@
+//! Graceful shutdown on SIGTERM/SIGINT (and configuration reloading on
+//! SIGHUP, and signing latency, client, and chain status reports on SIGUSR1).
+//!
+//! Signals are blocked in every thread and received synchronously by a
+//! dedicated thread using `sigwait(3)`, so no work happens in signal handler
+//! context. Once shutdown is requested no new requests are accepted, and the
+//! process exits as soon as the in-flight ones (if any) have persisted their
+//! state and written their responses and the validator clients have been
+//! stopped and joined, or forcibly once the grace period has elapsed.
+
+use crate::{
+    client::CLIENTS,
+    error::{Error, ErrorKind::*},
+    latency,
+    prelude::*,
+    status,
+};
+use once_cell::sync::Lazy;
+use std::{
+    io, mem, process, ptr,
+    sync::{Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Default time to wait for in-flight requests to complete (in seconds)
+pub const DEFAULT_GRACE_PERIOD: u64 = 5;
+
+/// Exit status used when the grace period elapses before in-flight requests
+/// have completed
+pub const FORCED_EXIT_STATUS: i32 = 1;
+
+/// Shutdown state shared between the signal thread and request handlers
+static STATE: Lazy<(Mutex<State>, Condvar)> =
+    Lazy::new(|| (Mutex::new(State::default()), Condvar::new()));
+
+/// Shutdown state
+#[derive(Debug, Default)]
+struct State {
+    /// Has shutdown been requested?
+    requested: bool,
+
+    /// Number of requests currently being handled
+    in_flight: usize,
+}
+
+/// Guard which marks a request as in-flight until it's dropped
+#[derive(Debug)]
+pub struct InFlight(());
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let (lock, condvar) = &*STATE;
+        let mut state = lock.lock().unwrap_or_else(|e| e.into_inner());
+        state.in_flight -= 1;
+        condvar.notify_all();
+    }
+}
+
+/// Mark the start of handling a request, returning a guard which defers
+/// shutdown until it's dropped, or `None` if shutdown is in progress and the
+/// request should not be handled
+pub fn begin_request() -> Option<InFlight> {
+    let mut state = STATE.0.lock().unwrap_or_else(|e| e.into_inner());
+
+    if state.requested {
+        None
+    } else {
+        state.in_flight += 1;
+        Some(InFlight(()))
+    }
+}
+
+/// Has shutdown been requested?
+pub fn requested() -> bool {
+    STATE.0.lock().unwrap_or_else(|e| e.into_inner()).requested
+}
+
+/// Install signal handling for the current process.
+///
+/// Must be called before any other threads are spawned so they inherit the
+/// signal mask.
+///
+/// - `SIGTERM`/`SIGINT`: graceful shutdown, waiting up to `grace_period` for
+///   in-flight requests to complete
+/// - `SIGHUP`: invokes `reload`
+/// - `SIGUSR1`: logs the signing latency of recent signatures, the status of
+///   each validator client, and a table of each chain's [`status`]
+pub fn install_handlers<F>(grace_period: Duration, reload: F) -> Result<(), Error>
+where
+    F: FnMut() + Send + 'static,
+{
+    let signals = signal_set(&[libc::SIGTERM, libc::SIGINT, libc::SIGHUP, libc::SIGUSR1]);
+
+    let rc = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &signals, ptr::null_mut()) };
+
+    if rc != 0 {
+        fail!(
+            IoError,
+            "error blocking signals: {}",
+            io::Error::from_raw_os_error(rc)
+        );
+    }
+
+    thread::Builder::new()
+        .name("signals".to_owned())
+        .spawn(move || wait_for_signals(signals, grace_period, reload))
+        .map_err(|e| format_err!(IoError, "error spawning signal thread: {}", e))?;
+
+    Ok(())
+}
+
+/// Receive signals, shutting down the process on `SIGTERM` or `SIGINT`,
+/// invoking `reload` on `SIGHUP`, and logging signing latency and client
+/// status on `SIGUSR1`
+fn wait_for_signals<F>(signals: libc::sigset_t, grace_period: Duration, mut reload: F)
+where
+    F: FnMut(),
+{
+    loop {
+        let mut signal = 0;
+        let rc = unsafe { libc::sigwait(&signals, &mut signal) };
+
+        if rc != 0 {
+            error!(
+                "error waiting for signals: {}",
+                io::Error::from_raw_os_error(rc)
+            );
+            return;
+        }
+
+        match signal {
+            libc::SIGHUP => reload(),
+            libc::SIGUSR1 => {
+                latency::log_summary();
+                CLIENTS.log_status();
+                status::log_summary();
+            }
+            libc::SIGINT => shutdown("SIGINT", grace_period),
+            _ => shutdown("SIGTERM", grace_period),
+        }
+    }
+}
+
+/// Stop accepting new requests and exit once the in-flight ones have
+/// completed and the clients have exited, or forcibly once `grace_period` has
+/// elapsed
+fn shutdown(signal: &str, grace_period: Duration) -> ! {
+    let deadline = Instant::now() + grace_period;
+    let (lock, condvar) = &*STATE;
+    let mut state = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    state.requested = true;
+    info!(
+        "received {}: shutting down ({} request(s) in flight)",
+        signal, state.in_flight
+    );
+
+    while state.in_flight > 0 {
+        let now = Instant::now();
+
+        if now >= deadline {
+            error!(
+                "{} request(s) still in flight after {:?}: forcing exit",
+                state.in_flight, grace_period
+            );
+            latency::log_summary();
+            process::exit(FORCED_EXIT_STATUS);
+        }
+
+        state = condvar
+            .wait_timeout(state, deadline - now)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+    }
+
+    // Sessions check for shutdown before handling each request, so holding
+    // the lock while joining the clients would deadlock
+    drop(state);
+
+    CLIENTS.interrupt();
+    let running = CLIENTS.wait_until(deadline);
+
+    // Nothing is in flight, so clients which are still blocked (e.g. waiting
+    // for a validator to connect) are safe to abandon
+    if !running.is_empty() {
+        warn!(
+            "client(s) still running after {:?}: {}",
+            grace_period,
+            running.join(", ")
+        );
+    }
+
+    latency::log_summary();
+    info!("Shutdown completed successfully");
+    process::exit(0);
+}
+
+/// Build a signal set containing the given signals
+fn signal_set(signals: &[libc::c_int]) -> libc::sigset_t {
+    unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+
+        for &signal in signals {
+            libc::sigaddset(&mut set, signal);
+        }
+
+        set
+    }
+}
@@ -0,0 +1,375 @@
+//! Library API for embedding the KMS in other applications.
+//!
+//! A [`Signer`] is built from a [`KmsConfig`] (e.g. parsed from a TOML
+//! string, or constructed in code) and either started, which connects to the
+//! configured validators just like `tmkms start`, or used to [`Signer::serve`]
+//! a validator over a stream supplied by the embedding application. Both
+//! return a [`Handle`] which is used to wait for or shut down the signer.
+//!
+//! Applications which need their own signing providers or consensus state
+//! storage can build a [`chain::Registry`] themselves, registering chains
+//! created with [`Chain::new`](chain::Chain::new) and adding the keys of
+//! their own [`SignerProvider`](keyring::SignerProvider) implementations to
+//! them with [`Registry::add_provider`](chain::Registry::add_provider), and
+//! pass it to [`Signer::with_registry`] (see `examples/custom_provider.rs`).
+//!
+//! The chain registry is global to the process, so only one [`Signer`] may
+//! exist at a time.
+//!
+//! # Example
+//!
+//! Serving a validator connected over a [`UnixStream`] pair with a
+//! `softsign` key (requires the `softsign` cargo feature):
+//!
+//! ```
+//! # #[cfg(feature = "softsign")]
+//! # fn main() -> Result<(), tmkms::error::Error> {
+//! use prost::Message as _;
+//! use std::{io::{Read, Write}, os::unix::net::UnixStream};
+//! use tendermint_proto::privval::{message::Sum, Message, PingRequest};
+//! use tmkms::{config::KmsConfig, signer::Signer};
+//!
+//! let state_dir = tempfile::tempdir()?;
+//! let config: KmsConfig = format!(
+//!     r#"
+//!     [[chain]]
+//!     id = "cosmoshub-4"
+//!     key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+//!     state_file = "{}/cosmoshub-4-state.json"
+//!
+//!     [[validator]]
+//!     chain_id = "cosmoshub-4"
+//!     addr = "unix:///var/run/validator.sock"
+//!     protocol_version = "v0.34"
+//!
+//!     [[providers.softsign]]
+//!     chain_ids = ["cosmoshub-4"]
+//!     key_format = "base64"
+//!     path = "tests/support/signing.key"
+//!     "#,
+//!     state_dir.path().display()
+//! )
+//! .parse()?;
+//!
+//! let signer = Signer::new(config)?;
+//! let validator_config = signer.config().validator[0].clone();
+//!
+//! let (kms_end, mut validator_end) = UnixStream::pair()?;
+//! let handle = signer.serve(validator_config, kms_end)?;
+//!
+//! let ping = Message {
+//!     sum: Some(Sum::PingRequest(PingRequest {})),
+//! };
+//! validator_end.write_all(&ping.encode_length_delimited_to_vec())?;
+//!
+//! let mut response = [0u8; 64];
+//! let len = validator_end.read(&mut response)?;
+//! let response = Message::decode_length_delimited(&response[..len]).unwrap();
+//! assert!(matches!(response.sum, Some(Sum::PingResponse(_))));
+//!
+//! handle.shutdown()?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "softsign"))]
+//! # fn main() {}
+//! ```
+
+use crate::{
+    alerts,
+    chain::{self, REGISTRY},
+    client::{self, Control, CLIENTS},
+    config::{KmsConfig, ValidatorConfig},
+    connection::unix::UnixConnection,
+    control,
+    error::{Error, ErrorKind::*},
+    height_monitor, keyring, latency, metrics,
+    prelude::*,
+    session::Session,
+    status, tx_signer_socket,
+};
+use std::{
+    io,
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Is a [`Signer`] currently active?
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Signer which handles requests from validators using the chains and keys
+/// in the global chain registry
+pub struct Signer {
+    /// Active configuration
+    config: Mutex<Arc<KmsConfig>>,
+
+    /// Was the chain registry supplied by the application?
+    custom_registry: bool,
+
+    /// Have the configured validator clients been started?
+    started: AtomicBool,
+}
+
+impl Signer {
+    /// Create a signer from the given configuration, registering its chains
+    /// and loading the keys of its signing providers
+    pub fn new(config: impl Into<Arc<KmsConfig>>) -> Result<Self, Error> {
+        let signer = Self::activate(config.into(), false)?;
+        chain::load_config(&signer.config())?;
+        signer.check_chains(&signer.config().validator)?;
+        Ok(signer)
+    }
+
+    /// Create a signer which uses the given chain registry in place of the
+    /// configuration's `[[chain]]` sections. The keys of the configuration's
+    /// signing providers are added to the registry's keyrings.
+    pub fn with_registry(
+        config: impl Into<Arc<KmsConfig>>,
+        mut registry: chain::Registry,
+    ) -> Result<Self, Error> {
+        let signer = Self::activate(config.into(), true)?;
+        keyring::load_config(&mut registry, &signer.config().providers)?;
+        chain::check_consensus_keys(&registry)?;
+        REGISTRY.replace(registry);
+        signer.check_chains(&signer.config().validator)?;
+        Ok(signer)
+    }
+
+    /// Get the active configuration
+    pub fn config(&self) -> Arc<KmsConfig> {
+        self.config.lock().unwrap().clone()
+    }
+
+    /// Start the metrics server, alerts and control socket (if configured) and connect to the
+    /// configured validators, returning a handle to the validator clients.
+    ///
+    /// Clients are shut down once their current session handles its next
+    /// request, or fails.
+    pub fn start(&self) -> Result<Handle, Error> {
+        if self.started.swap(true, Ordering::SeqCst) {
+            fail!(ConfigError, "signer already started");
+        }
+
+        let config = self.config();
+        client::set_async(config.async_clients)?;
+
+        if let Some(metrics_config) = &config.metrics {
+            metrics::spawn_server(metrics_config)?;
+        }
+
+        if let Some(alerts_config) = &config.alerts {
+            alerts::spawn(alerts_config)?;
+        }
+
+        if let Some(status_file) = &config.status_file {
+            status::spawn_writer(status_file)?;
+        }
+
+        height_monitor::spawn(&config)?;
+
+        if let Some(control_socket) = &config.control_socket {
+            control::spawn_server(control_socket)?;
+        }
+
+        if let Some(tx_signer_socket) = &config.tx_signer_socket {
+            tx_signer_socket::spawn_server(tx_signer_socket)?;
+        }
+
+        CLIENTS.spawn(&config.validator);
+        Ok(Handle(Inner::Clients))
+    }
+
+    /// Serve requests from a validator connected over the given stream on a
+    /// new thread. The `addr` in `validator` is only used in log messages.
+    pub fn serve<S: Stream>(&self, validator: ValidatorConfig, stream: S) -> Result<Handle, Error> {
+        self.check_chains(std::slice::from_ref(&validator))?;
+
+        let control = Arc::new(Control::new(&validator));
+        let interrupt = stream.try_clone()?;
+        let name = format!("{}@{}", &validator.chain_id, &validator.addr);
+
+        let thread = {
+            let control = control.clone();
+
+            thread::Builder::new().name(name).spawn(move || {
+                let connection = Box::new(UnixConnection::new(stream));
+                Session::new(validator, connection).request_loop(&control)
+            })?
+        };
+
+        Ok(Handle(Inner::Session {
+            control,
+            interrupt: Box::new(interrupt),
+            thread,
+        }))
+    }
+
+    /// Replace the active configuration, applying changes to the registered
+    /// chains and (if started) the validator clients. If the new
+    /// configuration can't be loaded, the active one remains in place.
+    ///
+    /// Not supported for signers created with [`Signer::with_registry`].
+    pub fn reload(&self, config: impl Into<Arc<KmsConfig>>) -> Result<chain::Changes, Error> {
+        if self.custom_registry {
+            fail!(
+                ConfigError,
+                "can't reload the configuration of a signer with a custom chain registry"
+            );
+        }
+
+        let config = config.into();
+        let mut active = self.config.lock().unwrap();
+        let changes = chain::reload_config(&active, &config)?;
+        latency::set_slow_sign_threshold(config.slow_sign_threshold_ms);
+
+        if self.started.load(Ordering::SeqCst) {
+            CLIENTS.reload(&config.validator);
+        }
+
+        *active = config;
+        Ok(changes)
+    }
+
+    /// Claim the global chain registry for a new signer
+    fn activate(config: Arc<KmsConfig>, custom_registry: bool) -> Result<Self, Error> {
+        if ACTIVE.swap(true, Ordering::SeqCst) {
+            fail!(ConfigError, "only one signer may be active at a time");
+        }
+
+        latency::set_slow_sign_threshold(config.slow_sign_threshold_ms);
+
+        Ok(Self {
+            config: Mutex::new(config),
+            custom_registry,
+            started: AtomicBool::new(false),
+        })
+    }
+
+    /// Ensure the chains of the given validators are registered
+    fn check_chains(&self, validators: &[ValidatorConfig]) -> Result<(), Error> {
+        let registry = REGISTRY.get();
+
+        for validator in validators {
+            for chain_id in &validator.chain_ids {
+                let label = validator.label.as_deref();
+
+                if registry.get_validator_chain(chain_id, label).is_none() {
+                    fail!(
+                        ConfigError,
+                        "unregistered chain: {} (add it to tmkms.toml's [[chain]] section)",
+                        chain::name(chain_id, label)
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Signer {
+    /// Clear the global chain registry, allowing another signer to be created
+    fn drop(&mut self) {
+        REGISTRY.replace(chain::Registry::default());
+        ACTIVE.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Handle to a running [`Signer`], used to wait for it to exit or shut it down
+pub struct Handle(Inner);
+
+/// What a [`Handle`] refers to
+enum Inner {
+    /// Clients for the configured validators
+    Clients,
+
+    /// Session with a validator connected over a [`Stream`]
+    Session {
+        /// Control of the session
+        control: Arc<Control>,
+
+        /// Stream used to interrupt the session
+        interrupt: Box<dyn Interrupt>,
+
+        /// Thread running the session
+        thread: thread::JoinHandle<Result<(), Error>>,
+    },
+}
+
+impl Handle {
+    /// Wait for the signer to exit, e.g. because the validator disconnected
+    pub fn wait(self) -> Result<(), Error> {
+        match self.0 {
+            Inner::Clients => {
+                if CLIENTS.wait() {
+                    Ok(())
+                } else {
+                    fail!(ProtocolError, "one or more clients exited with errors")
+                }
+            }
+            Inner::Session { thread, .. } => {
+                thread.join().unwrap_or_else(|e| Err(Error::from_panic(e)))
+            }
+        }
+    }
+
+    /// Shut the signer down, waiting for it to exit
+    pub fn shutdown(self) -> Result<(), Error> {
+        match self.0 {
+            Inner::Clients => {
+                CLIENTS.stop();
+                Handle(Inner::Clients).wait()
+            }
+            Inner::Session {
+                control,
+                interrupt,
+                thread,
+            } => {
+                control.stop();
+                interrupt.interrupt()?;
+
+                // Errors reading from the interrupted stream are expected
+                match thread.join() {
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(Error::from_panic(e)),
+                }
+            }
+        }
+    }
+}
+
+/// Bidirectional byte stream a [`Signer`] can serve a validator over
+pub trait Stream: io::Read + io::Write + Send + Sync + Sized + 'static {
+    /// Create a new handle to the same underlying stream
+    fn try_clone(&self) -> io::Result<Self>;
+
+    /// Shut down both directions of the stream, interrupting any blocked
+    /// reads or writes
+    fn shutdown(&self) -> io::Result<()>;
+}
+
+impl Stream for UnixStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        UnixStream::try_clone(self)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        UnixStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+/// Object-safe interface for shutting down a [`Stream`]
+trait Interrupt: Send {
+    /// Interrupt the stream
+    fn interrupt(&self) -> io::Result<()>;
+}
+
+impl<S: Stream> Interrupt for S {
+    fn interrupt(&self) -> io::Result<()> {
+        self.shutdown()
+    }
+}
@@ -0,0 +1,473 @@
+//! Status of each chain: the state and configured role of its validator
+//! connections, when each last sent a request and how many heights each can still sign before its
+//! `max_height` and how many of its requests are queued or were dropped, the
+//! last height/round/step signed, and the number of requests signed and
+//! refused since startup.
+//!
+//! Updated by the clients and sessions as they go, and reported as a table
+//! on `SIGUSR1` and as JSON in the `status_file` (if configured).
+
+use crate::{
+    chain,
+    client::Status,
+    config::ValidatorRole,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    privileges, Map,
+};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::NamedTempFile;
+use tendermint::consensus;
+
+/// Interval at which the status file is rewritten
+pub const STATUS_FILE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Status of each chain
+static CHAINS: Lazy<Mutex<Map<chain::Id, ChainStatus>>> = Lazy::new(|| Mutex::new(Map::new()));
+
+/// Status of a chain
+#[derive(Debug, Default)]
+struct ChainStatus {
+    /// Validator connections serving the chain, by address
+    connections: Map<String, ConnectionStatus>,
+
+    /// Height/round/step of the last message signed
+    last_signed: Option<consensus::State>,
+
+    /// Number of requests signed since startup
+    signed: u64,
+
+    /// Number of requests refused since startup
+    refused: u64,
+}
+
+/// Status of a validator connection
+#[derive(Debug)]
+struct ConnectionStatus {
+    /// State of the connection
+    status: Status,
+
+    /// Which requests are signed for the validator
+    role: ValidatorRole,
+
+    /// When the validator last sent a request
+    last_request: Option<Instant>,
+
+    /// Heights which can still be signed before the validator's `max_height`
+    max_height_remaining: Option<u64>,
+
+    /// Number of requests queued for handling
+    queue_depth: usize,
+
+    /// Number of requests dropped (because the queue was full or they
+    /// weren't signed before the validator's `request_deadline_ms`)
+    dropped: u64,
+}
+
+/// Record the state of the connection to the validator at `addr`, which
+/// serves the given chains
+pub fn connection(chain_ids: &[chain::Id], addr: &str, status: Status) {
+    update(chain_ids, |chain| {
+        chain
+            .connections
+            .entry(addr.to_owned())
+            .and_modify(|connection| connection.status = status)
+            .or_insert(ConnectionStatus {
+                status,
+                role: ValidatorRole::default(),
+                last_request: None,
+                max_height_remaining: None,
+                queue_depth: 0,
+                dropped: 0,
+            });
+    });
+}
+
+/// Record the configured `role` of the validator at `addr`
+pub fn role(chain_ids: &[chain::Id], addr: &str, role: ValidatorRole) {
+    update(chain_ids, |chain| {
+        if let Some(connection) = chain.connections.get_mut(addr) {
+            connection.role = role;
+        }
+    });
+}
+
+/// Record that the client of a validator with several addresses has moved
+/// from the one at `from` to the one at `to`
+pub fn connection_moved(chain_ids: &[chain::Id], from: &str, to: &str) {
+    update(chain_ids, |chain| {
+        if let Some(mut connection) = chain.connections.remove(from) {
+            connection.last_request = None;
+            chain.connections.insert(to.to_owned(), connection);
+        }
+    });
+}
+
+/// Record a request from the validator at `addr`, which serves the given
+/// chains
+pub fn request_received(chain_ids: &[chain::Id], addr: &str) {
+    let now = Instant::now();
+
+    update(chain_ids, |chain| {
+        if let Some(connection) = chain.connections.get_mut(addr) {
+            connection.last_request = Some(now);
+        }
+    });
+}
+
+/// Record the number of heights which can still be signed before the
+/// `max_height` of the validator at `addr` (`None` if it has none)
+pub fn max_height_remaining(chain_id: &chain::Id, addr: &str, remaining: Option<u64>) {
+    update(std::slice::from_ref(chain_id), |chain| {
+        if let Some(connection) = chain.connections.get_mut(addr) {
+            connection.max_height_remaining = remaining;
+        }
+    });
+}
+
+/// Record the number of requests from the validator at `addr` which are
+/// queued for handling
+pub fn queue_depth(chain_ids: &[chain::Id], addr: &str, depth: usize) {
+    update(chain_ids, |chain| {
+        if let Some(connection) = chain.connections.get_mut(addr) {
+            connection.queue_depth = depth;
+        }
+    });
+}
+
+/// Record a request from the validator at `addr` being dropped
+pub fn dropped(chain_ids: &[chain::Id], addr: &str) {
+    update(chain_ids, |chain| {
+        if let Some(connection) = chain.connections.get_mut(addr) {
+            connection.dropped += 1;
+        }
+    });
+}
+
+/// Record a message signed at the given height/round/step
+pub fn signed(chain_id: &chain::Id, state: &consensus::State) {
+    update(std::slice::from_ref(chain_id), |chain| {
+        chain.last_signed = Some(state.clone());
+        chain.signed += 1;
+    });
+}
+
+/// Record a refused signing request
+pub fn refused(chain_id: &chain::Id) {
+    update(std::slice::from_ref(chain_id), |chain| chain.refused += 1);
+}
+
+/// Apply the given update to the status of each of the given chains
+fn update(chain_ids: &[chain::Id], mut f: impl FnMut(&mut ChainStatus)) {
+    let mut chains = CHAINS.lock().unwrap_or_else(|e| e.into_inner());
+
+    for chain_id in chain_ids {
+        f(chains.entry(chain_id.clone()).or_default());
+    }
+}
+
+/// Status report, as written to the status file
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    /// Chains, ordered by chain ID
+    pub chains: Vec<ChainReport>,
+}
+
+/// Status of a chain in a [`Report`]
+#[derive(Clone, Debug, Serialize)]
+pub struct ChainReport {
+    /// Chain ID
+    pub chain_id: chain::Id,
+
+    /// Validator connections serving the chain, ordered by address
+    pub connections: Vec<ConnectionReport>,
+
+    /// Height/round/step of the last message signed (if any)
+    pub last_signed: Option<LastSigned>,
+
+    /// Number of requests signed since startup
+    pub signed: u64,
+
+    /// Number of requests refused since startup
+    pub refused: u64,
+}
+
+/// Status of a validator connection in a [`Report`]
+#[derive(Clone, Debug, Serialize)]
+pub struct ConnectionReport {
+    /// Address of the validator
+    pub addr: String,
+
+    /// State of the connection (e.g. `connected`)
+    pub status: String,
+
+    /// Configured `role` of the validator (e.g. `voter`)
+    pub role: ValidatorRole,
+
+    /// Seconds since the validator last sent a request (if it has)
+    pub secs_since_last_request: Option<u64>,
+
+    /// Heights which can still be signed before the validator's `max_height`
+    /// (if it has one, once a message has been signed)
+    pub max_height_remaining: Option<u64>,
+
+    /// Number of requests queued for handling
+    pub queue_depth: usize,
+
+    /// Number of requests dropped since startup (because the queue was full
+    /// or they weren't signed in time)
+    pub dropped: u64,
+}
+
+/// Height/round/step of the last message signed in a [`ChainReport`]
+#[derive(Clone, Debug, Serialize)]
+pub struct LastSigned {
+    /// Block height
+    pub height: u64,
+
+    /// Consensus round
+    pub round: u32,
+
+    /// Consensus step
+    pub step: i8,
+}
+
+/// Get a report of the current status of each chain
+pub fn report() -> Report {
+    let chains = CHAINS.lock().unwrap_or_else(|e| e.into_inner());
+
+    let chains = chains
+        .iter()
+        .map(|(chain_id, chain)| ChainReport {
+            chain_id: chain_id.clone(),
+            connections: chain
+                .connections
+                .iter()
+                .map(|(addr, connection)| ConnectionReport {
+                    addr: addr.clone(),
+                    status: connection.status.to_string(),
+                    role: connection.role,
+                    secs_since_last_request: connection
+                        .last_request
+                        .map(|instant| instant.elapsed().as_secs()),
+                    max_height_remaining: connection.max_height_remaining,
+                    queue_depth: connection.queue_depth,
+                    dropped: connection.dropped,
+                })
+                .collect(),
+            last_signed: chain.last_signed.as_ref().map(|state| LastSigned {
+                height: state.height.value(),
+                round: state.round.value(),
+                step: state.step,
+            }),
+            signed: chain.signed,
+            refused: chain.refused,
+        })
+        .collect();
+
+    Report { chains }
+}
+
+impl Report {
+    /// Render this report as a table, with a row for each connection
+    pub fn to_table(&self) -> Vec<String> {
+        let mut rows = vec![[
+            "CHAIN".to_owned(),
+            "VALIDATOR".to_owned(),
+            "STATUS".to_owned(),
+            "ROLE".to_owned(),
+            "LAST REQUEST".to_owned(),
+            "HEIGHTS LEFT".to_owned(),
+            "DROPPED".to_owned(),
+            "LAST SIGNED (H/R/S)".to_owned(),
+            "SIGNED".to_owned(),
+            "REFUSED".to_owned(),
+        ]];
+
+        for chain in &self.chains {
+            let last_signed = chain.last_signed.as_ref().map_or_else(
+                || "-".to_owned(),
+                |last| format!("{}/{}/{}", last.height, last.round, last.step),
+            );
+
+            let connections = chain.connections.iter().map(|connection| {
+                (
+                    connection.addr.clone(),
+                    connection.status.clone(),
+                    connection.role.to_string(),
+                    connection
+                        .secs_since_last_request
+                        .map_or_else(|| "never".to_owned(), |secs| format!("{}s ago", secs)),
+                    connection
+                        .max_height_remaining
+                        .map_or_else(|| "-".to_owned(), |remaining| remaining.to_string()),
+                    connection.dropped.to_string(),
+                )
+            });
+
+            let no_connection = (
+                "-".to_owned(),
+                "-".to_owned(),
+                "-".to_owned(),
+                "-".to_owned(),
+                "-".to_owned(),
+                "-".to_owned(),
+            );
+
+            for (addr, status, role, last_request, heights_left, dropped) in
+                connections.chain(Some(no_connection).filter(|_| chain.connections.is_empty()))
+            {
+                rows.push([
+                    chain.chain_id.to_string(),
+                    addr,
+                    status,
+                    role,
+                    last_request,
+                    heights_left,
+                    dropped,
+                    last_signed.clone(),
+                    chain.signed.to_string(),
+                    chain.refused.to_string(),
+                ]);
+            }
+        }
+
+        let mut widths = [0; 10];
+
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        rows.iter()
+            .map(|row| {
+                row.iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+                    .trim_end()
+                    .to_owned()
+            })
+            .collect()
+    }
+}
+
+/// Log the status of each chain as a table (e.g. on `SIGUSR1`)
+pub fn log_summary() {
+    let report = report();
+
+    if report.chains.is_empty() {
+        info!("status: no chains yet");
+        return;
+    }
+
+    for line in report.to_table() {
+        info!("status: {}", line);
+    }
+}
+
+/// Spawn a thread which rewrites the status file at the given path every
+/// [`STATUS_FILE_INTERVAL`]
+pub fn spawn_writer(path: &Path) -> Result<(), Error> {
+    // Fail at startup, rather than in the background, if it can't be written
+    write_file(path).map_err(|e| {
+        format_err!(
+            IoError,
+            "couldn't write status file {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+
+    let path = PathBuf::from(path);
+
+    thread::Builder::new()
+        .name("status".to_owned())
+        .spawn(move || loop {
+            thread::sleep(STATUS_FILE_INTERVAL);
+
+            if let Err(e) = write_file(&path) {
+                warn!("couldn't write status file {}: {}", path.display(), e);
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Replace the status file at the given path with the current status, so
+/// readers never see a partially written file
+fn write_file(path: &Path) -> Result<(), Error> {
+    let path = &*privileges::resolve(path);
+    let dir = match path.parent() {
+        Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+        Some(dir) => dir,
+        None => fail!(ConfigError, "status file cannot be root directory"),
+    };
+
+    let mut file = NamedTempFile::new_in(dir)?;
+    serde_json::to_writer_pretty(&mut file, &report())?;
+    file.write_all(b"\n")?;
+    file.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tendermint::block;
+
+    #[test]
+    fn report_and_table() {
+        let chain_id = chain::Id::try_from("status-test-chain").unwrap();
+        let chain_ids = [chain_id.clone()];
+
+        connection(&chain_ids, "tcp://127.0.0.1:26658", Status::Connected);
+        role(&chain_ids, "tcp://127.0.0.1:26658", ValidatorRole::Voter);
+        request_received(&chain_ids, "tcp://127.0.0.1:26658");
+        queue_depth(&chain_ids, "tcp://127.0.0.1:26658", 1);
+        dropped(&chain_ids, "tcp://127.0.0.1:26658");
+        max_height_remaining(&chain_id, "tcp://127.0.0.1:26658", Some(1234));
+        signed(
+            &chain_id,
+            &consensus::State {
+                height: block::Height::from(42u32),
+                round: block::Round::from(1u16),
+                step: 2,
+                block_id: None,
+            },
+        );
+        refused(&chain_id);
+
+        let report = report();
+        let chain = report
+            .chains
+            .iter()
+            .find(|chain| chain.chain_id == chain_id)
+            .unwrap();
+
+        assert_eq!((chain.signed, chain.refused), (1, 1));
+        assert_eq!(chain.connections[0].status, "connected");
+        assert_eq!(chain.connections[0].role, ValidatorRole::Voter);
+        assert_eq!(chain.connections[0].secs_since_last_request, Some(0));
+        assert_eq!(chain.connections[0].max_height_remaining, Some(1234));
+        assert_eq!(chain.connections[0].queue_depth, 1);
+        assert_eq!(chain.connections[0].dropped, 1);
+
+        let table = report.to_table();
+        assert!(table[0].starts_with("CHAIN"));
+        assert!(table.iter().any(|row| row.contains("status-test-chain")
+            && row.contains("voter")
+            && row.contains("1234")
+            && row.contains("42/1/2")));
+    }
+}
@@ -0,0 +1,381 @@
+//! systemd service notifications (`sd_notify`): when running as a
+//! `Type=notify` service (i.e. `NOTIFY_SOCKET` is set), readiness is
+//! reported once keys and consensus state are loaded and the validator
+//! clients are started, along with a `STATUS=` summary of each chain's
+//! connections.
+//!
+//! If the service has `WatchdogSec` set, the watchdog is only pinged while
+//! the validator sessions are making progress: each session has a
+//! [`Heartbeat`] which is raised while it handles a request, and a request
+//! which hasn't finished within a watchdog interval (i.e. a wedged session)
+//! withholds the ping, so systemd restarts tmkms if it stays wedged.
+//!
+//! Notifications require the `systemd` cargo feature.
+
+use crate::prelude::*;
+use once_cell::sync::Lazy;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex, Weak,
+};
+
+#[cfg(feature = "systemd")]
+use crate::{
+    error::{Error, ErrorKind::*},
+    status,
+};
+#[cfg(feature = "systemd")]
+use once_cell::sync::OnceCell;
+#[cfg(feature = "systemd")]
+use std::{env, os::unix::net::UnixDatagram, thread, time::Duration};
+
+/// Environment variable with the address of systemd's notification socket
+pub const NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+
+/// Longest interval between watchdog checks (and `STATUS=` updates)
+#[cfg(feature = "systemd")]
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Heartbeats of the validator sessions
+static HEARTBEATS: Lazy<Mutex<Vec<Weak<Heartbeat>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Socket connected to systemd's notification socket (if `NOTIFY_SOCKET` is
+/// set)
+#[cfg(feature = "systemd")]
+static NOTIFIER: OnceCell<UnixDatagram> = OnceCell::new();
+
+/// Heartbeat of a validator client, which the watchdog checks is making
+/// progress handling requests
+#[derive(Debug)]
+pub struct Heartbeat {
+    /// Name of the client (e.g. `cosmoshub-4@tcp://...`)
+    name: String,
+
+    /// Number of requests being handled
+    busy: AtomicUsize,
+
+    /// Raised by the watchdog, and cleared whenever a request is handled
+    pending: AtomicBool,
+}
+
+/// Request being handled, which lowers the heartbeat flag when done
+#[derive(Debug)]
+pub struct Busy(Arc<Heartbeat>);
+
+impl Heartbeat {
+    /// Create a heartbeat for the given client, registering it with the
+    /// watchdog
+    pub fn register(name: impl Into<String>) -> Arc<Self> {
+        let heartbeat = Arc::new(Self {
+            name: name.into(),
+            busy: AtomicUsize::new(0),
+            pending: AtomicBool::new(false),
+        });
+
+        let mut heartbeats = HEARTBEATS.lock().unwrap_or_else(|e| e.into_inner());
+        heartbeats.retain(|heartbeat| heartbeat.strong_count() > 0);
+        heartbeats.push(Arc::downgrade(&heartbeat));
+        heartbeat
+    }
+
+    /// Mark a request as being handled until the returned guard is dropped
+    pub fn busy(self: &Arc<Self>) -> Busy {
+        self.busy.fetch_add(1, Ordering::SeqCst);
+        Busy(self.clone())
+    }
+
+    /// Check the client is making progress: it's either waiting (e.g. for a
+    /// request, or to reconnect), or has finished a request since the last
+    /// check. Raises the heartbeat flag for the next check.
+    fn check(&self) -> bool {
+        if self.busy.load(Ordering::SeqCst) == 0 {
+            self.pending.store(false, Ordering::SeqCst);
+            return true;
+        }
+
+        !self.pending.swap(true, Ordering::SeqCst)
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        self.0.busy.fetch_sub(1, Ordering::SeqCst);
+        self.0.pending.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Get the names of the clients which haven't made progress since the last
+/// check
+#[cfg_attr(not(feature = "systemd"), allow(dead_code))]
+fn wedged() -> Vec<String> {
+    HEARTBEATS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .filter_map(Weak::upgrade)
+        .filter(|heartbeat| !heartbeat.check())
+        .map(|heartbeat| heartbeat.name.clone())
+        .collect()
+}
+
+/// Connect to systemd's notification socket, if `NOTIFY_SOCKET` is set.
+/// Must be called before privileges are dropped, as the socket may not be
+/// reachable inside `chroot_dir`.
+#[cfg(feature = "systemd")]
+pub fn connect() -> Result<(), Error> {
+    let addr = match env::var_os(NOTIFY_SOCKET) {
+        Some(addr) if !addr.is_empty() => addr,
+        _ => return Ok(()),
+    };
+
+    let socket = connect_socket(&addr.to_string_lossy()).map_err(|e| {
+        format_err!(
+            IoError,
+            "couldn't connect to systemd notification socket {}: {}",
+            addr.to_string_lossy(),
+            e
+        )
+    })?;
+
+    NOTIFIER
+        .set(socket)
+        .map_err(|_| format_err!(ConfigError, "already connected to systemd"))?;
+
+    Ok(())
+}
+
+/// Connect to systemd's notification socket (placeholder when the `systemd`
+/// feature is disabled)
+#[cfg(not(feature = "systemd"))]
+pub fn connect() -> Result<(), crate::error::Error> {
+    if std::env::var_os(NOTIFY_SOCKET).is_some() {
+        warn!(
+            "{} is set, but tmkms was built without the `systemd` feature: \
+             systemd won't be notified when it's ready",
+            NOTIFY_SOCKET
+        );
+    }
+
+    Ok(())
+}
+
+/// Connect a datagram socket to the notification socket at the given path
+#[cfg(feature = "systemd")]
+fn connect_socket(addr: &str) -> std::io::Result<UnixDatagram> {
+    // Abstract socket addresses can't be connected to with the standard
+    // library on tmkms's minimum supported Rust version
+    if addr.starts_with('@') {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "abstract socket addresses aren't supported",
+        ));
+    }
+
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(addr)?;
+    Ok(socket)
+}
+
+/// Notify systemd that tmkms is ready, and start sending it status updates
+/// and (if the service has a watchdog) watchdog pings
+#[cfg(feature = "systemd")]
+pub fn ready() -> Result<(), Error> {
+    if NOTIFIER.get().is_none() {
+        return Ok(());
+    }
+
+    notify(&format!(
+        "READY=1\nSTATUS={}",
+        status_line(&status::report())
+    ))?;
+
+    let watchdog = watchdog_interval();
+    let interval = watchdog.map_or(MAX_CHECK_INTERVAL, |watchdog| {
+        (watchdog / 2).min(MAX_CHECK_INTERVAL)
+    });
+
+    if let Some(watchdog) = watchdog {
+        info!(
+            "systemd watchdog enabled (timeout: {:?}): pinging while sessions make progress",
+            watchdog
+        );
+    }
+
+    thread::Builder::new()
+        .name("systemd".to_owned())
+        .spawn(move || supervise(interval, watchdog.is_some()))?;
+
+    Ok(())
+}
+
+/// Notify systemd that tmkms is ready (placeholder when the `systemd`
+/// feature is disabled)
+#[cfg(not(feature = "systemd"))]
+pub fn ready() -> Result<(), crate::error::Error> {
+    Ok(())
+}
+
+/// Check the sessions are making progress every `interval`, updating the
+/// service's status and pinging the watchdog (if enabled) while they are
+#[cfg(feature = "systemd")]
+fn supervise(interval: Duration, watchdog: bool) {
+    let mut was_wedged = false;
+
+    loop {
+        thread::sleep(interval);
+
+        let wedged = wedged();
+        let mut msg = format!("STATUS={}", status_line(&status::report()));
+
+        if wedged.is_empty() {
+            if was_wedged {
+                info!("sessions making progress again: resuming systemd watchdog pings");
+            }
+
+            if watchdog {
+                msg.insert_str(0, "WATCHDOG=1\n");
+            }
+        } else {
+            if !was_wedged {
+                error!(
+                    "session(s) not making progress: {} (withholding systemd watchdog pings)",
+                    wedged.join(", ")
+                );
+            }
+
+            msg = format!("STATUS=wedged: {}", wedged.join(", "));
+        }
+
+        was_wedged = !wedged.is_empty();
+
+        if let Err(e) = notify(&msg) {
+            warn!("{}", e);
+        }
+    }
+}
+
+/// Get the service's watchdog timeout, if it has one (and it's meant for
+/// this process)
+#[cfg(feature = "systemd")]
+fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.trim().parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+
+    env::var("WATCHDOG_USEC")
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|&usec| usec > 0)
+        .map(Duration::from_micros)
+}
+
+/// Send the given newline-separated assignments to systemd
+#[cfg(feature = "systemd")]
+fn notify(msg: &str) -> Result<(), Error> {
+    if let Some(socket) = NOTIFIER.get() {
+        socket
+            .send(msg.as_bytes())
+            .map_err(|e| format_err!(IoError, "couldn't send systemd notification: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Summarize the connection state of each chain for `STATUS=`, e.g.
+/// `cosmoshub-4: connected; osmosis-1: 1/2 connected`
+#[cfg(feature = "systemd")]
+fn status_line(report: &status::Report) -> String {
+    if report.chains.is_empty() {
+        return "no chains".to_owned();
+    }
+
+    report
+        .chains
+        .iter()
+        .map(|chain| {
+            let connections = &chain.connections;
+            let state = match connections.first() {
+                None => "no validators".to_owned(),
+                Some(first) if connections.iter().all(|c| c.status == first.status) => {
+                    first.status.clone()
+                }
+                Some(_) => format!(
+                    "{}/{} connected",
+                    connections
+                        .iter()
+                        .filter(|c| c.status == "connected")
+                        .count(),
+                    connections.len()
+                ),
+            };
+
+            format!("{}: {}", chain.chain_id, state)
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeat_progress() {
+        let heartbeat = Heartbeat::register("heartbeat-test@tcp://127.0.0.1:26658");
+        let name = |wedged: Vec<String>| wedged.contains(&heartbeat.name);
+
+        // Waiting for requests
+        assert!(heartbeat.check());
+        assert!(heartbeat.check());
+
+        // Requests which finish between checks are progress
+        let busy = heartbeat.busy();
+        assert!(heartbeat.check());
+        drop(busy);
+        let _busy = heartbeat.busy();
+        assert!(heartbeat.check());
+
+        // A request which doesn't is wedged
+        assert!(name(wedged()));
+    }
+
+    #[cfg(feature = "systemd")]
+    #[test]
+    fn summarize_status() {
+        use crate::{chain, client::Status};
+
+        let chain_ids = [chain::Id::try_from("systemd-test-chain").unwrap()];
+        status::connection(&chain_ids, "tcp://127.0.0.1:1", Status::Connected);
+        status::connection(&chain_ids, "tcp://127.0.0.1:2", Status::Reconnecting);
+
+        let report = status::Report {
+            chains: status::report()
+                .chains
+                .into_iter()
+                .filter(|chain| chain.chain_id == chain_ids[0])
+                .collect(),
+        };
+
+        assert_eq!(status_line(&report), "systemd-test-chain: 1/2 connected");
+        assert_eq!(status_line(&status::Report { chains: vec![] }), "no chains");
+    }
+
+    #[cfg(feature = "systemd")]
+    #[test]
+    fn notify_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let systemd = UnixDatagram::bind(&path).unwrap();
+
+        let socket = connect_socket(path.to_str().unwrap()).unwrap();
+        socket.send(b"READY=1").unwrap();
+
+        let mut buf = [0u8; 16];
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+    }
+}
@@ -37,7 +37,7 @@ pub const RPC_POLL_INTERVAL: time::Duration = time::Duration::from_millis(500);
 /// Transaction signer
 pub struct TxSigner {
     /// Chain ID of the Tendermint network this validator is part of
-    chain_id: tendermint::chain::Id,
+    chain_id: chain::Id,
 
     /// Transaction builder
     tx_builder: amino::Builder,
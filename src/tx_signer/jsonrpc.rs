@@ -2,6 +2,7 @@
 //! protocol.
 
 use super::tx_request::TxSigningRequest;
+use crate::chain;
 use crate::{
     error::{Error, ErrorKind},
     prelude::*,
@@ -12,7 +13,6 @@ use hyper::{
 };
 use hyper_rustls::HttpsConnectorBuilder;
 use serde::{Deserialize, Serialize};
-use tendermint::chain;
 use tendermint_rpc::endpoint::{broadcast::tx_commit, status};
 
 /// Transaction builder JSONRPC client.
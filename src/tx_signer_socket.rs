@@ -0,0 +1,329 @@
+//! Transaction signing socket: a Unix domain socket accepting Cosmos SDK
+//! `SIGN_MODE_DIRECT` sign docs (e.g. for `MsgWithdrawDelegatorReward` or
+//! `MsgUnjail` transactions) to be signed with a chain's account key, so the
+//! account's mnemonic never has to be on the validator's host.
+//!
+//! Consensus signing doesn't use this socket, and a chain's account key must
+//! be distinct from its consensus key.
+//!
+//! As with the [control socket](crate::control), each connection carries a
+//! single JSON-encoded [`Request`], terminated by the client shutting down
+//! its end for writing, which is answered with a single JSON-encoded
+//! [`Response`].
+
+use crate::{
+    chain,
+    config::tx_signer_socket::TxSignerSocketConfig,
+    control,
+    error::{Error, ErrorKind::*},
+    keyring::ecdsa::Signature,
+    prelude::*,
+};
+use cosmrs::proto::cosmos::tx::v1beta1::{SignDoc, TxBody};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    path::Path,
+    thread,
+    time::Duration,
+};
+use subtle_encoding::base64;
+use tendermint::{account, TendermintKey};
+
+/// Maximum size of a request
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+/// Timeout for reading a request or writing a response
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Transaction to sign
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Request {
+    /// Chain the transaction is for
+    pub chain_id: chain::Id,
+
+    /// Protobuf-encoded `SignDoc` (Base64)
+    pub sign_doc: String,
+}
+
+/// Result of a [`Request`]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    /// Transaction signed
+    Ok {
+        /// Compressed secp256k1 public key of the account key (Base64)
+        public_key: String,
+
+        /// 64-byte secp256k1 signature of the `SignDoc` (Base64)
+        signature: String,
+    },
+
+    /// Transaction refused
+    Error {
+        /// Reason the transaction was refused
+        message: String,
+    },
+}
+
+/// Listen for transactions to sign on the configured socket on a new
+/// thread. Each of the configured chains must have an account key.
+pub fn spawn_server(config: &TxSignerSocketConfig) -> Result<(), Error> {
+    {
+        let registry = chain::REGISTRY.get();
+
+        for chain_id in &config.chain_ids {
+            let chain = registry.get_chain(chain_id).ok_or_else(|| {
+                format_err!(
+                    ConfigError,
+                    "[tx_signer_socket] unregistered chain: {}",
+                    chain_id
+                )
+            })?;
+
+            let public_key = chain.keyring.account_pubkey().map_err(|e| {
+                format_err!(ConfigError, "[tx_signer_socket] chain {}: {}", chain_id, e)
+            })?;
+
+            info!(
+                "[{}] signing transactions with account key {}",
+                chain_id,
+                chain
+                    .keyring
+                    .format()
+                    .serialize(TendermintKey::AccountKey(public_key))
+            );
+        }
+    }
+
+    let listener = control::bind(&config.path)?;
+    let config = config.clone();
+
+    info!("listening for transactions on {}", config.path.display());
+
+    thread::Builder::new()
+        .name("tx-signer-socket".to_owned())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream
+                    .map_err(Error::from)
+                    .and_then(|stream| handle_connection(&config, stream));
+
+                if let Err(e) = result {
+                    warn!("error serving transaction signing request: {}", e);
+                }
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Send a transaction to the signing socket at the given path, returning
+/// the KMS's response
+pub fn send(path: &Path, request: &Request) -> Result<Response, Error> {
+    let mut stream = UnixStream::connect(path).map_err(|e| {
+        format_err!(
+            IoError,
+            "couldn't connect to transaction signing socket {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    stream.write_all(&serde_json::to_vec(request)?)?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    Ok(serde_json::from_slice(&response)?)
+}
+
+/// Handle a single request to the signing socket
+fn handle_connection(config: &TxSignerSocketConfig, mut stream: UnixStream) -> Result<(), Error> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut request = Vec::new();
+    (&stream).take(MAX_REQUEST_SIZE).read_to_end(&mut request)?;
+
+    let response = match serde_json::from_slice(&request) {
+        Ok(request) => handle_request(config, request),
+        Err(e) => Response::Error {
+            message: format!("malformed request: {}", e),
+        },
+    };
+
+    stream.write_all(&serde_json::to_vec(&response)?)?;
+    Ok(())
+}
+
+/// Sign the requested transaction if it's permitted
+fn handle_request(config: &TxSignerSocketConfig, request: Request) -> Response {
+    match sign(config, &request) {
+        Ok((public_key, signature)) => Response::Ok {
+            public_key: encode(&public_key.to_bytes()),
+            signature: encode(signature.as_ref()),
+        },
+        Err(e) => {
+            warn!("[{}] refused to sign transaction: {}", request.chain_id, e);
+            Response::Error {
+                message: e.to_string(),
+            }
+        }
+    }
+}
+
+/// Sign the requested `SignDoc` with the chain's account key
+fn sign(
+    config: &TxSignerSocketConfig,
+    request: &Request,
+) -> Result<(tendermint::PublicKey, Signature), Error> {
+    let sign_doc = base64::decode(&request.sign_doc)
+        .map_err(|e| format_err!(ParseError, "malformed sign doc: {}", e))?;
+
+    let msg_types = authorize(config, &request.chain_id, &sign_doc)?;
+
+    let registry = chain::REGISTRY.get();
+    let chain = registry
+        .get_chain(&request.chain_id)
+        .ok_or_else(|| format_err!(ChainIdError, "unregistered chain: {}", request.chain_id))?;
+
+    let public_key = chain.keyring.account_pubkey()?;
+    let signature = chain
+        .keyring
+        .sign_ecdsa(account::Id::from(public_key), &sign_doc)?;
+
+    info!(
+        "[{}] signed TX for {} ({} msgs total; types: {})",
+        request.chain_id,
+        chain
+            .keyring
+            .format()
+            .serialize(TendermintKey::AccountKey(public_key)),
+        msg_types.len(),
+        msg_types.join(", ")
+    );
+
+    Ok((public_key, signature))
+}
+
+/// Check the given `SignDoc` is for the given chain and only contains
+/// permitted messages, returning the type URLs of its messages
+fn authorize(
+    config: &TxSignerSocketConfig,
+    chain_id: &chain::Id,
+    sign_doc: &[u8],
+) -> Result<Vec<String>, Error> {
+    if !config.chain_ids.contains(chain_id) {
+        fail!(
+            AccessError,
+            "signing transactions for chain {} isn't permitted",
+            chain_id
+        );
+    }
+
+    let sign_doc = SignDoc::decode(sign_doc)?;
+
+    if sign_doc.chain_id != chain_id.as_str() {
+        fail!(
+            ChainIdError,
+            "sign doc is for chain `{}`, not `{}`",
+            sign_doc.chain_id,
+            chain_id
+        );
+    }
+
+    let body = TxBody::decode(sign_doc.body_bytes.as_slice())?;
+
+    if body.messages.is_empty() {
+        fail!(AccessError, "transaction contains no messages");
+    }
+
+    if !body.extension_options.is_empty() {
+        fail!(AccessError, "transaction contains extension options");
+    }
+
+    for msg in &body.messages {
+        if !config.msg_types.contains(&msg.type_url) {
+            fail!(
+                AccessError,
+                "unauthorized request to sign `{}` message",
+                msg.type_url
+            );
+        }
+    }
+
+    Ok(body.messages.into_iter().map(|msg| msg.type_url).collect())
+}
+
+/// Encode the given bytes as Base64
+fn encode(bytes: &[u8]) -> String {
+    String::from_utf8(base64::encode(bytes)).expect("base64 should always be UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmrs::Any;
+
+    const UNJAIL: &str = "/cosmos.slashing.v1beta1.MsgUnjail";
+
+    fn config() -> TxSignerSocketConfig {
+        TxSignerSocketConfig {
+            path: "tx-signer.sock".into(),
+            chain_ids: vec!["cosmoshub-4".parse().unwrap()],
+            msg_types: vec![UNJAIL.to_owned()],
+        }
+    }
+
+    /// Encode a `SignDoc` for a transaction with messages of the given types
+    fn sign_doc(chain_id: &str, msg_types: &[&str]) -> Vec<u8> {
+        let body = TxBody {
+            messages: msg_types
+                .iter()
+                .map(|type_url| Any {
+                    type_url: type_url.to_string(),
+                    value: vec![],
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        SignDoc {
+            body_bytes: body.encode_to_vec(),
+            auth_info_bytes: vec![],
+            chain_id: chain_id.to_owned(),
+            account_number: 1,
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn authorize_permitted_messages() {
+        let chain_id = "cosmoshub-4".parse().unwrap();
+        let msg_types = authorize(&config(), &chain_id, &sign_doc("cosmoshub-4", &[UNJAIL]));
+        assert_eq!(msg_types.unwrap(), &[UNJAIL]);
+
+        let send = "/cosmos.bank.v1beta1.MsgSend";
+        let doc = sign_doc("cosmoshub-4", &[UNJAIL, send]);
+        assert!(authorize(&config(), &chain_id, &doc).is_err());
+        assert!(authorize(&config(), &chain_id, &sign_doc("cosmoshub-4", &[])).is_err());
+    }
+
+    #[test]
+    fn authorize_chain_id() {
+        let doc = sign_doc("osmosis-1", &[UNJAIL]);
+        let other_chain = "osmosis-1".parse().unwrap();
+        assert!(authorize(&config(), &other_chain, &doc).is_err());
+
+        // The sign doc's chain ID must match the requested one
+        let chain_id = "cosmoshub-4".parse().unwrap();
+        assert!(authorize(&config(), &chain_id, &doc).is_err());
+    }
+}
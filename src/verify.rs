@@ -0,0 +1,356 @@
+//! Offline verification of consensus signatures, e.g. those recorded in an
+//! [`audit`] log.
+//!
+//! Signed audit log entries record the bytes which were signed and the
+//! public key which signed them along with the signature, so a log can be
+//! checked independently of the KMS which wrote it. Ed25519 signatures are
+//! verified in batches (which is several times faster than verifying them
+//! one at a time), and only a batch which fails is verified signature by
+//! signature to find the culprits.
+
+use crate::{
+    audit::{self, Decision},
+    error::{Error, ErrorKind::*},
+    keyring::{ecdsa, ed25519},
+    prelude::*,
+};
+use signature::Verifier;
+use std::{
+    convert::TryFrom,
+    fmt,
+    io::BufRead,
+    time::{Duration, Instant},
+};
+use subtle_encoding::hex;
+use tendermint::PublicKey;
+
+/// Number of signatures verified per batch
+pub const BATCH_SIZE: usize = 256;
+
+/// Message signed with a consensus key
+#[derive(Clone, Debug)]
+pub struct SignedMessage {
+    /// Bytes which were signed
+    pub sign_bytes: Vec<u8>,
+
+    /// Signature of `sign_bytes`
+    pub signature: Vec<u8>,
+
+    /// Public key which signed them
+    pub public_key: PublicKey,
+}
+
+impl SignedMessage {
+    /// Verify the signature
+    pub fn verify(&self) -> Result<(), Error> {
+        match self.public_key {
+            PublicKey::Ed25519(public_key) => {
+                let signature = ed25519::Signature::try_from(self.signature.as_slice())
+                    .map_err(|_| format_err!(VerificationError, "malformed Ed25519 signature"))?;
+
+                public_key
+                    .verify(&self.sign_bytes, &signature)
+                    .map_err(|_| format_err!(VerificationError, "invalid Ed25519 signature"))?;
+            }
+            PublicKey::Secp256k1(public_key) => {
+                let signature = ecdsa::Signature::try_from(self.signature.as_slice())
+                    .map_err(|_| format_err!(VerificationError, "malformed ECDSA signature"))?;
+
+                public_key
+                    .verify(&self.sign_bytes, &signature)
+                    .map_err(|_| format_err!(VerificationError, "invalid ECDSA signature"))?;
+            }
+            _ => fail!(VerificationError, "unsupported public key type"),
+        }
+
+        Ok(())
+    }
+}
+
+/// Verify the given messages' signatures, returning the index of each
+/// message whose signature is invalid along with why
+pub fn verify_batch(messages: &[SignedMessage]) -> Vec<(usize, Error)> {
+    let mut failures = vec![];
+    let mut batch = Batch::default();
+
+    for (i, message) in messages.iter().enumerate() {
+        // Only well-formed Ed25519 signatures can be batched
+        let signature = ed25519::Signature::try_from(message.signature.as_slice());
+
+        match (message.public_key, signature) {
+            (PublicKey::Ed25519(public_key), Ok(signature)) => {
+                batch.push(i, &message.sign_bytes, signature, public_key)
+            }
+            _ => {
+                if let Err(e) = message.verify() {
+                    failures.push((i, e));
+                }
+            }
+        }
+    }
+
+    if !batch.verify() {
+        failures.extend(
+            batch
+                .indexes
+                .iter()
+                .filter_map(|&i| messages[i].verify().err().map(|e| (i, e))),
+        );
+
+        failures.sort_by_key(|(i, _)| *i);
+    }
+
+    failures
+}
+
+/// Batch of Ed25519 signatures
+#[derive(Default)]
+struct Batch<'a> {
+    indexes: Vec<usize>,
+    messages: Vec<&'a [u8]>,
+    signatures: Vec<ed25519::Signature>,
+    public_keys: Vec<ed25519::PublicKey>,
+}
+
+impl<'a> Batch<'a> {
+    fn push(
+        &mut self,
+        index: usize,
+        message: &'a [u8],
+        signature: ed25519::Signature,
+        public_key: ed25519::PublicKey,
+    ) {
+        self.indexes.push(index);
+        self.messages.push(message);
+        self.signatures.push(signature);
+        self.public_keys.push(public_key);
+    }
+
+    /// Are all of the signatures valid?
+    fn verify(&self) -> bool {
+        self.indexes.is_empty()
+            || ed25519_dalek::verify_batch(&self.messages, &self.signatures, &self.public_keys)
+                .is_ok()
+    }
+}
+
+/// Audit log record whose signature couldn't be verified
+#[derive(Debug)]
+pub struct Failure {
+    /// Line of the record in the log (starting from 1)
+    pub line: usize,
+
+    /// Why it couldn't be verified
+    pub reason: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+/// Result of verifying an audit log
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Number of signatures verified
+    pub verified: usize,
+
+    /// Number of records without a signature to verify: refused requests,
+    /// and signed ones logged without their sign bytes or public key (i.e.
+    /// by older versions of the KMS)
+    pub skipped: usize,
+
+    /// Records whose signatures are invalid (or which are malformed)
+    pub failures: Vec<Failure>,
+
+    /// Time spent verifying the log
+    pub elapsed: Duration,
+}
+
+impl Report {
+    /// Get the number of signatures verified per second
+    pub fn throughput(&self) -> f64 {
+        let total = self.verified + self.failures.len();
+        total as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Verify the signature of each signed record read from an audit log,
+/// streaming it in batches of [`BATCH_SIZE`]
+pub fn verify_audit_log(reader: impl BufRead) -> Result<Report, Error> {
+    let started_at = Instant::now();
+    let mut report = Report::default();
+    let mut lines = vec![];
+    let mut batch = vec![];
+
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match parse_record(&line) {
+            Ok(Some(message)) => {
+                lines.push(n + 1);
+                batch.push(message);
+            }
+            Ok(None) => report.skipped += 1,
+            Err(e) => report.failures.push(Failure {
+                line: n + 1,
+                reason: e.to_string(),
+            }),
+        }
+
+        if batch.len() == BATCH_SIZE {
+            verify_lines(&mut report, &lines, &batch);
+            lines.clear();
+            batch.clear();
+        }
+    }
+
+    verify_lines(&mut report, &lines, &batch);
+    report.failures.sort_by_key(|failure| failure.line);
+    report.elapsed = started_at.elapsed();
+    Ok(report)
+}
+
+/// Verify a batch of records from the given lines
+fn verify_lines(report: &mut Report, lines: &[usize], batch: &[SignedMessage]) {
+    let failures = verify_batch(batch);
+    report.verified += batch.len() - failures.len();
+
+    report
+        .failures
+        .extend(failures.into_iter().map(|(i, e)| Failure {
+            line: lines[i],
+            reason: e.to_string(),
+        }));
+}
+
+/// Parse an audit log record, returning the message it says was signed (if
+/// it has one to verify)
+fn parse_record(line: &str) -> Result<Option<SignedMessage>, Error> {
+    let entry: audit::Entry = serde_json::from_str(line)
+        .map_err(|e| format_err!(ParseError, "malformed record: {}", e))?;
+
+    if entry.decision != Decision::Signed {
+        return Ok(None);
+    }
+
+    let (signature, sign_bytes, public_key) =
+        match (&entry.signature, &entry.sign_bytes, &entry.public_key) {
+            (Some(signature), Some(sign_bytes), Some(public_key)) => {
+                (signature, sign_bytes, public_key)
+            }
+            _ => return Ok(None),
+        };
+
+    Ok(Some(SignedMessage {
+        sign_bytes: decode_hex("sign_bytes", sign_bytes)?,
+        signature: decode_hex("signature", signature)?,
+        public_key: parse_public_key(public_key)?,
+    }))
+}
+
+/// Parse a public key in any of the supported key formats (e.g. Bech32 with
+/// any prefix)
+pub fn parse_public_key(encoded: &str) -> Result<PublicKey, Error> {
+    crate::keyring::Format::detect(encoded).parse(encoded)
+}
+
+/// Decode a hex field (in either case)
+fn decode_hex(field: &str, digits: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(digits.trim().to_ascii_lowercase())
+        .map_err(|e| format_err!(ParseError, "malformed {}: {}", field, e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Signer as _;
+
+    fn keypair(seed: u8) -> ed25519::Keypair {
+        let secret = ed25519::SecretKey::from_bytes(&[seed; 32]).unwrap();
+        let public = ed25519::PublicKey::from(&secret);
+        ed25519::Keypair { secret, public }
+    }
+
+    fn signed(seed: u8, msg: &[u8]) -> SignedMessage {
+        let keypair = keypair(seed);
+
+        SignedMessage {
+            sign_bytes: msg.to_vec(),
+            signature: keypair.sign(msg).to_bytes().to_vec(),
+            public_key: PublicKey::Ed25519(keypair.public),
+        }
+    }
+
+    #[test]
+    fn batch_finds_invalid_signatures() {
+        let mut messages: Vec<_> = (0..10).map(|i| signed(i, &[i; 100])).collect();
+        assert!(verify_batch(&messages).is_empty());
+
+        messages[3].sign_bytes[0] ^= 1;
+        messages[7].signature.truncate(10);
+
+        let failures = verify_batch(&messages);
+        assert_eq!(
+            failures
+                .iter()
+                .map(|(i, e)| (*i, e.to_string()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    3,
+                    "verification failed: invalid Ed25519 signature".to_owned()
+                ),
+                (
+                    7,
+                    "verification failed: malformed Ed25519 signature".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn audit_log() {
+        let record = |message: &SignedMessage, decision: &str| {
+            format!(
+                r#"{{"timestamp":"2026-01-01T00:00:00Z","chain_id":"test-chain","msg_type":"prevote","height":1,"round":0,"step":6,"block_id":null,"decision":"{}","reason":null,"signature":"{}","sign_bytes":"{}","public_key":"{}","hash":"00"}}"#,
+                decision,
+                String::from_utf8(hex::encode(&message.signature)).unwrap(),
+                String::from_utf8(hex::encode(&message.sign_bytes)).unwrap(),
+                String::from_utf8(hex::encode(message.public_key.to_bytes())).unwrap(),
+            )
+        };
+
+        let mut log = vec![];
+        for i in 0..(BATCH_SIZE + 10) {
+            let seed = i as u8;
+            log.push(record(&signed(seed, &[seed; 64]), "signed"));
+        }
+
+        let mut forged = signed(1, b"vote");
+        forged.sign_bytes = b"other vote".to_vec();
+        log.push(record(&forged, "signed"));
+        log.push(record(&forged, "refused"));
+        log.push("{not json".to_owned());
+
+        let report = verify_audit_log(log.join("\n").as_bytes()).unwrap();
+        assert_eq!(report.verified, BATCH_SIZE + 10);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(
+            report
+                .failures
+                .iter()
+                .map(|failure| failure.line)
+                .collect::<Vec<_>>(),
+            [BATCH_SIZE + 11, BATCH_SIZE + 13]
+        );
+        assert!(report.failures[0]
+            .to_string()
+            .ends_with("invalid Ed25519 signature"));
+    }
+}
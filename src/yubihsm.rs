@@ -11,7 +11,7 @@ use std::thread;
 use std::{
     process,
     sync::{
-        atomic::{self, AtomicBool},
+        atomic::{self, AtomicBool, AtomicU64},
         Mutex, MutexGuard,
     },
 };
@@ -35,6 +35,9 @@ static HSM_CONNECTOR: Lazy<Connector> = Lazy::new(init_connector);
 // TODO(tarcieri): refactor with a straightforward `once_cell::sync::OnceCell`
 static HSM_CLIENT: Lazy<Mutex<Client>> = Lazy::new(|| Mutex::new(init_client()));
 
+/// Number of times the global client has been replaced by [`reopen_client`]
+static CLIENT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
 /// Flag indicating we're inside of a `tmkms yubihsm` command
 // TODO(tarcieri): refactor with a straightforward `once_cell::sync::OnceCell`
 static CLI_COMMAND: AtomicBool = AtomicBool::new(false);
@@ -60,6 +63,33 @@ pub fn client() -> MutexGuard<'static, Client> {
     HSM_CLIENT.lock().unwrap()
 }
 
+/// Get a handle to the global client along with its generation, which is
+/// incremented each time the client is reopened
+pub(crate) fn client_with_generation() -> (u64, Client) {
+    let client = client();
+    (
+        CLIENT_GENERATION.load(atomic::Ordering::SeqCst),
+        client.clone(),
+    )
+}
+
+/// Replace the global client with one using a newly opened and authenticated
+/// session (e.g. after `yubihsm-connector` restarted and the HSM session was
+/// lost), unless that's already been done since the client of the given
+/// generation was obtained
+pub(crate) fn reopen_client(generation: u64) -> Result<(), Error> {
+    let mut client = client();
+
+    if CLIENT_GENERATION.load(atomic::Ordering::SeqCst) != generation {
+        return Ok(());
+    }
+
+    let (credentials, reconnect) = client_config();
+    *client = Client::open(connector().clone(), credentials, reconnect)?;
+    CLIENT_GENERATION.fetch_add(1, atomic::Ordering::SeqCst);
+    Ok(())
+}
+
 /// Open a session with the YubiHSM2 using settings from the global config
 #[cfg(not(feature = "yubihsm-mock"))]
 fn init_connector() -> Connector {
@@ -0,0 +1,587 @@
+//! Integration tests for the `config validate` subcommand
+
+use crate::{cli, KMS_EXE_PATH};
+use std::{env, fs, path::Path, process::Command};
+
+/// Write a KMS configuration file with the given validator and provider
+/// sections, returning the path to it
+fn write_config(dir: &Path, validator: &str, providers: &str) -> String {
+    let config_path = dir.join("tmkms.toml");
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+            [[chain]]
+            id = "test_chain_id"
+            key_format = {{ type = "hex" }}
+
+            {}
+
+            {}
+            "#,
+            validator, providers
+        ),
+    )
+    .unwrap();
+
+    config_path.to_str().unwrap().to_owned()
+}
+
+/// Softsign provider section for the test chain
+fn softsign_provider() -> String {
+    let key_path = env::current_dir()
+        .unwrap()
+        .join("tests/support/signing.key");
+
+    format!(
+        r#"
+        [[providers.softsign]]
+        chain_ids = ["test_chain_id"]
+        path = "{}"
+        "#,
+        key_path.display()
+    )
+}
+
+#[test]
+fn test_valid_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let validator = r#"
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "unix:///tmp/validator.sock"
+        protocol_version = "v0.34"
+    "#;
+
+    let config_path = write_config(dir.path(), validator, &softsign_provider());
+    cli::run_successfully(&["config", "validate", "-c", &config_path]);
+}
+
+#[test]
+fn test_invalid_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let validator = r#"
+        [[validator]]
+        chain_id = "test_chain"
+        addr = "tcp://127.0.0.1:26658"
+        protocol_version = "v0.34"
+    "#;
+    let providers = r#"
+        [[providers.softsign]]
+        chain_ids = ["test_chain_id"]
+        path = "/nonexistent/signing.key"
+    "#;
+
+    let config_path = write_config(dir.path(), validator, providers);
+    let output = cli::run(&["config", "validate", "-c", &config_path]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let errors: Vec<_> = stderr.lines().collect();
+    assert_eq!(errors.len(), 3, "unexpected output: {}", stderr);
+
+    for path in &[
+        "validator[0].chain_id: no [[chain]] section with ID `test_chain`",
+        "validator[0]: `secret_key` is required",
+        "providers.softsign[0].path: couldn't read /nonexistent/signing.key",
+    ] {
+        assert!(stderr.contains(path), "missing `{}` in: {}", path, stderr);
+    }
+}
+
+/// Write a configuration whose chain has the given options, returning the
+/// path to it
+fn write_config_with_chain_options(dir: &Path, options: &str) -> String {
+    let config_path = dir.join("tmkms.toml");
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+            [[chain]]
+            id = "test_chain_id"
+            key_format = {{ type = "hex" }}
+            {}
+
+            [[validator]]
+            chain_id = "test_chain_id"
+            addr = "unix:///tmp/validator.sock"
+            protocol_version = "v0.34"
+
+            {}
+            "#,
+            options,
+            softsign_provider()
+        ),
+    )
+    .unwrap();
+
+    config_path.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn test_blocked_heights() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_config_with_chain_options(
+        dir.path(),
+        r#"blocked_heights = ["200000-200100", "123456"]"#,
+    );
+
+    let output = cli::run_successfully(&["config", "validate", "-c", &config_path]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("chain test_chain_id: blocked heights 123456, 200000-200100\n"),
+        "unexpected output: {}",
+        stdout
+    );
+
+    let config_path = write_config_with_chain_options(
+        dir.path(),
+        r#"blocked_heights = ["200000-200100", "200100"]"#,
+    );
+
+    let output = cli::run(&["config", "validate", "-c", &config_path]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    for error in &[
+        "chain[0].blocked_heights:",
+        "blocked heights `200000-200100` and `200100` overlap",
+    ] {
+        assert!(stderr.contains(error), "missing `{}` in: {}", error, stderr);
+    }
+}
+
+#[test]
+fn test_rpc_endpoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_config_with_chain_options(
+        dir.path(),
+        r#"rpc_endpoint = { url = "http://127.0.0.1:26657", max_lag = 5 }"#,
+    );
+    cli::run_successfully(&["config", "validate", "-c", &config_path]);
+
+    for (options, error) in &[
+        (
+            r#"rpc_endpoint = { url = "http://127.0.0.1:26657", poll_interval_secs = 0 }"#,
+            "chain[0].rpc_endpoint.poll_interval_secs: must be at least 1 second",
+        ),
+        (
+            r#"rpc_endpoint = { url = "https://rpc.example.com" }"#,
+            "https:// isn't supported",
+        ),
+    ] {
+        let config_path = write_config_with_chain_options(dir.path(), options);
+        let output = cli::run(&["config", "validate", "-c", &config_path]);
+        assert!(!output.status.success());
+
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(error), "missing `{}` in: {}", error, stderr);
+    }
+}
+
+#[test]
+fn test_aliases() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path =
+        write_config_with_chain_options(dir.path(), r#"aliases = ["test_chain_old"]"#);
+    cli::run_successfully(&["config", "validate", "-c", &config_path]);
+
+    for aliases in &[
+        r#"["test_chain_id"]"#,
+        r#"["test_chain_old", "test_chain_old"]"#,
+    ] {
+        let config_path =
+            write_config_with_chain_options(dir.path(), &format!("aliases = {}", aliases));
+        let output = cli::run(&["config", "validate", "-c", &config_path]);
+        assert!(!output.status.success());
+
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let error = "is already the ID or an alias of chain `test_chain_id`";
+        assert!(
+            stderr.contains("chain[0].aliases:"),
+            "unexpected: {}",
+            stderr
+        );
+        assert!(stderr.contains(error), "missing `{}` in: {}", error, stderr);
+    }
+}
+
+#[test]
+fn test_secret_key_options() {
+    let dir = tempfile::tempdir().unwrap();
+    let signing_key = env::current_dir()
+        .unwrap()
+        .join("tests/support/signing.key");
+
+    let validator = format!(
+        r#"
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "tcp://127.0.0.1:26658"
+        protocol_version = "v0.34"
+        secret_key = "{}"
+        secret_key_write_if_missing = true
+
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "tcp://127.0.0.1:26659"
+        protocol_version = "v0.34"
+        secret_key = "env:TMKMS_CLI_TEST_UNSET_IDENTITY_KEY"
+
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "unix:///tmp/validator.sock"
+        protocol_version = "v0.34"
+        secret_key_format = "json"
+
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "tcp://127.0.0.1:26660"
+        protocol_version = "v0.34"
+        secret_key = "{}"
+        secret_key_format = "json"
+        "#,
+        dir.path().join("missing.key").display(),
+        signing_key.display()
+    );
+
+    let config_path = write_config(dir.path(), &validator, &softsign_provider());
+    let output = cli::run(&["config", "validate", "-c", &config_path]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        !stderr.contains("validator[0]"),
+        "unexpected output: {}",
+        stderr
+    );
+
+    // `config validate` never generates keys
+    assert!(!dir.path().join("missing.key").exists());
+
+    for error in &[
+        "validator[1].secret_key: environment variable TMKMS_CLI_TEST_UNSET_IDENTITY_KEY is not set",
+        "validator[2].secret_key_format: only used with `secret_key`",
+        "validator[3].secret_key: parse error",
+    ] {
+        assert!(stderr.contains(error), "missing `{}` in: {}", error, stderr);
+    }
+}
+
+#[test]
+fn test_unknown_protocol_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let validator = r#"
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "unix:///tmp/validator.sock"
+        protocol_version = "v0.35"
+    "#;
+
+    let config_path = write_config(dir.path(), validator, &softsign_provider());
+    let output = cli::run(&["config", "validate", "-c", &config_path]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("validator[0].protocol_version: unknown variant `v0.35`"));
+}
+
+#[test]
+fn test_sr25519_key_algorithm() {
+    let dir = tempfile::tempdir().unwrap();
+    let validator = r#"
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "unix:///tmp/validator.sock"
+        protocol_version = "v0.34"
+    "#;
+    let providers = softsign_provider() + "key_algorithm = \"sr25519\"\n";
+
+    let config_path = write_config(dir.path(), validator, &providers);
+    let output = cli::run(["config", "validate", "-c", &config_path]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("sr25519 keys aren't supported"),
+        "unexpected error: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_invalid_tls_options() {
+    let dir = tempfile::tempdir().unwrap();
+    let validator = r#"
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "tls://127.0.0.1:26658"
+        protocol_version = "v0.34"
+        tls_ca = "/tmp/ca.pem"
+
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "unix:///tmp/validator.sock"
+        protocol_version = "v0.34"
+        tls_client_cert = "/tmp/client.pem"
+    "#;
+
+    let config_path = write_config(dir.path(), validator, &softsign_provider());
+    let output = cli::run(&["config", "validate", "-c", &config_path]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    for path in &[
+        "validator[0].addr: `tls://` addresses must use the hostname",
+        "validator[0].tls_client_cert: required for `tls://` addresses",
+        "validator[0].tls_client_key: required for `tls://` addresses",
+        "validator[1].tls_client_cert: only used with `tls://` and `grpc://` addresses",
+    ] {
+        assert!(stderr.contains(path), "missing `{}` in: {}", path, stderr);
+    }
+}
+
+#[test]
+fn test_grpc_without_mutual_tls() {
+    let dir = tempfile::tempdir().unwrap();
+    let validator = r#"
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "grpc://127.0.0.1:26659"
+        protocol_version = "grpc"
+
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "grpc://0.0.0.0:26660"
+        protocol_version = "grpc"
+
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "grpc://0.0.0.0:26661"
+        protocol_version = "grpc"
+        tls_ca = "/tmp/ca.pem"
+    "#;
+
+    let config_path = write_config(dir.path(), validator, &softsign_provider());
+    let output = cli::run(["config", "validate", "-c", &config_path]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("validator[0]"), "{}", stderr);
+
+    for path in &[
+        "validator[1].addr: `grpc://` addresses other than loopback ones require mutual TLS",
+        "validator[2].tls_client_cert: required for mutual TLS on `grpc://` addresses",
+        "validator[2].tls_client_key: required for mutual TLS on `grpc://` addresses",
+    ] {
+        assert!(stderr.contains(path), "missing `{}` in: {}", path, stderr);
+    }
+}
+
+#[test]
+fn test_softsign_key_from_env() {
+    let dir = tempfile::tempdir().unwrap();
+    let validator = r#"
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "unix:///tmp/validator.sock"
+        protocol_version = "v0.34"
+    "#;
+    let providers = r#"
+        [[providers.softsign]]
+        chain_ids = ["test_chain_id"]
+        path = "env:TMKMS_TEST_SIGNING_KEY"
+    "#;
+
+    let config_path = write_config(dir.path(), validator, providers);
+    let args = ["config", "validate", "-c", &config_path];
+
+    let output = Command::new(KMS_EXE_PATH)
+        .args(&args)
+        .env_remove("TMKMS_TEST_SIGNING_KEY")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(
+        "providers.softsign[0].path: environment variable TMKMS_TEST_SIGNING_KEY is not set"
+    ));
+
+    // Present, but only `tmkms start` reads it
+    let output = Command::new(KMS_EXE_PATH)
+        .args(&args)
+        .env("TMKMS_TEST_SIGNING_KEY", "not a key")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_invalid_socket_options() {
+    let dir = tempfile::tempdir().unwrap();
+    let validator = r#"
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "unix-listen:///tmp/validator.sock"
+        protocol_version = "v0.34"
+        socket_mode = "0660"
+        socket_owner = "tmkms-no-such-user"
+
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "unix:///tmp/validator.sock"
+        protocol_version = "v0.34"
+        socket_mode = "0660"
+    "#;
+
+    let config_path = write_config(dir.path(), validator, &softsign_provider());
+    let output = cli::run(&["config", "validate", "-c", &config_path]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let errors: Vec<_> = stderr.lines().collect();
+    assert_eq!(errors.len(), 2, "unexpected output: {}", stderr);
+
+    for path in &[
+        "validator[0].socket_owner: couldn't set socket ownership or permissions: no such user `tmkms-no-such-user`",
+        "validator[1].socket_mode: only used with `unix-listen://` addresses",
+    ] {
+        assert!(stderr.contains(path), "missing `{}` in: {}", path, stderr);
+    }
+}
+
+#[test]
+fn test_invalid_socket_mode() {
+    let dir = tempfile::tempdir().unwrap();
+    let validator = r#"
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "unix-listen:///tmp/validator.sock"
+        protocol_version = "v0.34"
+        socket_mode = "0999"
+    "#;
+
+    let config_path = write_config(dir.path(), validator, &softsign_provider());
+    let output = cli::run(&["config", "validate", "-c", &config_path]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("invalid socket mode `0999`"),
+        "unexpected output: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_invalid_keepalive_interval() {
+    let dir = tempfile::tempdir().unwrap();
+    let validator = r#"
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "unix:///tmp/validator.sock"
+        protocol_version = "v0.34"
+        keepalive_interval_secs = 30
+    "#;
+
+    let config_path = write_config(dir.path(), validator, &softsign_provider());
+    let output = cli::run(&["config", "validate", "-c", &config_path]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("validator[0].keepalive_interval_secs: only used with `tcp://`"),
+        "unexpected output: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_invalid_privileges() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = format!(
+        r#"
+        run_as_user = "tmkms"
+        run_as_group = "tmkms"
+        chroot_dir = "/var/lib/tmkms"
+        status_file = "/run/tmkms/status.json"
+
+        [[chain]]
+        id = "test_chain_id"
+        key_format = {{ type = "hex" }}
+
+        {}
+        "#,
+        softsign_provider()
+    );
+
+    let config_path = dir.path().join("tmkms.toml");
+    fs::write(&config_path, config).unwrap();
+
+    let output = cli::run(&["config", "validate", "-c", config_path.to_str().unwrap()]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "/run/tmkms/status.json is written to after startup, so it must be inside `chroot_dir`"
+        ),
+        "unexpected output: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_invalid_labelled_validators() {
+    let dir = tempfile::tempdir().unwrap();
+    let state_file = dir.path().join("state.json");
+    let validator = format!(
+        r#"
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "unix:///tmp/validator-a.sock"
+        protocol_version = "v0.34"
+        label = "a"
+        state_file = "{}"
+
+        [[validator]]
+        chain_id = "test_chain_id"
+        addr = "unix:///tmp/validator-b.sock"
+        protocol_version = "v0.34"
+        label = "b"
+        state_file = "{}"
+        "#,
+        state_file.display(),
+        state_file.display()
+    );
+    let providers = format!(
+        r#"
+        {}
+        validator = "c"
+        "#,
+        softsign_provider()
+    );
+
+    let config_path = write_config(dir.path(), &validator, &providers);
+    let output = cli::run(&["config", "validate", "-c", &config_path]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let errors: Vec<_> = stderr.lines().collect();
+    assert_eq!(errors.len(), 4, "unexpected output: {}", stderr);
+
+    for error in &[
+        "validator[1].state_file: ",
+        "is also a state file of chain `test_chain_id`",
+        "providers.softsign[0].validator: no [[validator]] section for chain `test_chain_id` has label `c`",
+        "validator[0].label: no signing provider has a consensus key for validator `a`",
+        "validator[1].label: no signing provider has a consensus key for validator `b`",
+    ] {
+        assert!(stderr.contains(error), "missing `{}` in: {}", error, stderr);
+    }
+}
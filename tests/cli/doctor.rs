@@ -0,0 +1,69 @@
+//! Integration tests for the `doctor` subcommand
+
+use crate::cli;
+use std::{env, fs, path::Path};
+
+/// Write a KMS configuration with a single chain using a softsign key and a
+/// validator at the given address, returning the path to the configuration
+/// file
+fn write_config(dir: &Path, validator_addr: &str) -> String {
+    let config_path = dir.join("tmkms.toml");
+    let key_path = env::current_dir()
+        .unwrap()
+        .join("tests/support/signing.key");
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+            [[chain]]
+            id = "test_chain_id"
+            key_format = {{ type = "hex" }}
+            state_file = "{}"
+
+            [[validator]]
+            chain_id = "test_chain_id"
+            addr = "{}"
+            protocol_version = "v0.34"
+
+            [[providers.softsign]]
+            chain_ids = ["test_chain_id"]
+            key_format = "base64"
+            path = "{}"
+            "#,
+            dir.join("priv_validator_state.json").display(),
+            validator_addr,
+            key_path.display()
+        ),
+    )
+    .unwrap();
+
+    config_path.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn test_doctor() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_config(dir.path(), "tcp://127.0.0.1:26658");
+
+    let output = cli::run_successfully(&["doctor", "-c", &config_path, "--sign-test"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("FAIL"), "{}", stdout);
+    assert!(stdout.contains("softsign"));
+    assert!(stdout.contains("test signature verified"));
+    assert!(stdout.contains("resolves to 127.0.0.1:26658"));
+}
+
+#[test]
+fn test_doctor_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket_path = dir.path().join("missing.sock");
+    let config_path = write_config(dir.path(), &format!("unix://{}", socket_path.display()));
+
+    let output = cli::run(&["doctor", "-c", &config_path]);
+    assert_eq!(output.status.code().unwrap(), 1);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("FAIL  validator"), "{}", stdout);
+    assert!(stdout.contains("pass  state"), "{}", stdout);
+}
@@ -2,7 +2,7 @@
 
 use crate::cli;
 use abscissa_core::Config;
-use std::{ffi::OsStr, fs};
+use std::{ffi::OsStr, fs, os::unix::fs::PermissionsExt};
 use tmkms::{commands::init::networks::Network, config::KmsConfig};
 
 #[test]
@@ -39,5 +39,56 @@ fn test_command() {
             .map(|c| c.id.as_str().split("-").next().unwrap().to_owned())
             .collect::<Vec<_>>(),
         &networks
-    )
+    );
+
+    // Validators use each network's protocol version
+    for network in Network::all() {
+        let validator = kms_config
+            .validator
+            .iter()
+            .find(|v| v.chain_id.as_str() == network.chain_id)
+            .unwrap();
+
+        assert_eq!(validator.protocol_version, network.protocol_version);
+    }
+
+    // Keys and state are only accessible to the KMS's user
+    for subdir in &["secrets", "state"] {
+        let mode = fs::metadata(output_dir.join(subdir))
+            .unwrap()
+            .permissions()
+            .mode();
+
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
+
+#[test]
+fn test_unknown_network() {
+    let parent_dir = tempfile::tempdir().unwrap();
+    let output_dir = parent_dir.path().join("tmkms");
+
+    let result = cli::run(&[
+        OsStr::new("init"),
+        OsStr::new("-n"),
+        OsStr::new("juno,mychain-1"),
+        output_dir.as_os_str(),
+    ]);
+
+    assert!(result.status.success());
+
+    // Unknown networks get a templated configuration with TODOs to fill in
+    let kms_config_path = output_dir.join("tmkms.toml");
+    let kms_config_toml = fs::read_to_string(&kms_config_path).unwrap();
+    assert!(kms_config_toml.contains("TODO: `mychain-1` isn't a well-known network"));
+
+    let kms_config = KmsConfig::load_toml(kms_config_toml).unwrap();
+    assert_eq!(
+        kms_config
+            .chain
+            .iter()
+            .map(|c| c.id.as_str())
+            .collect::<Vec<_>>(),
+        &["juno-1", "mychain-1"]
+    );
 }
@@ -8,7 +8,15 @@ use std::{
 
 use super::KMS_EXE_PATH;
 
+mod config;
+#[cfg(feature = "softsign")]
+mod doctor;
 mod init;
+mod pubkey;
+#[cfg(feature = "softsign")]
+mod softsign;
+mod state;
+mod verify;
 mod version;
 
 #[cfg(feature = "yubihsm")]
@@ -0,0 +1,331 @@
+//! Integration tests for the `pubkey` subcommand
+
+use crate::{cli, KMS_EXE_PATH};
+use std::{env, fs, path::Path, process::Command};
+
+/// Bech32 encoding of the consensus key in `tests/support/signing.key`
+const BECH32_PUBKEY: &str =
+    "cosmosvalconspub1zcjduepqew5va3ef3znvmxnpjqhn367rswa5u0x2hdd83hmf2q8at4wxas0qy7ncuk";
+
+/// Hex encoding of the consensus key in `tests/support/signing.key`
+const HEX_PUBKEY: &str = "CBA8CEC72988A6CD9A61902F38EBC383BB4E3CCABB5A78DF69500FD5D5C6EC1E";
+
+/// Tendermint address of the consensus key in `tests/support/signing.key`
+const ADDRESS: &str = "D1B82BBD8F2CF01C5E8F451DA43DCE9B369C86A9";
+
+/// Write a KMS configuration with a single chain using a softsign key,
+/// returning the path to the configuration file
+fn write_config(dir: &Path) -> String {
+    write_config_with_key_format(
+        dir,
+        r#"{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }"#,
+    )
+}
+
+/// Write a KMS configuration with a single chain using a softsign key and
+/// the given `key_format`, returning the path to the configuration file
+fn write_config_with_key_format(dir: &Path, key_format: &str) -> String {
+    let config_path = dir.join("tmkms.toml");
+    let key_path = env::current_dir()
+        .unwrap()
+        .join("tests/support/signing.key");
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+            [[chain]]
+            id = "test_chain_id"
+            key_format = {}
+            state_file = "{}"
+
+            [[providers.softsign]]
+            chain_ids = ["test_chain_id"]
+            key_format = "base64"
+            path = "{}"
+            "#,
+            key_format,
+            dir.join("priv_validator_state.json").display(),
+            key_path.display()
+        ),
+    )
+    .unwrap();
+
+    config_path.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn test_pubkey() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_config(dir.path());
+
+    let output = cli::run_successfully(&["pubkey", "-c", &config_path]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(BECH32_PUBKEY));
+    assert!(stdout.contains(HEX_PUBKEY));
+    assert!(stdout.contains(ADDRESS));
+}
+
+#[test]
+fn test_pubkey_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_config(dir.path());
+
+    let output = cli::run_successfully(&["pubkey", "-c", &config_path, "--json", "test_chain_id"]);
+    let keys: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(keys[0]["chain_id"], "test_chain_id");
+    assert_eq!(keys[0]["address"], ADDRESS);
+    assert_eq!(keys[0]["bech32"], BECH32_PUBKEY);
+    assert_eq!(keys[0]["hex"], HEX_PUBKEY);
+    assert_eq!(keys[0]["pub_key"]["type"], "tendermint/PubKeyEd25519");
+    assert_eq!(
+        keys[0]["pub_key"]["value"],
+        "y6jOxymIps2aYZAvOOvDg7tOPMq7WnjfaVAP1dXG7B4="
+    );
+}
+
+#[test]
+fn test_pubkey_key_formats() {
+    let dir = tempfile::tempdir().unwrap();
+
+    for (key_format, expected) in &[
+        (
+            r#"{ type = "hex", case = "lower", prefix_0x = true }"#,
+            format!("0x{}", HEX_PUBKEY.to_lowercase()),
+        ),
+        (
+            r#"{ type = "base64" }"#,
+            "y6jOxymIps2aYZAvOOvDg7tOPMq7WnjfaVAP1dXG7B4=".to_owned(),
+        ),
+    ] {
+        let config_path = write_config_with_key_format(dir.path(), key_format);
+        let output = cli::run_successfully(&["pubkey", "-c", &config_path, "--json"]);
+        let keys: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(&keys[0]["key"], expected);
+        assert_eq!(keys[0]["hex"], HEX_PUBKEY);
+    }
+
+    let config_path = write_config_with_key_format(dir.path(), r#"{ type = "did-key" }"#);
+    let output = cli::run_successfully(&["pubkey", "-c", &config_path]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("key:     did:key:z6Mk"));
+}
+
+#[test]
+fn test_pubkey_key_from_env() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("tmkms.toml");
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+            [[chain]]
+            id = "test_chain_id"
+            key_format = {{ type = "hex" }}
+            state_file = "{}"
+
+            [[providers.softsign]]
+            chain_ids = ["test_chain_id"]
+            path = "env:TMKMS_TEST_SIGNING_KEY"
+            "#,
+            dir.path().join("priv_validator_state.json").display(),
+        ),
+    )
+    .unwrap();
+
+    let output = Command::new(KMS_EXE_PATH)
+        .args(&["pubkey", "-c", config_path.to_str().unwrap()])
+        .env(
+            "TMKMS_TEST_SIGNING_KEY",
+            fs::read_to_string("tests/support/signing.key").unwrap(),
+        )
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains(HEX_PUBKEY));
+}
+
+#[test]
+fn test_pubkey_unknown_chain() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_config(dir.path());
+
+    let output = cli::run(&["pubkey", "-c", &config_path, "other_chain_id"]);
+    assert_eq!(output.status.code().unwrap(), 1);
+}
+
+/// Write a KMS configuration with two Interchain Security consumer chains,
+/// each with its own softsign key: `consumer-1` uses the key in
+/// `tests/support/signing.key` and `consumer-2` one at `consumer-2.key` in the
+/// given directory. Returns the path to the configuration file.
+fn write_consumer_config(dir: &Path) -> String {
+    let config_path = dir.join("tmkms.toml");
+    let key_paths = [
+        env::current_dir()
+            .unwrap()
+            .join("tests/support/signing.key"),
+        dir.join("consumer-2.key"),
+    ];
+
+    let mut config = String::new();
+
+    for (i, key_path) in key_paths.iter().enumerate() {
+        let chain_id = format!("consumer-{}", i + 1);
+
+        config.push_str(&format!(
+            r#"
+            [[chain]]
+            id = "{chain_id}"
+            key_format = {{ type = "hex" }}
+            state_file = "{state_file}"
+
+            [[providers.softsign]]
+            chain_ids = ["{chain_id}"]
+            key_format = "base64"
+            path = "{key_path}"
+            "#,
+            chain_id = chain_id,
+            state_file = dir.join(format!("{}_state.json", chain_id)).display(),
+            key_path = key_path.display()
+        ));
+    }
+
+    fs::write(&config_path, config).unwrap();
+    config_path.to_str().unwrap().to_owned()
+}
+
+/// Get the `assign-consensus-key` JSON of a consumer chain's key
+fn consumer_key(config_path: &str, chain_id: &str) -> serde_json::Value {
+    let output = cli::run_successfully(&["pubkey", "-c", config_path, "--consumer", chain_id]);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn test_consumer_chain_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_consumer_config(dir.path());
+
+    cli::run_successfully(&[
+        "softsign",
+        "keygen",
+        "-c",
+        &config_path,
+        "--chain",
+        "consumer-2",
+    ]);
+
+    // The generated key isn't overwritten
+    let key = fs::read(dir.path().join("consumer-2.key")).unwrap();
+    let output = cli::run(&[
+        "softsign",
+        "keygen",
+        "-c",
+        &config_path,
+        "--chain",
+        "consumer-2",
+    ]);
+    assert_eq!(output.status.code().unwrap(), 1);
+    assert_eq!(fs::read(dir.path().join("consumer-2.key")).unwrap(), key);
+
+    let consumer_1 = consumer_key(&config_path, "consumer-1");
+    assert_eq!(consumer_1["@type"], "/cosmos.crypto.ed25519.PubKey");
+    assert_eq!(
+        consumer_1["key"],
+        "y6jOxymIps2aYZAvOOvDg7tOPMq7WnjfaVAP1dXG7B4="
+    );
+
+    let consumer_2 = consumer_key(&config_path, "consumer-2");
+    assert_eq!(consumer_2["@type"], "/cosmos.crypto.ed25519.PubKey");
+    assert_ne!(consumer_2["key"], consumer_1["key"]);
+
+    // Chains can't share a state file
+    let config = fs::read_to_string(&config_path).unwrap();
+    fs::write(
+        &config_path,
+        config.replace("consumer-2_state.json", "consumer-1_state.json"),
+    )
+    .unwrap();
+
+    let output = cli::run(&["pubkey", "-c", &config_path, "--consumer", "consumer-1"]);
+    assert_eq!(output.status.code().unwrap(), 1);
+}
+
+/// Write a KMS configuration with two softsign consensus keys for the same
+/// chain: the one in `tests/support/signing.key` and a newly generated one,
+/// with the given `[[chain]]` options. Returns the path to the configuration
+/// file.
+fn write_rotation_config(dir: &Path, chain_options: &str) -> String {
+    let config_path = dir.join("tmkms.toml");
+    let new_key_path = dir.join("new.key");
+
+    if !new_key_path.exists() {
+        cli::run_successfully(&["softsign", "keygen", new_key_path.to_str().unwrap()]);
+    }
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+            [[chain]]
+            id = "test_chain_id"
+            key_format = {{ type = "hex" }}
+            state_file = "{}"
+            {}
+
+            [[providers.softsign]]
+            chain_ids = ["test_chain_id"]
+            key_format = "base64"
+            path = "{}"
+
+            [[providers.softsign]]
+            chain_ids = ["test_chain_id"]
+            path = "{}"
+            "#,
+            dir.join("priv_validator_state.json").display(),
+            chain_options,
+            env::current_dir()
+                .unwrap()
+                .join("tests/support/signing.key")
+                .display(),
+            new_key_path.display()
+        ),
+    )
+    .unwrap();
+
+    config_path.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn test_pubkey_active_key() {
+    let dir = tempfile::tempdir().unwrap();
+
+    // Which of the two keys to sign with is ambiguous
+    let config_path = write_rotation_config(dir.path(), "");
+    let output = cli::run(&["pubkey", "-c", &config_path]);
+    assert_eq!(output.status.code().unwrap(), 1);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("`active_key`"), "{}", stderr);
+
+    // Only the selected key is the chain's consensus key
+    let config_path =
+        write_rotation_config(dir.path(), &format!(r#"active_key = "{}""#, HEX_PUBKEY));
+    let output = cli::run_successfully(&["pubkey", "-c", &config_path, "--json"]);
+    let keys: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(keys.as_array().unwrap().len(), 1);
+    assert_eq!(keys[0]["hex"], HEX_PUBKEY);
+
+    // The selected key must be one of the chain's keys
+    let config_path = write_rotation_config(dir.path(), r#"active_key = "00""#);
+    let output = cli::run(&["pubkey", "-c", &config_path]);
+    assert_eq!(output.status.code().unwrap(), 1);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("doesn't match any of its consensus keys"),
+        "{}",
+        stderr
+    );
+}
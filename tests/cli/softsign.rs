@@ -0,0 +1,228 @@
+//! Integration tests for the `softsign` subcommand
+
+use crate::{cli, KMS_EXE_PATH};
+use std::{
+    fs,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Softsign key used in tests
+const KEY_PATH: &str = "tests/support/signing.key";
+
+/// Tendermint address of the key in `tests/support/signing.key`
+const ADDRESS: &str = "D1B82BBD8F2CF01C5E8F451DA43DCE9B369C86A9";
+
+/// Base64 encoding of the public key in `tests/support/signing.key`
+const PUBKEY_BASE64: &str = "y6jOxymIps2aYZAvOOvDg7tOPMq7WnjfaVAP1dXG7B4=";
+
+/// BIP39 test vector mnemonic
+const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon \
+    abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+    abandon abandon abandon abandon art";
+
+#[test]
+fn test_export_and_import() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = dir.path().join("priv_validator_key.json");
+    let key_path = dir.path().join("signing.key");
+    let json = json_path.to_str().unwrap();
+
+    // Plaintext export must be acknowledged
+    let output = cli::run(&["softsign", "export", "-o", json, KEY_PATH]);
+    assert_eq!(output.status.code().unwrap(), 1);
+    assert!(!json_path.exists());
+
+    cli::run_successfully(&[
+        "softsign",
+        "export",
+        "--format",
+        "priv-validator",
+        "-o",
+        json,
+        "--i-know-this-is-dangerous",
+        KEY_PATH,
+    ]);
+
+    let exported: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+    assert_eq!(exported["address"], ADDRESS);
+    assert_eq!(exported["pub_key"]["type"], "tendermint/PubKeyEd25519");
+    assert_eq!(exported["pub_key"]["value"], PUBKEY_BASE64);
+
+    cli::run_successfully(&["softsign", "import", json, "-o", key_path.to_str().unwrap()]);
+
+    assert_eq!(
+        fs::read_to_string(&key_path).unwrap().trim_end(),
+        fs::read_to_string(KEY_PATH).unwrap().trim_end()
+    );
+}
+
+#[test]
+fn test_import_mismatched_pub_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = dir.path().join("priv_validator_key.json");
+    let key_path = dir.path().join("signing.key");
+    let json = json_path.to_str().unwrap();
+
+    cli::run_successfully(&[
+        "softsign",
+        "export",
+        "-o",
+        json,
+        "--i-know-this-is-dangerous",
+        KEY_PATH,
+    ]);
+
+    let contents = fs::read_to_string(&json_path).unwrap().replace(
+        PUBKEY_BASE64,
+        "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+    );
+    fs::write(&json_path, contents).unwrap();
+
+    let output = cli::run(&["softsign", "import", json, "-o", key_path.to_str().unwrap()]);
+    assert_eq!(output.status.code().unwrap(), 1);
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("doesn't match its private key"));
+    assert!(!key_path.exists());
+}
+
+#[test]
+fn test_verify() {
+    let output = cli::run_successfully(&[
+        "softsign",
+        "verify",
+        "--expect",
+        "cosmosvalconspub1zcjduepqew5va3ef3znvmxnpjqhn367rswa5u0x2hdd83hmf2q8at4wxas0qy7ncuk",
+        "--expect-address",
+        &ADDRESS.to_lowercase(),
+        KEY_PATH,
+    ]);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(&format!("address:    {}", ADDRESS)));
+
+    // The derived key is printed in the expected key's format
+    let output = cli::run(&[
+        "softsign",
+        "verify",
+        "--expect",
+        "f0O0w9aJK3HwLr+MQEnWxBFtz6MEH13u2DPnDQN/0So=",
+        KEY_PATH,
+    ]);
+    assert_eq!(output.status.code().unwrap(), 1);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains(&format!("public key: {}", PUBKEY_BASE64)));
+
+    let output = cli::run(&["softsign", "verify", KEY_PATH]);
+    assert_eq!(output.status.code().unwrap(), 1);
+}
+
+#[test]
+fn test_keygen_from_mnemonic() {
+    let dir = tempfile::tempdir().unwrap();
+    let mnemonic_path = dir.path().join("mnemonic.txt");
+    let passphrase_path = dir.path().join("passphrase.txt");
+    let json_path = dir.path().join("priv_validator_key.json");
+
+    fs::write(&mnemonic_path, format!("{}\n", MNEMONIC)).unwrap();
+    fs::write(&passphrase_path, "TREZOR\n").unwrap();
+
+    let output = cli::run_successfully(&[
+        "softsign",
+        "keygen",
+        "--from-mnemonic",
+        mnemonic_path.to_str().unwrap(),
+        "--bip39-passphrase-file",
+        passphrase_path.to_str().unwrap(),
+        "-f",
+        "json",
+        json_path.to_str().unwrap(),
+    ]);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("key:     f0O0w9aJK3HwLr+MQEnWxBFtz6MEH13u2DPnDQN/0So="));
+    assert!(stdout.contains("address: cosmosvalcons1elaxlg9npgkwcc7wg0nq3sjlupa24nj4ery4zz"));
+
+    let written: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+    assert_eq!(
+        written["pub_key"]["value"],
+        "f0O0w9aJK3HwLr+MQEnWxBFtz6MEH13u2DPnDQN/0So="
+    );
+    assert_eq!(
+        written["address"],
+        "CFFA6FA0B30A2CEC63CE43E608C25FE07AAACE55"
+    );
+}
+
+#[test]
+fn test_keygen_from_mnemonic_stdin() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_path = dir.path().join("signing.key");
+
+    let mut process = Command::new(KMS_EXE_PATH)
+        .args(&["softsign", "keygen", "--from-mnemonic", "-"])
+        .arg(&key_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    process
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(MNEMONIC.as_bytes())
+        .unwrap();
+
+    let output = process.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("key:     GISCfDMksTRUvnfXZW0N3rO7PQd0vP8PLFR1FdKyAVw="));
+    assert!(stdout.contains("address: cosmosvalcons1pywgxt6s0n9qmv2vqgd8vpvcw73j2t26d3lf45"));
+
+    // The key file holds the derived key
+    cli::run_successfully(&[
+        "softsign",
+        "export",
+        "-o",
+        dir.path().join("priv_validator_key.json").to_str().unwrap(),
+        "--i-know-this-is-dangerous",
+        key_path.to_str().unwrap(),
+    ]);
+
+    let exported: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(dir.path().join("priv_validator_key.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        exported["pub_key"]["value"],
+        "GISCfDMksTRUvnfXZW0N3rO7PQd0vP8PLFR1FdKyAVw="
+    );
+}
+
+#[test]
+fn test_keygen_invalid_mnemonic() {
+    let dir = tempfile::tempdir().unwrap();
+    let mnemonic_path = dir.path().join("mnemonic.txt");
+    let key_path = dir.path().join("signing.key");
+
+    fs::write(&mnemonic_path, MNEMONIC.replace("art", "abandon")).unwrap();
+
+    let output = cli::run(&[
+        "softsign",
+        "keygen",
+        "--from-mnemonic",
+        mnemonic_path.to_str().unwrap(),
+        key_path.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code().unwrap(), 1);
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("invalid mnemonic"));
+    assert!(!key_path.exists());
+}
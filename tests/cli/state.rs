@@ -0,0 +1,234 @@
+//! Integration tests for the `state` subcommand
+
+use crate::cli;
+use std::{fs, path::Path};
+
+/// Write a KMS configuration with a single chain whose state is stored in
+/// the given directory, returning the path to the configuration file
+fn write_config(dir: &Path) -> String {
+    let config_path = dir.join("tmkms.toml");
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+            [[chain]]
+            id = "test_chain_id"
+            key_format = {{ type = "hex" }}
+            state_file = "{}"
+
+            [providers]
+            "#,
+            dir.join("priv_validator_state.json").display()
+        ),
+    )
+    .unwrap();
+
+    config_path.to_str().unwrap().to_owned()
+}
+
+/// Get the state of the test chain as JSON
+fn show_state(config_path: &str) -> serde_json::Value {
+    let output = cli::run_successfully(&["state", "show", "-c", config_path, "--json"]);
+    let states: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    states[0].clone()
+}
+
+#[test]
+fn test_show_and_set() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_config(dir.path());
+
+    assert_eq!(show_state(&config_path)["height"], serde_json::Value::Null);
+
+    cli::run_successfully(&[
+        "state",
+        "set",
+        "-c",
+        &config_path,
+        "test_chain_id",
+        "--height",
+        "12345",
+        "--round",
+        "2",
+        "--step",
+        "1",
+    ]);
+
+    let state = show_state(&config_path);
+    assert_eq!(state["chain_id"], "test_chain_id");
+    assert_eq!(state["height"], 12345);
+    assert_eq!(state["round"], 2);
+    assert_eq!(state["step"], 1);
+}
+
+#[test]
+fn test_set_lower_state() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_config(dir.path());
+    let set = |height: &str, force: bool| {
+        let mut args = vec![
+            "state",
+            "set",
+            "-c",
+            &config_path,
+            "test_chain_id",
+            "--height",
+            height,
+        ];
+
+        if force {
+            args.push("--force");
+        }
+
+        cli::run(&args)
+    };
+
+    assert!(set("100", false).status.success());
+
+    // Lowering the state requires `--force`
+    assert!(!set("99", false).status.success());
+    assert_eq!(show_state(&config_path)["height"], 100);
+
+    assert!(set("99", true).status.success());
+    assert_eq!(show_state(&config_path)["height"], 99);
+}
+
+#[test]
+fn test_init() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_config(dir.path());
+    let init = |height: &str| {
+        cli::run(&[
+            "state",
+            "init",
+            "-c",
+            &config_path,
+            "test_chain_id",
+            "--height",
+            height,
+        ])
+    };
+
+    assert!(init("500").status.success());
+
+    let state = show_state(&config_path);
+    assert_eq!(state["height"], 500);
+    assert_eq!(state["round"], 0);
+    assert_eq!(state["step"], 0);
+
+    // An existing state is never overwritten
+    assert!(!init("1").status.success());
+    assert_eq!(show_state(&config_path)["height"], 500);
+}
+
+#[test]
+fn test_set_with_state_hmac() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_config(dir.path());
+    let key_path = dir.path().join("state_hmac.key");
+    let state_path = dir.path().join("priv_validator_state.json");
+
+    // 32-byte key, Base64-encoded
+    fs::write(&key_path, "QkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkI=").unwrap();
+
+    let config = fs::read_to_string(&config_path).unwrap().replace(
+        "state_file =",
+        &format!(
+            "state_hmac_key_path = \"{}\"\nstate_file =",
+            key_path.display()
+        ),
+    );
+    fs::write(&config_path, config).unwrap();
+
+    let set = |height: &str, force: bool| {
+        let mut args = vec![
+            "state",
+            "set",
+            "-c",
+            &config_path,
+            "test_chain_id",
+            "--height",
+            height,
+        ];
+
+        if force {
+            args.push("--force");
+        }
+
+        cli::run(&args)
+    };
+
+    assert!(set("100", false).status.success());
+    assert_eq!(show_state(&config_path)["height"], 100);
+
+    // State edited by hand is refused...
+    let json = fs::read_to_string(&state_path).unwrap();
+    fs::write(&state_path, json.replace("\"100\"", "\"10\"")).unwrap();
+
+    let show = cli::run(&["state", "show", "-c", &config_path]);
+    assert!(!show.status.success());
+    assert!(String::from_utf8_lossy(&show.stderr).contains("failed verification"));
+    assert!(!set("200", false).status.success());
+
+    // ...unless it's replaced with `--force`
+    assert!(set("200", true).status.success());
+    assert_eq!(show_state(&config_path)["height"], 200);
+}
+
+#[test]
+fn test_import() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = write_config(dir.path());
+    let import_path = dir.path().join("validator_state.json");
+    let import = |json: &str, force: bool| {
+        fs::write(&import_path, json).unwrap();
+
+        let mut args = vec![
+            "state",
+            "import",
+            "-c",
+            &config_path,
+            "test_chain_id",
+            import_path.to_str().unwrap(),
+        ];
+
+        if force {
+            args.push("--force");
+        }
+
+        cli::run(&args)
+    };
+
+    // State of a Tendermint v0.33 validator, whose round is a string
+    let imported = import(
+        r#"{
+  "height": "1234",
+  "round": "2",
+  "step": 2,
+  "signature": "b6Fl0Ru3GUvqSzyUz3xBLdmhB4eU1mYxJ8Tg2bLw5HSoZ5Y0fj8vCdKx9o8LlYrUqD3y5d0tRnOEfZ1vGQJ3Bw==",
+  "signbytes": "2E080111D2040000000000001902000000000000002A0C0891D9B4F505108093CB9B01320B746573745F636861696E"
+}"#,
+        false,
+    );
+    assert!(imported.status.success());
+
+    let state = show_state(&config_path);
+    assert_eq!(state["height"], 1234);
+    assert_eq!(state["round"], 2);
+    assert_eq!(state["step"], 2);
+    assert_eq!(state["msg_type"], "prevote");
+    assert!(state["sign_bytes_sha256"].is_string());
+
+    // Importing a lower state requires `--force`
+    let lower = r#"{"height": "1000", "round": 0, "step": 3}"#;
+    assert!(!import(lower, false).status.success());
+    assert_eq!(show_state(&config_path)["height"], 1234);
+
+    assert!(import(lower, true).status.success());
+    let state = show_state(&config_path);
+    assert_eq!(state["height"], 1000);
+    assert_eq!(state["sign_bytes_sha256"], serde_json::Value::Null);
+
+    assert!(!import("not JSON", true).status.success());
+}
@@ -0,0 +1,104 @@
+//! Integration tests for the `verify` subcommand
+
+use crate::cli;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+use std::fs;
+
+/// Get the keypair for the given seed
+fn keypair(seed: u8) -> Keypair {
+    let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+    let public = PublicKey::from(&secret);
+    Keypair { secret, public }
+}
+
+/// Encode bytes as hex
+fn hex(bytes: &[u8]) -> String {
+    String::from_utf8(subtle_encoding::hex::encode(bytes)).unwrap()
+}
+
+/// Serialize an audit log record of a signed prevote
+fn record(keypair: &Keypair, sign_bytes: &[u8], signature: &[u8]) -> String {
+    format!(
+        r#"{{"timestamp":"2026-01-01T00:00:00Z","chain_id":"test_chain_id","msg_type":"prevote","height":1,"round":0,"step":6,"block_id":null,"decision":"signed","reason":null,"signature":"{}","sign_bytes":"{}","public_key":"{}","hash":"00"}}"#,
+        hex(signature),
+        hex(sign_bytes),
+        hex(keypair.public.as_bytes())
+    )
+}
+
+#[test]
+fn test_verify_one() {
+    let keypair = keypair(1);
+    let signature = keypair.sign(b"sign bytes").to_bytes();
+    let pubkey = hex(keypair.public.as_bytes());
+
+    cli::run_successfully(&[
+        "verify",
+        "--sign-bytes",
+        &hex(b"sign bytes"),
+        "--signature",
+        &hex(&signature),
+        "--pubkey",
+        &pubkey,
+    ]);
+
+    let out = cli::run(&[
+        "verify",
+        "--sign-bytes",
+        &hex(b"other bytes"),
+        "--signature",
+        &hex(&signature),
+        "--pubkey",
+        &pubkey,
+    ]);
+
+    assert_eq!(out.status.code().unwrap(), 1);
+    assert!(String::from_utf8(out.stderr)
+        .unwrap()
+        .contains("invalid Ed25519 signature"));
+}
+
+#[test]
+fn test_verify_audit_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.log");
+    let path_str = path.to_str().unwrap();
+
+    let mut log = String::new();
+    for seed in 0..5 {
+        let keypair = keypair(seed);
+        let sign_bytes = [seed; 32];
+        let signature = keypair.sign(&sign_bytes).to_bytes();
+        log.push_str(&record(&keypair, &sign_bytes, &signature));
+        log.push('\n');
+    }
+
+    fs::write(&path, &log).unwrap();
+    cli::run_successfully(&["verify", "--audit-log", path_str]);
+
+    // Append a record whose signature is of different bytes
+    let keypair = keypair(9);
+    let signature = keypair.sign(b"sign bytes").to_bytes();
+    log.push_str(&record(&keypair, b"forged bytes", &signature));
+    log.push('\n');
+    fs::write(&path, &log).unwrap();
+
+    let out = cli::run(&["verify", "--audit-log", path_str]);
+    assert_eq!(out.status.code().unwrap(), 1);
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap().trim(),
+        format!(
+            "{}:line 6: verification failed: invalid Ed25519 signature",
+            path_str
+        )
+    );
+    assert!(String::from_utf8(out.stderr)
+        .unwrap()
+        .contains("5 signature(s) verified, 1 failed, 0 record(s) skipped"));
+}
+
+#[test]
+fn test_verify_requires_arguments() {
+    let out = cli::run(&["verify", "--sign-bytes", "00"]);
+    assert_eq!(out.status.code().unwrap(), 1);
+}
@@ -11,3 +11,23 @@ fn test_version() {
     let stdout = str::from_utf8(&result.stdout).unwrap().trim().to_owned();
     assert!(stdout.eq(option_env!("CARGO_PKG_VERSION").unwrap_or("unknown")));
 }
+
+#[test]
+fn test_version_json() {
+    let result = cli::run(&["version", "--json"]);
+    assert!(result.status.success());
+
+    let info: serde_json::Value = serde_json::from_slice(&result.stdout).unwrap();
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert!(info["git_commit"].is_string() || info["git_commit"].is_null());
+    assert!(info["features"].is_array());
+    assert!(info["rustc_version"]
+        .as_str()
+        .map_or(true, |version| version.starts_with("rustc ")));
+
+    let protocol_versions = info["protocol_versions"].as_array().unwrap();
+
+    for version in &["v0.38", "v0.37", "v0.34", "v0.33", "legacy"] {
+        assert!(protocol_versions.contains(&(*version).into()));
+    }
+}
@@ -19,3 +19,29 @@ fn keys_command_test() {
     let stderr = str::from_utf8(&out.stderr).unwrap().trim().to_owned();
     assert!(stderr.contains("no keys in this YubiHSM"));
 }
+
+#[cfg(feature = "yubihsm-mock")]
+#[test]
+fn chains_flags_missing_keys() {
+    let out = cli::run(&[
+        "yubihsm",
+        "keys",
+        "list",
+        "--chains",
+        "--json",
+        "-c",
+        super::KMS_CONFIG_PATH,
+    ]);
+
+    // The configured key doesn't exist in the (empty) MockHSM
+    assert_eq!(out.status.code(), Some(1));
+
+    let keys: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(keys[0]["chain_id"], "cosmoshub");
+    assert_eq!(keys[0]["key_id"], 1);
+    assert_eq!(keys[0]["key_type"], "consensus");
+    assert_eq!(
+        keys[0]["problems"][0],
+        "no such asymmetric key on the device"
+    );
+}
@@ -2,7 +2,7 @@
 
 use std::{
     fs,
-    io::{self, Cursor, Read, Write},
+    io::{self, Read, Write},
     net::{TcpListener, TcpStream},
     os::unix::net::{UnixListener, UnixStream},
     process::{Child, Command},
@@ -15,12 +15,13 @@ use rand::Rng;
 use tempfile::NamedTempFile;
 
 use prost_amino::Message;
-use tendermint_p2p::secret_connection::{self, SecretConnection};
+use tendermint_p2p::secret_connection::{self, SecretConnection, DATA_MAX_SIZE};
 
 use tmkms::{
     amino_types::{self, *},
     config::validator::ProtocolVersion,
     connection::unix::UnixConnection,
+    rpc::{self, MsgReader},
 };
 
 /// Integration tests for the KMS command-line interface
@@ -29,9 +30,15 @@ mod cli;
 /// Path to the KMS executable
 const KMS_EXE_PATH: &str = "target/debug/tmkms";
 
+/// Maximum size of a response read from the KMS in these tests
+const MAX_RESPONSE_SIZE: usize = 65536;
+
 /// Path to the example validator signing key
 const SIGNING_KEY_PATH: &str = "tests/support/signing.key";
 
+/// Path to the example secp256k1 validator signing key
+const SECP256K1_SIGNING_KEY_PATH: &str = "tests/support/signing_secp256k1.key";
+
 enum KmsSocket {
     /// TCP socket type
     TCP(TcpStream),
@@ -80,6 +87,9 @@ struct KmsProcess {
 
     /// A socket to KMS process
     socket: KmsSocket,
+
+    /// Path to the KMS's state file
+    state_file: String,
 }
 
 impl KmsProcess {
@@ -99,17 +109,35 @@ impl KmsProcess {
         Self {
             process: process,
             socket: KmsSocket::TCP(socket),
+            state_file: state_file_path(config.path()),
         }
     }
 
     /// Spawn the KMS process and connect to the Unix listener
     pub fn create_unix() -> Self {
+        Self::create_unix_with_protocol_version("legacy")
+    }
+
+    /// Spawn the KMS process using the given protocol version and connect
+    /// to the Unix listener
+    pub fn create_unix_with_protocol_version(protocol_version: &str) -> Self {
+        Self::create_unix_with_key(protocol_version, SIGNING_KEY_PATH, "ed25519")
+    }
+
+    /// Spawn the KMS process using the given protocol version and softsign
+    /// consensus key, and connect to the Unix listener
+    pub fn create_unix_with_key(
+        protocol_version: &str,
+        key_path: &str,
+        key_algorithm: &str,
+    ) -> Self {
         // Create a random socket path and a config file
         let mut rng = rand::thread_rng();
         let letter: char = rng.gen_range(b'a', b'z') as char;
         let number: u32 = rng.gen_range(0, 999999);
         let socket_path = format!("/tmp/tmkms-{}{:06}.sock", letter, number);
-        let config = KmsProcess::create_unix_config(&socket_path);
+        let config =
+            KmsProcess::create_unix_config(&socket_path, protocol_version, key_path, key_algorithm);
 
         // Start listening for connections via the Unix socket
         let listener = UnixListener::bind(socket_path).unwrap();
@@ -122,6 +150,7 @@ impl KmsProcess {
         Self {
             process: process,
             socket: KmsSocket::UNIX(socket),
+            state_file: state_file_path(config.path()),
         }
     }
 
@@ -137,11 +166,14 @@ impl KmsProcess {
             [[chain]]
             id = "test_chain_id"
             key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+            state_file = "{}"
 
             [[validator]]
             addr = "tcp://{}@127.0.0.1:{}"
             chain_id = "test_chain_id"
             max_height = "500000"
+            min_height = "100"
+            max_requests_per_second = 5
             reconnect = false
             secret_key = "tests/support/secret_connection.key"
             protocol_version = "legacy"
@@ -151,7 +183,10 @@ impl KmsProcess {
             key_format = "base64"
             path = "{}"
         "#,
-            &peer_id.to_string(), port, SIGNING_KEY_PATH
+            state_file_path(config_file.path()),
+            &peer_id.to_string(),
+            port,
+            SIGNING_KEY_PATH
         )
         .unwrap();
 
@@ -159,7 +194,12 @@ impl KmsProcess {
     }
 
     /// Create a config file for a UNIX KMS and return its path
-    fn create_unix_config(socket_path: &str) -> NamedTempFile {
+    fn create_unix_config(
+        socket_path: &str,
+        protocol_version: &str,
+        key_path: &str,
+        key_algorithm: &str,
+    ) -> NamedTempFile {
         let mut config_file = NamedTempFile::new().unwrap();
         writeln!(
             config_file,
@@ -167,19 +207,27 @@ impl KmsProcess {
             [[chain]]
             id = "test_chain_id"
             key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+            state_file = "{}"
 
             [[validator]]
             addr = "unix://{}"
             chain_id = "test_chain_id"
             max_height = "500000"
-            protocol_version = "legacy"
+            min_height = "100"
+            max_requests_per_second = 5
+            protocol_version = "{}"
 
             [[providers.softsign]]
             chain_ids = ["test_chain_id"]
+            key_algorithm = "{}"
             key_format = "base64"
             path = "{}"
         "#,
-            socket_path, SIGNING_KEY_PATH
+            state_file_path(config_file.path()),
+            socket_path,
+            protocol_version,
+            key_algorithm,
+            key_path
         )
         .unwrap();
 
@@ -219,8 +267,10 @@ impl KmsProcess {
 struct ProtocolTester {
     tcp_device: KmsProcess,
     tcp_connection: KmsConnection,
+    tcp_reader: MsgReader,
     unix_device: KmsProcess,
     unix_connection: KmsConnection,
+    unix_reader: MsgReader,
 }
 
 impl ProtocolTester {
@@ -236,10 +286,32 @@ impl ProtocolTester {
         functor(Self {
             tcp_device,
             tcp_connection,
+            tcp_reader: MsgReader::new(),
             unix_device,
             unix_connection,
+            unix_reader: MsgReader::new(),
         });
     }
+
+    /// Read a whole (length-prefixed) response from each connection, however
+    /// many frames it spans
+    pub fn read_response(&mut self) -> Vec<u8> {
+        let tcp_resp = self
+            .tcp_reader
+            .read_msg(&mut self.tcp_connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+        let unix_resp = self
+            .unix_reader
+            .read_msg(&mut self.unix_connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+
+        // Assert handler sanity
+        if tcp_resp != unix_resp {
+            warn!("binary protocol differs between TCP and UNIX sockets");
+        }
+
+        unix_resp
+    }
 }
 
 impl Drop for ProtocolTester {
@@ -247,12 +319,8 @@ impl Drop for ProtocolTester {
         self.tcp_device.process.kill().unwrap();
         self.unix_device.process.kill().unwrap();
 
-        match fs::remove_file("test_chain_id_priv_validator_state.json") {
-            Err(ref e) if e.kind() != io::ErrorKind::NotFound => {
-                panic!("{}", e);
-            }
-            _ => (),
-        }
+        remove_state_files(&self.tcp_device.state_file);
+        remove_state_files(&self.unix_device.state_file);
     }
 }
 
@@ -289,19 +357,38 @@ impl io::Read for ProtocolTester {
     }
 }
 
+/// Get the path of the state file of the KMS using the given config file,
+/// which is unique to it so concurrently running KMS processes don't contend
+/// for the state's lock
+fn state_file_path(config_path: &std::path::Path) -> String {
+    format!("{}_priv_validator_state.json", config_path.display())
+}
+
+/// Remove the chain state file written by the KMS (along with its backup and
+/// lock file)
+fn remove_state_files(state_file: &str) {
+    for suffix in &["", ".bak", ".lock"] {
+        match fs::remove_file(format!("{}{}", state_file, suffix)) {
+            Err(ref e) if e.kind() != io::ErrorKind::NotFound => {
+                panic!("{}", e);
+            }
+            _ => (),
+        }
+    }
+}
+
 /// Get the Ed25519 signing keypair used by the tests
 fn test_ed25519_keypair() -> ed25519::Keypair {
     tmkms::key_utils::load_base64_ed25519_key(SIGNING_KEY_PATH).unwrap()
 }
 
-/// Extract the actual length of an amino message
-pub fn extract_actual_len(buf: &[u8]) -> Result<u64, prost_amino::DecodeError> {
-    let mut buff = Cursor::new(buf);
-    let actual_len = prost_amino::encoding::decode_varint(&mut buff)?;
-    if actual_len == 0 {
-        return Ok(1);
-    }
-    Ok(actual_len + (prost_amino::encoding::encoded_len_varint(actual_len) as u64))
+/// Get the address of the validator whose consensus key is the test Ed25519
+/// key, which votes must be from
+fn test_validator_address() -> Vec<u8> {
+    let public_key = tendermint::PublicKey::from(test_ed25519_keypair().public);
+    tendermint::account::Id::from(public_key)
+        .as_bytes()
+        .to_vec()
 }
 
 #[test]
@@ -328,6 +415,7 @@ fn test_handle_and_sign_proposal() {
 
         let spr = amino_types::proposal::SignProposalRequest {
             proposal: Some(proposal),
+            chain_id: String::new(),
         };
 
         let mut buf = vec![];
@@ -335,12 +423,7 @@ fn test_handle_and_sign_proposal() {
         pt.write_all(&buf).unwrap();
 
         // receive response:
-        let mut resp_buf = vec![0u8; 1024];
-        pt.read(&mut resp_buf).unwrap();
-
-        let actual_len = extract_actual_len(&resp_buf).unwrap();
-        let mut resp = vec![0u8; actual_len as usize];
-        resp.copy_from_slice(&mut resp_buf[..(actual_len as usize)]);
+        let resp = pt.read_response();
 
         let p_req = proposal::SignedProposalResponse::decode(resp.as_ref())
             .expect("decoding proposal failed");
@@ -387,28 +470,23 @@ fn test_handle_and_sign_vote() {
                     hash: b"parts_hash0000000000000000000000".to_vec(),
                 }),
             }),
-            validator_address: vec![
-                0xa3, 0xb2, 0xcc, 0xdd, 0x71, 0x86, 0xf1, 0x68, 0x5f, 0x21, 0xf2, 0x48, 0x2a, 0xf4,
-                0xfb, 0x34, 0x46, 0xa8, 0x4b, 0x35,
-            ],
+            validator_address: test_validator_address(),
             validator_index: 56789,
             signature: vec![],
+            extension: vec![],
+            extension_signature: vec![],
         };
 
         let svr = amino_types::vote::SignVoteRequest {
             vote: Some(vote_msg),
+            chain_id: String::new(),
         };
         let mut buf = vec![];
         svr.encode(&mut buf).unwrap();
         pt.write_all(&buf).unwrap();
 
         // receive response:
-        let mut resp_buf = vec![0u8; 1024];
-        pt.read(&mut resp_buf).unwrap();
-
-        let actual_len = extract_actual_len(&resp_buf).unwrap();
-        let mut resp = vec![0u8; actual_len as usize];
-        resp.copy_from_slice(&resp_buf[..actual_len as usize]);
+        let resp = pt.read_response();
 
         let v_resp = vote::SignedVoteResponse::decode(resp.as_ref()).expect("decoding vote failed");
         let mut sign_bytes: Vec<u8> = vec![];
@@ -434,11 +512,197 @@ fn test_handle_and_sign_vote() {
 }
 
 #[test]
-#[should_panic]
-fn test_exceed_max_height() {
-    let chain_id = "test_chain_id";
+fn test_retried_vote() {
     let pub_key = test_ed25519_keypair().public;
 
+    let vote = |hash: &[u8], nanos: i32| amino_types::vote::SignVoteRequest {
+        vote: Some(amino_types::vote::Vote {
+            vote_type: 0x02,
+            height: 12345,
+            round: 2,
+            timestamp: Some(TimeMsg {
+                seconds: 1_518_332_962,
+                nanos,
+            }),
+            block_id: Some(BlockId {
+                hash: hash.to_vec(),
+                parts_header: Some(PartsSetHeader {
+                    total: 1,
+                    hash: b"parts_hash0000000000000000000000".to_vec(),
+                }),
+            }),
+            validator_address: test_validator_address(),
+            validator_index: 56789,
+            signature: vec![],
+            extension: vec![],
+            extension_signature: vec![],
+        }),
+        chain_id: String::new(),
+    };
+
+    ProtocolTester::apply(|mut pt| {
+        let mut send = |svr: &amino_types::vote::SignVoteRequest| {
+            let mut buf = vec![];
+            svr.encode(&mut buf).unwrap();
+            pt.write_all(&buf).unwrap();
+
+            // receive response:
+            let resp = pt.read_response();
+            vote::SignedVoteResponse::decode(resp.as_ref()).expect("decoding vote failed")
+        };
+
+        let original = vote(b"some hash00000000000000000000000", 765_000_000);
+        let signed = send(&original).vote.expect("vote wasn't signed");
+
+        let mut sign_bytes = vec![];
+        original
+            .sign_bytes(
+                "test_chain_id".parse().unwrap(),
+                ProtocolVersion::Legacy,
+                &mut sign_bytes,
+            )
+            .unwrap();
+        let signature = ed25519::Signature::try_from(signed.signature.as_slice()).unwrap();
+        assert!(pub_key.verify(&sign_bytes, &signature).is_ok());
+
+        // Retrying the same payload gets the same signature
+        let retried = send(&original).vote.expect("retry wasn't signed");
+        assert_eq!(retried.signature, signed.signature);
+
+        // ...as does one which only differs in its timestamp, which is reset
+        let retried = send(&vote(b"some hash00000000000000000000000", 123_000_000))
+            .vote
+            .expect("retry with a new timestamp wasn't signed");
+        assert_eq!(retried.signature, signed.signature);
+        assert_eq!(retried.timestamp, signed.timestamp);
+
+        // A different block at the same height/round/step is a double sign
+        let err = send(&vote(b"other hash0000000000000000000000", 765_000_000))
+            .err
+            .expect("conflicting vote was signed");
+        assert_eq!(err.code, RemoteErrorCode::DoubleSignError as i32);
+    });
+}
+
+#[test]
+fn test_step_regression() {
+    let timestamp = TimeMsg {
+        seconds: 1_518_332_962,
+        nanos: 765_000_000,
+    };
+
+    let vote = |vote_type: u32| amino_types::vote::SignVoteRequest {
+        vote: Some(amino_types::vote::Vote {
+            vote_type,
+            height: 12345,
+            round: 2,
+            timestamp: Some(timestamp.clone()),
+            block_id: None,
+            validator_address: test_validator_address(),
+            validator_index: 56789,
+            signature: vec![],
+            extension: vec![],
+            extension_signature: vec![],
+        }),
+        chain_id: String::new(),
+    };
+
+    ProtocolTester::apply(|mut pt| {
+        let mut send = |buf: &[u8]| {
+            pt.write_all(buf).unwrap();
+
+            // receive response:
+            pt.read_response()
+        };
+
+        let mut buf = vec![];
+        vote(0x02).encode(&mut buf).unwrap();
+        let resp = vote::SignedVoteResponse::decode(send(&buf).as_ref()).unwrap();
+        assert!(resp.err.is_none());
+
+        // A prevote after a precommit at the same height/round is refused
+        let mut buf = vec![];
+        vote(0x01).encode(&mut buf).unwrap();
+        let resp = vote::SignedVoteResponse::decode(send(&buf).as_ref()).unwrap();
+        assert!(resp.vote.is_none());
+        assert_eq!(
+            resp.err.unwrap().code,
+            RemoteErrorCode::StepRegressionError as i32
+        );
+
+        // ...as is a proposal, without dropping the connection
+        let spr = amino_types::proposal::SignProposalRequest {
+            proposal: Some(amino_types::proposal::Proposal {
+                msg_type: amino_types::SignedMsgType::Proposal.to_u32(),
+                height: 12345,
+                round: 2,
+                pol_round: -1,
+                block_id: None,
+                timestamp: Some(timestamp.clone()),
+                signature: vec![],
+            }),
+            chain_id: String::new(),
+        };
+
+        let mut buf = vec![];
+        spr.encode(&mut buf).unwrap();
+        let resp = proposal::SignedProposalResponse::decode(send(&buf).as_ref()).unwrap();
+        assert!(resp.proposal.is_none());
+        assert_eq!(
+            resp.err.unwrap().code,
+            RemoteErrorCode::StepRegressionError as i32
+        );
+    });
+}
+
+#[test]
+fn test_validator_address_mismatch() {
+    let vote = |validator_address: Vec<u8>| amino_types::vote::SignVoteRequest {
+        vote: Some(amino_types::vote::Vote {
+            vote_type: 0x01,
+            height: 12345,
+            round: 2,
+            timestamp: Some(TimeMsg {
+                seconds: 1_518_332_962,
+                nanos: 765_000_000,
+            }),
+            block_id: None,
+            validator_address,
+            validator_index: 56789,
+            signature: vec![],
+            extension: vec![],
+            extension_signature: vec![],
+        }),
+        chain_id: String::new(),
+    };
+
+    ProtocolTester::apply(|mut pt| {
+        let mut send = |svr: &amino_types::vote::SignVoteRequest| {
+            let mut buf = vec![];
+            svr.encode(&mut buf).unwrap();
+            pt.write_all(&buf).unwrap();
+
+            // receive response:
+            let resp = pt.read_response();
+            vote::SignedVoteResponse::decode(resp.as_ref()).expect("decoding vote failed")
+        };
+
+        // A vote from another validator is refused...
+        let resp = send(&vote(vec![0xa3; 20]));
+        assert!(resp.vote.is_none());
+        let err = resp.err.expect("vote from another validator was signed");
+        assert_eq!(err.code, RemoteErrorCode::ValidatorAddressError as i32);
+        assert!(err.description.contains(&"A3".repeat(20)));
+
+        // ...without dropping the connection or affecting our own votes
+        let resp = send(&vote(test_validator_address()));
+        assert!(resp.err.is_none());
+        assert!(resp.vote.is_some());
+    });
+}
+
+#[test]
+fn test_exceed_max_height() {
     let dt = "2018-02-11T07:09:22.765Z".parse::<DateTime<Utc>>().unwrap();
     let t = TimeMsg {
         seconds: dt.timestamp(),
@@ -458,68 +722,279 @@ fn test_exceed_max_height() {
                     hash: b"parts_hash0000000000000000000000".to_vec(),
                 }),
             }),
-            validator_address: vec![
-                0xa3, 0xb2, 0xcc, 0xdd, 0x71, 0x86, 0xf1, 0x68, 0x5f, 0x21, 0xf2, 0x48, 0x2a, 0xf4,
-                0xfb, 0x34, 0x46, 0xa8, 0x4b, 0x35,
-            ],
+            validator_address: test_validator_address(),
             validator_index: 56789,
             signature: vec![],
+            extension: vec![],
+            extension_signature: vec![],
         };
 
         let svr = amino_types::vote::SignVoteRequest {
             vote: Some(vote_msg),
+            chain_id: String::new(),
         };
         let mut buf = vec![];
         svr.encode(&mut buf).unwrap();
         pt.write_all(&buf).unwrap();
 
         // receive response:
-        let mut resp_buf = vec![0u8; 1024];
-        pt.read(&mut resp_buf).unwrap();
-
-        let actual_len = extract_actual_len(&resp_buf).unwrap();
-        let mut resp = vec![0u8; actual_len as usize];
-        resp.copy_from_slice(&resp_buf[..actual_len as usize]);
+        let resp = pt.read_response();
 
         let v_resp = vote::SignedVoteResponse::decode(resp.as_ref()).expect("decoding vote failed");
-        let mut sign_bytes: Vec<u8> = vec![];
-        svr.sign_bytes(
-            chain_id.parse().unwrap(),
-            ProtocolVersion::Legacy,
-            &mut sign_bytes,
-        )
-        .unwrap();
+        assert!(v_resp.vote.is_none());
 
-        let vote_msg: amino_types::vote::Vote = v_resp
-            .vote
-            .expect("vote should be embedded int the response but none was found");
+        let err = v_resp
+            .err
+            .expect("error should be embedded in the response but none was found");
 
-        let sig: Vec<u8> = vote_msg.signature;
-        assert_ne!(sig.len(), 0);
+        assert_eq!(err.code, RemoteErrorCode::MaxHeightError as i32);
+    });
+}
 
-        let signature = ed25519::Signature::try_from(sig.as_slice()).unwrap();
-        let msg: &[u8] = sign_bytes.as_slice();
+#[test]
+fn test_below_min_height() {
+    ProtocolTester::apply(|mut pt| {
+        let proposal = amino_types::proposal::Proposal {
+            msg_type: amino_types::SignedMsgType::Proposal.to_u32(),
+            height: 99,
+            round: 0,
+            pol_round: -1,
+            block_id: None,
+            timestamp: Some(TimeMsg {
+                seconds: 1_518_332_962,
+                nanos: 765_000_000,
+            }),
+            signature: vec![],
+        };
 
-        assert!(pub_key.verify(msg, &signature).is_ok());
+        let spr = amino_types::proposal::SignProposalRequest {
+            proposal: Some(proposal),
+            chain_id: String::new(),
+        };
+        let mut buf = vec![];
+        spr.encode(&mut buf).unwrap();
+        pt.write_all(&buf).unwrap();
+
+        // receive response:
+        let resp = pt.read_response();
+        let p_resp = proposal::SignedProposalResponse::decode(resp.as_ref())
+            .expect("decoding proposal failed");
+        assert!(p_resp.proposal.is_none());
+
+        let err = p_resp
+            .err
+            .expect("error should be embedded in the response but none was found");
+
+        assert_eq!(err.code, RemoteErrorCode::MinHeightError as i32);
+    });
+}
+
+#[test]
+fn test_rate_limited() {
+    ProtocolTester::apply(|mut pt| {
+        let mut refused = 0;
+
+        // The limit is 5 requests per second, all of which may arrive at once
+        for height in 1000..1030 {
+            let vote_msg = amino_types::vote::Vote {
+                vote_type: 0x01,
+                height,
+                round: 0,
+                timestamp: Some(TimeMsg {
+                    seconds: 1_518_332_962,
+                    nanos: 765_000_000,
+                }),
+                block_id: None,
+                validator_address: test_validator_address(),
+                validator_index: 0,
+                signature: vec![],
+                extension: vec![],
+                extension_signature: vec![],
+            };
+
+            let svr = amino_types::vote::SignVoteRequest {
+                vote: Some(vote_msg),
+                chain_id: String::new(),
+            };
+            let mut buf = vec![];
+            svr.encode(&mut buf).unwrap();
+            pt.write_all(&buf).unwrap();
+
+            // receive response:
+            let resp = pt.read_response();
+            let v_resp =
+                vote::SignedVoteResponse::decode(resp.as_ref()).expect("decoding vote failed");
+
+            match v_resp.err {
+                Some(err) => {
+                    assert!(height >= 1005, "refused within the limit at {}", height);
+                    assert_eq!(err.code, RemoteErrorCode::RateLimitError as i32);
+                    assert!(v_resp.vote.is_none());
+                    refused += 1;
+                }
+                None => assert_ne!(v_resp.vote.unwrap().signature.len(), 0),
+            }
+        }
+
+        assert!(refused > 0, "no requests were rate limited");
     });
 }
 
+#[test]
+fn test_failover_addrs() {
+    let mut rng = rand::thread_rng();
+    let mut socket_path =
+        |name: &str| format!("/tmp/tmkms-{}{:06}.sock", name, rng.gen_range(0, 999999));
+    let (down_path, up_path) = (socket_path("down"), socket_path("up"));
+
+    let mut config_file = NamedTempFile::new().unwrap();
+    writeln!(
+        config_file,
+        r#"
+        [[chain]]
+        id = "test_chain_id"
+        key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+        state_file = "{}"
+
+        [[validator]]
+        addr = ["unix://{}", "unix://{}"]
+        chain_id = "test_chain_id"
+        protocol_version = "legacy"
+
+        [[providers.softsign]]
+        chain_ids = ["test_chain_id"]
+        key_format = "base64"
+        path = "{}"
+    "#,
+        state_file_path(config_file.path()),
+        down_path,
+        up_path,
+        SIGNING_KEY_PATH
+    )
+    .unwrap();
+
+    // Nothing listens on the first address, so the KMS fails over to the
+    // second...
+    let listener = UnixListener::bind(&up_path).unwrap();
+    let args = &["start", "-c", config_file.path().to_str().unwrap()];
+    let mut process = Command::new(KMS_EXE_PATH).args(args).spawn().unwrap();
+
+    let (socket, _) = listener.accept().unwrap();
+    let mut connection = UnixConnection::new(socket.try_clone().unwrap());
+    let mut buf = vec![];
+    PingRequest {}.encode(&mut buf).unwrap();
+    connection.write_all(&buf).unwrap();
+
+    let resp = MsgReader::new()
+        .read_msg(&mut connection, MAX_RESPONSE_SIZE)
+        .unwrap();
+    PingResponse::decode(resp.as_ref()).expect("decoding ping response failed");
+
+    // ...and back to it (by way of the first) once the connection is lost
+    drop(connection);
+    drop(socket);
+    listener.accept().unwrap();
+
+    process.kill().unwrap();
+    process.wait().unwrap();
+    fs::remove_file(&up_path).unwrap();
+    remove_state_files(&state_file_path(config_file.path()));
+}
+
+#[test]
+fn test_labelled_validators() {
+    let mut rng = rand::thread_rng();
+    let mut socket_path =
+        |name: &str| format!("/tmp/tmkms-{}{:06}.sock", name, rng.gen_range(0, 999999));
+    let (a_path, b_path) = (socket_path("a"), socket_path("b"));
+
+    let mut config_file = NamedTempFile::new().unwrap();
+    let state_file = |label: &str| format!("{}_{}", state_file_path(config_file.path()), label);
+    let (a_state_file, b_state_file) = (state_file("a"), state_file("b"));
+
+    writeln!(
+        config_file,
+        r#"
+        [[chain]]
+        id = "test_chain_id"
+        key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+
+        [[validator]]
+        addr = "unix://{}"
+        chain_id = "test_chain_id"
+        protocol_version = "legacy"
+        label = "a"
+        state_file = "{}"
+
+        [[validator]]
+        addr = "unix://{}"
+        chain_id = "test_chain_id"
+        protocol_version = "legacy"
+        label = "b"
+        state_file = "{}"
+
+        [[providers.softsign]]
+        chain_ids = ["test_chain_id"]
+        key_format = "base64"
+        path = "{}"
+        validator = "a"
+
+        [[providers.softsign]]
+        chain_ids = ["test_chain_id"]
+        key_format = "base64"
+        path = "tests/support/secret_connection.key"
+        validator = "b"
+    "#,
+        a_path, a_state_file, b_path, b_state_file, SIGNING_KEY_PATH
+    )
+    .unwrap();
+
+    let (a_listener, b_listener) = (
+        UnixListener::bind(&a_path).unwrap(),
+        UnixListener::bind(&b_path).unwrap(),
+    );
+    let args = &["start", "-c", config_file.path().to_str().unwrap()];
+    let mut process = Command::new(KMS_EXE_PATH).args(args).spawn().unwrap();
+
+    // Each validator is answered with its own key
+    let mut pub_key = |listener: &UnixListener| {
+        let (socket, _) = listener.accept().unwrap();
+        let mut connection = UnixConnection::new(socket);
+        let mut buf = vec![];
+        PubKeyRequest::default().encode(&mut buf).unwrap();
+        connection.write_all(&buf).unwrap();
+
+        let resp = MsgReader::new()
+            .read_msg(&mut connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+        PubKeyResponse::decode(resp.as_ref())
+            .expect("decoding public key failed")
+            .pub_key_ed25519
+    };
+
+    let (a_key, b_key) = (pub_key(&a_listener), pub_key(&b_listener));
+    assert_eq!(a_key, test_ed25519_keypair().public.to_bytes());
+    assert_ne!(a_key, b_key);
+
+    process.kill().unwrap();
+    process.wait().unwrap();
+    fs::remove_file(&a_path).unwrap();
+    fs::remove_file(&b_path).unwrap();
+    remove_state_files(&a_state_file);
+    remove_state_files(&b_state_file);
+}
+
 #[test]
 fn test_handle_and_sign_get_publickey() {
     ProtocolTester::apply(|mut pt| {
         let mut buf = vec![];
 
-        PubKeyRequest {}.encode(&mut buf).unwrap();
+        PubKeyRequest::default().encode(&mut buf).unwrap();
 
         pt.write_all(&buf).unwrap();
 
         // receive response:
-        let mut resp_buf = vec![0u8; 1024];
-        pt.read(&mut resp_buf).unwrap();
-
-        let actual_len = extract_actual_len(&resp_buf).unwrap();
-        let mut resp = vec![0u8; actual_len as usize];
-        resp.copy_from_slice(&resp_buf[..actual_len as usize]);
+        let resp = pt.read_response();
 
         let pk_resp = PubKeyResponse::decode(resp.as_ref()).expect("decoding public key failed");
         assert_ne!(pk_resp.pub_key_ed25519.len(), 0);
@@ -534,12 +1009,3542 @@ fn test_handle_and_sign_ping_pong() {
         pt.write_all(&buf).unwrap();
 
         // receive response:
-        let mut resp_buf = vec![0u8; 1024];
-        pt.read(&mut resp_buf).unwrap();
-
-        let actual_len = extract_actual_len(&resp_buf).unwrap();
-        let mut resp = vec![0u8; actual_len as usize];
-        resp.copy_from_slice(&resp_buf[..actual_len as usize]);
+        let resp = pt.read_response();
         PingResponse::decode(resp.as_ref()).expect("decoding ping response failed");
     });
 }
+
+#[test]
+fn test_handle_request_split_across_writes() {
+    ProtocolTester::apply(|mut pt| {
+        let mut buf = vec![];
+        PubKeyRequest::default().encode(&mut buf).unwrap();
+
+        // Each write is sent in its own Secret Connection frame
+        for byte in buf.chunks(1) {
+            pt.write_all(byte).unwrap();
+            pt.flush().unwrap();
+        }
+
+        let resp = pt.read_response();
+        let pk_resp = PubKeyResponse::decode(resp.as_ref()).expect("decoding public key failed");
+        assert_ne!(pk_resp.pub_key_ed25519.len(), 0);
+    });
+}
+
+#[test]
+fn test_handle_request_larger_than_frame() {
+    ProtocolTester::apply(|mut pt| {
+        let mut buf = vec![];
+        amino_types::vote::SignVoteRequest {
+            vote: Some(amino_types::vote::Vote {
+                vote_type: 0x01,
+                height: 12345,
+                round: 2,
+                timestamp: Some(TimeMsg {
+                    seconds: 1_518_332_962,
+                    nanos: 765_000_000,
+                }),
+                block_id: None,
+                validator_address: vec![0xa3; 3000],
+                validator_index: 56789,
+                signature: vec![],
+                extension: vec![],
+                extension_signature: vec![],
+            }),
+            chain_id: String::new(),
+        }
+        .encode(&mut buf)
+        .unwrap();
+        assert!(buf.len() > DATA_MAX_SIZE);
+
+        rpc::write_msg(&mut pt, &buf).unwrap();
+
+        // The whole request is read and decoded (and refused, as no validator
+        // has such an address)
+        let resp = pt.read_response();
+        let v_resp = vote::SignedVoteResponse::decode(resp.as_ref()).expect("decoding vote failed");
+        assert!(v_resp.vote.is_none());
+        assert!(v_resp.err.is_some());
+    });
+}
+
+#[test]
+fn test_handle_requests_sharing_a_write() {
+    ProtocolTester::apply(|mut pt| {
+        let mut buf = vec![];
+        PingRequest {}.encode(&mut buf).unwrap();
+        PubKeyRequest::default().encode(&mut buf).unwrap();
+        PingRequest {}.encode(&mut buf).unwrap();
+
+        // All three requests are sent in a single Secret Connection frame
+        pt.write_all(&buf).unwrap();
+
+        PingResponse::decode(pt.read_response().as_ref()).expect("decoding ping response failed");
+        PubKeyResponse::decode(pt.read_response().as_ref()).expect("decoding public key failed");
+        PingResponse::decode(pt.read_response().as_ref()).expect("decoding ping response failed");
+    });
+}
+
+#[test]
+fn test_handle_and_sign_vote_extension() {
+    use prost::Message as _;
+    use tmkms::rpc::v0_38;
+
+    let chain_id = "test_chain_id";
+    let pub_key = test_ed25519_keypair().public;
+
+    let dt = "2018-02-11T07:09:22.765Z".parse::<DateTime<Utc>>().unwrap();
+    let t = TimeMsg {
+        seconds: dt.timestamp(),
+        nanos: dt.timestamp_subsec_nanos() as i32,
+    };
+
+    let vote = amino_types::vote::Vote {
+        vote_type: SignedMsgType::PreCommit.to_u32(),
+        height: 12345,
+        round: 2,
+        timestamp: Some(t),
+        block_id: Some(BlockId {
+            hash: b"some hash00000000000000000000000".to_vec(),
+            parts_header: Some(PartsSetHeader {
+                total: 1000000,
+                hash: b"parts_hash0000000000000000000000".to_vec(),
+            }),
+        }),
+        validator_address: test_validator_address(),
+        validator_index: 56789,
+        signature: vec![],
+        extension: b"vote extension".to_vec(),
+        extension_signature: vec![],
+    };
+
+    let mut device = KmsProcess::create_unix_with_protocol_version("v0.38");
+    let mut connection = device.create_connection();
+
+    let request = v0_38::Message {
+        sign_vote_request: Some(v0_38::SignVoteRequest {
+            vote: Some(vote.clone().into()),
+            chain_id: chain_id.to_owned(),
+        }),
+        signed_vote_response: None,
+    };
+
+    let mut buf = vec![];
+    request.encode_length_delimited(&mut buf).unwrap();
+    connection.write_all(&buf).unwrap();
+
+    // receive response:
+    let resp = MsgReader::new()
+        .read_msg(&mut connection, MAX_RESPONSE_SIZE)
+        .unwrap();
+
+    let response = v0_38::Message::decode_length_delimited(resp.as_ref())
+        .expect("decoding vote failed")
+        .signed_vote_response
+        .expect("signed vote response should be embedded but none was found");
+
+    device.process.kill().unwrap();
+    remove_state_files(&device.state_file);
+
+    let signed_vote = response
+        .vote
+        .expect("vote should be embedded in the response");
+    assert_eq!(signed_vote.extension, vote.extension);
+
+    let svr = amino_types::vote::SignVoteRequest {
+        vote: Some(vote),
+        chain_id: String::new(),
+    };
+
+    let mut sign_bytes = vec![];
+    svr.sign_bytes(
+        chain_id.parse().unwrap(),
+        ProtocolVersion::V0_38,
+        &mut sign_bytes,
+    )
+    .unwrap();
+
+    let signature = ed25519::Signature::try_from(signed_vote.signature.as_slice()).unwrap();
+    assert!(pub_key.verify(&sign_bytes, &signature).is_ok());
+
+    let mut extension_sign_bytes = vec![];
+    assert!(svr
+        .extension_sign_bytes(
+            chain_id.parse().unwrap(),
+            ProtocolVersion::V0_38,
+            &mut extension_sign_bytes,
+        )
+        .unwrap());
+
+    let extension_signature =
+        ed25519::Signature::try_from(signed_vote.extension_signature.as_slice()).unwrap();
+    assert!(pub_key
+        .verify(&extension_sign_bytes, &extension_signature)
+        .is_ok());
+}
+
+#[test]
+fn test_unsupported_requests_keep_connection() {
+    use prost::Message as _;
+    use std::{thread, time::Duration};
+    use tendermint_proto as proto;
+
+    /// `cometbft.privval.v1.SignBytesResponse`
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct SignBytesResponse {
+        #[prost(bytes = "vec", tag = "1")]
+        signature: Vec<u8>,
+        #[prost(message, optional, tag = "2")]
+        error: Option<proto::privval::RemoteSignerError>,
+    }
+
+    let mut device = KmsProcess::create_unix_with_protocol_version("v0.38");
+    let mut connection = device.create_connection();
+
+    // `SignBytesRequest` (field 9) with `value` (field 1) set
+    let sign_bytes_request = [0x4a, 0x05, 0x0a, 0x03, b'a', b'b', b'c'];
+    let mut buf = vec![];
+    prost::encoding::encode_varint(sign_bytes_request.len() as u64, &mut buf);
+    buf.extend_from_slice(&sign_bytes_request);
+    connection.write_all(&buf).unwrap();
+
+    let resp_buf = MsgReader::new()
+        .read_msg(&mut connection, MAX_RESPONSE_SIZE)
+        .unwrap();
+    let mut resp = resp_buf.as_slice();
+    prost::decode_length_delimiter(&mut resp).unwrap();
+    let (tag, _) = prost::encoding::decode_key(&mut resp).unwrap();
+    assert_eq!(tag, 10);
+
+    let err = SignBytesResponse::decode_length_delimited(resp)
+        .unwrap()
+        .error
+        .unwrap();
+    assert_eq!(err.code, RemoteErrorCode::UnsupportedRequestError as i32);
+    assert_eq!(
+        err.description,
+        "unsupported message: /cometbft.privval.v1.SignBytesRequest"
+    );
+
+    // Messages of unknown types get no response, but the connection is kept
+    let unknown = [0x03, 0xfa, 0x01, 0x00];
+    connection.write_all(&unknown).unwrap();
+
+    // Give the KMS a chance to read the message on its own: data following
+    // a message in the same read is rejected
+    thread::sleep(Duration::from_millis(200));
+
+    let ping = proto::privval::Message {
+        sum: Some(proto::privval::message::Sum::PingRequest(
+            proto::privval::PingRequest {},
+        )),
+    };
+    connection
+        .write_all(&ping.encode_length_delimited_to_vec())
+        .unwrap();
+
+    let resp = MsgReader::new()
+        .read_msg(&mut connection, MAX_RESPONSE_SIZE)
+        .unwrap();
+    let response = proto::privval::Message::decode_length_delimited(resp.as_ref())
+        .unwrap()
+        .sum;
+
+    device.process.kill().unwrap();
+    remove_state_files(&device.state_file);
+
+    assert!(matches!(
+        response,
+        Some(proto::privval::message::Sum::PingResponse(_))
+    ));
+}
+
+/// Integration tests for secp256k1 consensus keys
+mod secp256k1 {
+    use super::*;
+    use k256::ecdsa;
+    use prost::Message as _;
+    use tendermint_proto as proto;
+
+    /// Get the secp256k1 consensus public key used by the tests
+    fn test_secp256k1_verifying_key() -> ecdsa::VerifyingKey {
+        let key_bytes = tmkms::key_utils::load_base64_secret(SECP256K1_SIGNING_KEY_PATH).unwrap();
+        ecdsa::SigningKey::from_bytes(&key_bytes)
+            .unwrap()
+            .verifying_key()
+    }
+
+    /// Get the address of the validator whose consensus key is the test
+    /// secp256k1 key
+    fn test_secp256k1_validator_address() -> Vec<u8> {
+        let public_key =
+            tendermint::PublicKey::from_raw_secp256k1(&test_secp256k1_verifying_key().to_bytes())
+                .unwrap();
+        tendermint::account::Id::from(public_key)
+            .as_bytes()
+            .to_vec()
+    }
+
+    /// Send a request to a KMS using the test secp256k1 consensus key (which
+    /// requires a Protobuf-based protocol version) and return its response
+    fn send_request(request: proto::privval::message::Sum) -> proto::privval::message::Sum {
+        let mut device =
+            KmsProcess::create_unix_with_key("v0.34", SECP256K1_SIGNING_KEY_PATH, "secp256k1");
+        let mut connection = device.create_connection();
+
+        let mut buf = vec![];
+        proto::privval::Message { sum: Some(request) }
+            .encode_length_delimited(&mut buf)
+            .unwrap();
+        connection.write_all(&buf).unwrap();
+
+        // receive response:
+        let resp = MsgReader::new()
+            .read_msg(&mut connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+
+        device.process.kill().unwrap();
+        remove_state_files(&device.state_file);
+
+        proto::privval::Message::decode_length_delimited(resp.as_ref())
+            .expect("decoding response failed")
+            .sum
+            .expect("response should be embedded but none was found")
+    }
+
+    /// Verify a 64-byte `r || s` signature is low-s normalized and valid
+    fn verify_signature(sign_bytes: &[u8], signature: &[u8]) {
+        assert_eq!(signature.len(), 64);
+
+        let signature = ecdsa::Signature::try_from(signature).unwrap();
+        assert!(signature.normalize_s().is_none());
+        assert!(test_secp256k1_verifying_key()
+            .verify(sign_bytes, &signature)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_handle_and_sign_proposal() {
+        let chain_id = "test_chain_id";
+
+        let dt = "2018-02-11T07:09:22.765Z".parse::<DateTime<Utc>>().unwrap();
+        let t = TimeMsg {
+            seconds: dt.timestamp(),
+            nanos: dt.timestamp_subsec_nanos() as i32,
+        };
+
+        let spr = amino_types::proposal::SignProposalRequest {
+            proposal: Some(amino_types::proposal::Proposal {
+                msg_type: amino_types::SignedMsgType::Proposal.to_u32(),
+                height: 12345,
+                round: 1,
+                timestamp: Some(t.clone()),
+                pol_round: -1,
+                block_id: None,
+                signature: vec![],
+            }),
+            chain_id: chain_id.to_owned(),
+        };
+
+        let request = proto::privval::SignProposalRequest {
+            proposal: Some(proto::types::Proposal {
+                r#type: amino_types::SignedMsgType::Proposal.to_u32() as i32,
+                height: 12345,
+                round: 1,
+                pol_round: -1,
+                block_id: None,
+                timestamp: Some(t.into()),
+                signature: vec![],
+            }),
+            chain_id: chain_id.to_owned(),
+        };
+
+        let response =
+            match send_request(proto::privval::message::Sum::SignProposalRequest(request)) {
+                proto::privval::message::Sum::SignedProposalResponse(response) => response,
+                other => panic!("unexpected response: {:?}", other),
+            };
+
+        assert!(response.error.is_none());
+
+        let mut sign_bytes: Vec<u8> = vec![];
+        spr.sign_bytes(
+            chain_id.parse().unwrap(),
+            ProtocolVersion::V0_34,
+            &mut sign_bytes,
+        )
+        .unwrap();
+
+        let prop = response
+            .proposal
+            .expect("proposal should be embedded but none was found");
+
+        verify_signature(&sign_bytes, &prop.signature);
+    }
+
+    #[test]
+    fn test_handle_and_sign_vote() {
+        let chain_id = "test_chain_id";
+
+        let dt = "2018-02-11T07:09:22.765Z".parse::<DateTime<Utc>>().unwrap();
+        let t = TimeMsg {
+            seconds: dt.timestamp(),
+            nanos: dt.timestamp_subsec_nanos() as i32,
+        };
+
+        let block_id = BlockId {
+            hash: b"some hash00000000000000000000000".to_vec(),
+            parts_header: Some(PartsSetHeader {
+                total: 1000000,
+                hash: b"parts_hash0000000000000000000000".to_vec(),
+            }),
+        };
+
+        let validator_address = test_secp256k1_validator_address();
+
+        let svr = amino_types::vote::SignVoteRequest {
+            vote: Some(amino_types::vote::Vote {
+                vote_type: 0x01,
+                height: 12345,
+                round: 2,
+                timestamp: Some(t.clone()),
+                block_id: Some(block_id.clone()),
+                validator_address: validator_address.clone(),
+                validator_index: 56789,
+                signature: vec![],
+                extension: vec![],
+                extension_signature: vec![],
+            }),
+            chain_id: chain_id.to_owned(),
+        };
+
+        let request = proto::privval::SignVoteRequest {
+            vote: Some(proto::types::Vote {
+                r#type: 0x01,
+                height: 12345,
+                round: 2,
+                block_id: Some(block_id.into()),
+                timestamp: Some(t.into()),
+                validator_address,
+                validator_index: 56789,
+                signature: vec![],
+            }),
+            chain_id: chain_id.to_owned(),
+        };
+
+        let response = match send_request(proto::privval::message::Sum::SignVoteRequest(request)) {
+            proto::privval::message::Sum::SignedVoteResponse(response) => response,
+            other => panic!("unexpected response: {:?}", other),
+        };
+
+        assert!(response.error.is_none());
+
+        let mut sign_bytes: Vec<u8> = vec![];
+        svr.sign_bytes(
+            chain_id.parse().unwrap(),
+            ProtocolVersion::V0_34,
+            &mut sign_bytes,
+        )
+        .unwrap();
+
+        let vote = response
+            .vote
+            .expect("vote should be embedded in the response but none was found");
+
+        verify_signature(&sign_bytes, &vote.signature);
+    }
+
+    #[test]
+    fn test_handle_and_sign_get_publickey() {
+        let response = match send_request(proto::privval::message::Sum::PubKeyRequest(
+            proto::privval::PubKeyRequest {
+                chain_id: "test_chain_id".to_owned(),
+            },
+        )) {
+            proto::privval::message::Sum::PubKeyResponse(response) => response,
+            other => panic!("unexpected response: {:?}", other),
+        };
+
+        match response.pub_key.and_then(|pk| pk.sum) {
+            Some(proto::crypto::public_key::Sum::Secp256k1(pk)) => {
+                assert_eq!(pk.len(), 33);
+                assert_eq!(
+                    pk.as_slice(),
+                    test_secp256k1_verifying_key().to_bytes().as_slice()
+                );
+            }
+            other => panic!("unexpected public key: {:?}", other),
+        }
+    }
+}
+
+/// Integration tests for the gRPC `PrivValidatorAPI` listener
+#[cfg(feature = "grpc")]
+mod grpc {
+    use super::*;
+    use std::{path::Path, thread, time::Duration};
+    use tendermint_proto as proto;
+    use tmkms::connection::grpc::PrivValidatorApiClient;
+
+    /// Spawns a KMS process serving gRPC, then drives requests against it
+    struct GrpcTester {
+        /// KMS child process
+        process: Child,
+
+        /// Tokio runtime used to drive the gRPC client
+        runtime: tokio::runtime::Runtime,
+
+        /// gRPC client connected to the KMS
+        client: PrivValidatorApiClient,
+
+        /// Path to the KMS's state file
+        state_file: String,
+    }
+
+    impl GrpcTester {
+        pub fn apply<F>(functor: F)
+        where
+            F: FnOnce(GrpcTester),
+        {
+            // Generate a random port and a config file
+            let port: u16 = rand::thread_rng().gen_range(60000, 65535);
+            let config = GrpcTester::create_config(port);
+
+            let args = &["start", "-c", config.path().to_str().unwrap()];
+            let process = Command::new(KMS_EXE_PATH).args(args).spawn().unwrap();
+
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+
+            // Wait for the KMS to start listening
+            let uri = format!("http://127.0.0.1:{}", port);
+            let client = runtime.block_on(async {
+                for _ in 0..50 {
+                    if let Ok(client) = PrivValidatorApiClient::connect(uri.clone()).await {
+                        return client;
+                    }
+
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+
+                panic!("couldn't connect to KMS gRPC listener at {}", uri);
+            });
+
+            functor(Self {
+                process,
+                runtime,
+                client,
+                state_file: state_file_path(config.path()),
+            });
+        }
+
+        /// Create a config file for a gRPC KMS and return its path
+        fn create_config(port: u16) -> NamedTempFile {
+            let mut config_file = NamedTempFile::new().unwrap();
+            writeln!(
+                config_file,
+                r#"
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+
+                [[validator]]
+                addr = "grpc://127.0.0.1:{}"
+                chain_id = "test_chain_id"
+                max_height = "500000"
+                protocol_version = "grpc"
+
+                [[providers.softsign]]
+                chain_ids = ["test_chain_id"]
+                key_format = "base64"
+                path = "{}"
+            "#,
+                state_file_path(config_file.path()),
+                port,
+                SIGNING_KEY_PATH
+            )
+            .unwrap();
+
+            config_file
+        }
+    }
+
+    impl Drop for GrpcTester {
+        fn drop(&mut self) {
+            self.process.kill().unwrap();
+
+            // Give the KMS a moment to exit before removing its state file
+            thread::sleep(Duration::from_millis(100));
+
+            remove_state_files(&self.state_file);
+        }
+    }
+
+    /// Write a CA, and a server and a client certificate for `localhost`
+    /// issued by it, to the given directory
+    fn write_test_pki(dir: &Path) {
+        let mut params = rcgen::CertificateParams::new(vec![]);
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(params).unwrap();
+        fs::write(dir.join("ca.pem"), ca.serialize_pem().unwrap()).unwrap();
+
+        for (name, purpose) in &[
+            ("server", rcgen::ExtendedKeyUsagePurpose::ServerAuth),
+            ("client", rcgen::ExtendedKeyUsagePurpose::ClientAuth),
+        ] {
+            let mut params = rcgen::CertificateParams::new(vec!["localhost".to_owned()]);
+            params.extended_key_usages = vec![purpose.clone()];
+            let cert = rcgen::Certificate::from_params(params).unwrap();
+
+            fs::write(
+                dir.join(format!("{}.pem", name)),
+                cert.serialize_pem_with_signer(&ca).unwrap(),
+            )
+            .unwrap();
+            fs::write(
+                dir.join(format!("{}.key", name)),
+                cert.serialize_private_key_pem(),
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_grpc_mutual_tls_rejects_unauthenticated_client() {
+        use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+
+        let dir = tempfile::tempdir().unwrap();
+        write_test_pki(dir.path());
+
+        let port: u16 = rand::thread_rng().gen_range(60000, 65535);
+        let config_path = dir.path().join("tmkms.toml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+
+                [[validator]]
+                addr = "grpc://localhost:{}"
+                chain_id = "test_chain_id"
+                protocol_version = "grpc"
+                tls_ca = "{}"
+                tls_client_cert = "{}"
+                tls_client_key = "{}"
+
+                [[providers.softsign]]
+                chain_ids = ["test_chain_id"]
+                key_format = "base64"
+                path = "{}"
+                "#,
+                dir.path().join("state.json").display(),
+                port,
+                dir.path().join("ca.pem").display(),
+                dir.path().join("server.pem").display(),
+                dir.path().join("server.key").display(),
+                SIGNING_KEY_PATH
+            ),
+        )
+        .unwrap();
+
+        let mut process = Command::new(KMS_EXE_PATH)
+            .args(["start", "-c", config_path.to_str().unwrap()])
+            .spawn()
+            .unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let ca = Certificate::from_pem(fs::read(dir.path().join("ca.pem")).unwrap());
+        let identity = Identity::from_pem(
+            fs::read(dir.path().join("client.pem")).unwrap(),
+            fs::read(dir.path().join("client.key")).unwrap(),
+        );
+        let uri = format!("https://localhost:{}", port);
+        let request = || proto::privval::PubKeyRequest {
+            chain_id: "test_chain_id".to_owned(),
+        };
+
+        runtime.block_on(async {
+            // A client presenting a certificate issued by `tls_ca` is served
+            let tls_config = ClientTlsConfig::new()
+                .ca_certificate(ca.clone())
+                .identity(identity);
+            let mut served = false;
+
+            for _ in 0..50 {
+                if let Ok(mut client) =
+                    PrivValidatorApiClient::connect_tls(uri.clone(), tls_config.clone()).await
+                {
+                    assert!(client
+                        .get_pub_key(request())
+                        .await
+                        .unwrap()
+                        .pub_key
+                        .is_some());
+                    served = true;
+                    break;
+                }
+
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+
+            assert!(served, "couldn't connect to KMS gRPC listener at {}", uri);
+
+            // A client without a certificate is refused
+            let tls_config = ClientTlsConfig::new().ca_certificate(ca);
+            let result = match PrivValidatorApiClient::connect_tls(uri.clone(), tls_config).await {
+                Ok(mut client) => client.get_pub_key(request()).await.map(drop),
+                Err(e) => Err(tonic::Status::unavailable(e.to_string())),
+            };
+            assert!(result.is_err(), "unauthenticated TLS client was served");
+
+            // So is a client which doesn't speak TLS at all
+            let plaintext_uri = format!("http://localhost:{}", port);
+            let result = match PrivValidatorApiClient::connect(plaintext_uri).await {
+                Ok(mut client) => client.get_pub_key(request()).await.map(drop),
+                Err(e) => Err(tonic::Status::unavailable(e.to_string())),
+            };
+            assert!(result.is_err(), "plaintext client was served");
+        });
+
+        process.kill().unwrap();
+        process.wait().unwrap();
+    }
+
+    #[test]
+    fn test_grpc_sign_proposal() {
+        let chain_id = "test_chain_id";
+        let pub_key = test_ed25519_keypair().public;
+
+        let dt = "2018-02-11T07:09:22.765Z".parse::<DateTime<Utc>>().unwrap();
+        let t = TimeMsg {
+            seconds: dt.timestamp(),
+            nanos: dt.timestamp_subsec_nanos() as i32,
+        };
+
+        GrpcTester::apply(|mut gt| {
+            let spr = amino_types::proposal::SignProposalRequest {
+                proposal: Some(amino_types::proposal::Proposal {
+                    msg_type: amino_types::SignedMsgType::Proposal.to_u32(),
+                    height: 12345,
+                    round: 1,
+                    timestamp: Some(t.clone()),
+                    pol_round: -1,
+                    block_id: None,
+                    signature: vec![],
+                }),
+                chain_id: chain_id.to_owned(),
+            };
+
+            let request = proto::privval::SignProposalRequest {
+                proposal: Some(proto::types::Proposal {
+                    r#type: amino_types::SignedMsgType::Proposal.to_u32() as i32,
+                    height: 12345,
+                    round: 1,
+                    pol_round: -1,
+                    block_id: None,
+                    timestamp: Some(t.into()),
+                    signature: vec![],
+                }),
+                chain_id: chain_id.to_owned(),
+            };
+
+            let response = gt
+                .runtime
+                .block_on(gt.client.sign_proposal(request))
+                .unwrap();
+
+            assert!(response.error.is_none());
+
+            let mut sign_bytes: Vec<u8> = vec![];
+            spr.sign_bytes(
+                chain_id.parse().unwrap(),
+                ProtocolVersion::Grpc,
+                &mut sign_bytes,
+            )
+            .unwrap();
+
+            let prop = response
+                .proposal
+                .expect("proposal should be embedded but none was found");
+
+            let signature = ed25519::Signature::try_from(prop.signature.as_slice()).unwrap();
+            assert!(pub_key.verify(&sign_bytes, &signature).is_ok());
+        });
+    }
+
+    #[test]
+    fn test_grpc_sign_vote() {
+        let chain_id = "test_chain_id";
+        let pub_key = test_ed25519_keypair().public;
+
+        let dt = "2018-02-11T07:09:22.765Z".parse::<DateTime<Utc>>().unwrap();
+        let t = TimeMsg {
+            seconds: dt.timestamp(),
+            nanos: dt.timestamp_subsec_nanos() as i32,
+        };
+
+        let block_id = BlockId {
+            hash: b"some hash00000000000000000000000".to_vec(),
+            parts_header: Some(PartsSetHeader {
+                total: 1000000,
+                hash: b"parts_hash0000000000000000000000".to_vec(),
+            }),
+        };
+
+        let validator_address = test_validator_address();
+
+        GrpcTester::apply(|mut gt| {
+            let svr = amino_types::vote::SignVoteRequest {
+                vote: Some(amino_types::vote::Vote {
+                    vote_type: 0x01,
+                    height: 12345,
+                    round: 2,
+                    timestamp: Some(t.clone()),
+                    block_id: Some(block_id.clone()),
+                    validator_address: validator_address.clone(),
+                    validator_index: 56789,
+                    signature: vec![],
+                    extension: vec![],
+                    extension_signature: vec![],
+                }),
+                chain_id: chain_id.to_owned(),
+            };
+
+            let request = proto::privval::SignVoteRequest {
+                vote: Some(proto::types::Vote {
+                    r#type: 0x01,
+                    height: 12345,
+                    round: 2,
+                    block_id: Some(block_id.into()),
+                    timestamp: Some(t.into()),
+                    validator_address,
+                    validator_index: 56789,
+                    signature: vec![],
+                }),
+                chain_id: chain_id.to_owned(),
+            };
+
+            let response = gt.runtime.block_on(gt.client.sign_vote(request)).unwrap();
+            assert!(response.error.is_none());
+
+            let mut sign_bytes: Vec<u8> = vec![];
+            svr.sign_bytes(
+                chain_id.parse().unwrap(),
+                ProtocolVersion::Grpc,
+                &mut sign_bytes,
+            )
+            .unwrap();
+
+            let vote = response
+                .vote
+                .expect("vote should be embedded in the response but none was found");
+
+            assert_ne!(vote.signature.len(), 0);
+
+            let signature = ed25519::Signature::try_from(vote.signature.as_slice()).unwrap();
+            assert!(pub_key.verify(&sign_bytes, &signature).is_ok());
+        });
+    }
+
+    #[test]
+    fn test_grpc_chain_id_mismatch() {
+        GrpcTester::apply(|mut gt| {
+            let request = proto::privval::SignProposalRequest {
+                proposal: Some(proto::types::Proposal {
+                    r#type: amino_types::SignedMsgType::Proposal.to_u32() as i32,
+                    height: 12345,
+                    round: 1,
+                    pol_round: -1,
+                    block_id: None,
+                    timestamp: None,
+                    signature: vec![],
+                }),
+                chain_id: "other_chain_id".to_owned(),
+            };
+
+            let response = gt
+                .runtime
+                .block_on(gt.client.sign_proposal(request))
+                .unwrap();
+
+            assert!(response.proposal.is_none());
+
+            let err = response
+                .error
+                .expect("error should be embedded in the response but none was found");
+
+            assert_eq!(err.code, RemoteErrorCode::ChainIdError as i32);
+        });
+    }
+
+    #[test]
+    fn test_grpc_get_publickey() {
+        GrpcTester::apply(|mut gt| {
+            let request = proto::privval::PubKeyRequest {
+                chain_id: "test_chain_id".to_owned(),
+            };
+
+            let response = gt.runtime.block_on(gt.client.get_pub_key(request)).unwrap();
+
+            match response.pub_key.and_then(|pk| pk.sum) {
+                Some(proto::crypto::public_key::Sum::Ed25519(pk)) => {
+                    assert_eq!(pk, test_ed25519_keypair().public.as_bytes())
+                }
+                other => panic!("unexpected public key: {:?}", other),
+            }
+        });
+    }
+}
+
+/// Integration tests for `tcp-listen://` and `unix-listen://` addresses, where
+/// the test harness (acting as the validator) dials into the KMS
+#[cfg(feature = "tls")]
+mod tls {
+    use super::*;
+    use rustls::{
+        server::AllowAnyAuthenticatedClient, Certificate, PrivateKey, RootCertStore, ServerConfig,
+        ServerConnection, StreamOwned,
+    };
+    use std::{path::Path, process::Stdio, sync::Arc, thread, time::Duration};
+
+    /// Certificates for a TLS validator and the KMS, issued by a test CA
+    struct TestPki {
+        /// CA certificate
+        ca: rcgen::Certificate,
+
+        /// Directory the CA and KMS client certificate and key are written to
+        dir: tempfile::TempDir,
+    }
+
+    impl TestPki {
+        fn new() -> Self {
+            let mut params = rcgen::CertificateParams::new(vec![]);
+            params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+            let ca = rcgen::Certificate::from_params(params).unwrap();
+
+            let dir = tempfile::tempdir().unwrap();
+            fs::write(dir.path().join("ca.pem"), ca.serialize_pem().unwrap()).unwrap();
+
+            let client = Self::issue_cert("tmkms", rcgen::ExtendedKeyUsagePurpose::ClientAuth);
+            fs::write(
+                dir.path().join("client.pem"),
+                client.serialize_pem_with_signer(&ca).unwrap(),
+            )
+            .unwrap();
+            fs::write(
+                dir.path().join("client.key"),
+                client.serialize_private_key_pem(),
+            )
+            .unwrap();
+
+            Self { ca, dir }
+        }
+
+        /// Generate a certificate for the given name (to be signed by the CA)
+        fn issue_cert(name: &str, purpose: rcgen::ExtendedKeyUsagePurpose) -> rcgen::Certificate {
+            let mut params = rcgen::CertificateParams::new(vec![name.to_owned()]);
+            params.extended_key_usages = vec![purpose];
+            rcgen::Certificate::from_params(params).unwrap()
+        }
+
+        /// Validator (server) TLS configuration with a certificate issued
+        /// for the given name, requiring a client certificate from the CA
+        fn server_config(&self, name: &str) -> Arc<ServerConfig> {
+            let cert = Self::issue_cert(name, rcgen::ExtendedKeyUsagePurpose::ServerAuth);
+
+            let mut roots = RootCertStore::empty();
+            roots
+                .add(&Certificate(self.ca.serialize_der().unwrap()))
+                .unwrap();
+
+            let config = ServerConfig::builder()
+                .with_safe_defaults()
+                .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+                .with_single_cert(
+                    vec![Certificate(
+                        cert.serialize_der_with_signer(&self.ca).unwrap(),
+                    )],
+                    PrivateKey(cert.serialize_private_key_der()),
+                )
+                .unwrap();
+
+            Arc::new(config)
+        }
+
+        fn path(&self, file: &str) -> String {
+            self.dir.path().join(file).to_str().unwrap().to_owned()
+        }
+    }
+
+    /// Spawn the KMS dialing a TLS validator listening on the given port
+    fn spawn(pki: &TestPki, port: u16, config_dir: &Path) -> Child {
+        let config_path = config_dir.join("tmkms.toml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+
+                [[validator]]
+                addr = "tls://localhost:{}"
+                chain_id = "test_chain_id"
+                max_height = "500000"
+                protocol_version = "legacy"
+                tls_ca = "{}"
+                tls_client_cert = "{}"
+                tls_client_key = "{}"
+
+                [[providers.softsign]]
+                chain_ids = ["test_chain_id"]
+                key_format = "base64"
+                path = "{}"
+                "#,
+                config_dir.join("state.json").display(),
+                port,
+                pki.path("ca.pem"),
+                pki.path("client.pem"),
+                pki.path("client.key"),
+                SIGNING_KEY_PATH
+            ),
+        )
+        .unwrap();
+
+        Command::new(KMS_EXE_PATH)
+            .args(&["start", "-c", config_path.to_str().unwrap()])
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_tls_ping() {
+        let pki = TestPki::new();
+        let config_dir = tempfile::tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut process = spawn(&pki, port, config_dir.path());
+
+        let (socket, _) = listener.accept().unwrap();
+        let server = ServerConnection::new(pki.server_config("localhost")).unwrap();
+        let mut connection = StreamOwned::new(server, socket);
+
+        let mut buf = vec![];
+        PingRequest {}.encode(&mut buf).unwrap();
+        connection.write_all(&buf).unwrap();
+
+        let resp = MsgReader::new()
+            .read_msg(&mut connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+        PingResponse::decode(resp.as_ref()).expect("decoding ping response failed");
+
+        // The KMS authenticated with its client certificate
+        assert!(connection.conn.peer_certificates().is_some());
+
+        process.kill().unwrap();
+        process.wait().unwrap();
+    }
+
+    #[test]
+    fn test_tls_untrusted_validator_certificate() {
+        let pki = TestPki::new();
+        let config_dir = tempfile::tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut process = spawn(&pki, port, config_dir.path());
+
+        // Present a certificate for some other name
+        let (socket, _) = listener.accept().unwrap();
+        let server = ServerConnection::new(pki.server_config("other.example.com")).unwrap();
+        let mut connection = StreamOwned::new(server, socket);
+        let mut buf = [0u8; 1];
+        assert!(connection.read(&mut buf).is_err() || buf[0] == 0);
+
+        // Verification failures are fatal, so the KMS doesn't reconnect
+        listener.set_nonblocking(true).unwrap();
+        thread::sleep(Duration::from_secs(2));
+        assert!(listener.accept().is_err());
+
+        process.kill().unwrap();
+        process.wait().unwrap();
+        let mut stderr = String::new();
+        process
+            .stderr
+            .take()
+            .unwrap()
+            .read_to_string(&mut stderr)
+            .unwrap();
+
+        assert!(stderr.contains("untrusted certificate"), "{}", stderr);
+        assert!(stderr.contains("DNS:other.example.com"), "{}", stderr);
+    }
+}
+
+mod listener {
+    use super::*;
+    use std::{
+        env,
+        os::unix::fs::{MetadataExt, PermissionsExt},
+        thread,
+        time::Duration,
+    };
+
+    /// Spawns a KMS process listening for validator connections
+    struct ListenTester {
+        /// KMS child process
+        process: Child,
+
+        /// Address the KMS is listening on
+        addr: ListenAddr,
+
+        /// KMS config file (kept alive for the lifetime of the process)
+        config: NamedTempFile,
+    }
+
+    /// Address the KMS is listening on
+    enum ListenAddr {
+        /// TCP port on localhost
+        Tcp(u16),
+
+        /// UNIX domain socket path
+        Unix(String),
+    }
+
+    impl ListenTester {
+        /// Spawn a KMS listening on a random TCP port
+        fn tcp() -> Self {
+            // Let the OS pick a free port
+            let port = TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            let peer_id =
+                secret_connection::PublicKey::from(test_ed25519_keypair().public).peer_id();
+
+            Self::spawn(
+                &format!("tcp-listen://{}@127.0.0.1:{}", peer_id, port),
+                ListenAddr::Tcp(port),
+            )
+        }
+
+        /// Spawn a KMS listening on a random UNIX domain socket path
+        fn unix() -> Self {
+            Self::unix_with_options("")
+        }
+
+        /// Spawn a KMS listening on a random UNIX domain socket path, with
+        /// the given additional `[[validator]]` options
+        fn unix_with_options(options: &str) -> Self {
+            let number: u32 = rand::thread_rng().gen_range(0, 999999);
+            let socket_path = format!("/tmp/tmkms-listen-{:06}.sock", number);
+
+            Self::spawn_with_options(
+                &format!("unix-listen://{}", socket_path),
+                ListenAddr::Unix(socket_path),
+                options,
+            )
+        }
+
+        fn spawn(addr: &str, listen_addr: ListenAddr) -> Self {
+            Self::spawn_with_options(addr, listen_addr, "")
+        }
+
+        fn spawn_with_options(addr: &str, listen_addr: ListenAddr, options: &str) -> Self {
+            let mut config = NamedTempFile::new().unwrap();
+            writeln!(
+                config,
+                r#"
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+
+                [[validator]]
+                addr = "{}"
+                chain_id = "test_chain_id"
+                max_height = "500000"
+                secret_key = "tests/support/secret_connection.key"
+                protocol_version = "legacy"
+                {}
+
+                [[providers.softsign]]
+                chain_ids = ["test_chain_id"]
+                key_format = "base64"
+                path = "{}"
+            "#,
+                state_file_path(config.path()),
+                addr,
+                options,
+                SIGNING_KEY_PATH
+            )
+            .unwrap();
+
+            let args = &["start", "-c", config.path().to_str().unwrap()];
+            let process = Command::new(KMS_EXE_PATH).args(args).spawn().unwrap();
+
+            Self {
+                process,
+                addr: listen_addr,
+                config,
+            }
+        }
+
+        /// Connect to the KMS, waiting for it to start listening
+        fn connect(&self) -> KmsConnection {
+            for _ in 0..50 {
+                match self.addr {
+                    ListenAddr::Tcp(port) => {
+                        if let Ok(socket) = TcpStream::connect(("127.0.0.1", port)) {
+                            return KmsConnection::Tcp(
+                                SecretConnection::new(
+                                    socket,
+                                    test_ed25519_keypair(),
+                                    secret_connection::Version::Legacy,
+                                )
+                                .unwrap(),
+                            );
+                        }
+                    }
+                    ListenAddr::Unix(ref path) => {
+                        if let Ok(socket) = UnixStream::connect(path) {
+                            return KmsConnection::Unix(UnixConnection::new(socket));
+                        }
+                    }
+                }
+
+                thread::sleep(Duration::from_millis(100));
+            }
+
+            panic!("couldn't connect to KMS listener");
+        }
+    }
+
+    impl Drop for ListenTester {
+        fn drop(&mut self) {
+            self.process.kill().unwrap();
+
+            if let ListenAddr::Unix(ref path) = self.addr {
+                let _ = fs::remove_file(path);
+            }
+
+            remove_state_files(&state_file_path(self.config.path()));
+        }
+    }
+
+    /// Send a ping over the given connection and expect a pong
+    fn ping(connection: &mut KmsConnection) {
+        let mut buf = vec![];
+        PingRequest {}.encode(&mut buf).unwrap();
+        connection.write_all(&buf).unwrap();
+
+        let resp = MsgReader::new()
+            .read_msg(connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+        PingResponse::decode(resp.as_ref()).expect("decoding ping response failed");
+    }
+
+    /// Write the given message over the given connection, and expect the
+    /// KMS to drop the connection rather than respond
+    fn expect_dropped(mut connection: KmsConnection, msg: &[u8]) {
+        connection.write_all(msg).unwrap();
+
+        let mut resp_buf = vec![0u8; 1024];
+        match connection.read(&mut resp_buf) {
+            Ok(0) | Err(_) => (),
+            Ok(n) => panic!("expected connection to be dropped, got {} bytes", n),
+        }
+    }
+
+    #[test]
+    fn test_malformed_requests() {
+        // Random bytes (with a valid length prefix)
+        let garbage: Vec<u8> = (0..64).map(|_| rand::thread_rng().gen()).collect();
+        let mut random_msg = vec![];
+        prost::encoding::encode_varint(garbage.len() as u64, &mut random_msg);
+        random_msg.extend_from_slice(&garbage);
+
+        // A valid message, but not a request
+        let mut response_msg = vec![];
+        PingResponse {}.encode(&mut response_msg).unwrap();
+
+        for spawn in &[
+            ListenTester::tcp as fn() -> ListenTester,
+            ListenTester::unix,
+        ] {
+            let tester = spawn();
+
+            for msg in &[&random_msg, &response_msg] {
+                expect_dropped(tester.connect(), msg);
+
+                // The KMS stays responsive to the next connection
+                ping(&mut tester.connect());
+            }
+        }
+    }
+
+    #[test]
+    fn test_listen_and_reaccept() {
+        // Testers share a state file, so only run one KMS at a time
+        for spawn in &[
+            ListenTester::tcp as fn() -> ListenTester,
+            ListenTester::unix,
+        ] {
+            let tester = spawn();
+            ping(&mut tester.connect());
+
+            // After the validator drops the connection, the KMS goes back to
+            // accepting connections on the same listener
+            ping(&mut tester.connect());
+        }
+    }
+
+    #[test]
+    fn test_unix_socket_permissions() {
+        // Our own group, which the KMS can always give the socket to
+        let gid = fs::metadata(env::temp_dir()).unwrap().gid();
+        let tester = ListenTester::unix_with_options(&format!(
+            r#"socket_mode = "0660"
+            socket_group = "{}""#,
+            gid
+        ));
+        ping(&mut tester.connect());
+
+        let path = match tester.addr {
+            ListenAddr::Unix(ref path) => path,
+            _ => unreachable!(),
+        };
+        let metadata = fs::metadata(path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o660);
+        assert_eq!(metadata.gid(), gid);
+    }
+
+    #[test]
+    fn test_unix_socket_unknown_owner() {
+        let mut tester = ListenTester::unix_with_options(r#"socket_owner = "tmkms-no-such-user""#);
+
+        // Failing to set the socket's ownership is fatal, not retried
+        for _ in 0..50 {
+            if let Some(status) = tester.process.try_wait().unwrap() {
+                assert!(!status.success());
+                return;
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        panic!("KMS didn't exit");
+    }
+}
+
+mod shutdown {
+    use super::*;
+    use std::{
+        os::unix::process::ExitStatusExt,
+        path::{Path, PathBuf},
+        process::ExitStatus,
+        thread,
+        time::Duration,
+    };
+    use tempfile::TempDir;
+    use tmkms::session::SIGNING_DELAY_ENV_VAR;
+
+    /// Spawns a KMS process whose signing operations are artificially slow
+    struct ShutdownTester {
+        /// KMS child process
+        process: Child,
+
+        /// Connection to the KMS
+        connection: KmsConnection,
+
+        /// Directory containing the config, state file and socket
+        dir: TempDir,
+    }
+
+    impl ShutdownTester {
+        /// Spawn a KMS which takes `signing_delay_ms` to sign and waits
+        /// `grace_period` seconds for in-flight requests on shutdown
+        fn spawn(signing_delay_ms: u64, grace_period: u64) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let socket_path = dir.path().join("tmkms.sock");
+            let config_path = dir.path().join("tmkms.toml");
+
+            fs::write(
+                &config_path,
+                format!(
+                    r#"
+                    shutdown_grace_period = {}
+
+                    [[chain]]
+                    id = "test_chain_id"
+                    key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                    state_file = "{}"
+
+                    [[validator]]
+                    addr = "unix://{}"
+                    chain_id = "test_chain_id"
+                    protocol_version = "legacy"
+
+                    [[providers.softsign]]
+                    chain_ids = ["test_chain_id"]
+                    key_format = "base64"
+                    path = "{}"
+                    "#,
+                    grace_period,
+                    Self::state_path(dir.path()).display(),
+                    socket_path.display(),
+                    SIGNING_KEY_PATH
+                ),
+            )
+            .unwrap();
+
+            let listener = UnixListener::bind(&socket_path).unwrap();
+
+            let process = Command::new(KMS_EXE_PATH)
+                .args(&["start", "-c", config_path.to_str().unwrap()])
+                .env(SIGNING_DELAY_ENV_VAR, signing_delay_ms.to_string())
+                .spawn()
+                .unwrap();
+
+            let (socket, _) = listener.accept().unwrap();
+
+            Self {
+                process,
+                connection: KmsConnection::Unix(UnixConnection::new(socket)),
+                dir,
+            }
+        }
+
+        /// Path to the chain's state file
+        fn state_path(dir: &Path) -> PathBuf {
+            dir.join("priv_validator_state.json")
+        }
+
+        /// Send a request to sign a vote at the given height
+        fn send_vote(&mut self, height: i64) -> SignVoteRequest {
+            let request = SignVoteRequest {
+                vote: Some(Vote {
+                    vote_type: 0x01,
+                    height,
+                    round: 0,
+                    timestamp: Some(TimeMsg {
+                        seconds: 1_518_332_962,
+                        nanos: 765_000_000,
+                    }),
+                    block_id: None,
+                    validator_address: test_validator_address(),
+                    validator_index: 1,
+                    signature: vec![],
+                    extension: vec![],
+                    extension_signature: vec![],
+                }),
+                chain_id: String::new(),
+            };
+
+            let mut buf = vec![];
+            request.encode(&mut buf).unwrap();
+            self.connection.write_all(&buf).unwrap();
+            request
+        }
+
+        /// Send the KMS process a signal
+        fn signal(&self, signal: libc::c_int) {
+            let rc = unsafe { libc::kill(self.process.id() as libc::pid_t, signal) };
+            assert_eq!(rc, 0);
+        }
+
+        /// Wait for the KMS process to exit (failing after 10 seconds)
+        fn wait(&mut self) -> ExitStatus {
+            for _ in 0..100 {
+                if let Some(status) = self.process.try_wait().unwrap() {
+                    return status;
+                }
+
+                thread::sleep(Duration::from_millis(100));
+            }
+
+            panic!("KMS didn't exit");
+        }
+
+        /// Height recorded in the chain's state file
+        fn persisted_height(&self) -> String {
+            let state: serde_json::Value =
+                serde_json::from_slice(&fs::read(Self::state_path(self.dir.path())).unwrap())
+                    .unwrap();
+
+            state["height"].as_str().unwrap().to_owned()
+        }
+    }
+
+    impl Drop for ShutdownTester {
+        fn drop(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+        }
+    }
+
+    #[test]
+    fn test_sigterm_completes_in_flight_request() {
+        let mut tester = ShutdownTester::spawn(1500, 5);
+        let request = tester.send_vote(1234);
+
+        // Signal while the vote is being signed
+        thread::sleep(Duration::from_millis(500));
+        tester.signal(libc::SIGTERM);
+
+        let resp = MsgReader::new()
+            .read_msg(&mut tester.connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+        let response =
+            vote::SignedVoteResponse::decode(resp.as_ref()).expect("decoding vote failed");
+        assert!(response.err.is_none());
+
+        let mut sign_bytes = vec![];
+        request
+            .sign_bytes(
+                "test_chain_id".parse().unwrap(),
+                ProtocolVersion::Legacy,
+                &mut sign_bytes,
+            )
+            .unwrap();
+
+        let signature =
+            ed25519::Signature::try_from(response.vote.unwrap().signature.as_slice()).unwrap();
+        assert!(test_ed25519_keypair()
+            .public
+            .verify(&sign_bytes, &signature)
+            .is_ok());
+
+        assert_eq!(tester.wait().code(), Some(0));
+        assert_eq!(tester.persisted_height(), "1234");
+    }
+
+    #[test]
+    fn test_sigterm_forces_exit_after_grace_period() {
+        let mut tester = ShutdownTester::spawn(5000, 1);
+        tester.send_vote(1234);
+
+        thread::sleep(Duration::from_millis(500));
+        tester.signal(libc::SIGTERM);
+
+        let status = tester.wait();
+        assert_eq!(status.code(), Some(1));
+        assert_eq!(status.signal(), None);
+    }
+
+    #[test]
+    fn test_sighup_is_ignored() {
+        let mut tester = ShutdownTester::spawn(0, 5);
+        tester.signal(libc::SIGHUP);
+        thread::sleep(Duration::from_millis(500));
+        assert!(tester.process.try_wait().unwrap().is_none());
+
+        tester.signal(libc::SIGINT);
+        assert_eq!(tester.wait().code(), Some(0));
+    }
+}
+
+mod reload {
+    use super::*;
+    use std::{os::unix::net::UnixListener, path::Path, thread, time::Duration};
+    use tempfile::TempDir;
+
+    /// Spawns a KMS process whose configuration is rewritten and reloaded
+    struct ReloadTester {
+        /// KMS child process
+        process: Child,
+
+        /// Directory containing the config, state file and sockets
+        dir: TempDir,
+    }
+
+    impl ReloadTester {
+        /// Spawn a KMS connecting to the given validators (see `write_config`)
+        /// and accept its connection to the first one
+        fn spawn(validators: &[(&str, Option<u64>)]) -> (Self, KmsConnection) {
+            let dir = tempfile::tempdir().unwrap();
+            let listener = UnixListener::bind(dir.path().join(validators[0].0)).unwrap();
+            write_config(dir.path(), validators);
+
+            let process = Command::new(KMS_EXE_PATH)
+                .args(&["start", "-c", config_path(dir.path()).as_str()])
+                .spawn()
+                .unwrap();
+
+            let connection = accept(&listener);
+            (Self { process, dir }, connection)
+        }
+
+        /// Path to the KMS configuration file
+        fn config_path(&self) -> String {
+            config_path(self.dir.path())
+        }
+
+        /// Listen on the socket with the given name
+        fn listen(&self, name: &str) -> UnixListener {
+            UnixListener::bind(self.dir.path().join(name)).unwrap()
+        }
+
+        /// Rewrite the config and tell the KMS to reload it
+        fn reload(&self, validators: &[(&str, Option<u64>)]) {
+            write_config(self.dir.path(), validators);
+            self.hangup();
+        }
+
+        /// Send the KMS process `SIGHUP`
+        fn hangup(&self) {
+            let rc = unsafe { libc::kill(self.process.id() as libc::pid_t, libc::SIGHUP) };
+            assert_eq!(rc, 0);
+        }
+    }
+
+    impl Drop for ReloadTester {
+        fn drop(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+        }
+    }
+
+    /// Path to the KMS configuration file in the given directory
+    fn config_path(dir: &Path) -> String {
+        dir.join("tmkms.toml").display().to_string()
+    }
+
+    /// Write a config to the given directory with a validator for each of the
+    /// given socket names (and their optional `max_height`)
+    fn write_config(dir: &Path, validators: &[(&str, Option<u64>)]) {
+        let mut config = format!(
+            r#"
+            [[chain]]
+            id = "test_chain_id"
+            key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+            state_file = "{}"
+
+            [[providers.softsign]]
+            chain_ids = ["test_chain_id"]
+            key_format = "base64"
+            path = "{}"
+            "#,
+            dir.join("priv_validator_state.json").display(),
+            SIGNING_KEY_PATH
+        );
+
+        for (name, max_height) in validators {
+            config.push_str(&format!(
+                r#"
+                [[validator]]
+                addr = "unix://{}"
+                chain_id = "test_chain_id"
+                protocol_version = "legacy"
+                "#,
+                dir.join(name).display()
+            ));
+
+            if let Some(max_height) = max_height {
+                config.push_str(&format!("max_height = \"{}\"\n", max_height));
+            }
+        }
+
+        fs::write(config_path(dir), config).unwrap();
+    }
+
+    /// Accept a connection from the KMS
+    fn accept(listener: &UnixListener) -> KmsConnection {
+        let (socket, _) = listener.accept().unwrap();
+        KmsConnection::Unix(UnixConnection::new(socket))
+    }
+
+    /// Ask the KMS to sign a vote at the given height, returning the response
+    fn sign_vote(connection: &mut KmsConnection, height: i64) -> vote::SignedVoteResponse {
+        let request = SignVoteRequest {
+            vote: Some(Vote {
+                vote_type: 0x01,
+                height,
+                round: 0,
+                timestamp: Some(TimeMsg {
+                    seconds: 1_518_332_962,
+                    nanos: 765_000_000,
+                }),
+                block_id: None,
+                validator_address: test_validator_address(),
+                validator_index: 1,
+                signature: vec![],
+                extension: vec![],
+                extension_signature: vec![],
+            }),
+            chain_id: String::new(),
+        };
+
+        let mut buf = vec![];
+        request.encode(&mut buf).unwrap();
+        connection.write_all(&buf).unwrap();
+
+        let resp = MsgReader::new()
+            .read_msg(connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+        vote::SignedVoteResponse::decode(resp.as_ref()).expect("decoding vote failed")
+    }
+
+    /// Wait for the KMS to process a `SIGHUP` which doesn't result in a new
+    /// connection
+    fn wait_for_reload() {
+        thread::sleep(Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_sighup_reload() {
+        let (mut tester, mut validator_a) = ReloadTester::spawn(&[("a.sock", None)]);
+        assert!(sign_vote(&mut validator_a, 10).err.is_none());
+
+        // Invalid configuration is rejected, keeping the old one
+        fs::write(tester.config_path(), "[[chain]\n").unwrap();
+        tester.hangup();
+        wait_for_reload();
+
+        assert!(tester.process.try_wait().unwrap().is_none());
+        assert!(sign_vote(&mut validator_a, 11).err.is_none());
+
+        // Adding a validator connects to it, and `max_height` changes apply
+        // to the existing connection
+        let listener_b = tester.listen("b.sock");
+        tester.reload(&[("a.sock", Some(100)), ("b.sock", None)]);
+        let mut validator_b = accept(&listener_b);
+
+        assert!(sign_vote(&mut validator_a, 200).err.is_some());
+        assert!(sign_vote(&mut validator_a, 12).err.is_none());
+        assert!(sign_vote(&mut validator_b, 13).err.is_none());
+
+        // Removing a validator disconnects it after its next request
+        tester.reload(&[("a.sock", Some(100))]);
+        wait_for_reload();
+
+        assert!(sign_vote(&mut validator_b, 14).err.is_none());
+        let mut buf = [0u8; 1];
+        assert_eq!(validator_b.read(&mut buf).unwrap_or(0), 0);
+
+        assert!(sign_vote(&mut validator_a, 15).err.is_none());
+    }
+
+    /// Write a config with a validator for each of the given chains (each
+    /// with its own socket, named after it)
+    fn write_chains_config(dir: &Path, chain_ids: &[&str]) {
+        let mut config = String::new();
+
+        for chain_id in chain_ids {
+            config.push_str(&format!(
+                r#"
+                [[chain]]
+                id = "{chain_id}"
+                key_format = {{ type = "hex" }}
+                state_file = "{state_file}"
+
+                [[validator]]
+                addr = "unix://{socket}"
+                chain_id = "{chain_id}"
+                protocol_version = "v0.34"
+
+                [[providers.softsign]]
+                chain_ids = ["{chain_id}"]
+                key_format = "base64"
+                path = "{key_path}"
+                "#,
+                chain_id = chain_id,
+                state_file = dir.join(format!("{}_state.json", chain_id)).display(),
+                socket = dir.join(format!("{}.sock", chain_id)).display(),
+                key_path = SIGNING_KEY_PATH
+            ));
+        }
+
+        fs::write(config_path(dir), config).unwrap();
+    }
+
+    #[test]
+    fn test_pub_key_request_for_removed_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener_a = UnixListener::bind(dir.path().join("chain-a.sock")).unwrap();
+        let listener_b = UnixListener::bind(dir.path().join("chain-b.sock")).unwrap();
+        write_chains_config(dir.path(), &["chain-a", "chain-b"]);
+
+        let process = Command::new(KMS_EXE_PATH)
+            .args(["start", "-c", config_path(dir.path()).as_str()])
+            .spawn()
+            .unwrap();
+
+        let tester = ReloadTester { process, dir };
+        let mut validator_a = accept(&listener_a);
+        let mut validator_b = accept(&listener_b);
+        assert!(get_pub_key(&mut validator_b, "chain-b").pub_key.is_some());
+
+        // Give the session time to go back to waiting for a request, as a
+        // client stopped before then disconnects without handling another
+        thread::sleep(Duration::from_millis(100));
+
+        // Removing the chain (and its validator) leaves the validator's
+        // session running until its next request, which is refused
+        write_chains_config(tester.dir.path(), &["chain-a"]);
+        tester.hangup();
+        wait_for_reload();
+
+        let resp = get_pub_key(&mut validator_b, "chain-b");
+        assert!(resp.pub_key.is_none());
+        assert_eq!(
+            resp.error.unwrap().code,
+            RemoteErrorCode::ChainIdError as i32
+        );
+
+        // ...while the other chain is unaffected
+        assert!(get_pub_key(&mut validator_a, "chain-a").pub_key.is_some());
+    }
+
+    /// Request the public key for the given chain, returning the response
+    fn get_pub_key(
+        connection: &mut KmsConnection,
+        chain_id: &str,
+    ) -> tendermint_proto::privval::PubKeyResponse {
+        use prost::Message as _;
+        use tendermint_proto::privval::{message::Sum, Message, PubKeyRequest};
+
+        let mut buf = vec![];
+        Message {
+            sum: Some(Sum::PubKeyRequest(PubKeyRequest {
+                chain_id: chain_id.to_owned(),
+            })),
+        }
+        .encode_length_delimited(&mut buf)
+        .unwrap();
+        connection.write_all(&buf).unwrap();
+
+        let resp = MsgReader::new()
+            .read_msg(connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+
+        match Message::decode_length_delimited(resp.as_ref()).unwrap().sum {
+            Some(Sum::PubKeyResponse(response)) => response,
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+}
+
+/// Integration tests for the lock on each chain's consensus state
+mod state_lock {
+    use super::*;
+    use std::{
+        os::unix::net::UnixListener,
+        process::Stdio,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn test_second_instance_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("tmkms.sock");
+        let config_path = dir.path().join("tmkms.toml");
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+
+                [[validator]]
+                addr = "unix://{}"
+                chain_id = "test_chain_id"
+                protocol_version = "legacy"
+
+                [[providers.softsign]]
+                chain_ids = ["test_chain_id"]
+                key_format = "base64"
+                path = "{}"
+                "#,
+                dir.path().join("priv_validator_state.json").display(),
+                socket_path.display(),
+                SIGNING_KEY_PATH
+            ),
+        )
+        .unwrap();
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let args = &["start", "-c", config_path.to_str().unwrap()];
+
+        // The first instance has locked the state by the time it connects
+        let mut first = Command::new(KMS_EXE_PATH).args(args).spawn().unwrap();
+        let _connection = listener.accept().unwrap();
+
+        let mut second = Command::new(KMS_EXE_PATH)
+            .args(args)
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let started_at = Instant::now();
+        let status = loop {
+            if let Some(status) = second.try_wait().unwrap() {
+                break status;
+            }
+
+            if started_at.elapsed() > Duration::from_secs(5) {
+                second.kill().unwrap();
+                first.kill().unwrap();
+                panic!("second KMS instance didn't exit");
+            }
+
+            thread::sleep(Duration::from_millis(50));
+        };
+
+        first.kill().unwrap();
+        first.wait().unwrap();
+
+        let mut stderr = String::new();
+        second
+            .stderr
+            .take()
+            .unwrap()
+            .read_to_string(&mut stderr)
+            .unwrap();
+
+        assert!(!status.success());
+        assert!(
+            stderr.contains(&format!(
+                "another tmkms instance holds the lock (pid {})",
+                first.id()
+            )),
+            "{}",
+            stderr
+        );
+    }
+
+    #[test]
+    fn test_state_dir_is_created() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("tmkms.sock");
+        let config_path = dir.path().join("tmkms.toml");
+
+        // Relative to the configuration file's directory
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                state_dir = "state/test"
+
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+
+                [[validator]]
+                addr = "unix://{}"
+                chain_id = "test_chain_id"
+                protocol_version = "legacy"
+
+                [[providers.softsign]]
+                chain_ids = ["test_chain_id"]
+                key_format = "base64"
+                path = "{}"
+                "#,
+                socket_path.display(),
+                SIGNING_KEY_PATH
+            ),
+        )
+        .unwrap();
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let mut process = Command::new(KMS_EXE_PATH)
+            .args(&["start", "-c", config_path.to_str().unwrap()])
+            .spawn()
+            .unwrap();
+
+        // The state is locked by the time the KMS connects
+        let _connection = listener.accept().unwrap();
+        process.kill().unwrap();
+        process.wait().unwrap();
+
+        let state_dir = dir.path().join("state/test");
+        let mode = fs::metadata(&state_dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        assert!(state_dir
+            .join("test_chain_id_priv_validator_state.json.lock")
+            .exists());
+    }
+}
+
+/// Integration tests for the consensus key recorded with each chain's state
+mod key_change {
+    use super::*;
+    use std::{os::unix::net::UnixListener, process::Stdio};
+    use tendermint::PublicKey;
+
+    /// Write a config for a chain using the test key, whose state records
+    /// another key, returning the paths of the config and state file
+    fn setup(dir: &std::path::Path) -> (std::path::PathBuf, std::path::PathBuf) {
+        let config_path = dir.join("tmkms.toml");
+        let state_path = dir.join("priv_validator_state.json");
+        let other_key = PublicKey::from_raw_ed25519(&[0x11; 32]).unwrap();
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+
+                [[validator]]
+                addr = "unix://{}"
+                chain_id = "test_chain_id"
+                protocol_version = "legacy"
+
+                [[providers.softsign]]
+                chain_ids = ["test_chain_id"]
+                key_format = "base64"
+                path = "{}"
+                "#,
+                state_path.display(),
+                dir.join("tmkms.sock").display(),
+                SIGNING_KEY_PATH
+            ),
+        )
+        .unwrap();
+
+        fs::write(
+            &state_path,
+            serde_json::json!({
+                "height": "10",
+                "round": "0",
+                "step": 3,
+                "block_id": null,
+                "pub_key": other_key,
+                "version": 2
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        (config_path, state_path)
+    }
+
+    #[test]
+    fn test_changed_key_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config_path, _) = setup(dir.path());
+
+        let output = Command::new(KMS_EXE_PATH)
+            .args(["start", "-c", config_path.to_str().unwrap()])
+            .stderr(Stdio::piped())
+            .output()
+            .unwrap();
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success());
+        assert!(
+            stderr.contains("differs from cosmosvalconspub1")
+                && stderr.contains("--accept-key-change"),
+            "{}",
+            stderr
+        );
+    }
+
+    #[test]
+    fn test_accept_key_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config_path, state_path) = setup(dir.path());
+        let listener = UnixListener::bind(dir.path().join("tmkms.sock")).unwrap();
+
+        let mut process = Command::new(KMS_EXE_PATH)
+            .args([
+                "start",
+                "-c",
+                config_path.to_str().unwrap(),
+                "--accept-key-change",
+            ])
+            .spawn()
+            .unwrap();
+
+        // The new key is recorded by the time the KMS connects
+        let _connection = listener.accept().unwrap();
+        process.kill().unwrap();
+        process.wait().unwrap();
+
+        let state: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
+        let public_key = PublicKey::from_raw_ed25519(test_ed25519_keypair().public.as_bytes());
+        assert_eq!(state["pub_key"], serde_json::to_value(public_key).unwrap());
+        assert_eq!(state["height"], "10");
+    }
+}
+
+mod msg_type_policy {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    /// Spawn a KMS whose chain only allows signing votes, returning the
+    /// process, its connection and the directory holding its files
+    fn spawn() -> (Child, KmsConnection, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("tmkms.sock");
+        let config_path = dir.path().join("tmkms.toml");
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+                allowed_msg_types = ["prevote", "precommit"]
+
+                [[validator]]
+                addr = "unix://{}"
+                chain_id = "test_chain_id"
+                protocol_version = "legacy"
+
+                [[providers.softsign]]
+                chain_ids = ["test_chain_id"]
+                key_format = "base64"
+                path = "{}"
+                "#,
+                dir.path().join("priv_validator_state.json").display(),
+                socket_path.display(),
+                SIGNING_KEY_PATH
+            ),
+        )
+        .unwrap();
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let process = Command::new(KMS_EXE_PATH)
+            .args(&["start", "-c", config_path.to_str().unwrap()])
+            .spawn()
+            .unwrap();
+
+        let (socket, _) = listener.accept().unwrap();
+        let connection = KmsConnection::Unix(UnixConnection::new(socket));
+        (process, connection, dir)
+    }
+
+    /// Send a request and return the (length-delimited) response
+    fn send_request(connection: &mut KmsConnection, request: &impl Message) -> Vec<u8> {
+        let mut buf = vec![];
+        request.encode(&mut buf).unwrap();
+        connection.write_all(&buf).unwrap();
+
+        MsgReader::new()
+            .read_msg(connection, MAX_RESPONSE_SIZE)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_proposal_refused_votes_signed() {
+        let (mut process, mut connection, _dir) = spawn();
+        let timestamp = TimeMsg {
+            seconds: 1_518_332_962,
+            nanos: 765_000_000,
+        };
+
+        let spr = amino_types::proposal::SignProposalRequest {
+            proposal: Some(amino_types::proposal::Proposal {
+                msg_type: amino_types::SignedMsgType::Proposal.to_u32(),
+                height: 10,
+                round: 0,
+                pol_round: -1,
+                block_id: None,
+                timestamp: Some(timestamp.clone()),
+                signature: vec![],
+            }),
+            chain_id: String::new(),
+        };
+
+        let resp = send_request(&mut connection, &spr);
+        let p_resp = proposal::SignedProposalResponse::decode(resp.as_ref())
+            .expect("decoding proposal failed");
+        assert!(p_resp.proposal.is_none());
+
+        let err = p_resp
+            .err
+            .expect("error should be embedded in the response but none was found");
+        assert_eq!(err.code, RemoteErrorCode::MsgTypeError as i32);
+
+        for (height, vote_type) in &[(10, 0x01), (10, 0x02)] {
+            let svr = SignVoteRequest {
+                vote: Some(Vote {
+                    vote_type: *vote_type,
+                    height: *height,
+                    round: 0,
+                    timestamp: Some(timestamp.clone()),
+                    block_id: None,
+                    validator_address: test_validator_address(),
+                    validator_index: 1,
+                    signature: vec![],
+                    extension: vec![],
+                    extension_signature: vec![],
+                }),
+                chain_id: String::new(),
+            };
+
+            let resp = send_request(&mut connection, &svr);
+            let v_resp =
+                vote::SignedVoteResponse::decode(resp.as_ref()).expect("decoding vote failed");
+            assert!(v_resp.err.is_none());
+            assert!(v_resp.vote.is_some());
+        }
+
+        let _ = process.kill();
+        let _ = process.wait();
+    }
+}
+
+mod blocked_heights {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    /// Spawn a KMS whose chain blocks height 200 and heights 300 to 310,
+    /// returning the process, its connection and the directory holding its
+    /// files
+    fn spawn() -> (Child, KmsConnection, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("tmkms.sock");
+        let config_path = dir.path().join("tmkms.toml");
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+                blocked_heights = ["300-310", "200"]
+
+                [[validator]]
+                addr = "unix://{}"
+                chain_id = "test_chain_id"
+                protocol_version = "legacy"
+
+                [[providers.softsign]]
+                chain_ids = ["test_chain_id"]
+                key_format = "base64"
+                path = "{}"
+                "#,
+                dir.path().join("priv_validator_state.json").display(),
+                socket_path.display(),
+                SIGNING_KEY_PATH
+            ),
+        )
+        .unwrap();
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let process = Command::new(KMS_EXE_PATH)
+            .args(&["start", "-c", config_path.to_str().unwrap()])
+            .spawn()
+            .unwrap();
+
+        let (socket, _) = listener.accept().unwrap();
+        let connection = KmsConnection::Unix(UnixConnection::new(socket));
+        (process, connection, dir)
+    }
+
+    /// Request a prevote at the given height from the given validator,
+    /// returning the response
+    fn sign_prevote(
+        connection: &mut KmsConnection,
+        height: i64,
+        validator_address: Vec<u8>,
+    ) -> vote::SignedVoteResponse {
+        let svr = SignVoteRequest {
+            vote: Some(Vote {
+                vote_type: 0x01,
+                height,
+                round: 0,
+                timestamp: Some(TimeMsg {
+                    seconds: 1_518_332_962,
+                    nanos: 765_000_000,
+                }),
+                block_id: None,
+                validator_address,
+                validator_index: 1,
+                signature: vec![],
+                extension: vec![],
+                extension_signature: vec![],
+            }),
+            chain_id: String::new(),
+        };
+
+        let mut buf = vec![];
+        svr.encode(&mut buf).unwrap();
+        connection.write_all(&buf).unwrap();
+
+        let resp = MsgReader::new()
+            .read_msg(connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+
+        vote::SignedVoteResponse::decode(resp.as_ref()).expect("decoding vote failed")
+    }
+
+    #[test]
+    fn test_range_edges() {
+        let (mut process, mut connection, _dir) = spawn();
+
+        for (height, rule) in &[
+            (199, None),
+            (200, Some("200")),
+            (201, None),
+            (299, None),
+            (300, Some("300-310")),
+            (310, Some("300-310")),
+            (311, None),
+        ] {
+            let resp = sign_prevote(&mut connection, *height, test_validator_address());
+
+            match rule {
+                Some(rule) => {
+                    assert!(resp.vote.is_none(), "height {} signed", height);
+                    let err = resp.err.unwrap();
+                    assert_eq!(err.code, RemoteErrorCode::BlockedHeightError as i32);
+                    assert_eq!(
+                        err.description,
+                        format!(
+                            "height {} is blocked (blocked_heights rule \"{}\")",
+                            height, rule
+                        )
+                    );
+                }
+                None => {
+                    assert!(resp.err.is_none(), "height {} refused", height);
+                    assert!(resp.vote.is_some());
+                }
+            }
+        }
+
+        // Blocked heights are refused before any other check
+        let resp = sign_prevote(&mut connection, 305, vec![0xAB; 20]);
+        assert_eq!(
+            resp.err.unwrap().code,
+            RemoteErrorCode::BlockedHeightError as i32
+        );
+
+        let _ = process.kill();
+        let _ = process.wait();
+    }
+}
+
+mod validator_role {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    /// Spawn a KMS whose validator has `role = "voter"`, returning the
+    /// process, its connection and the directory holding its files
+    fn spawn() -> (Child, KmsConnection, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("tmkms.sock");
+        let config_path = dir.path().join("tmkms.toml");
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+
+                [[validator]]
+                addr = "unix://{}"
+                chain_id = "test_chain_id"
+                protocol_version = "legacy"
+                role = "voter"
+
+                [[providers.softsign]]
+                chain_ids = ["test_chain_id"]
+                key_format = "base64"
+                path = "{}"
+                "#,
+                dir.path().join("priv_validator_state.json").display(),
+                socket_path.display(),
+                SIGNING_KEY_PATH
+            ),
+        )
+        .unwrap();
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let process = Command::new(KMS_EXE_PATH)
+            .args(&["start", "-c", config_path.to_str().unwrap()])
+            .spawn()
+            .unwrap();
+
+        let (socket, _) = listener.accept().unwrap();
+        let connection = KmsConnection::Unix(UnixConnection::new(socket));
+        (process, connection, dir)
+    }
+
+    /// Send a request and return the (length-delimited) response
+    fn send_request(connection: &mut KmsConnection, request: &impl Message) -> Vec<u8> {
+        let mut buf = vec![];
+        request.encode(&mut buf).unwrap();
+        connection.write_all(&buf).unwrap();
+
+        MsgReader::new()
+            .read_msg(connection, MAX_RESPONSE_SIZE)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_voter_refuses_proposals() {
+        let (mut process, mut connection, _dir) = spawn();
+        let timestamp = TimeMsg {
+            seconds: 1_518_332_962,
+            nanos: 765_000_000,
+        };
+
+        for (height, vote_type) in &[(10, 0x01), (10, 0x02)] {
+            let svr = SignVoteRequest {
+                vote: Some(Vote {
+                    vote_type: *vote_type,
+                    height: *height,
+                    round: 0,
+                    timestamp: Some(timestamp.clone()),
+                    block_id: None,
+                    validator_address: test_validator_address(),
+                    validator_index: 1,
+                    signature: vec![],
+                    extension: vec![],
+                    extension_signature: vec![],
+                }),
+                chain_id: String::new(),
+            };
+
+            let resp = send_request(&mut connection, &svr);
+            let v_resp =
+                vote::SignedVoteResponse::decode(resp.as_ref()).expect("decoding vote failed");
+            assert!(v_resp.err.is_none());
+            assert!(v_resp.vote.is_some());
+        }
+
+        let spr = amino_types::proposal::SignProposalRequest {
+            proposal: Some(amino_types::proposal::Proposal {
+                msg_type: amino_types::SignedMsgType::Proposal.to_u32(),
+                height: 11,
+                round: 0,
+                pol_round: -1,
+                block_id: None,
+                timestamp: Some(timestamp),
+                signature: vec![],
+            }),
+            chain_id: String::new(),
+        };
+
+        let resp = send_request(&mut connection, &spr);
+        let p_resp = proposal::SignedProposalResponse::decode(resp.as_ref())
+            .expect("decoding proposal failed");
+        assert!(p_resp.proposal.is_none());
+
+        let err = p_resp
+            .err
+            .expect("error should be embedded in the response but none was found");
+        assert_eq!(err.code, RemoteErrorCode::RoleError as i32);
+        assert_eq!(
+            err.description,
+            "signing Proposal messages is not allowed for role \"voter\""
+        );
+
+        let _ = process.kill();
+        let _ = process.wait();
+    }
+}
+
+mod multi_chain {
+    use super::*;
+    use prost::Message as _;
+    use std::os::unix::net::UnixListener;
+    use tendermint_proto as proto;
+
+    /// Spawn a KMS with one validator connection serving two chains (the
+    /// second of which has the alias `chain-b-fork`), returning the process,
+    /// its connection and the directory holding its files
+    fn spawn() -> (Child, KmsConnection, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("tmkms.sock");
+        let config_path = dir.path().join("tmkms.toml");
+        let mut chains = String::new();
+
+        for (chain_id, aliases) in &[("chain-a", "[]"), ("chain-b", r#"["chain-b-fork"]"#)] {
+            chains.push_str(&format!(
+                r#"
+                [[chain]]
+                id = "{}"
+                aliases = {}
+                key_format = {{ type = "hex" }}
+                state_file = "{}"
+                "#,
+                chain_id,
+                aliases,
+                dir.path()
+                    .join(format!("{}_state.json", chain_id))
+                    .display(),
+            ));
+        }
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                {}
+
+                [[validator]]
+                addr = "unix://{}"
+                chain_ids = ["chain-a", "chain-b"]
+                protocol_version = "v0.34"
+
+                [[providers.softsign]]
+                chain_ids = ["chain-a", "chain-b"]
+                key_format = "base64"
+                path = "{}"
+                "#,
+                chains,
+                socket_path.display(),
+                SIGNING_KEY_PATH
+            ),
+        )
+        .unwrap();
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let process = Command::new(KMS_EXE_PATH)
+            .args(&["start", "-c", config_path.to_str().unwrap()])
+            .spawn()
+            .unwrap();
+
+        let (socket, _) = listener.accept().unwrap();
+        let connection = KmsConnection::Unix(UnixConnection::new(socket));
+        (process, connection, dir)
+    }
+
+    /// Build a vote signing request for the given chain
+    fn vote_request(chain_id: &str, vote_type: u32, height: i64) -> SignVoteRequest {
+        SignVoteRequest {
+            vote: Some(Vote {
+                vote_type,
+                height,
+                round: 0,
+                timestamp: Some(TimeMsg {
+                    seconds: 1_518_332_962,
+                    nanos: 765_000_000,
+                }),
+                block_id: None,
+                validator_address: test_validator_address(),
+                validator_index: 1,
+                signature: vec![],
+                extension: vec![],
+                extension_signature: vec![],
+            }),
+            chain_id: chain_id.to_owned(),
+        }
+    }
+
+    /// Send a vote signing request and return the response
+    fn sign_vote(
+        connection: &mut KmsConnection,
+        svr: &SignVoteRequest,
+    ) -> proto::privval::SignedVoteResponse {
+        let vote = svr.vote.as_ref().unwrap();
+        let request = proto::privval::SignVoteRequest {
+            vote: Some(proto::types::Vote {
+                r#type: vote.vote_type as i32,
+                height: vote.height,
+                round: vote.round as i32,
+                block_id: None,
+                timestamp: vote.timestamp.clone().map(Into::into),
+                validator_address: vote.validator_address.clone(),
+                validator_index: vote.validator_index as i32,
+                signature: vec![],
+            }),
+            chain_id: svr.chain_id.clone(),
+        };
+
+        let mut buf = vec![];
+        proto::privval::Message {
+            sum: Some(proto::privval::message::Sum::SignVoteRequest(request)),
+        }
+        .encode_length_delimited(&mut buf)
+        .unwrap();
+        connection.write_all(&buf).unwrap();
+
+        let resp = MsgReader::new()
+            .read_msg(connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+
+        match proto::privval::Message::decode_length_delimited(resp.as_ref())
+            .unwrap()
+            .sum
+        {
+            Some(proto::privval::message::Sum::SignedVoteResponse(response)) => response,
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_requests_routed_by_chain_id() {
+        let (mut process, mut connection, dir) = spawn();
+
+        // A precommit on one chain doesn't stop a prevote at the same height
+        // being signed on the other
+        let resp = sign_vote(&mut connection, &vote_request("chain-a", 0x02, 10));
+        assert!(resp.error.is_none());
+
+        let svr = vote_request("chain-b", 0x01, 10);
+        let resp = sign_vote(&mut connection, &svr);
+        assert!(resp.error.is_none());
+
+        // Votes are signed for the chain they're requested for
+        let mut sign_bytes = vec![];
+        svr.sign_bytes(
+            "chain-b".parse().unwrap(),
+            ProtocolVersion::V0_34,
+            &mut sign_bytes,
+        )
+        .unwrap();
+
+        let signature = resp.vote.unwrap().signature;
+        let signature = ed25519::Signature::try_from(signature.as_slice()).unwrap();
+        assert!(test_ed25519_keypair()
+            .public
+            .verify(&sign_bytes, &signature)
+            .is_ok());
+
+        // Requests for other chains are refused
+        let resp = sign_vote(&mut connection, &vote_request("chain-c", 0x01, 11));
+        assert!(resp.vote.is_none());
+        assert_eq!(
+            resp.error.unwrap().code,
+            RemoteErrorCode::ChainIdError as i32
+        );
+
+        let _ = process.kill();
+        let _ = process.wait();
+
+        // Each chain has its own consensus state
+        for (chain_id, step) in &[("chain-a", 3), ("chain-b", 2)] {
+            let state_file = dir.path().join(format!("{}_state.json", chain_id));
+            let state: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(state_file).unwrap()).unwrap();
+            assert_eq!(state["height"], "10");
+            assert_eq!(state["step"], *step);
+        }
+    }
+
+    /// Request the public key for the given chain, returning the response
+    fn get_pub_key(
+        connection: &mut KmsConnection,
+        chain_id: &str,
+    ) -> proto::privval::PubKeyResponse {
+        let mut buf = vec![];
+        proto::privval::Message {
+            sum: Some(proto::privval::message::Sum::PubKeyRequest(
+                proto::privval::PubKeyRequest {
+                    chain_id: chain_id.to_owned(),
+                },
+            )),
+        }
+        .encode_length_delimited(&mut buf)
+        .unwrap();
+        connection.write_all(&buf).unwrap();
+
+        let resp = MsgReader::new()
+            .read_msg(connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+
+        match proto::privval::Message::decode_length_delimited(resp.as_ref())
+            .unwrap()
+            .sum
+        {
+            Some(proto::privval::message::Sum::PubKeyResponse(response)) => response,
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pub_key_requests_for_other_chains_refused() {
+        let (mut process, mut connection, _dir) = spawn();
+
+        for chain_id in &["chain-a", "chain-b"] {
+            let resp = get_pub_key(&mut connection, chain_id);
+            assert!(resp.error.is_none());
+            assert!(resp.pub_key.is_some());
+        }
+
+        // Refused with the same error as signing requests for other chains
+        let resp = get_pub_key(&mut connection, "chain-c");
+        assert!(resp.pub_key.is_none());
+
+        let err = resp.error.unwrap();
+        assert_eq!(err.code, RemoteErrorCode::ChainIdError as i32);
+        assert!(err.description.contains("requested chain-c"));
+
+        // ...without dropping the connection
+        assert!(get_pub_key(&mut connection, "chain-a").pub_key.is_some());
+
+        let _ = process.kill();
+        let _ = process.wait();
+    }
+
+    #[test]
+    fn test_requests_for_alias_signed_with_alias() {
+        let (mut process, mut connection, dir) = spawn();
+
+        // Requests bearing the alias are signed with it
+        let svr = vote_request("chain-b-fork", 0x01, 10);
+        let resp = sign_vote(&mut connection, &svr);
+        assert!(resp.error.is_none());
+
+        let signature = resp.vote.unwrap().signature;
+        let signature = ed25519::Signature::try_from(signature.as_slice()).unwrap();
+
+        for (chain_id, verifies) in &[("chain-b-fork", true), ("chain-b", false)] {
+            let mut sign_bytes = vec![];
+            svr.sign_bytes(
+                chain_id.parse().unwrap(),
+                ProtocolVersion::V0_34,
+                &mut sign_bytes,
+            )
+            .unwrap();
+
+            assert_eq!(
+                test_ed25519_keypair()
+                    .public
+                    .verify(&sign_bytes, &signature)
+                    .is_ok(),
+                *verifies
+            );
+        }
+
+        // The alias shares the chain's consensus state: a precommit signed
+        // for the chain stops a conflicting prevote for the alias
+        let resp = sign_vote(&mut connection, &vote_request("chain-b", 0x02, 11));
+        assert!(resp.error.is_none());
+
+        let resp = sign_vote(&mut connection, &vote_request("chain-b-fork", 0x01, 11));
+        assert!(resp.vote.is_none());
+        assert!(resp.error.is_some());
+
+        let _ = process.kill();
+        let _ = process.wait();
+
+        let state_file = dir.path().join("chain-b_state.json");
+        let state: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(state_file).unwrap()).unwrap();
+        assert_eq!(state["height"], "11");
+        assert_eq!(state["step"], 3);
+        assert!(!dir.path().join("chain-b-fork_state.json").exists());
+    }
+}
+
+#[cfg(feature = "alerts")]
+mod alerts {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        os::unix::net::UnixListener,
+        time::Duration,
+    };
+
+    /// Sign a vote at height 10, returning the response
+    fn sign_vote(
+        connection: &mut KmsConnection,
+        block_id: Option<BlockId>,
+    ) -> vote::SignedVoteResponse {
+        let request = SignVoteRequest {
+            vote: Some(Vote {
+                vote_type: 0x01,
+                height: 10,
+                round: 0,
+                timestamp: Some(TimeMsg {
+                    seconds: 1_518_332_962,
+                    nanos: 765_000_000,
+                }),
+                block_id,
+                validator_address: test_validator_address(),
+                validator_index: 1,
+                signature: vec![],
+                extension: vec![],
+                extension_signature: vec![],
+            }),
+            chain_id: String::new(),
+        };
+
+        let mut buf = vec![];
+        request.encode(&mut buf).unwrap();
+        connection.write_all(&buf).unwrap();
+
+        let resp = MsgReader::new()
+            .read_msg(connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+        vote::SignedVoteResponse::decode(resp.as_ref()).expect("decoding vote failed")
+    }
+
+    /// Accept a webhook request, returning its headers and JSON body
+    fn receive_alert(webhook: &TcpListener) -> (Vec<String>, serde_json::Value) {
+        let (stream, _) = webhook.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        assert_eq!(request_line.trim_end(), "POST /hook HTTP/1.1");
+
+        let mut headers = vec![];
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+
+            if line.trim_end().is_empty() {
+                break;
+            }
+
+            headers.push(line.trim_end().to_lowercase());
+        }
+
+        let content_length = headers
+            .iter()
+            .find_map(|h| h.strip_prefix("content-length: "))
+            .expect("missing content-length")
+            .parse::<usize>()
+            .unwrap();
+
+        let mut body = vec![0u8; content_length];
+        reader.read_exact(&mut body).unwrap();
+
+        (&stream)
+            .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+            .unwrap();
+
+        (headers, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn test_double_sign_alert() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("tmkms.sock");
+        let config_path = dir.path().join("tmkms.toml");
+        let webhook = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                [alerts]
+                webhook_url = "http://{}/hook"
+                bearer_token = "test-token"
+
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+
+                [[validator]]
+                addr = "unix://{}"
+                chain_id = "test_chain_id"
+                protocol_version = "legacy"
+
+                [[providers.softsign]]
+                chain_ids = ["test_chain_id"]
+                key_format = "base64"
+                path = "{}"
+                "#,
+                webhook.local_addr().unwrap(),
+                dir.path().join("priv_validator_state.json").display(),
+                socket_path.display(),
+                SIGNING_KEY_PATH
+            ),
+        )
+        .unwrap();
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let mut process = Command::new(KMS_EXE_PATH)
+            .args(&["start", "-c", config_path.to_str().unwrap()])
+            .spawn()
+            .unwrap();
+
+        let (socket, _) = listener.accept().unwrap();
+        let mut connection = KmsConnection::Unix(UnixConnection::new(socket));
+
+        // Prevote nil, then for a block at the same height/round/step
+        assert!(sign_vote(&mut connection, None).err.is_none());
+
+        let block_id = BlockId {
+            hash: b"some hash00000000000000000000000".to_vec(),
+            parts_header: Some(PartsSetHeader {
+                total: 1,
+                hash: b"parts_hash0000000000000000000000".to_vec(),
+            }),
+        };
+
+        let err = sign_vote(&mut connection, Some(block_id)).err.unwrap();
+        assert_eq!(err.code, RemoteErrorCode::DoubleSignError as i32);
+
+        let (headers, alert) = receive_alert(&webhook);
+        assert!(headers.contains(&"content-type: application/json".to_owned()));
+        assert!(headers.contains(&"authorization: bearer test-token".to_owned()));
+
+        assert_eq!(alert["event"], "double_sign");
+        assert_eq!(alert["chain_id"], "test_chain_id");
+        assert_eq!(
+            alert["validator"],
+            format!("unix://{}", socket_path.display())
+        );
+        assert!(alert["message"]
+            .as_str()
+            .unwrap()
+            .contains("attempted double sign"));
+        assert!(alert["timestamp"]
+            .as_str()
+            .unwrap()
+            .parse::<DateTime<Utc>>()
+            .is_ok());
+        assert_eq!(alert.as_object().unwrap().len(), 5);
+
+        let _ = process.kill();
+        let _ = process.wait();
+    }
+}
+
+/// Integration tests against the mock signing provider, which stands in for
+/// a slow, flaky HSM
+#[cfg(feature = "testing")]
+mod mock {
+    use super::*;
+    use sha2::{Digest, Sha256};
+    use std::{os::unix::net::UnixListener, time::Instant};
+
+    /// Seed the mock provider's key is derived from
+    const SEED: &str = "integration tests";
+
+    /// Spawn a KMS signing with a mock provider with the given latency and
+    /// error probability, returning the process, its connection and the
+    /// directory holding its files
+    fn spawn(latency_ms: u64, error_probability: f64) -> (Child, KmsConnection, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("tmkms.sock");
+        let config_path = dir.path().join("tmkms.toml");
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+                selftest = "verify"
+
+                [[validator]]
+                addr = "unix://{}"
+                chain_id = "test_chain_id"
+                protocol_version = "legacy"
+
+                [[providers.mock]]
+                chain_ids = ["test_chain_id"]
+                seed = "{}"
+                latency_ms = {}
+                error_probability = {:.2}
+                "#,
+                dir.path().join("priv_validator_state.json").display(),
+                socket_path.display(),
+                SEED,
+                latency_ms,
+                error_probability
+            ),
+        )
+        .unwrap();
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let process = Command::new(KMS_EXE_PATH)
+            .args(&["start", "-c", config_path.to_str().unwrap()])
+            .spawn()
+            .unwrap();
+
+        let (socket, _) = listener.accept().unwrap();
+        let connection = KmsConnection::Unix(UnixConnection::new(socket));
+        (process, connection, dir)
+    }
+
+    /// Public key of the mock provider's key
+    fn mock_public_key() -> ed25519::PublicKey {
+        let secret = ed25519::SecretKey::from_bytes(&Sha256::digest(SEED.as_bytes())).unwrap();
+        ed25519::PublicKey::from(&secret)
+    }
+
+    /// Request a signature for a proposal at the given height
+    fn sign_proposal(
+        connection: &mut KmsConnection,
+        height: i64,
+    ) -> (Vec<u8>, proposal::SignedProposalResponse) {
+        let spr = amino_types::proposal::SignProposalRequest {
+            proposal: Some(amino_types::proposal::Proposal {
+                msg_type: amino_types::SignedMsgType::Proposal.to_u32(),
+                height,
+                round: 0,
+                pol_round: -1,
+                block_id: None,
+                timestamp: Some(TimeMsg {
+                    seconds: 1_518_332_962,
+                    nanos: 765_000_000,
+                }),
+                signature: vec![],
+            }),
+            chain_id: String::new(),
+        };
+
+        let mut sign_bytes = vec![];
+        spr.sign_bytes(
+            "test_chain_id".parse().unwrap(),
+            ProtocolVersion::Legacy,
+            &mut sign_bytes,
+        )
+        .unwrap();
+
+        let mut buf = vec![];
+        spr.encode(&mut buf).unwrap();
+        connection.write_all(&buf).unwrap();
+
+        let resp = MsgReader::new()
+            .read_msg(connection, MAX_RESPONSE_SIZE)
+            .unwrap();
+
+        let resp = proposal::SignedProposalResponse::decode(resp.as_ref())
+            .expect("decoding proposal failed");
+
+        (sign_bytes, resp)
+    }
+
+    #[test]
+    fn test_mock_latency() {
+        let (mut process, mut connection, _dir) = spawn(300, 0.0);
+
+        let started_at = Instant::now();
+        let (sign_bytes, resp) = sign_proposal(&mut connection, 10);
+        assert!(started_at.elapsed().as_millis() >= 300);
+
+        let proposal = resp.proposal.expect("proposal should have been signed");
+        let signature = ed25519::Signature::try_from(proposal.signature.as_slice()).unwrap();
+        assert!(mock_public_key().verify(&sign_bytes, &signature).is_ok());
+
+        let _ = process.kill();
+        let _ = process.wait();
+    }
+
+    #[test]
+    fn test_mock_injected_errors_are_refused() {
+        let (mut process, mut connection, _dir) = spawn(0, 1.0);
+
+        // Signing errors are reported to the validator without dropping the
+        // connection
+        for height in 10..13 {
+            let (_, resp) = sign_proposal(&mut connection, height);
+            assert!(resp.proposal.is_none());
+            assert_eq!(resp.err.unwrap().code, RemoteErrorCode::SigningError as i32);
+        }
+
+        let _ = process.kill();
+        let _ = process.wait();
+    }
+
+    #[test]
+    fn test_mock_retry_after_injected_error() {
+        let (mut process, mut connection, _dir) = spawn(0, 0.5);
+
+        // The validator retries a request which failed to sign: it must
+        // eventually be signed rather than refused as a double sign
+        let mut failures = 0;
+
+        loop {
+            let (sign_bytes, resp) = sign_proposal(&mut connection, 10);
+
+            match resp.err {
+                None => {
+                    let proposal = resp.proposal.unwrap();
+                    let signature =
+                        ed25519::Signature::try_from(proposal.signature.as_slice()).unwrap();
+                    assert!(mock_public_key().verify(&sign_bytes, &signature).is_ok());
+                    break;
+                }
+                Some(err) => {
+                    assert_eq!(err.code, RemoteErrorCode::SigningError as i32);
+                    failures += 1;
+                    assert!(failures < 20, "too many consecutive signing failures");
+                }
+            }
+        }
+
+        let _ = process.kill();
+        let _ = process.wait();
+    }
+}
+
+mod protocol_capture {
+    use super::*;
+    use std::path::Path;
+
+    /// Write a configuration capturing the protocol to `capture` in the
+    /// given directory and signing with the given key, returning its path
+    fn write_config(dir: &Path, key_path: &str) -> String {
+        let config_path = dir.join("tmkms.toml");
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+
+                [[validator]]
+                addr = "unix://{}"
+                chain_id = "test_chain_id"
+                protocol_version = "legacy"
+                protocol_capture_dir = "{}"
+
+                [[providers.softsign]]
+                chain_ids = ["test_chain_id"]
+                key_format = "base64"
+                path = "{}"
+                "#,
+                dir.join("priv_validator_state.json").display(),
+                dir.join("tmkms.sock").display(),
+                dir.join("capture").display(),
+                key_path
+            ),
+        )
+        .unwrap();
+
+        config_path.to_str().unwrap().to_owned()
+    }
+
+    /// Send a request and return the (length-delimited) response
+    fn send_request(connection: &mut KmsConnection, request: &impl Message) -> Vec<u8> {
+        let mut buf = vec![];
+        request.encode(&mut buf).unwrap();
+        connection.write_all(&buf).unwrap();
+
+        MsgReader::new()
+            .read_msg(connection, MAX_RESPONSE_SIZE)
+            .unwrap()
+    }
+
+    /// Replay the capture in the given directory with the given configuration
+    fn replay(dir: &Path, config_path: &str) -> std::process::Output {
+        Command::new(KMS_EXE_PATH)
+            .args(&[
+                "replay",
+                dir.join("capture").to_str().unwrap(),
+                "-c",
+                config_path,
+            ])
+            .output()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_capture_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = write_config(dir.path(), SIGNING_KEY_PATH);
+        let listener = UnixListener::bind(dir.path().join("tmkms.sock")).unwrap();
+
+        let mut process = Command::new(KMS_EXE_PATH)
+            .args(&["start", "-c", &config_path])
+            .spawn()
+            .unwrap();
+
+        let (socket, _) = listener.accept().unwrap();
+        let mut connection = KmsConnection::Unix(UnixConnection::new(socket));
+
+        send_request(&mut connection, &PingRequest {});
+        send_request(&mut connection, &PubKeyRequest::default());
+
+        let svr = SignVoteRequest {
+            vote: Some(Vote {
+                vote_type: 0x01,
+                height: 10,
+                round: 0,
+                timestamp: Some(TimeMsg {
+                    seconds: 1_518_332_962,
+                    nanos: 765_000_000,
+                }),
+                block_id: None,
+                validator_address: test_validator_address(),
+                validator_index: 1,
+                signature: vec![],
+                extension: vec![],
+                extension_signature: vec![],
+            }),
+            chain_id: String::new(),
+        };
+
+        let resp = send_request(&mut connection, &svr);
+        let v_resp = vote::SignedVoteResponse::decode(resp.as_ref()).unwrap();
+        assert!(v_resp.err.is_none());
+
+        // Undecodable requests are captured before the connection is dropped
+        connection
+            .write_all(&[0x04, 0xde, 0xad, 0xbe, 0xef])
+            .unwrap();
+        let mut buf = [0u8; 16];
+        assert_eq!(connection.read(&mut buf).unwrap(), 0);
+
+        process.kill().unwrap();
+        process.wait().unwrap();
+
+        let index = fs::read_to_string(dir.path().join("capture/index.jsonl")).unwrap();
+        assert_eq!(index.lines().count(), 4, "unexpected index: {}", index);
+        assert!(index.contains(r#""request_type":"sign_vote","msg_type":"prevote","height":10"#));
+
+        let output = replay(dir.path(), &config_path);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(output.status.success(), "replay failed: {}", stdout);
+        assert!(stdout.contains("#000003 sign_vote prevote at 10/0: match"));
+        assert!(stdout.contains("#000004 malformed: match"));
+        assert!(stdout.contains("4 request(s): 4 matched, 0 mismatched, 0 skipped"));
+
+        // Replaying with another key reproduces the request, but not its
+        // signature
+        let other_dir = tempfile::tempdir().unwrap();
+        let other_config_path =
+            write_config(other_dir.path(), "tests/support/secret_connection.key");
+
+        let output = replay(dir.path(), &other_config_path);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(!output.status.success());
+        assert!(
+            stdout.contains("#000003 sign_vote prevote at 10/0: MISMATCH: the signature differs"),
+            "unexpected output: {}",
+            stdout
+        );
+    }
+}
+
+/// Integration tests for systemd notifications
+#[cfg(feature = "systemd")]
+mod systemd {
+    use super::*;
+    use std::{
+        os::unix::net::{UnixDatagram, UnixListener},
+        time::Duration,
+    };
+
+    #[test]
+    fn test_ready_and_watchdog() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("tmkms.sock");
+        let notify_path = dir.path().join("notify.sock");
+        let config_path = dir.path().join("tmkms.toml");
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+
+                [[validator]]
+                addr = "unix://{}"
+                chain_id = "test_chain_id"
+                protocol_version = "legacy"
+
+                [[providers.softsign]]
+                chain_ids = ["test_chain_id"]
+                key_format = "base64"
+                path = "{}"
+                "#,
+                dir.path().join("priv_validator_state.json").display(),
+                socket_path.display(),
+                SIGNING_KEY_PATH
+            ),
+        )
+        .unwrap();
+
+        let notify = UnixDatagram::bind(&notify_path).unwrap();
+        notify
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let mut process = Command::new(KMS_EXE_PATH)
+            .args(["start", "-c", config_path.to_str().unwrap()])
+            .env("NOTIFY_SOCKET", &notify_path)
+            .env("WATCHDOG_USEC", "1000000")
+            .spawn()
+            .unwrap();
+
+        let _connection = listener.accept().unwrap();
+        let mut buf = [0u8; 1024];
+        let mut recv = || {
+            let len = notify.recv(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf[..len]).into_owned()
+        };
+
+        let ready = recv();
+        let watchdog = recv();
+
+        process.kill().unwrap();
+        process.wait().unwrap();
+
+        assert!(
+            ready.starts_with("READY=1\nSTATUS=test_chain_id: "),
+            "{}",
+            ready
+        );
+        assert_eq!(
+            watchdog, "WATCHDOG=1\nSTATUS=test_chain_id: connected",
+            "{}",
+            watchdog
+        );
+    }
+}
+
+/// Signing with the Ledger provider against a running [Speculos] emulator
+/// with the Tendermint Validator app, whose APDU server's `host:port` is
+/// given by the `TMKMS_SPECULOS_ADDR` environment variable (the test is
+/// skipped if it's unset). The app asks for the first vote to be approved,
+/// so Speculos should be run with an automation rule pressing both buttons
+/// (or have it pressed through its REST API).
+///
+/// [Speculos]: https://github.com/LedgerHQ/speculos
+#[cfg(feature = "ledger")]
+mod speculos {
+    use super::*;
+    use std::env;
+
+    /// Environment variable with the address of the Speculos APDU server
+    const SPECULOS_ADDR_ENV_VAR: &str = "TMKMS_SPECULOS_ADDR";
+
+    /// Send the given request to the KMS and return its response
+    fn request(connection: &mut KmsConnection, buf: &[u8]) -> Vec<u8> {
+        connection.write_all(buf).unwrap();
+
+        MsgReader::new()
+            .read_msg(connection, MAX_RESPONSE_SIZE)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_sign_vote() {
+        let addr = match env::var(SPECULOS_ADDR_ENV_VAR) {
+            Ok(addr) => addr,
+            Err(_) => {
+                eprintln!("{} not set: skipping Speculos test", SPECULOS_ADDR_ENV_VAR);
+                return;
+            }
+        };
+
+        let (host, port) = addr
+            .rsplit_once(':')
+            .expect("Speculos address should be host:port");
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("tmkms.sock");
+        let config_path = dir.path().join("tmkms.toml");
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+                [[chain]]
+                id = "test_chain_id"
+                key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+                state_file = "{}"
+
+                [[validator]]
+                addr = "unix://{}"
+                chain_id = "test_chain_id"
+                protocol_version = "legacy"
+
+                [[providers.ledgertm]]
+                chain_ids = ["test_chain_id"]
+                transport = "tcp"
+                host = "{}"
+                port = {}
+                "#,
+                dir.path().join("priv_validator_state.json").display(),
+                socket_path.display(),
+                host.trim_start_matches('[').trim_end_matches(']'),
+                port
+            ),
+        )
+        .unwrap();
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let mut process = Command::new(KMS_EXE_PATH)
+            .args(["start", "-c", config_path.to_str().unwrap()])
+            .spawn()
+            .unwrap();
+
+        let (socket, _) = listener.accept().unwrap();
+        let mut connection = KmsConnection::Unix(UnixConnection::new(socket));
+
+        let mut buf = vec![];
+        PubKeyRequest::default().encode(&mut buf).unwrap();
+        let pk_resp = PubKeyResponse::decode(request(&mut connection, &buf).as_ref())
+            .expect("decoding public key failed");
+        let pub_key = ed25519::PublicKey::from_bytes(&pk_resp.pub_key_ed25519).unwrap();
+
+        let dt = "2018-02-11T07:09:22.765Z".parse::<DateTime<Utc>>().unwrap();
+        let svr = amino_types::vote::SignVoteRequest {
+            vote: Some(amino_types::vote::Vote {
+                vote_type: 0x01,
+                height: 12345,
+                round: 2,
+                timestamp: Some(TimeMsg {
+                    seconds: dt.timestamp(),
+                    nanos: dt.timestamp_subsec_nanos() as i32,
+                }),
+                block_id: Some(BlockId {
+                    hash: b"some hash00000000000000000000000".to_vec(),
+                    parts_header: Some(PartsSetHeader {
+                        total: 1000000,
+                        hash: b"parts_hash0000000000000000000000".to_vec(),
+                    }),
+                }),
+                validator_address: tendermint::account::Id::from(tendermint::PublicKey::from(
+                    pub_key,
+                ))
+                .as_bytes()
+                .to_vec(),
+                validator_index: 56789,
+                signature: vec![],
+                extension: vec![],
+                extension_signature: vec![],
+            }),
+            chain_id: String::new(),
+        };
+
+        let mut buf = vec![];
+        svr.encode(&mut buf).unwrap();
+        let v_resp = vote::SignedVoteResponse::decode(request(&mut connection, &buf).as_ref())
+            .expect("decoding vote failed");
+
+        process.kill().unwrap();
+        process.wait().unwrap();
+
+        let mut sign_bytes = vec![];
+        svr.sign_bytes(
+            "test_chain_id".parse().unwrap(),
+            ProtocolVersion::Legacy,
+            &mut sign_bytes,
+        )
+        .unwrap();
+
+        let sig = v_resp
+            .vote
+            .expect("vote should be embedded in the response")
+            .signature;
+        let signature = ed25519::Signature::try_from(sig.as_slice()).unwrap();
+        assert!(pub_key.verify(&sign_bytes, &signature).is_ok());
+    }
+}
+
+#[test]
+fn test_json_banner() {
+    use std::{
+        io::{BufRead, BufReader},
+        process::Stdio,
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("tmkms.toml");
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+            [[chain]]
+            id = "test_chain_id"
+            key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+            state_file = "{}"
+
+            [[validator]]
+            addr = "unix://{}"
+            chain_id = "test_chain_id"
+            max_height = "500000"
+            protocol_version = "legacy"
+
+            [[providers.softsign]]
+            chain_ids = ["test_chain_id"]
+            key_format = "base64"
+            path = "{}"
+            "#,
+            dir.path().join("state.json").display(),
+            dir.path().join("validator.sock").display(),
+            SIGNING_KEY_PATH
+        ),
+    )
+    .unwrap();
+
+    let mut process = Command::new(KMS_EXE_PATH)
+        .args(&[
+            "start",
+            "--json-banner",
+            "-c",
+            config_path.to_str().unwrap(),
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // The banner is the only output on stdout
+    let mut banner = String::new();
+    BufReader::new(process.stdout.take().unwrap())
+        .read_line(&mut banner)
+        .unwrap();
+
+    process.kill().unwrap();
+    process.wait().unwrap();
+
+    let banner: serde_json::Value = serde_json::from_str(&banner).unwrap();
+    let chain = &banner["chains"][0];
+    assert_eq!(chain["chain_id"], "test_chain_id");
+
+    let key = &chain["keys"][0];
+    assert_eq!(key["provider"], "softsign");
+    assert_eq!(key["key_type"], "consensus");
+    assert_eq!(key["algorithm"], "ed25519");
+    assert!(key["public_key"]
+        .as_str()
+        .unwrap()
+        .starts_with("cosmosvalconspub"));
+    assert_eq!(key["fingerprint"].as_str().unwrap().len(), 16);
+
+    assert_eq!(chain["state"]["backend"], "json");
+    assert_eq!(chain["state"]["height"], 0);
+    assert_eq!(chain["validators"][0]["max_height"], 500000);
+    assert_eq!(
+        chain["validators"][0]["min_height"],
+        serde_json::Value::Null
+    );
+}